@@ -0,0 +1,149 @@
+# Deutsche Meldungen
+
+## status
+
+status-updated-header = Dateien mit geänderten Metadaten (Größe/Änderungszeit):
+status-updated-hint =   'ddrive verify' prüft, ob sich der Inhalt tatsächlich geändert hat
+status-new-header = Neue Dateien:
+status-moved-header = Umbenannte Verzeichnisse:
+status-moved-line =   { $from } { $arrow } { $to }, { $files } Dateien
+status-moved-hint =   'ddrive add <Pfad>' zeichnet diese Verschiebungen auf
+status-renamed-header = Möglicherweise umbenannte Dateien:
+status-renamed-line =   { $from } { $arrow } { $to } (Übereinstimmung: { $confidence })
+status-renamed-hint =   'ddrive add <Pfad>' bestätigt diese Umbenennungen
+status-renamed-low-confidence =   { $count } nur nach Größe und Erstellungszeit zugeordnet; 'ddrive add' prüft ihren Inhalt und fragt nach, bevor unbestätigte aufgezeichnet werden (--confirm-renames übernimmt sie)
+status-deleted-header = Nicht mehr vorhandene Dateien:
+status-failures-header = Dateien mit fehlgeschlagener Prüfung: { $count } Dateien
+status-failures-hint =   'ddrive verify --failed' prüft sie erneut
+status-unreadable-header = { $count } Dateien sind nicht lesbar und nicht geschützt:
+status-unreadable-hint =   Berechtigungen korrigieren und 'ddrive add <Pfad>' erneut ausführen
+status-due = Zur Prüfung fällige Dateien: { $count } Dateien
+status-due-hint = 'ddrive verify' prüft, ob sich geschützte Dateien geändert haben
+status-all-verified = Alle Dateien wurden kürzlich geprüft
+status-protected-header = Geschützte Dateien:
+status-files-size =   { $count } Dateien ({ $size })
+status-last-backup =   Letzte Sicherung: { $date }
+status-date-format = %d.%m.%Y um %H:%M
+status-devices-header = Geräte:
+status-device-line =   { $device }: { $files } Dateien ({ $size })
+status-device-failures = { $line }, davon { $failures } mit Integritätsfehlern
+status-stored-header = Gespeicherte Kopien:
+status-stored-objects =   { $count } Objekte in { $location }
+status-stored-size =   { $size } im Objektspeicher, davon etwa { $extra } zusätzlicher Speicherplatz
+status-stored-reflinked =   Kopien aktueller Dateien teilen sich Blöcke mit ihnen (Reflink)
+status-stored-full-copies =   Kopien sind vollständige Kopien: Reflinks sind hier nicht verfügbar
+status-doctor-hint =   Details mit 'ddrive doctor'
+status-stored-missing =   { $count } geschützte Dateien haben ihre gespeicherte Kopie verloren:
+status-stored-missing-hint =   'ddrive doctor' zeigt, wie sie erneut gespeichert werden
+status-stored-orphaned =   { $count } Objekte werden nicht mehr referenziert; 'ddrive prune' gibt sie nach der Karenzzeit frei
+status-untracked-header = Noch nicht geschützte Dateien:
+status-untracked-hint =   'ddrive add <Pfad>' schützt diese Dateien
+status-duplicates-header = Doppelte Dateien:
+status-duplicates-sets =   { $sets } Gruppen von Duplikaten mit insgesamt { $files } Dateien
+status-duplicates-size =   Von Duplikaten belegter Speicher: { $size }
+status-duplicates-hint =   Details mit 'ddrive dedup'
+status-summary-header = Zusammenfassung:
+status-summary-total =   Gesamt: { $count } Dateien ({ $size })
+status-summary-coverage =   Schutzabdeckung: { $percent } %
+status-and-more = ... und { $count } weitere
+
+## verify
+
+verify-complete = Prüfung abgeschlossen: { $passed }/{ $checked } bestanden, { $modified } geändert, { $failed } fehlgeschlagen, { $skipped } übersprungen
+verify-read =   { $files } Dateien ({ $size }) von der Platte gelesen, { $unchanged } anhand unveränderter Metadaten bestanden
+verify-missing-checksum =   { $count } übersprungen, da keine Prüfsumme für diesen Algorithmus vorliegt
+verify-filtered =   { $count } durch den Pfadfilter oder --since ausgelassen
+verify-modified-header = Seit dem letzten Hinzufügen geändert ('ddrive add' zeichnet die Änderungen auf):
+verify-failures-header = Integritätsfehler:
+verify-mac-mismatch =   { $path }: MAC stimmt nicht überein (Datei und Datenbank wurden möglicherweise manipuliert)
+verify-checksum-mismatch =   { $path }: Prüfsumme stimmt nicht überein
+verify-expected =     Erwartet:    { $checksum }
+verify-actual =     Tatsächlich: { $checksum }
+verify-damaged-bytes =     Beschädigte Bytes: { $ranges }
+verify-quarantined =     In { $path } unter Quarantäne gestellt, gespeicherte Kopie wiederhergestellt
+verify-drift-header = Geänderte Metadaten (Inhalt intakt):
+verify-objects-intact = Gespeicherte Objekte: { $intact }/{ $checked } intakt
+verify-objects-header = Beschädigte gespeicherte Objekte:
+verify-object-missing =   { $checksum }: fehlt oder ist nicht lesbar
+verify-object-held =     enthielt den Inhalt von { $path }
+verify-devices-header = Fehler nach Gerät:
+verify-device-line =   { $device }: { $count } Datei(en)
+verify-unavailable = { $count } Datei(en) auf nicht verfügbaren Medien übersprungen (ist eine Platte getrennt?):
+verify-failed = { $warning } { $count } Datei(en) haben die Integritätsprüfung nicht bestanden!
+verify-objects-failed = { $warning } { $count } gespeicherte(s) Objekt(e) fehlen oder sind beschädigt!
+verify-only-modified = Keine Beschädigung gefunden; { $count } Datei(en) seit dem letzten Hinzufügen geändert
+verify-all-passed = { $success } Alle Dateien haben die Integritätsprüfung bestanden!
+verify-external-start = Vergleiche { $count } erfasste Dateien mit { $directory }
+verify-external-matched = { $count } Dateien ({ $size }) stimmen mit dem Repository überein
+verify-external-missing = Fehlen in der Kopie ({ $count }):
+verify-external-mismatched = Weichen vom Repository ab ({ $count }):
+verify-external-unreadable = Nicht lesbar ({ $count }):
+verify-external-extra = Nicht im Repository erfasst ({ $count }):
+verify-external-passed = { $success } { $directory } enthält jede erfasste Datei mit ihrem aufgezeichneten Inhalt
+
+## notifications
+
+notify-failed-title = ddrive: Integritätsfehler in { $repo }
+notify-failed-body = { $failed } Datei(en) haben die Prüfung nicht bestanden und { $objects } gespeicherte Objekt(e) fehlen oder sind beschädigt. Details mit 'ddrive verify --failed'.
+notify-complete-title = ddrive: Prüfung in { $repo } abgeschlossen
+notify-complete-body = { $passed }/{ $checked } Dateien bestanden, { $modified } geändert, { $skipped } übersprungen
+
+## errors
+
+error-prefix = Fehler[{ $code }]: { $error }
+error-context = { $context }: { $error }
+error-context-action = (Aktion { $action })
+error-invalid-directory = Ungültiges Verzeichnis
+error-invalid-path = Fehler beim Ermitteln des relativen Pfads: { $error }
+error-database = Datenbankfehler: { $error }
+error-io = E/A-Fehler: { $error }
+error-file-system = Dateisystemfehler: { $message }
+error-hard-link = Fehler bei harter Verknüpfung: { $message }
+error-checksum = Fehler beim Berechnen der Prüfsumme: { $message }
+error-repository = Repository-Fehler: { $message }
+error-validation = Ungültige Eingabe: { $message }
+error-migration = Fehler bei der Datenbankmigration: { $error }
+error-ignore-pattern = Fehler im Ignoriermuster: { $message }
+error-glob-pattern = Fehler im Glob-Muster: { $error }
+error-permission-denied = Zugriff verweigert: { $message }
+error-configuration = Konfigurationsfehler: { $message }
+error-cancelled = Vorgang vom Benutzer abgebrochen
+error-partial-failure = { $count } Datei(en) konnten nicht geschützt werden
+error-unreadable = { $count } Datei(en) oder Ordner konnten nicht gelesen werden; Details mit 'ddrive status'
+error-object-store = Fehler im Objektspeicher: { $message }
+error-mirror = Spiegelungsfehler: { $message }
+error-encryption = Verschlüsselungsfehler: { $message }
+error-verify-failed = { $count } Datei(en) haben die Integritätsprüfung nicht bestanden. Details mit 'ddrive status'.
+error-verify-objects = { $count } gespeicherte(s) Objekt(e) fehlen oder sind beschädigt.
+error-verify-modified = { $count } Datei(en) wurden seit dem letzten Hinzufügen geändert.
+error-read-only-quarantine = --quarantine ändert Dateien und ist mit --read-only nicht möglich
+error-read-only-command = --read-only funktioniert nur mit status, log, verify und prune --estimate
+error-undeleted-files = { $count } Datei(en) konnten nicht von der Platte gelöscht werden
+error-kept-files = { $count } Datei(en) wurden auf der Platte behalten, da keine gespeicherte Kopie ihres aktuellen Inhalts existiert; mit --force trotzdem löschen
+error-external-mismatch = Die Kopie stimmt nicht mit dem Repository überein: { $missing } fehlen, { $different } weichen ab, { $unreadable } nicht lesbar.
+error-external-untracked = Die Kopie enthält { $count } Datei(en), die das Repository nicht erfasst.
+
+## operations
+
+operation-resolving = Auflösen von
+operation-scanning = Durchsuchen von
+operation-hashing = Hashen von
+operation-reading-metadata = Lesen der Metadaten von
+operation-reading-mtime = Lesen der Änderungszeit von
+operation-creating = Anlegen von
+operation-copying = Kopieren von
+operation-removing = Entfernen von
+operation-storing-copy = Speichern einer Kopie von
+operation-staging-copy = Bereitstellen einer Kopie unter
+operation-hashing-staged-copy = Hashen der bereitgestellten Kopie
+operation-storing-staged-copy = Speichern der bereitgestellten Kopie
+operation-starting-journal = Beginnen des Journals
+operation-journaling-copy = Eintragen der gespeicherten Kopie von
+operation-recording-add = Erfassen der hinzugefügten Dateien
+operation-hashing-stored-copy = Hashen der gespeicherten Kopie
+operation-fetching-stored-copy = Abrufen der gespeicherten Kopie nach
+operation-restoring-stored-copy = Wiederherstellen der gespeicherten Kopie nach
+operation-copying-to-quarantine = Kopieren in die Quarantäne von
+operation-recording-quarantine = Erfassen der Quarantäne von
+operation-saving-verify-progress = Sichern des Fortschritts der Prüfung
+operation-finishing-verify = Abschließen der Prüfung
@@ -0,0 +1,150 @@
+# English messages, the reference catalog: every other catalog translates
+# these IDs, and any it leaves out are shown in English.
+
+## status
+
+status-updated-header = Files with metadata changes (size/modification time):
+status-updated-hint =   Run 'ddrive verify' to check if content has actually changed
+status-new-header = New files found:
+status-moved-header = Renamed directories:
+status-moved-line =   { $from } { $arrow } { $to }, { $files } files
+status-moved-hint =   Run 'ddrive add <path>' to record these moves
+status-renamed-header = Potentially renamed files:
+status-renamed-line =   { $from } { $arrow } { $to } ({ $confidence } match)
+status-renamed-hint =   Run 'ddrive add <path>' to confirm these renames
+status-renamed-low-confidence =   { $count } matched by size and creation time only; 'ddrive add' checks their content and asks before recording any that cannot be confirmed (--confirm-renames to accept them)
+status-deleted-header = Files no longer present:
+status-failures-header = Files that failed verification: { $count } files
+status-failures-hint =   Run 'ddrive verify --failed' to re-check them
+status-unreadable-header = { $count } files cannot be read and are not protected:
+status-unreadable-hint =   Fix their permissions and run 'ddrive add <path>' again
+status-due = Files due for verification: { $count } files
+status-due-hint = Run 'ddrive verify' to verify if any tracked files have changed
+status-all-verified = All your files have been verified recently
+status-protected-header = Protected files:
+status-files-size =   { $count } files ({ $size })
+status-last-backup =   Last backup: { $date }
+status-date-format = %B %d, %Y at %H:%M
+status-devices-header = Devices:
+status-device-line =   { $device }: { $files } files ({ $size })
+status-device-failures = { $line }, { $failures } with integrity failures
+status-stored-header = Stored copies:
+status-stored-objects =   { $count } objects in { $location }
+status-stored-size =   { $size } in the object store, using about { $extra } of extra disk space
+status-stored-reflinked =   Copies of current files share blocks with them (reflink)
+status-stored-full-copies =   Copies are full copies: reflinks are not available here
+status-doctor-hint =   Run 'ddrive doctor' for details
+status-stored-missing =   { $count } tracked files have lost their stored copy:
+status-stored-missing-hint =   Run 'ddrive doctor' for how to store them again
+status-stored-orphaned =   { $count } objects are no longer referenced; 'ddrive prune' reclaims them after the grace period
+status-untracked-header = Files not yet protected:
+status-untracked-hint =   Run 'ddrive add <path>' to protect these files
+status-duplicates-header = Duplicate files found:
+status-duplicates-sets =   { $sets } sets of duplicates with { $files } total files
+status-duplicates-size =   Storage used by duplicates: { $size }
+status-duplicates-hint =   Run 'ddrive dedup' to see details
+status-summary-header = Summary:
+status-summary-total =   Total: { $count } files ({ $size })
+status-summary-coverage =   Protection coverage: { $percent }%
+status-and-more = ... and { $count } more
+
+## verify
+
+verify-complete = Verification complete: { $passed }/{ $checked } passed, { $modified } modified, { $failed } failed, { $skipped } skipped
+verify-read =   Read { $files } files ({ $size }) off disk, { $unchanged } passed on unchanged metadata
+verify-missing-checksum =   { $count } skipped without a checksum for this algorithm
+verify-filtered =   { $count } left out by the path filter or --since
+verify-modified-header = Modified since last add (run 'ddrive add' to record the changes):
+verify-failures-header = Integrity failures:
+verify-mac-mismatch =   { $path }: MAC mismatch (file and database may have been tampered with)
+verify-checksum-mismatch =   { $path }: checksum mismatch
+verify-expected =     Expected: { $checksum }
+verify-actual =     Actual:   { $checksum }
+verify-damaged-bytes =     Damaged bytes: { $ranges }
+verify-quarantined =     Quarantined to { $path }, stored copy restored
+verify-drift-header = Metadata drift (content intact):
+verify-objects-intact = Stored objects: { $intact }/{ $checked } intact
+verify-objects-header = Damaged stored objects:
+verify-object-missing =   { $checksum }: missing or unreadable
+verify-object-held =     held content of { $path }
+verify-devices-header = Failures by device:
+verify-device-line =   { $device }: { $count } file(s)
+verify-unavailable = Skipped { $count } file(s) on unavailable media (is a disk detached?):
+verify-failed = { $warning } { $count } file(s) failed integrity verification!
+verify-objects-failed = { $warning } { $count } stored object(s) are missing or damaged!
+verify-only-modified = No corruption found; { $count } file(s) modified since last add
+verify-all-passed = { $success } All files passed integrity verification!
+verify-external-start = Comparing { $count } tracked files with { $directory }
+verify-external-matched = { $count } files ({ $size }) match the repository
+verify-external-missing = Missing from the copy ({ $count }):
+verify-external-mismatched = Different from the repository ({ $count }):
+verify-external-unreadable = Could not be read ({ $count }):
+verify-external-extra = Not tracked by the repository ({ $count }):
+verify-external-passed = { $success } { $directory } has every tracked file with its recorded content
+
+## notifications
+
+notify-failed-title = ddrive: integrity failures in { $repo }
+notify-failed-body = { $failed } file(s) failed verification and { $objects } stored object(s) are missing or damaged. Run 'ddrive verify --failed' for details.
+notify-complete-title = ddrive: verification finished in { $repo }
+notify-complete-body = { $passed }/{ $checked } files passed, { $modified } modified, { $skipped } skipped
+
+## errors
+
+error-prefix = error[{ $code }]: { $error }
+error-context = { $context }: { $error }
+error-context-action = (action { $action })
+error-invalid-directory = Invalid directory
+error-invalid-path = error getting relative path: { $error }
+error-database = Database error: { $error }
+error-io = IO error: { $error }
+error-file-system = File system error: { $message }
+error-hard-link = Hard link error: { $message }
+error-checksum = Checksum calculation error: { $message }
+error-repository = Repository error: { $message }
+error-validation = Validation error: { $message }
+error-migration = Database migration error: { $error }
+error-ignore-pattern = Ignore pattern error: { $message }
+error-glob-pattern = Glob pattern error: { $error }
+error-permission-denied = Permission denied: { $message }
+error-configuration = Configuration error: { $message }
+error-cancelled = User cancelled operation
+error-partial-failure = { $count } file(s) could not be protected
+error-unreadable = { $count } file(s) or folder(s) could not be read; run 'ddrive status' for details
+error-object-store = Object store error: { $message }
+error-mirror = Mirror error: { $message }
+error-encryption = Encryption error: { $message }
+error-verify-failed = { $count } file(s) failed integrity verification. Run 'ddrive status' for details.
+error-verify-objects = { $count } stored object(s) are missing or damaged.
+error-verify-modified = { $count } file(s) were modified since they were last added.
+error-read-only-quarantine = --quarantine changes files and cannot run with --read-only
+error-read-only-command = --read-only only works with status, log, verify and prune --estimate
+error-undeleted-files = { $count } file(s) could not be deleted from disk
+error-kept-files = { $count } file(s) were kept on disk because they have no stored copy of their current content; pass --force to delete them anyway
+error-external-mismatch = The copy does not match the repository: { $missing } missing, { $different } different, { $unreadable } unreadable.
+error-external-untracked = The copy has { $count } file(s) the repository does not track.
+
+## operations
+
+operation-resolving = resolving
+operation-scanning = scanning
+operation-hashing = hashing
+operation-reading-metadata = reading metadata of
+operation-reading-mtime = reading the modification time of
+operation-creating = creating
+operation-copying = copying
+operation-removing = removing
+operation-storing-copy = storing a copy of
+operation-staging-copy = staging a copy at
+operation-hashing-staged-copy = hashing the staged copy
+operation-storing-staged-copy = storing the staged copy
+operation-starting-journal = starting the journal
+operation-journaling-copy = journaling the stored copy of
+operation-recording-add = recording the added files
+operation-hashing-stored-copy = hashing the stored copy
+operation-fetching-stored-copy = fetching the stored copy to
+operation-restoring-stored-copy = restoring the stored copy to
+operation-copying-to-quarantine = copying to quarantine
+operation-recording-quarantine = recording the quarantine of
+operation-saving-verify-progress = saving the progress of the verify run
+operation-finishing-verify = finishing the verify run
@@ -0,0 +1,5 @@
+-- Soft-delete support: removed files keep their row with a tombstone timestamp
+-- until prune purges them after the retention window
+ALTER TABLE files ADD COLUMN deleted_at DATETIME NULL;
+
+CREATE INDEX IF NOT EXISTS idx_files_deleted_at ON files(deleted_at);
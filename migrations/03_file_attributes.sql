@@ -0,0 +1,5 @@
+-- File attributes: permission bits, ownership and extended attributes
+ALTER TABLE files ADD COLUMN mode INTEGER NULL;
+ALTER TABLE files ADD COLUMN uid INTEGER NULL;
+ALTER TABLE files ADD COLUMN gid INTEGER NULL;
+ALTER TABLE files ADD COLUMN xattrs TEXT NULL; -- JSON object of attribute name to hex-encoded value
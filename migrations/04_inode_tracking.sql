@@ -0,0 +1,5 @@
+-- Device/inode identity for hardlink awareness and rename detection
+ALTER TABLE files ADD COLUMN dev INTEGER NULL;
+ALTER TABLE files ADD COLUMN ino INTEGER NULL;
+
+CREATE INDEX IF NOT EXISTS idx_files_dev_ino ON files(dev, ino);
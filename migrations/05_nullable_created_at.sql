@@ -0,0 +1,32 @@
+-- Allow unknown file creation (birth) time. Many Linux filesystems and NFS
+-- mounts don't expose it, and such files must still be tracked.
+CREATE TABLE files_new (
+    id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    path TEXT NOT NULL UNIQUE,
+    created_at DATETIME NULL,
+    updated_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    last_checked DATETIME NULL,
+    b3sum TEXT NOT NULL,
+    size INTEGER NOT NULL,
+    deleted_at DATETIME NULL,
+    mode INTEGER NULL,
+    uid INTEGER NULL,
+    gid INTEGER NULL,
+    xattrs TEXT NULL,
+    dev INTEGER NULL,
+    ino INTEGER NULL
+);
+
+INSERT INTO files_new (id, path, created_at, updated_at, last_checked, b3sum, size, deleted_at, mode, uid, gid, xattrs, dev, ino)
+SELECT id, path, created_at, updated_at, last_checked, b3sum, size, deleted_at, mode, uid, gid, xattrs, dev, ino
+FROM files;
+
+DROP TABLE files;
+ALTER TABLE files_new RENAME TO files;
+
+CREATE INDEX IF NOT EXISTS idx_files_path ON files(path);
+CREATE INDEX IF NOT EXISTS idx_files_last_checked ON files(last_checked);
+CREATE INDEX IF NOT EXISTS idx_files_updated_at ON files(updated_at);
+CREATE INDEX IF NOT EXISTS idx_files_b3sum ON files(b3sum);
+CREATE INDEX IF NOT EXISTS idx_files_deleted_at ON files(deleted_at);
+CREATE INDEX IF NOT EXISTS idx_files_dev_ino ON files(dev, ino);
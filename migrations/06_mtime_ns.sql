@@ -0,0 +1,5 @@
+-- Nanosecond precision modification time (integer ns since the Unix epoch, UTC).
+-- Existing rows are seeded from the second-precision updated_at column.
+ALTER TABLE files ADD COLUMN mtime_ns INTEGER NULL;
+
+UPDATE files SET mtime_ns = CAST(strftime('%s', updated_at) AS INTEGER) * 1000000000;
@@ -0,0 +1,5 @@
+-- Tracks one-off data migrations that are performed in Rust rather than SQL
+CREATE TABLE IF NOT EXISTS data_migrations (
+    name TEXT NOT NULL PRIMARY KEY,
+    applied_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
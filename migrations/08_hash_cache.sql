@@ -0,0 +1,11 @@
+-- Checksums of untracked files, keyed by file identity, so unchanged files
+-- are not re-hashed on every status or add run
+CREATE TABLE IF NOT EXISTS hash_cache (
+    dev INTEGER NOT NULL,
+    ino INTEGER NOT NULL,
+    size INTEGER NOT NULL,
+    mtime_ns INTEGER NOT NULL,
+    b3sum TEXT NOT NULL,
+    cached_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (dev, ino)
+);
@@ -0,0 +1,3 @@
+-- Optional secondary checksums for interoperability with external tools
+ALTER TABLE files ADD COLUMN sha256 TEXT;
+ALTER TABLE files ADD COLUMN xxh3 TEXT;
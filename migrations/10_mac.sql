@@ -0,0 +1,2 @@
+-- Keyed BLAKE3 MAC for tamper evidence
+ALTER TABLE files ADD COLUMN mac TEXT;
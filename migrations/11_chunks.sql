@@ -0,0 +1,8 @@
+-- Per-chunk BLAKE3 hashes of large file contents, keyed by whole-file checksum
+CREATE TABLE IF NOT EXISTS chunks (
+    b3sum TEXT NOT NULL,
+    chunk_index INTEGER NOT NULL,
+    chunk_size INTEGER NOT NULL,
+    hash TEXT NOT NULL,
+    PRIMARY KEY (b3sum, chunk_index)
+);
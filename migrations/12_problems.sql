@@ -0,0 +1,9 @@
+-- Files that could not be protected, with the most recent failure per path.
+-- Rows are cleared once the file is stored successfully.
+CREATE TABLE IF NOT EXISTS problems (
+    path TEXT NOT NULL PRIMARY KEY,
+    action_id INTEGER NOT NULL,
+    kind TEXT NOT NULL,
+    message TEXT NOT NULL,
+    occurred_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
@@ -0,0 +1,13 @@
+-- Add actions in progress and the objects they created. Rows are removed in
+-- the same transaction that records the action's files, so rows left behind
+-- belong to interrupted actions and are cleaned up by the next add.
+CREATE TABLE IF NOT EXISTS journal (
+    action_id INTEGER NOT NULL PRIMARY KEY,
+    started_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE TABLE IF NOT EXISTS journal_objects (
+    action_id INTEGER NOT NULL,
+    b3sum TEXT NOT NULL,
+    PRIMARY KEY (action_id, b3sum)
+);
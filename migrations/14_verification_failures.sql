@@ -0,0 +1,16 @@
+-- Integrity failures found by verify. A failure stays unresolved until the
+-- file passes verification, is re-added, or stops being tracked.
+CREATE TABLE IF NOT EXISTS verification_failures (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    path TEXT NOT NULL,
+    algorithm TEXT NOT NULL,
+    expected_checksum TEXT NOT NULL,
+    actual_checksum TEXT NOT NULL,
+    detected_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    last_detected_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    resolved_at DATETIME,
+    resolution TEXT
+);
+
+CREATE INDEX IF NOT EXISTS idx_verification_failures_unresolved
+    ON verification_failures (path) WHERE resolved_at IS NULL;
@@ -0,0 +1,61 @@
+-- Reference counts of object store entries: one reference per file record and
+-- per history entry with that checksum. Kept up to date by triggers so prune
+-- can find orphaned objects with an indexed query instead of walking the store.
+CREATE TABLE IF NOT EXISTS objects (
+    b3sum TEXT NOT NULL PRIMARY KEY,
+    size INTEGER NOT NULL,
+    refcount INTEGER NOT NULL DEFAULT 0,
+    -- When the last reference went away; NULL while referenced
+    unreferenced_at DATETIME
+);
+
+CREATE INDEX IF NOT EXISTS idx_objects_unreferenced
+    ON objects (unreferenced_at) WHERE refcount <= 0;
+
+INSERT INTO objects (b3sum, size, refcount)
+SELECT b3sum, MAX(size), COUNT(*)
+FROM (
+    SELECT b3sum, size FROM files WHERE b3sum IS NOT NULL
+    UNION ALL
+    SELECT b3sum, size FROM history WHERE b3sum IS NOT NULL
+)
+GROUP BY b3sum;
+
+CREATE TRIGGER IF NOT EXISTS objects_files_insert AFTER INSERT ON files
+WHEN NEW.b3sum IS NOT NULL
+BEGIN
+    INSERT INTO objects (b3sum, size, refcount) VALUES (NEW.b3sum, NEW.size, 1)
+    ON CONFLICT (b3sum) DO UPDATE SET refcount = refcount + 1, unreferenced_at = NULL;
+END;
+
+CREATE TRIGGER IF NOT EXISTS objects_files_delete AFTER DELETE ON files
+WHEN OLD.b3sum IS NOT NULL
+BEGIN
+    UPDATE objects SET refcount = refcount - 1,
+        unreferenced_at = CASE WHEN refcount <= 1 THEN CURRENT_TIMESTAMP END
+    WHERE b3sum = OLD.b3sum;
+END;
+
+CREATE TRIGGER IF NOT EXISTS objects_files_update AFTER UPDATE OF b3sum ON files
+WHEN OLD.b3sum IS NOT NEW.b3sum
+BEGIN
+    UPDATE objects SET refcount = refcount - 1,
+        unreferenced_at = CASE WHEN refcount <= 1 THEN CURRENT_TIMESTAMP END
+    WHERE OLD.b3sum IS NOT NULL AND b3sum = OLD.b3sum;
+    INSERT INTO objects (b3sum, size, refcount)
+    SELECT NEW.b3sum, NEW.size, 1 WHERE NEW.b3sum IS NOT NULL
+    ON CONFLICT (b3sum) DO UPDATE SET refcount = refcount + 1, unreferenced_at = NULL;
+END;
+
+CREATE TRIGGER IF NOT EXISTS objects_history_insert AFTER INSERT ON history
+BEGIN
+    INSERT INTO objects (b3sum, size, refcount) VALUES (NEW.b3sum, NEW.size, 1)
+    ON CONFLICT (b3sum) DO UPDATE SET refcount = refcount + 1, unreferenced_at = NULL;
+END;
+
+CREATE TRIGGER IF NOT EXISTS objects_history_delete AFTER DELETE ON history
+BEGIN
+    UPDATE objects SET refcount = refcount - 1,
+        unreferenced_at = CASE WHEN refcount <= 1 THEN CURRENT_TIMESTAMP END
+    WHERE b3sum = OLD.b3sum;
+END;
@@ -0,0 +1,3 @@
+-- Whether the object store holds a copy of the recorded content. Files added
+-- before store policies existed were always copied.
+ALTER TABLE files ADD COLUMN stored BOOLEAN NOT NULL DEFAULT TRUE;
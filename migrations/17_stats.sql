@@ -0,0 +1,45 @@
+-- Running totals of tracked files, kept up to date by triggers so `status
+-- --fast` can answer without scanning the tree or loading every record
+CREATE TABLE IF NOT EXISTS stats (
+    id INTEGER NOT NULL PRIMARY KEY CHECK (id = 1),
+    tracked_files INTEGER NOT NULL DEFAULT 0,
+    tracked_size INTEGER NOT NULL DEFAULT 0,
+    -- Newest action recorded in history
+    last_action_id INTEGER
+);
+
+INSERT OR IGNORE INTO stats (id, tracked_files, tracked_size, last_action_id)
+SELECT 1, COUNT(*), COALESCE(SUM(size), 0), (SELECT MAX(action_id) FROM history)
+FROM files
+WHERE deleted_at IS NULL;
+
+CREATE TRIGGER IF NOT EXISTS stats_files_insert AFTER INSERT ON files
+WHEN NEW.deleted_at IS NULL
+BEGIN
+    UPDATE stats SET tracked_files = tracked_files + 1, tracked_size = tracked_size + NEW.size
+    WHERE id = 1;
+END;
+
+CREATE TRIGGER IF NOT EXISTS stats_files_delete AFTER DELETE ON files
+WHEN OLD.deleted_at IS NULL
+BEGIN
+    UPDATE stats SET tracked_files = tracked_files - 1, tracked_size = tracked_size - OLD.size
+    WHERE id = 1;
+END;
+
+CREATE TRIGGER IF NOT EXISTS stats_files_update AFTER UPDATE OF size, deleted_at ON files
+BEGIN
+    UPDATE stats SET
+        tracked_files = tracked_files
+            - (OLD.deleted_at IS NULL) + (NEW.deleted_at IS NULL),
+        tracked_size = tracked_size
+            - CASE WHEN OLD.deleted_at IS NULL THEN OLD.size ELSE 0 END
+            + CASE WHEN NEW.deleted_at IS NULL THEN NEW.size ELSE 0 END
+    WHERE id = 1;
+END;
+
+CREATE TRIGGER IF NOT EXISTS stats_history_insert AFTER INSERT ON history
+BEGIN
+    UPDATE stats SET last_action_id = MAX(COALESCE(last_action_id, NEW.action_id), NEW.action_id)
+    WHERE id = 1;
+END;
@@ -0,0 +1,11 @@
+-- Mount point and file system of the devices tracked files reside on, keyed
+-- by the device number recorded in files.dev, so failures and statistics can
+-- be reported per disk even while it is detached
+CREATE TABLE IF NOT EXISTS devices (
+    dev INTEGER PRIMARY KEY,
+    mount_point TEXT NOT NULL,
+    fs_type TEXT NOT NULL,
+    source TEXT NOT NULL,
+    uuid TEXT,
+    last_seen_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
@@ -0,0 +1,3 @@
+-- Inode change time (integer ns since the Unix epoch, UTC) when the file was
+-- recorded, for the "ctime" change detection. Unknown for existing rows.
+ALTER TABLE files ADD COLUMN ctime_ns INTEGER NULL;
@@ -0,0 +1,13 @@
+-- One row per command run that recorded an action: how long it took, how
+-- much it processed and whether any files failed. Runs that share an action
+-- ID (same second) are folded into one row.
+CREATE TABLE IF NOT EXISTS actions (
+    action_id INTEGER NOT NULL PRIMARY KEY,
+    command TEXT NOT NULL,
+    duration_ms INTEGER NOT NULL,
+    files INTEGER NOT NULL DEFAULT 0,
+    bytes INTEGER NOT NULL DEFAULT 0,
+    errors INTEGER NOT NULL DEFAULT 0,
+    -- 'success' or 'partial_failure'
+    status TEXT NOT NULL
+);
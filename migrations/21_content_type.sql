@@ -0,0 +1,3 @@
+-- MIME type detected from the content of the file when it was recorded.
+-- Unknown for existing rows until their content is recorded again.
+ALTER TABLE files ADD COLUMN content_type TEXT NULL;
@@ -0,0 +1,10 @@
+-- Files copied to each mirror destination by `mirror`, with the checksum
+-- they had, so later runs transfer only what changed since
+CREATE TABLE IF NOT EXISTS mirrored_files (
+    destination TEXT NOT NULL,
+    path TEXT NOT NULL,
+    b3sum TEXT NOT NULL,
+    size INTEGER NOT NULL,
+    mirrored_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (destination, path)
+);
@@ -0,0 +1,19 @@
+-- Removable media (discs, drives) written by `export`, and the objects each
+-- one holds, so a lost disc can be mapped back to the files it protected
+CREATE TABLE IF NOT EXISTS media (
+    label TEXT NOT NULL PRIMARY KEY,
+    capacity INTEGER NOT NULL,
+    created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    last_verified_at DATETIME,
+    -- Missing or damaged objects found by the last `media verify`
+    last_verify_failures INTEGER
+);
+
+CREATE TABLE IF NOT EXISTS media_objects (
+    label TEXT NOT NULL REFERENCES media (label),
+    b3sum TEXT NOT NULL,
+    size INTEGER NOT NULL,
+    PRIMARY KEY (label, b3sum)
+);
+
+CREATE INDEX IF NOT EXISTS idx_media_objects_b3sum ON media_objects (b3sum);
@@ -0,0 +1,3 @@
+-- Which store holds each object: 'hot' for the object store, 'cold' once
+-- `tier` has moved it to [object_store] cold_url
+ALTER TABLE objects ADD COLUMN tier TEXT NOT NULL DEFAULT 'hot';
@@ -0,0 +1,17 @@
+-- Verify runs that have not finished, with the filters they were started
+-- with and the files they have checked, so that a run killed midway is
+-- continued by the next run with the same filters under the same action ID
+CREATE TABLE IF NOT EXISTS verify_sessions (
+    action_id INTEGER NOT NULL PRIMARY KEY,
+    parameters TEXT NOT NULL,
+    total_files INTEGER NOT NULL,
+    started_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE TABLE IF NOT EXISTS verify_session_files (
+    action_id INTEGER NOT NULL REFERENCES verify_sessions (action_id) ON DELETE CASCADE,
+    path TEXT NOT NULL,
+    PRIMARY KEY (action_id, path)
+);
+
+CREATE INDEX IF NOT EXISTS idx_verify_sessions_parameters ON verify_sessions (parameters);
@@ -0,0 +1,298 @@
+//! Library facade for embedding ddrive in other tools.
+//!
+//! [`Ddrive`] wraps one repository and runs the same commands as the CLI,
+//! returning their typed results. Commands still log through `tracing`,
+//! which is silent unless the embedding program installs a subscriber; for
+//! live progress, pass a channel to [`AppContextBuilder::progress`].
+
+use crate::{
+    AppContext, AppContextBuilder, Result,
+    checksum::HashAlgorithm,
+    cli::{
+        add::{AddCommand, AddResult},
+        coverage::{CoverageCommand, DirectoryCoverage},
+        doctor::{DoctorCommand, DoctorReport},
+        prune::{PruneCommand, PruneEstimate, PruneResult},
+        status::{RepositoryStats, StatusCommand},
+        verify::{PathFilter, VerifyCommand, VerifyResult},
+    },
+    repository::Repository,
+};
+use chrono::{DateTime, Utc};
+use std::path::{Path, PathBuf};
+
+/// Options for [`Ddrive::add`]
+#[derive(Debug, Clone, Default)]
+pub struct AddOptions {
+    /// Do not descend into other file systems
+    pub one_file_system: bool,
+    /// Record renames matched only by size and creation time; without this
+    /// they are recorded as deleted and new files
+    pub confirm_renames: bool,
+}
+
+/// Options for [`Ddrive::verify`]
+#[derive(Debug, Clone)]
+pub struct VerifyOptions {
+    /// Only verify the files under this directory or matching this pattern
+    pub path: Option<PathFilter>,
+    /// Only verify files modified or added at or after this time
+    pub since: Option<DateTime<Utc>>,
+    /// Verify every file, not just those due for a check
+    pub force: bool,
+    pub algorithm: HashAlgorithm,
+    /// Only re-check files with unresolved integrity failures
+    pub failed_only: bool,
+    /// Move corrupted files aside and restore the stored copy
+    pub quarantine: bool,
+    /// Also re-hash the stored objects
+    pub objects: bool,
+    /// Trust files whose size and modification time are unchanged
+    pub metadata_shortcut: bool,
+    /// Only report, without recording check times, failures or the run
+    pub no_update: bool,
+}
+
+impl Default for VerifyOptions {
+    fn default() -> Self {
+        Self {
+            path: None,
+            since: None,
+            force: false,
+            algorithm: HashAlgorithm::Blake3,
+            failed_only: false,
+            quarantine: false,
+            objects: false,
+            metadata_shortcut: true,
+            no_update: false,
+        }
+    }
+}
+
+/// Options for [`Ddrive::prune`]
+#[derive(Debug, Clone, Default)]
+pub struct PruneOptions {
+    /// Report what would be removed without removing it
+    pub dry_run: bool,
+    /// Keep pruning older history until this many bytes are free
+    pub target_free: Option<u64>,
+}
+
+/// A ddrive repository opened as a library
+pub struct Ddrive {
+    context: AppContext,
+}
+
+impl Ddrive {
+    /// Open the repository containing `path`
+    pub async fn open(path: impl Into<PathBuf>) -> Result<Self> {
+        let repo = Repository::find_repository(path.into())?;
+        Ok(Self::from_context(AppContext::new(repo).await?))
+    }
+
+    /// Create a repository at `path` and open it
+    pub async fn init(path: impl Into<PathBuf>) -> Result<Self> {
+        let repo = Repository::init_repository(path.into()).await?;
+        Ok(Self::from_context(AppContext::new(repo).await?))
+    }
+
+    /// Wrap a context made with [`AppContextBuilder`]
+    pub fn from_context(context: AppContext) -> Self {
+        Self { context }
+    }
+
+    /// Start building a context for the repository at `root`
+    pub fn builder(root: impl Into<PathBuf>) -> AppContextBuilder {
+        AppContextBuilder::new(Repository::new(root.into()))
+    }
+
+    pub fn context(&self) -> &AppContext {
+        &self.context
+    }
+
+    /// Track new and changed files under each of `paths`
+    pub async fn add<P: AsRef<Path>>(
+        &self,
+        paths: &[P],
+        options: &AddOptions,
+    ) -> Result<AddResult> {
+        let mut context = self.context.clone();
+        if options.one_file_system {
+            context.config.scan.same_file_system = true;
+        }
+
+        let mut result = AddResult::default();
+        for path in paths {
+            result.merge(
+                AddCommand::new(&context)
+                    .confirm_renames(options.confirm_renames)
+                    .execute(path)
+                    .await?,
+            );
+        }
+        Ok(result)
+    }
+
+    /// Track exactly the listed files, without scanning any directory
+    pub async fn add_files(&self, files: &[PathBuf], options: &AddOptions) -> Result<AddResult> {
+        let mut context = self.context.clone();
+        if options.one_file_system {
+            context.config.scan.same_file_system = true;
+        }
+        AddCommand::new(&context)
+            .confirm_renames(options.confirm_renames)
+            .execute_files(files)
+            .await
+    }
+
+    /// Track the files the `[track]` policy includes across the repository
+    pub async fn add_auto(&self, options: &AddOptions) -> Result<AddResult> {
+        let mut context = self.context.clone();
+        if options.one_file_system {
+            context.config.scan.same_file_system = true;
+        }
+        AddCommand::new(&context)
+            .confirm_renames(options.confirm_renames)
+            .execute_auto()
+            .await
+    }
+
+    /// Check tracked files against their recorded checksums
+    pub async fn verify(&self, options: &VerifyOptions) -> Result<VerifyResult> {
+        let mut context = self.context.clone();
+        if options.quarantine {
+            context.config.verify.quarantine = true;
+        }
+        if options.objects {
+            context.config.verify.objects = true;
+        }
+        if !options.metadata_shortcut {
+            context.config.verify.metadata_shortcut = false;
+        }
+
+        let mut verify_command = VerifyCommand::new(&context);
+        if options.no_update {
+            verify_command = verify_command.update(false);
+        }
+        verify_command
+            .execute(
+                options.path.as_ref(),
+                options.since,
+                options.force,
+                options.algorithm,
+                options.failed_only,
+            )
+            .await
+    }
+
+    /// Tracked, changed and untracked files and other repository statistics
+    pub async fn status(&self) -> Result<RepositoryStats> {
+        StatusCommand::new(&self.context).execute().await
+    }
+
+    /// Tracked and untracked files per top-level directory
+    pub async fn coverage(&self) -> Result<Vec<DirectoryCoverage>> {
+        CoverageCommand::new(&self.context).execute().await
+    }
+
+    /// Remove old history, deleted records and orphaned objects
+    pub async fn prune(&self, options: &PruneOptions) -> Result<PruneResult> {
+        PruneCommand::new(&self.context)
+            .execute(options.dry_run, options.target_free)
+            .await
+    }
+
+    /// Space prune would reclaim with `prune.retention_days` at each of
+    /// `retention_days` (and at the configured value), removing nothing
+    pub async fn prune_estimate(&self, retention_days: &[u32]) -> Result<PruneEstimate> {
+        PruneCommand::new(&self.context)
+            .estimate(retention_days)
+            .await
+    }
+
+    /// Run the repository health checks
+    pub async fn doctor(&self) -> Result<DoctorReport> {
+        DoctorCommand::new(&self.context).execute().await
+    }
+}
+
+/// Repositories for the tests of the commands
+#[cfg(test)]
+pub(crate) mod testing {
+    use super::Ddrive;
+    use crate::{config::Config, repository::Repository};
+    use assert_fs::{TempDir, prelude::*};
+
+    /// A new repository in a temporary directory holding `files`, given as
+    /// path and content pairs, none of them added yet
+    pub async fn repository(files: &[(&str, &str)]) -> (TempDir, Ddrive) {
+        configured_repository(files, |_| {}).await
+    }
+
+    /// Like [`repository`], opened with `configure` applied to its config
+    pub async fn configured_repository(
+        files: &[(&str, &str)],
+        configure: impl FnOnce(&mut Config) + Send + 'static,
+    ) -> (TempDir, Ddrive) {
+        let temp = TempDir::new().unwrap();
+        for (path, content) in files {
+            temp.child(path).write_str(content).unwrap();
+        }
+        Repository::init_repository(temp.path().to_path_buf())
+            .await
+            .unwrap();
+        let context = Ddrive::builder(temp.path())
+            .configure(configure)
+            .build()
+            .await
+            .unwrap();
+        (temp, Ddrive::from_context(context))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::testing::repository;
+    use super::*;
+    use assert_fs::TempDir;
+
+    #[tokio::test]
+    async fn test_add_status_and_verify() {
+        let (temp, ddrive) = repository(&[("photos/a.jpg", "a"), ("photos/b.jpg", "b")]).await;
+        let added = ddrive
+            .add(&[temp.path().join("photos")], &AddOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(added.new_files, 2);
+
+        let status = ddrive.status().await.unwrap();
+        assert_eq!(status.tracked_files, 2);
+
+        let verified = ddrive
+            .verify(&VerifyOptions {
+                force: true,
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        assert_eq!(verified.failed_files, 0);
+    }
+
+    #[tokio::test]
+    async fn test_builder_config_without_files() {
+        let temp = TempDir::new().unwrap();
+        Repository::init_repository(temp.path().to_path_buf())
+            .await
+            .unwrap();
+        std::fs::remove_file(temp.path().join(".ddrive/config.toml")).ok();
+
+        let context = Ddrive::builder(temp.path())
+            .config(Default::default())
+            .configure(|config| config.verify.interval_days = 7)
+            .build()
+            .await
+            .unwrap();
+        assert_eq!(context.config.verify.interval_days, 7);
+        assert!(!temp.path().join(".ddrive/config.toml").exists());
+    }
+}
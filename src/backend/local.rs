@@ -0,0 +1,86 @@
+use super::{ObjectBackend, StoredObject, object_path};
+use crate::{
+    DdriveError, Result,
+    scanner::{WalkFilter, get_all_files},
+};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Objects kept as plain files in a directory, reflinked from the tracked
+/// files where the file system supports it
+#[derive(Debug, Clone)]
+pub struct LocalBackend {
+    root: PathBuf,
+}
+
+impl LocalBackend {
+    pub fn new(root: PathBuf) -> Self {
+        Self { root }
+    }
+}
+
+impl ObjectBackend for LocalBackend {
+    fn location(&self) -> String {
+        self.root.display().to_string()
+    }
+
+    fn put(&self, checksum: &str, source: &Path) -> Result<bool> {
+        let target = object_path(&self.root, checksum);
+        if target.exists() {
+            return Ok(false);
+        }
+        let Some(dir) = target.parent() else {
+            return Err(DdriveError::InvalidDirectory);
+        };
+        fs::create_dir_all(dir).map_err(|e| DdriveError::FileSystem {
+            message: format!("Failed to create object directory: {e}"),
+        })?;
+        // Copied under a temporary name so a partial copy is never taken for the object
+        let partial = dir.join(format!(".{checksum}.partial"));
+        let copied = reflink_copy::reflink_or_copy(source, &partial)
+            .and_then(|_| fs::rename(&partial, &target));
+        if let Err(e) = copied {
+            let _ = fs::remove_file(&partial);
+            return Err(e.into());
+        }
+        Ok(true)
+    }
+
+    fn get(&self, checksum: &str, target: &Path) -> Result<()> {
+        reflink_copy::reflink_or_copy(object_path(&self.root, checksum), target)?;
+        Ok(())
+    }
+
+    fn exists(&self, checksum: &str) -> Result<bool> {
+        Ok(object_path(&self.root, checksum).exists())
+    }
+
+    fn delete(&self, checksum: &str) -> Result<bool> {
+        match fs::remove_file(object_path(&self.root, checksum)) {
+            Ok(()) => Ok(true),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn list(&self) -> Result<Vec<StoredObject>> {
+        let mut objects = Vec::new();
+        if !self.root.exists() {
+            return Ok(objects);
+        }
+        for file in get_all_files(&self.root, &self.root, WalkFilter::ALL)? {
+            let Some(name) = file.path.file_name().and_then(|name| name.to_str()) else {
+                continue;
+            };
+            objects.push(StoredObject {
+                checksum: name.to_string(),
+                size: file.size,
+            });
+        }
+        Ok(objects)
+    }
+
+    fn local_root(&self) -> Option<&Path> {
+        Some(&self.root)
+    }
+}
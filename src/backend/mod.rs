@@ -0,0 +1,222 @@
+//! Where stored objects live.
+//!
+//! Stored copies of tracked files are kept by an [`ObjectBackend`] under
+//! their BLAKE3 checksum. The backend is chosen by the scheme of
+//! `[object_store] url`: `file://` keeps objects as plain files (the default,
+//! in `.ddrive/objects`), `s3://` in an S3-compatible bucket. With
+//! `[object_store] cold_url`, a [`TieredBackend`] also finds the objects
+//! `tier` moved to the cold store.
+
+mod local;
+mod s3;
+mod tiered;
+
+pub use local::LocalBackend;
+pub use s3::S3Backend;
+pub use tiered::TieredBackend;
+
+use crate::{DdriveError, Result, config::ObjectStoreConfig, repository::Repository};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Counter keeping the names of objects fetched at the same time apart
+static FETCHED_OBJECTS: AtomicU64 = AtomicU64::new(0);
+
+/// An object held by a backend
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StoredObject {
+    pub checksum: String,
+    pub size: u64,
+}
+
+/// Storage for objects, addressed by checksum
+pub trait ObjectBackend: Send + Sync {
+    /// Where the objects are, for messages
+    fn location(&self) -> String;
+
+    /// Store the file at `source` as object `checksum`. Returns whether a new
+    /// object was created, or `false` if it was already stored.
+    fn put(&self, checksum: &str, source: &Path) -> Result<bool>;
+
+    /// Write object `checksum` to the file `target`
+    fn get(&self, checksum: &str, target: &Path) -> Result<()>;
+
+    fn exists(&self, checksum: &str) -> Result<bool>;
+
+    /// Delete object `checksum`. Returns whether it existed.
+    fn delete(&self, checksum: &str) -> Result<bool>;
+
+    /// Every object held, in no particular order. Anything else found where
+    /// objects are kept is listed under its name too.
+    fn list(&self) -> Result<Vec<StoredObject>>;
+
+    /// Directory of backends that keep objects as plain files on this
+    /// machine, so they can be reflinked, renamed into place and hashed
+    /// without a copy
+    fn local_root(&self) -> Option<&Path> {
+        None
+    }
+
+    /// Path of object `checksum`, for backends with a local root
+    fn local_path(&self, checksum: &str) -> Option<PathBuf> {
+        self.local_root().map(|root| object_path(root, checksum))
+    }
+}
+
+/// A backend shared by the commands of a context
+pub type SharedBackend = Arc<dyn ObjectBackend>;
+
+/// Opens a backend from the rest of its URL after `scheme://`
+type Opener = fn(&str, &Repository, &ObjectStoreConfig) -> Result<SharedBackend>;
+
+/// Backends by URL scheme
+const BACKENDS: &[(&str, Opener)] = &[("file", open_local), ("s3", open_s3)];
+
+/// `file:///path`, relative to the repository root unless absolute
+fn open_local(path: &str, repo: &Repository, _: &ObjectStoreConfig) -> Result<SharedBackend> {
+    Ok(Arc::new(LocalBackend::new(repo.root().join(path))))
+}
+
+/// `s3://bucket/prefix`
+fn open_s3(location: &str, _: &Repository, config: &ObjectStoreConfig) -> Result<SharedBackend> {
+    Ok(Arc::new(S3Backend::from_location(location, config)?))
+}
+
+/// Open the backend configured by `[object_store] url`, or the local object
+/// store in `.ddrive/objects` if none is, backed by the cold store if
+/// `cold_url` is set
+pub fn open(repo: &Repository, config: &ObjectStoreConfig) -> Result<SharedBackend> {
+    let (hot, cold) = open_tiers(repo, config)?;
+    Ok(match cold {
+        Some(cold) => Arc::new(TieredBackend::new(hot, cold)),
+        None => hot,
+    })
+}
+
+/// Open the object store and the cold store separately, for moving objects
+/// between them
+pub fn open_tiers(
+    repo: &Repository,
+    config: &ObjectStoreConfig,
+) -> Result<(SharedBackend, Option<SharedBackend>)> {
+    let hot = match &config.url {
+        Some(url) => open_url(url, repo, config)?,
+        None => Arc::new(LocalBackend::new(repo.objects_root())),
+    };
+    let cold = config
+        .cold_url
+        .as_deref()
+        .map(|url| open_url(url, repo, config))
+        .transpose()?;
+    Ok((hot, cold))
+}
+
+fn open_url(url: &str, repo: &Repository, config: &ObjectStoreConfig) -> Result<SharedBackend> {
+    let invalid = |message: String| DdriveError::Configuration { message };
+    let (scheme, location) = url.split_once("://").ok_or_else(|| {
+        invalid(format!(
+            "object store URL '{url}' has no scheme, e.g. file:///mnt/objects"
+        ))
+    })?;
+    let (_, opener) = BACKENDS
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case(scheme))
+        .ok_or_else(|| {
+            let schemes: Vec<&str> = BACKENDS.iter().map(|(name, _)| *name).collect();
+            invalid(format!(
+                "Unknown object store scheme '{scheme}' (supported: {})",
+                schemes.join(", ")
+            ))
+        })?;
+    opener(location, repo, config)
+}
+
+/// Path of object `checksum` under `root`, fanned out by the first two
+/// pairs of characters
+pub fn object_path(root: &Path, checksum: &str) -> PathBuf {
+    root.join(object_key(checksum))
+}
+
+/// Key of object `checksum` relative to the root of a backend
+pub fn object_key(checksum: &str) -> String {
+    format!("{}/{}/{}", &checksum[0..2], &checksum[2..4], checksum)
+}
+
+/// Run `read` on the content of object `checksum`: in place for local
+/// backends, or on a copy fetched into `tmp_dir` that is removed afterwards.
+/// Returns `None` if the object is not stored.
+pub fn with_object<T>(
+    backend: &dyn ObjectBackend,
+    checksum: &str,
+    tmp_dir: &Path,
+    read: impl FnOnce(&Path) -> Result<T>,
+) -> Result<Option<T>> {
+    if !backend.exists(checksum)? {
+        return Ok(None);
+    }
+    // Objects in a cold store have no local path even if the object store does
+    if let Some(path) = backend.local_path(checksum).filter(|path| path.exists()) {
+        return read(&path).map(Some);
+    }
+
+    std::fs::create_dir_all(tmp_dir)?;
+    let fetched = tmp_dir.join(format!(
+        "fetch-{}-{}",
+        std::process::id(),
+        FETCHED_OBJECTS.fetch_add(1, Ordering::Relaxed)
+    ));
+    let result = backend
+        .get(checksum, &fetched)
+        .and_then(|()| read(&fetched));
+    let _ = std::fs::remove_file(&fetched);
+    result.map(Some)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        AddOptions, Ddrive, DdriveError, VerifyOptions, api::testing::configured_repository,
+    };
+    use assert_fs::TempDir;
+
+    #[tokio::test]
+    async fn test_object_store_url() {
+        let store = TempDir::new().unwrap();
+        let url = format!("file://{}", store.path().display());
+        let (temp, ddrive) = configured_repository(&[("photos/a.jpg", "a")], move |config| {
+            config.object_store.url = Some(url)
+        })
+        .await;
+        ddrive
+            .add(&[temp.path()], &AddOptions::default())
+            .await
+            .unwrap();
+
+        let b3sum = blake3::hash(b"a").to_hex().to_string();
+        assert!(object_path(store.path(), &b3sum).exists());
+        assert!(!ddrive.context().repo.object_dir(&b3sum).exists());
+        let objects = ddrive.context().objects.list().unwrap();
+        assert_eq!(objects.len(), 1);
+        assert_eq!(
+            (objects[0].checksum.as_str(), objects[0].size),
+            (b3sum.as_str(), 1)
+        );
+
+        let options = VerifyOptions {
+            objects: true,
+            force: true,
+            ..Default::default()
+        };
+        let verified = ddrive.verify(&options).await.unwrap();
+        assert_eq!(verified.checked_objects, 1);
+        assert!(verified.object_failures.is_empty());
+
+        let unknown = Ddrive::builder(temp.path())
+            .configure(|config| config.object_store.url = Some("ftp://host/objects".to_string()))
+            .build()
+            .await;
+        assert!(matches!(unknown, Err(DdriveError::Configuration { .. })));
+    }
+}
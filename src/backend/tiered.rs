@@ -0,0 +1,58 @@
+use super::{ObjectBackend, SharedBackend, StoredObject};
+use crate::Result;
+use std::path::Path;
+
+/// The object store with a cold store behind it. New objects go to the hot
+/// store; objects moved to the cold one by `tier` are still found, fetched
+/// and deleted through it.
+pub struct TieredBackend {
+    hot: SharedBackend,
+    cold: SharedBackend,
+}
+
+impl TieredBackend {
+    pub fn new(hot: SharedBackend, cold: SharedBackend) -> Self {
+        Self { hot, cold }
+    }
+}
+
+impl ObjectBackend for TieredBackend {
+    fn location(&self) -> String {
+        format!("{} (cold: {})", self.hot.location(), self.cold.location())
+    }
+
+    fn put(&self, checksum: &str, source: &Path) -> Result<bool> {
+        if !self.hot.exists(checksum)? && self.cold.exists(checksum)? {
+            return Ok(false);
+        }
+        self.hot.put(checksum, source)
+    }
+
+    fn get(&self, checksum: &str, target: &Path) -> Result<()> {
+        if self.hot.exists(checksum)? {
+            self.hot.get(checksum, target)
+        } else {
+            self.cold.get(checksum, target)
+        }
+    }
+
+    fn exists(&self, checksum: &str) -> Result<bool> {
+        Ok(self.hot.exists(checksum)? || self.cold.exists(checksum)?)
+    }
+
+    fn delete(&self, checksum: &str) -> Result<bool> {
+        let hot = self.hot.delete(checksum)?;
+        let cold = self.cold.delete(checksum)?;
+        Ok(hot || cold)
+    }
+
+    fn list(&self) -> Result<Vec<StoredObject>> {
+        let mut objects = self.hot.list()?;
+        objects.extend(self.cold.list()?);
+        Ok(objects)
+    }
+
+    fn local_root(&self) -> Option<&Path> {
+        self.hot.local_root()
+    }
+}
@@ -1,48 +1,267 @@
-use crate::{DdriveError, Result};
+use crate::{DdriveError, Result, config::ChecksumConfig};
 use blake3::Hasher;
+use clap::ValueEnum;
+use memmap2::Mmap;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use std::fs::File;
-use std::io::{BufReader, Read};
+use std::io::{Read, Write};
 use std::path::Path;
+use strum::{Display, EnumString};
 use tracing::debug;
+use xxhash_rust::xxh3::Xxh3;
 
-/// Default buffer size for checksum calculation (8KB)
-const DEFAULT_BUFFER_SIZE: usize = 8192;
+/// Smallest read buffer used for checksum calculation (8KB)
+const MIN_BUFFER_SIZE: usize = 8 * 1024;
 
-/// Calculator for BLAKE3 checksums with configurable buffer size
-pub struct ChecksumCalculator {
-    buffer_size: usize,
+/// Largest adaptive read buffer (1MB); beyond this fewer syscalls stop paying off
+const MAX_BUFFER_SIZE: usize = 1024 * 1024;
+
+/// Pick a read buffer size for a file of `file_size` bytes: small files get a
+/// buffer just large enough to be read in one call, large files the maximum
+/// so network filesystems are not hit with thousands of tiny reads.
+pub fn adaptive_buffer_size(file_size: u64) -> usize {
+    usize::try_from(file_size)
+        .unwrap_or(MAX_BUFFER_SIZE)
+        .saturating_add(1)
+        .next_power_of_two()
+        .clamp(MIN_BUFFER_SIZE, MAX_BUFFER_SIZE)
 }
 
-impl Default for ChecksumCalculator {
-    fn default() -> Self {
-        ChecksumCalculator {
-            buffer_size: DEFAULT_BUFFER_SIZE,
+/// Hash algorithms ddrive can record. BLAKE3 is always used for content
+/// addressing; the others are optional secondary checksums for cross-checking
+/// against published SHA256SUMS files or cloud ETags.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ValueEnum, Display, EnumString,
+)]
+#[serde(rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase")]
+pub enum HashAlgorithm {
+    Blake3,
+    Sha256,
+    Xxh3,
+}
+
+/// Secondary checksums recorded alongside the BLAKE3 checksum
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SecondaryChecksums {
+    pub sha256: Option<String>,
+    pub xxh3: Option<String>,
+    /// Keyed BLAKE3 MAC, when a MAC key is configured
+    pub mac: Option<String>,
+    /// BLAKE3 hashes of consecutive fixed-size chunks, for large files
+    pub chunks: Option<ChunkHashes>,
+}
+
+/// Per-chunk BLAKE3 hashes of a file; the last chunk may be shorter
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChunkHashes {
+    pub chunk_size: u64,
+    pub hashes: Vec<String>,
+}
+
+impl ChunkHashes {
+    /// Byte ranges (start, end exclusive) of chunks whose hash differs from
+    /// `other`, given the total file size
+    pub fn damaged_ranges(&self, other: &[String], file_size: u64) -> Vec<(u64, u64)> {
+        let mut ranges: Vec<(u64, u64)> = Vec::new();
+        let count = self.hashes.len().max(other.len());
+        for index in 0..count {
+            if self.hashes.get(index) == other.get(index) {
+                continue;
+            }
+            let start = index as u64 * self.chunk_size;
+            let end = (start + self.chunk_size).min(file_size.max(start));
+            match ranges.last_mut() {
+                Some(last) if last.1 == start => last.1 = end,
+                _ => ranges.push((start, end)),
+            }
+        }
+        ranges
+    }
+}
+
+impl SecondaryChecksums {
+    /// Recorded checksum for a secondary algorithm
+    pub fn get(&self, algorithm: HashAlgorithm) -> Option<&str> {
+        match algorithm {
+            HashAlgorithm::Blake3 => None,
+            HashAlgorithm::Sha256 => self.sha256.as_deref(),
+            HashAlgorithm::Xxh3 => self.xxh3.as_deref(),
         }
     }
 }
 
+/// Calculator for BLAKE3 checksums with configurable buffer size
+#[derive(Default)]
+pub struct ChecksumCalculator {
+    /// Fixed read buffer size; `None` sizes the buffer per file
+    buffer_size: Option<usize>,
+    /// Files at least this large are memory-mapped and hashed on all cores
+    mmap_threshold: Option<u64>,
+    /// Secondary algorithms computed by `calculate_checksums`
+    secondary: Vec<HashAlgorithm>,
+    /// Key for the keyed BLAKE3 MAC computed by `calculate_checksums`
+    mac_key: Option<[u8; 32]>,
+    /// Chunk size and minimum file size for chunk hashes in `calculate_checksums`
+    chunking: Option<(u64, u64)>,
+}
+
 impl ChecksumCalculator {
-    /// Create a new checksum calculator with default 8KB buffer
+    /// Create a new checksum calculator that sizes its buffer per file
     pub fn new() -> Self {
         Self::default()
     }
 
     /// Create a new checksum calculator with custom buffer size
     pub fn with_buffer_size(buffer_size: usize) -> Self {
-        ChecksumCalculator { buffer_size }
+        ChecksumCalculator {
+            buffer_size: Some(buffer_size.max(1)),
+            ..Self::default()
+        }
+    }
+
+    /// Create a checksum calculator from the repository configuration
+    pub fn from_config(config: &ChecksumConfig) -> Self {
+        ChecksumCalculator {
+            buffer_size: config.buffer_size.map(|size| size.max(1)),
+            mmap_threshold: config.mmap.then_some(config.mmap_threshold),
+            secondary: config.secondary.clone(),
+            mac_key: config.mac_key,
+            chunking: config
+                .chunk_hashes
+                .then_some((config.chunk_size.max(1), config.chunk_min_file_size)),
+        }
+    }
+
+    /// Whether `calculate_checksums` computes anything beyond BLAKE3 for a
+    /// file of `file_size` bytes
+    pub fn has_secondary(&self, file_size: u64) -> bool {
+        !self.secondary.is_empty() || self.mac_key.is_some() || self.chunk_size(file_size).is_some()
+    }
+
+    fn chunk_size(&self, file_size: u64) -> Option<u64> {
+        self.chunking
+            .filter(|&(_, min_file_size)| file_size >= min_file_size)
+            .map(|(chunk_size, _)| chunk_size)
     }
 
     /// Calculate BLAKE3 checksum for a file
     pub fn calculate_checksum<P: AsRef<Path>>(&self, file_path: P) -> Result<String> {
+        let (checksum, _) = self.hash_file(file_path.as_ref(), &[], None, None, None)?;
+        Ok(checksum)
+    }
+
+    /// Calculate BLAKE3 hashes of consecutive `chunk_size` chunks of a file
+    pub fn calculate_chunk_hashes<P: AsRef<Path>>(
+        &self,
+        file_path: P,
+        chunk_size: u64,
+    ) -> Result<Vec<String>> {
+        let (_, secondary) =
+            self.hash_file(file_path.as_ref(), &[], None, Some(chunk_size), None)?;
+        Ok(secondary.chunks.map(|c| c.hashes).unwrap_or_default())
+    }
+
+    /// Calculate the BLAKE3 checksum and the configured secondary checksums
+    /// in a single pass over the file
+    pub fn calculate_checksums<P: AsRef<Path>>(
+        &self,
+        file_path: P,
+    ) -> Result<(String, SecondaryChecksums)> {
+        let file_path = file_path.as_ref();
+        let file_size = std::fs::metadata(file_path).map(|m| m.len()).unwrap_or(0);
+        self.hash_file(
+            file_path,
+            &self.secondary,
+            self.mac_key.as_ref(),
+            self.chunk_size(file_size),
+            None,
+        )
+    }
+
+    /// Copy a file to `writer` while calculating the same checksums as
+    /// `calculate_checksums`, so the file is only read once
+    pub fn copy_with_checksums<P: AsRef<Path>, W: Write>(
+        &self,
+        file_path: P,
+        writer: &mut W,
+    ) -> Result<(String, SecondaryChecksums)> {
         let file_path = file_path.as_ref();
+        let file_size = std::fs::metadata(file_path).map(|m| m.len()).unwrap_or(0);
+        self.hash_file(
+            file_path,
+            &self.secondary,
+            self.mac_key.as_ref(),
+            self.chunk_size(file_size),
+            Some(writer),
+        )
+    }
+
+    /// Calculate the checksum of a file with a specific algorithm
+    pub fn calculate_with<P: AsRef<Path>>(
+        &self,
+        file_path: P,
+        algorithm: HashAlgorithm,
+    ) -> Result<String> {
+        let (b3sum, secondary) =
+            self.hash_file(file_path.as_ref(), &[algorithm], None, None, None)?;
+        Ok(match algorithm {
+            HashAlgorithm::Blake3 => b3sum,
+            other => secondary.get(other).unwrap_or_default().to_string(),
+        })
+    }
 
-        let file = File::open(file_path).map_err(|e| DdriveError::Checksum {
-            message: format!("Could not open file {}: {}", file_path.display(), e),
+    fn hash_file(
+        &self,
+        file_path: &Path,
+        secondary: &[HashAlgorithm],
+        mac_key: Option<&[u8; 32]>,
+        chunk_size: Option<u64>,
+        mut sink: Option<&mut dyn Write>,
+    ) -> Result<(String, SecondaryChecksums)> {
+        let write_error = |e: std::io::Error| DdriveError::FileSystem {
+            message: format!("Could not copy file {}: {}", file_path.display(), e),
+        };
+        let file = File::open(file_path).map_err(|e| {
+            let message = format!("Could not open file {}: {}", file_path.display(), e);
+            match e.kind() {
+                std::io::ErrorKind::PermissionDenied => DdriveError::PermissionDenied { message },
+                _ => DdriveError::Checksum { message },
+            }
         })?;
 
-        let mut reader = BufReader::new(file);
-        let mut hasher = Hasher::new();
-        let mut buffer = vec![0; self.buffer_size];
+        let mut hasher = MultiHasher::new(secondary, mac_key, chunk_size);
+        let len = file.metadata().map(|m| m.len()).unwrap_or(0);
+        if self
+            .mmap_threshold
+            .is_some_and(|threshold| len >= threshold)
+        {
+            // SAFETY: the map is only read while hashing. A concurrent truncation
+            // can fault the process, the same risk blake3's own mmap helper takes.
+            match unsafe { Mmap::map(&file) } {
+                Ok(map) => {
+                    if let Some(sink) = sink {
+                        sink.write_all(&map).map_err(write_error)?;
+                        sink.flush().map_err(write_error)?;
+                    }
+                    hasher.update_rayon(&map);
+                    return Ok(hasher.finish());
+                }
+                Err(e) => debug!(
+                    "Could not mmap {}, falling back to buffered reads: {}",
+                    file_path.display(),
+                    e
+                ),
+            }
+        }
+
+        let mut reader = file;
+        let buffer_size = self
+            .buffer_size
+            .unwrap_or_else(|| adaptive_buffer_size(len));
+        let mut buffer = vec![0; buffer_size];
 
         loop {
             let bytes_read = reader
@@ -55,13 +274,153 @@ impl ChecksumCalculator {
                 break;
             }
 
+            if let Some(sink) = sink.as_mut() {
+                sink.write_all(&buffer[..bytes_read]).map_err(write_error)?;
+            }
             hasher.update(&buffer[..bytes_read]);
         }
+        if let Some(sink) = sink {
+            sink.flush().map_err(write_error)?;
+        }
+
+        Ok(hasher.finish())
+    }
+}
+
+/// Feeds the same data to BLAKE3 and any enabled secondary hashers
+struct MultiHasher {
+    blake3: Hasher,
+    sha256: Option<Sha256>,
+    xxh3: Option<Xxh3>,
+    mac: Option<Hasher>,
+    chunks: Option<ChunkHasher>,
+}
+
+impl MultiHasher {
+    fn new(
+        secondary: &[HashAlgorithm],
+        mac_key: Option<&[u8; 32]>,
+        chunk_size: Option<u64>,
+    ) -> Self {
+        Self {
+            blake3: Hasher::new(),
+            mac: mac_key.map(Hasher::new_keyed),
+            chunks: chunk_size.map(ChunkHasher::new),
+            sha256: secondary.contains(&HashAlgorithm::Sha256).then(Sha256::new),
+            xxh3: secondary.contains(&HashAlgorithm::Xxh3).then(Xxh3::new),
+        }
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        self.blake3.update(data);
+        if let Some(mac) = &mut self.mac {
+            mac.update(data);
+        }
+        if let Some(chunks) = &mut self.chunks {
+            chunks.update(data);
+        }
+        self.update_secondary(data);
+    }
+
+    fn update_rayon(&mut self, data: &[u8]) {
+        self.blake3.update_rayon(data);
+        if let Some(mac) = &mut self.mac {
+            mac.update_rayon(data);
+        }
+        if let Some(chunks) = &mut self.chunks {
+            chunks.update_rayon(data);
+        }
+        if self.sha256.is_some() || self.xxh3.is_some() {
+            self.update_secondary(data);
+        }
+    }
+
+    fn update_secondary(&mut self, data: &[u8]) {
+        if let Some(sha256) = &mut self.sha256 {
+            sha256.update(data);
+        }
+        if let Some(xxh3) = &mut self.xxh3 {
+            xxh3.update(data);
+        }
+    }
 
-        let hash = hasher.finalize();
-        let checksum = hash.to_hex().to_string();
+    fn finish(self) -> (String, SecondaryChecksums) {
+        let checksum = self.blake3.finalize().to_hex().to_string();
         debug!("Calculated checksum: {}", &checksum[..16]);
-        Ok(checksum)
+        let secondary = SecondaryChecksums {
+            sha256: self.sha256.map(|sha256| {
+                sha256
+                    .finalize()
+                    .iter()
+                    .map(|b| format!("{b:02x}"))
+                    .collect()
+            }),
+            xxh3: self.xxh3.map(|xxh3| format!("{:016x}", xxh3.digest())),
+            mac: self.mac.map(|mac| mac.finalize().to_hex().to_string()),
+            chunks: self.chunks.map(ChunkHasher::finish),
+        };
+        (checksum, secondary)
+    }
+}
+
+/// Splits a stream into fixed-size chunks and hashes each one
+struct ChunkHasher {
+    chunk_size: u64,
+    current: Hasher,
+    filled: u64,
+    hashes: Vec<String>,
+}
+
+impl ChunkHasher {
+    fn new(chunk_size: u64) -> Self {
+        Self {
+            chunk_size,
+            current: Hasher::new(),
+            filled: 0,
+            hashes: Vec::new(),
+        }
+    }
+
+    fn update(&mut self, mut data: &[u8]) {
+        while !data.is_empty() {
+            let take = (self.chunk_size - self.filled).min(data.len() as u64) as usize;
+            self.current.update(&data[..take]);
+            self.filled += take as u64;
+            data = &data[take..];
+            if self.filled == self.chunk_size {
+                self.hashes
+                    .push(self.current.finalize().to_hex().to_string());
+                self.current.reset();
+                self.filled = 0;
+            }
+        }
+    }
+
+    /// Hash whole chunks of a memory-mapped file in parallel
+    fn update_rayon(&mut self, data: &[u8]) {
+        if self.filled != 0 {
+            return self.update(data);
+        }
+        let chunk_size = usize::try_from(self.chunk_size).unwrap_or(usize::MAX);
+        let whole = data.len() - data.len() % chunk_size;
+        self.hashes.extend(
+            data[..whole]
+                .par_chunks(chunk_size)
+                .map(|chunk| blake3::hash(chunk).to_hex().to_string())
+                .collect::<Vec<_>>(),
+        );
+        self.update(&data[whole..]);
+    }
+
+    fn finish(mut self) -> ChunkHashes {
+        if self.filled > 0 || self.hashes.is_empty() {
+            self.hashes
+                .push(self.current.finalize().to_hex().to_string());
+        }
+        ChunkHashes {
+            chunk_size: self.chunk_size,
+            hashes: self.hashes,
+        }
     }
 }
 
@@ -103,6 +462,212 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_calculate_checksum_mmap_matches_buffered() {
+        let temp_dir = TempDir::new().unwrap();
+        let file_path = temp_dir.path().join("large.bin");
+        let content: Vec<u8> = (0..3_000_000u32).map(|i| (i % 251) as u8).collect();
+        fs::write(&file_path, &content).unwrap();
+
+        let mmap_calculator = ChecksumCalculator::from_config(&ChecksumConfig {
+            mmap: true,
+            mmap_threshold: 0,
+            ..Default::default()
+        });
+        let buffered = ChecksumCalculator::new()
+            .calculate_checksum(&file_path)
+            .unwrap();
+
+        assert_eq!(
+            mmap_calculator.calculate_checksum(&file_path).unwrap(),
+            buffered
+        );
+        assert_eq!(buffered, blake3::hash(&content).to_hex().to_string());
+    }
+
+    #[test]
+    fn test_copy_with_checksums() {
+        let temp_dir = TempDir::new().unwrap();
+        let file_path = temp_dir.path().join("large.bin");
+        let content: Vec<u8> = (0..300_000u32).map(|i| (i % 241) as u8).collect();
+        fs::write(&file_path, &content).unwrap();
+        let expected = blake3::hash(&content).to_hex().to_string();
+
+        for mmap_threshold in [0, u64::MAX] {
+            let calculator = ChecksumCalculator::from_config(&ChecksumConfig {
+                mmap: true,
+                mmap_threshold,
+                ..Default::default()
+            });
+            let mut copy = Vec::new();
+            let (checksum, _) = calculator
+                .copy_with_checksums(&file_path, &mut copy)
+                .unwrap();
+            assert_eq!(checksum, expected);
+            assert_eq!(copy, content);
+        }
+    }
+
+    #[test]
+    fn test_adaptive_buffer_size() {
+        assert_eq!(adaptive_buffer_size(0), MIN_BUFFER_SIZE);
+        assert_eq!(adaptive_buffer_size(100), MIN_BUFFER_SIZE);
+        assert_eq!(adaptive_buffer_size(100_000), 128 * 1024);
+        assert_eq!(
+            adaptive_buffer_size(10 * 1024 * 1024 * 1024),
+            MAX_BUFFER_SIZE
+        );
+    }
+
+    #[test]
+    fn test_fixed_buffer_size_matches_adaptive() {
+        let temp_dir = TempDir::new().unwrap();
+        let file_path = temp_dir.path().join("data.bin");
+        let content: Vec<u8> = (0..200_000u32).map(|i| (i % 253) as u8).collect();
+        fs::write(&file_path, &content).unwrap();
+
+        let adaptive = ChecksumCalculator::new()
+            .calculate_checksum(&file_path)
+            .unwrap();
+        for buffer_size in [1, 4096, 65536] {
+            let fixed = ChecksumCalculator::with_buffer_size(buffer_size)
+                .calculate_checksum(&file_path)
+                .unwrap();
+            assert_eq!(fixed, adaptive);
+        }
+    }
+
+    /// Throughput regression check: adaptive sizing must not be slower than
+    /// the old fixed 8KB buffer. Timing-sensitive, so run explicitly with
+    /// `cargo test --release -- --ignored bench_`.
+    #[test]
+    #[ignore]
+    fn bench_adaptive_buffer_vs_8kb() {
+        use std::time::Instant;
+
+        let temp_dir = TempDir::new().unwrap();
+        let file_path = temp_dir.path().join("bench.bin");
+        let content: Vec<u8> = (0..64 * 1024 * 1024u32).map(|i| i as u8).collect();
+        fs::write(&file_path, &content).unwrap();
+
+        let time = |calculator: &ChecksumCalculator| {
+            let start = Instant::now();
+            for _ in 0..3 {
+                calculator.calculate_checksum(&file_path).unwrap();
+            }
+            start.elapsed()
+        };
+        let fixed = time(&ChecksumCalculator::with_buffer_size(MIN_BUFFER_SIZE));
+        let adaptive = time(&ChecksumCalculator::new());
+        println!("8KB buffer: {fixed:?}, adaptive buffer: {adaptive:?}");
+
+        assert!(adaptive <= fixed.mul_f64(1.1));
+    }
+
+    #[test]
+    fn test_secondary_checksums() {
+        let temp_dir = TempDir::new().unwrap();
+        let file_path = temp_dir.path().join("test.txt");
+        fs::write(&file_path, "Hello, World!").unwrap();
+
+        let calculator = ChecksumCalculator::from_config(&ChecksumConfig {
+            secondary: vec![HashAlgorithm::Sha256, HashAlgorithm::Xxh3],
+            ..Default::default()
+        });
+        let (b3sum, secondary) = calculator.calculate_checksums(&file_path).unwrap();
+
+        assert_eq!(
+            b3sum,
+            "288a86a79f20a3d6dccdca7713beaed178798296bdfa7913fa2a62d9727bf8f8"
+        );
+        assert_eq!(
+            secondary.sha256.as_deref(),
+            Some("dffd6021bb2bd5b0af676290809ec3a53191dd81c7f70a4b28688a362182986f")
+        );
+        assert_eq!(
+            secondary.xxh3,
+            Some(format!(
+                "{:016x}",
+                xxhash_rust::xxh3::xxh3_64(b"Hello, World!")
+            ))
+        );
+    }
+
+    #[test]
+    fn test_keyed_mac() {
+        let temp_dir = TempDir::new().unwrap();
+        let file_path = temp_dir.path().join("test.txt");
+        fs::write(&file_path, "Hello, World!").unwrap();
+
+        let key = [7u8; 32];
+        let calculator = ChecksumCalculator::from_config(&ChecksumConfig {
+            mac_key: Some(key),
+            ..Default::default()
+        });
+        let (_, secondary) = calculator.calculate_checksums(&file_path).unwrap();
+
+        assert_eq!(
+            secondary.mac,
+            Some(
+                blake3::keyed_hash(&key, b"Hello, World!")
+                    .to_hex()
+                    .to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn test_chunk_hashes_locate_damage() {
+        let temp_dir = TempDir::new().unwrap();
+        let file_path = temp_dir.path().join("large.bin");
+        let mut content = vec![0u8; 10_000];
+        fs::write(&file_path, &content).unwrap();
+
+        let calculator = ChecksumCalculator::new();
+        let original = ChunkHashes {
+            chunk_size: 4096,
+            hashes: calculator.calculate_chunk_hashes(&file_path, 4096).unwrap(),
+        };
+        assert_eq!(original.hashes.len(), 3);
+        assert_eq!(
+            original.hashes[0],
+            blake3::hash(&content[..4096]).to_hex().to_string()
+        );
+
+        content[5000] = 1;
+        fs::write(&file_path, &content).unwrap();
+        let damaged = calculator.calculate_chunk_hashes(&file_path, 4096).unwrap();
+        assert_eq!(
+            original.damaged_ranges(&damaged, 10_000),
+            vec![(4096, 8192)]
+        );
+    }
+
+    #[test]
+    fn test_chunk_hashes_mmap_matches_buffered() {
+        let temp_dir = TempDir::new().unwrap();
+        let file_path = temp_dir.path().join("large.bin");
+        let content: Vec<u8> = (0..100_000u32).map(|i| (i % 249) as u8).collect();
+        fs::write(&file_path, &content).unwrap();
+
+        let config = ChecksumConfig {
+            chunk_size: 4096,
+            chunk_min_file_size: 0,
+            ..Default::default()
+        };
+        let buffered = ChecksumCalculator::from_config(&config)
+            .calculate_checksums(&file_path)
+            .unwrap();
+        let mapped = ChecksumCalculator::from_config(&ChecksumConfig {
+            mmap_threshold: 0,
+            ..config
+        })
+        .calculate_checksums(&file_path)
+        .unwrap();
+        assert_eq!(buffered, mapped);
+        assert_eq!(buffered.1.chunks.unwrap().hashes.len(), 25);
+    }
+
     #[test]
     fn test_calculate_checksum_nonexistent_file() {
         let calculator = ChecksumCalculator::new();
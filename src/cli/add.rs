@@ -6,24 +6,157 @@
 //! with CoW if supported.
 
 use crate::{
-    AppContext, DdriveError, Result,
-    scanner::{FileInfo, FileScanner},
-    utils::FileProcessor,
+    AppContext, DdriveError, Operation, Result, ResultExt,
+    checksum::SecondaryChecksums,
+    cli::summary,
+    codes,
+    config::ObjectStoreMode,
+    content_type,
+    database::{ActionType, FileRecord, Problem},
+    device, logging,
+    metadata::FileAttributes,
+    progress::ProgressEvent,
+    scanner::{FileInfo, FileScanner, Scan},
+    utils::{self, DirectoryMove, FileChange, FileProcessor, TrackedDirectories, to_storage_path},
 };
+use futures_util::{Stream, TryStreamExt, future, stream};
+use std::collections::{BTreeSet, HashSet};
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Instant, SystemTime};
+use strum::Display;
 use tracing::{debug, error, info, warn};
 
-#[derive(Debug)]
+/// Number of times a file that changes while being stored is retried
+const STABILITY_RETRIES: usize = 2;
+
+/// Counter for unique staging file names within this process
+static STAGED_OBJECTS: AtomicU64 = AtomicU64::new(0);
+
+#[derive(Debug, Default)]
 pub struct AddResult {
     pub new_files: usize,
     pub changed_files: usize,
     pub renamed_files: usize,
+    /// Directories that moved as a whole; their files count as renamed
+    pub moved_directories: usize,
+    /// Files that kept changing while being hashed/copied and were not recorded
+    pub unstable_files: Vec<PathBuf>,
+    /// Files that could not be hashed or stored
+    pub failed: Vec<AddFailure>,
+    /// Files and folders skipped because permission to read them was denied
+    pub unreadable: Vec<AddFailure>,
+    /// History action the changes were recorded under, if any were
+    pub action_id: Option<i64>,
+}
+
+impl AddResult {
+    /// Fold the result of adding another path into this one
+    pub fn merge(&mut self, other: AddResult) {
+        self.new_files += other.new_files;
+        self.changed_files += other.changed_files;
+        self.renamed_files += other.renamed_files;
+        self.moved_directories += other.moved_directories;
+        self.unstable_files.extend(other.unstable_files);
+        self.failed.extend(other.failed);
+        self.unreadable.extend(other.unreadable);
+        self.action_id = other.action_id.or(self.action_id);
+    }
+
+    /// Details for `--summary-file`
+    pub fn summary(&self) -> serde_json::Value {
+        let failures = |failures: &[AddFailure]| -> Vec<_> {
+            failures
+                .iter()
+                .map(|failure| {
+                    serde_json::json!({
+                        "path": failure.path,
+                        "kind": failure.kind.to_string(),
+                        "message": failure.message,
+                    })
+                })
+                .collect()
+        };
+        serde_json::json!({
+            "action_id": summary::action_id_json(self.action_id),
+            "new_files": self.new_files,
+            "changed_files": self.changed_files,
+            "renamed_files": self.renamed_files,
+            "moved_directories": self.moved_directories,
+            "unstable_files": self.unstable_files,
+            "failed": failures(&self.failed),
+            "unreadable": failures(&self.unreadable),
+        })
+    }
+
+    fn record_failure(&mut self, path: &Path, error: &DdriveError) {
+        warn!(code = %codes::STORE_FAILED, "Failed to store {}: {}", path.display(), error);
+        let failure = AddFailure {
+            path: path.to_path_buf(),
+            kind: FailureKind::of(error),
+            message: error.to_string(),
+        };
+        if failure.kind == FailureKind::PermissionDenied {
+            self.unreadable.push(failure);
+        } else {
+            self.failed.push(failure);
+        }
+    }
+
+    /// Note the entries a scan was not allowed to read
+    fn record_unreadable(&mut self, scan: &Scan) {
+        self.unreadable
+            .extend(scan.unreadable.iter().map(|entry| AddFailure {
+                path: entry.path.clone(),
+                kind: FailureKind::PermissionDenied,
+                message: entry.message.clone(),
+            }));
+    }
+}
+
+/// A file that could not be protected by `add`
+#[derive(Debug, Clone)]
+pub struct AddFailure {
+    pub path: PathBuf,
+    pub kind: FailureKind,
+    pub message: String,
+}
+
+/// Why a file could not be protected
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Display)]
+#[strum(serialize_all = "snake_case")]
+pub enum FailureKind {
+    /// The file disappeared before it could be stored
+    NotFound,
+    /// The file could not be read
+    PermissionDenied,
+    /// Reading or hashing the file failed
+    Checksum,
+    /// Writing to the object store failed
+    Storage,
+}
+
+impl FailureKind {
+    fn of(error: &DdriveError) -> Self {
+        match error.root() {
+            DdriveError::Io(e) => match e.kind() {
+                std::io::ErrorKind::NotFound => FailureKind::NotFound,
+                std::io::ErrorKind::PermissionDenied => FailureKind::PermissionDenied,
+                _ => FailureKind::Storage,
+            },
+            DdriveError::PermissionDenied { .. } => FailureKind::PermissionDenied,
+            DdriveError::Checksum { .. } => FailureKind::Checksum,
+            _ => FailureKind::Storage,
+        }
+    }
 }
 
 pub struct AddCommand<'a> {
     context: &'a AppContext,
     processor: FileProcessor<'a>,
+    /// Record low-confidence renames without asking
+    confirm_renames: bool,
 }
 
 impl<'a> AddCommand<'a> {
@@ -31,16 +164,29 @@ impl<'a> AddCommand<'a> {
         AddCommand {
             context,
             processor: FileProcessor::new(context),
+            confirm_renames: false,
         }
     }
 
+    /// Record renames matched only by size and creation time without asking.
+    /// Otherwise they are confirmed on the terminal, or recorded as deleted
+    /// and new files when there is none.
+    pub fn confirm_renames(mut self, confirm_renames: bool) -> Self {
+        self.confirm_renames = confirm_renames;
+        self
+    }
+
     /// Execute the complete file tracking workflow
     pub async fn execute<P: AsRef<Path>>(&self, path: P) -> Result<AddResult> {
+        let started = Instant::now();
         let repo_root = &self.context.repo.root().canonicalize()?;
         let path = path.as_ref();
-        let scanner = FileScanner::new(repo_root.clone());
+        let scanner = FileScanner::with_config(repo_root.clone(), self.context.config.scan.clone());
 
-        let add_path = &repo_root.join(path).canonicalize()?;
+        let add_path = &repo_root
+            .join(path)
+            .canonicalize()
+            .context(Operation::Resolving, path)?;
         if !add_path.starts_with(repo_root) {
             error!(
                 "given path is not inside repo {}: {}",
@@ -50,6 +196,9 @@ impl<'a> AddCommand<'a> {
             return Err(DdriveError::InvalidDirectory);
         }
 
+        let _lock = self.context.repo.lock()?;
+        self.recover_interrupted().await?;
+
         if add_path == repo_root {
             info!("Adding all files to repo")
         } else {
@@ -60,60 +209,381 @@ impl<'a> AddCommand<'a> {
             );
         }
 
-        let files = scanner.get_all_files(add_path)?;
-        if files.is_empty() {
+        let scope = to_storage_path(add_path.strip_prefix(repo_root).unwrap_or(add_path));
+        let scan = scanner
+            .scan(add_path)
+            .context(Operation::Scanning, add_path)?;
+        if scan.files.is_empty() {
             info!("No files found in {}", add_path.display());
-            return Ok(AddResult {
-                new_files: 0,
-                changed_files: 0,
-                renamed_files: 0,
-            });
+            return self.report_unreadable(&scan, Some(&scope)).await;
         }
 
-        let path = path.to_str().expect("path error");
-        let tracked_files = self.context.database.get_all_files().await?;
-        let tracked_files = if add_path == repo_root {
-            tracked_files
-        } else {
-            tracked_files
-                .into_iter()
-                .filter(|f| f.path.starts_with(path))
-                .collect()
-        };
-        let (new_files, changed_files, deleted_files, renames) = self
-            .processor
-            .detect_changes(&files, tracked_files.as_slice(), true)
-            .await?;
+        // Records are matched by their path relative to the root, however
+        // the added path was given
+        let whole_repo = add_path == repo_root;
+        let tracked_files = self.context.database.stream_files().try_filter(|f| {
+            let in_scope = whole_repo
+                || f.path
+                    .strip_prefix(scope.as_str())
+                    .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'));
+            future::ready(in_scope)
+        });
+        self.add_files(started, &scan, tracked_files, Some(&scope))
+            .await
+    }
 
-        self.display_summary(&changed_files, deleted_files.as_slice(), &renames);
+    /// Track the files across the whole repository that the `[track]`
+    /// policy includes. Tracked files outside the policy are left alone.
+    pub async fn execute_auto(&self) -> Result<AddResult> {
+        let started = Instant::now();
+        let repo_root = &self.context.repo.root().canonicalize()?;
+        let policy = self.context.config.track.policy()?;
+        let scanner = FileScanner::with_config(repo_root.clone(), self.context.config.scan.clone());
 
-        let action_id = chrono::Utc::now().timestamp();
+        let _lock = self.context.repo.lock()?;
+        self.recover_interrupted().await?;
 
-        // Process renames first (most efficient)
-        if !renames.is_empty() {
-            info!("Processing {} file renames...", renames.len());
-            self.process_renames(action_id, &renames).await?;
+        info!("Adding files matching the tracking policy");
+        let mut scan = scanner.scan(repo_root)?;
+        scan.files
+            .retain(|file| policy.allows(&to_storage_path(&file.path)));
+        if scan.files.is_empty() {
+            info!("No files match the tracking policy");
+            return self.report_unreadable(&scan, Some("")).await;
         }
 
+        let tracked_files = self
+            .context
+            .database
+            .stream_files()
+            .try_filter(|file| future::ready(policy.allows(&file.path)));
+        self.add_files(started, &scan, tracked_files, Some(""))
+            .await
+    }
+
+    /// Track exactly the listed files, without scanning directories. Listed
+    /// files that are missing are skipped, so nothing is detected as deleted.
+    pub async fn execute_files(&self, paths: &[PathBuf]) -> Result<AddResult> {
+        let started = Instant::now();
+        let repo_root = &self.context.repo.root().canonicalize()?;
+        let scanner = FileScanner::with_config(repo_root.clone(), self.context.config.scan.clone());
+
+        let mut add_paths = Vec::with_capacity(paths.len());
+        for path in paths {
+            let add_path = match repo_root.join(path).canonicalize() {
+                Ok(add_path) => add_path,
+                Err(e) => {
+                    warn!("Skipping {}: {}", path.display(), e);
+                    continue;
+                }
+            };
+            if !add_path.starts_with(repo_root) {
+                error!(
+                    "given path is not inside repo {}: {}",
+                    path.display(),
+                    repo_root.display()
+                );
+                return Err(DdriveError::InvalidDirectory);
+            }
+            add_paths.push(add_path);
+        }
+        add_paths.sort();
+        add_paths.dedup();
+
+        let _lock = self.context.repo.lock()?;
+        self.recover_interrupted().await?;
+
+        info!("Adding {} listed files", add_paths.len());
+        let scan = scanner.get_files(&add_paths);
+        if scan.files.is_empty() {
+            info!("No files to add");
+            return self.report_unreadable(&scan, None).await;
+        }
+
+        let paths: Vec<String> = scan
+            .files
+            .iter()
+            .map(|f| to_storage_path(&f.path))
+            .collect();
+        let tracked_files = self
+            .context
+            .database
+            .get_files_by_paths(&paths.iter().map(String::as_str).collect())
+            .await?;
+        let tracked_files = stream::iter(tracked_files.into_iter().map(Ok));
+        self.add_files(started, &scan, tracked_files, None).await
+    }
+
+    /// Store new and changed scanned files and record the changes against
+    /// `tracked_files`, the records the scan covered. Permission problems
+    /// recorded under `scope` are replaced by those of this scan, and the run
+    /// that `started` is recorded with the action.
+    async fn add_files(
+        &self,
+        started: Instant,
+        scan: &Scan,
+        tracked_files: impl Stream<Item = Result<FileRecord>>,
+        scope: Option<&str>,
+    ) -> Result<AddResult> {
+        let mut new_files: Vec<&FileInfo> = Vec::new();
+        let mut changed_files: Vec<FileInfo> = Vec::new();
+        let mut deleted_files: Vec<FileInfo> = Vec::new();
+        let mut renames: Vec<(FileInfo, &FileInfo)> = Vec::new();
+        let mut guessed_renames: Vec<(FileInfo, &FileInfo, Option<String>)> = Vec::new();
+        let mut tracked_directories = TrackedDirectories::default();
+        let tracked_files =
+            tracked_files.inspect_ok(|record| tracked_directories.insert(&record.path));
+        self.processor
+            .detect_changes(&scan.files, tracked_files, true, |change| match change {
+                FileChange::New(file) => new_files.push(file),
+                FileChange::Changed { file, b3sum } => {
+                    let mut changed_file = file.clone();
+                    changed_file.b3sum = b3sum;
+                    changed_files.push(changed_file);
+                }
+                FileChange::Deleted(file) => deleted_files.push(file),
+                FileChange::Renamed {
+                    from,
+                    to,
+                    b3sum,
+                    confidence,
+                } if confidence.is_low() => guessed_renames.push((from, to, b3sum)),
+                FileChange::Renamed { from, to, .. } => renames.push((from, to)),
+            })
+            .await?;
+        // A guessed rename is recorded as a rename of the file followed by a
+        // change of its content, or as a deleted and a new file if rejected
+        if self.confirm_low_confidence_renames(&guessed_renames)? {
+            for (from, to, b3sum) in guessed_renames {
+                let mut changed_file = to.clone();
+                changed_file.b3sum = b3sum;
+                changed_files.push(changed_file);
+                renames.push((from, to));
+            }
+        } else {
+            for (from, to, _) in guessed_renames {
+                new_files.push(to);
+                deleted_files.push(from);
+            }
+        }
+
+        let rename_pairs: Vec<(String, String)> = renames
+            .iter()
+            .map(|(old_file, new_file)| {
+                (
+                    to_storage_path(&old_file.path),
+                    to_storage_path(&new_file.path),
+                )
+            })
+            .collect();
+        let directory_moves = utils::find_directory_moves(&rename_pairs, &tracked_directories);
+        drop(tracked_directories);
+
+        self.display_summary(
+            &changed_files,
+            deleted_files.as_slice(),
+            &rename_pairs,
+            &directory_moves,
+        );
+
+        let action_id = chrono::Utc::now().timestamp();
+        logging::set_action_id(action_id);
+        let mut result = AddResult::default();
+        result.record_unreadable(scan);
+        self.context.emit(ProgressEvent::Started {
+            command: "add",
+            files: new_files.len() + changed_files.len() + renames.len(),
+        });
+        self.context
+            .database
+            .begin_journal(action_id)
+            .await
+            .during(Operation::StartingJournal)?;
+
         if !new_files.is_empty() {
             info!("Processing {} new files...", new_files.len());
-            let new_files_refs: Vec<_> = new_files.iter().collect();
-            self.process_new_files(action_id, &new_files_refs).await?;
         }
+        let stored_new = self.store_files(action_id, &new_files, &mut result).await?;
 
-        // Process changed files
         if !changed_files.is_empty() {
             info!("Processing {} changed files...", changed_files.len());
-            let changed_files: Vec<_> = changed_files.iter().collect();
-            self.process_changed_files(action_id, &changed_files)
+        }
+        let changed_files: Vec<_> = changed_files.iter().collect();
+        let stored_changed = self
+            .store_files(action_id, &changed_files, &mut result)
+            .await?;
+
+        // For renames, we don't need to copy files to object store since the content is the same
+        // and the object already exists from when the file was originally added
+        if !renames.is_empty() {
+            info!("Processing {} file renames...", renames.len());
+        }
+
+        let stored_new: Vec<&FileInfo> = stored_new.iter().collect();
+        let stored_changed: Vec<&FileInfo> = stored_changed.iter().collect();
+        self.context
+            .database
+            .commit_add_action(
+                action_id,
+                &rename_pairs,
+                &directory_moves,
+                &stored_new,
+                &stored_changed,
+            )
+            .await
+            .during(Operation::RecordingAdd)?;
+        self.context.emit(ProgressEvent::ActionCommitted {
+            action_id,
+            action_type: ActionType::Add,
+            files: stored_new.len() + stored_changed.len() + rename_pairs.len(),
+        });
+        self.resolve_problems(&stored_new).await?;
+        self.resolve_problems(&stored_changed).await?;
+        let devs = stored_new
+            .iter()
+            .chain(&stored_changed)
+            .copied()
+            .chain(renames.iter().map(|(_, to)| *to))
+            .filter_map(|file| file.dev);
+        self.context
+            .database
+            .record_devices(&device::describe(devs.collect::<BTreeSet<_>>()))
+            .await?;
+        result.new_files = stored_new.len();
+        result.changed_files = stored_changed.len();
+
+        if !result.unstable_files.is_empty() {
+            warn!(
+                code = %codes::UNSTABLE_FILES,
+                "{} files changed while being added and were skipped:",
+                result.unstable_files.len()
+            );
+            for path in &result.unstable_files {
+                warn!("  {}", path.display());
+            }
+        }
+
+        self.record_problems(action_id, &result, scope).await?;
+
+        let bytes = stored_new
+            .iter()
+            .chain(&stored_changed)
+            .map(|file| file.size)
+            .sum();
+        self.context
+            .database
+            .record_action_run(
+                action_id,
+                "add",
+                started.elapsed(),
+                stored_new.len() + stored_changed.len() + renames.len(),
+                bytes,
+                result.failed.len() + result.unreadable.len(),
+            )
+            .await?;
+
+        result.renamed_files = renames.len();
+        result.moved_directories = directory_moves.len();
+        result.action_id = Some(action_id);
+        Ok(result)
+    }
+
+    /// Whether to record renames that were only matched by size and creation
+    /// time, as allowed by `--confirm-renames` or answered on the terminal
+    fn confirm_low_confidence_renames(
+        &self,
+        renames: &[(FileInfo, &FileInfo, Option<String>)],
+    ) -> Result<bool> {
+        if renames.is_empty() || self.confirm_renames {
+            return Ok(true);
+        }
+        let symbols = self.context.symbols();
+        info!(
+            "{} files look renamed, but only their size and creation time match:",
+            renames.len()
+        );
+        for (from, to, _) in renames {
+            info!(
+                "  {} {} {}",
+                from.path.display(),
+                symbols.arrow(),
+                to.path.display()
+            );
+        }
+        if utils::confirm("Record them as renamed?")? {
+            return Ok(true);
+        }
+        info!(
+            "Recording them as deleted and new files; pass --confirm-renames to record them as renamed"
+        );
+        Ok(false)
+    }
+
+    /// Report the unreadable entries of a scan that found nothing to add
+    async fn report_unreadable(&self, scan: &Scan, scope: Option<&str>) -> Result<AddResult> {
+        let mut result = AddResult::default();
+        result.record_unreadable(scan);
+        let action_id = chrono::Utc::now().timestamp();
+        self.record_problems(action_id, &result, scope).await?;
+        Ok(result)
+    }
+
+    /// Log and persist the files that could not be protected, so `status`
+    /// keeps showing them until they are stored
+    async fn record_problems(
+        &self,
+        action_id: i64,
+        result: &AddResult,
+        scope: Option<&str>,
+    ) -> Result<()> {
+        if let Some(scope) = scope {
+            let kind = FailureKind::PermissionDenied.to_string();
+            self.context
+                .database
+                .clear_problems_under(scope, &kind)
                 .await?;
         }
 
-        Ok(AddResult {
-            new_files: new_files.len(),
-            changed_files: changed_files.len(),
-            renamed_files: renames.len(),
-        })
+        if !result.failed.is_empty() {
+            warn!(
+                code = %codes::STORE_FAILED,
+                "{} files could not be protected:",
+                result.failed.len()
+            );
+            for failure in &result.failed {
+                warn!(
+                    "  {} ({}): {}",
+                    failure.path.display(),
+                    failure.kind,
+                    failure.message
+                );
+            }
+        }
+        if !result.unreadable.is_empty() {
+            warn!(
+                code = %codes::UNREADABLE_FILES,
+                "{} files or folders could not be read:",
+                result.unreadable.len()
+            );
+            for failure in &result.unreadable {
+                warn!("  {}", failure.path.display());
+            }
+        }
+
+        let problems: Vec<Problem> = result
+            .failed
+            .iter()
+            .chain(&result.unreadable)
+            .map(|failure| Problem {
+                path: to_storage_path(&failure.path),
+                kind: failure.kind.to_string(),
+                message: failure.message.clone(),
+            })
+            .collect();
+        self.context
+            .database
+            .record_problems(action_id, &problems)
+            .await
     }
 
     /// Display summary of files to be processed
@@ -121,26 +591,42 @@ impl<'a> AddCommand<'a> {
         &self,
         changed_files: &[FileInfo],
         deleted_files: &[FileInfo],
-        renames: &[(FileInfo, FileInfo)],
+        renames: &[(String, String)],
+        directory_moves: &[DirectoryMove],
     ) {
-        // Display renames
-        if !renames.is_empty() && renames.len() <= 5 {
-            info!("Renamed files:");
-            for (old_file, new_file) in renames {
+        let symbols = self.context.symbols();
+        // Display moved directories, then the files renamed on their own
+        if !directory_moves.is_empty() {
+            info!("Renamed directories:");
+            for directory_move in directory_moves {
                 info!(
-                    "  {} → {}",
-                    old_file.path.display(),
-                    new_file.path.display()
+                    "  {} {} {}, {} files",
+                    directory_move.from,
+                    symbols.arrow(),
+                    directory_move.to,
+                    utils::format_count(directory_move.files.len())
                 );
             }
+        }
+        let moved: HashSet<usize> = directory_moves
+            .iter()
+            .flat_map(|directory_move| directory_move.files.iter().copied())
+            .collect();
+        let renames: Vec<_> = renames
+            .iter()
+            .enumerate()
+            .filter(|(index, _)| !moved.contains(index))
+            .map(|(_, rename)| rename)
+            .collect();
+        if !renames.is_empty() && renames.len() <= 5 {
+            info!("Renamed files:");
+            for (old_path, new_path) in &renames {
+                info!("  {} {} {}", old_path, symbols.arrow(), new_path);
+            }
         } else if renames.len() > 5 {
             info!("Renamed files (showing 5 out of {}):", renames.len());
-            for (old_file, new_file) in renames.iter().take(5) {
-                info!(
-                    "  {} → {}",
-                    old_file.path.display(),
-                    new_file.path.display()
-                );
+            for (old_path, new_path) in renames.iter().take(5) {
+                info!("  {} {} {}", old_path, symbols.arrow(), new_path);
             }
             info!("  ... and {} more", renames.len() - 5);
         }
@@ -172,121 +658,786 @@ impl<'a> AddCommand<'a> {
         }
     }
 
-    /// Process new files by calculating checksums, inserting records, and copying to object store
-    async fn process_new_files(&self, action_id: i64, files: &[&FileInfo]) -> Result<usize> {
-        // Calculate checksums and create FileInfo objects with checksums
-        let mut files_with_checksums = Vec::new();
-        let mut failed_count = 0;
-
+    /// Hash and copy files into the object store, journaling each object created
+    /// so it can be cleaned up if the action is interrupted. Files outside the
+    /// `[object_store]` policy are only hashed. Returns the stored files; files
+    /// that could not be stored are noted in `result`.
+    async fn store_files(
+        &self,
+        action_id: i64,
+        files: &[&FileInfo],
+        result: &mut AddResult,
+    ) -> Result<Vec<FileInfo>> {
+        let policy = self.context.config.object_store.policy()?;
+        let mut stored_files = Vec::new();
         for file_info in files {
-            match self.processor.calculate_single_checksum(&file_info.path) {
-                Ok(checksum) => {
-                    if let Err(e) = self.copy_to_object_store(&file_info.path, &checksum) {
-                        warn!(
-                            "Failed to copy {} to object store: {}",
-                            file_info.path.display(),
-                            e
-                        );
-                        failed_count += 1;
-                        continue;
+            let store = policy.stores(&to_storage_path(&file_info.path), file_info.size);
+            match self.store_stable(file_info, store) {
+                Ok(Some((stored, created))) => {
+                    let checksum = stored.b3sum.as_deref().expect("stored checksum");
+                    if created {
+                        self.context
+                            .database
+                            .journal_object(action_id, checksum)
+                            .await
+                            .context(Operation::JournalingCopy, &stored.path)?;
+                        self.context.emit(ProgressEvent::ObjectCopied {
+                            b3sum: checksum.to_string(),
+                            size: stored.size,
+                        });
                     }
+                    self.context.emit(ProgressEvent::FileHashed {
+                        path: stored.path.clone(),
+                        size: stored.size,
+                        b3sum: checksum.to_string(),
+                    });
+                    stored_files.push(stored);
+                }
+                Ok(None) => result.unstable_files.push(file_info.path.clone()),
+                Err(e) => result.record_failure(&file_info.path, &e),
+            }
+        }
+        Ok(stored_files)
+    }
+
+    /// Undo what an interrupted add left behind: staged copies and objects
+    /// of actions that never committed
+    async fn recover_interrupted(&self) -> Result<()> {
+        self.context.repo.clear_tmp_dir()?;
+        self.context
+            .database
+            .recover_journal(self.context.objects.as_ref())
+            .await?;
+        Ok(())
+    }
+
+    /// Clear problems recorded by earlier runs for files that are now stored
+    async fn resolve_problems(&self, files: &[&FileInfo]) -> Result<()> {
+        let paths: Vec<String> = files.iter().map(|f| to_storage_path(&f.path)).collect();
+        let paths: Vec<&str> = paths.iter().map(String::as_str).collect();
+        self.context.database.resolve_problems(&paths).await
+    }
+
+    /// Hash and, if `store` is set, copy a file into the object store, making
+    /// sure it did not change in the meantime. Returns the file info refreshed
+    /// with the stored checksum, size and modification time along with whether
+    /// a new object was created, or `None` if it kept changing on every retry.
+    fn store_stable(&self, file_info: &FileInfo, store: bool) -> Result<Option<(FileInfo, bool)>> {
+        let absolute_path = self.context.repo.root().join(&file_info.path);
 
-                    let mut file_with_checksum = (*file_info).clone();
-                    file_with_checksum.b3sum = Some(checksum);
-                    files_with_checksums.push(file_with_checksum);
+        // A checksum from change detection was computed against the scanned metadata.
+        // It can't be reused when secondary checksums have to be computed anyway.
+        let secondary_enabled = self.processor.has_secondary_checksums(file_info.size);
+        let mut known = file_info
+            .b3sum
+            .clone()
+            .filter(|_| !secondary_enabled)
+            .map(|checksum| (checksum, (file_info.size, file_info.modified)));
+
+        for attempt in 0..=STABILITY_RETRIES {
+            let mut secondary = SecondaryChecksums::default();
+            let before = match &known {
+                Some((_, before)) => *before,
+                None => fingerprint(&absolute_path)?,
+            };
+            // The cache is only valid if the file still matches the scan
+            let cached = known.take().map(|(checksum, _)| checksum).or_else(|| {
+                (!secondary_enabled
+                    && attempt == 0
+                    && before == (file_info.size, file_info.modified))
+                    .then(|| self.processor.known_checksum(file_info))
+                    .flatten()
+            });
+
+            let (checksum, created) = match cached {
+                Some(checksum) => {
+                    let created = self.copy_to_object_store(&absolute_path, &checksum, store)?;
+                    (checksum, created)
                 }
-                Err(e) => {
-                    warn!(
-                        "Failed to calculate checksum for {}: {}",
-                        file_info.path.display(),
-                        e
-                    );
-                    failed_count += 1;
+                None => {
+                    let (checksum, checksums, created) =
+                        self.hash_into_object_store(&absolute_path, store)?;
+                    secondary = checksums;
+                    (checksum, created)
                 }
+            };
+
+            let after = fingerprint(&absolute_path)?;
+            if before == after {
+                let mut stored = file_info.clone();
+                // Content shared with a stored file is recoverable even if
+                // this file was not copied itself
+                stored.stored = self.context.objects.exists(&checksum)?;
+                stored.b3sum = Some(checksum);
+                stored.secondary = secondary;
+                (stored.size, stored.modified) = after;
+                stored.attributes = self.read_attributes(&absolute_path);
+                stored.content_type = content_type::detect(&absolute_path);
+                return Ok(Some((stored, created)));
             }
-        }
 
-        if !files_with_checksums.is_empty() {
-            let file_refs: Vec<&FileInfo> = files_with_checksums.iter().collect();
-            self.context
-                .database
-                .batch_insert_file_records(action_id, &file_refs)
-                .await?;
+            warn!(
+                "{} changed while being added (attempt {} of {})",
+                file_info.path.display(),
+                attempt + 1,
+                STABILITY_RETRIES + 1
+            );
+            // The copy may not match the checksum it was stored under
+            if created {
+                self.context.objects.delete(&checksum)?;
+            }
         }
 
-        Ok(failed_count)
+        Ok(None)
     }
 
-    /// Process changed files by updating records and copying to object store
-    async fn process_changed_files(&self, action_id: i64, files: &[&FileInfo]) -> Result<usize> {
-        let mut failed_count = 0;
-        for file_info in files.iter() {
-            let b3sum = file_info.b3sum.as_ref().expect("b3sum");
-            if let Err(e) = self.copy_to_object_store(&file_info.path, b3sum) {
-                warn!(
-                    "Failed to copy {} to object store: {}",
-                    file_info.path.display(),
-                    e
-                );
-                failed_count += 1;
-                continue;
+    /// Capture permission, ownership and xattr attributes as configured
+    fn read_attributes(&self, path: &Path) -> FileAttributes {
+        FileAttributes::read(path, &self.context.config.metadata).unwrap_or_else(|e| {
+            warn!("Failed to read attributes of {}: {}", path.display(), e);
+            FileAttributes::default()
+        })
+    }
+
+    /// Copy a file to the object store, using reflinks when possible, unless
+    /// `store` is unset. Returns whether a new object was created.
+    fn copy_to_object_store(&self, file_path: &Path, checksum: &str, store: bool) -> Result<bool> {
+        if !store {
+            return Ok(false);
+        }
+        let objects = &self.context.objects;
+        let Some(object_path) = objects.local_path(checksum) else {
+            return objects
+                .put(checksum, file_path)
+                .context(Operation::StoringCopy, file_path);
+        };
+
+        // If object already exists, no need to copy again
+        if object_path.exists() {
+            debug!("Object {} already exists in store", checksum);
+            return Ok(false);
+        }
+
+        match self.context.config.object_store.mode {
+            ObjectStoreMode::Reflink => {
+                if let Some(object_dir) = object_path.parent() {
+                    fs::create_dir_all(object_dir).map_err(|e| DdriveError::FileSystem {
+                        message: format!("Failed to create object directory: {e}"),
+                    })?;
+                }
+                if let Err(e) = reflink_copy::reflink(file_path, &object_path) {
+                    debug!("Not storing {}: {}", file_path.display(), e);
+                    return Ok(false);
+                }
+                Ok(true)
             }
+            _ => objects
+                .put(checksum, file_path)
+                .context(Operation::StoringCopy, file_path),
+        }
+    }
 
-            self.context
-                .database
-                .batch_update_file_records(action_id, &[file_info])
-                .await?;
+    /// Hash a file while copying it into the object store, so its content is
+    /// read only once. The copy is staged in a temporary file and renamed into
+    /// place under its checksum. With reflinks the clone is hashed instead.
+    /// Remote object stores get the file uploaded once it is hashed.
+    /// If `store` is unset the file is only hashed. Returns the checksums and
+    /// whether a new object was created.
+    fn hash_into_object_store(
+        &self,
+        file_path: &Path,
+        store: bool,
+    ) -> Result<(String, SecondaryChecksums, bool)> {
+        if !store || self.context.objects.local_root().is_none() {
+            let (checksum, secondary) = self
+                .processor
+                .calculate_single_checksums(file_path)
+                .context(Operation::Hashing, file_path)?;
+            let created = store
+                && self
+                    .context
+                    .objects
+                    .put(&checksum, file_path)
+                    .context(Operation::StoringCopy, file_path)?;
+            return Ok((checksum, secondary, created));
         }
+        let tmp_dir = self.context.repo.tmp_dir();
+        fs::create_dir_all(&tmp_dir).map_err(|e| DdriveError::FileSystem {
+            message: format!("Failed to create staging directory: {e}"),
+        })?;
+        let tmp_path = tmp_dir.join(format!(
+            "{}-{}",
+            std::process::id(),
+            STAGED_OBJECTS.fetch_add(1, Ordering::Relaxed)
+        ));
 
-        Ok(failed_count)
+        let result = self
+            .stage_object(file_path, &tmp_path)
+            .and_then(|staged| match staged {
+                Some((checksum, secondary)) => {
+                    let created = self.commit_staged_object(&tmp_path, &checksum)?;
+                    Ok((checksum, secondary, created))
+                }
+                None => {
+                    let (checksum, secondary) = self
+                        .processor
+                        .calculate_single_checksums(file_path)
+                        .context(Operation::Hashing, file_path)?;
+                    Ok((checksum, secondary, false))
+                }
+            });
+        if result.is_err() {
+            let _ = fs::remove_file(&tmp_path);
+        }
+        result
     }
 
-    /// Copy a file to the object store, using hard links when possible
-    fn copy_to_object_store(&self, file_path: &Path, checksum: &str) -> Result<()> {
-        // Create object store directory structure (first 2 chars / next 2 chars)
-        let object_dir = self.context.repo.object_dir(checksum);
+    /// Copy a file to `tmp_path`, reflinking when possible, and return its
+    /// checksums. Nothing is staged in reflink-only mode if reflinking fails.
+    fn stage_object(
+        &self,
+        file_path: &Path,
+        tmp_path: &Path,
+    ) -> Result<Option<(String, SecondaryChecksums)>> {
+        if reflink_copy::reflink(file_path, tmp_path).is_ok() {
+            return self
+                .processor
+                .calculate_single_checksums(tmp_path)
+                .map(Some)
+                .context(Operation::HashingStagedCopy, tmp_path);
+        }
+        if self.context.config.object_store.mode == ObjectStoreMode::Reflink {
+            debug!("Not storing {}: reflink unsupported", file_path.display());
+            let _ = fs::remove_file(tmp_path);
+            return Ok(None);
+        }
+        let mut staged = fs::File::create(tmp_path).context(Operation::StagingCopy, tmp_path)?;
+        self.processor
+            .copy_with_checksums(file_path, &mut staged)
+            .map(Some)
+            .context(Operation::Copying, file_path)
+    }
 
-        if !object_dir.exists() {
-            fs::create_dir_all(&object_dir).map_err(|e| DdriveError::FileSystem {
+    /// Move a staged object into place, discarding it if the object already
+    /// exists. Object stores on another file system get a copy instead.
+    fn commit_staged_object(&self, tmp_path: &Path, checksum: &str) -> Result<bool> {
+        let objects = &self.context.objects;
+        let Some(object_path) = objects.local_path(checksum) else {
+            return Err(DdriveError::InvalidDirectory);
+        };
+        if object_path.exists() {
+            debug!("Object {} already exists in store", checksum);
+            fs::remove_file(tmp_path)?;
+            return Ok(false);
+        }
+        if let Some(object_dir) = object_path.parent() {
+            fs::create_dir_all(object_dir).map_err(|e| DdriveError::FileSystem {
                 message: format!("Failed to create object directory: {e}"),
             })?;
         }
 
-        let object_path = object_dir.join(checksum);
+        if fs::rename(tmp_path, &object_path).is_err() {
+            let created = objects
+                .put(checksum, tmp_path)
+                .context(Operation::StoringStagedCopy, tmp_path)?;
+            fs::remove_file(tmp_path).context(Operation::Removing, tmp_path)?;
+            return Ok(created);
+        }
+        Ok(true)
+    }
+}
 
-        // If object already exists, no need to copy again
-        if object_path.exists() {
-            debug!("Object {} already exists in store", checksum);
-            return Ok(());
+/// Size and modification time used to detect files changing during add
+fn fingerprint(path: &Path) -> Result<(u64, SystemTime)> {
+    let metadata = fs::metadata(path).context(Operation::ReadingMetadata, path)?;
+    let modified = metadata.modified().context(Operation::ReadingMtime, path)?;
+    Ok((metadata.len(), modified))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        AddOptions, Ddrive,
+        api::testing::{configured_repository, repository},
+    };
+    use assert_fs::{TempDir, prelude::*};
+
+    #[tokio::test]
+    async fn test_add_listed_files() {
+        let (temp, ddrive) = repository(&[
+            ("photos/a.jpg", "a"),
+            ("photos/b.jpg", "b"),
+            ("photos/c.jpg", "c"),
+        ])
+        .await;
+        ddrive
+            .add(&[temp.path().join("photos")], &AddOptions::default())
+            .await
+            .unwrap();
+        temp.child("photos/a.jpg").write_str("changed").unwrap();
+        temp.child("photos/d.jpg").write_str("d").unwrap();
+        std::fs::remove_file(temp.path().join("photos/c.jpg")).unwrap();
+
+        let listed = [
+            temp.path().join("photos/a.jpg"),
+            temp.path().join("photos/d.jpg"),
+            temp.path().join("photos/missing.jpg"),
+        ];
+        let added = ddrive
+            .add_files(&listed, &AddOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(added.new_files, 1);
+        assert_eq!(added.changed_files, 1);
+
+        // c.jpg was not listed, so it is still tracked
+        let status = ddrive.status().await.unwrap();
+        assert_eq!(status.tracked_files, 4);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_add_reports_unreadable_folders() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let (temp, ddrive) =
+            repository(&[("photos/a.jpg", "a"), ("photos/private/b.jpg", "b")]).await;
+        let private = temp.path().join("photos/private");
+        std::fs::set_permissions(&private, std::fs::Permissions::from_mode(0o000)).unwrap();
+        if std::fs::read_dir(&private).is_ok() {
+            // Permissions are not enforced (e.g. running as root)
+            return;
         }
 
-        reflink_copy::reflink_or_copy(file_path, object_path)?;
-        Ok(())
+        let added = ddrive
+            .add(&[temp.path().join("photos")], &AddOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(added.new_files, 1);
+        assert_eq!(added.unreadable.len(), 1);
+        let status = ddrive.status().await.unwrap();
+        assert_eq!(status.unreadable_files, vec!["photos/private".to_string()]);
+
+        std::fs::set_permissions(&private, std::fs::Permissions::from_mode(0o755)).unwrap();
+        ddrive
+            .add(&[temp.path().join("photos")], &AddOptions::default())
+            .await
+            .unwrap();
+        let status = ddrive.status().await.unwrap();
+        assert!(status.unreadable_files.is_empty());
     }
 
-    /// Process file renames efficiently without recalculating checksums or copying files
-    async fn process_renames(
-        &self,
-        action_id: i64,
-        renames: &[(FileInfo, FileInfo)],
-    ) -> Result<()> {
-        let rename_pairs: Vec<(String, String)> = renames
+    #[tokio::test]
+    async fn test_add_matches_files_scanned_out_of_path_order() {
+        // The scan lists "docs/a/x.txt" before "docs/a-b/y.txt", while the
+        // records are in byte order, where '-' sorts before '/'
+        let (temp, ddrive) = repository(&[
+            ("docs/a/x.txt", "x"),
+            ("docs/a-b/y.txt", "y"),
+            ("docs/a.txt", "a"),
+            ("docs/a b.txt", "a b"),
+        ])
+        .await;
+        let docs = [temp.path().join("docs")];
+        let added = ddrive.add(&docs, &AddOptions::default()).await.unwrap();
+        assert_eq!(added.new_files, 4);
+
+        temp.child("docs/a/x.txt").write_str("changed").unwrap();
+        let added = ddrive.add(&docs, &AddOptions::default()).await.unwrap();
+        assert_eq!(
+            (added.new_files, added.changed_files, added.renamed_files),
+            (0, 1, 0)
+        );
+        let files = ddrive.context().database.get_all_files().await.unwrap();
+        assert_eq!(files.len(), 4);
+    }
+
+    #[tokio::test]
+    async fn test_interrupted_add_is_recovered() {
+        let (temp, ddrive) = repository(&[("docs/a.txt", "committed")]).await;
+        let docs = [temp.path().join("docs")];
+        ddrive.add(&docs, &AddOptions::default()).await.unwrap();
+
+        // An add that stored an object and re-stored the committed one, then
+        // was killed before committing, leaving a staged copy behind
+        let context = ddrive.context();
+        let (database, objects) = (&context.database, &context.objects);
+        let committed = blake3::hash(b"committed").to_hex().to_string();
+        let uncommitted = blake3::hash(b"uncommitted").to_hex().to_string();
+        let source = temp.path().join("uncommitted.bin");
+        std::fs::write(&source, "uncommitted").unwrap();
+        objects.put(&uncommitted, &source).unwrap();
+        std::fs::remove_file(&source).unwrap();
+        database.begin_journal(1_000).await.unwrap();
+        database.journal_object(1_000, &uncommitted).await.unwrap();
+        database.journal_object(1_000, &committed).await.unwrap();
+        let staged = context.repo.tmp_dir().join("stage-1000");
+        std::fs::create_dir_all(staged.parent().unwrap()).unwrap();
+        std::fs::write(&staged, "partial").unwrap();
+
+        let added = ddrive.add(&docs, &AddOptions::default()).await.unwrap();
+        assert_eq!(added.new_files, 0);
+        assert!(!objects.exists(&uncommitted).unwrap());
+        assert!(!staged.exists());
+        // The committed action keeps its object and record
+        assert!(objects.exists(&committed).unwrap());
+        let files = database.get_all_files().await.unwrap();
+        assert_eq!(files[0].b3sum, committed);
+        let journal: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM journal_objects")
+            .fetch_one(&database.pool)
+            .await
+            .unwrap();
+        assert_eq!(journal, 0);
+    }
+
+    /// Object store that appends to `busy.bin` whenever it stores a copy of
+    /// it, as if something kept writing to the file while it was added
+    struct TouchingBackend(crate::backend::LocalBackend);
+
+    impl crate::backend::ObjectBackend for TouchingBackend {
+        fn location(&self) -> String {
+            self.0.location()
+        }
+
+        fn put(&self, checksum: &str, source: &Path) -> Result<bool> {
+            let created = self.0.put(checksum, source)?;
+            if source.ends_with("busy.bin") {
+                use std::io::Write;
+                let mut file = std::fs::File::options().append(true).open(source)?;
+                file.write_all(b"!")?;
+            }
+            Ok(created)
+        }
+
+        fn get(&self, checksum: &str, target: &Path) -> Result<()> {
+            self.0.get(checksum, target)
+        }
+
+        fn exists(&self, checksum: &str) -> Result<bool> {
+            self.0.exists(checksum)
+        }
+
+        fn delete(&self, checksum: &str) -> Result<bool> {
+            self.0.delete(checksum)
+        }
+
+        fn list(&self) -> Result<Vec<crate::backend::StoredObject>> {
+            self.0.list()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_add_skips_files_changing_while_stored() {
+        let (temp, ddrive) =
+            repository(&[("docs/stable.txt", "stable"), ("docs/busy.bin", "busy")]).await;
+        let mut context = ddrive.context().clone();
+        let store = TempDir::new().unwrap();
+        context.objects = std::sync::Arc::new(TouchingBackend(crate::backend::LocalBackend::new(
+            store.path().to_path_buf(),
+        )));
+        let ddrive = Ddrive::from_context(context);
+
+        let added = ddrive
+            .add(&[temp.path().join("docs")], &AddOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(added.unstable_files, [PathBuf::from("docs/busy.bin")]);
+        assert_eq!(added.new_files, 1);
+        // Every copy made while it changed was removed again
+        let objects = ddrive.context().objects.list().unwrap();
+        let stable = blake3::hash(b"stable").to_hex().to_string();
+        assert_eq!(
+            objects
+                .iter()
+                .map(|object| object.checksum.as_str())
+                .collect::<Vec<_>>(),
+            [stable.as_str()]
+        );
+    }
+
+    /// An object store that cannot read `locked.bin` and has no room for
+    /// `full.bin`
+    struct RefusingBackend(crate::backend::LocalBackend);
+
+    impl crate::backend::ObjectBackend for RefusingBackend {
+        fn location(&self) -> String {
+            self.0.location()
+        }
+
+        fn put(&self, checksum: &str, source: &Path) -> Result<bool> {
+            if source.ends_with("locked.bin") {
+                return Err(std::io::Error::from(std::io::ErrorKind::PermissionDenied).into());
+            }
+            if source.ends_with("full.bin") {
+                return Err(std::io::Error::from(std::io::ErrorKind::StorageFull).into());
+            }
+            self.0.put(checksum, source)
+        }
+
+        fn get(&self, checksum: &str, target: &Path) -> Result<()> {
+            self.0.get(checksum, target)
+        }
+
+        fn exists(&self, checksum: &str) -> Result<bool> {
+            self.0.exists(checksum)
+        }
+
+        fn delete(&self, checksum: &str) -> Result<bool> {
+            self.0.delete(checksum)
+        }
+
+        fn list(&self) -> Result<Vec<crate::backend::StoredObject>> {
+            self.0.list()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_add_records_and_resolves_problems() {
+        let (temp, ddrive) = repository(&[
+            ("docs/ok.txt", "ok"),
+            ("docs/locked.bin", "locked"),
+            ("docs/full.bin", "full"),
+        ])
+        .await;
+        let mut context = ddrive.context().clone();
+        let store = context.repo.objects_root();
+        context.objects =
+            std::sync::Arc::new(RefusingBackend(crate::backend::LocalBackend::new(store)));
+        let ddrive = Ddrive::from_context(context);
+        let docs = [temp.path().join("docs")];
+
+        let added = ddrive.add(&docs, &AddOptions::default()).await.unwrap();
+        assert_eq!(added.new_files, 1);
+        let failed: Vec<_> = added
+            .failed
             .iter()
-            .map(|(old_file, new_file)| {
+            .map(|failure| (failure.path.clone(), failure.kind))
+            .collect();
+        assert_eq!(
+            failed,
+            [(PathBuf::from("docs/full.bin"), FailureKind::Storage)]
+        );
+        assert_eq!(added.unreadable[0].path, PathBuf::from("docs/locked.bin"));
+        assert_eq!(
+            crate::cli::check_add_result(&added)
+                .unwrap_err()
+                .exit_code(),
+            12
+        );
+
+        let database = &ddrive.context().database;
+        let mut problems: Vec<(String, String)> = database
+            .get_problems()
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|problem| (problem.path, problem.kind))
+            .collect();
+        problems.sort();
+        assert_eq!(
+            problems,
+            [
                 (
-                    old_file.path.to_string_lossy().into_owned(),
-                    new_file.path.to_string_lossy().into_owned(),
-                )
-            })
+                    "docs/full.bin".to_string(),
+                    FailureKind::Storage.to_string()
+                ),
+                (
+                    "docs/locked.bin".to_string(),
+                    FailureKind::PermissionDenied.to_string()
+                ),
+            ]
+        );
+
+        // Once the files can be stored, adding them again clears the problems
+        let ddrive = Ddrive::open(temp.path()).await.unwrap();
+        let added = ddrive.add(&docs, &AddOptions::default()).await.unwrap();
+        assert_eq!(added.new_files, 2);
+        assert!(crate::cli::check_add_result(&added).is_ok());
+        assert!(database.get_problems().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_add_without_object_store() {
+        let (temp, ddrive) = configured_repository(&[("photos/a.jpg", "a")], |config| {
+            config.object_store.mode = ObjectStoreMode::None
+        })
+        .await;
+
+        let added = ddrive
+            .add(&[temp.path().join("photos")], &AddOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(added.new_files, 1);
+        let b3sum = blake3::hash(b"a").to_hex().to_string();
+        let repo = &ddrive.context().repo;
+        assert!(!repo.object_dir(&b3sum).join(&b3sum).exists());
+        let files = ddrive.context().database.get_all_files().await.unwrap();
+        assert_eq!(files[0].b3sum, b3sum);
+        assert!(!files[0].stored);
+    }
+
+    #[tokio::test]
+    async fn test_low_confidence_renames_need_confirmation() {
+        async fn rename_and_edit(confirm_renames: bool) -> AddResult {
+            let (temp, ddrive) = repository(&[("docs/a.txt", "before")]).await;
+            let docs = temp.path().join("docs");
+            ddrive.add(&[&docs], &AddOptions::default()).await.unwrap();
+            if std::fs::metadata(docs.join("a.txt"))
+                .unwrap()
+                .created()
+                .is_err()
+            {
+                // Only size and creation time can pair a renamed and edited file
+                return AddResult {
+                    renamed_files: confirm_renames as usize,
+                    ..Default::default()
+                };
+            }
+
+            std::fs::rename(docs.join("a.txt"), docs.join("b.txt")).unwrap();
+            std::fs::write(docs.join("b.txt"), "edited").unwrap();
+            // Edited well after it was recorded, so the inode alone is no proof
+            let later = std::time::SystemTime::now() + std::time::Duration::from_secs(60);
+            std::fs::File::options()
+                .write(true)
+                .open(docs.join("b.txt"))
+                .unwrap()
+                .set_modified(later)
+                .unwrap();
+            let status = ddrive.status().await.unwrap();
+            assert_eq!(status.renamed_files.len(), 1);
+            assert!(status.renamed_files[0].2.is_low());
+
+            let options = AddOptions {
+                confirm_renames,
+                ..Default::default()
+            };
+            ddrive.add(&[&docs], &options).await.unwrap()
+        }
+
+        let rejected = rename_and_edit(false).await;
+        assert_eq!((rejected.renamed_files, rejected.new_files), (0, 1));
+        let confirmed = rename_and_edit(true).await;
+        assert_eq!(confirmed.renamed_files, 1);
+    }
+
+    #[tokio::test]
+    async fn test_directory_move_recorded_once() {
+        let (temp, ddrive) = repository(&[
+            ("photos/2021/a.jpg", "a"),
+            ("photos/2021/b.jpg", "b"),
+            ("photos/2022/c.jpg", "c"),
+        ])
+        .await;
+        ddrive
+            .add(&[temp.path()], &AddOptions::default())
+            .await
+            .unwrap();
+
+        std::fs::create_dir(temp.path().join("archive")).unwrap();
+        std::fs::rename(
+            temp.path().join("photos/2021"),
+            temp.path().join("archive/2021"),
+        )
+        .unwrap();
+        let status = ddrive.status().await.unwrap();
+        assert_eq!(
+            status.moved_directories,
+            vec![("photos/2021".to_string(), "archive/2021".to_string(), 2)]
+        );
+        assert!(status.renamed_files.is_empty());
+
+        let added = ddrive
+            .add(&[temp.path()], &AddOptions::default())
+            .await
+            .unwrap();
+        assert_eq!((added.renamed_files, added.moved_directories), (2, 1));
+        let database = &ddrive.context().database;
+        let action_id = bs58::encode(added.action_id.unwrap().to_be_bytes()).into_string();
+        let entries = database
+            .get_history_entries_by_action_id_base58(&action_id)
+            .await
+            .unwrap();
+        let directory: Vec<_> = entries
+            .iter()
+            .filter(|entry| entry.action_type_enum() == ActionType::DirectoryRename)
             .collect();
+        assert_eq!(directory.len(), 1);
+        assert_eq!(directory[0].path, "archive/2021");
+        let renames = entries
+            .iter()
+            .filter(|entry| entry.action_type_enum() == ActionType::Rename)
+            .filter(|entry| {
+                entry
+                    .metadata
+                    .as_deref()
+                    .unwrap()
+                    .contains("archive/2021\"")
+            })
+            .count();
+        assert_eq!(renames, 2);
+        assert!(
+            database
+                .get_file_by_path("archive/2021/a.jpg")
+                .await
+                .unwrap()
+                .is_some()
+        );
+    }
 
-        // For renames, we don't need to copy files to object store since the content is the same
-        // and the object already exists from when the file was originally added
-        self.context
-            .database
-            .batch_rename_files(action_id, &rename_pairs)
-            .await?;
+    #[tokio::test]
+    async fn test_case_only_rename() {
+        let (temp, ddrive) = configured_repository(&[("photos/Photo.JPG", "photo")], |config| {
+            config.changes.case_insensitive = Some(true)
+        })
+        .await;
+        let photos = temp.path().join("photos");
+        ddrive
+            .add(&[&photos], &AddOptions::default())
+            .await
+            .unwrap();
 
-        Ok(())
+        std::fs::rename(photos.join("Photo.JPG"), photos.join("photo.jpg")).unwrap();
+        let status = ddrive.status().await.unwrap();
+        assert!(status.new_files.is_empty() && status.deleted_files.is_empty());
+        assert_eq!(status.renamed_files.len(), 1);
+
+        let added = ddrive
+            .add(&[&photos], &AddOptions::default())
+            .await
+            .unwrap();
+        assert_eq!((added.renamed_files, added.new_files), (1, 0));
+        let files = ddrive.context().database.get_all_files().await.unwrap();
+        let paths: Vec<_> = files.iter().map(|file| file.path.as_str()).collect();
+        assert_eq!(paths, ["photos/photo.jpg"]);
+    }
+
+    #[tokio::test]
+    async fn test_add_with_store_policy() {
+        let files = [
+            ("media/photos/a.jpg", "a"),
+            ("media/photos/big.jpg", "big photo"),
+            ("media/videos/b.mp4", "b"),
+        ];
+        let (temp, ddrive) = configured_repository(&files, |config| {
+            config.object_store.include = vec!["media/photos/**".to_string()];
+            config.object_store.max_file_size = Some(4);
+        })
+        .await;
+
+        let added = ddrive
+            .add(&[temp.path().join("media")], &AddOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(added.new_files, 3);
+        let files = ddrive.context().database.get_all_files().await.unwrap();
+        let repo = &ddrive.context().repo;
+        for file in &files {
+            let expected = file.path == "media/photos/a.jpg";
+            assert_eq!(file.stored, expected, "{}", file.path);
+            let object_path = repo.object_dir(&file.b3sum).join(&file.b3sum);
+            assert_eq!(object_path.exists(), expected, "{}", file.path);
+        }
     }
 }
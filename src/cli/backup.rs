@@ -0,0 +1,219 @@
+//! Copies of the metadata database.
+//!
+//! `BackupCommand` writes a consistent copy of `.ddrive/metadata.sqlite3`,
+//! optionally encrypted with a passphrase (see `crate::encryption`), and
+//! restores one in place of the database. The copy is what to keep off the
+//! machine: without it, stored objects can only be told apart by checksum.
+
+use crate::{
+    AppContext, DdriveError, Result, database::Database, encryption, repository::Repository,
+};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+use tracing::info;
+
+/// Start of every SQLite database file
+const SQLITE_HEADER: &[u8] = b"SQLite format 3\0";
+
+#[derive(Debug, Default)]
+pub struct BackupOptions {
+    /// Encrypt the copy with a passphrase
+    pub encrypt: bool,
+    /// File holding the passphrase, instead of `DDRIVE_PASSPHRASE` or the terminal
+    pub keyfile: Option<PathBuf>,
+}
+
+#[derive(Debug)]
+pub struct BackupResult {
+    pub path: PathBuf,
+    pub size: u64,
+    pub encrypted: bool,
+}
+
+pub struct BackupCommand<'a> {
+    context: &'a AppContext,
+}
+
+impl<'a> BackupCommand<'a> {
+    pub fn new(context: &'a AppContext) -> Self {
+        Self { context }
+    }
+
+    /// Write a copy of the metadata database to `output`
+    pub async fn execute(&self, output: &Path, options: &BackupOptions) -> Result<BackupResult> {
+        let encrypt = options.encrypt || options.keyfile.is_some();
+        let passphrase = if encrypt {
+            Some(encryption::read_passphrase(
+                options.keyfile.as_deref(),
+                true,
+            )?)
+        } else {
+            None
+        };
+
+        // VACUUM INTO gives a consistent copy while other processes write
+        let tmp_dir = self.context.repo.tmp_dir();
+        fs::create_dir_all(&tmp_dir)?;
+        let snapshot = tmp_dir.join(format!("metadata-backup-{}.sqlite3", std::process::id()));
+        if snapshot.exists() {
+            fs::remove_file(&snapshot)?;
+        }
+        sqlx::query("VACUUM INTO ?1")
+            .bind(snapshot.to_string_lossy().into_owned())
+            .execute(&self.context.database.pool)
+            .await?;
+        let copy = fs::read(&snapshot);
+        fs::remove_file(&snapshot)?;
+        let mut copy = copy?;
+        if let Some(passphrase) = &passphrase {
+            copy = encryption::encrypt(&copy, passphrase)?;
+        }
+
+        write_private(output, &copy)?;
+        info!(
+            "Wrote {} copy of the metadata database to {} ({})",
+            if encrypt { "an encrypted" } else { "a" },
+            output.display(),
+            self.context.format_size(copy.len() as u64)
+        );
+        Ok(BackupResult {
+            path: output.to_path_buf(),
+            size: copy.len() as u64,
+            encrypted: encrypt,
+        })
+    }
+
+    /// Replace the metadata database of `repo` with the copy at `input`,
+    /// decrypting it when it is encrypted. Works without opening the current
+    /// database, which may be damaged or missing; it is kept beside the new
+    /// one as `metadata-before-restore.sqlite3`.
+    pub async fn restore(repo: &Repository, input: &Path, keyfile: Option<&Path>) -> Result<()> {
+        let _lock = repo.lock()?;
+        let mut copy = fs::read(input)?;
+        if encryption::is_encrypted(&copy) {
+            let passphrase = encryption::read_passphrase(keyfile, false)?;
+            copy = encryption::decrypt(&copy, &passphrase)?;
+        }
+        if !copy.starts_with(SQLITE_HEADER) {
+            return Err(DdriveError::Validation {
+                message: format!("{} is not a copy of a ddrive database", input.display()),
+            });
+        }
+
+        let tmp_dir = repo.tmp_dir();
+        fs::create_dir_all(&tmp_dir)?;
+        let restored = tmp_dir.join("metadata-restore.sqlite3");
+        write_private(&restored, &copy)?;
+
+        // Move the database aside together with its WAL files, which belong
+        // to it and must not be applied to the restored copy
+        let database_path = repo.database_path();
+        let previous = database_path.with_file_name("metadata-before-restore.sqlite3");
+        for suffix in ["", "-wal", "-shm"] {
+            let from = PathBuf::from(format!("{}{suffix}", database_path.display()));
+            let to = PathBuf::from(format!("{}{suffix}", previous.display()));
+            if to.exists() {
+                fs::remove_file(&to)?;
+            }
+            if from.exists() {
+                fs::rename(&from, &to)?;
+            }
+        }
+        fs::rename(&restored, &database_path)?;
+
+        // Bring the copy up to the current schema
+        let url = format!("sqlite://{}", database_path.display());
+        Database::new(&url, repo.root().clone())
+            .await?
+            .pool
+            .close()
+            .await;
+        info!(
+            "Restored the metadata database from {}; the previous one is {}",
+            input.display(),
+            previous.display()
+        );
+        Ok(())
+    }
+}
+
+/// Write `data` to `path` through a temporary file, readable only by the
+/// owner on Unix
+fn write_private(path: &Path, data: &[u8]) -> Result<()> {
+    use std::io::Write;
+
+    let partial = PathBuf::from(format!("{}.partial", path.display()));
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let mut file = options.open(&partial)?;
+    file.write_all(data)?;
+    file.sync_all()?;
+    fs::rename(&partial, path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{AddOptions, Ddrive};
+    use assert_fs::{TempDir, prelude::*};
+
+    #[tokio::test]
+    async fn test_encrypted_backup_restores() {
+        let temp = TempDir::new().unwrap();
+        let outside = TempDir::new().unwrap();
+        temp.child("secret-plans.txt").write_str("plans").unwrap();
+        crate::repository::Repository::init_repository(temp.path().to_path_buf())
+            .await
+            .unwrap();
+        let ddrive = Ddrive::open(temp.path()).await.unwrap();
+        ddrive
+            .add(&[temp.path()], &AddOptions::default())
+            .await
+            .unwrap();
+
+        let keyfile = outside.child("key");
+        keyfile.write_str("correct horse\n").unwrap();
+        let backup = outside.path().join("metadata.backup");
+        let options = BackupOptions {
+            encrypt: true,
+            keyfile: Some(keyfile.path().to_path_buf()),
+        };
+        let result = BackupCommand::new(ddrive.context())
+            .execute(&backup, &options)
+            .await
+            .unwrap();
+        assert!(result.encrypted);
+        let written = fs::read(&backup).unwrap();
+        assert!(encryption::is_encrypted(&written));
+        assert!(!written.windows(12).any(|window| window == b"secret-plans"));
+
+        // Lose the database, then restore it from the copy
+        let repo = ddrive.context().repo.clone();
+        ddrive.context().database.pool.close().await;
+        fs::write(repo.database_path(), b"damaged").unwrap();
+        let wrong_key = outside.child("wrong");
+        wrong_key.write_str("battery staple").unwrap();
+        assert!(
+            BackupCommand::restore(&repo, &backup, Some(wrong_key.path()))
+                .await
+                .is_err()
+        );
+        BackupCommand::restore(&repo, &backup, Some(keyfile.path()))
+            .await
+            .unwrap();
+
+        let restored = Ddrive::open(temp.path()).await.unwrap();
+        let files = restored.context().database.get_all_files().await.unwrap();
+        assert_eq!(files.len(), 1);
+        assert!(
+            temp.path()
+                .join(".ddrive/metadata-before-restore.sqlite3")
+                .exists()
+        );
+    }
+}
@@ -0,0 +1,345 @@
+//! Measuring the repository's storage.
+//!
+//! `BenchCommand` times the work ddrive does on the file system holding the
+//! repository: scanning it, hashing a test file with buffered reads, a
+//! memory map and rayon threads, inserting rows into SQLite, and reflinking
+//! and copying the file. It then recommends `checksum.buffer_size` and
+//! `performance.hash_threads`, and writes them to the configuration when
+//! asked to.
+//!
+//! Hashing runs after the test file was written, so it is read from the page
+//! cache and measures the CPU side; the scan and copy rates include the disk.
+
+use crate::{
+    AppContext, Result, checksum::ChecksumCalculator, config::Config, scanner::FileScanner,
+    utils::format_count,
+};
+use memmap2::Mmap;
+use sqlx::{SqlitePool, sqlite::SqliteConnectOptions};
+use std::{
+    fs,
+    io::{Read, Write},
+    path::Path,
+    time::{Duration, Instant},
+};
+use tracing::info;
+
+/// Default size of the test file (128 MiB)
+pub const DEFAULT_BENCH_SIZE: u64 = 128 * 1024 * 1024;
+
+/// Read buffer sizes tried for buffered hashing
+const BUFFER_SIZES: &[usize] = &[64 * 1024, 256 * 1024, 1024 * 1024, 4 * 1024 * 1024];
+
+/// Rows inserted into the scratch database
+const INSERT_ROWS: usize = 20_000;
+
+/// Rows per transaction, as add commits them
+const INSERT_BATCH: usize = 1_000;
+
+/// A thread count is recommended once it reaches this share of the best rate
+const THREAD_RATE_SHARE: f64 = 0.9;
+
+#[derive(Debug, Clone)]
+pub struct BenchOptions {
+    /// Size of the test file in bytes
+    pub size: u64,
+    /// Write the recommendations to `.ddrive/config.toml`
+    pub apply: bool,
+}
+
+impl Default for BenchOptions {
+    fn default() -> Self {
+        Self {
+            size: DEFAULT_BENCH_SIZE,
+            apply: false,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct BenchResult {
+    pub scanned_files: usize,
+    pub scan_files_per_sec: f64,
+    /// Buffered hashing rate in bytes per second for each buffer size
+    pub buffered: Vec<(usize, f64)>,
+    /// Hashing rate of a memory-mapped file on one thread
+    pub mmap: f64,
+    /// Hashing rate of a memory-mapped file for each number of rayon threads
+    pub rayon: Vec<(usize, f64)>,
+    pub inserts_per_sec: f64,
+    /// Reflink rate, if the file system supports reflinks
+    pub reflink: Option<f64>,
+    pub copy: f64,
+    /// Recommended `checksum.buffer_size`
+    pub buffer_size: usize,
+    /// Recommended `performance.hash_threads`
+    pub hash_threads: usize,
+}
+
+pub struct BenchCommand<'a> {
+    context: &'a AppContext,
+}
+
+impl<'a> BenchCommand<'a> {
+    pub fn new(context: &'a AppContext) -> Self {
+        Self { context }
+    }
+
+    pub async fn execute(&self, options: &BenchOptions) -> Result<BenchResult> {
+        let repo = &self.context.repo;
+        // The scratch files live in the staging directory, which add clears
+        let _lock = repo.lock()?;
+        let bench_dir = repo.tmp_dir().join(format!("bench-{}", std::process::id()));
+        fs::create_dir_all(&bench_dir)?;
+        let result = self.measure(&bench_dir, options).await;
+        fs::remove_dir_all(&bench_dir)?;
+        let result = result?;
+
+        self.report(&result, options);
+        if options.apply {
+            let mut config = Config::load(repo.root())?;
+            config.checksum.buffer_size = Some(result.buffer_size);
+            config.performance.hash_threads = Some(result.hash_threads);
+            config.save(repo.root())?;
+            info!("Wrote the recommendations to .ddrive/config.toml");
+        }
+        Ok(result)
+    }
+
+    async fn measure(&self, bench_dir: &Path, options: &BenchOptions) -> Result<BenchResult> {
+        let mut result = BenchResult::default();
+        let root = self.context.repo.root();
+
+        let start = Instant::now();
+        let scan =
+            FileScanner::with_config(root.clone(), self.context.config.scan.clone()).scan(root)?;
+        result.scanned_files = scan.files.len();
+        result.scan_files_per_sec = rate(scan.files.len() as u64, start.elapsed());
+
+        let test_file = bench_dir.join("test-file");
+        write_test_file(&test_file, options.size)?;
+        let size = options.size;
+
+        for &buffer_size in BUFFER_SIZES {
+            let calculator = ChecksumCalculator::with_buffer_size(buffer_size);
+            let start = Instant::now();
+            calculator.calculate_checksum(&test_file)?;
+            result
+                .buffered
+                .push((buffer_size, rate(size, start.elapsed())));
+        }
+
+        let file = fs::File::open(&test_file)?;
+        // SAFETY: the test file is private to this run and not modified while mapped
+        let map = unsafe { Mmap::map(&file)? };
+        let start = Instant::now();
+        std::hint::black_box(blake3::Hasher::new().update(&map).finalize());
+        result.mmap = rate(size, start.elapsed());
+        for threads in thread_counts() {
+            let pool = rayon::ThreadPoolBuilder::new()
+                .num_threads(threads)
+                .build()
+                .map_err(|e| std::io::Error::other(e.to_string()))?;
+            let start = Instant::now();
+            pool.install(|| {
+                std::hint::black_box(blake3::Hasher::new().update_rayon(&map).finalize())
+            });
+            result.rayon.push((threads, rate(size, start.elapsed())));
+        }
+        drop(map);
+
+        result.inserts_per_sec = insert_rate(&bench_dir.join("bench.sqlite3")).await?;
+
+        let start = Instant::now();
+        result.reflink = reflink_copy::reflink(&test_file, bench_dir.join("reflinked"))
+            .ok()
+            .map(|()| rate(size, start.elapsed()));
+        let start = Instant::now();
+        copy_file(&test_file, &bench_dir.join("copied"))?;
+        result.copy = rate(size, start.elapsed());
+
+        result.buffer_size = result
+            .buffered
+            .iter()
+            .max_by(|a, b| a.1.total_cmp(&b.1))
+            .map_or(BUFFER_SIZES[0], |&(buffer_size, _)| buffer_size);
+        let best = result
+            .rayon
+            .iter()
+            .map(|&(_, rate)| rate)
+            .fold(0.0, f64::max);
+        result.hash_threads = result
+            .rayon
+            .iter()
+            .find(|&&(_, rate)| rate >= best * THREAD_RATE_SHARE)
+            .map_or(1, |&(threads, _)| threads);
+        Ok(result)
+    }
+
+    fn report(&self, result: &BenchResult, options: &BenchOptions) {
+        let per_sec = |rate: f64| format!("{}/s", self.context.format_size(rate as u64));
+        info!(
+            "Benchmark of {} with a {} test file",
+            self.context.repo.root().display(),
+            self.context.format_size(options.size)
+        );
+        info!(
+            "  Scan: {} files, {} files/s",
+            format_count(result.scanned_files),
+            format_count(result.scan_files_per_sec as usize)
+        );
+        for &(buffer_size, rate) in &result.buffered {
+            info!(
+                "  Hashing, {} buffer: {}",
+                self.context.format_size(buffer_size as u64),
+                per_sec(rate)
+            );
+        }
+        info!("  Hashing, mmap on 1 thread: {}", per_sec(result.mmap));
+        for &(threads, rate) in &result.rayon {
+            info!(
+                "  Hashing, mmap on {} rayon thread(s): {}",
+                threads,
+                per_sec(rate)
+            );
+        }
+        info!(
+            "  SQLite inserts: {} rows/s",
+            format_count(result.inserts_per_sec as usize)
+        );
+        match result.reflink {
+            Some(rate) => info!("  Reflink: {}", per_sec(rate)),
+            None => info!("  Reflink: not supported here; stored objects are full copies"),
+        }
+        info!("  Copy: {}", per_sec(result.copy));
+        info!("Recommended settings:");
+        info!("  checksum.buffer_size = {}", result.buffer_size);
+        info!("  performance.hash_threads = {}", result.hash_threads);
+        if !options.apply {
+            info!("Run `ddrive bench --apply` to write them to .ddrive/config.toml");
+        }
+    }
+}
+
+/// Bytes (or items) per second
+fn rate(amount: u64, elapsed: Duration) -> f64 {
+    amount as f64 / elapsed.as_secs_f64().max(1e-9)
+}
+
+/// Thread counts to try: powers of two up to the number of cores, and the
+/// number of cores itself
+fn thread_counts() -> Vec<usize> {
+    let cores = std::thread::available_parallelism().map_or(1, |cores| cores.get());
+    let mut counts: Vec<usize> = std::iter::successors(Some(1), |&n| Some(n * 2))
+        .take_while(|&n| n < cores)
+        .collect();
+    counts.push(cores);
+    counts
+}
+
+/// Write `size` bytes of incompressible data to `path`, flushed to disk
+fn write_test_file(path: &Path, size: u64) -> Result<()> {
+    let mut file = fs::File::create(path)?;
+    let mut block = vec![0u8; 1024 * 1024];
+    let mut seed = blake3::hash(b"ddrive bench");
+    let mut written = 0;
+    while written < size {
+        for chunk in block.chunks_mut(blake3::OUT_LEN) {
+            seed = blake3::hash(seed.as_bytes());
+            chunk.copy_from_slice(&seed.as_bytes()[..chunk.len()]);
+        }
+        let length = (size - written).min(block.len() as u64) as usize;
+        file.write_all(&block[..length])?;
+        written += length as u64;
+    }
+    file.sync_all()?;
+    Ok(())
+}
+
+/// Copy through a buffer, so the kernel cannot share extents as
+/// `fs::copy` may, and flush the copy to disk
+fn copy_file(source: &Path, target: &Path) -> Result<()> {
+    let mut reader = fs::File::open(source)?;
+    let mut writer = fs::File::create(target)?;
+    let mut buffer = vec![0u8; 1024 * 1024];
+    loop {
+        let read = reader.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        writer.write_all(&buffer[..read])?;
+    }
+    writer.sync_all()?;
+    Ok(())
+}
+
+/// Rows per second inserted into a scratch database at `path`, in
+/// transactions of `INSERT_BATCH` rows
+async fn insert_rate(path: &Path) -> Result<f64> {
+    let options = SqliteConnectOptions::new()
+        .filename(path)
+        .create_if_missing(true);
+    let pool = SqlitePool::connect_with(options).await?;
+    sqlx::query("CREATE TABLE files (path TEXT PRIMARY KEY, b3sum TEXT NOT NULL, size INTEGER)")
+        .execute(&pool)
+        .await?;
+
+    let start = Instant::now();
+    for batch in (0..INSERT_ROWS).collect::<Vec<_>>().chunks(INSERT_BATCH) {
+        let mut transaction = pool.begin().await?;
+        for &row in batch {
+            sqlx::query("INSERT INTO files (path, b3sum, size) VALUES (?1, ?2, ?3)")
+                .bind(format!("photos/{row:06}.jpg"))
+                .bind(blake3::hash(&row.to_le_bytes()).to_hex().to_string())
+                .bind(row as i64)
+                .execute(&mut *transaction)
+                .await?;
+        }
+        transaction.commit().await?;
+    }
+    let elapsed = start.elapsed();
+    pool.close().await;
+    Ok(rate(INSERT_ROWS as u64, elapsed))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Ddrive;
+    use assert_fs::{TempDir, prelude::*};
+
+    #[tokio::test]
+    async fn test_bench_recommends_and_applies() {
+        let temp = TempDir::new().unwrap();
+        temp.child("photo.jpg").write_str("pixels").unwrap();
+        crate::repository::Repository::init_repository(temp.path().to_path_buf())
+            .await
+            .unwrap();
+        let ddrive = Ddrive::open(temp.path()).await.unwrap();
+
+        let options = BenchOptions {
+            size: 1024 * 1024,
+            apply: true,
+        };
+        let result = BenchCommand::new(ddrive.context())
+            .execute(&options)
+            .await
+            .unwrap();
+        assert!(result.scanned_files >= 1);
+        assert_eq!(result.buffered.len(), BUFFER_SIZES.len());
+        assert!(result.mmap > 0.0 && result.copy > 0.0 && result.inserts_per_sec > 0.0);
+        assert!(result.hash_threads >= 1);
+        assert!(BUFFER_SIZES.contains(&result.buffer_size));
+        // Nothing is left behind in the staging directory
+        assert_eq!(
+            fs::read_dir(ddrive.context().repo.tmp_dir())
+                .unwrap()
+                .count(),
+            0
+        );
+
+        let config = Config::load(temp.path()).unwrap();
+        assert_eq!(config.checksum.buffer_size, Some(result.buffer_size));
+        assert_eq!(config.performance.hash_threads, Some(result.hash_threads));
+    }
+}
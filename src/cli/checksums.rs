@@ -0,0 +1,377 @@
+//! Interoperability with external checksum lists.
+//!
+//! This module provides the `ChecksumsCommand` which imports published
+//! checksum files (`sha256sum`/`b3sum` output) and verifies the listed
+//! files against them, and exports tracked checksums in the same format.
+
+use crate::{
+    AppContext, DdriveError, Result,
+    checksum::HashAlgorithm,
+    database::ImportedChecksum,
+    logging,
+    utils::{FileProcessor, to_storage_path},
+};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use tracing::{debug, info, warn};
+
+pub struct ChecksumsCommand<'a> {
+    context: &'a AppContext,
+    processor: FileProcessor<'a>,
+}
+
+#[derive(Debug, Default)]
+pub struct ImportResult {
+    pub algorithm: Option<HashAlgorithm>,
+    /// Listed files that matched their published checksum
+    pub verified: usize,
+    /// Listed files whose content does not match the published checksum
+    pub mismatched: Vec<String>,
+    /// Listed files that are not tracked by ddrive
+    pub untracked: Vec<String>,
+    /// Listed files that could not be read
+    pub unreadable: Vec<String>,
+}
+
+/// One `<checksum>  <path>` line of a checksum list
+#[derive(Debug, PartialEq, Eq)]
+pub struct ChecksumEntry {
+    pub checksum: String,
+    pub path: String,
+}
+
+impl<'a> ChecksumsCommand<'a> {
+    pub fn new(context: &'a AppContext) -> Self {
+        ChecksumsCommand {
+            context,
+            processor: FileProcessor::new(context),
+        }
+    }
+
+    /// Verify tracked files against an external checksum list and record the
+    /// outcome in history. Listed paths are relative to the list's directory.
+    pub async fn import(
+        &self,
+        list_path: &Path,
+        algorithm: Option<HashAlgorithm>,
+    ) -> Result<ImportResult> {
+        let content = std::fs::read_to_string(list_path)?;
+        let entries = parse_checksum_list(&content)?;
+        if entries.is_empty() {
+            info!("No checksums found in {}", list_path.display());
+            return Ok(ImportResult::default());
+        }
+
+        let algorithm = match algorithm {
+            Some(algorithm) => algorithm,
+            None => detect_algorithm(list_path, &entries[0].checksum)?,
+        };
+        info!(
+            "Importing {} {} checksums from {}",
+            entries.len(),
+            algorithm,
+            list_path.display()
+        );
+
+        let repo_root = self.context.repo.root().canonicalize()?;
+        let list_dir = list_path
+            .canonicalize()?
+            .parent()
+            .map(Path::to_path_buf)
+            .unwrap_or_else(|| repo_root.clone());
+
+        let mut result = ImportResult {
+            algorithm: Some(algorithm),
+            ..Default::default()
+        };
+        let mut imported = Vec::new();
+        for entry in entries {
+            let absolute_path = list_dir.join(&entry.path);
+            let Some(relative_path) = repo_relative(&repo_root, &absolute_path) else {
+                result.untracked.push(entry.path);
+                continue;
+            };
+            let Some(record) = self
+                .context
+                .database
+                .get_file_by_path(&relative_path)
+                .await?
+            else {
+                result.untracked.push(entry.path);
+                continue;
+            };
+
+            let actual = match self
+                .processor
+                .calculate_single_checksum_with(&relative_path, algorithm)
+            {
+                Ok(actual) => actual,
+                Err(e) => {
+                    warn!("Could not read {}: {}", entry.path, e);
+                    result.unreadable.push(entry.path);
+                    continue;
+                }
+            };
+
+            let verified = actual.eq_ignore_ascii_case(&entry.checksum);
+            if verified {
+                result.verified += 1;
+            } else {
+                warn!(
+                    "{} {}: does not match {}",
+                    self.context.symbols().fail(),
+                    record.path,
+                    list_path.display()
+                );
+                result.mismatched.push(record.path.clone());
+            }
+            imported.push(ImportedChecksum {
+                path: record.path,
+                b3sum: record.b3sum,
+                size: record.size,
+                expected: entry.checksum.to_lowercase(),
+                verified,
+            });
+        }
+
+        let source = list_path.display().to_string();
+        let action_id = chrono::Utc::now().timestamp();
+        logging::set_action_id(action_id);
+        self.context
+            .database
+            .record_imported_checksums(action_id, &source, algorithm, &imported)
+            .await?;
+
+        self.display_summary(&result);
+        Ok(result)
+    }
+
+    /// Write checksums of tracked files under `path` in `b3sum` format, with
+    /// paths relative to `path` so the list can be checked from that directory
+    /// with `b3sum --check` (or `sha256sum --check`). Returns the number of
+    /// lines written; files without a checksum for `algorithm` are left out.
+    pub async fn export<W: Write>(
+        &self,
+        path: &Path,
+        algorithm: HashAlgorithm,
+        out: &mut W,
+    ) -> Result<usize> {
+        let repo_root = self.context.repo.root().canonicalize()?;
+        let base = path.canonicalize()?;
+        let Some(prefix) = repo_relative(&repo_root, &base) else {
+            return Err(DdriveError::InvalidDirectory);
+        };
+
+        let mut files = self.context.database.get_all_files().await?;
+        files.sort_by(|a, b| a.path.cmp(&b.path));
+
+        let mut written = 0;
+        let mut skipped = 0;
+        for file in &files {
+            let relative = if prefix.is_empty() {
+                file.path.as_str()
+            } else if file.path == prefix {
+                // Exporting a single file
+                file.path.rsplit('/').next().unwrap_or(&file.path)
+            } else {
+                match file
+                    .path
+                    .strip_prefix(prefix.as_str())
+                    .and_then(|rest| rest.strip_prefix('/'))
+                {
+                    Some(rest) => rest,
+                    None => continue,
+                }
+            };
+            let Some(checksum) = file.checksum(algorithm) else {
+                skipped += 1;
+                continue;
+            };
+            writeln!(out, "{}", format_checksum_line(checksum, relative))?;
+            written += 1;
+        }
+
+        debug!(
+            "Exported {} {} checksums ({} without one skipped)",
+            written, algorithm, skipped
+        );
+        Ok(written)
+    }
+
+    fn display_summary(&self, result: &ImportResult) {
+        info!(
+            "Import complete: {} verified, {} mismatched, {} untracked, {} unreadable",
+            result.verified,
+            result.mismatched.len(),
+            result.untracked.len(),
+            result.unreadable.len()
+        );
+        if !result.untracked.is_empty() {
+            info!("Listed files not tracked by ddrive:");
+            for path in result.untracked.iter().take(5) {
+                info!("  {}", path);
+            }
+            if result.untracked.len() > 5 {
+                info!("  ... and {} more", result.untracked.len() - 5);
+            }
+        }
+    }
+}
+
+/// Parse `sha256sum`/`b3sum` style output: `<hex>  <path>` or `<hex> *<path>`.
+/// Blank lines and `#` comments are ignored.
+pub fn parse_checksum_list(content: &str) -> Result<Vec<ChecksumEntry>> {
+    let mut entries = Vec::new();
+    for (number, line) in content.lines().enumerate() {
+        let line = line.trim_end_matches('\r');
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+        // A leading backslash marks an escaped path (see `format_checksum_line`)
+        let (escaped, line) = match line.strip_prefix('\\') {
+            Some(rest) => (true, rest),
+            None => (false, line),
+        };
+        let parsed = line.split_once(' ').and_then(|(checksum, rest)| {
+            let path = rest.strip_prefix(' ').or_else(|| rest.strip_prefix('*'))?;
+            let valid = !checksum.is_empty() && checksum.chars().all(|c| c.is_ascii_hexdigit());
+            (valid && !path.is_empty()).then(|| ChecksumEntry {
+                checksum: checksum.to_string(),
+                path: if escaped {
+                    unescape_path(path)
+                } else {
+                    path.to_string()
+                },
+            })
+        });
+        let Some(entry) = parsed else {
+            return Err(DdriveError::Validation {
+                message: format!("Malformed checksum line {}: {}", number + 1, line),
+            });
+        };
+        entries.push(entry);
+    }
+    Ok(entries)
+}
+
+/// Format one line like `b3sum` does: `<hex>  <path>`. Paths containing a
+/// backslash or newline are escaped and the line is prefixed with `\`.
+pub fn format_checksum_line(checksum: &str, path: &str) -> String {
+    if path.contains(['\\', '\n']) {
+        let escaped = path.replace('\\', "\\\\").replace('\n', "\\n");
+        format!("\\{checksum}  {escaped}")
+    } else {
+        format!("{checksum}  {path}")
+    }
+}
+
+fn unescape_path(path: &str) -> String {
+    let mut unescaped = String::with_capacity(path.len());
+    let mut chars = path.chars();
+    while let Some(c) = chars.next() {
+        match (c, chars.clone().next()) {
+            ('\\', Some('\\')) => {
+                unescaped.push('\\');
+                chars.next();
+            }
+            ('\\', Some('n')) => {
+                unescaped.push('\n');
+                chars.next();
+            }
+            _ => unescaped.push(c),
+        }
+    }
+    unescaped
+}
+
+/// Guess the algorithm of a checksum list from its file name, falling back to
+/// the digest length. 64-hex-digit digests are ambiguous (SHA-256 or BLAKE3).
+fn detect_algorithm(list_path: &Path, checksum: &str) -> Result<HashAlgorithm> {
+    let name = list_path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    if name.contains("sha256") {
+        return Ok(HashAlgorithm::Sha256);
+    }
+    if name.contains("b3") || name.contains("blake3") {
+        return Ok(HashAlgorithm::Blake3);
+    }
+    if name.contains("xxh") || checksum.len() == 16 {
+        return Ok(HashAlgorithm::Xxh3);
+    }
+    Err(DdriveError::Validation {
+        message: format!(
+            "Cannot tell which algorithm {} uses; pass --algorithm",
+            list_path.display()
+        ),
+    })
+}
+
+/// Path relative to the repository root in stored form, if it lies inside it
+fn repo_relative(repo_root: &Path, path: &Path) -> Option<String> {
+    let path: PathBuf = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+    path.strip_prefix(repo_root).ok().map(to_storage_path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_checksum_list() {
+        let content = "# published checksums\n\
+            abc123  photos/a.jpg\n\
+            DEF456 *videos/clip with spaces.mp4\n\
+            \n";
+        let entries = parse_checksum_list(content).unwrap();
+        assert_eq!(
+            entries,
+            vec![
+                ChecksumEntry {
+                    checksum: "abc123".to_string(),
+                    path: "photos/a.jpg".to_string(),
+                },
+                ChecksumEntry {
+                    checksum: "DEF456".to_string(),
+                    path: "videos/clip with spaces.mp4".to_string(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_checksum_list_rejects_malformed_lines() {
+        assert!(parse_checksum_list("not-a-checksum file.txt\n").is_err());
+        assert!(parse_checksum_list("abc123\n").is_err());
+    }
+
+    #[test]
+    fn test_format_checksum_line() {
+        assert_eq!(format_checksum_line("abc", "dir/a.txt"), "abc  dir/a.txt");
+        assert_eq!(
+            format_checksum_line("abc", "odd\\name\n.txt"),
+            "\\abc  odd\\\\name\\n.txt"
+        );
+        let entries = parse_checksum_list(&format_checksum_line("abc", "odd\\name\n.txt")).unwrap();
+        assert_eq!(entries[0].path, "odd\\name\n.txt");
+    }
+
+    #[test]
+    fn test_detect_algorithm() {
+        let sha = "a".repeat(64);
+        assert_eq!(
+            detect_algorithm(Path::new("SHA256SUMS"), &sha).unwrap(),
+            HashAlgorithm::Sha256
+        );
+        assert_eq!(
+            detect_algorithm(Path::new("files.b3"), &sha).unwrap(),
+            HashAlgorithm::Blake3
+        );
+        assert_eq!(
+            detect_algorithm(Path::new("list.txt"), "0123456789abcdef").unwrap(),
+            HashAlgorithm::Xxh3
+        );
+        assert!(detect_algorithm(Path::new("list.txt"), &sha).is_err());
+    }
+}
@@ -0,0 +1,212 @@
+use crate::{AppContext, Result, scanner::FileScanner, utils::to_storage_path};
+use std::collections::{BTreeMap, HashSet};
+use tracing::info;
+
+/// Name shown for files directly in the repository root
+const ROOT_DIRECTORY: &str = ".";
+
+pub struct CoverageCommand<'a> {
+    context: &'a AppContext,
+}
+
+/// Protection of the files under one top-level directory
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct DirectoryCoverage {
+    /// Top-level directory, or `.` for files in the repository root
+    pub directory: String,
+    pub tracked_files: usize,
+    pub tracked_size: u64,
+    pub untracked_files: usize,
+    pub untracked_size: u64,
+    /// Tracked files not verified since they were added or changed
+    pub unverified_files: usize,
+    /// When every tracked file was last verified, if all of them were
+    pub last_fully_verified: Option<chrono::NaiveDateTime>,
+}
+
+impl DirectoryCoverage {
+    /// Share of the directory's bytes that is tracked, in percent
+    pub fn percent_protected(&self) -> f64 {
+        let total = self.tracked_size + self.untracked_size;
+        if total == 0 {
+            return 100.0;
+        }
+        self.tracked_size as f64 * 100.0 / total as f64
+    }
+}
+
+impl<'a> CoverageCommand<'a> {
+    pub fn new(context: &'a AppContext) -> Self {
+        Self { context }
+    }
+
+    pub async fn execute(&self) -> Result<Vec<DirectoryCoverage>> {
+        let coverage = self.gather().await?;
+        self.display(&coverage);
+        Ok(coverage)
+    }
+
+    /// Compare the tracked files per top-level directory with a scan of the
+    /// repository
+    pub async fn gather(&self) -> Result<Vec<DirectoryCoverage>> {
+        let mut directories: BTreeMap<String, DirectoryCoverage> = BTreeMap::new();
+        for tracked in self.context.database.get_directory_coverage().await? {
+            let directory = display_name(&tracked.directory);
+            let last_fully_verified = tracked.last_fully_verified();
+            directories.insert(
+                directory.clone(),
+                DirectoryCoverage {
+                    directory,
+                    tracked_files: tracked.files as usize,
+                    tracked_size: tracked.size as u64,
+                    unverified_files: tracked.unverified as usize,
+                    last_fully_verified,
+                    ..Default::default()
+                },
+            );
+        }
+
+        let tracked_paths: HashSet<String> = self
+            .context
+            .database
+            .get_tracked_file_paths()
+            .await?
+            .into_iter()
+            .map(|file| file.path)
+            .collect();
+        let scanner = FileScanner::with_config(
+            self.context.repo.root().clone(),
+            self.context.config.scan.clone(),
+        );
+        for file in scanner.scan(self.context.repo.root())?.files {
+            let path = to_storage_path(&file.path);
+            if tracked_paths.contains(&path) {
+                continue;
+            }
+            let directory = display_name(top_level_directory(&path));
+            let entry = directories
+                .entry(directory.clone())
+                .or_insert_with(|| DirectoryCoverage {
+                    directory,
+                    ..Default::default()
+                });
+            entry.untracked_files += 1;
+            entry.untracked_size += file.size;
+        }
+
+        Ok(directories.into_values().collect())
+    }
+
+    fn display(&self, coverage: &[DirectoryCoverage]) {
+        let symbols = self.context.symbols();
+        if coverage.is_empty() {
+            info!("No files found");
+            return;
+        }
+
+        info!("Protection coverage by directory:");
+        for directory in coverage {
+            let verified = match directory.last_fully_verified {
+                Some(date) => format!("fully verified {}", date.format("%Y-%m-%d")),
+                None if directory.tracked_files == 0 => String::new(),
+                None => format!("{} never verified", directory.unverified_files),
+            };
+            info!(
+                "  {}{}: {:.0}% protected, {} tracked ({}), {} untracked ({}){}",
+                symbols.folder(),
+                directory.directory,
+                directory.percent_protected(),
+                directory.tracked_files,
+                self.context.format_size(directory.tracked_size),
+                directory.untracked_files,
+                self.context.format_size(directory.untracked_size),
+                if verified.is_empty() {
+                    verified
+                } else {
+                    format!(", {}", verified)
+                }
+            );
+        }
+
+        let unprotected: Vec<&str> = coverage
+            .iter()
+            .filter(|directory| {
+                directory.tracked_files == 0 && directory.directory != ROOT_DIRECTORY
+            })
+            .map(|directory| directory.directory.as_str())
+            .collect();
+        if !unprotected.is_empty() {
+            info!("");
+            info!(
+                "{} Never added: {}",
+                symbols.warning(),
+                unprotected.join(", ")
+            );
+            info!("  Run 'ddrive add <directory>' to protect them");
+        }
+    }
+}
+
+/// First component of a storage path, or `""` for files in the root
+fn top_level_directory(path: &str) -> &str {
+    path.split_once('/').map_or("", |(directory, _)| directory)
+}
+
+fn display_name(directory: &str) -> String {
+    if directory.is_empty() {
+        ROOT_DIRECTORY.to_string()
+    } else {
+        directory.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{AddOptions, VerifyOptions, api::testing::repository};
+    use assert_fs::prelude::*;
+
+    #[tokio::test]
+    async fn test_coverage_by_directory() {
+        let (temp, ddrive) = repository(&[
+            ("docs/a.txt", "a"),
+            ("docs/b/c.txt", "cc"),
+            ("music/d.mp3", "ddd"),
+            ("e.txt", "eeee"),
+        ])
+        .await;
+        ddrive
+            .add(&[temp.path().join("docs")], &AddOptions::default())
+            .await
+            .unwrap();
+        temp.child("docs/new.txt").write_str("new").unwrap();
+
+        let coverage = ddrive.coverage().await.unwrap();
+        let directories: Vec<_> = coverage
+            .iter()
+            .map(|directory| directory.directory.as_str())
+            .collect();
+        assert_eq!(directories, vec![".", "docs", "music"]);
+
+        let docs = &coverage[1];
+        assert_eq!((docs.tracked_files, docs.tracked_size), (2, 3));
+        assert_eq!((docs.untracked_files, docs.untracked_size), (1, 3));
+        assert_eq!(docs.unverified_files, 2);
+        assert!(docs.last_fully_verified.is_none());
+        assert_eq!(docs.percent_protected(), 50.0);
+
+        let music = &coverage[2];
+        assert_eq!((music.tracked_files, music.untracked_files), (0, 1));
+        assert_eq!(coverage[0].untracked_size, 4);
+
+        ddrive
+            .verify(&VerifyOptions {
+                force: true,
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        let coverage = ddrive.coverage().await.unwrap();
+        assert_eq!(coverage[1].unverified_files, 0);
+        assert!(coverage[1].last_fully_verified.is_some());
+    }
+}
@@ -1,19 +1,67 @@
-use crate::{AppContext, Result, database::FileRecord, utils};
+use crate::{
+    AppContext, DdriveError, Operation, Result, ResultExt,
+    checksum::ChecksumCalculator,
+    config::MetadataConfig,
+    database::FileRecord,
+    metadata::{FileAttributes, read_xattrs},
+    scanner::{self, WalkFilter},
+    utils::{format_count, from_storage_path},
+};
+use clap::ValueEnum;
 use glob::Pattern;
 use reflink_copy;
-use std::collections::HashMap;
-use tracing::{debug, error, info};
+use std::collections::{HashMap, HashSet};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use tracing::{debug, error, info, warn};
 
 pub struct DedupCommand<'a> {
     context: &'a AppContext,
     path_filter: Option<String>,
+    /// List duplicates without rewriting any of them
+    report_only: bool,
+    /// Share extents through the kernel instead of replacing duplicates
+    share_extents: bool,
 }
 
 #[derive(Debug)]
 pub struct DuplicateGroup {
     pub checksum: String,
+    /// One path per distinct inode
     pub files: Vec<String>,
     pub file_size: i64,
+    /// Additional paths that are hardlinks of a file already in `files`
+    pub hardlinks: usize,
+}
+
+impl DuplicateGroup {
+    /// Bytes that removing all but one copy would free
+    pub fn wasted_bytes(&self) -> i64 {
+        self.file_size * (self.files.len() as i64 - 1)
+    }
+}
+
+/// Files of an external directory compared with the tracked files
+#[derive(Debug, Default)]
+pub struct ExternalComparison {
+    /// Files whose content is tracked, with the tracked paths holding it
+    pub found: Vec<(PathBuf, Vec<String>)>,
+    /// Files whose content is not in the repository
+    pub missing: Vec<PathBuf>,
+    /// Files that could not be read
+    pub unreadable: Vec<PathBuf>,
+    /// Total size of the found and missing files
+    pub found_bytes: u64,
+    pub missing_bytes: u64,
+}
+
+/// Machine-readable formats for exporting duplicate groups
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum DuplicateFormat {
+    /// One object per group with its checksum, size, wasted bytes and files
+    Json,
+    /// One row per file, with the checksum, size and wasted bytes of its group
+    Csv,
 }
 
 impl<'a> DedupCommand<'a> {
@@ -21,6 +69,18 @@ impl<'a> DedupCommand<'a> {
         Self {
             context,
             path_filter: None,
+            report_only: false,
+            share_extents: false,
+        }
+    }
+
+    /// Dedup that only reports duplicate groups, as used by `prune`
+    pub fn report_only(context: &'a AppContext) -> Self {
+        Self {
+            context,
+            path_filter: None,
+            report_only: true,
+            share_extents: false,
         }
     }
 
@@ -28,15 +88,198 @@ impl<'a> DedupCommand<'a> {
         Self {
             context,
             path_filter: Some(path_filter),
+            report_only: false,
+            share_extents: false,
         }
     }
 
+    /// Have the kernel share the extents of duplicates (`FIDEDUPERANGE`)
+    /// instead of deleting them and reflinking them to the kept copy. The
+    /// kernel compares the contents itself and files are never rewritten.
+    pub fn share_extents(mut self, enabled: bool) -> Self {
+        self.share_extents = enabled;
+        self
+    }
+
     pub async fn execute(&self) -> Result<Vec<DuplicateGroup>> {
+        let duplicates = self.find().await?;
+
+        if duplicates.is_empty() {
+            info!("No duplicate files found");
+            return Ok(duplicates);
+        } else {
+            self.display_duplicates(&duplicates)?;
+            if !self.report_only {
+                // Keep prune from trashing the stored copies of the groups
+                // while their duplicates are replaced
+                let _lock = self.context.repo.lock()?;
+                self.context.database.recount_object_references().await?;
+                self.process_duplicates(&duplicates)?;
+            }
+        }
+
+        Ok(duplicates)
+    }
+
+    /// Write the duplicate groups to `out` in `format`, largest waste first,
+    /// without changing any file
+    pub async fn export<W: Write>(
+        &self,
+        format: DuplicateFormat,
+        out: &mut W,
+    ) -> Result<Vec<DuplicateGroup>> {
+        let duplicates = self.find().await?;
+        match format {
+            DuplicateFormat::Json => {
+                let groups: Vec<_> = duplicates
+                    .iter()
+                    .map(|group| {
+                        serde_json::json!({
+                            "checksum": group.checksum,
+                            "size": group.file_size,
+                            "wasted_bytes": group.wasted_bytes(),
+                            "hardlinks": group.hardlinks,
+                            "files": group.files,
+                        })
+                    })
+                    .collect();
+                writeln!(out, "{:#}", serde_json::Value::from(groups))?;
+            }
+            DuplicateFormat::Csv => {
+                let mut writer = csv::Writer::from_writer(out);
+                writer
+                    .write_record(["checksum", "size", "wasted_bytes", "copies", "path"])
+                    .map_err(std::io::Error::from)?;
+                for group in &duplicates {
+                    for path in &group.files {
+                        writer
+                            .write_record([
+                                group.checksum.as_str(),
+                                &group.file_size.to_string(),
+                                &group.wasted_bytes().to_string(),
+                                &group.files.len().to_string(),
+                                path.as_str(),
+                            ])
+                            .map_err(std::io::Error::from)?;
+                    }
+                }
+                writer.flush()?;
+            }
+        }
+        Ok(duplicates)
+    }
+
+    /// Hash the files of `directory`, which need not be tracked or even in
+    /// the repository, and report which of them are already tracked, to
+    /// tell whether an old copy of some files can be deleted
+    pub async fn against(&self, directory: &Path) -> Result<ExternalComparison> {
+        let directory = directory
+            .canonicalize()
+            .context(Operation::Resolving, directory)?;
+        if directory.starts_with(self.context.repo.root().canonicalize()?) {
+            return Err(DdriveError::Validation {
+                message: format!(
+                    "{} is inside the repository; run dedup without --against",
+                    directory.display()
+                ),
+            });
+        }
+
+        let mut tracked: HashMap<String, Vec<String>> = HashMap::new();
+        let mut tracked_sizes = HashSet::new();
+        for file in self.context.database.get_all_files().await? {
+            tracked_sizes.insert(file.size as u64);
+            tracked.entry(file.b3sum).or_default().push(file.path);
+        }
+
+        // Hidden and ignored files count as well, they would be deleted too
+        let mut files = scanner::get_all_files(&directory, &directory, WalkFilter::ALL)
+            .context(Operation::Scanning, &directory)?;
+        files.sort_by(|a, b| a.path.cmp(&b.path));
+        info!(
+            "Comparing {} files in {} with the repository",
+            format_count(files.len()),
+            directory.display()
+        );
+
+        let calculator = ChecksumCalculator::from_config(&self.context.config.checksum);
+        let mut comparison = ExternalComparison::default();
+        for file in files {
+            // Scanned paths are relative to the directory
+            let relative = file.path;
+            let path = directory.join(&relative);
+            // Only files with the size of a tracked file can match one
+            let paths = if tracked_sizes.contains(&file.size) {
+                match calculator.calculate_checksum(&path) {
+                    Ok(checksum) => tracked.get(&checksum).cloned(),
+                    Err(e) => {
+                        warn!("Failed to hash {}: {}", path.display(), e);
+                        comparison.unreadable.push(relative);
+                        continue;
+                    }
+                }
+            } else {
+                None
+            };
+            match paths {
+                Some(paths) => {
+                    comparison.found_bytes += file.size;
+                    comparison.found.push((relative, paths));
+                }
+                None => {
+                    comparison.missing_bytes += file.size;
+                    comparison.missing.push(relative);
+                }
+            }
+        }
+
+        self.display_comparison(&directory, &comparison);
+        Ok(comparison)
+    }
+
+    fn display_comparison(&self, directory: &Path, comparison: &ExternalComparison) {
+        info!(
+            "{} files ({}) are already in the repository",
+            format_count(comparison.found.len()),
+            self.context.format_size(comparison.found_bytes)
+        );
+        for (path, tracked) in comparison.found.iter().take(10) {
+            info!("  {} = {}", path.display(), tracked[0]);
+        }
+        if comparison.found.len() > 10 {
+            info!("  ... and {} more", comparison.found.len() - 10);
+        }
+
+        if comparison.missing.is_empty() && comparison.unreadable.is_empty() {
+            info!("Every file in {} is in the repository", directory.display());
+            return;
+        }
+        if !comparison.missing.is_empty() {
+            warn!(
+                "{} files ({}) are not in the repository:",
+                format_count(comparison.missing.len()),
+                self.context.format_size(comparison.missing_bytes)
+            );
+            for path in &comparison.missing {
+                warn!("  {}", path.display());
+            }
+        }
+        if !comparison.unreadable.is_empty() {
+            warn!(
+                "{} files could not be read and were not compared",
+                format_count(comparison.unreadable.len())
+            );
+        }
+    }
+
+    /// Tracked files with the same content, grouped and filtered by the
+    /// path pattern
+    async fn find(&self) -> Result<Vec<DuplicateGroup>> {
         let all_files = self.context.database.find_duplicates().await?;
 
         // Apply path filter if specified
         let filtered_files = if let Some(filter) = &self.path_filter {
-            info!("Filtering duplicates with pattern: {}", filter);
+            debug!("Filtering duplicates with pattern: {}", filter);
             let pattern = Pattern::new(filter)?;
             all_files
                 .into_iter()
@@ -46,17 +289,7 @@ impl<'a> DedupCommand<'a> {
             all_files
         };
 
-        let duplicates = self.group_duplicates(filtered_files);
-
-        if duplicates.is_empty() {
-            info!("No duplicate files found");
-            return Ok(duplicates);
-        } else {
-            self.display_duplicates(&duplicates)?;
-            self.process_duplicates(&duplicates)?;
-        }
-
-        Ok(duplicates)
+        Ok(self.group_duplicates(filtered_files))
     }
 
     fn group_duplicates(&self, files: Vec<FileRecord>) -> Vec<DuplicateGroup> {
@@ -76,11 +309,23 @@ impl<'a> DedupCommand<'a> {
         let mut duplicates: Vec<_> = checksum_groups
             .into_iter()
             .filter_map(|(checksum, files)| {
+                let file_size = files[0].size;
+                let total = files.len();
+
+                // Collapse hardlinks so they are neither reported as wasted nor rewritten
+                let mut seen_inodes = HashSet::new();
+                let files: Vec<_> = files
+                    .into_iter()
+                    .filter(|f| f.inode_key().is_none_or(|key| seen_inodes.insert(key)))
+                    .map(|f| f.path)
+                    .collect();
+
                 if files.len() > 1 {
                     Some(DuplicateGroup {
                         checksum,
-                        file_size: files[0].size,
-                        files: files.into_iter().map(|f| f.path).collect(),
+                        file_size,
+                        hardlinks: total - files.len(),
+                        files,
                     })
                 } else {
                     None
@@ -89,9 +334,7 @@ impl<'a> DedupCommand<'a> {
             .collect();
 
         // Sort by wasted space (descending)
-        duplicates.sort_by_key(|group| {
-            std::cmp::Reverse(group.file_size * (group.files.len() as i64 - 1))
-        });
+        duplicates.sort_by_key(|group| std::cmp::Reverse(group.wasted_bytes()));
 
         duplicates
     }
@@ -122,7 +365,7 @@ impl<'a> DedupCommand<'a> {
                 i + 1,
                 &group.checksum[..8],
                 group.files.len(),
-                utils::format_size(group.file_size as u64)
+                self.context.format_size(group.file_size as u64)
             );
 
             // Show files for smaller groups, or just count for large groups
@@ -138,9 +381,13 @@ impl<'a> DedupCommand<'a> {
                 info!("  ... and {} more", group.files.len() - 3);
             }
 
-            let wasted = group.file_size * (group.files.len() as i64 - 1);
+            if group.hardlinks > 0 {
+                info!("  (+{} hardlinks not counted)", group.hardlinks);
+            }
+
+            let wasted = group.wasted_bytes();
             total_wasted_space += wasted;
-            info!("  Wasted: {}", utils::format_size(wasted as u64));
+            info!("  Wasted: {}", self.context.format_size(wasted as u64));
         }
 
         // If there are more groups than we displayed, show a summary
@@ -153,7 +400,7 @@ impl<'a> DedupCommand<'a> {
 
         info!(
             "Total wasted space: {}",
-            utils::format_size(total_wasted_space as u64)
+            self.context.format_size(total_wasted_space as u64)
         );
 
         Ok(())
@@ -161,13 +408,12 @@ impl<'a> DedupCommand<'a> {
 
     /// Process duplicate groups by automatically reflinking duplicates and creating backups in .ddrive/objects
     fn process_duplicates(&self, duplicates: &[DuplicateGroup]) -> Result<()> {
-        // Create the objects directory if it doesn't exist
-        let objects_dir = ".ddrive/objects";
-        std::fs::create_dir_all(objects_dir)?;
+        let repo_root = self.context.repo.root();
 
         for (i, group) in duplicates.iter().enumerate() {
             // Always keep the first file and replace others with reflinks
             let file_to_keep = &group.files[0];
+            let keep_path = repo_root.join(from_storage_path(file_to_keep));
             debug!(
                 "Processing duplicate group {} of {} ({}). Keeping: {}",
                 i + 1,
@@ -177,26 +423,54 @@ impl<'a> DedupCommand<'a> {
             );
 
             // Create a copy at object store
-            let object_dir = self.context.repo.object_dir(&group.checksum);
-            let backup_path = object_dir.join(group.checksum.clone());
-            std::fs::create_dir_all(&object_dir)?;
-            if !std::path::Path::new(&backup_path).exists() {
-                reflink_copy::reflink_or_copy(file_to_keep, &backup_path)?;
-            }
+            self.context
+                .objects
+                .put(&group.checksum, &keep_path)
+                .context(Operation::StoringCopy, &keep_path)?;
 
             // Process each file except the one we're keeping
             for other_file in group.files.iter().skip(1) {
+                let other_path = repo_root.join(from_storage_path(other_file));
+                if self.share_extents {
+                    debug!("Sharing extents of {other_file} with {file_to_keep}");
+                    match share_extents(&keep_path, &other_path, group.file_size as u64) {
+                        Ok(()) => {}
+                        Err(e) if e.kind() == std::io::ErrorKind::Unsupported => {
+                            return Err(DdriveError::FileSystem {
+                                message: format!(
+                                    "The file system does not support sharing extents: {e}"
+                                ),
+                            });
+                        }
+                        Err(e) => error!("Error sharing extents of {other_file}: {e}"),
+                    }
+                    continue;
+                }
+
                 debug!("Replacing {other_file} with reflink to {file_to_keep}");
 
+                // The replacement must look like the file it replaces
+                let preserved = match PreservedMetadata::capture(&other_path) {
+                    Ok(preserved) => preserved,
+                    Err(e) => {
+                        error!("Skipping {other_file}: cannot read its metadata: {e}");
+                        continue;
+                    }
+                };
+
                 // Delete the file first
-                if let Err(e) = std::fs::remove_file(other_file) {
+                if let Err(e) = std::fs::remove_file(&other_path) {
                     error!("Error removing file {other_file}: {e}");
                     continue;
                 }
 
                 // Create reflink copy
-                if let Err(e) = reflink_copy::reflink_or_copy(file_to_keep, other_file) {
-                    error!("Error creating reflink: {e}",);
+                if let Err(e) = reflink_copy::reflink_or_copy(&keep_path, &other_path) {
+                    error!("Error replacing {other_file} with a reflink to {file_to_keep}: {e}");
+                    continue;
+                }
+                if let Err(e) = preserved.restore(&other_path) {
+                    warn!("Could not restore metadata of {other_file}: {e}");
                 }
             }
         }
@@ -209,3 +483,288 @@ impl<'a> DedupCommand<'a> {
         Ok(())
     }
 }
+
+/// Timestamps, permissions, ownership and xattrs of a duplicate about to be
+/// replaced, so the reflinked copy can take them over
+struct PreservedMetadata {
+    attributes: FileAttributes,
+    times: std::fs::FileTimes,
+}
+
+impl PreservedMetadata {
+    fn capture(path: &Path) -> Result<Self> {
+        let metadata = std::fs::metadata(path)?;
+        let times = std::fs::FileTimes::new()
+            .set_accessed(metadata.accessed()?)
+            .set_modified(metadata.modified()?);
+        // Everything is kept, whatever the repository tracks
+        let all = MetadataConfig {
+            permissions: true,
+            ownership: true,
+            xattrs: true,
+        };
+        let mut attributes = FileAttributes::from_metadata(&metadata, &all);
+        attributes.xattrs = read_xattrs(path).unwrap_or_else(|e| {
+            debug!("Not keeping xattrs of {}: {}", path.display(), e);
+            None
+        });
+        Ok(Self { attributes, times })
+    }
+
+    /// Apply the captured metadata. The times go last as the other changes
+    /// may touch them, and are set even if some attribute cannot be.
+    fn restore(&self, path: &Path) -> Result<()> {
+        let attributes = self.attributes.apply(path);
+        // Setting the times only needs ownership, so read-only files work too
+        std::fs::File::open(path)?.set_times(self.times)?;
+        attributes
+    }
+}
+
+/// `struct file_dedupe_range` with room for a single destination
+#[cfg(target_os = "linux")]
+#[repr(C)]
+struct FileDedupeRange {
+    src_offset: u64,
+    src_length: u64,
+    dest_count: u16,
+    reserved1: u16,
+    reserved2: u32,
+    info: FileDedupeRangeInfo,
+}
+
+/// `struct file_dedupe_range_info`
+#[cfg(target_os = "linux")]
+#[repr(C)]
+struct FileDedupeRangeInfo {
+    dest_fd: i64,
+    dest_offset: u64,
+    bytes_deduped: u64,
+    status: i32,
+    reserved: u32,
+}
+
+/// `FIDEDUPERANGE`, sized by the header without the trailing info array
+#[cfg(target_os = "linux")]
+const FIDEDUPERANGE: rustix::ioctl::Opcode =
+    rustix::ioctl::opcode::read_write::<[u64; 3]>(0x94, 54);
+
+/// Contents differ (`FILE_DEDUPE_RANGE_DIFFERS`)
+#[cfg(target_os = "linux")]
+const FILE_DEDUPE_RANGE_DIFFERS: i32 = 1;
+
+/// Make `dest` share the extents of `source` for their first `len` bytes.
+/// The kernel locks both files and only shares ranges whose contents are
+/// identical, so a failure or a concurrent change leaves `dest` intact.
+#[cfg(target_os = "linux")]
+fn share_extents(source: &Path, dest: &Path, len: u64) -> std::io::Result<()> {
+    use std::os::fd::AsRawFd;
+
+    let source_file = std::fs::File::open(source)?;
+    let dest_file = std::fs::OpenOptions::new().write(true).open(dest)?;
+
+    let mut offset = 0;
+    while offset < len {
+        let mut range = FileDedupeRange {
+            src_offset: offset,
+            src_length: len - offset,
+            dest_count: 1,
+            reserved1: 0,
+            reserved2: 0,
+            info: FileDedupeRangeInfo {
+                dest_fd: dest_file.as_raw_fd() as i64,
+                dest_offset: offset,
+                bytes_deduped: 0,
+                status: 0,
+                reserved: 0,
+            },
+        };
+        // SAFETY: `FileDedupeRange` matches the kernel's layout for one destination
+        unsafe {
+            let ioctl = rustix::ioctl::Updater::<FIDEDUPERANGE, _>::new(&mut range);
+            rustix::ioctl::ioctl(&source_file, ioctl)?;
+        }
+
+        match range.info.status {
+            0 => {}
+            FILE_DEDUPE_RANGE_DIFFERS => {
+                return Err(std::io::Error::other("contents differ"));
+            }
+            errno => return Err(std::io::Error::from_raw_os_error(-errno)),
+        }
+        if range.info.bytes_deduped == 0 {
+            // The kernel shares whole blocks only and leaves a partial tail
+            break;
+        }
+        offset += range.info.bytes_deduped;
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn share_extents(_source: &Path, _dest: &Path, _len: u64) -> std::io::Result<()> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "FIDEDUPERANGE is only available on Linux",
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{AddOptions, Ddrive};
+    use assert_fs::{TempDir, prelude::*};
+
+    #[tokio::test]
+    async fn test_share_extents_keeps_files() {
+        let temp = TempDir::new().unwrap();
+        let content = "duplicate content ".repeat(1024);
+        temp.child("docs/a.txt").write_str(&content).unwrap();
+        temp.child("docs/b.txt").write_str(&content).unwrap();
+        let ddrive = Ddrive::init(temp.path()).await.unwrap();
+        ddrive
+            .add(&[temp.path().join("docs")], &AddOptions::default())
+            .await
+            .unwrap();
+
+        // File systems without extent sharing refuse before touching anything
+        let result = DedupCommand::new(ddrive.context())
+            .share_extents(true)
+            .execute()
+            .await;
+        if let Err(e) = &result {
+            assert!(matches!(e, DdriveError::FileSystem { .. }), "{e}");
+        }
+        for name in ["a.txt", "b.txt"] {
+            let path = temp.path().join("docs").join(name);
+            assert_eq!(std::fs::read_to_string(path).unwrap(), content);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_export_duplicates() {
+        let temp = TempDir::new().unwrap();
+        temp.child("docs/a.txt").write_str("same").unwrap();
+        temp.child("docs/b, copy.txt").write_str("same").unwrap();
+        temp.child("docs/c.txt").write_str("unique").unwrap();
+        let ddrive = Ddrive::init(temp.path()).await.unwrap();
+        ddrive
+            .add(&[temp.path().join("docs")], &AddOptions::default())
+            .await
+            .unwrap();
+        let dedup = DedupCommand::new(ddrive.context());
+
+        let mut json = Vec::new();
+        dedup
+            .export(DuplicateFormat::Json, &mut json)
+            .await
+            .unwrap();
+        let groups: serde_json::Value = serde_json::from_slice(&json).unwrap();
+        assert_eq!(groups[0]["size"], 4);
+        assert_eq!(groups[0]["wasted_bytes"], 4);
+        assert_eq!(groups[0]["files"].as_array().unwrap().len(), 2);
+
+        let mut csv = Vec::new();
+        dedup.export(DuplicateFormat::Csv, &mut csv).await.unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines[0], "checksum,size,wasted_bytes,copies,path");
+        assert_eq!(lines.len(), 3);
+        assert!(
+            lines
+                .iter()
+                .any(|line| line.ends_with(",4,4,2,\"docs/b, copy.txt\""))
+        );
+
+        // Exporting leaves the duplicates alone
+        assert!(temp.path().join("docs/b, copy.txt").exists());
+    }
+
+    #[tokio::test]
+    async fn test_against_external_directory() {
+        let temp = TempDir::new().unwrap();
+        let external = TempDir::new().unwrap();
+        temp.child("docs/a.txt").write_str("kept").unwrap();
+        external.child("old/a copy.txt").write_str("kept").unwrap();
+        external.child("old/.hidden").write_str("kept").unwrap();
+        external.child("old/b.txt").write_str("gone").unwrap();
+        external.child("c.txt").write_str("only here").unwrap();
+        let ddrive = Ddrive::init(temp.path()).await.unwrap();
+        ddrive
+            .add(&[temp.path().join("docs")], &AddOptions::default())
+            .await
+            .unwrap();
+
+        let comparison = DedupCommand::new(ddrive.context())
+            .against(external.path())
+            .await
+            .unwrap();
+        let found: Vec<_> = comparison
+            .found
+            .iter()
+            .map(|(path, tracked)| (path.to_str().unwrap(), tracked[0].as_str()))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                ("old/.hidden", "docs/a.txt"),
+                ("old/a copy.txt", "docs/a.txt")
+            ]
+        );
+        assert_eq!(
+            comparison.missing,
+            [PathBuf::from("c.txt"), PathBuf::from("old/b.txt")]
+        );
+        assert_eq!(comparison.missing_bytes, 13);
+
+        let inside = DedupCommand::new(ddrive.context())
+            .against(&temp.path().join("docs"))
+            .await;
+        assert!(matches!(inside, Err(DdriveError::Validation { .. })));
+    }
+
+    #[tokio::test]
+    async fn test_dedup_preserves_metadata() {
+        let temp = TempDir::new().unwrap();
+        temp.child("docs/a.txt").write_str("same").unwrap();
+        temp.child("docs/b.txt").write_str("same").unwrap();
+        let duplicate = temp.path().join("docs/b.txt");
+        let modified = std::time::UNIX_EPOCH + std::time::Duration::new(1_500_000_000, 123_456_789);
+        std::fs::File::options()
+            .write(true)
+            .open(&duplicate)
+            .unwrap()
+            .set_modified(modified)
+            .unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(&duplicate, std::fs::Permissions::from_mode(0o640)).unwrap();
+        }
+        // Not every file system supports user xattrs
+        #[cfg(unix)]
+        let with_xattr = xattr::set(&duplicate, "user.ddrive.test", b"kept").is_ok();
+
+        let ddrive = Ddrive::init(temp.path()).await.unwrap();
+        ddrive
+            .add(&[temp.path().join("docs")], &AddOptions::default())
+            .await
+            .unwrap();
+        // Keep a.txt and replace b.txt
+        let groups = DedupCommand::new(ddrive.context()).execute().await.unwrap();
+        assert_eq!(groups[0].files[0], "docs/a.txt");
+
+        let metadata = std::fs::metadata(&duplicate).unwrap();
+        assert_eq!(metadata.modified().unwrap(), modified);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(metadata.permissions().mode() & 0o7777, 0o640);
+            if with_xattr {
+                let value = xattr::get(&duplicate, "user.ddrive.test").unwrap();
+                assert_eq!(value.as_deref(), Some(&b"kept"[..]));
+            }
+        }
+        assert_eq!(std::fs::read_to_string(&duplicate).unwrap(), "same");
+    }
+}
@@ -0,0 +1,465 @@
+use crate::{AppContext, DdriveError, Result, utils::normalize_path};
+use chrono::Utc;
+use std::{
+    fs,
+    time::{Duration, SystemTime},
+};
+use strum::Display;
+use tracing::{info, warn};
+
+/// Clock differences below this are treated as noise
+const CLOCK_SKEW_TOLERANCE: Duration = Duration::from_secs(120);
+
+/// Details listed per check before the rest are summarized
+const MAX_DETAILS: usize = 5;
+
+pub struct DoctorCommand<'a> {
+    context: &'a AppContext,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Display)]
+pub enum CheckStatus {
+    #[strum(serialize = "OK")]
+    Ok,
+    #[strum(serialize = "WARN")]
+    Warning,
+    #[strum(serialize = "FAIL")]
+    Error,
+}
+
+#[derive(Debug)]
+pub struct CheckResult {
+    pub name: &'static str,
+    pub status: CheckStatus,
+    pub summary: String,
+    pub details: Vec<String>,
+    /// What to do about a warning or failure
+    pub fix: Option<String>,
+}
+
+impl CheckResult {
+    fn ok(name: &'static str, summary: impl Into<String>) -> Self {
+        Self {
+            name,
+            status: CheckStatus::Ok,
+            summary: summary.into(),
+            details: Vec::new(),
+            fix: None,
+        }
+    }
+
+    fn problem(
+        name: &'static str,
+        status: CheckStatus,
+        summary: impl Into<String>,
+        details: Vec<String>,
+        fix: impl Into<String>,
+    ) -> Self {
+        Self {
+            name,
+            status,
+            summary: summary.into(),
+            details,
+            fix: Some(fix.into()),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct DoctorReport {
+    pub checks: Vec<CheckResult>,
+}
+
+impl DoctorReport {
+    /// A when every check passed, B with one warning, C with several, F when
+    /// any check failed
+    pub fn grade(&self) -> char {
+        let warnings = self
+            .checks
+            .iter()
+            .filter(|check| check.status == CheckStatus::Warning)
+            .count();
+        if self.has_failures() {
+            'F'
+        } else if warnings > 1 {
+            'C'
+        } else if warnings == 1 {
+            'B'
+        } else {
+            'A'
+        }
+    }
+
+    pub fn has_failures(&self) -> bool {
+        self.checks
+            .iter()
+            .any(|check| check.status == CheckStatus::Error)
+    }
+}
+
+impl<'a> DoctorCommand<'a> {
+    pub fn new(context: &'a AppContext) -> Self {
+        Self { context }
+    }
+
+    pub async fn execute(&self) -> Result<DoctorReport> {
+        let mut report = DoctorReport::default();
+        report.checks.push(self.check_schema().await?);
+        report.checks.push(self.check_missing_objects().await?);
+        report.checks.push(self.check_history_objects().await?);
+        report.checks.push(self.check_orphaned_objects().await?);
+        report.checks.push(self.check_refcounts().await?);
+        report.checks.push(self.check_lock().await?);
+        report.checks.push(self.check_reflink());
+        report.checks.push(self.check_paths().await?);
+        report.checks.push(self.check_clock().await?);
+
+        self.display_report(&report);
+        Ok(report)
+    }
+
+    async fn check_schema(&self) -> Result<CheckResult> {
+        const NAME: &str = "Database schema";
+        let database = &self.context.database;
+        let expected = sqlx::migrate!("./migrations").migrations.len() as i64;
+        let (applied, failed) = database.get_migration_state().await?;
+        let damage = database.quick_check().await?;
+
+        if !damage.is_empty() {
+            return Ok(CheckResult::problem(
+                NAME,
+                CheckStatus::Error,
+                "the metadata database is damaged",
+                damage,
+                "Restore .ddrive/metadata.sqlite3 from a backup, or try `sqlite3 .ddrive/metadata.sqlite3 .recover`",
+            ));
+        }
+        if failed > 0 || applied < expected {
+            return Ok(CheckResult::problem(
+                NAME,
+                CheckStatus::Error,
+                format!("{applied} of {expected} migrations applied, {failed} incomplete"),
+                Vec::new(),
+                "Back up .ddrive/metadata.sqlite3 and rerun any ddrive command to retry the migrations",
+            ));
+        }
+        Ok(CheckResult::ok(
+            NAME,
+            format!("{applied} migrations applied, database intact"),
+        ))
+    }
+
+    async fn check_missing_objects(&self) -> Result<CheckResult> {
+        const NAME: &str = "Tracked file objects";
+        let files = self.context.database.get_all_files().await?;
+        let stored = files.iter().filter(|file| file.stored).count();
+        if stored == 0 && !files.is_empty() {
+            return Ok(CheckResult::ok(
+                NAME,
+                "no copies are stored by the [object_store] policy",
+            ));
+        }
+        let missing: Vec<String> = files
+            .iter()
+            .filter(|file| file.stored && !self.object_exists(&file.b3sum))
+            .map(|file| format!("{} ({})", file.path, &file.b3sum[..8]))
+            .collect();
+
+        if missing.is_empty() {
+            return Ok(CheckResult::ok(
+                NAME,
+                format!("all {stored} stored files have their copy"),
+            ));
+        }
+        Ok(CheckResult::problem(
+            NAME,
+            CheckStatus::Error,
+            format!("{} tracked files have no stored copy", missing.len()),
+            missing,
+            "Run `ddrive verify` on them; files that still pass can be re-stored by touching them and running `ddrive add`",
+        ))
+    }
+
+    async fn check_history_objects(&self) -> Result<CheckResult> {
+        const NAME: &str = "History references";
+        let history_only = self.context.database.get_history_only_checksums().await?;
+        let dangling: Vec<String> = history_only
+            .iter()
+            .filter(|(b3sum, _)| !self.object_exists(b3sum))
+            .map(|(b3sum, path)| format!("{path} ({})", &b3sum[..8]))
+            .collect();
+
+        if dangling.is_empty() {
+            return Ok(CheckResult::ok(
+                NAME,
+                format!(
+                    "all {} past versions in history are stored",
+                    history_only.len()
+                ),
+            ));
+        }
+        Ok(CheckResult::problem(
+            NAME,
+            CheckStatus::Warning,
+            format!(
+                "{} history entries point at objects that are gone",
+                dangling.len()
+            ),
+            dangling,
+            "These versions cannot be restored; `ddrive prune` removes their entries once past retention",
+        ))
+    }
+
+    async fn check_orphaned_objects(&self) -> Result<CheckResult> {
+        const NAME: &str = "Orphaned objects";
+        let database = &self.context.database;
+        let prune_config = &self.context.config.prune;
+
+        let expired = database
+            .find_orphaned_objects(prune_config.orphan_grace_cutoff())
+            .await?;
+        let in_grace = database.find_orphaned_objects(Utc::now()).await?.len() - expired.len();
+
+        // Objects the reference counts do not know about at all
+        let known = database.get_object_checksums().await?;
+        let stray: Vec<String> = self
+            .context
+            .objects
+            .list()?
+            .into_iter()
+            .filter(|object| !known.contains(&object.checksum))
+            .map(|object| object.checksum)
+            .collect();
+
+        if stray.is_empty() && expired.is_empty() {
+            return Ok(CheckResult::ok(
+                NAME,
+                format!("none past the grace period, {in_grace} within it"),
+            ));
+        }
+        let size: u64 = expired.iter().map(|object| object.size).sum();
+        let mut details: Vec<String> = expired.iter().map(|object| object.b3sum.clone()).collect();
+        details.extend(
+            stray
+                .iter()
+                .map(|path| format!("{path} (not in the database)")),
+        );
+        Ok(CheckResult::problem(
+            NAME,
+            CheckStatus::Warning,
+            format!(
+                "{} orphaned objects ({}) past the grace period, {} unknown to the database",
+                expired.len(),
+                self.context.format_size(size),
+                stray.len()
+            ),
+            details,
+            "Run `ddrive prune --dry-run`, then `ddrive prune`; objects unknown to the database can be deleted by hand",
+        ))
+    }
+
+    async fn check_refcounts(&self) -> Result<CheckResult> {
+        const NAME: &str = "Reference counts";
+        let mismatches = self.context.database.get_refcount_mismatches().await?;
+        if mismatches.is_empty() {
+            return Ok(CheckResult::ok(NAME, "consistent with records and history"));
+        }
+        Ok(CheckResult::problem(
+            NAME,
+            CheckStatus::Error,
+            format!("{} objects have a wrong reference count", mismatches.len()),
+            mismatches,
+            "`ddrive prune`, `dedup` and `rm --drop-objects` correct the counts before removing anything; run one of them to fix this",
+        ))
+    }
+
+    async fn check_lock(&self) -> Result<CheckResult> {
+        const NAME: &str = "Repository lock";
+        let interrupted = self.context.database.count_interrupted_actions().await?;
+        let staged = match fs::read_dir(self.context.repo.tmp_dir()) {
+            Ok(entries) => entries.count(),
+            Err(_) => 0,
+        };
+
+        match self.context.repo.lock() {
+            Err(DdriveError::Repository { .. }) => Ok(CheckResult::problem(
+                NAME,
+                CheckStatus::Warning,
+                "held by another ddrive process",
+                Vec::new(),
+                "Wait for it to finish; the lock is released automatically if that process dies",
+            )),
+            Err(e) => Err(e),
+            Ok(_lock) if interrupted > 0 || staged > 0 => Ok(CheckResult::problem(
+                NAME,
+                CheckStatus::Warning,
+                format!(
+                    "{interrupted} interrupted add actions, {staged} staged objects left behind"
+                ),
+                Vec::new(),
+                "Run `ddrive add` to clean up after the interrupted action",
+            )),
+            Ok(_lock) => Ok(CheckResult::ok(NAME, "free, no interrupted actions")),
+        }
+    }
+
+    fn check_reflink(&self) -> CheckResult {
+        const NAME: &str = "Reflink support";
+        let capabilities = self.context.repo.probe_storage();
+        if capabilities.shares_blocks() {
+            return CheckResult::ok(NAME, "objects share blocks with the tracked files");
+        }
+        let summary = match &capabilities.reflink_error {
+            Some(e) => format!("not available ({e})"),
+            None => "object store is on another device".to_string(),
+        };
+        CheckResult::problem(
+            NAME,
+            CheckStatus::Warning,
+            summary,
+            capabilities.warnings(),
+            "Stored objects are full copies, doubling disk use; keep .ddrive/objects on the repository's file system and use a copy-on-write one (btrfs, XFS, APFS)",
+        )
+    }
+
+    async fn check_paths(&self) -> Result<CheckResult> {
+        const NAME: &str = "Path normalization";
+        let paths = self.context.database.get_stored_paths().await?;
+        let bad: Vec<String> = paths
+            .iter()
+            .filter(|path| {
+                normalize_path(path) != path.as_str()
+                    || path.starts_with('/')
+                    || path.split('/').any(|part| matches!(part, "" | "." | ".."))
+                    || (cfg!(windows) && path.contains('\\'))
+            })
+            .cloned()
+            .collect();
+
+        if bad.is_empty() {
+            return Ok(CheckResult::ok(
+                NAME,
+                format!("all {} stored paths are normalized", paths.len()),
+            ));
+        }
+        Ok(CheckResult::problem(
+            NAME,
+            CheckStatus::Warning,
+            format!("{} stored paths are not in normalized form", bad.len()),
+            bad,
+            "These files may show up as renamed or deleted; `ddrive rm tracked` them and add them again",
+        ))
+    }
+
+    async fn check_clock(&self) -> Result<CheckResult> {
+        const NAME: &str = "Clock";
+        let mut details = Vec::new();
+        let now = Utc::now();
+        let tolerance = chrono::Duration::from_std(CLOCK_SKEW_TOLERANCE).expect("tolerance");
+
+        let (updated_at, action_id) = self.context.database.get_latest_timestamps().await?;
+        if let Some(updated_at) = updated_at
+            && updated_at.and_utc() > now + tolerance
+        {
+            details.push(format!(
+                "a file record was updated in the future, at {updated_at}"
+            ));
+        }
+        if let Some(action_id) = action_id
+            && action_id > (now + tolerance).timestamp()
+        {
+            details.push(format!(
+                "the newest action is dated in the future, at {}",
+                chrono::DateTime::from_timestamp(action_id, 0).unwrap_or(now)
+            ));
+        }
+
+        // Files on network shares get their times from the server's clock
+        let probe = self
+            .context
+            .repo
+            .tmp_dir()
+            .join(format!("clock-{}", std::process::id()));
+        let written_at = SystemTime::now();
+        if fs::write(&probe, b"").is_ok() {
+            if let Ok(modified) = fs::metadata(&probe).and_then(|m| m.modified()) {
+                let skew = modified
+                    .duration_since(written_at)
+                    .or_else(|e| Ok::<_, ()>(e.duration()))
+                    .unwrap_or_default();
+                if skew > CLOCK_SKEW_TOLERANCE {
+                    details.push(format!(
+                        "file system times differ from this machine's clock by {}s",
+                        skew.as_secs()
+                    ));
+                }
+            }
+            let _ = fs::remove_file(&probe);
+        }
+
+        if details.is_empty() {
+            return Ok(CheckResult::ok(NAME, "no skew detected"));
+        }
+        Ok(CheckResult::problem(
+            NAME,
+            CheckStatus::Warning,
+            "clock skew detected",
+            details,
+            "Sync the clocks (NTP); modification-time checks and retention depend on them",
+        ))
+    }
+
+    fn object_exists(&self, b3sum: &str) -> bool {
+        matches!(self.context.objects.exists(b3sum), Ok(true))
+    }
+
+    fn display_report(&self, report: &DoctorReport) {
+        for check in &report.checks {
+            let line = format!("[{}] {}: {}", check.status, check.name, check.summary);
+            if check.status == CheckStatus::Ok {
+                info!("{line}");
+            } else {
+                warn!("{line}");
+            }
+            for detail in check.details.iter().take(MAX_DETAILS) {
+                info!("  {detail}");
+            }
+            if check.details.len() > MAX_DETAILS {
+                info!("  ... and {} more", check.details.len() - MAX_DETAILS);
+            }
+            if let Some(fix) = &check.fix {
+                info!("  Fix: {fix}");
+            }
+        }
+        info!("Health grade: {}", report.grade());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn report(statuses: &[CheckStatus]) -> DoctorReport {
+        DoctorReport {
+            checks: statuses
+                .iter()
+                .map(|&status| CheckResult {
+                    name: "check",
+                    status,
+                    summary: String::new(),
+                    details: Vec::new(),
+                    fix: None,
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_grade() {
+        use CheckStatus::*;
+        assert_eq!(report(&[Ok, Ok]).grade(), 'A');
+        assert_eq!(report(&[Ok, Warning]).grade(), 'B');
+        assert_eq!(report(&[Warning, Ok, Warning]).grade(), 'C');
+        assert_eq!(report(&[Warning, Error]).grade(), 'F');
+    }
+}
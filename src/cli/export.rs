@@ -0,0 +1,488 @@
+//! Offline copies on removable media.
+//!
+//! `ExportCommand` fills a disc or drive with stored objects that no
+//! exported medium holds yet, laid out like the object store under
+//! `objects/`, with a manifest mapping each object back to the tracked paths
+//! it protects. What does not fit is left for the next medium. Each medium
+//! and its checksums are recorded in the `media` tables, so `media verify`
+//! can check a disc years later.
+
+use crate::{
+    AppContext, DdriveError, Result, backend,
+    checksum::ChecksumCalculator,
+    database::{MediaObject, MediaRecord},
+    utils::{available_space, format_count, from_storage_path},
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use tracing::{info, warn};
+
+/// Name of the manifest written to the root of each medium
+pub const MANIFEST_NAME: &str = "ddrive-manifest.json";
+
+/// Describes what an exported medium holds
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MediaManifest {
+    pub label: String,
+    /// Root of the repository the objects were exported from
+    pub repository: String,
+    pub created_at: DateTime<Utc>,
+    pub objects: Vec<ManifestObject>,
+}
+
+/// An object on a medium, stored at `objects/<key>`, and the tracked files
+/// with its content
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ManifestObject {
+    pub b3sum: String,
+    pub size: u64,
+    pub paths: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ExportOptions {
+    /// Bytes to fill at most; the free space of the medium if unset
+    pub size: Option<u64>,
+    /// Name of the medium; `<repository>-NNN` if unset
+    pub label: Option<String>,
+    /// List what would be written without writing anything
+    pub dry_run: bool,
+}
+
+#[derive(Debug, Default)]
+pub struct ExportResult {
+    pub label: String,
+    /// Objects written to the medium
+    pub exported: Vec<ManifestObject>,
+    pub exported_bytes: u64,
+    /// Objects left for the next medium
+    pub remaining: usize,
+    pub remaining_bytes: u64,
+    /// Objects larger than the whole medium, by checksum
+    pub too_large: Vec<String>,
+    /// Objects that could not be written, with the reason
+    pub failed: Vec<(String, String)>,
+}
+
+#[derive(Debug, Default)]
+pub struct MediaVerifyResult {
+    pub label: String,
+    pub checked: usize,
+    /// Checksums of recorded objects absent from the medium
+    pub missing: Vec<String>,
+    /// Checksums of objects whose content no longer matches
+    pub damaged: Vec<String>,
+}
+
+impl MediaVerifyResult {
+    pub fn failures(&self) -> usize {
+        self.missing.len() + self.damaged.len()
+    }
+}
+
+pub struct ExportCommand<'a> {
+    context: &'a AppContext,
+    calculator: ChecksumCalculator,
+}
+
+impl<'a> ExportCommand<'a> {
+    pub fn new(context: &'a AppContext) -> Self {
+        Self {
+            context,
+            calculator: ChecksumCalculator::from_config(&context.config.checksum),
+        }
+    }
+
+    /// Fill the medium mounted at `mount_point` with objects no exported
+    /// medium holds yet, in path order, and record it
+    pub async fn execute(
+        &self,
+        mount_point: &Path,
+        options: &ExportOptions,
+    ) -> Result<ExportResult> {
+        if mount_point.join(MANIFEST_NAME).exists() {
+            return Err(DdriveError::Validation {
+                message: format!(
+                    "{} already holds an export; use an empty medium",
+                    mount_point.display()
+                ),
+            });
+        }
+        let media = self.context.database.get_media().await?;
+        let label = match &options.label {
+            Some(label) => label.clone(),
+            None => self.default_label(media.len() + 1),
+        };
+        if media.iter().any(|medium| medium.label == label) {
+            return Err(DdriveError::Validation {
+                message: format!("a medium labelled '{label}' was already exported"),
+            });
+        }
+        let free = available_space(mount_point).ok();
+        let capacity = match (options.size, free) {
+            (Some(size), Some(free)) => size.min(free),
+            (Some(size), None) => size,
+            (None, Some(free)) => free,
+            (None, None) => {
+                return Err(DdriveError::Validation {
+                    message: "could not read the free space of the medium; pass --size".to_string(),
+                });
+            }
+        };
+        let _lock = self.context.repo.lock()?;
+
+        let pending = self.pending_objects().await?;
+        let mut manifest = MediaManifest {
+            label: label.clone(),
+            repository: self.context.repo.root().display().to_string(),
+            created_at: Utc::now(),
+            objects: Vec::new(),
+        };
+        let mut result = ExportResult {
+            label: label.clone(),
+            ..Default::default()
+        };
+        if pending.is_empty() {
+            info!("Every tracked file is already on exported media");
+            return Ok(result);
+        }
+
+        // The manifest is written last, so room for it is set aside as objects are added
+        let base = manifest_size(&manifest);
+        let mut used = base;
+        for object in pending {
+            let needed = object.size + manifest_size(&object);
+            if base + needed > capacity {
+                warn!(
+                    "{} ({}) does not fit on a medium of {}",
+                    object.paths[0],
+                    self.context.format_size(object.size),
+                    self.context.format_size(capacity)
+                );
+                result.too_large.push(object.b3sum);
+                continue;
+            }
+            if used + needed > capacity {
+                result.remaining += 1;
+                result.remaining_bytes += object.size;
+                continue;
+            }
+            if !options.dry_run
+                && let Err(e) = self.write_object(mount_point, &object)
+            {
+                warn!("Failed to export {}: {}", object.paths[0], e);
+                result.failed.push((object.b3sum, e.to_string()));
+                continue;
+            }
+            used += needed;
+            result.exported_bytes += object.size;
+            manifest.objects.push(object);
+        }
+
+        info!(
+            "{} {} objects ({}) to {} as '{}'",
+            if options.dry_run {
+                "Would export"
+            } else {
+                "Exported"
+            },
+            format_count(manifest.objects.len()),
+            self.context.format_size(result.exported_bytes),
+            mount_point.display(),
+            label
+        );
+        if result.remaining > 0 {
+            info!(
+                "{} objects ({}) remain; insert the next medium and run export again",
+                format_count(result.remaining),
+                self.context.format_size(result.remaining_bytes)
+            );
+        }
+        if !options.dry_run && !manifest.objects.is_empty() {
+            let json =
+                serde_json::to_vec_pretty(&manifest).map_err(|e| DdriveError::FileSystem {
+                    message: format!("Failed to write the manifest: {e}"),
+                })?;
+            fs::write(mount_point.join(MANIFEST_NAME), json)?;
+            let objects: Vec<MediaObject> = manifest
+                .objects
+                .iter()
+                .map(|object| MediaObject {
+                    b3sum: object.b3sum.clone(),
+                    size: object.size as i64,
+                })
+                .collect();
+            self.context
+                .database
+                .record_media(&label, capacity, &objects)
+                .await?;
+        }
+        result.exported = manifest.objects;
+        Ok(result)
+    }
+
+    /// Show every exported medium and when it was last verified
+    pub async fn list_media(&self) -> Result<Vec<MediaRecord>> {
+        let media = self.context.database.get_media().await?;
+        if media.is_empty() {
+            info!("No media exported yet; run 'ddrive export --media <mount point>'");
+        }
+        for medium in &media {
+            let verified = match (medium.last_verified_at, medium.last_verify_failures) {
+                (Some(at), Some(0)) => format!("verified {}", at.format("%Y-%m-%d")),
+                (Some(at), Some(failures)) => format!(
+                    "{} failures when verified {}",
+                    failures,
+                    at.format("%Y-%m-%d")
+                ),
+                _ => "never verified".to_string(),
+            };
+            info!(
+                "{}: {} objects ({}), exported {}, {}",
+                medium.label,
+                format_count(medium.objects as usize),
+                self.context.format_size(medium.bytes as u64),
+                medium.created_at.format("%Y-%m-%d"),
+                verified
+            );
+        }
+        Ok(media)
+    }
+
+    /// Re-hash every object recorded for the medium mounted at
+    /// `mount_point`, identified by its manifest
+    pub async fn verify_media(&self, mount_point: &Path) -> Result<MediaVerifyResult> {
+        let manifest = read_manifest(mount_point)?;
+        let label = manifest.label;
+        if !self
+            .context
+            .database
+            .get_media()
+            .await?
+            .iter()
+            .any(|medium| medium.label == label)
+        {
+            return Err(DdriveError::Validation {
+                message: format!("medium '{label}' was not exported from this repository"),
+            });
+        }
+        let paths: HashMap<String, Vec<String>> = manifest
+            .objects
+            .into_iter()
+            .map(|object| (object.b3sum, object.paths))
+            .collect();
+        let objects = self.context.database.get_media_objects(&label).await?;
+        info!(
+            "Verifying {} objects on '{}'",
+            format_count(objects.len()),
+            label
+        );
+
+        let mut result = MediaVerifyResult {
+            label: label.clone(),
+            checked: objects.len(),
+            ..Default::default()
+        };
+        for object in &objects {
+            let path = backend::object_path(&mount_point.join("objects"), &object.b3sum);
+            let described = paths
+                .get(&object.b3sum)
+                .and_then(|paths| paths.first())
+                .unwrap_or(&object.b3sum);
+            if !path.exists() {
+                warn!("Missing from '{}': {}", label, described);
+                result.missing.push(object.b3sum.clone());
+                continue;
+            }
+            let matches = self
+                .calculator
+                .calculate_checksum(&path)
+                .is_ok_and(|checksum| checksum == object.b3sum);
+            if !matches {
+                warn!("Damaged on '{}': {}", label, described);
+                result.damaged.push(object.b3sum.clone());
+            }
+        }
+        self.context
+            .database
+            .record_media_verification(&label, result.failures())
+            .await?;
+        if result.failures() == 0 {
+            info!(
+                "All {} objects on '{}' are intact",
+                format_count(result.checked),
+                label
+            );
+        }
+        Ok(result)
+    }
+
+    /// Tracked content that no exported medium holds, one entry per
+    /// checksum with every path that has it, in path order
+    async fn pending_objects(&self) -> Result<Vec<ManifestObject>> {
+        let exported = self.context.database.get_exported_checksums().await?;
+        let mut objects: Vec<ManifestObject> = Vec::new();
+        let mut index: HashMap<String, usize> = HashMap::new();
+        for file in self.context.database.get_all_files().await? {
+            if exported.contains(&file.b3sum) {
+                continue;
+            }
+            match index.get(&file.b3sum) {
+                Some(&i) => objects[i].paths.push(file.path),
+                None => {
+                    index.insert(file.b3sum.clone(), objects.len());
+                    objects.push(ManifestObject {
+                        b3sum: file.b3sum,
+                        size: file.size as u64,
+                        paths: vec![file.path],
+                    });
+                }
+            }
+        }
+        Ok(objects)
+    }
+
+    /// Write `object` from the object store, or from a tracked file with its
+    /// content if none is stored, and check what was written
+    fn write_object(&self, mount_point: &Path, object: &ManifestObject) -> Result<()> {
+        let target = backend::object_path(&mount_point.join("objects"), &object.b3sum);
+        let Some(dir) = target.parent() else {
+            return Err(DdriveError::InvalidDirectory);
+        };
+        fs::create_dir_all(dir)?;
+        let partial = dir.join(format!(".{}.partial", object.b3sum));
+        let written = self.copy_object(object, &partial).and_then(|()| {
+            if self.calculator.calculate_checksum(&partial)? != object.b3sum {
+                return Err(DdriveError::Validation {
+                    message: "written copy does not match its checksum".to_string(),
+                });
+            }
+            fs::rename(&partial, &target)?;
+            Ok(())
+        });
+        if written.is_err() {
+            let _ = fs::remove_file(&partial);
+        }
+        written
+    }
+
+    fn copy_object(&self, object: &ManifestObject, target: &Path) -> Result<()> {
+        if self.context.objects.exists(&object.b3sum)? {
+            return self.context.objects.get(&object.b3sum, target);
+        }
+        let root = self.context.repo.root();
+        let Some(source) = object
+            .paths
+            .iter()
+            .map(|path| root.join(from_storage_path(path)))
+            .find(|path| path.exists())
+        else {
+            return Err(DdriveError::FileSystem {
+                message: "no stored copy and no tracked file with this content".to_string(),
+            });
+        };
+        reflink_copy::reflink_or_copy(source, target)?;
+        Ok(())
+    }
+
+    /// `<repository name>-NNN`
+    fn default_label(&self, number: usize) -> String {
+        let name = self
+            .context
+            .repo
+            .root()
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "ddrive".to_string());
+        format!("{name}-{number:03}")
+    }
+}
+
+/// Read the manifest of the medium mounted at `mount_point`
+pub fn read_manifest(mount_point: &Path) -> Result<MediaManifest> {
+    let path = mount_point.join(MANIFEST_NAME);
+    let json = fs::read(&path).map_err(|e| DdriveError::Validation {
+        message: format!("{} is not an exported medium: {e}", mount_point.display()),
+    })?;
+    serde_json::from_slice(&json).map_err(|e| DdriveError::Validation {
+        message: format!("{} is damaged: {e}", path.display()),
+    })
+}
+
+/// Bytes `value` takes up in the manifest
+fn manifest_size<T: Serialize>(value: &T) -> u64 {
+    serde_json::to_vec_pretty(value).map_or(0, |json| json.len() as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{AddOptions, Ddrive};
+    use assert_fs::{TempDir, prelude::*};
+
+    #[tokio::test]
+    async fn test_export_splits_across_media() {
+        let temp = TempDir::new().unwrap();
+        temp.child("photos/a.jpg")
+            .write_str(&"a".repeat(1000))
+            .unwrap();
+        temp.child("photos/b.jpg")
+            .write_str(&"b".repeat(1000))
+            .unwrap();
+        temp.child("photos/copy of b.jpg")
+            .write_str(&"b".repeat(1000))
+            .unwrap();
+        temp.child("photos/c.jpg")
+            .write_str(&"c".repeat(1000))
+            .unwrap();
+        let ddrive = Ddrive::init(temp.path()).await.unwrap();
+        ddrive
+            .add(&[temp.path()], &AddOptions::default())
+            .await
+            .unwrap();
+
+        let export = ExportCommand::new(ddrive.context());
+        let first = TempDir::new().unwrap();
+        let options = ExportOptions {
+            size: Some(3000),
+            ..Default::default()
+        };
+        let result = export.execute(first.path(), &options).await.unwrap();
+        assert_eq!(result.exported.len(), 2);
+        assert_eq!(
+            result.exported[1].paths,
+            ["photos/b.jpg", "photos/copy of b.jpg"]
+        );
+        assert_eq!((result.remaining, result.remaining_bytes), (1, 1000));
+        let manifest = read_manifest(first.path()).unwrap();
+        assert_eq!(manifest.label, result.label);
+        assert_eq!(manifest.objects, result.exported);
+        assert!(export.execute(first.path(), &options).await.is_err());
+
+        // The next medium gets only what did not fit
+        let second = TempDir::new().unwrap();
+        let result = export.execute(second.path(), &options).await.unwrap();
+        assert_eq!(result.exported.len(), 1);
+        assert_eq!(result.exported[0].paths, ["photos/c.jpg"]);
+        let third = TempDir::new().unwrap();
+        let result = export.execute(third.path(), &options).await.unwrap();
+        assert!(result.exported.is_empty());
+        assert_eq!(export.list_media().await.unwrap().len(), 2);
+
+        let verified = export.verify_media(first.path()).await.unwrap();
+        assert_eq!((verified.checked, verified.failures()), (2, 0));
+        let damaged = &manifest.objects[0].b3sum;
+        fs::write(
+            backend::object_path(&first.path().join("objects"), damaged),
+            "x",
+        )
+        .unwrap();
+        let verified = export.verify_media(first.path()).await.unwrap();
+        assert_eq!(&verified.damaged, std::slice::from_ref(damaged));
+        let media = export.list_media().await.unwrap();
+        assert_eq!(media[0].last_verify_failures, Some(1));
+    }
+}
@@ -0,0 +1,199 @@
+//! Trying out ignore rules.
+//!
+//! `IgnoreCommand` tells for single paths whether a scan finds them and which
+//! rule decides it, and lists the tracked files the present rules would
+//! ignore. A tracked file a scan no longer finds is reported as deleted, so
+//! the list is worth reading before tightening an ignore file.
+
+use crate::{
+    AppContext, DdriveError, Result,
+    scanner::{FileScanner, IgnoreRule, SkipReason},
+    utils::to_storage_path,
+};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use tracing::{info, warn};
+
+/// Whether a scan finds a path, and why
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PathCheck {
+    /// Path relative to the repository root
+    pub path: String,
+    /// Why a scan skips the path, or `None` if it is found
+    pub skipped: Option<SkipReason>,
+    /// Negated pattern that keeps the path although another one matches it
+    pub kept_by: Option<IgnoreRule>,
+    pub tracked: bool,
+}
+
+pub struct IgnoreCommand<'a> {
+    context: &'a AppContext,
+}
+
+impl<'a> IgnoreCommand<'a> {
+    pub fn new(context: &'a AppContext) -> Self {
+        Self { context }
+    }
+
+    /// Report for each path whether it would be tracked and which rule
+    /// matched it. Paths need not exist, so patterns can be tried on names.
+    pub async fn check(&self, paths: &[PathBuf]) -> Result<Vec<PathCheck>> {
+        let repo_root = self.context.repo.root().canonicalize()?;
+        let scanner = FileScanner::with_config(repo_root.clone(), self.context.config.scan.clone());
+        let mut checks = Vec::with_capacity(paths.len());
+        for path in paths {
+            let path = absolute(path)?;
+            let relative = path
+                .strip_prefix(&repo_root)
+                .map_err(|_| DdriveError::Validation {
+                    message: format!("{} is not inside the repository", path.display()),
+                })?;
+            let storage_path = to_storage_path(relative);
+            let skipped = scanner.skip_reason(&path)?;
+            let kept_by = scanner
+                .ignore_rule(&path, path.is_dir())
+                .filter(|rule| rule.whitelist);
+            let tracked = self
+                .context
+                .database
+                .get_file_by_path(&storage_path)
+                .await?
+                .is_some();
+
+            let status = if tracked { "tracked" } else { "not tracked" };
+            match (&skipped, &kept_by) {
+                (Some(reason), _) => info!("{storage_path}: ignored, {reason} ({status})"),
+                (None, Some(rule)) => {
+                    info!("{storage_path}: would be tracked, kept by {rule} ({status})")
+                }
+                (None, None) => {
+                    info!("{storage_path}: would be tracked, no rule matches ({status})")
+                }
+            }
+            checks.push(PathCheck {
+                path: storage_path,
+                skipped,
+                kept_by,
+                tracked,
+            });
+        }
+        Ok(checks)
+    }
+
+    /// Tracked files that a scan under the present rules would skip,
+    /// with the reason for each. Tracked files that are gone from disk are
+    /// not listed: they are deleted, not ignored.
+    pub async fn diff(&self) -> Result<Vec<(String, SkipReason)>> {
+        let repo_root = self.context.repo.root().canonicalize()?;
+        let scanner = FileScanner::with_config(repo_root.clone(), self.context.config.scan.clone());
+        let found: HashSet<String> = scanner
+            .scan(&repo_root)?
+            .files
+            .iter()
+            .map(|file| to_storage_path(&file.path))
+            .collect();
+
+        let mut ignored = Vec::new();
+        for file in self.context.database.get_tracked_file_paths().await? {
+            if found.contains(&file.path) {
+                continue;
+            }
+            let path = repo_root.join(&file.path);
+            if !path.is_file() {
+                continue;
+            }
+            if let Some(reason) = scanner.skip_reason(&path)? {
+                ignored.push((file.path, reason));
+            }
+        }
+
+        if ignored.is_empty() {
+            info!("The present rules keep every tracked file");
+            return Ok(ignored);
+        }
+        warn!(
+            "{} tracked files would be ignored under the present rules:",
+            ignored.len()
+        );
+        for (path, reason) in &ignored {
+            warn!("  {path}: {reason}");
+        }
+        info!(
+            "'ddrive status' reports them as deleted, and the next 'ddrive add' records them as deleted"
+        );
+        info!("Add '!' patterns to an ignore file to keep them tracked");
+        Ok(ignored)
+    }
+}
+
+/// `path` made absolute without resolving a final component that does not
+/// exist, so names can be checked before the files are created
+fn absolute(path: &Path) -> Result<PathBuf> {
+    if path.exists() {
+        return Ok(path.canonicalize()?);
+    }
+    match (path.parent(), path.file_name()) {
+        (Some(parent), Some(name)) if !parent.as_os_str().is_empty() => {
+            Ok(absolute(parent)?.join(name))
+        }
+        _ => Ok(std::env::current_dir()?.join(path)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{AddOptions, Ddrive};
+    use assert_fs::{TempDir, prelude::*};
+
+    #[tokio::test]
+    async fn test_check_and_diff() {
+        let temp = TempDir::new().unwrap();
+        temp.child("photos/beach.jpg").write_str("sand").unwrap();
+        temp.child("photos/beach.xmp").write_str("sidecar").unwrap();
+        temp.child("logs/app.log").write_str("started").unwrap();
+        let ddrive = Ddrive::init(temp.path()).await.unwrap();
+        ddrive
+            .add(&[temp.path()], &AddOptions::default())
+            .await
+            .unwrap();
+
+        temp.child(".ddriveignore")
+            .write_str("*.xmp\nlogs/\n!photos/keep.xmp\n")
+            .unwrap();
+        let command = IgnoreCommand::new(ddrive.context());
+        let root = temp.path();
+        let checks = command
+            .check(&[
+                root.join("photos/beach.jpg"),
+                root.join("photos/beach.xmp"),
+                root.join("photos/keep.xmp"),
+                root.join("photos/new.xmp"),
+            ])
+            .await
+            .unwrap();
+        assert!(checks[0].skipped.is_none() && checks[0].kept_by.is_none());
+        assert!(checks[0].tracked);
+        assert!(matches!(
+            &checks[1].skipped,
+            Some(SkipReason::IgnoreFile { pattern, .. }) if pattern == "*.xmp"
+        ));
+        assert!(checks[1].tracked);
+        assert!(checks[2].skipped.is_none());
+        assert_eq!(
+            checks[2].kept_by.as_ref().map(|rule| rule.pattern.as_str()),
+            Some("!photos/keep.xmp")
+        );
+        assert_eq!(checks[3].path, "photos/new.xmp");
+        assert!(checks[3].skipped.is_some() && !checks[3].tracked);
+
+        let ignored: Vec<String> = command
+            .diff()
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|(path, _)| path)
+            .collect();
+        assert_eq!(ignored, vec!["logs/app.log", "photos/beach.xmp"]);
+    }
+}
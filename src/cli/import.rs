@@ -0,0 +1,337 @@
+//! Copying files from external media into the repository.
+//!
+//! `ImportCommand` copies the files of a directory outside the repository
+//! (an SD card, an old drive) into a folder of the repository, leaving out
+//! files whose content is already tracked, and records the copies as one
+//! add action.
+
+use crate::{
+    AppContext, DdriveError, Result,
+    checksum::ChecksumCalculator,
+    cli::add::{AddCommand, AddResult},
+    media,
+    scanner::{self, FileInfo, WalkFilter},
+    utils::{format_count, unique_path},
+};
+use chrono::{DateTime, Local};
+use std::collections::HashSet;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use tracing::{info, warn};
+
+#[derive(Debug, Clone, Default)]
+pub struct ImportOptions {
+    /// strftime pattern naming each copy after its date, such as
+    /// `%Y/%m/%Y%m%d_%H%M%S`; the extension is kept. Without one, files keep
+    /// their path relative to the source directory.
+    pub rename: Option<String>,
+    /// List what would be copied without copying anything
+    pub dry_run: bool,
+}
+
+#[derive(Debug, Default)]
+pub struct ImportResult {
+    /// Source files and the paths they were copied to
+    pub imported: Vec<(PathBuf, PathBuf)>,
+    pub imported_bytes: u64,
+    /// Source files left out because their content is already tracked, or
+    /// was imported from an earlier file of the source
+    pub duplicates: Vec<PathBuf>,
+    /// Source files that could not be read or copied
+    pub failed: Vec<(PathBuf, String)>,
+    /// Result of tracking the copies
+    pub added: AddResult,
+}
+
+pub struct ImportCommand<'a> {
+    context: &'a AppContext,
+    calculator: ChecksumCalculator,
+}
+
+impl<'a> ImportCommand<'a> {
+    pub fn new(context: &'a AppContext) -> Self {
+        Self {
+            context,
+            calculator: ChecksumCalculator::from_config(&context.config.checksum),
+        }
+    }
+
+    /// Copy the files of `source` into `destination`, a directory inside the
+    /// repository, and track the copies
+    pub async fn execute(
+        &self,
+        source: &Path,
+        destination: &Path,
+        options: &ImportOptions,
+    ) -> Result<ImportResult> {
+        let repo_root = self.context.repo.root().canonicalize()?;
+        let source = source.canonicalize()?;
+        if source.starts_with(&repo_root) {
+            return Err(DdriveError::Validation {
+                message: format!(
+                    "{} is inside the repository; use add to track it",
+                    source.display()
+                ),
+            });
+        }
+        let destination = normalize_destination(&repo_root, destination)?;
+        if let Some(pattern) = &options.rename {
+            // Reject bad patterns before copying anything
+            format_date(pattern, Local::now())?;
+        }
+
+        let mut seen: HashSet<String> = self
+            .context
+            .database
+            .get_all_files()
+            .await?
+            .into_iter()
+            .map(|file| file.b3sum)
+            .collect();
+        let mut files = scanner::get_all_files(
+            &source,
+            &source,
+            WalkFilter {
+                hidden: true,
+                ..WalkFilter::ALL
+            },
+        )?;
+        files.sort_by(|a, b| a.path.cmp(&b.path));
+        info!(
+            "Importing {} files from {} into {}",
+            format_count(files.len()),
+            source.display(),
+            destination.display()
+        );
+
+        let mut result = ImportResult::default();
+        let mut claimed: HashSet<PathBuf> = HashSet::new();
+        for file in &files {
+            // Scanned paths are relative to the source directory
+            let from = source.join(&file.path);
+            let checksum = match self.calculator.calculate_checksum(&from) {
+                Ok(checksum) => checksum,
+                Err(e) => {
+                    warn!("Failed to read {}: {}", from.display(), e);
+                    result.failed.push((file.path.clone(), e.to_string()));
+                    continue;
+                }
+            };
+            if !seen.insert(checksum.clone()) {
+                result.duplicates.push(file.path.clone());
+                continue;
+            }
+
+            let to = match &options.rename {
+                Some(pattern) => {
+                    let name = format_date(pattern, self.capture_time(&from, file))?;
+                    match file.path.extension() {
+                        Some(extension) => destination.join(name).with_extension(extension),
+                        None => destination.join(name),
+                    }
+                }
+                None => destination.join(&file.path),
+            };
+            let to = unique_path(to, &claimed);
+            claimed.insert(to.clone());
+            if options.dry_run {
+                info!(
+                    "  {} -> {}",
+                    file.path.display(),
+                    display_relative(&repo_root, &to)
+                );
+            } else if let Err(e) = self.copy(&from, &to, &checksum) {
+                warn!("Failed to copy {}: {}", from.display(), e);
+                result.failed.push((file.path.clone(), e.to_string()));
+                continue;
+            }
+            result.imported_bytes += file.size;
+            result.imported.push((file.path.clone(), to));
+        }
+
+        if !result.duplicates.is_empty() {
+            info!(
+                "Skipped {} files already in the repository",
+                format_count(result.duplicates.len())
+            );
+        }
+        if options.dry_run || result.imported.is_empty() {
+            return Ok(result);
+        }
+
+        let copies: Vec<PathBuf> = result.imported.iter().map(|(_, to)| to.clone()).collect();
+        result.added = AddCommand::new(self.context).execute_files(&copies).await?;
+        info!(
+            "Imported {} files ({})",
+            format_count(result.imported.len()),
+            self.context.format_size(result.imported_bytes)
+        );
+        Ok(result)
+    }
+
+    /// When a file was taken, for naming its copy
+    fn capture_time(&self, path: &Path, file: &FileInfo) -> DateTime<Local> {
+        media::capture_time(path, file.modified, &self.context.config.media)
+    }
+
+    /// Copy `from` to `to` with its modification time, and check that the
+    /// copy has the source's checksum. Nothing is left behind on failure.
+    fn copy(&self, from: &Path, to: &Path, checksum: &str) -> Result<()> {
+        if let Some(parent) = to.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let copied = reflink_copy::reflink_or_copy(from, to)
+            .map_err(DdriveError::from)
+            .and_then(|_| {
+                let modified = fs::metadata(from)?.modified()?;
+                fs::File::options()
+                    .write(true)
+                    .open(to)?
+                    .set_modified(modified)?;
+                let copied = self.calculator.calculate_checksum(to)?;
+                if copied != checksum {
+                    return Err(DdriveError::Validation {
+                        message: format!("copy of {} does not match the source", from.display()),
+                    });
+                }
+                Ok(())
+            });
+        if copied.is_err() {
+            let _ = fs::remove_file(to);
+        }
+        copied
+    }
+}
+
+/// Resolve the destination inside the repository, relative to its root
+/// unless absolute. It is created by the first copy.
+fn normalize_destination(repo_root: &Path, destination: &Path) -> Result<PathBuf> {
+    let destination = repo_root.join(destination);
+    let destination = if destination.exists() {
+        destination.canonicalize()?
+    } else if destination
+        .components()
+        .any(|component| component == Component::ParentDir)
+    {
+        return Err(DdriveError::InvalidDirectory);
+    } else {
+        destination
+    };
+    if !destination.starts_with(repo_root) {
+        return Err(DdriveError::InvalidDirectory);
+    }
+    Ok(destination)
+}
+
+/// Format `date` with a strftime `pattern`, failing on invalid patterns
+fn format_date(pattern: &str, date: DateTime<Local>) -> Result<String> {
+    let mut name = String::new();
+    write!(name, "{}", date.format(pattern)).map_err(|_| DdriveError::Validation {
+        message: format!("invalid date pattern '{pattern}'"),
+    })?;
+    Ok(name)
+}
+
+fn display_relative(repo_root: &Path, path: &Path) -> String {
+    path.strip_prefix(repo_root)
+        .unwrap_or(path)
+        .display()
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{AddOptions, Ddrive};
+    use assert_fs::{TempDir, prelude::*};
+
+    #[tokio::test]
+    async fn test_import_skips_tracked_content() {
+        let temp = TempDir::new().unwrap();
+        let card = TempDir::new().unwrap();
+        temp.child("photos/old.jpg").write_str("old").unwrap();
+        card.child("DCIM/a.jpg").write_str("old").unwrap();
+        card.child("DCIM/b.jpg").write_str("new").unwrap();
+        card.child("DCIM/copy of b.jpg").write_str("new").unwrap();
+        card.child("DCIM/c.mov").write_str("movie").unwrap();
+        let ddrive = Ddrive::init(temp.path()).await.unwrap();
+        ddrive
+            .add(&[temp.path()], &AddOptions::default())
+            .await
+            .unwrap();
+
+        let result = ImportCommand::new(ddrive.context())
+            .execute(card.path(), Path::new("photos"), &ImportOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(result.imported.len(), 2);
+        assert_eq!(result.duplicates.len(), 2);
+        assert_eq!(result.added.new_files, 2);
+        assert_eq!(
+            fs::read_to_string(temp.path().join("photos/DCIM/b.jpg")).unwrap(),
+            "new"
+        );
+        assert!(!temp.path().join("photos/DCIM/a.jpg").exists());
+    }
+
+    #[tokio::test]
+    async fn test_import_renames_by_date() {
+        let temp = TempDir::new().unwrap();
+        let card = TempDir::new().unwrap();
+        card.child("IMG_1.JPG").write_str("one").unwrap();
+        card.child("IMG_2.JPG").write_str("two").unwrap();
+        let taken = std::time::UNIX_EPOCH + std::time::Duration::from_secs(1_700_000_000);
+        for name in ["IMG_1.JPG", "IMG_2.JPG"] {
+            fs::File::options()
+                .write(true)
+                .open(card.path().join(name))
+                .unwrap()
+                .set_modified(taken)
+                .unwrap();
+        }
+        let ddrive = Ddrive::init(temp.path()).await.unwrap();
+
+        let options = ImportOptions {
+            rename: Some("%Y/%Y%m%d".to_string()),
+            ..Default::default()
+        };
+        let result = ImportCommand::new(ddrive.context())
+            .execute(card.path(), Path::new("photos"), &options)
+            .await
+            .unwrap();
+        let day = DateTime::<Local>::from(taken)
+            .format("%Y/%Y%m%d")
+            .to_string();
+        let copies: Vec<_> = result
+            .imported
+            .iter()
+            .map(|(_, to)| {
+                to.strip_prefix(temp.path().canonicalize().unwrap())
+                    .unwrap()
+            })
+            .map(Path::to_path_buf)
+            .collect();
+        assert_eq!(
+            copies,
+            [
+                PathBuf::from(format!("photos/{day}.JPG")),
+                PathBuf::from(format!("photos/{day}-1.JPG"))
+            ]
+        );
+        // Both copies are recorded by one action
+        assert!(result.added.action_id.is_some());
+        assert_eq!(result.added.new_files, 2);
+
+        let invalid = ImportOptions {
+            rename: Some("%Q".to_string()),
+            ..Default::default()
+        };
+        assert!(
+            ImportCommand::new(ddrive.context())
+                .execute(card.path(), Path::new("photos"), &invalid)
+                .await
+                .is_err()
+        );
+    }
+}
@@ -0,0 +1,191 @@
+//! Repository initialization with starter settings.
+//!
+//! This module provides the `InitCommand` which creates a repository and
+//! writes its first `config.toml` from command-line flags or answers given
+//! on the terminal, and can seed a starter `.ddriveignore`.
+
+use crate::{
+    DdriveError, Result, codes,
+    config::{Config, ObjectStoreMode},
+    repository::Repository,
+    scanner::IGNORE_FILE,
+    utils,
+};
+use clap::ValueEnum;
+use std::fs;
+use std::path::Path;
+use tracing::{info, warn};
+
+/// Starter `.ddriveignore`: caches, partial downloads and OS clutter
+const IGNORE_TEMPLATE: &str = "\
+# Files ddrive never tracks, in .gitignore syntax
+.DS_Store
+Thumbs.db
+desktop.ini
+.Trash-*/
+.cache/
+node_modules/
+*.tmp
+*.part
+*.crdownload
+*.swp
+";
+
+/// Options for `init`
+#[derive(Debug, Clone, Default)]
+pub struct InitOptions {
+    /// Write a starter `.ddriveignore`
+    pub ignore_template: bool,
+    /// How added files are stored
+    pub object_store: Option<ObjectStoreMode>,
+    /// `key=value` config settings, as for `Config::set`
+    pub settings: Vec<(String, String)>,
+    /// Ask for the main settings on the terminal
+    pub interactive: bool,
+}
+
+pub struct InitCommand;
+
+impl InitCommand {
+    /// Create the repository at `repo_root` and write its initial settings
+    pub async fn execute(repo_root: &Path, mut options: InitOptions) -> Result<Repository> {
+        let repo = Repository::init_repository(repo_root.to_path_buf()).await?;
+        let mut config = Config::load(repo.root())?;
+
+        if options.interactive {
+            Self::ask(&mut config, &mut options)?;
+        }
+        if let Some(mode) = options.object_store {
+            config.object_store.mode = mode;
+        }
+        for (key, value) in &options.settings {
+            config.set(key, value)?;
+        }
+        config.save(repo.root())?;
+        Self::check_storage(&repo, &config);
+
+        if options.ignore_template {
+            Self::write_ignore_template(repo.root())?;
+        }
+        Ok(repo)
+    }
+
+    /// Ask for the settings not given as flags
+    fn ask(config: &mut Config, options: &mut InitOptions) -> Result<()> {
+        if options.object_store.is_none() {
+            let mode = config
+                .object_store
+                .mode
+                .to_possible_value()
+                .expect("modes are not skipped");
+            let answer = utils::ask(
+                "Store copies of files (copy, reflink, none)",
+                mode.get_name(),
+            )?;
+            let mode =
+                ObjectStoreMode::from_str(&answer, true).map_err(|_| DdriveError::Validation {
+                    message: format!("Unknown object store mode '{answer}'"),
+                })?;
+            options.object_store = Some(mode);
+        }
+
+        let questions = [
+            (
+                "verify.interval_days",
+                "Days between verifications",
+                config.verify.interval_days,
+            ),
+            (
+                "prune.retention_days",
+                "Days to keep history of deleted files",
+                config.prune.retention_days,
+            ),
+        ];
+        for (key, question, current) in questions {
+            if options.settings.iter().any(|(set, _)| set == key) {
+                continue;
+            }
+            let answer = utils::ask(question, &current.to_string())?;
+            options.settings.push((key.to_string(), answer));
+        }
+
+        if !options.ignore_template {
+            options.ignore_template = utils::confirm("Write a starter .ddriveignore?")?;
+        }
+        Ok(())
+    }
+
+    /// Warn when stored copies cannot share blocks with the tracked files
+    fn check_storage(repo: &Repository, config: &Config) {
+        if config.object_store.mode == ObjectStoreMode::None {
+            return;
+        }
+        let capabilities = repo.probe_storage();
+        for warning in capabilities.warnings() {
+            warn!("{}", warning);
+        }
+        if capabilities.shares_blocks() {
+            info!("Stored copies will share blocks with the tracked files (reflink)");
+        } else if config.object_store.mode == ObjectStoreMode::Reflink {
+            warn!("With object_store.mode = \"reflink\" no copies will be stored here");
+        } else {
+            warn!(
+                code = %codes::NO_REFLINK,
+                "Stored copies take as much space again as the files they protect"
+            );
+        }
+    }
+
+    /// Write the starter ignore file, keeping an existing one
+    fn write_ignore_template(repo_root: &Path) -> Result<()> {
+        let ignore_path = repo_root.join(IGNORE_FILE);
+        if ignore_path.exists() {
+            info!("Keeping existing {}", ignore_path.display());
+            return Ok(());
+        }
+        fs::write(&ignore_path, IGNORE_TEMPLATE)?;
+        info!("Wrote {}", ignore_path.display());
+        Ok(())
+    }
+}
+
+/// Parse a `key=value` setting for `init --set`
+pub fn parse_setting(setting: &str) -> std::result::Result<(String, String), String> {
+    let (key, value) = setting
+        .split_once('=')
+        .ok_or_else(|| format!("expected key=value, got '{setting}'"))?;
+    Ok((key.trim().to_string(), value.trim().to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_fs::TempDir;
+
+    #[tokio::test]
+    async fn test_init_with_options() {
+        let temp = TempDir::new().unwrap();
+        let options = InitOptions {
+            ignore_template: true,
+            object_store: Some(ObjectStoreMode::None),
+            settings: vec![
+                parse_setting("verify.interval_days=60").unwrap(),
+                parse_setting("scan.exclude_extensions = [\"iso\"]").unwrap(),
+            ],
+            interactive: false,
+        };
+        let repo = InitCommand::execute(temp.path(), options).await.unwrap();
+
+        let config = Config::load(repo.root()).unwrap();
+        assert_eq!(config.object_store.mode, ObjectStoreMode::None);
+        assert_eq!(config.verify.interval_days, 60);
+        assert_eq!(config.scan.exclude_extensions, vec!["iso".to_string()]);
+        assert!(temp.path().join(IGNORE_FILE).exists());
+
+        let mut config = Config::default();
+        assert!(config.set("verify.no_such_key", "1").is_err());
+        assert!(config.set("verify.interval_days", "soon").is_err());
+        config.set("logging.level", "debug").unwrap();
+        assert_eq!(config.logging.level, "debug");
+    }
+}
@@ -1,14 +1,39 @@
+use std::collections::BTreeMap;
+
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use serde_json::Value as JsonValue;
-use std::collections::HashMap;
 use tracing::info;
 
 use crate::{
     AppContext, Result,
-    database::{ActionType, HistoryRecord},
+    database::{
+        ActionObject, ActionObjects, ActionRun, ActionStatus, ActionSummary, ActionType,
+        HistoryQuery, HistoryRecord,
+    },
+    utils::{format_count, format_duration, unique_prefix},
 };
 
+/// Minimum length of the action IDs printed by `log list`, which are
+/// lengthened as needed to stay unambiguous
+const ABBREVIATED_ACTION_ID_LEN: usize = 7;
+
+/// Number of entries listed per action unless `--all` is given
+const PREVIEW_ENTRIES: usize = 5;
+
+/// What `log show` prints for an action
+#[derive(Debug, Clone, Default)]
+pub struct ShowOptions {
+    /// Only the entries of this type
+    pub action_type: Option<ActionType>,
+    /// Total files and bytes per entry type instead of the entries
+    pub stat: bool,
+    /// Every entry instead of the first few
+    pub all: bool,
+    /// The objects the action introduced and released instead of the entries
+    pub objects: bool,
+}
+
 /// A grouped history entry representing an action that may affect multiple files
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct HistoryEntry {
@@ -31,97 +56,543 @@ impl<'a> HistoryManager<'a> {
         Self { context }
     }
 
-    /// List history entries, optionally filtered by action type
-    pub async fn list_history(
+    /// Entry counts and sizes of the actions selected by `query`
+    pub async fn list_actions(&self, query: &HistoryQuery) -> Result<Vec<ActionSummary>> {
+        self.context.database.get_action_summaries(query).await
+    }
+
+    /// The first `limit` entries of an action
+    pub async fn action_sample(
         &self,
-        limit: Option<usize>,
-        action_filter: Option<ActionType>,
+        action_id: i64,
+        action_type: Option<ActionType>,
+        limit: usize,
     ) -> Result<Vec<HistoryRecord>> {
-        let history_records = self
-            .context
+        self.context
+            .database
+            .get_action_sample(action_id, action_type, limit)
+            .await
+    }
+
+    /// Every action ID in the history or with a recorded run, newest first
+    pub async fn action_ids(&self) -> Result<Vec<i64>> {
+        self.context.database.get_recorded_action_ids().await
+    }
+
+    /// How the command run of an action went, if it was recorded
+    pub async fn action_run(&self, action_id: i64) -> Result<Option<ActionRun>> {
+        self.context.database.get_action_run(action_id).await
+    }
+
+    /// The action ID named by a base58 string or an unambiguous prefix of one
+    pub async fn resolve_action_id(&self, action_id_base58: &str) -> Result<i64> {
+        self.context
             .database
-            .get_history_entries(limit, action_filter)
-            .await?;
+            .resolve_action_id(action_id_base58)
+            .await
+    }
 
-        Ok(history_records)
+    /// The objects an action introduced and released, and what expiring it
+    /// would let prune reclaim
+    pub async fn action_objects(&self, action_id: i64) -> Result<ActionObjects> {
+        self.context.database.get_action_objects(action_id).await
     }
 
-    /// Get a specific history entry by action ID (base58 string)
-    pub async fn get_history_entry(&self, action_id_base58: &str) -> Result<Vec<HistoryRecord>> {
+    /// Get the entries of a specific history action
+    pub async fn get_history_entry(&self, action_id: i64) -> Result<Vec<HistoryRecord>> {
         self.context
             .database
-            .get_history_entries_by_action_id_base58(action_id_base58)
+            .get_history_entries_by_action_id(action_id)
             .await
     }
 }
 
 pub struct HistoryCommand<'a> {
     history_manager: HistoryManager<'a>,
+    context: &'a AppContext,
 }
 
 impl<'a> HistoryCommand<'a> {
     pub fn new(context: &'a AppContext) -> Self {
         let history_manager = HistoryManager::new(context);
-        Self { history_manager }
+        Self {
+            history_manager,
+            context,
+        }
     }
 
-    /// List history entries
-    pub async fn list(
-        &self,
-        limit: Option<usize>,
-        action_filter: Option<ActionType>,
-    ) -> Result<()> {
-        let entries = self
-            .history_manager
-            .list_history(limit, action_filter)
-            .await?;
+    /// One line describing a history entry. With `compact`, renames of files
+    /// in a moved directory are left to the directory's entry.
+    fn describe(&self, entry: &HistoryRecord, compact: bool) -> Option<String> {
+        let metadata: Option<JsonValue> = entry
+            .metadata
+            .as_deref()
+            .and_then(|metadata| serde_json::from_str(metadata).ok());
+        let field = |name: &str| {
+            metadata
+                .as_ref()
+                .and_then(|metadata| metadata.get(name).cloned())
+        };
+        let action_type = entry.action_type_enum();
+        match action_type {
+            ActionType::DirectoryRename => {
+                let old_path = field("old_path");
+                let files = field("files").and_then(|files| files.as_u64());
+                Some(format!(
+                    "{} {} {} {}, {} files",
+                    action_type,
+                    old_path.as_ref().and_then(JsonValue::as_str).unwrap_or("?"),
+                    self.context.symbols().arrow(),
+                    entry.path,
+                    format_count(files.unwrap_or_default() as usize)
+                ))
+            }
+            ActionType::Rename if compact && field("directory").is_some() => None,
+            ActionType::Rename => match field("old_path") {
+                Some(JsonValue::String(old_path)) => Some(format!(
+                    "{} {} {} {}",
+                    action_type,
+                    old_path,
+                    self.context.symbols().arrow(),
+                    entry.path
+                )),
+                _ => Some(format!("{} {}", action_type, entry.path)),
+            },
+            _ => Some(format!("{} {}", action_type, entry.path)),
+        }
+    }
 
-        if entries.is_empty() {
+    /// One line saying how long a command run took, how much it processed
+    /// and whether it succeeded
+    fn describe_run(&self, run: &ActionRun) -> String {
+        let outcome = match run.status_enum() {
+            ActionStatus::Success => "success".to_string(),
+            ActionStatus::PartialFailure => {
+                format!(
+                    "partial failure ({} errors)",
+                    format_count(run.errors as usize)
+                )
+            }
+            ActionStatus::Interrupted => "interrupted, resumed by the next run".to_string(),
+        };
+        format!(
+            "{}: {} files ({}) in {}, {}",
+            run.command,
+            format_count(run.files as usize),
+            self.context.format_size(run.bytes as u64),
+            format_duration(run.duration()),
+            outcome
+        )
+    }
+
+    /// List the actions selected by `query`, newest first, each with its
+    /// entry counts and sizes and its first few entries. Returns the
+    /// summaries of the listed actions.
+    pub async fn list(&self, query: &HistoryQuery) -> Result<Vec<ActionSummary>> {
+        let summaries = self.history_manager.list_actions(query).await?;
+        if summaries.is_empty() {
             info!("No history entries found");
-            return Ok(());
+            return Ok(summaries);
         }
 
-        let entries =
-            entries
+        let mut action_ids: Vec<String> = self
+            .history_manager
+            .action_ids()
+            .await?
+            .into_iter()
+            .map(|action_id| bs58::encode(action_id.to_be_bytes()).into_string())
+            .collect();
+        action_ids.sort();
+
+        for action in summaries.chunk_by(|a, b| a.action_id == b.action_id) {
+            let action_id = action[0].action_id;
+            let run = self.history_manager.action_run(action_id).await?;
+            let mut counts: Vec<String> = action
                 .iter()
-                .fold(HashMap::new(), |h: HashMap<i64, Vec<&HistoryRecord>>, e| {
-                    let mut h = h;
-                    h.entry(e.action_id)
-                        .and_modify(|l: &mut Vec<&HistoryRecord>| l.push(e))
-                        .or_insert(vec![e]);
-                    h
-                });
-
-        for (action_id, entries) in entries {
+                .filter_map(|summary| {
+                    let count = format!(
+                        "{} {}",
+                        format_count(summary.entries as usize),
+                        ActionType::from(summary.action_type?)
+                    );
+                    Some(if summary.size > 0 {
+                        format!(
+                            "{} ({})",
+                            count,
+                            self.context.format_size(summary.size as u64)
+                        )
+                    } else {
+                        count
+                    })
+                })
+                .collect();
+            if counts.is_empty() {
+                counts.extend(run.as_ref().map(|run| run.command.clone()));
+            }
             info!(
-                "{} {}",
+                "{} {}: {}",
                 DateTime::from_timestamp(action_id, 0).unwrap_or_else(Utc::now),
-                bs58::encode(action_id.to_be_bytes()).into_string(),
+                unique_prefix(
+                    &bs58::encode(action_id.to_be_bytes()).into_string(),
+                    &action_ids,
+                    ABBREVIATED_ACTION_ID_LEN
+                ),
+                counts.join(", ")
             );
-            for entry in entries.iter().take(5) {
-                info!("  {} {}", entry.action_type, entry.path,)
+            if let Some(run) = &run {
+                info!("  {}", self.describe_run(run));
             }
-            if entries.len() > 5 {
-                info!("  and {} more...", entries.len() - 5);
+
+            let sample = self
+                .history_manager
+                .action_sample(action_id, query.action_type, PREVIEW_ENTRIES)
+                .await?;
+            for entry in &sample {
+                if let Some(line) = self.describe(entry, true) {
+                    info!("  {}", line);
+                }
+            }
+            let listed: i64 = action.iter().map(|summary| summary.listed).sum();
+            if listed as usize > sample.len() {
+                info!("  and {} more...", listed as usize - sample.len());
             }
         }
 
-        Ok(())
+        Ok(summaries)
     }
 
     /// Show details of a specific history entry
-    pub async fn show(&self, action_id: &str) -> Result<()> {
+    pub async fn show(&self, action_id: &str, options: &ShowOptions) -> Result<()> {
+        let action_id = self.history_manager.resolve_action_id(action_id).await?;
         let entries = self.history_manager.get_history_entry(action_id).await?;
-        if entries.is_empty() {
+        let run = self.history_manager.action_run(action_id).await?;
+        if entries.is_empty() && run.is_none() {
             info!("No such entry");
+            return Ok(());
+        }
+        info!(
+            "{} {}",
+            DateTime::from_timestamp(action_id, 0).unwrap_or_else(Utc::now),
+            bs58::encode(action_id.to_be_bytes()).into_string()
+        );
+        if let Some(run) = &run {
+            info!("  {}", self.describe_run(run));
         }
-        let mut entries = entries.iter();
-        let entry = entries.next().expect("entry");
-        info!("{} {}", entry.action_timestamp(), entry.action_id_base58(),);
-        for entry in entries {
-            info!("  {} {}", entry.action_type, entry.path,)
+        if options.objects {
+            let objects = self.history_manager.action_objects(action_id).await?;
+            self.display_objects(&objects, options.all);
+            return Ok(());
+        }
+        // Moved directories first, then every file with its own entry
+        let (directories, files): (Vec<_>, Vec<_>) = entries
+            .iter()
+            .filter(|entry| {
+                options
+                    .action_type
+                    .is_none_or(|action_type| entry.action_type_enum() == action_type)
+            })
+            .partition(|entry| entry.action_type_enum() == ActionType::DirectoryRename);
+        let entries: Vec<_> = directories.into_iter().chain(files).collect();
+
+        if options.stat {
+            let mut totals: BTreeMap<i64, (usize, u64)> = BTreeMap::new();
+            for entry in &entries {
+                let total = totals.entry(entry.action_type).or_default();
+                total.0 += 1;
+                total.1 += entry.size.unwrap_or_default() as u64;
+            }
+            for (action_type, (count, size)) in totals {
+                let action_type = ActionType::from(action_type);
+                let unit = match action_type {
+                    ActionType::DirectoryRename => "directories",
+                    _ => "files",
+                };
+                info!(
+                    "  {}: {} {} ({})",
+                    action_type,
+                    format_count(count),
+                    unit,
+                    self.context.format_size(size)
+                );
+            }
+            return Ok(());
+        }
+
+        let shown = if options.all {
+            entries.len()
+        } else {
+            PREVIEW_ENTRIES.min(entries.len())
+        };
+        for entry in &entries[..shown] {
+            if let Some(line) = self.describe(entry, false) {
+                info!("  {}", line);
+            }
+        }
+        if entries.len() > shown {
+            info!(
+                "  and {} more... (use --all to list them)",
+                entries.len() - shown
+            );
         }
 
         Ok(())
     }
+
+    /// List the objects an action introduced and released with their sizes
+    /// and where they are stored, and what prune could reclaim once the
+    /// action's history expires
+    fn display_objects(&self, objects: &ActionObjects, all: bool) {
+        let total = |objects: &[ActionObject]| objects.iter().map(|object| object.size).sum();
+        let sections = [
+            ("Objects introduced", &objects.introduced),
+            (
+                "Objects released (no tracked file has them; older history may keep them)",
+                &objects.released,
+            ),
+        ];
+        for (title, section) in sections {
+            info!(
+                "  {}: {} ({})",
+                title,
+                format_count(section.len()),
+                self.context.format_size(total(section))
+            );
+            let shown = if all {
+                section.len()
+            } else {
+                PREVIEW_ENTRIES.min(section.len())
+            };
+            for object in &section[..shown] {
+                let mut paths = object.paths[0].clone();
+                if object.paths.len() > 1 {
+                    paths.push_str(&format!(" and {} more", object.paths.len() - 1));
+                }
+                info!(
+                    "    {} ({}) {}",
+                    paths,
+                    self.context.format_size(object.size),
+                    self.object_location(&object.b3sum)
+                );
+            }
+            if section.len() > shown {
+                info!(
+                    "    and {} more... (use --all to list them)",
+                    section.len() - shown
+                );
+            }
+        }
+
+        let reclaimable: u64 = objects.reclaimable.iter().map(|object| object.size).sum();
+        info!(
+            "  Reclaimable by prune once this action expires: {} objects ({})",
+            format_count(objects.reclaimable.len()),
+            self.context.format_size(reclaimable)
+        );
+    }
+
+    /// Where the object store keeps an object, or that it holds no copy
+    fn object_location(&self, b3sum: &str) -> String {
+        let objects = &self.context.objects;
+        match objects.local_path(b3sum) {
+            Some(path) if path.exists() => path.display().to_string(),
+            Some(_) => format!("{b3sum}, no stored copy"),
+            None => format!("{}/{}", objects.location(), b3sum),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{AddOptions, DdriveError, api::testing::repository};
+
+    #[tokio::test]
+    async fn test_history_actions_newest_first() {
+        let (_temp, ddrive) = repository(&[]).await;
+        let database = &ddrive.context().database;
+        let entry = |path: &str, size: i64| (path.to_string(), Some("b3".to_string()), Some(size));
+        for (action_id, action_type, entries) in [
+            (1_000, ActionType::Add, vec![entry("a", 1), entry("b", 2)]),
+            (3_000, ActionType::Delete, vec![entry("a", 1)]),
+            (2_000, ActionType::Add, vec![entry("c", 4)]),
+        ] {
+            database
+                .insert_history_entries(action_id, action_type, &entries, None)
+                .await
+                .unwrap();
+        }
+        let actions = |query: HistoryQuery| async move {
+            database
+                .get_action_summaries(&query)
+                .await
+                .unwrap()
+                .iter()
+                .map(|summary| (summary.action_id, summary.entries, summary.size))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            actions(HistoryQuery::default()).await,
+            [(3_000, 1, 1), (2_000, 1, 4), (1_000, 2, 3)]
+        );
+        let page = HistoryQuery {
+            limit: 1,
+            offset: 1,
+            ..Default::default()
+        };
+        assert_eq!(actions(page).await, [(2_000, 1, 4)]);
+        let between = HistoryQuery {
+            before: DateTime::from_timestamp(3_000, 0),
+            after: DateTime::from_timestamp(1_500, 0),
+            ..Default::default()
+        };
+        assert_eq!(actions(between).await, [(2_000, 1, 4)]);
+        let adds = HistoryQuery {
+            action_type: Some(ActionType::Add),
+            ..Default::default()
+        };
+        assert_eq!(actions(adds).await, [(2_000, 1, 4), (1_000, 2, 3)]);
+
+        let sample = database.get_action_sample(1_000, None, 1).await.unwrap();
+        assert_eq!(sample[0].path, "a");
+    }
+
+    #[tokio::test]
+    async fn test_action_objects() {
+        let (_temp, ddrive) = repository(&[]).await;
+        let database = &ddrive.context().database;
+        let entry = |path: &str, b3sum: &str, size: i64| {
+            (path.to_string(), Some(b3sum.to_string()), Some(size))
+        };
+        for (action_id, action_type, entries) in [
+            (
+                1_000,
+                ActionType::Add,
+                vec![entry("a", "first", 5), entry("b", "kept", 4)],
+            ),
+            (2_000, ActionType::Update, vec![entry("a", "second", 6)]),
+            (2_000, ActionType::Delete, vec![entry("b", "kept", 4)]),
+        ] {
+            database
+                .insert_history_entries(action_id, action_type, &entries, None)
+                .await
+                .unwrap();
+        }
+        let checksums = |objects: &[ActionObject]| {
+            objects
+                .iter()
+                .map(|object| object.b3sum.clone())
+                .collect::<Vec<_>>()
+        };
+
+        let first = database.get_action_objects(1_000).await.unwrap();
+        assert_eq!(checksums(&first.introduced), ["first", "kept"]);
+        assert!(first.released.is_empty());
+        // The deletion entry of the later action still references "kept"
+        let reclaimable: Vec<&str> = first.reclaimable.iter().map(|o| o.b3sum.as_str()).collect();
+        assert_eq!(reclaimable, ["first"]);
+
+        let second = database.get_action_objects(2_000).await.unwrap();
+        assert_eq!(checksums(&second.introduced), ["second"]);
+        assert_eq!(checksums(&second.released), ["first", "kept"]);
+        assert_eq!(second.released[1].paths, ["b"]);
+        let reclaimable: Vec<&str> = second
+            .reclaimable
+            .iter()
+            .map(|o| o.b3sum.as_str())
+            .collect();
+        assert_eq!(reclaimable, ["second"]);
+    }
+
+    #[tokio::test]
+    async fn test_action_id_prefixes() {
+        let (_temp, ddrive) = repository(&[]).await;
+        let database = &ddrive.context().database;
+        let entries = [("a".to_string(), Some("b3".to_string()), Some(1))];
+        for action_id in [1_000, 1_001, 2_000_000] {
+            database
+                .insert_history_entries(action_id, ActionType::Add, &entries, None)
+                .await
+                .unwrap();
+        }
+        let encode = |action_id: i64| bs58::encode(action_id.to_be_bytes()).into_string();
+        let (first, second) = (encode(1_000), encode(1_001));
+        let shared = first
+            .bytes()
+            .zip(second.bytes())
+            .take_while(|(a, b)| a == b)
+            .count();
+
+        assert_eq!(database.resolve_action_id(&first).await.unwrap(), 1_000);
+        assert_eq!(
+            database
+                .resolve_action_id(&first[..shared + 1])
+                .await
+                .unwrap(),
+            1_000
+        );
+        assert!(matches!(
+            database.resolve_action_id(&first[..shared]).await,
+            Err(DdriveError::Validation { .. })
+        ));
+        assert!(matches!(
+            database.resolve_action_id("zzz").await,
+            Err(DdriveError::Validation { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_action_runs_recorded() {
+        let (temp, ddrive) = repository(&[("a.txt", "aaa")]).await;
+        let added = ddrive
+            .add(&[temp.path()], &AddOptions::default())
+            .await
+            .unwrap();
+        let database = &ddrive.context().database;
+
+        let run = database
+            .get_action_run(added.action_id.unwrap())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            (
+                run.command.as_str(),
+                run.files,
+                run.bytes,
+                run.status_enum()
+            ),
+            ("add", 1, 3, ActionStatus::Success)
+        );
+
+        // Runs are listed even when they recorded no history entries, and
+        // runs sharing an action ID are folded together
+        for errors in [0, 2] {
+            database
+                .record_action_run(
+                    1_000,
+                    "verify",
+                    std::time::Duration::from_millis(5),
+                    4,
+                    10,
+                    errors,
+                )
+                .await
+                .unwrap();
+        }
+        let run = database.get_action_run(1_000).await.unwrap().unwrap();
+        assert_eq!(
+            (run.files, run.errors, run.duration_ms, run.status_enum()),
+            (8, 2, 10, ActionStatus::PartialFailure)
+        );
+        let summaries = database
+            .get_action_summaries(&HistoryQuery::default())
+            .await
+            .unwrap();
+        let oldest = summaries.last().unwrap();
+        assert_eq!(
+            (oldest.action_id, oldest.action_type, oldest.entries),
+            (1_000, None, 0)
+        );
+    }
 }
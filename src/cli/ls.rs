@@ -0,0 +1,136 @@
+use crate::{
+    AppContext, DdriveError, Result, content_type,
+    database::TypedFile,
+    utils::{format_count, from_storage_path, to_storage_path},
+};
+use std::path::Path;
+use tracing::info;
+
+/// Shown for files whose content type could not be detected
+const UNKNOWN_TYPE: &str = "unknown";
+
+pub struct ListCommand<'a> {
+    context: &'a AppContext,
+}
+
+impl<'a> ListCommand<'a> {
+    pub fn new(context: &'a AppContext) -> Self {
+        Self { context }
+    }
+
+    /// List the tracked files under `path` with their content types, keeping
+    /// only those matching `type_filter` (a MIME type like `image/png`, or a
+    /// kind like `image`) if given
+    pub async fn execute(&self, path: &Path, type_filter: Option<&str>) -> Result<Vec<TypedFile>> {
+        let files = self.typed_files(path).await?;
+        let files: Vec<TypedFile> = files
+            .into_iter()
+            .filter(|file| match type_filter {
+                Some(filter) => file
+                    .content_type
+                    .as_deref()
+                    .is_some_and(|content_type| content_type::matches(content_type, filter)),
+                None => true,
+            })
+            .collect();
+
+        let symbols = self.context.symbols();
+        for file in &files {
+            let content_type = file.content_type.as_deref();
+            info!(
+                "{}{}  {}  {}",
+                symbols.type_icon(content_type, &file.path),
+                file.path,
+                content_type.unwrap_or(UNKNOWN_TYPE),
+                self.context.format_size(file.size as u64)
+            );
+        }
+        let total: u64 = files.iter().map(|file| file.size as u64).sum();
+        info!(
+            "{} files ({})",
+            format_count(files.len()),
+            self.context.format_size(total)
+        );
+        Ok(files)
+    }
+
+    /// The tracked files under `path`. Files recorded before content types
+    /// were detected are typed from disk now, and the types are saved.
+    pub async fn typed_files(&self, path: &Path) -> Result<Vec<TypedFile>> {
+        let repo_root = self.context.repo.root().canonicalize()?;
+        let path = path.canonicalize()?;
+        let Ok(prefix) = path.strip_prefix(&repo_root) else {
+            return Err(DdriveError::InvalidDirectory);
+        };
+        let prefix = to_storage_path(prefix);
+        let mut files: Vec<TypedFile> = self
+            .context
+            .database
+            .get_typed_files(&prefix)
+            .await?
+            .into_iter()
+            .filter(|file| {
+                prefix.is_empty()
+                    || file.path == prefix
+                    || file
+                        .path
+                        .strip_prefix(prefix.as_str())
+                        .is_some_and(|rest| rest.starts_with('/'))
+            })
+            .collect();
+
+        let mut detected = Vec::new();
+        for file in files.iter_mut().filter(|file| file.content_type.is_none()) {
+            let absolute_path = repo_root.join(from_storage_path(&file.path));
+            // Only trust the file on disk while it still has the recorded size
+            let unchanged = absolute_path
+                .metadata()
+                .is_ok_and(|metadata| metadata.len() == file.size as u64);
+            if !unchanged {
+                continue;
+            }
+            if let Some(content_type) = content_type::detect(&absolute_path) {
+                detected.push((file.path.clone(), content_type.clone()));
+                file.content_type = Some(content_type);
+            }
+        }
+        if !detected.is_empty() {
+            self.context.database.set_content_types(&detected).await?;
+        }
+        Ok(files)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{AddOptions, api::testing::repository, cli::stats::StatsCommand};
+    use assert_fs::prelude::*;
+
+    #[tokio::test]
+    async fn test_content_types_detected_on_add() {
+        let (temp, ddrive) = repository(&[("notes.md", "# notes")]).await;
+        // Named like text, but the content is a PNG
+        temp.child("photos/scan.txt")
+            .write_binary(b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR")
+            .unwrap();
+        ddrive
+            .add(&[temp.path()], &AddOptions::default())
+            .await
+            .unwrap();
+
+        let list = ListCommand::new(ddrive.context());
+        let images = list.execute(temp.path(), Some("image")).await.unwrap();
+        let images: Vec<_> = images.iter().map(|file| file.path.as_str()).collect();
+        assert_eq!(images, ["photos/scan.txt"]);
+        let text = list.execute(temp.path(), Some("text/plain")).await.unwrap();
+        assert_eq!(text[0].path, "notes.md");
+
+        let types = StatsCommand::new(ddrive.context()).types(10).await.unwrap();
+        let types: Vec<_> = types
+            .iter()
+            .map(|stats| (stats.content_type.as_str(), stats.files))
+            .collect();
+        assert_eq!(types, [("image/png", 1), ("text/plain", 1)]);
+    }
+}
@@ -0,0 +1,593 @@
+//! Plain copies of the tracked tree somewhere else.
+//!
+//! `MirrorCommand` keeps a browseable copy of the tracked files in a
+//! directory or at an rsync target (`host:path`, `user@host:path` or
+//! `rsync://`). The checksum each file had when it was mirrored is recorded
+//! per destination, so later runs only transfer files whose content changed,
+//! and every run re-hashes a random sample of the mirror.
+
+use crate::{
+    AppContext, DdriveError, Result,
+    checksum::ChecksumCalculator,
+    database::{FileRecord, MirroredFile},
+    utils::{format_count, from_storage_path},
+};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::UNIX_EPOCH;
+use tracing::{info, warn};
+
+/// Mirrored files re-hashed after each run unless told otherwise
+pub const DEFAULT_SPOT_CHECKS: usize = 16;
+
+/// Where a mirror is kept
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MirrorTarget {
+    /// A directory on this machine, written directly
+    Directory(PathBuf),
+    /// A destination handed to rsync, such as `backup@nas:/volume1/photos`
+    Rsync(String),
+}
+
+impl MirrorTarget {
+    /// `host:path`, `user@host:path` and `rsync://` URLs are rsync targets;
+    /// anything else is a directory, relative to `current_dir` unless absolute
+    pub fn parse(destination: &str, current_dir: &Path) -> Self {
+        if destination.starts_with("rsync://") {
+            return Self::Rsync(destination.to_string());
+        }
+        // A colon before any slash names a host; one letter is a Windows drive
+        match destination.split_once(':') {
+            Some((host, _)) if host.len() > 1 && !host.contains('/') => {
+                Self::Rsync(destination.to_string())
+            }
+            _ => Self::Directory(current_dir.join(destination)),
+        }
+    }
+
+    /// Name the mirrored files of this target are recorded under
+    fn key(&self) -> String {
+        match self {
+            Self::Directory(path) => path.display().to_string(),
+            Self::Rsync(destination) => destination.trim_end_matches('/').to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct MirrorOptions {
+    /// List what would be transferred and removed without changing anything
+    pub dry_run: bool,
+    /// Number of mirrored files to re-hash after the transfer
+    pub spot_checks: usize,
+}
+
+impl Default for MirrorOptions {
+    fn default() -> Self {
+        Self {
+            dry_run: false,
+            spot_checks: DEFAULT_SPOT_CHECKS,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct MirrorResult {
+    /// Files transferred because they are new or their checksum changed
+    pub copied: Vec<String>,
+    pub copied_bytes: u64,
+    /// Files already mirrored with their current checksum
+    pub unchanged: usize,
+    /// Files removed from the mirror because they are no longer tracked
+    pub removed: Vec<String>,
+    /// Files that could not be mirrored, with the reason
+    pub failed: Vec<(String, String)>,
+    /// Mirrored files re-hashed after the transfer
+    pub spot_checked: usize,
+    /// Re-hashed files that are missing from the mirror or differ from the
+    /// checksum they were mirrored with; the next run transfers them again
+    pub mismatched: Vec<String>,
+}
+
+pub struct MirrorCommand<'a> {
+    context: &'a AppContext,
+    calculator: ChecksumCalculator,
+}
+
+impl<'a> MirrorCommand<'a> {
+    pub fn new(context: &'a AppContext) -> Self {
+        Self {
+            context,
+            calculator: ChecksumCalculator::from_config(&context.config.checksum),
+        }
+    }
+
+    /// Bring the mirror at `target` up to date with the tracked files, then
+    /// spot-check it
+    pub async fn execute(
+        &self,
+        target: &MirrorTarget,
+        options: &MirrorOptions,
+    ) -> Result<MirrorResult> {
+        let target = self.resolve(target, options.dry_run)?;
+        let key = target.key();
+        let _lock = self.context.repo.lock()?;
+
+        let files = self.context.database.get_all_files().await?;
+        let mirrored: HashMap<String, String> = self
+            .context
+            .database
+            .get_mirrored_files(&key)
+            .await?
+            .into_iter()
+            .map(|file| (file.path, file.b3sum))
+            .collect();
+
+        let mut result = MirrorResult::default();
+        let mut pending = Vec::new();
+        for file in &files {
+            let up_to_date = mirrored.get(&file.path) == Some(&file.b3sum)
+                && match &target {
+                    MirrorTarget::Directory(root) => {
+                        root.join(from_storage_path(&file.path)).exists()
+                    }
+                    MirrorTarget::Rsync(_) => true,
+                };
+            if up_to_date {
+                result.unchanged += 1;
+            } else {
+                pending.push(file);
+            }
+        }
+        let tracked: HashSet<&str> = files.iter().map(|file| file.path.as_str()).collect();
+        let mut removed: Vec<String> = mirrored
+            .into_keys()
+            .filter(|path| !tracked.contains(path.as_str()))
+            .collect();
+        removed.sort();
+
+        info!(
+            "Mirroring {} tracked files to {}: {} to transfer, {} to remove",
+            format_count(files.len()),
+            key,
+            format_count(pending.len()),
+            format_count(removed.len())
+        );
+        if options.dry_run {
+            for file in &pending {
+                info!("  + {}", file.path);
+            }
+            for path in &removed {
+                info!("  - {}", path);
+            }
+            result.copied = pending.iter().map(|file| file.path.clone()).collect();
+            result.copied_bytes = pending.iter().map(|file| file.size as u64).sum();
+            result.removed = removed;
+            return Ok(result);
+        }
+
+        let copied = match &target {
+            MirrorTarget::Directory(root) => self.copy_files(root, &pending, &mut result),
+            MirrorTarget::Rsync(destination) => {
+                self.rsync_files(destination, &pending, &mut result)?
+            }
+        };
+        match &target {
+            MirrorTarget::Directory(root) => remove_files(root, &removed)?,
+            MirrorTarget::Rsync(destination) => self.rsync_removals(destination, &removed)?,
+        }
+        result.copied = copied.iter().map(|file| file.path.clone()).collect();
+        result.copied_bytes = copied.iter().map(|file| file.size as u64).sum();
+        self.context
+            .database
+            .update_mirrored_files(&key, &copied, &removed)
+            .await?;
+        result.removed = removed;
+        info!(
+            "Mirrored {} files ({}), {} unchanged, {} removed",
+            format_count(result.copied.len()),
+            self.context.format_size(result.copied_bytes),
+            format_count(result.unchanged),
+            format_count(result.removed.len())
+        );
+
+        self.spot_check(&target, &key, options.spot_checks, &mut result)
+            .await?;
+        Ok(result)
+    }
+
+    /// Make a directory target absolute and check it is outside the
+    /// repository, which would otherwise mirror into itself
+    fn resolve(&self, target: &MirrorTarget, dry_run: bool) -> Result<MirrorTarget> {
+        let MirrorTarget::Directory(path) = target else {
+            return Ok(target.clone());
+        };
+        let resolved = if path.exists() {
+            path.canonicalize()?
+        } else {
+            path.clone()
+        };
+        if resolved.starts_with(self.context.repo.root().canonicalize()?) {
+            return Err(DdriveError::Validation {
+                message: format!(
+                    "{} is inside the repository; mirror to another disk or host",
+                    resolved.display()
+                ),
+            });
+        }
+        if !dry_run && !resolved.exists() {
+            fs::create_dir_all(&resolved)?;
+            return Ok(MirrorTarget::Directory(resolved.canonicalize()?));
+        }
+        Ok(MirrorTarget::Directory(resolved))
+    }
+
+    /// Copy `files` into the directory `root`, checking each copy against
+    /// the recorded checksum before it replaces the mirrored file. Returns
+    /// the files copied.
+    fn copy_files(
+        &self,
+        root: &Path,
+        files: &[&FileRecord],
+        result: &mut MirrorResult,
+    ) -> Vec<MirroredFile> {
+        let mut copied = Vec::new();
+        for file in files {
+            let from = self.context.repo.root().join(from_storage_path(&file.path));
+            let to = root.join(from_storage_path(&file.path));
+            match self.copy_file(&from, &to, &file.b3sum) {
+                Ok(()) => copied.push(mirrored_file(file)),
+                Err(e) => {
+                    warn!("Failed to mirror {}: {}", file.path, e);
+                    result.failed.push((file.path.clone(), e.to_string()));
+                }
+            }
+        }
+        copied
+    }
+
+    /// Copy `from` next to `to` with its modification time and rename it
+    /// into place if its checksum is `checksum`
+    fn copy_file(&self, from: &Path, to: &Path, checksum: &str) -> Result<()> {
+        let (Some(dir), Some(name)) = (to.parent(), to.file_name()) else {
+            return Err(DdriveError::InvalidDirectory);
+        };
+        fs::create_dir_all(dir)?;
+        let partial = dir.join(format!(".{}.ddrive-partial", name.to_string_lossy()));
+        let copied = reflink_copy::reflink_or_copy(from, &partial)
+            .map_err(DdriveError::from)
+            .and_then(|_| {
+                let modified = fs::metadata(from)?.modified()?;
+                fs::File::options()
+                    .write(true)
+                    .open(&partial)?
+                    .set_modified(modified)?;
+                if self.calculator.calculate_checksum(&partial)? != checksum {
+                    return Err(DdriveError::Validation {
+                        message:
+                            "content differs from the recorded checksum; run add or verify first"
+                                .to_string(),
+                    });
+                }
+                fs::rename(&partial, to)?;
+                Ok(())
+            });
+        if copied.is_err() {
+            let _ = fs::remove_file(&partial);
+        }
+        copied
+    }
+
+    /// Send `files` to `destination` with rsync, leaving out files modified
+    /// since they were added. Returns the files sent.
+    fn rsync_files(
+        &self,
+        destination: &str,
+        files: &[&FileRecord],
+        result: &mut MirrorResult,
+    ) -> Result<Vec<MirroredFile>> {
+        let mut sent = Vec::new();
+        for file in files {
+            let path = self.context.repo.root().join(from_storage_path(&file.path));
+            match modified_since_add(&path, file) {
+                Ok(false) => sent.push(mirrored_file(file)),
+                Ok(true) => {
+                    warn!("Not mirroring {}: modified since it was added", file.path);
+                    result.failed.push((
+                        file.path.clone(),
+                        "modified since it was added; run add first".to_string(),
+                    ));
+                }
+                Err(e) => {
+                    warn!("Failed to mirror {}: {}", file.path, e);
+                    result.failed.push((file.path.clone(), e.to_string()));
+                }
+            }
+        }
+        if !sent.is_empty() {
+            let paths: Vec<&str> = sent.iter().map(|file| file.path.as_str()).collect();
+            let source = format!("{}/", self.context.repo.root().display());
+            self.rsync(&paths, &source, &rsync_dir(destination), &[])?;
+        }
+        Ok(sent)
+    }
+
+    /// Delete `paths` at `destination` by syncing them from an empty
+    /// directory, where rsync finds them missing
+    fn rsync_removals(&self, destination: &str, paths: &[String]) -> Result<()> {
+        if paths.is_empty() {
+            return Ok(());
+        }
+        let empty = self
+            .context
+            .repo
+            .tmp_dir()
+            .join(format!("mirror-{}-empty", std::process::id()));
+        fs::create_dir_all(&empty)?;
+        let paths: Vec<&str> = paths.iter().map(String::as_str).collect();
+        let removed = self.rsync(
+            &paths,
+            &format!("{}/", empty.display()),
+            &rsync_dir(destination),
+            &["--delete-missing-args"],
+        );
+        let _ = fs::remove_dir(&empty);
+        removed
+    }
+
+    /// Run rsync on the listed `paths` (relative to `source`)
+    fn rsync(
+        &self,
+        paths: &[&str],
+        source: &str,
+        destination: &str,
+        options: &[&str],
+    ) -> Result<()> {
+        let tmp_dir = self.context.repo.tmp_dir();
+        fs::create_dir_all(&tmp_dir)?;
+        let list = tmp_dir.join(format!("mirror-{}.list", std::process::id()));
+        fs::write(&list, paths.join("\0"))?;
+        let status = Command::new("rsync")
+            .args(["--archive", "--from0"])
+            .arg(format!("--files-from={}", list.display()))
+            .args(options)
+            .arg(source)
+            .arg(destination)
+            .status();
+        let _ = fs::remove_file(&list);
+        match status {
+            Ok(status) if status.success() => Ok(()),
+            Ok(status) => Err(DdriveError::Mirror {
+                message: format!("rsync to {destination} failed ({status})"),
+            }),
+            Err(e) => Err(DdriveError::Mirror {
+                message: format!("could not run rsync: {e}"),
+            }),
+        }
+    }
+
+    /// Re-hash up to `count` mirrored files picked at random. Files that do
+    /// not match are forgotten, so the next run transfers them again.
+    async fn spot_check(
+        &self,
+        target: &MirrorTarget,
+        key: &str,
+        count: usize,
+        result: &mut MirrorResult,
+    ) -> Result<()> {
+        let sample = self.context.database.get_mirror_sample(key, count).await?;
+        if sample.is_empty() {
+            return Ok(());
+        }
+
+        let fetched = match target {
+            MirrorTarget::Directory(root) => root.clone(),
+            MirrorTarget::Rsync(destination) => {
+                let fetched = self
+                    .context
+                    .repo
+                    .tmp_dir()
+                    .join(format!("mirror-{}-check", std::process::id()));
+                fs::create_dir_all(&fetched)?;
+                let paths: Vec<&str> = sample.iter().map(|file| file.path.as_str()).collect();
+                let copied = self.rsync(
+                    &paths,
+                    &rsync_dir(destination),
+                    &format!("{}/", fetched.display()),
+                    &["--ignore-missing-args"],
+                );
+                if let Err(e) = copied {
+                    let _ = fs::remove_dir_all(&fetched);
+                    return Err(e);
+                }
+                fetched
+            }
+        };
+        for file in &sample {
+            let path = fetched.join(from_storage_path(&file.path));
+            let matches = path.exists()
+                && self
+                    .calculator
+                    .calculate_checksum(&path)
+                    .is_ok_and(|checksum| checksum == file.b3sum);
+            if !matches {
+                warn!("Mirrored copy of {} is missing or damaged", file.path);
+                result.mismatched.push(file.path.clone());
+            }
+        }
+        if matches!(target, MirrorTarget::Rsync(_)) {
+            let _ = fs::remove_dir_all(&fetched);
+        }
+        result.spot_checked = sample.len();
+
+        if result.mismatched.is_empty() {
+            info!(
+                "Spot-checked {} mirrored files: all match",
+                format_count(sample.len())
+            );
+        } else {
+            warn!(
+                "Spot-checked {} mirrored files: {} missing or damaged, they will be transferred again on the next run",
+                format_count(sample.len()),
+                format_count(result.mismatched.len())
+            );
+            self.context
+                .database
+                .update_mirrored_files(key, &[], &result.mismatched)
+                .await?;
+        }
+        Ok(())
+    }
+}
+
+fn mirrored_file(file: &FileRecord) -> MirroredFile {
+    MirroredFile {
+        path: file.path.clone(),
+        b3sum: file.b3sum.clone(),
+        size: file.size,
+    }
+}
+
+/// Whether the size or modification time of the file at `path` differs
+/// from its record
+fn modified_since_add(path: &Path, file: &FileRecord) -> Result<bool> {
+    let metadata = fs::metadata(path)?;
+    if metadata.len() != file.size as u64 {
+        return Ok(true);
+    }
+    let modified = metadata.modified()?.duration_since(UNIX_EPOCH).ok();
+    Ok(match (modified, file.mtime_ns) {
+        (Some(modified), Some(mtime_ns)) => modified.as_nanos() as i64 != mtime_ns,
+        _ => false,
+    })
+}
+
+/// Delete `paths` under `root`, and the directories left empty
+fn remove_files(root: &Path, paths: &[String]) -> Result<()> {
+    for path in paths {
+        let path = root.join(from_storage_path(path));
+        match fs::remove_file(&path) {
+            Ok(()) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e.into()),
+        }
+        let mut dir = path.parent();
+        while let Some(parent) = dir.filter(|dir| *dir != root) {
+            if fs::remove_dir(parent).is_err() {
+                break;
+            }
+            dir = parent.parent();
+        }
+    }
+    Ok(())
+}
+
+/// `destination` as a directory for rsync, whose contents are synced
+fn rsync_dir(destination: &str) -> String {
+    format!("{}/", destination.trim_end_matches('/'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{AddOptions, Ddrive};
+    use assert_fs::{TempDir, prelude::*};
+
+    #[test]
+    fn test_parse_target() {
+        let cwd = Path::new("/home/me");
+        assert_eq!(
+            MirrorTarget::parse("backup@nas:/volume1/photos", cwd),
+            MirrorTarget::Rsync("backup@nas:/volume1/photos".to_string())
+        );
+        assert_eq!(
+            MirrorTarget::parse("rsync://nas/photos", cwd),
+            MirrorTarget::Rsync("rsync://nas/photos".to_string())
+        );
+        assert_eq!(
+            MirrorTarget::parse("/mnt/usb", cwd),
+            MirrorTarget::Directory(PathBuf::from("/mnt/usb"))
+        );
+        assert_eq!(
+            MirrorTarget::parse("copies/a:b", cwd),
+            MirrorTarget::Directory(PathBuf::from("/home/me/copies/a:b"))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_mirror_transfers_changes() {
+        let temp = TempDir::new().unwrap();
+        let mirror = TempDir::new().unwrap();
+        temp.child("photos/a.jpg").write_str("a").unwrap();
+        temp.child("photos/b.jpg").write_str("b").unwrap();
+        temp.child("notes.txt").write_str("notes").unwrap();
+        let ddrive = Ddrive::init(temp.path()).await.unwrap();
+        ddrive
+            .add(&[temp.path()], &AddOptions::default())
+            .await
+            .unwrap();
+
+        let target = MirrorTarget::Directory(mirror.path().to_path_buf());
+        let command = MirrorCommand::new(ddrive.context());
+        let first = command
+            .execute(&target, &MirrorOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(first.copied.len(), 3);
+        assert_eq!(first.spot_checked, 3);
+        assert!(first.mismatched.is_empty());
+        assert_eq!(
+            fs::read_to_string(mirror.path().join("photos/a.jpg")).unwrap(),
+            "a"
+        );
+
+        // Only the changed file is copied, and untracked files are removed
+        temp.child("photos/a.jpg").write_str("edited").unwrap();
+        fs::remove_file(temp.path().join("notes.txt")).unwrap();
+        ddrive
+            .add(&[temp.path()], &AddOptions::default())
+            .await
+            .unwrap();
+        crate::cli::rm::RmCommand::new(ddrive.context())
+            .deleted(None, false)
+            .await
+            .unwrap();
+        let second = command
+            .execute(&target, &MirrorOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(second.copied, ["photos/a.jpg"]);
+        assert_eq!(second.unchanged, 1);
+        assert_eq!(second.removed, ["notes.txt"]);
+        assert!(!mirror.path().join("notes.txt").exists());
+        assert_eq!(
+            fs::read_to_string(mirror.path().join("photos/a.jpg")).unwrap(),
+            "edited"
+        );
+
+        // A damaged copy is caught by the spot check and transferred again
+        fs::write(mirror.path().join("photos/b.jpg"), "x").unwrap();
+        let checked = command
+            .execute(&target, &MirrorOptions::default())
+            .await
+            .unwrap();
+        assert!(checked.copied.is_empty());
+        assert_eq!(checked.mismatched, ["photos/b.jpg"]);
+        let repaired = command
+            .execute(&target, &MirrorOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(repaired.copied, ["photos/b.jpg"]);
+        assert!(repaired.mismatched.is_empty());
+
+        let inside = MirrorTarget::Directory(temp.path().join("mirror"));
+        assert!(
+            command
+                .execute(&inside, &MirrorOptions::default())
+                .await
+                .is_err()
+        );
+    }
+}
@@ -1,25 +1,69 @@
 pub mod add;
+pub mod backup;
+pub mod bench;
+pub mod checksums;
+pub mod coverage;
 pub mod dedup;
+pub mod doctor;
+pub mod export;
+pub mod ignore;
+pub mod import;
+pub mod init;
 pub mod log;
+pub mod ls;
+pub mod mirror;
+pub mod organize;
 pub mod prune;
+pub mod repos;
 pub mod rm;
+pub mod serve;
+pub mod stats;
 pub mod status;
+pub mod summary;
+pub mod tier;
 pub mod verify;
 
 use std::path::PathBuf;
 
-use crate::{AppContext, Result, database::ActionType, repository::Repository};
-use add::AddCommand;
-use dedup::DedupCommand;
-use log::HistoryCommand;
+use crate::{
+    AppContext, AppContextBuilder, Result,
+    checksum::HashAlgorithm,
+    codes,
+    config::ObjectStoreMode,
+    database::{ActionType, HistoryQuery},
+    registry::{self, Registry},
+    repository::Repository,
+    tr,
+    utils::{self, DirectoryGrouping, SizeUnits},
+};
+use add::{AddCommand, AddResult};
+use backup::{BackupCommand, BackupOptions};
+use bench::{BenchCommand, BenchOptions};
+use checksums::ChecksumsCommand;
+use coverage::CoverageCommand;
+use dedup::{DedupCommand, DuplicateFormat};
+use doctor::DoctorCommand;
+use export::{ExportCommand, ExportOptions};
+use ignore::IgnoreCommand;
+use import::{ImportCommand, ImportOptions};
+use init::{InitCommand, InitOptions};
+use log::{HistoryCommand, ShowOptions};
+use ls::ListCommand;
+use mirror::{MirrorCommand, MirrorOptions, MirrorTarget};
+use organize::OrganizeCommand;
 use prune::PruneCommand;
-use rm::RmCommand;
-use status::StatusCommand;
-use verify::VerifyCommand;
+use repos::ReposCommand;
+use rm::{RmCommand, RmOptions};
+use serve::ServeCommand;
+use stats::StatsCommand;
+use status::{StatusCommand, StatusListing};
+use summary::SummaryFile;
+use tier::TierCommand;
+use verify::{ExternalVerification, PathFilter, VerifyCommand, VerifyResult};
 
-use clap::{Parser, Subcommand};
+use clap::{Args, Parser, Subcommand};
 use glob::Pattern;
-use tracing::{debug, info};
+use tracing::{debug, info, warn};
 
 #[derive(Parser)]
 #[command(name = "ddrive")]
@@ -28,16 +72,88 @@ use tracing::{debug, info};
 pub struct Cli {
     #[command(subcommand)]
     pub command: Option<Commands>,
+
+    #[command(flatten)]
+    pub global: GlobalArgs,
+}
+
+/// Options accepted by every command, overriding the configuration
+#[derive(Args, Clone, Default)]
+pub struct GlobalArgs {
+    /// Run as if started in this directory instead of the current one (like `git -C`)
+    #[arg(short = 'C', long = "repo", global = true, value_name = "PATH")]
+    pub repo: Option<PathBuf>,
+
+    /// Use ASCII markers instead of emoji and unicode symbols (like general.emoji = false)
+    #[arg(long, global = true)]
+    pub plain: bool,
+
+    /// Units to display sizes in (like general.size_units)
+    #[arg(long, global = true, value_enum)]
+    pub size_units: Option<SizeUnits>,
+
+    /// Open the database read-only, for status, log and verify (which then records nothing)
+    #[arg(long, global = true)]
+    pub read_only: bool,
 }
 
 #[derive(Subcommand)]
 pub enum Commands {
     /// Initialize a new ddrive repository
-    Init,
+    Init {
+        /// Write a starter .ddriveignore (caches, partial downloads, OS clutter)
+        #[arg(long)]
+        ignore_template: bool,
+
+        /// How added files are stored: full copies (reflinked when possible), reflinks only, or none
+        #[arg(long, value_enum)]
+        object_store: Option<ObjectStoreMode>,
+
+        /// Store no copies, only checksums and metadata (like --object-store none)
+        #[arg(long, conflicts_with = "object_store")]
+        no_object_store: bool,
+
+        /// Initial config value, e.g. --set verify.interval_days=60 (repeatable)
+        #[arg(long = "set", value_name = "KEY=VALUE", value_parser = init::parse_setting)]
+        settings: Vec<(String, String)>,
+
+        /// Ask for the main settings on the terminal
+        #[arg(short, long)]
+        interactive: bool,
+    },
     /// Add files for tracking (and update existing files)
     Add {
         /// Path to track (file or directory). Only files within this path will be considered for deletion.
-        path: PathBuf,
+        #[arg(required_unless_present_any = ["from_file", "from_stdin", "auto"], conflicts_with_all = ["from_file", "from_stdin", "auto"])]
+        path: Option<PathBuf>,
+
+        /// Track exactly the files listed in this file (one path per line), without scanning directories
+        #[arg(long, conflicts_with = "from_stdin")]
+        from_file: Option<PathBuf>,
+
+        /// Track exactly the files listed on standard input, without scanning directories
+        #[arg(long, conflicts_with = "auto")]
+        from_stdin: bool,
+
+        /// Track the files matching the [track] include/exclude globs across the whole repository
+        #[arg(long, conflicts_with = "from_file")]
+        auto: bool,
+
+        /// List entries are separated by NUL bytes (as from `find -print0`) instead of newlines
+        #[arg(short = '0', long)]
+        null: bool,
+
+        /// Do not descend into directories on other file systems
+        #[arg(short = 'x', long)]
+        one_file_system: bool,
+
+        /// Record renames matched only by size and creation time without asking
+        #[arg(long)]
+        confirm_renames: bool,
+
+        /// Write a JSON summary (counts, failures, duration, action ID) to this file
+        #[arg(long)]
+        summary_file: Option<PathBuf>,
     },
     /// Remove files from tracking
     Rm {
@@ -46,69 +162,585 @@ pub enum Commands {
     },
     /// Verify integrity of tracked files
     Verify {
-        /// Optional path prefix to verify only files under this path
+        /// Only verify files under this directory, or matching this glob pattern
         #[arg(long)]
-        path: Option<Pattern>,
+        path: Option<PathFilter>,
+
+        /// Only verify files modified or added on or after this date (e.g. 2024-05-01)
+        #[arg(long, value_parser = utils::parse_date, conflicts_with = "failed")]
+        since: Option<chrono::DateTime<chrono::Utc>>,
 
         /// Force verification of all files regardless of last check time
         #[arg(short, long)]
         force: bool,
+
+        /// Checksum to verify against (sha256/xxh3 must be enabled in [checksum] secondary)
+        #[arg(long, value_enum, default_value_t = HashAlgorithm::Blake3)]
+        algorithm: HashAlgorithm,
+
+        /// Also fail for files modified since they were last added (with --external: for extra files)
+        #[arg(long)]
+        strict: bool,
+
+        /// Re-check only files that failed a previous verification
+        #[arg(long)]
+        failed: bool,
+
+        /// Move corrupted files to .ddrive/quarantine and restore the stored copy
+        #[arg(long)]
+        quarantine: bool,
+
+        /// Also re-hash the stored objects of the verified files
+        #[arg(long)]
+        objects: bool,
+
+        /// Hash files even if their size and modification time are unchanged
+        #[arg(long)]
+        no_metadata_shortcut: bool,
+
+        /// Only report, without recording check times, failures or the run (implied by --read-only)
+        #[arg(long, conflicts_with = "quarantine")]
+        no_update: bool,
+
+        /// Check a copy of the repository elsewhere (e.g. a restore test) against the recorded checksums by relative path, reporting missing, different and extra files
+        #[arg(
+            long,
+            value_name = "DIR",
+            conflicts_with_all = ["since", "force", "failed", "quarantine", "objects", "no_metadata_shortcut"]
+        )]
+        external: Option<PathBuf>,
+
+        /// Write a JSON summary (counts, failures, duration, action ID) to this file
+        #[arg(long)]
+        summary_file: Option<PathBuf>,
     },
     /// Find duplicate files based on BLAKE3 checksums
     Dedup {
         /// Optional path pattern to filter which files to consider for deduplication
         #[arg(short, long)]
         path: Option<String>,
+
+        /// Share extents through the kernel (FIDEDUPERANGE on btrfs/XFS) instead of replacing duplicates with reflinks
+        #[arg(long)]
+        share_extents: bool,
+
+        /// Print the duplicate groups as JSON instead of deduplicating them
+        #[arg(long, conflicts_with_all = ["share_extents", "csv"])]
+        json: bool,
+
+        /// Print one CSV row per duplicate file instead of deduplicating them
+        #[arg(long, conflicts_with = "share_extents")]
+        csv: bool,
+
+        /// Report which files of this directory outside the repository are already tracked, by content
+        #[arg(long, value_name = "DIR", conflicts_with_all = ["path", "share_extents", "json", "csv"])]
+        against: Option<PathBuf>,
     },
     /// Show repository status and statistics
-    Status,
-    /// Prune deleted files and handle duplicates
-    Prune,
+    Status {
+        #[command(subcommand)]
+        view: Option<StatusView>,
+
+        /// Only show the cached totals, without scanning the tree
+        #[arg(long, conflicts_with = "full")]
+        fast: bool,
+
+        /// Scan the tree even in repositories large enough to default to --fast
+        #[arg(long)]
+        full: bool,
+
+        /// Directories listed per group of changed files before the rest are summed up (0 lists all)
+        #[arg(long, default_value_t = 10, value_name = "N")]
+        max_dirs: usize,
+
+        /// Group changed files by this many directory levels
+        #[arg(long, value_name = "N", value_parser = clap::value_parser!(u64).range(1..))]
+        depth: Option<u64>,
+
+        /// Show changed files as a tree of directories with their file counts and sizes
+        #[arg(long)]
+        tree: bool,
+    },
+    /// Test ignore rules against paths and the tracked files
+    Ignore {
+        #[command(subcommand)]
+        action: IgnoreAction,
+    },
+    /// List tracked files with the content types detected from their content
+    Ls {
+        /// Directory (or file) to list
+        #[arg(default_value = ".")]
+        path: PathBuf,
+
+        /// Only list files of this content type: a MIME type (image/png) or its kind (image)
+        #[arg(short = 't', long = "type", value_name = "TYPE")]
+        content_type: Option<String>,
+    },
+    /// Show tracked and untracked files per top-level directory and when each was last verified
+    Coverage,
+    /// Analyze the recorded history
+    Stats {
+        #[command(subcommand)]
+        view: StatsView,
+    },
+    /// Check repository health and suggest fixes
+    Doctor,
+    /// Measure scan, hashing, SQLite and copy speed here and recommend settings
+    Bench {
+        /// Size of the test file (default 128MiB)
+        #[arg(long, value_parser = utils::parse_size)]
+        size: Option<u64>,
+
+        /// Write the recommended settings to .ddrive/config.toml
+        #[arg(long)]
+        apply: bool,
+    },
+    /// Prune deleted files and report duplicates
+    Prune {
+        /// List the history entries, records and objects that would be removed, without removing them
+        #[arg(long)]
+        dry_run: bool,
+
+        /// Also prune the oldest history beyond the retention policy until this much space is free (e.g. 100GB)
+        #[arg(long, value_parser = utils::parse_size)]
+        target_free: Option<u64>,
+
+        /// Only estimate the space pruning would reclaim with prune.retention_days at each of these values (default 30,60,90), removing nothing
+        #[arg(
+            long,
+            value_name = "DAYS",
+            value_delimiter = ',',
+            num_args = 0..,
+            default_missing_values = ["30", "60", "90"],
+            conflicts_with_all = ["dry_run", "target_free"]
+        )]
+        estimate: Option<Vec<u32>>,
+
+        /// Write a JSON summary (counts, failures, duration, action ID) to this file
+        #[arg(long)]
+        summary_file: Option<PathBuf>,
+    },
+    /// Move objects only history has referenced for object_store.cold_after_days to the cold store
+    Tier {
+        /// List what would be moved without moving anything
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Copy files from external media into the repository, skipping content that is already tracked
+    Import {
+        /// Directory to copy from, outside the repository (e.g. an SD card)
+        source: PathBuf,
+
+        /// Repository folder to copy into
+        #[arg(long)]
+        to: PathBuf,
+
+        /// Name copies by their date with this strftime pattern, e.g. '%Y/%m/%Y%m%d_%H%M%S'
+        #[arg(long, value_name = "PATTERN")]
+        rename: Option<String>,
+
+        /// List what would be copied without copying anything
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Move tracked photos and videos into folders, recording the moves as renames
+    Organize {
+        /// Directory whose photos and videos to organize (default: the repository root)
+        path: Option<PathBuf>,
+
+        /// Move them into YYYY/MM folders by the date they were taken
+        #[arg(long, required = true)]
+        by_date: bool,
+
+        /// List the moves without moving anything
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Keep a plain copy of the tracked files in a directory or at an rsync target
+    Mirror {
+        /// Directory, or rsync destination such as user@host:/backups/photos
+        destination: String,
+
+        /// Number of mirrored files to re-hash afterwards, picked at random
+        #[arg(long, default_value_t = mirror::DEFAULT_SPOT_CHECKS)]
+        spot_checks: usize,
+
+        /// List what would be transferred and removed without changing anything
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Fill a disc or drive with stored objects no exported medium holds yet, plus a manifest
+    Export {
+        /// Mount point of the empty medium to write
+        #[arg(long = "media", value_name = "MOUNT_POINT")]
+        mount_point: PathBuf,
+
+        /// Capacity to fill (e.g. 25GB); the free space of the medium by default
+        #[arg(long, value_parser = utils::parse_size)]
+        size: Option<u64>,
+
+        /// Name of the medium (default: <repository>-NNN)
+        #[arg(long)]
+        label: Option<String>,
+
+        /// List what would be written without writing anything
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Work with the removable media written by export
+    Media {
+        #[command(subcommand)]
+        action: MediaAction,
+    },
+    /// Verify tracked files against a published checksum list (sha256sum/b3sum format)
+    ImportChecksums {
+        /// Checksum list; paths in it are relative to its directory
+        file: PathBuf,
+
+        /// Algorithm of the list (detected from the file name when omitted)
+        #[arg(long, value_enum)]
+        algorithm: Option<HashAlgorithm>,
+    },
+    /// Print checksums of tracked files in b3sum format (for `b3sum --check`)
+    ExportChecksums {
+        /// Directory (or file) to export; listed paths are relative to it
+        #[arg(default_value = ".")]
+        path: PathBuf,
+
+        /// Checksum to export (sha256/xxh3 only for files that recorded one)
+        #[arg(long, value_enum, default_value_t = HashAlgorithm::Blake3)]
+        algorithm: HashAlgorithm,
+    },
+    /// Back up or restore the metadata database
+    Db {
+        #[command(subcommand)]
+        action: DbAction,
+    },
     /// View and manage command history
     Log {
         #[command(subcommand)]
         action: Option<HistoryAction>,
     },
+    /// Work with all repositories registered on this machine
+    Repos {
+        #[command(subcommand)]
+        action: ReposAction,
+    },
+    /// Serve a JSON API for dashboards and monitoring
+    Serve {
+        /// Address to listen on (default from serve.listen, 127.0.0.1:7878)
+        #[arg(long)]
+        listen: Option<String>,
+    },
+    /// List the stable codes of errors and warnings
+    Codes {
+        /// Print the registry as Markdown, as in docs/CODES.md
+        #[arg(long)]
+        markdown: bool,
+    },
 }
 
 #[derive(Subcommand, Clone)]
 pub enum RmAction {
-    Tracked { pattern: Pattern },
-    Deleted { pattern: Option<Pattern> },
+    Tracked {
+        pattern: Pattern,
+
+        /// Also delete the files from disk (their stored copies are kept).
+        /// Files without a stored copy of their current content are kept
+        #[arg(long)]
+        delete_files: bool,
+
+        /// Delete files from disk even without a stored copy of their current content
+        #[arg(long, requires = "delete_files")]
+        force: bool,
+
+        /// Forget the files and their history past prune.retention_days, moving stored objects no other file or history entry references to the trash
+        #[arg(long)]
+        drop_objects: bool,
+
+        /// List what would be removed without removing anything
+        #[arg(long)]
+        dry_run: bool,
+
+        /// Do not ask for confirmation before deleting
+        #[arg(short, long)]
+        yes: bool,
+
+        /// Pick which of the matching files to remove from a numbered list
+        #[arg(short, long)]
+        interactive: bool,
+    },
+    Deleted {
+        pattern: Option<Pattern>,
+
+        /// Also remove files whose stored copy is missing from the object store
+        #[arg(long)]
+        force: bool,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum StatsView {
+    /// Show the directories and file types whose files change most often
+    Churn {
+        /// Only count changes since this date or age (e.g. 2024-05-01 or 90d)
+        #[arg(long, value_parser = utils::parse_date)]
+        since: Option<chrono::DateTime<chrono::Utc>>,
+
+        /// Number of directories and file types to show
+        #[arg(short, long, default_value_t = 10)]
+        limit: usize,
+    },
+    /// Show how recorded integrity failures spread over file age, size, directory, device and type
+    Corruption {
+        /// Number of directories, devices and content types to show
+        #[arg(short, long, default_value_t = 10)]
+        limit: usize,
+    },
+    /// Show the number and size of tracked files per content type
+    Types {
+        /// Number of content types to show
+        #[arg(short, long, default_value_t = 20)]
+        limit: usize,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum StatusView {
+    /// List the files that are not protected yet
+    Untracked {
+        /// Explain why this path is not protected (ignore file, config or repository data)
+        #[arg(long, value_name = "PATH")]
+        why: Option<PathBuf>,
+
+        /// Page of the list to show
+        #[arg(long, default_value_t = 1)]
+        page: usize,
+
+        /// Files listed per page
+        #[arg(long, default_value_t = 50)]
+        per_page: usize,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum IgnoreAction {
+    /// Show whether each path would be tracked and which rule matched it
+    Check {
+        /// Paths to check; they need not exist
+        #[arg(required = true)]
+        paths: Vec<PathBuf>,
+    },
+    /// List tracked files that the present ignore rules would ignore
+    Diff,
+}
+
+#[derive(Subcommand)]
+pub enum MediaAction {
+    /// List exported media with their size and when each was last verified
+    List,
+    /// Re-hash the objects on an exported medium
+    Verify {
+        /// Mount point of the medium
+        mount_point: PathBuf,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum DbAction {
+    /// Write a consistent copy of the metadata database, optionally encrypted
+    Backup {
+        /// File to write the copy to
+        output: PathBuf,
+        /// Encrypt the copy with a passphrase from DDRIVE_PASSPHRASE or the terminal
+        #[arg(long)]
+        encrypt: bool,
+        /// Encrypt with the passphrase in this file instead
+        #[arg(long, value_name = "FILE")]
+        keyfile: Option<PathBuf>,
+    },
+    /// Replace the metadata database with a copy written by backup
+    Restore {
+        /// Copy to restore
+        input: PathBuf,
+        /// File holding the passphrase of an encrypted copy
+        #[arg(long, value_name = "FILE")]
+        keyfile: Option<PathBuf>,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum ReposAction {
+    /// List registered repositories and whether their drives are attached
+    List,
+    /// Show a one-line status of each available repository
+    Status,
+    /// Verify the files due for a check in every available repository
+    VerifyAll,
+    /// Register an existing repository, such as one initialized before the
+    /// registry existed
+    Add {
+        #[arg(default_value = ".")]
+        path: PathBuf,
+    },
+    /// Remove a repository from the registry (its files are not touched)
+    Forget { path: PathBuf },
 }
 
 #[derive(Subcommand)]
 pub enum HistoryAction {
     /// List command history
     List {
-        /// Maximum number of entries to show
+        /// Maximum number of actions to show
         #[arg(short, long, default_value = "20")]
         limit: usize,
+        /// Number of newer actions to skip, for paging through older ones
+        #[arg(long, default_value = "0")]
+        offset: usize,
         /// Filter by action type (add, delete)
         #[arg(short, long)]
         filter: Option<ActionType>,
+        /// Only actions before this date (e.g. 2024-05-01)
+        #[arg(long, value_parser = utils::parse_date)]
+        before: Option<chrono::DateTime<chrono::Utc>>,
+        /// Only actions on or after this date (e.g. 2024-05-01)
+        #[arg(long, value_parser = utils::parse_date)]
+        after: Option<chrono::DateTime<chrono::Utc>>,
     },
     /// Show details of a specific history entry
     Show {
-        /// History entry action ID to show
+        /// Action ID to show, or an unambiguous prefix of one
         id: String,
+        /// Only show entries of this type (add, delete, rename, update)
+        #[arg(short = 't', long = "type")]
+        action_type: Option<ActionType>,
+        /// Show total entries and bytes per entry type instead of the entries
+        #[arg(long)]
+        stat: bool,
+        /// List every entry instead of the first few
+        #[arg(long)]
+        all: bool,
+        /// Show the objects the action introduced and released, and what
+        /// prune would reclaim once it expires, instead of the entries
+        #[arg(long, conflicts_with_all = ["stat", "action_type"])]
+        objects: bool,
     },
 }
 
+/// Open the repository containing `current_dir`, applying the global flags
+async fn open_context(current_dir: PathBuf, global: &GlobalArgs) -> Result<AppContext> {
+    let repo = Repository::find_repository(current_dir)?;
+    let mut context = AppContextBuilder::new(repo)
+        .read_only(global.read_only)
+        .build()
+        .await?;
+    if global.plain {
+        context.config.general.emoji = false;
+    }
+    if let Some(size_units) = global.size_units {
+        context.config.general.size_units = size_units;
+    }
+    crate::i18n::init(context.config.general.locale.as_deref());
+    Ok(context)
+}
+
+/// Check that `command` can run with `--read-only`: only those that look at
+/// the repository without recording anything
+fn check_read_only(command: &Option<Commands>) -> Result<()> {
+    match command {
+        None
+        | Some(Commands::Status { .. })
+        | Some(Commands::Log { .. })
+        | Some(Commands::Codes { .. })
+        | Some(Commands::Prune {
+            estimate: Some(_), ..
+        }) => Ok(()),
+        Some(Commands::Verify {
+            quarantine: false, ..
+        }) => Ok(()),
+        Some(Commands::Verify { .. }) => Err(crate::DdriveError::Validation {
+            message: tr!("error-read-only-quarantine"),
+        }),
+        Some(_) => Err(crate::DdriveError::Validation {
+            message: tr!("error-read-only-command"),
+        }),
+    }
+}
+
 pub async fn run_command(cli: Cli) -> Result<()> {
-    let current_dir = std::env::current_dir()?;
+    let global = cli.global;
+    if global.read_only {
+        check_read_only(&cli.command)?;
+    }
+    let current_dir = match &global.repo {
+        Some(repo) => std::env::current_dir()?.join(repo),
+        None => std::env::current_dir()?,
+    };
     match cli.command {
-        Some(Commands::Init) => {
-            Repository::init_repository(current_dir).await?;
+        Some(Commands::Init {
+            ignore_template,
+            object_store,
+            no_object_store,
+            settings,
+            interactive,
+        }) => {
+            let options = InitOptions {
+                ignore_template,
+                object_store: object_store.or(no_object_store.then_some(ObjectStoreMode::None)),
+                settings,
+                interactive,
+            };
+            let repo = InitCommand::execute(&current_dir, options).await?;
+            if let Err(e) = registry::register_repository(repo.root()) {
+                warn!("Could not register {}: {}", repo.root().display(), e);
+            }
             Ok(())
         }
-        Some(Commands::Add { path }) => {
-            let repo = Repository::find_repository(current_dir)?;
-            let context = AppContext::new(repo).await?;
-            let add_command = AddCommand::new(&context);
+        Some(Commands::Add {
+            path,
+            from_file,
+            from_stdin,
+            null,
+            auto,
+            one_file_system,
+            confirm_renames,
+            summary_file,
+        }) => {
+            let summary = SummaryFile::new(summary_file, "add");
+            let list = if let Some(list_file) = &from_file {
+                Some(utils::read_path_list(
+                    std::fs::File::open(current_dir.join(list_file))?,
+                    null,
+                )?)
+            } else if from_stdin {
+                Some(utils::read_path_list(std::io::stdin().lock(), null)?)
+            } else {
+                None
+            };
+            let mut context = open_context(current_dir.clone(), &global).await?;
+            if one_file_system {
+                context.config.scan.same_file_system = true;
+            }
+            let add_command = AddCommand::new(&context).confirm_renames(confirm_renames);
 
-            debug!("Tracking files in: {}", path.display());
-            let result = add_command.execute(&path).await?;
+            let added = match (list, path) {
+                (Some(list), _) => {
+                    // Listed paths are relative to where the listing tool ran
+                    let paths: Vec<PathBuf> = list.iter().map(|p| current_dir.join(p)).collect();
+                    debug!("Tracking {} listed files", paths.len());
+                    add_command.execute_files(&paths).await
+                }
+                (None, _) if auto => add_command.execute_auto().await,
+                (None, Some(path)) => {
+                    debug!("Tracking files in: {}", path.display());
+                    add_command.execute(&path).await
+                }
+                (None, None) => unreachable!("clap requires a path, a list or --auto"),
+            };
+            let result = match added {
+                Ok(result) => result,
+                Err(e) => return summary.finish(None, Err(e)),
+            };
 
             if result.new_files > 0 || result.changed_files > 0 || result.renamed_files > 0 {
                 let mut parts = Vec::new();
@@ -121,99 +753,627 @@ pub async fn run_command(cli: Cli) -> Result<()> {
                 if result.renamed_files > 0 {
                     parts.push(format!("{} renamed", result.renamed_files));
                 }
+                if result.moved_directories > 0 {
+                    parts.push(format!("{} directories moved", result.moved_directories));
+                }
                 info!("Processed: {}", parts.join(", "));
-            } else {
+            } else if result.failed.is_empty() && result.unreadable.is_empty() {
                 info!("No changes detected - all files are up to date");
             }
-            Ok(())
+            summary.finish(Some(result.summary()), check_add_result(&result))
         }
         Some(Commands::Rm { action }) => {
-            let repo = Repository::find_repository(current_dir)?;
-            let context = AppContext::new(repo).await?;
+            let context = open_context(current_dir, &global).await?;
             let rm_command = RmCommand::new(&context);
 
             match action {
-                RmAction::Tracked { pattern } => rm_command.tracked(pattern).await?,
-                RmAction::Deleted { pattern } => rm_command.deleted(pattern).await?,
+                RmAction::Tracked {
+                    pattern,
+                    delete_files,
+                    force,
+                    drop_objects,
+                    dry_run,
+                    yes,
+                    interactive,
+                } => {
+                    let options = RmOptions {
+                        delete_files,
+                        force,
+                        drop_objects,
+                        dry_run,
+                        yes,
+                        interactive,
+                    };
+                    let result = rm_command.tracked(pattern, options).await?;
+                    if !result.undeleted_files.is_empty() {
+                        return Err(crate::DdriveError::FileSystem {
+                            message: tr!(
+                                "error-undeleted-files",
+                                count = result.undeleted_files.len()
+                            ),
+                        });
+                    }
+                    if !result.kept_files.is_empty() {
+                        return Err(crate::DdriveError::Validation {
+                            message: tr!("error-kept-files", count = result.kept_files.len()),
+                        });
+                    }
+                }
+                RmAction::Deleted { pattern, force } => {
+                    rm_command.deleted(pattern, force).await?;
+                }
             };
             Ok(())
         }
-        Some(Commands::Verify { path, force }) => {
-            let repo = Repository::find_repository(current_dir)?;
-            let context = AppContext::new(repo).await?;
-            let verify_command = VerifyCommand::new(&context);
+        Some(Commands::Verify {
+            path,
+            since,
+            force,
+            algorithm,
+            strict,
+            failed,
+            quarantine,
+            objects,
+            no_metadata_shortcut,
+            no_update,
+            external,
+            summary_file,
+        }) => {
+            let summary = SummaryFile::new(summary_file, "verify");
+            let mut context = open_context(current_dir.clone(), &global).await?;
+            if let Some(directory) = external {
+                let result = match VerifyCommand::new(&context)
+                    .external(&current_dir.join(directory), path.as_ref(), algorithm)
+                    .await
+                {
+                    Ok(result) => result,
+                    Err(e) => return summary.finish(None, Err(e)),
+                };
+                return summary.finish(
+                    Some(result.summary()),
+                    check_external_result(&result, strict),
+                );
+            }
+            if quarantine {
+                context.config.verify.quarantine = true;
+            }
+            if objects {
+                context.config.verify.objects = true;
+            }
+            if no_metadata_shortcut {
+                context.config.verify.metadata_shortcut = false;
+            }
+            if let Err(e) = utils::lower_priority(&context.config.performance) {
+                warn!("Could not lower the priority of this run: {}", e);
+            }
+            let mut verify_command = VerifyCommand::new(&context);
+            if no_update {
+                verify_command = verify_command.update(false);
+            }
+
+            let result = match verify_command
+                .execute(path.as_ref(), since, force, algorithm, failed)
+                .await
+            {
+                Ok(result) => result,
+                Err(e) => return summary.finish(None, Err(e)),
+            };
+            summary.finish(Some(result.summary()), check_verify_result(&result, strict))
+        }
+        Some(Commands::Dedup {
+            path,
+            share_extents,
+            json,
+            csv,
+            against,
+        }) => {
+            let context = open_context(current_dir.clone(), &global).await?;
+            if let Some(directory) = against {
+                DedupCommand::new(&context)
+                    .against(&current_dir.join(directory))
+                    .await?;
+                return Ok(());
+            }
 
-            let result = verify_command.execute(path.as_ref(), force).await?;
+            let dedup_command = if let Some(path_filter) = path {
+                DedupCommand::with_path_filter(&context, path_filter)
+            } else {
+                DedupCommand::new(&context)
+            };
 
-            if result.failed_files > 0 {
+            let format = if json {
+                Some(DuplicateFormat::Json)
+            } else if csv {
+                Some(DuplicateFormat::Csv)
+            } else {
+                None
+            };
+            if let Some(format) = format {
+                let mut stdout = std::io::stdout().lock();
+                dedup_command.export(format, &mut stdout).await?;
+            } else {
+                dedup_command.share_extents(share_extents).execute().await?;
+            }
+            Ok(())
+        }
+        Some(Commands::Ignore { action }) => {
+            let context = open_context(current_dir.clone(), &global).await?;
+            let ignore_command = IgnoreCommand::new(&context);
+            match action {
+                IgnoreAction::Check { paths } => {
+                    let paths: Vec<PathBuf> =
+                        paths.iter().map(|path| current_dir.join(path)).collect();
+                    ignore_command.check(&paths).await?;
+                }
+                IgnoreAction::Diff => {
+                    ignore_command.diff().await?;
+                }
+            }
+            Ok(())
+        }
+        Some(Commands::Ls { path, content_type }) => {
+            let context = open_context(current_dir.clone(), &global).await?;
+            ListCommand::new(&context)
+                .execute(&current_dir.join(path), content_type.as_deref())
+                .await?;
+            Ok(())
+        }
+        Some(Commands::Coverage) => {
+            let context = open_context(current_dir, &global).await?;
+            CoverageCommand::new(&context).execute().await?;
+            Ok(())
+        }
+        Some(Commands::Stats { view }) => {
+            let context = open_context(current_dir, &global).await?;
+            let stats_command = StatsCommand::new(&context);
+            match view {
+                StatsView::Churn { since, limit } => {
+                    stats_command.churn(since, limit).await?;
+                }
+                StatsView::Corruption { limit } => {
+                    stats_command.corruption(limit).await?;
+                }
+                StatsView::Types { limit } => {
+                    stats_command.types(limit).await?;
+                }
+            }
+            Ok(())
+        }
+
+        Some(Commands::Doctor) => {
+            let context = open_context(current_dir, &global).await?;
+            let report = DoctorCommand::new(&context).execute().await?;
+            if report.has_failures() {
                 return Err(crate::DdriveError::Validation {
                     message: format!(
-                        "{} file(s) failed integrity verification. Run 'ddrive status' for details.",
-                        result.failed_files
+                        "Repository health grade {}: see the failed checks above",
+                        report.grade()
                     ),
                 });
             }
             Ok(())
         }
-        Some(Commands::Dedup { path }) => {
-            let repo = Repository::find_repository(current_dir)?;
-            let context = AppContext::new(repo).await?;
+        Some(Commands::Status {
+            view,
+            fast,
+            full,
+            max_dirs,
+            depth,
+            tree,
+        }) => {
+            let context = open_context(current_dir.clone(), &global).await?;
+            let status_command = StatusCommand::new(&context).listing(StatusListing {
+                grouping: DirectoryGrouping {
+                    max_dirs,
+                    depth: depth.map(|depth| depth as usize),
+                },
+                tree,
+            });
+            if let Some(StatusView::Untracked {
+                why,
+                page,
+                per_page,
+            }) = view
+            {
+                match why {
+                    Some(path) => {
+                        status_command.explain(&current_dir.join(path)).await?;
+                    }
+                    None => {
+                        status_command.untracked(page, per_page).await?;
+                    }
+                }
+            } else if fast || (!full && status_command.prefers_fast().await?) {
+                status_command.execute_fast().await?;
+            } else {
+                status_command.execute().await?;
+            }
+            Ok(())
+        }
 
-            let dedup_command = if let Some(path_filter) = path {
-                DedupCommand::with_path_filter(&context, path_filter)
+        Some(Commands::Prune {
+            dry_run,
+            target_free,
+            estimate,
+            summary_file,
+        }) => {
+            let summary = SummaryFile::new(summary_file, "prune");
+            let context = open_context(current_dir, &global).await?;
+            let prune_command = PruneCommand::new(&context);
+            if let Some(retention_days) = estimate {
+                return match prune_command.estimate(&retention_days).await {
+                    Ok(estimate) => summary.finish(Some(estimate.summary()), Ok(())),
+                    Err(e) => summary.finish(None, Err(e)),
+                };
+            }
+            let result = match prune_command.execute(dry_run, target_free).await {
+                Ok(result) => result,
+                Err(e) => return summary.finish(None, Err(e)),
+            };
+            if result.dry_run {
+                info!(
+                    "Dry run: {} old entries and {} deleted records would be removed, {} orphaned objects ({}) moved to trash and {} trashed objects ({}) deleted, {} duplicate groups found",
+                    result.pruned_backups,
+                    result.purged_file_records,
+                    result.orphaned_objects_trashed,
+                    context.format_size(result.orphaned_bytes),
+                    result.trash_objects_deleted,
+                    context.format_size(result.trash_bytes_freed),
+                    result.duplicates_processed
+                );
             } else {
-                DedupCommand::new(&context)
+                info!(
+                    "Pruning complete: {} old entries removed, {} orphaned objects ({}) moved to trash, {} trashed objects ({}) deleted, {} duplicate groups found",
+                    result.pruned_backups,
+                    result.orphaned_objects_trashed,
+                    context.format_size(result.orphaned_bytes),
+                    result.trash_objects_deleted,
+                    context.format_size(result.trash_bytes_freed),
+                    result.duplicates_processed
+                );
+            }
+            if let Some(available) = result.available_space {
+                info!(
+                    "Reclaimed {} from {} history actions beyond the retention policy, {} free",
+                    context.format_size(result.reclaimed_bytes),
+                    result.reclaimed_actions,
+                    context.format_size(available)
+                );
+            }
+            summary.finish(Some(result.summary()), Ok(()))
+        }
+        Some(Commands::Bench { size, apply }) => {
+            let context = open_context(current_dir, &global).await?;
+            let mut options = BenchOptions {
+                apply,
+                ..BenchOptions::default()
             };
-
-            dedup_command.execute().await?;
+            if let Some(size) = size {
+                options.size = size;
+            }
+            BenchCommand::new(&context).execute(&options).await?;
             Ok(())
         }
-        Some(Commands::Status) => {
-            let repo = Repository::find_repository(current_dir)?;
-            let context = AppContext::new(repo).await?;
-            let status_command = StatusCommand::new(&context);
-            status_command.execute().await?;
+        Some(Commands::Tier { dry_run }) => {
+            let context = open_context(current_dir, &global).await?;
+            let result = TierCommand::new(&context).execute(dry_run).await?;
+            if !result.failed.is_empty() {
+                return Err(crate::DdriveError::PartialFailure {
+                    failed: result.failed.len(),
+                });
+            }
             Ok(())
         }
+        Some(Commands::Import {
+            source,
+            to,
+            rename,
+            dry_run,
+        }) => {
+            let context = open_context(current_dir.clone(), &global).await?;
+            let options = ImportOptions { rename, dry_run };
+            let result = ImportCommand::new(&context)
+                .execute(&current_dir.join(source), &current_dir.join(to), &options)
+                .await?;
+            if !result.failed.is_empty() {
+                return Err(crate::DdriveError::PartialFailure {
+                    failed: result.failed.len(),
+                });
+            }
+            check_add_result(&result.added)
+        }
+        Some(Commands::Organize {
+            path,
+            by_date: _,
+            dry_run,
+        }) => {
+            let context = open_context(current_dir.clone(), &global).await?;
+            let directory = match path {
+                Some(path) => current_dir.join(path),
+                None => context.repo.root().clone(),
+            };
+            let result = OrganizeCommand::new(&context)
+                .by_date(&directory, dry_run)
+                .await?;
+            if !result.failed.is_empty() {
+                return Err(crate::DdriveError::PartialFailure {
+                    failed: result.failed.len(),
+                });
+            }
+            Ok(())
+        }
+        Some(Commands::Mirror {
+            destination,
+            spot_checks,
+            dry_run,
+        }) => {
+            let context = open_context(current_dir.clone(), &global).await?;
+            let target = MirrorTarget::parse(&destination, &current_dir);
+            let options = MirrorOptions {
+                dry_run,
+                spot_checks,
+            };
+            let result = MirrorCommand::new(&context)
+                .execute(&target, &options)
+                .await?;
+            if !result.mismatched.is_empty() {
+                return Err(crate::DdriveError::Validation {
+                    message: format!(
+                        "{} mirrored file(s) are missing or damaged; run mirror again to replace them",
+                        result.mismatched.len()
+                    ),
+                });
+            }
+            if !result.failed.is_empty() {
+                return Err(crate::DdriveError::PartialFailure {
+                    failed: result.failed.len(),
+                });
+            }
+            Ok(())
+        }
+        Some(Commands::Export {
+            mount_point,
+            size,
+            label,
+            dry_run,
+        }) => {
+            let context = open_context(current_dir.clone(), &global).await?;
+            let options = ExportOptions {
+                size,
+                label,
+                dry_run,
+            };
+            let result = ExportCommand::new(&context)
+                .execute(&current_dir.join(mount_point), &options)
+                .await?;
+            if !result.failed.is_empty() {
+                return Err(crate::DdriveError::PartialFailure {
+                    failed: result.failed.len(),
+                });
+            }
+            Ok(())
+        }
+        Some(Commands::Media { action }) => {
+            let context = open_context(current_dir.clone(), &global).await?;
+            let export_command = ExportCommand::new(&context);
+            match action {
+                MediaAction::List => {
+                    export_command.list_media().await?;
+                }
+                MediaAction::Verify { mount_point } => {
+                    let result = export_command
+                        .verify_media(&current_dir.join(mount_point))
+                        .await?;
+                    if result.failures() > 0 {
+                        return Err(crate::DdriveError::Validation {
+                            message: format!(
+                                "{} object(s) on '{}' are missing or damaged",
+                                result.failures(),
+                                result.label
+                            ),
+                        });
+                    }
+                }
+            }
+            Ok(())
+        }
+        Some(Commands::Db { action }) => match action {
+            DbAction::Backup {
+                output,
+                encrypt,
+                keyfile,
+            } => {
+                let context = open_context(current_dir.clone(), &global).await?;
+                let options = BackupOptions {
+                    encrypt,
+                    keyfile: keyfile.map(|keyfile| current_dir.join(keyfile)),
+                };
+                BackupCommand::new(&context)
+                    .execute(&current_dir.join(output), &options)
+                    .await?;
+                Ok(())
+            }
+            DbAction::Restore { input, keyfile } => {
+                // The database may be missing, so look for .ddrive itself
+                let repo = match Repository::find_repository(current_dir.clone()) {
+                    Err(crate::DdriveError::InvalidDirectory)
+                        if current_dir.join(".ddrive").is_dir() =>
+                    {
+                        Repository::new(current_dir.canonicalize()?)
+                    }
+                    found => found?,
+                };
+                let keyfile = keyfile.map(|keyfile| current_dir.join(keyfile));
+                BackupCommand::restore(&repo, &current_dir.join(input), keyfile.as_deref()).await
+            }
+        },
+        Some(Commands::ImportChecksums { file, algorithm }) => {
+            let context = open_context(current_dir.clone(), &global).await?;
+            let checksums_command = ChecksumsCommand::new(&context);
+            let result = checksums_command
+                .import(&current_dir.join(&file), algorithm)
+                .await?;
 
-        Some(Commands::Prune) => {
-            let repo = Repository::find_repository(current_dir)?;
-            let context = AppContext::new(repo).await?;
-            let prune_command = PruneCommand::new(&context);
-            let result = prune_command.execute().await?;
-            info!(
-                "Pruning complete: {} old entries removed, {} orphaned objects deleted, {} duplicate groups processed",
-                result.pruned_backups, result.orphaned_objects_deleted, result.duplicates_processed
-            );
+            if !result.mismatched.is_empty() {
+                return Err(crate::DdriveError::Validation {
+                    message: format!(
+                        "{} file(s) do not match {}",
+                        result.mismatched.len(),
+                        file.display()
+                    ),
+                });
+            }
+            Ok(())
+        }
+        Some(Commands::ExportChecksums { path, algorithm }) => {
+            let context = open_context(current_dir.clone(), &global).await?;
+            let checksums_command = ChecksumsCommand::new(&context);
+            let mut stdout = std::io::stdout().lock();
+            checksums_command
+                .export(&current_dir.join(path), algorithm, &mut stdout)
+                .await?;
             Ok(())
         }
         Some(Commands::Log { action }) => {
-            let repo = Repository::find_repository(current_dir)?;
-            let context = AppContext::new(repo).await?;
+            let context = open_context(current_dir, &global).await?;
             let history_command = HistoryCommand::new(&context);
             let Some(action) = action else {
-                history_command.list(None, None).await?;
+                history_command.list(&HistoryQuery::default()).await?;
                 return Ok(());
             };
 
             match action {
-                HistoryAction::List { limit, filter } => {
-                    history_command.list(Some(limit), filter).await?;
+                HistoryAction::List {
+                    limit,
+                    offset,
+                    filter,
+                    before,
+                    after,
+                } => {
+                    let query = HistoryQuery {
+                        action_type: filter,
+                        before,
+                        after,
+                        limit,
+                        offset,
+                    };
+                    history_command.list(&query).await?;
                     Ok(())
                 }
-                HistoryAction::Show { id } => {
-                    history_command.show(&id).await?;
+                HistoryAction::Show {
+                    id,
+                    action_type,
+                    stat,
+                    all,
+                    objects,
+                } => {
+                    let options = ShowOptions {
+                        action_type,
+                        stat,
+                        all,
+                        objects,
+                    };
+                    history_command.show(&id, &options).await?;
                     Ok(())
                 }
             }
         }
+        Some(Commands::Repos { action }) => {
+            let mut repos_command = ReposCommand::new(Registry::load_default()?, &global);
+            match action {
+                ReposAction::List => {
+                    repos_command.list();
+                    Ok(())
+                }
+                ReposAction::Status => repos_command.status().await,
+                ReposAction::VerifyAll => repos_command.verify_all().await,
+                ReposAction::Add { path } => repos_command.add(&current_dir.join(path)),
+                ReposAction::Forget { path } => repos_command.forget(&path),
+            }
+        }
+        Some(Commands::Serve { listen }) => {
+            let mut context = open_context(current_dir, &global).await?;
+            if let Some(listen) = listen {
+                context.config.serve.listen = listen;
+            }
+            let serve_command = ServeCommand::new(&context)?;
+            serve_command.execute(&context.config.serve.listen).await
+        }
+        Some(Commands::Codes { markdown }) => {
+            if markdown {
+                print!("{}", codes::registry_markdown());
+                return Ok(());
+            }
+            for code in codes::REGISTRY {
+                info!(
+                    "{}  {:<7}  {:<18}  {}",
+                    code.id, code.severity, code.name, code.summary
+                );
+            }
+            Ok(())
+        }
         None => {
             info!("Showing ddrive status (default command)...");
-            let repo = Repository::find_repository(current_dir)?;
-            let context = AppContext::new(repo).await?;
+            let context = open_context(current_dir, &global).await?;
             let status_command = StatusCommand::new(&context);
             status_command.execute().await?;
             Ok(())
         }
     }
 }
+
+/// Fail `add` if some files could not be protected
+pub(crate) fn check_add_result(result: &AddResult) -> Result<()> {
+    if !result.failed.is_empty() {
+        return Err(crate::DdriveError::PartialFailure {
+            failed: result.failed.len(),
+        });
+    }
+    if !result.unreadable.is_empty() {
+        return Err(crate::DdriveError::Unreadable {
+            unreadable: result.unreadable.len(),
+        });
+    }
+    Ok(())
+}
+
+/// Fail `verify --external` when the copy lacks or differs in tracked
+/// files, and with `strict` when it has files the repository does not track
+fn check_external_result(result: &ExternalVerification, strict: bool) -> Result<()> {
+    if !result.passed() {
+        return Err(crate::DdriveError::Validation {
+            message: tr!(
+                "error-external-mismatch",
+                missing = result.missing.len(),
+                different = result.mismatched.len(),
+                unreadable = result.unreadable.len()
+            ),
+        });
+    }
+    if strict && !result.extra.is_empty() {
+        return Err(crate::DdriveError::Validation {
+            message: tr!("error-external-untracked", count = result.extra.len()),
+        });
+    }
+    Ok(())
+}
+
+/// Fail `verify` on integrity failures, and with `strict` on modified files
+fn check_verify_result(result: &VerifyResult, strict: bool) -> Result<()> {
+    if result.failed_files > 0 {
+        return Err(crate::DdriveError::Validation {
+            message: tr!("error-verify-failed", count = result.failed_files),
+        });
+    }
+    if !result.object_failures.is_empty() {
+        return Err(crate::DdriveError::Validation {
+            message: tr!("error-verify-objects", count = result.object_failures.len()),
+        });
+    }
+    if strict && result.modified_files > 0 {
+        return Err(crate::DdriveError::Validation {
+            message: tr!("error-verify-modified", count = result.modified_files),
+        });
+    }
+    Ok(())
+}
@@ -0,0 +1,191 @@
+use crate::{
+    AppContext, DdriveError, Result, logging, media,
+    utils::{format_count, from_storage_path, to_storage_path, unique_path},
+};
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::{info, warn};
+
+#[derive(Debug, Default)]
+pub struct OrganizeResult {
+    /// Tracked files moved, as (old path, new path) relative to the root
+    pub moved: Vec<(String, String)>,
+    /// Files that could not be moved
+    pub failed: Vec<(String, String)>,
+    /// History action the moves were recorded under, if any were
+    pub action_id: Option<i64>,
+}
+
+pub struct OrganizeCommand<'a> {
+    context: &'a AppContext,
+}
+
+impl<'a> OrganizeCommand<'a> {
+    pub fn new(context: &'a AppContext) -> Self {
+        Self { context }
+    }
+
+    /// Move the tracked photos and videos under `directory` into
+    /// `YYYY/MM` folders of it by the date they were taken. The moves are
+    /// recorded as renames, so checksums, stored copies and history stay
+    /// with the files.
+    pub async fn by_date(&self, directory: &Path, dry_run: bool) -> Result<OrganizeResult> {
+        let repo_root = self.context.repo.root().canonicalize()?;
+        let directory = directory.canonicalize()?;
+        let Ok(scope) = directory.strip_prefix(&repo_root) else {
+            return Err(DdriveError::InvalidDirectory);
+        };
+        let scope = to_storage_path(scope);
+        let _lock = self.context.repo.lock()?;
+
+        let files: Vec<_> = self
+            .context
+            .database
+            .get_all_files()
+            .await?
+            .into_iter()
+            .filter(|file| {
+                scope.is_empty()
+                    || file
+                        .path
+                        .strip_prefix(scope.as_str())
+                        .is_some_and(|rest| rest.starts_with('/'))
+            })
+            .filter(|file| media::is_media(Path::new(&file.path)))
+            .collect();
+
+        let mut result = OrganizeResult::default();
+        let mut claimed: HashSet<PathBuf> = HashSet::new();
+        for file in &files {
+            let from = repo_root.join(from_storage_path(&file.path));
+            let modified = match fs::metadata(&from).and_then(|metadata| metadata.modified()) {
+                Ok(modified) => modified,
+                Err(e) => {
+                    warn!("Skipping {}: {}", file.path, e);
+                    result.failed.push((file.path.clone(), e.to_string()));
+                    continue;
+                }
+            };
+            let taken = media::capture_time(&from, modified, &self.context.config.media);
+            let Some(name) = from.file_name() else {
+                continue;
+            };
+            let folder = directory.join(taken.format("%Y/%m").to_string());
+            if from.parent() == Some(folder.as_path()) {
+                continue;
+            }
+            let to = unique_path(folder.join(name), &claimed);
+            claimed.insert(to.clone());
+            let to_path = to_storage_path(to.strip_prefix(&repo_root)?);
+
+            if dry_run {
+                info!("  {} -> {}", file.path, to_path);
+            } else if let Err(e) = fs::create_dir_all(&folder).and_then(|()| fs::rename(&from, &to))
+            {
+                warn!("Failed to move {}: {}", file.path, e);
+                result.failed.push((file.path.clone(), e.to_string()));
+                continue;
+            }
+            result.moved.push((file.path.clone(), to_path));
+        }
+
+        if result.moved.is_empty() {
+            info!("All photos and videos are already organized by date");
+            return Ok(result);
+        }
+        if dry_run {
+            info!(
+                "Dry run: {} files would be moved",
+                format_count(result.moved.len())
+            );
+            return Ok(result);
+        }
+
+        let action_id = chrono::Utc::now().timestamp();
+        logging::set_action_id(action_id);
+        self.context
+            .database
+            .batch_rename_files(action_id, &result.moved)
+            .await?;
+        result.action_id = Some(action_id);
+        info!(
+            "Moved {} files into folders by date",
+            format_count(result.moved.len())
+        );
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{AddOptions, Ddrive, database::ActionType};
+    use assert_fs::{TempDir, prelude::*};
+    use chrono::{DateTime, Local};
+
+    #[tokio::test]
+    async fn test_organize_by_date() {
+        let temp = TempDir::new().unwrap();
+        temp.child("photos/IMG_1.jpg").write_str("one").unwrap();
+        temp.child("photos/notes.txt").write_str("notes").unwrap();
+        let taken = std::time::UNIX_EPOCH + std::time::Duration::from_secs(1_700_000_000);
+        fs::File::options()
+            .write(true)
+            .open(temp.path().join("photos/IMG_1.jpg"))
+            .unwrap()
+            .set_modified(taken)
+            .unwrap();
+        let ddrive = Ddrive::init(temp.path()).await.unwrap();
+        ddrive
+            .add(&[temp.path()], &AddOptions::default())
+            .await
+            .unwrap();
+        let month = DateTime::<Local>::from(taken).format("%Y/%m").to_string();
+        let organize = OrganizeCommand::new(ddrive.context());
+
+        let planned = organize
+            .by_date(&temp.path().join("photos"), true)
+            .await
+            .unwrap();
+        assert!(temp.path().join("photos/IMG_1.jpg").exists());
+        assert_eq!(planned.action_id, None);
+
+        let result = organize
+            .by_date(&temp.path().join("photos"), false)
+            .await
+            .unwrap();
+        let moved_to = format!("photos/{month}/IMG_1.jpg");
+        assert_eq!(
+            result.moved,
+            [("photos/IMG_1.jpg".to_string(), moved_to.clone())]
+        );
+        assert!(temp.path().join(&moved_to).exists());
+        assert!(temp.path().join("photos/notes.txt").exists());
+        let database = &ddrive.context().database;
+        let paths: Vec<String> = database
+            .get_all_files()
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|file| file.path)
+            .collect();
+        assert!(paths.contains(&moved_to));
+        let renames: Vec<_> = database
+            .get_history_entries_by_action_id(result.action_id.unwrap())
+            .await
+            .unwrap()
+            .into_iter()
+            .filter(|entry| entry.action_type_enum() == ActionType::Rename)
+            .map(|entry| entry.path)
+            .collect();
+        assert_eq!(renames, [moved_to]);
+
+        // Organized files stay where they are
+        let again = organize
+            .by_date(&temp.path().join("photos"), false)
+            .await
+            .unwrap();
+        assert!(again.moved.is_empty());
+    }
+}
@@ -1,5 +1,20 @@
-use crate::{AppContext, Result, cli::dedup::DedupCommand, database::ActionType};
-use tracing::info;
+use crate::{
+    AppContext, Result,
+    cli::dedup::DedupCommand,
+    codes,
+    config::PruneConfig,
+    database::{ActionType, HistoryRecord, OrphanedObject},
+    utils,
+};
+use chrono::{DateTime, Datelike, Duration, Local, Utc};
+use std::{
+    collections::{BTreeSet, HashSet},
+    path::PathBuf,
+};
+use tracing::{info, warn};
+
+/// Calendar period an action falls in, for the daily/weekly/monthly keep rules
+type PeriodKey = fn(&DateTime<Local>) -> (i32, u32, u32);
 
 pub struct PruneCommand<'a> {
     context: &'a AppContext,
@@ -7,9 +22,86 @@ pub struct PruneCommand<'a> {
 
 #[derive(Debug, Default)]
 pub struct PruneResult {
+    pub dry_run: bool,
     pub duplicates_processed: usize,
     pub pruned_backups: usize,
-    pub orphaned_objects_deleted: usize,
+    pub purged_file_records: usize,
+    /// Orphaned objects moved from the object store to the trash
+    pub orphaned_objects_trashed: usize,
+    pub orphaned_bytes: u64,
+    /// Trashed objects deleted for good once past the grace period
+    pub trash_objects_deleted: usize,
+    pub trash_bytes_freed: u64,
+    /// History actions pruned beyond the retention policy to reach `--target-free`
+    pub reclaimed_actions: usize,
+    /// Bytes freed (or, for a dry run, at least freed) to reach `--target-free`
+    pub reclaimed_bytes: u64,
+    /// Free space after pruning, when a free-space target was given
+    pub available_space: Option<u64>,
+}
+
+impl PruneResult {
+    /// Details for `--summary-file`
+    pub fn summary(&self) -> serde_json::Value {
+        serde_json::json!({
+            "action_id": null,
+            "dry_run": self.dry_run,
+            "pruned_history_entries": self.pruned_backups,
+            "purged_file_records": self.purged_file_records,
+            "orphaned_objects_trashed": self.orphaned_objects_trashed,
+            "orphaned_bytes": self.orphaned_bytes,
+            "trash_objects_deleted": self.trash_objects_deleted,
+            "trash_bytes_freed": self.trash_bytes_freed,
+            "reclaimed_actions": self.reclaimed_actions,
+            "reclaimed_bytes": self.reclaimed_bytes,
+            "available_space": self.available_space,
+            "duplicate_groups": self.duplicates_processed,
+        })
+    }
+}
+
+/// Space prune would reclaim with `prune.retention_days` set to
+/// `retention_days` and the rest of the configuration unchanged
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RetentionEstimate {
+    pub retention_days: u32,
+    /// History actions with entries that would be pruned
+    pub pruned_actions: usize,
+    /// Objects nothing would reference afterwards
+    pub objects: usize,
+    pub bytes: u64,
+}
+
+/// What `prune --estimate` found: the space each retention setting would
+/// reclaim, on top of what is already unreferenced or trashed
+#[derive(Debug, Default)]
+pub struct PruneEstimate {
+    pub retention: Vec<RetentionEstimate>,
+    /// Objects already orphaned, whatever the retention setting
+    pub orphaned_objects: usize,
+    pub orphaned_bytes: u64,
+    /// Objects in `.ddrive/trash`, deleted for good after the grace period
+    pub trash_objects: usize,
+    pub trash_bytes: u64,
+}
+
+impl PruneEstimate {
+    /// Details for `--summary-file`
+    pub fn summary(&self) -> serde_json::Value {
+        serde_json::json!({
+            "action_id": null,
+            "retention": self.retention.iter().map(|estimate| serde_json::json!({
+                "retention_days": estimate.retention_days,
+                "pruned_actions": estimate.pruned_actions,
+                "objects": estimate.objects,
+                "bytes": estimate.bytes,
+            })).collect::<Vec<_>>(),
+            "orphaned_objects": self.orphaned_objects,
+            "orphaned_bytes": self.orphaned_bytes,
+            "trash_objects": self.trash_objects,
+            "trash_bytes": self.trash_bytes,
+        })
+    }
 }
 
 impl<'a> PruneCommand<'a> {
@@ -17,44 +109,937 @@ impl<'a> PruneCommand<'a> {
         Self { context }
     }
 
-    pub async fn execute(&self) -> Result<PruneResult> {
-        info!("Starting prune operation...");
+    /// Prune old history, tombstoned records and orphaned objects, then, with
+    /// `target_free`, older history until the file system has that much free
+    /// space. With `dry_run` nothing is removed; the entries that would be are
+    /// listed instead.
+    pub async fn execute(&self, dry_run: bool, target_free: Option<u64>) -> Result<PruneResult> {
+        if dry_run {
+            info!("Starting prune dry run, nothing will be removed...");
+        } else {
+            info!("Starting prune operation...");
+        }
+
+        // Keep adds from referencing objects while they are being deleted
+        let _lock = if dry_run {
+            None
+        } else {
+            Some(self.context.repo.lock()?)
+        };
+
+        if dry_run {
+            let drifted = self.context.database.get_refcount_mismatches().await?;
+            if !drifted.is_empty() {
+                warn!(
+                    code = %codes::REFCOUNT_DRIFT,
+                    "{} objects have a wrong reference count, which prune will correct first",
+                    drifted.len()
+                );
+            }
+        } else {
+            self.context.database.recount_object_references().await?;
+        }
+
+        let history_cutoff = self.context.config.prune.cutoff_date().timestamp();
+        let records_cutoff = self.context.config.prune.cutoff_date();
+        let grace_cutoff = self.context.config.prune.orphan_grace_cutoff();
+        let database = &self.context.database;
+        let repo = &self.context.repo;
+
+        let mut result = PruneResult {
+            dry_run,
+            ..Default::default()
+        };
 
-        // Clean up old history entries first
-        let old_deleted_history_entry = self
-            .context
-            .database
-            .cleanup_old_history(
-                ActionType::Delete,
-                self.context.config.prune.cutoff_date().timestamp(),
+        // History rows to prune, as the actions they belong to and, without a keep
+        // policy, the action type they are limited to
+        let prune_config = &self.context.config.prune;
+        let (history, pruned_actions, action_type) = if prune_config.has_keep_policy() {
+            let action_ids = database.get_history_action_ids().await?;
+            let kept = Self::select_kept_actions(&action_ids, prune_config, Utc::now());
+            let pruned: Vec<i64> = action_ids
+                .into_iter()
+                .filter(|action_id| !kept.contains(action_id))
+                .collect();
+            info!(
+                "Keeping {} history actions under the retention policy",
+                kept.len()
+            );
+            let history = if dry_run {
+                database.get_history_for_actions(&pruned).await?
+            } else {
+                Vec::new()
+            };
+            (history, pruned, None)
+        } else {
+            let history = database
+                .get_old_history(ActionType::Delete, history_cutoff)
+                .await?;
+            let pruned: BTreeSet<i64> = history.iter().map(|entry| entry.action_id).collect();
+            (
+                history,
+                pruned.into_iter().collect(),
+                Some(ActionType::Delete),
             )
-            .await?;
-        info!("Pruned {old_deleted_history_entry} old history entries for deleted files",);
+        };
 
-        // Clean up orphaned objects from object store
-        let orphaned_objects_deleted = self.context.database.cleanup_orphaned_objects().await?;
-        info!(
-            "Deleted {} orphaned objects from object store",
-            orphaned_objects_deleted
-        );
+        if dry_run {
+            if action_type.is_some() {
+                info!(
+                    "Would prune {} old history entries for deleted files",
+                    history.len()
+                );
+            } else {
+                info!(
+                    "Would prune {} history entries of {} actions",
+                    history.len(),
+                    pruned_actions.len()
+                );
+            }
+            self.report_history(&history);
+            result.pruned_backups = history.len();
+
+            let records = database.get_purgeable_file_records(records_cutoff).await?;
+            info!("Would purge {} deleted file records", records.len());
+            for record in &records {
+                info!("  {}", record.path);
+            }
+            result.purged_file_records = records.len();
+
+            let expired = self.trashed_objects(repo.expired_trash(grace_cutoff.timestamp())?);
+            self.report_objects(&expired, "Would permanently delete trashed objects");
+            result.trash_objects_deleted = expired.len();
+            result.trash_bytes_freed = expired.iter().map(|object| object.size).sum();
+
+            let orphaned = database.find_orphaned_objects(grace_cutoff).await?;
+            self.report_objects(&orphaned, "Would move orphaned objects to trash");
+            result.orphaned_objects_trashed = orphaned.len();
+            result.orphaned_bytes = orphaned.iter().map(|object| object.size).sum();
+
+            // Objects only kept alive by the entries above become orphans too,
+            // but stay in the store for the grace period
+            let released = database
+                .find_objects_released_by_prune(&pruned_actions, action_type, records_cutoff)
+                .await?;
+            if !released.is_empty() {
+                self.report_objects(
+                    &released,
+                    &format!(
+                        "Would orphan objects, trashed after {} days",
+                        prune_config.orphan_grace_days
+                    ),
+                );
+            }
+        } else {
+            // Clean up old history entries first
+            if action_type.is_some() {
+                result.pruned_backups = database
+                    .cleanup_old_history(ActionType::Delete, history_cutoff)
+                    .await?;
+                info!(
+                    "Pruned {} old history entries for deleted files",
+                    result.pruned_backups
+                );
+            } else {
+                result.pruned_backups = database.delete_history_actions(&pruned_actions).await?;
+                info!(
+                    "Pruned {} history entries of {} actions",
+                    result.pruned_backups,
+                    pruned_actions.len()
+                );
+            }
+
+            // Permanently remove soft-deleted file records past the retention window
+            result.purged_file_records =
+                database.purge_deleted_file_records(records_cutoff).await?;
+            info!("Purged {} deleted file records", result.purged_file_records);
 
-        // Handle duplicates
-        let dedup_command = DedupCommand::new(self.context);
-        let duplicate_groups = dedup_command.execute().await?;
+            // Chunk hashes are only useful while some record still has that content
+            database.cleanup_orphaned_chunks().await?;
+
+            // Drop cached checksums of files that have not been seen for a while
+            database.purge_hash_cache(records_cutoff).await?;
+
+            // Delete objects that have sat in the trash for the grace period
+            let expired = self.trashed_objects(repo.empty_trash(grace_cutoff.timestamp())?);
+            self.report_objects(&expired, "Permanently deleted trashed objects");
+            result.trash_objects_deleted = expired.len();
+            result.trash_bytes_freed = expired.iter().map(|object| object.size).sum();
+
+            // Move objects orphaned for the grace period out of the object store
+            let trash_dir = repo.trash_dir(Utc::now().timestamp());
+            let orphaned = database
+                .cleanup_orphaned_objects(grace_cutoff, &trash_dir, self.context.objects.as_ref())
+                .await?;
+            self.report_objects(&orphaned, "Moved orphaned objects to trash");
+            result.orphaned_objects_trashed = orphaned.len();
+            result.orphaned_bytes = orphaned.iter().map(|object| object.size).sum();
+        }
+
+        if let Some(target) = target_free {
+            self.reclaim_free_space(target, dry_run, &mut result)
+                .await?;
+        }
+
+        // Report duplicates; rewriting them is left to an explicit 'ddrive dedup'
+        let duplicate_groups = DedupCommand::report_only(self.context).execute().await?;
         if !duplicate_groups.is_empty() {
             info!(
                 "Found {} duplicate groups. Use 'ddrive dedup' command to handle them.",
                 duplicate_groups.len()
             );
         }
+        result.duplicates_processed = duplicate_groups.len();
 
-        let result = PruneResult {
-            pruned_backups: old_deleted_history_entry,
-            duplicates_processed: duplicate_groups.len(),
-            orphaned_objects_deleted,
+        if !dry_run {
+            info!("Prune operation completed successfully");
+        }
+        Ok(result)
+    }
+
+    /// Estimate the object-store space prune would reclaim with
+    /// `prune.retention_days` set to each of `retention_days`, using the
+    /// keep policy and reference counts as they are now. Nothing is removed
+    /// and the repository is not locked.
+    pub async fn estimate(&self, retention_days: &[u32]) -> Result<PruneEstimate> {
+        let database = &self.context.database;
+        let mut days: Vec<u32> = retention_days.to_vec();
+        days.push(self.context.config.prune.retention_days);
+        days.sort_unstable();
+        days.dedup();
+
+        let now = Utc::now();
+        let action_ids = database.get_history_action_ids().await?;
+        let mut estimate = PruneEstimate::default();
+        for retention_days in days {
+            let config = PruneConfig {
+                retention_days,
+                ..self.context.config.prune.clone()
+            };
+            let cutoff = now - Duration::days(retention_days as i64);
+            // The same history execute() would prune under this configuration
+            let (pruned, action_type) = if config.has_keep_policy() {
+                let kept = Self::select_kept_actions(&action_ids, &config, now);
+                let pruned: Vec<i64> = action_ids
+                    .iter()
+                    .copied()
+                    .filter(|action_id| !kept.contains(action_id))
+                    .collect();
+                (pruned, None)
+            } else {
+                let pruned: BTreeSet<i64> = database
+                    .get_old_history(ActionType::Delete, cutoff.timestamp())
+                    .await?
+                    .iter()
+                    .map(|entry| entry.action_id)
+                    .collect();
+                (pruned.into_iter().collect(), Some(ActionType::Delete))
+            };
+            let released = database
+                .find_objects_released_by_prune(&pruned, action_type, cutoff)
+                .await?;
+            estimate.retention.push(RetentionEstimate {
+                retention_days,
+                pruned_actions: pruned.len(),
+                objects: released.len(),
+                bytes: released.iter().map(|object| object.size).sum(),
+            });
+        }
+
+        let orphaned = database.find_orphaned_objects(now).await?;
+        estimate.orphaned_objects = orphaned.len();
+        estimate.orphaned_bytes = orphaned.iter().map(|object| object.size).sum();
+        let trash = self.context.repo.expired_trash(i64::MAX)?;
+        estimate.trash_objects = trash.len();
+        estimate.trash_bytes = trash.iter().map(|(_, size)| size).sum();
+
+        self.report_estimate(&estimate);
+        Ok(estimate)
+    }
+
+    fn report_estimate(&self, estimate: &PruneEstimate) {
+        let current = self.context.config.prune.retention_days;
+        info!("Space prune would reclaim by prune.retention_days:");
+        for row in &estimate.retention {
+            info!(
+                "  {:>4} days: {} actions pruned, {} objects ({}){}",
+                row.retention_days,
+                row.pruned_actions,
+                row.objects,
+                self.context.format_size(row.bytes),
+                if row.retention_days == current {
+                    " (current)"
+                } else {
+                    ""
+                }
+            );
+        }
+        info!(
+            "Reclaimable at any setting: {} orphaned objects ({}) and {} trashed objects ({})",
+            estimate.orphaned_objects,
+            self.context.format_size(estimate.orphaned_bytes),
+            estimate.trash_objects,
+            self.context.format_size(estimate.trash_bytes)
+        );
+        if self.context.config.prune.has_keep_policy() {
+            info!("Actions the keep_* policy keeps are kept at every setting");
+        } else {
+            info!("Without a keep_* policy only the history of deleted files expires");
+        }
+        info!(
+            "Objects become orphaned first and are trashed after {} days",
+            self.context.config.prune.orphan_grace_days
+        );
+    }
+
+    /// Free space until `target` bytes are available: first by emptying the trash,
+    /// then by pruning history actions oldest first, deleting the objects they
+    /// leave unreferenced right away. Moving them to the trash would free
+    /// nothing; the grace period they would have sat there is instead required
+    /// of the actions, as those kept by the `keep_*` policy or younger than the
+    /// orphan grace period are never pruned.
+    async fn reclaim_free_space(
+        &self,
+        target: u64,
+        dry_run: bool,
+        result: &mut PruneResult,
+    ) -> Result<()> {
+        let repo = &self.context.repo;
+        let database = &self.context.database;
+        let prune_config = &self.context.config.prune;
+
+        // A dry run removes nothing, so it tracks the space it would free instead
+        let mut available = utils::available_space(repo.root())?;
+        if available >= target {
+            info!(
+                "{} free, free-space target of {} already met",
+                self.context.format_size(available),
+                self.context.format_size(target)
+            );
+            result.available_space = Some(available);
+            return Ok(());
+        }
+        info!(
+            "{} free, reclaiming space to reach {}",
+            self.context.format_size(available),
+            self.context.format_size(target)
+        );
+
+        // Trashed objects are already unreferenced, so they go first
+        for (_, run_dir) in repo.trash_runs()? {
+            if available >= target {
+                break;
+            }
+            let objects = if dry_run {
+                repo.trash_run_objects(&run_dir)?
+            } else {
+                repo.remove_trash_run(&run_dir)?
+            };
+            let freed: u64 = objects.iter().map(|(_, size)| size).sum();
+            info!(
+                "{} trash {}: {} objects ({})",
+                if dry_run { "Would empty" } else { "Emptied" },
+                run_dir.display(),
+                objects.len(),
+                self.context.format_size(freed)
+            );
+            result.trash_objects_deleted += objects.len();
+            result.trash_bytes_freed += freed;
+            result.reclaimed_bytes += freed;
+            available = if dry_run {
+                available + freed
+            } else {
+                utils::available_space(repo.root())?
+            };
+        }
+
+        let action_ids = database.get_history_action_ids().await?;
+        let kept = if prune_config.has_keep_policy() {
+            let minimums = PruneConfig {
+                retention_days: 0,
+                ..prune_config.clone()
+            };
+            Self::select_kept_actions(&action_ids, &minimums, Utc::now())
+        } else {
+            HashSet::new()
         };
+        let grace_cutoff = prune_config.orphan_grace_cutoff().timestamp();
+        let candidates = action_ids
+            .iter()
+            .rev()
+            .copied()
+            .filter(|action_id| *action_id < grace_cutoff && !kept.contains(action_id));
 
-        info!("Prune operation completed successfully");
-        Ok(result)
+        let mut pruned = Vec::new();
+        let mut released = HashSet::new();
+        for action_id in candidates {
+            if available >= target {
+                break;
+            }
+            let objects = if dry_run {
+                // Lower bound: tombstones this action would purge are not counted
+                pruned.push(action_id);
+                database
+                    .find_objects_released_by_prune(&pruned, None, DateTime::UNIX_EPOCH)
+                    .await?
+                    .into_iter()
+                    .filter(|object| released.insert(object.b3sum.clone()))
+                    .collect()
+            } else {
+                database
+                    .reclaim_action(action_id, self.context.objects.as_ref())
+                    .await?
+            };
+            let freed: u64 = objects.iter().map(|object| object.size).sum();
+            let timestamp = DateTime::from_timestamp(action_id, 0).unwrap_or_else(Utc::now);
+            self.report_objects(
+                &objects,
+                &format!(
+                    "{} action from {}",
+                    if dry_run { "Would prune" } else { "Pruned" },
+                    timestamp.format("%Y-%m-%d %H:%M")
+                ),
+            );
+            result.reclaimed_actions += 1;
+            result.reclaimed_bytes += freed;
+            available = if dry_run {
+                available + freed
+            } else {
+                utils::available_space(repo.root())?
+            };
+        }
+
+        result.available_space = Some(available);
+        if available < target {
+            warn!(
+                code = %codes::LOW_SPACE,
+                "Only {} free after pruning; the remaining history is kept by the retention policy or grace period",
+                self.context.format_size(available)
+            );
+        }
+        Ok(())
+    }
+
+    /// Actions kept by the `keep_*` policy of `config`, given all action IDs
+    /// (Unix timestamps) newest first. Like restic, each daily/weekly/monthly rule
+    /// keeps the newest action of each of its last N periods that have one.
+    /// Actions within `retention_days` of `now` are always kept.
+    pub fn select_kept_actions(
+        action_ids: &[i64],
+        config: &PruneConfig,
+        now: DateTime<Utc>,
+    ) -> HashSet<i64> {
+        let within = (now - Duration::days(config.retention_days as i64)).timestamp();
+        let mut kept: HashSet<i64> = action_ids
+            .iter()
+            .copied()
+            .filter(|&action_id| action_id >= within)
+            .collect();
+
+        if let Some(last) = config.keep_last {
+            kept.extend(action_ids.iter().take(last as usize));
+        }
+
+        let rules: [(Option<u32>, PeriodKey); 3] = [
+            (config.keep_daily, |time| {
+                (time.year(), time.month(), time.day())
+            }),
+            (config.keep_weekly, |time| {
+                let week = time.iso_week();
+                (week.year(), week.week(), 0)
+            }),
+            (config.keep_monthly, |time| (time.year(), time.month(), 0)),
+        ];
+        for (limit, period) in rules {
+            let Some(mut remaining) = limit else {
+                continue;
+            };
+            let mut last_period = None;
+            for &action_id in action_ids {
+                if remaining == 0 {
+                    break;
+                }
+                let Some(time) = DateTime::from_timestamp(action_id, 0) else {
+                    continue;
+                };
+                let current = period(&time.with_timezone(&Local));
+                if last_period != Some(current) {
+                    kept.insert(action_id);
+                    last_period = Some(current);
+                    remaining -= 1;
+                }
+            }
+        }
+
+        kept
+    }
+
+    fn report_history(&self, history: &[HistoryRecord]) {
+        for entry in history {
+            info!(
+                "  {} {} {} (action {})",
+                entry.action_timestamp().format("%Y-%m-%d"),
+                entry.action_type_enum(),
+                entry.path,
+                entry.action_id_base58()
+            );
+        }
+    }
+
+    fn trashed_objects(&self, trashed: Vec<(PathBuf, u64)>) -> Vec<OrphanedObject> {
+        trashed
+            .into_iter()
+            .map(|(path, size)| OrphanedObject {
+                b3sum: path
+                    .file_name()
+                    .map(|name| name.to_string_lossy().into_owned())
+                    .unwrap_or_default(),
+                size,
+            })
+            .collect()
+    }
+
+    fn report_objects(&self, objects: &[OrphanedObject], summary: &str) {
+        let total: u64 = objects.iter().map(|object| object.size).sum();
+        info!(
+            "{summary}: {} ({})",
+            objects.len(),
+            self.context.format_size(total)
+        );
+        for object in objects {
+            info!(
+                "  {} ({})",
+                object.b3sum,
+                self.context.format_size(object.size)
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        AddOptions, PruneOptions,
+        api::testing::{configured_repository, repository},
+    };
+    use assert_fs::prelude::*;
+    use chrono::TimeZone;
+
+    fn at(year: i32, month: u32, day: u32, hour: u32) -> i64 {
+        Utc.with_ymd_and_hms(year, month, day, hour, 0, 0)
+            .unwrap()
+            .timestamp()
+    }
+
+    fn policy() -> PruneConfig {
+        PruneConfig {
+            retention_days: 0,
+            ..Default::default()
+        }
+    }
+
+    fn kept(action_ids: &[i64], config: &PruneConfig) -> Vec<i64> {
+        let now = Utc.with_ymd_and_hms(2026, 6, 30, 12, 0, 0).unwrap();
+        let mut kept: Vec<_> = PruneCommand::select_kept_actions(action_ids, config, now)
+            .into_iter()
+            .collect();
+        kept.sort_unstable_by(|a, b| b.cmp(a));
+        kept
+    }
+
+    #[test]
+    fn test_keep_last() {
+        let actions = [at(2026, 6, 3, 12), at(2026, 6, 2, 12), at(2026, 6, 1, 12)];
+        let config = PruneConfig {
+            keep_last: Some(2),
+            ..policy()
+        };
+        assert_eq!(kept(&actions, &config), actions[..2]);
+    }
+
+    #[test]
+    fn test_keep_daily_keeps_newest_of_each_day() {
+        let actions = [
+            at(2026, 6, 3, 14),
+            at(2026, 6, 3, 10),
+            at(2026, 6, 2, 12),
+            at(2026, 5, 30, 12),
+        ];
+        let config = PruneConfig {
+            keep_daily: Some(2),
+            ..policy()
+        };
+        assert_eq!(kept(&actions, &config), [actions[0], actions[2]]);
+    }
+
+    #[test]
+    fn test_keep_weekly_and_monthly() {
+        // 2026-06-10 and 2026-06-08 share an ISO week; 2026-06-03 is the week before
+        let actions = [
+            at(2026, 6, 10, 12),
+            at(2026, 6, 8, 12),
+            at(2026, 6, 3, 12),
+            at(2026, 5, 20, 12),
+            at(2026, 4, 15, 12),
+        ];
+        let weekly = PruneConfig {
+            keep_weekly: Some(2),
+            ..policy()
+        };
+        assert_eq!(kept(&actions, &weekly), [actions[0], actions[2]]);
+
+        let monthly = PruneConfig {
+            keep_monthly: Some(3),
+            ..policy()
+        };
+        assert_eq!(
+            kept(&actions, &monthly),
+            [actions[0], actions[3], actions[4]]
+        );
+    }
+
+    #[test]
+    fn test_policies_combine() {
+        let actions = [
+            at(2026, 6, 10, 14),
+            at(2026, 6, 10, 12),
+            at(2026, 6, 9, 12),
+            at(2026, 4, 15, 12),
+        ];
+        let config = PruneConfig {
+            keep_last: Some(2),
+            keep_monthly: Some(2),
+            ..policy()
+        };
+        assert_eq!(
+            kept(&actions, &config),
+            [actions[0], actions[1], actions[3]]
+        );
+    }
+
+    #[test]
+    fn test_retention_days_keeps_recent_actions() {
+        let actions = [at(2026, 6, 29, 12), at(2026, 6, 20, 12), at(2026, 1, 1, 12)];
+        let config = PruneConfig {
+            retention_days: 14,
+            keep_last: Some(0),
+            ..Default::default()
+        };
+        assert_eq!(kept(&actions, &config), [actions[0], actions[1]]);
+    }
+
+    #[tokio::test]
+    async fn test_prune_keeps_objects_only_history_references() {
+        let (temp, ddrive) = repository(&[("docs/a.txt", "first")]).await;
+        let docs = [temp.path().join("docs")];
+        ddrive.add(&docs, &AddOptions::default()).await.unwrap();
+        temp.child("docs/a.txt")
+            .write_str("second version")
+            .unwrap();
+        ddrive.add(&docs, &AddOptions::default()).await.unwrap();
+
+        // Only the history of the first add references the old content; make
+        // its count drift to zero as if it had been orphaned long ago
+        let first = blake3::hash(b"first").to_hex().to_string();
+        let database = &ddrive.context().database;
+        sqlx::query(
+            "UPDATE objects SET refcount = 0, unreferenced_at = '2000-01-01' WHERE b3sum = ?1",
+        )
+        .bind(&first)
+        .execute(&database.pool)
+        .await
+        .unwrap();
+        assert_eq!(
+            database.get_refcount_mismatches().await.unwrap(),
+            std::slice::from_ref(&first)
+        );
+
+        let result = ddrive.prune(&PruneOptions::default()).await.unwrap();
+        assert_eq!(result.orphaned_objects_trashed, 0);
+        assert!(ddrive.context().objects.exists(&first).unwrap());
+        assert!(database.get_refcount_mismatches().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_prune_trashes_orphans_after_grace_period() {
+        let files = [
+            ("docs/old.txt", "old"),
+            ("docs/young.txt", "young"),
+            ("docs/cold.txt", "cold"),
+        ];
+        let (temp, ddrive) = configured_repository(&files, |config| {
+            config.object_store.cold_url = Some("file://cold".into())
+        })
+        .await;
+        ddrive
+            .add(&[temp.path().join("docs")], &AddOptions::default())
+            .await
+            .unwrap();
+        let [old, young, cold] = [b"old".as_slice(), b"young", b"cold"]
+            .map(|content| blake3::hash(content).to_hex().to_string());
+
+        // Forget every file so all three objects become orphans, two of them
+        // long ago, and move one to the cold store
+        let database = &ddrive.context().database;
+        for sql in [
+            "DELETE FROM files",
+            "DELETE FROM history",
+            "UPDATE objects SET unreferenced_at = '2000-01-01' WHERE b3sum != ?1",
+        ] {
+            sqlx::query(sql)
+                .bind(&young)
+                .execute(&database.pool)
+                .await
+                .unwrap();
+        }
+        let repo = &ddrive.context().repo;
+        let cold_path = crate::backend::object_path(&temp.path().join("cold"), &cold);
+        std::fs::create_dir_all(cold_path.parent().unwrap()).unwrap();
+        std::fs::rename(repo.object_dir(&cold).join(&cold), &cold_path).unwrap();
+
+        // One prune run trashed long ago, one recently
+        let expired_run = repo.trash_dir(1);
+        let recent_run = repo.trash_dir(Utc::now().timestamp() - 86400);
+        for run in [&expired_run, &recent_run] {
+            std::fs::create_dir_all(run).unwrap();
+            std::fs::write(run.join("object"), "trashed").unwrap();
+        }
+
+        let result = ddrive.prune(&PruneOptions::default()).await.unwrap();
+        assert_eq!(result.orphaned_objects_trashed, 1);
+        assert_eq!(result.trash_objects_deleted, 1);
+        assert!(!expired_run.exists());
+        assert!(recent_run.join("object").exists());
+
+        let objects = &ddrive.context().objects;
+        assert!(!objects.exists(&old).unwrap());
+        let trashed: Vec<PathBuf> = repo
+            .trash_runs()
+            .unwrap()
+            .iter()
+            .flat_map(|(_, run)| repo.trash_run_objects(run).unwrap())
+            .map(|(path, _)| path)
+            .collect();
+        assert!(trashed.iter().any(|path| path.ends_with(&old)));
+        // Still in its grace period
+        assert!(objects.exists(&young).unwrap());
+        // The cold store has no trash, so the object and its row stay
+        assert!(cold_path.exists());
+        let rows: Vec<String> = sqlx::query_scalar("SELECT b3sum FROM objects ORDER BY b3sum")
+            .fetch_all(&database.pool)
+            .await
+            .unwrap();
+        let mut expected = vec![young.clone(), cold.clone()];
+        expected.sort();
+        assert_eq!(rows, expected);
+    }
+
+    #[tokio::test]
+    async fn test_prune_dry_run_changes_nothing() {
+        let (temp, ddrive) = repository(&[
+            ("docs/gone.txt", "gone"),
+            ("docs/orphan.txt", "orphan"),
+            ("docs/kept.txt", "kept"),
+        ])
+        .await;
+        ddrive
+            .add(&[temp.path().join("docs")], &AddOptions::default())
+            .await
+            .unwrap();
+        crate::cli::rm::RmCommand::new(ddrive.context())
+            .tracked(
+                glob::Pattern::new("docs/gone.txt").unwrap(),
+                crate::cli::rm::RmOptions {
+                    yes: true,
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+
+        // Age the deletion past retention, orphan one object long ago and
+        // leave a trash run past its grace period
+        let orphan = blake3::hash(b"orphan").to_hex().to_string();
+        let database = &ddrive.context().database;
+        for sql in [
+            "UPDATE files SET deleted_at = '2000-01-01' WHERE deleted_at IS NOT NULL",
+            "UPDATE history SET action_id = 1000 WHERE path = 'docs/gone.txt'",
+            "DELETE FROM files WHERE path = 'docs/orphan.txt'",
+            "DELETE FROM history WHERE path = 'docs/orphan.txt'",
+            "UPDATE objects SET refcount = 0, unreferenced_at = '2000-01-01' WHERE b3sum = ?1",
+        ] {
+            sqlx::query(sql)
+                .bind(&orphan)
+                .execute(&database.pool)
+                .await
+                .unwrap();
+        }
+        let repo = &ddrive.context().repo;
+        let expired_run = repo.trash_dir(1);
+        std::fs::create_dir_all(&expired_run).unwrap();
+        std::fs::write(expired_run.join("object"), "trashed").unwrap();
+
+        let snapshot = || async {
+            let history: Vec<(i64, String)> =
+                sqlx::query_as("SELECT action_id, path FROM history ORDER BY id")
+                    .fetch_all(&database.pool)
+                    .await
+                    .unwrap();
+            let files: Vec<(String, Option<String>)> =
+                sqlx::query_as("SELECT path, deleted_at FROM files ORDER BY path")
+                    .fetch_all(&database.pool)
+                    .await
+                    .unwrap();
+            let objects: Vec<(String, i64)> =
+                sqlx::query_as("SELECT b3sum, refcount FROM objects ORDER BY b3sum")
+                    .fetch_all(&database.pool)
+                    .await
+                    .unwrap();
+            let mut stored: Vec<PathBuf> = [repo.objects_root(), temp.path().join(".ddrive/trash")]
+                .iter()
+                .flat_map(|root| {
+                    ignore::WalkBuilder::new(root)
+                        .standard_filters(false)
+                        .build()
+                })
+                .map(|entry| entry.unwrap().into_path())
+                .collect();
+            stored.sort();
+            (history, files, objects, stored)
+        };
+
+        let before = snapshot().await;
+        let dry_run = ddrive
+            .prune(&PruneOptions {
+                dry_run: true,
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        assert_eq!(snapshot().await, before);
+
+        let result = ddrive.prune(&PruneOptions::default()).await.unwrap();
+        assert_ne!(snapshot().await, before);
+        assert!(dry_run.dry_run && !result.dry_run);
+        assert_eq!(dry_run.pruned_backups, 1);
+        assert_eq!(dry_run.purged_file_records, 1);
+        assert_eq!(
+            (dry_run.orphaned_objects_trashed, dry_run.orphaned_bytes),
+            (1, 6)
+        );
+        assert_eq!(dry_run.trash_objects_deleted, 1);
+        assert_eq!(
+            (
+                dry_run.pruned_backups,
+                dry_run.purged_file_records,
+                dry_run.orphaned_objects_trashed,
+                dry_run.orphaned_bytes,
+                dry_run.trash_objects_deleted,
+                dry_run.trash_bytes_freed,
+            ),
+            (
+                result.pruned_backups,
+                result.purged_file_records,
+                result.orphaned_objects_trashed,
+                result.orphaned_bytes,
+                result.trash_objects_deleted,
+                result.trash_bytes_freed,
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn test_target_free_deletes_objects_without_trash() {
+        let (temp, ddrive) = repository(&[("docs/a.txt", "first")]).await;
+        let docs = [temp.path().join("docs")];
+        ddrive.add(&docs, &AddOptions::default()).await.unwrap();
+        // The first add is past the orphan grace period but within retention
+        let database = &ddrive.context().database;
+        let aged = (Utc::now() - chrono::Duration::days(30)).timestamp();
+        sqlx::query("UPDATE history SET action_id = ?1")
+            .bind(aged)
+            .execute(&database.pool)
+            .await
+            .unwrap();
+        temp.child("docs/a.txt")
+            .write_str("second version")
+            .unwrap();
+        let second_add = ddrive
+            .add(&docs, &AddOptions::default())
+            .await
+            .unwrap()
+            .action_id
+            .unwrap();
+
+        let result = PruneCommand::new(ddrive.context())
+            .execute(false, Some(u64::MAX))
+            .await
+            .unwrap();
+        assert_eq!(result.reclaimed_actions, 1);
+        assert_eq!(result.orphaned_objects_trashed, 0);
+        // Freeing space is the point, so the object is gone, not trashed
+        let [first, second] = [b"first".as_slice(), b"second version"]
+            .map(|content| blake3::hash(content).to_hex().to_string());
+        let objects = &ddrive.context().objects;
+        assert!(!objects.exists(&first).unwrap());
+        assert!(objects.exists(&second).unwrap());
+        let repo = &ddrive.context().repo;
+        assert!(
+            repo.trash_runs()
+                .unwrap()
+                .iter()
+                .all(|(_, run)| repo.trash_run_objects(run).unwrap().is_empty())
+        );
+        // The action younger than the grace period is kept
+        assert_eq!(
+            database.get_history_action_ids().await.unwrap(),
+            [second_add]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_prune_estimate() {
+        let (_temp, ddrive) =
+            configured_repository(&[], |config| config.prune.keep_last = Some(1)).await;
+        let days_ago = |days: i64| (chrono::Utc::now() - chrono::Duration::days(days)).timestamp();
+        for (action_id, action_type, b3sum, size) in [
+            (days_ago(100), ActionType::Add, "first", 5),
+            (days_ago(50), ActionType::Update, "second", 6),
+            (days_ago(10), ActionType::Update, "third", 7),
+        ] {
+            ddrive
+                .context()
+                .database
+                .insert_history_entries(
+                    action_id,
+                    action_type,
+                    &[("a".to_string(), Some(b3sum.to_string()), Some(size))],
+                    None,
+                )
+                .await
+                .unwrap();
+        }
+
+        let estimate = ddrive.prune_estimate(&[30, 60, 120]).await.unwrap();
+        let rows: Vec<(u32, usize, usize, u64)> = estimate
+            .retention
+            .iter()
+            .map(|row| {
+                (
+                    row.retention_days,
+                    row.pruned_actions,
+                    row.objects,
+                    row.bytes,
+                )
+            })
+            .collect();
+        // The configured 90 days are included; keep_last keeps the newest action
+        assert_eq!(
+            rows,
+            [(30, 2, 2, 11), (60, 1, 1, 5), (90, 1, 1, 5), (120, 0, 0, 0)]
+        );
+        assert_eq!(estimate.orphaned_objects, 0);
+        assert_eq!(estimate.trash_objects, 0);
     }
 }
@@ -0,0 +1,140 @@
+//! Maintenance across every registered repository.
+//!
+//! This module provides the `ReposCommand` which lists the repositories in
+//! the user's registry and runs `status` or `verify` on each of them,
+//! skipping those on drives that are not attached.
+
+use super::{GlobalArgs, check_verify_result, open_context};
+use crate::{
+    DdriveError, Result,
+    checksum::HashAlgorithm,
+    cli::{status::StatusCommand, verify::VerifyCommand},
+    registry::{RegisteredRepo, Registry},
+    repository::Repository,
+    utils,
+};
+use std::path::Path;
+use tracing::{error, info, warn};
+
+pub struct ReposCommand<'a> {
+    registry: Registry,
+    global: &'a GlobalArgs,
+}
+
+impl<'a> ReposCommand<'a> {
+    /// Work on the repositories in `registry`, applying the global flags to each
+    pub fn new(registry: Registry, global: &'a GlobalArgs) -> Self {
+        Self { registry, global }
+    }
+
+    /// List the registered repositories and whether they are available
+    pub fn list(&self) {
+        if self.registry.repos.is_empty() {
+            info!("No repositories registered yet; run 'ddrive init' or 'ddrive repos add' in one");
+            return;
+        }
+        for repo in &self.registry.repos {
+            let state = if repo.is_available() {
+                "available"
+            } else {
+                "not available"
+            };
+            info!("{} ({})", repo.path.display(), state);
+        }
+    }
+
+    /// Show a one-line status of each available repository
+    pub async fn status(&self) -> Result<()> {
+        for repo in self.available_repos() {
+            let context = open_context(repo.path.clone(), self.global).await?;
+            let stats = StatusCommand::new(&context).gather_stats().await?;
+            let mut line = format!(
+                "{}: {} files ({}), {} not protected, {} due for verification",
+                repo.path.display(),
+                stats.tracked_files,
+                context.format_size(stats.total_tracked_size),
+                stats.untracked_files,
+                stats.files_needing_check
+            );
+            if !stats.unresolved_failures.is_empty() {
+                line.push_str(&format!(
+                    ", {} failed verification",
+                    stats.unresolved_failures.len()
+                ));
+            }
+            if !stats.unreadable_files.is_empty() {
+                line.push_str(&format!(", {} unreadable", stats.unreadable_files.len()));
+            }
+            info!("{}", line);
+        }
+        Ok(())
+    }
+
+    /// Verify the files due for a check in each available repository. All
+    /// repositories are verified even if one fails.
+    pub async fn verify_all(&self) -> Result<()> {
+        let mut failed = 0;
+        for repo in self.available_repos() {
+            info!("Verifying {}", repo.path.display());
+            let outcome = match open_context(repo.path.clone(), self.global).await {
+                Ok(context) => {
+                    if let Err(e) = utils::lower_priority(&context.config.performance) {
+                        warn!("Could not lower the priority of this run: {}", e);
+                    }
+                    VerifyCommand::new(&context)
+                        .execute(None, None, false, HashAlgorithm::Blake3, false)
+                        .await
+                        .and_then(|result| check_verify_result(&result, false))
+                }
+                Err(e) => Err(e),
+            };
+            if let Err(e) = outcome {
+                error!("{}: {}", repo.path.display(), e);
+                failed += 1;
+            }
+        }
+
+        if failed > 0 {
+            return Err(DdriveError::Validation {
+                message: format!("{failed} repositories failed verification"),
+            });
+        }
+        Ok(())
+    }
+
+    /// Register the repository containing `path`, for one created before the
+    /// registry or forgotten since
+    pub fn add(&mut self, path: &Path) -> Result<()> {
+        let repo = Repository::find_repository(path.to_path_buf())?;
+        let root = repo.root();
+        if Registry::update(self.registry.file(), |registry| registry.register(root))? {
+            info!("Registered {}", root.display());
+        } else {
+            info!("{} is already registered", root.display());
+        }
+        Ok(())
+    }
+
+    /// Forget a repository
+    pub fn forget(&mut self, path: &Path) -> Result<()> {
+        if !Registry::update(self.registry.file(), |registry| {
+            Ok(registry.unregister(path))
+        })? {
+            warn!("{} is not registered", path.display());
+            return Ok(());
+        }
+        info!("Forgot {}", path.display());
+        Ok(())
+    }
+
+    /// Registered repositories that can be opened, warning about the others
+    fn available_repos(&self) -> impl Iterator<Item = &RegisteredRepo> {
+        self.registry.repos.iter().filter(|repo| {
+            let available = repo.is_available();
+            if !available {
+                warn!("Skipping {}: not available", repo.path.display());
+            }
+            available
+        })
+    }
+}
@@ -1,64 +1,327 @@
 //! File removal functionality for removing files from repository.
 //!
 //! This module provides the `RmCommand` which handles the workflow
-//! of removing files from tracking in the database without affecting
-//! the actual files on disk.
+//! of removing files from tracking in the database. By default the actual
+//! files on disk are not affected; `rm tracked` can also delete them and the
+//! objects only they referenced when decommissioning a directory.
 
-use crate::{AppContext, Result, scanner::FileScanner, utils::FileProcessor};
+use crate::{
+    AppContext, DdriveError, Result,
+    checksum::HashAlgorithm,
+    codes,
+    config::MissingObjects,
+    database::{ActionType, FileRecord},
+    logging,
+    progress::ProgressEvent,
+    scanner::FileScanner,
+    utils::{self, FileChange, FileProcessor, from_storage_path},
+};
 use glob::Pattern;
-use tracing::info;
+use std::collections::HashSet;
+use std::fs;
+use std::io::IsTerminal;
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+use tracing::{info, warn};
 
 pub struct RmCommand<'a> {
     context: &'a AppContext,
 }
 
+/// Options for `rm tracked`
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RmOptions {
+    /// Also delete the files from the working tree, those with a stored copy
+    /// of their current content
+    pub delete_files: bool,
+    /// Delete files from the working tree even without a stored copy of
+    /// their current content
+    pub force: bool,
+    /// Forget the files and their history past retention, and move the
+    /// objects no other file or history entry references to the trash
+    pub drop_objects: bool,
+    /// Only report what would be removed
+    pub dry_run: bool,
+    /// Do not ask before deleting anything
+    pub yes: bool,
+    /// Pick which of the matching files to remove on the terminal
+    pub interactive: bool,
+}
+
+#[derive(Debug, Default)]
+pub struct RmResult {
+    pub dry_run: bool,
+    /// Files removed (or, for a dry run, to be removed) from tracking
+    pub removed: usize,
+    /// Files deleted from the working tree
+    pub deleted_files: usize,
+    /// Files that could not be deleted from the working tree
+    pub undeleted_files: Vec<String>,
+    /// Files left tracked and on disk because deleting them would lose
+    /// content: they have no stored copy or changed since they were recorded
+    pub kept_files: Vec<String>,
+    /// Objects moved from the object store to the trash, and their total size
+    pub dropped_objects: usize,
+    pub dropped_bytes: u64,
+}
+
 impl<'a> RmCommand<'a> {
     pub fn new(context: &'a AppContext) -> Self {
         RmCommand { context }
     }
 
-    /// Remove tracked files
-    pub async fn tracked(&self, pattern: Pattern) -> Result<usize> {
+    /// Remove tracked files matching `pattern`, and with `options` also
+    /// delete them from disk and drop their objects
+    pub async fn tracked(&self, pattern: Pattern, options: RmOptions) -> Result<RmResult> {
         let tracked_files = self.context.database.get_all_files().await?;
-        let files_to_remove: Vec<_> = tracked_files
+        let mut files_to_remove: Vec<_> = tracked_files
             .into_iter()
             .filter(|file| pattern.matches(&file.path))
             .collect();
 
+        let mut result = RmResult {
+            dry_run: options.dry_run,
+            ..Default::default()
+        };
         if files_to_remove.is_empty() {
             info!("No matching files found to remove from tracking");
-            return Ok(0);
+            return Ok(result);
         }
 
+        if options.interactive {
+            files_to_remove = self.select_files(files_to_remove)?;
+            if files_to_remove.is_empty() {
+                info!("No files selected, nothing removed");
+                return Ok(result);
+            }
+        }
+        if options.delete_files && !options.force {
+            files_to_remove = self.keep_unsafe_files(files_to_remove, &mut result);
+            if files_to_remove.is_empty() {
+                info!("No files can be deleted safely, nothing removed");
+                return Ok(result);
+            }
+        }
         self.display_files_to_remove(&files_to_remove);
 
         let file_records: Vec<(String, String, i64)> = files_to_remove
             .iter()
             .map(|file| (file.path.clone(), file.b3sum.clone(), file.size))
             .collect();
-
+        let paths: Vec<String> = files_to_remove.iter().map(|f| f.path.clone()).collect();
         let action_id = chrono::Utc::now().timestamp();
+
+        if options.dry_run {
+            result.removed = file_records.len();
+            info!(
+                "Dry run: {} files would be removed from tracking",
+                result.removed
+            );
+            if options.delete_files {
+                info!("  and deleted from disk");
+            }
+            if options.drop_objects {
+                let released = self
+                    .context
+                    .database
+                    .purge_paths(
+                        &paths,
+                        self.context.config.prune.cutoff_date(),
+                        &self.context.repo.trash_dir(action_id),
+                        true,
+                        self.context.objects.as_ref(),
+                    )
+                    .await?;
+                result.dropped_objects = released.len();
+                result.dropped_bytes = released.iter().map(|object| object.size).sum();
+                info!(
+                    "  {} objects ({}) only they reference would be moved to trash",
+                    result.dropped_objects,
+                    self.context.format_size(result.dropped_bytes)
+                );
+            }
+            return Ok(result);
+        }
+
+        let _lock = if options.delete_files || options.drop_objects {
+            self.confirm(file_records.len(), options)?;
+            Some(self.context.repo.lock()?)
+        } else {
+            None
+        };
+        if options.drop_objects {
+            self.context.database.recount_object_references().await?;
+        }
+
+        logging::set_action_id(action_id);
         self.context
             .database
             .batch_delete_file_records(action_id, &file_records)
             .await?;
+        self.context.emit(ProgressEvent::ActionCommitted {
+            action_id,
+            action_type: ActionType::Delete,
+            files: file_records.len(),
+        });
+        result.removed = file_records.len();
+        info!("Removed {} files from tracking", result.removed);
 
-        info!("Removed {} files from tracking", files_to_remove.len());
-        Ok(file_records.len())
+        if options.delete_files {
+            self.delete_files(&file_records, &mut result);
+        }
+        if options.drop_objects {
+            let dropped = self
+                .context
+                .database
+                .purge_paths(
+                    &paths,
+                    self.context.config.prune.cutoff_date(),
+                    &self.context.repo.trash_dir(action_id),
+                    false,
+                    self.context.objects.as_ref(),
+                )
+                .await?;
+            result.dropped_objects = dropped.len();
+            result.dropped_bytes = dropped.iter().map(|object| object.size).sum();
+            info!(
+                "Moved {} objects ({}) no other file or history entry references to trash",
+                result.dropped_objects,
+                self.context.format_size(result.dropped_bytes)
+            );
+        }
+        Ok(result)
     }
 
-    /// Remove the deleted files from tracking
-    pub async fn deleted(&self, pattern: Option<Pattern>) -> Result<usize> {
+    /// Let the user pick which of the matching files to remove
+    fn select_files(&self, files: Vec<FileRecord>) -> Result<Vec<FileRecord>> {
+        if !std::io::stdin().is_terminal() {
+            warn!("--interactive needs a terminal to select files on");
+            return Err(DdriveError::UserCancelled);
+        }
+        info!("{} tracked files match:", files.len());
+        let paths: Vec<String> = files.iter().map(|file| file.path.clone()).collect();
+        let selected: HashSet<usize> = utils::select("Files to remove from tracking", &paths)?
+            .into_iter()
+            .collect();
+        Ok(files
+            .into_iter()
+            .enumerate()
+            .filter(|(i, _)| selected.contains(i))
+            .map(|(_, file)| file)
+            .collect())
+    }
+
+    /// Leave out the files whose deletion from disk would lose content,
+    /// noting them in `result.kept_files`
+    fn keep_unsafe_files(&self, files: Vec<FileRecord>, result: &mut RmResult) -> Vec<FileRecord> {
+        let processor = FileProcessor::new(self.context);
+        let (kept, safe): (Vec<_>, Vec<_>) = files
+            .into_iter()
+            .map(|file| (self.delete_risk(&file, &processor), file))
+            .partition(|(risk, _)| risk.is_some());
+        if !kept.is_empty() {
+            warn!(
+                "Keeping {} files that deleting would lose; pass --force to delete them anyway:",
+                kept.len()
+            );
+            for (risk, file) in &kept {
+                warn!("  {} ({})", file.path, risk.unwrap_or_default());
+            }
+        }
+        result.kept_files = kept.into_iter().map(|(_, file)| file.path).collect();
+        safe.into_iter().map(|(_, file)| file).collect()
+    }
+
+    /// Why deleting `file` from disk would lose content, if it would: the
+    /// stored copy must exist and the file must still be what was recorded
+    fn delete_risk(&self, file: &FileRecord, processor: &FileProcessor) -> Option<&'static str> {
+        if !file.stored || !matches!(self.context.objects.exists(&file.b3sum), Ok(true)) {
+            return Some("no stored copy");
+        }
+        let absolute_path = self.context.repo.root().join(from_storage_path(&file.path));
+        let metadata = match fs::metadata(&absolute_path) {
+            Ok(metadata) => metadata,
+            // Nothing left to delete
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return None,
+            Err(_) => return Some("cannot be read"),
+        };
+        let tolerance = self.context.config.changes.mtime_tolerance_ms as u128 * 1_000_000;
+        let mtime_changed = file.mtime_ns.is_some_and(|recorded| {
+            metadata
+                .modified()
+                .ok()
+                .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+                .is_none_or(|modified| modified.as_nanos().abs_diff(recorded as u128) > tolerance)
+        });
+        if metadata.len() != file.size as u64 || mtime_changed {
+            return Some("changed since it was recorded");
+        }
+        match processor.calculate_single_checksum_with(&absolute_path, HashAlgorithm::Blake3) {
+            Ok(b3sum) if b3sum == file.b3sum => None,
+            Ok(_) => Some("changed since it was recorded"),
+            Err(_) => Some("cannot be read"),
+        }
+    }
+
+    /// Ask before deleting files or objects, unless `options.yes` is set
+    fn confirm(&self, count: usize, options: RmOptions) -> Result<()> {
+        if options.yes {
+            return Ok(());
+        }
+        let prompt = match (options.delete_files, options.drop_objects) {
+            (true, true) => format!(
+                "Delete {count} files from disk and their stored copies? This cannot be undone."
+            ),
+            (true, false) => format!("Delete {count} files from disk? Stored copies are kept."),
+            _ => format!(
+                "Delete the stored copies of {count} files and forget them? This cannot be undone."
+            ),
+        };
+        if utils::confirm(&prompt)? {
+            return Ok(());
+        }
+        warn!("Nothing removed; pass --yes to confirm without a terminal");
+        Err(DdriveError::UserCancelled)
+    }
+
+    /// Delete the removed files from the working tree, and directories left empty
+    fn delete_files(&self, records: &[(String, String, i64)], result: &mut RmResult) {
+        let repo_root = self.context.repo.root();
+        for (path, _, _) in records {
+            let absolute_path = repo_root.join(from_storage_path(path));
+            match fs::remove_file(&absolute_path) {
+                Ok(()) => {
+                    result.deleted_files += 1;
+                    remove_empty_parents(&absolute_path, repo_root);
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => {
+                    warn!("Failed to delete {}: {}", path, e);
+                    result.undeleted_files.push(path.clone());
+                }
+            }
+        }
+        info!("Deleted {} files from disk", result.deleted_files);
+    }
+
+    /// Remove the deleted files from tracking. Files whose object is missing
+    /// from the store are handled per `rm.missing_objects` unless `force` is set.
+    pub async fn deleted(&self, pattern: Option<Pattern>, force: bool) -> Result<usize> {
         let pattern = pattern.as_ref();
         let repo_root = &self.context.repo.root().canonicalize()?;
         let processor = FileProcessor::new(self.context);
-        let scanner = FileScanner::new(repo_root.clone());
+        let scanner = FileScanner::with_config(repo_root.clone(), self.context.config.scan.clone());
 
-        let tracked_files = self.context.database.get_all_files().await?;
         let files = scanner.get_all_files(repo_root)?;
+        let tracked_files = self.context.database.stream_files();
 
-        let (_, _, deleted_files, _) = processor
-            .detect_changes(&files, tracked_files.as_slice(), false)
+        let mut deleted_files = Vec::new();
+        processor
+            .detect_changes(&files, tracked_files, false, |change| {
+                if let FileChange::Deleted(file) = change {
+                    deleted_files.push(file);
+                }
+            })
             .await?;
 
         info!("found {} deleted files", deleted_files.len());
@@ -84,6 +347,7 @@ impl<'a> RmCommand<'a> {
             .await?;
 
         self.display_files_to_remove(&deleted_file_records);
+        self.check_missing_objects(&deleted_file_records, force)?;
 
         let deleted_file_records: Vec<_> = deleted_file_records
             .iter()
@@ -91,10 +355,16 @@ impl<'a> RmCommand<'a> {
             .collect();
 
         let action_id = chrono::Utc::now().timestamp();
+        logging::set_action_id(action_id);
         self.context
             .database
             .batch_delete_file_records(action_id, deleted_file_records.as_slice())
             .await?;
+        self.context.emit(ProgressEvent::ActionCommitted {
+            action_id,
+            action_type: ActionType::Delete,
+            files: deleted_file_records.len(),
+        });
 
         info!(
             "Removed {} deleted files from tracking",
@@ -103,8 +373,40 @@ impl<'a> RmCommand<'a> {
         Ok(deleted_file_records.len())
     }
 
+    /// Refuse to drop the records of files whose stored copy is missing, as
+    /// the record is then the only trace of their content. Files that were
+    /// never stored are not expected to have a copy.
+    fn check_missing_objects(&self, files: &[FileRecord], force: bool) -> Result<()> {
+        let objects = &self.context.objects;
+        let missing: Vec<_> = files
+            .iter()
+            .filter(|file| file.stored && !matches!(objects.exists(&file.b3sum), Ok(true)))
+            .collect();
+        if missing.is_empty() {
+            return Ok(());
+        }
+
+        warn!(
+            code = %codes::MISSING_STORED_COPY,
+            "{} files have no stored copy; their records are the last trace of their content:",
+            missing.len()
+        );
+        for file in &missing {
+            warn!("  {} ({})", file.path, file.b3sum);
+        }
+        if force || self.context.config.rm.missing_objects == MissingObjects::Warn {
+            return Ok(());
+        }
+        Err(DdriveError::Validation {
+            message: format!(
+                "{} files without a stored copy; pass --force to remove them anyway",
+                missing.len()
+            ),
+        })
+    }
+
     /// Display files that will be removed from tracking
-    fn display_files_to_remove(&self, files: &[crate::database::FileRecord]) {
+    fn display_files_to_remove(&self, files: &[FileRecord]) {
         if files.len() <= 5 {
             info!("Files to remove from tracking:");
             for file in files {
@@ -122,3 +424,236 @@ impl<'a> RmCommand<'a> {
         }
     }
 }
+
+/// Remove the directories above `path` that are now empty, up to `root`
+fn remove_empty_parents(path: &Path, root: &Path) {
+    let mut dir = path.parent();
+    while let Some(current) = dir {
+        if current == root || !current.starts_with(root) || fs::remove_dir(current).is_err() {
+            break;
+        }
+        dir = current.parent();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::{AddOptions, Ddrive};
+    use assert_fs::TempDir;
+    use assert_fs::prelude::*;
+
+    #[tokio::test]
+    async fn test_tracked_delete_files_and_drop_objects() {
+        let temp = TempDir::new().unwrap();
+        temp.child("old/a.txt").write_str("first draft").unwrap();
+        temp.child("old/sub/b.txt").write_str("shared").unwrap();
+        temp.child("keep/c.txt").write_str("shared").unwrap();
+
+        let ddrive = Ddrive::init(temp.path()).await.unwrap();
+        for dir in ["old", "keep"] {
+            ddrive
+                .add(&[temp.path().join(dir)], &AddOptions::default())
+                .await
+                .unwrap();
+        }
+        temp.child("old/a.txt").write_str("only here").unwrap();
+        ddrive
+            .add(&[temp.path().join("old")], &AddOptions::default())
+            .await
+            .unwrap();
+        // The first draft is only in history, which is past retention
+        let database = &ddrive.context().database;
+        sqlx::query("UPDATE history SET action_id = 1000")
+            .execute(&database.pool)
+            .await
+            .unwrap();
+
+        let rm = RmCommand::new(ddrive.context());
+        let options = RmOptions {
+            delete_files: true,
+            drop_objects: true,
+            yes: true,
+            ..Default::default()
+        };
+
+        let dry_run = rm
+            .tracked(
+                Pattern::new("old/*").unwrap(),
+                RmOptions {
+                    dry_run: true,
+                    ..options
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(dry_run.dropped_objects, 1);
+        assert!(temp.path().join("old/a.txt").exists());
+
+        let result = rm
+            .tracked(Pattern::new("old/*").unwrap(), options)
+            .await
+            .unwrap();
+        assert_eq!(result.removed, 2);
+        assert_eq!(result.deleted_files, 2);
+        assert_eq!(result.dropped_objects, 1);
+        assert!(!temp.path().join("old").exists());
+
+        // The draft went to the trash; the last content is still referenced
+        // by the deletion just recorded, and the shared content by keep/
+        let [draft, last, shared] = [b"first draft".as_slice(), b"only here", b"shared"]
+            .map(|content| blake3::hash(content).to_hex().to_string());
+        let objects = &ddrive.context().objects;
+        assert!(!objects.exists(&draft).unwrap());
+        assert!(objects.exists(&last).unwrap());
+        assert!(objects.exists(&shared).unwrap());
+        let repo = &ddrive.context().repo;
+        let trashed: Vec<_> = repo
+            .trash_runs()
+            .unwrap()
+            .iter()
+            .flat_map(|(_, run)| repo.trash_run_objects(run).unwrap())
+            .collect();
+        assert_eq!(trashed.len(), 1);
+        assert!(trashed[0].0.ends_with(&draft));
+
+        // Of old/ only the recent deletions are left in history
+        let history = database.get_history_entries(None, None).await.unwrap();
+        assert!(history.iter().all(|entry| {
+            entry.path.starts_with("keep/") || entry.action_type_enum() == ActionType::Delete
+        }));
+    }
+
+    #[tokio::test]
+    async fn test_tracked_keeps_files_without_stored_copy() {
+        use crate::{config::ObjectStoreMode, repository::Repository};
+
+        let temp = TempDir::new().unwrap();
+        temp.child("docs/a.txt").write_str("only copy").unwrap();
+        Repository::init_repository(temp.path().to_path_buf())
+            .await
+            .unwrap();
+        let context = Ddrive::builder(temp.path())
+            .configure(|config| config.object_store.mode = ObjectStoreMode::None)
+            .build()
+            .await
+            .unwrap();
+        let ddrive = Ddrive::from_context(context);
+        ddrive
+            .add(&[temp.path().join("docs")], &AddOptions::default())
+            .await
+            .unwrap();
+
+        let rm = RmCommand::new(ddrive.context());
+        let options = RmOptions {
+            delete_files: true,
+            yes: true,
+            ..Default::default()
+        };
+        let result = rm
+            .tracked(Pattern::new("docs/*").unwrap(), options)
+            .await
+            .unwrap();
+        assert_eq!(result.kept_files, ["docs/a.txt"]);
+        assert_eq!((result.removed, result.deleted_files), (0, 0));
+        assert!(temp.path().join("docs/a.txt").exists());
+        let database = &ddrive.context().database;
+        assert_eq!(database.get_all_files().await.unwrap().len(), 1);
+
+        let result = rm
+            .tracked(
+                Pattern::new("docs/*").unwrap(),
+                RmOptions {
+                    force: true,
+                    ..options
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(result.deleted_files, 1);
+        assert!(!temp.path().join("docs/a.txt").exists());
+    }
+
+    #[tokio::test]
+    async fn test_tracked_keeps_files_changed_since_recorded() {
+        let temp = TempDir::new().unwrap();
+        temp.child("docs/edited.txt").write_str("draft").unwrap();
+        temp.child("docs/grown.txt").write_str("short").unwrap();
+        temp.child("docs/same.txt").write_str("same").unwrap();
+        let ddrive = Ddrive::init(temp.path()).await.unwrap();
+        ddrive
+            .add(&[temp.path().join("docs")], &AddOptions::default())
+            .await
+            .unwrap();
+
+        // An edit keeping size and modification time is caught by its checksum
+        let edited = temp.path().join("docs/edited.txt");
+        let modified = fs::metadata(&edited).unwrap().modified().unwrap();
+        fs::write(&edited, "final").unwrap();
+        fs::File::options()
+            .write(true)
+            .open(&edited)
+            .unwrap()
+            .set_modified(modified)
+            .unwrap();
+        temp.child("docs/grown.txt")
+            .write_str("much longer")
+            .unwrap();
+
+        let result = RmCommand::new(ddrive.context())
+            .tracked(
+                Pattern::new("docs/*").unwrap(),
+                RmOptions {
+                    delete_files: true,
+                    yes: true,
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(result.kept_files, ["docs/edited.txt", "docs/grown.txt"]);
+        assert_eq!((result.removed, result.deleted_files), (1, 1));
+        assert!(!temp.path().join("docs/same.txt").exists());
+        assert_eq!(fs::read_to_string(&edited).unwrap(), "final");
+        let tracked: Vec<String> = ddrive
+            .context()
+            .database
+            .get_all_files()
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|file| file.path)
+            .collect();
+        assert_eq!(tracked, ["docs/edited.txt", "docs/grown.txt"]);
+    }
+
+    #[tokio::test]
+    async fn test_deleted_keeps_files_without_stored_copy() {
+        let temp = TempDir::new().unwrap();
+        temp.child("docs/a.txt").write_str("gone").unwrap();
+        let ddrive = Ddrive::init(temp.path()).await.unwrap();
+        ddrive
+            .add(&[temp.path().join("docs")], &AddOptions::default())
+            .await
+            .unwrap();
+
+        std::fs::remove_file(temp.path().join("docs/a.txt")).unwrap();
+        let b3sum = blake3::hash(b"gone").to_hex().to_string();
+        let repo = &ddrive.context().repo;
+        std::fs::remove_file(repo.object_dir(&b3sum).join(&b3sum)).unwrap();
+
+        let rm = RmCommand::new(ddrive.context());
+        assert!(rm.deleted(None, false).await.is_err());
+        assert_eq!(
+            ddrive
+                .context()
+                .database
+                .get_all_files()
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+        assert_eq!(rm.deleted(None, true).await.unwrap(), 1);
+    }
+}
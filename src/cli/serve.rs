@@ -0,0 +1,594 @@
+//! HTTP API server for dashboards and monitoring.
+//!
+//! `ddrive serve` answers a handful of JSON endpoints over plain HTTP/1.1.
+//! Every request must carry `Authorization: Bearer <token>`. The server is
+//! meant for a trusted network or a reverse proxy that adds TLS.
+
+use crate::{
+    AppContext, DdriveError, Result,
+    checksum::HashAlgorithm,
+    cli::{
+        status::StatusCommand,
+        verify::{PathFilter, VerifyCommand},
+    },
+    database::{ActionType, FileRecord},
+    utils,
+};
+use ring::rand::{SecureRandom, SystemRandom};
+use serde_json::{Value, json};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::Mutex;
+use tracing::{debug, info, warn};
+
+/// Requests with larger headers are rejected
+const MAX_HEADER_SIZE: usize = 16 * 1024;
+
+/// Connections that have not sent a whole request head by then are closed,
+/// so idle or slow clients cannot tie up the server
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Environment variable that overrides `serve.token`
+pub const TOKEN_ENV: &str = "DDRIVE_TOKEN";
+
+pub struct ServeCommand {
+    state: Arc<ServerState>,
+}
+
+struct ServerState {
+    context: AppContext,
+    token: blake3::Hash,
+    verify: Mutex<VerifyJob>,
+}
+
+/// The verify run triggered through the API, if any
+#[derive(Default)]
+struct VerifyJob {
+    running: bool,
+    last_result: Option<Value>,
+}
+
+/// A parsed HTTP request; bodies are not used by any endpoint
+#[derive(Debug, PartialEq)]
+struct Request {
+    method: String,
+    path: String,
+    query: HashMap<String, String>,
+    authorization: Option<String>,
+}
+
+struct Response {
+    status: u16,
+    body: Value,
+}
+
+impl Response {
+    fn ok(body: Value) -> Self {
+        Self { status: 200, body }
+    }
+
+    fn error(status: u16, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            body: json!({ "error": message.into() }),
+        }
+    }
+
+    /// Error response for a failed command, with the error's stable code
+    fn failed(status: u16, error: &DdriveError) -> Self {
+        Self {
+            status,
+            body: json!({ "error": error.to_string(), "code": error.code().id }),
+        }
+    }
+}
+
+impl ServeCommand {
+    /// Serve with the token from `DDRIVE_TOKEN` or `serve.token`, generating
+    /// one for this run if neither is set
+    pub fn new(context: &AppContext) -> Result<Self> {
+        let token = match std::env::var(TOKEN_ENV)
+            .ok()
+            .or_else(|| context.config.serve.token.clone())
+        {
+            Some(token) if !token.is_empty() => token,
+            _ => {
+                let token = generate_token()?;
+                // Straight to the terminal, so the token stays out of log files
+                eprintln!("No API token configured, using {token} for this run");
+                token
+            }
+        };
+
+        Ok(Self {
+            state: Arc::new(ServerState {
+                context: context.clone(),
+                token: blake3::hash(token.as_bytes()),
+                verify: Mutex::new(VerifyJob::default()),
+            }),
+        })
+    }
+
+    /// Listen on `address` until the process is stopped
+    pub async fn execute(&self, address: &str) -> Result<()> {
+        let listener = TcpListener::bind(address).await?;
+        info!(
+            "Serving the ddrive API on http://{}",
+            listener.local_addr()?
+        );
+
+        loop {
+            let (stream, peer) = listener.accept().await?;
+            let state = Arc::clone(&self.state);
+            tokio::spawn(async move {
+                if let Err(e) = handle_connection(state, stream).await {
+                    debug!("Connection from {peer} failed: {e}");
+                }
+            });
+        }
+    }
+}
+
+async fn handle_connection(state: Arc<ServerState>, mut stream: TcpStream) -> Result<()> {
+    let response = match receive(&mut stream, REQUEST_TIMEOUT).await? {
+        Ok(request) => state.handle(request).await,
+        Err(response) => response,
+    };
+
+    let body = response.body.to_string();
+    let head = format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        response.status,
+        reason_phrase(response.status),
+        body.len()
+    );
+    stream.write_all(head.as_bytes()).await?;
+    stream.write_all(body.as_bytes()).await?;
+    stream.shutdown().await?;
+    Ok(())
+}
+
+/// Read the request head within `limit`, or the response to send instead
+/// when it is malformed or does not arrive in time
+async fn receive(
+    stream: &mut TcpStream,
+    limit: Duration,
+) -> Result<std::result::Result<Request, Response>> {
+    match tokio::time::timeout(limit, read_request(stream)).await {
+        Ok(read) => Ok(read?.ok_or_else(|| Response::error(400, "Malformed request"))),
+        Err(_) => Ok(Err(Response::error(
+            408,
+            "Timed out waiting for the request",
+        ))),
+    }
+}
+
+/// Read the request head. Returns `None` if it cannot be parsed.
+async fn read_request(stream: &mut TcpStream) -> Result<Option<Request>> {
+    let mut buffer = Vec::new();
+    let mut chunk = [0u8; 4096];
+    while !buffer.windows(4).any(|w| w == b"\r\n\r\n") {
+        if buffer.len() > MAX_HEADER_SIZE {
+            return Ok(None);
+        }
+        let read = stream.read(&mut chunk).await?;
+        if read == 0 {
+            return Ok(None);
+        }
+        buffer.extend_from_slice(&chunk[..read]);
+    }
+    Ok(parse_request(&String::from_utf8_lossy(&buffer)))
+}
+
+fn parse_request(head: &str) -> Option<Request> {
+    let mut lines = head.split("\r\n");
+    let mut request_line = lines.next()?.split(' ');
+    let method = request_line.next()?.to_string();
+    let target = request_line.next()?;
+    if !request_line.next()?.starts_with("HTTP/1.") {
+        return None;
+    }
+
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let query = query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            (percent_decode(key), percent_decode(value))
+        })
+        .collect();
+
+    let authorization = lines
+        .take_while(|line| !line.is_empty())
+        .filter_map(|line| line.split_once(':'))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("authorization"))
+        .map(|(_, value)| value.trim().to_string());
+
+    Some(Request {
+        method,
+        path: percent_decode(path),
+        query,
+        authorization,
+    })
+}
+
+fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = |b: u8| (b as char).to_digit(16);
+        match bytes[i] {
+            b'%' if i + 2 < bytes.len() => match (hex(bytes[i + 1]), hex(bytes[i + 2])) {
+                (Some(high), Some(low)) => {
+                    decoded.push((high * 16 + low) as u8);
+                    i += 3;
+                    continue;
+                }
+                _ => decoded.push(b'%'),
+            },
+            b'+' => decoded.push(b' '),
+            byte => decoded.push(byte),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+fn reason_phrase(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        202 => "Accepted",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        408 => "Request Timeout",
+        409 => "Conflict",
+        _ => "Internal Server Error",
+    }
+}
+
+/// Random token from the operating system's random source
+fn generate_token() -> Result<String> {
+    let mut bytes = [0u8; 24];
+    SystemRandom::new()
+        .fill(&mut bytes)
+        .map_err(|_| DdriveError::Configuration {
+            message: format!(
+                "Cannot generate an API token: no random numbers available; set {TOKEN_ENV} or serve.token"
+            ),
+        })?;
+    Ok(bs58::encode(bytes).into_string())
+}
+
+impl ServerState {
+    async fn handle(self: &Arc<Self>, request: Request) -> Response {
+        if !self.authorized(&request) {
+            return Response::error(401, "Missing or invalid bearer token");
+        }
+
+        let result = match (request.method.as_str(), request.path.as_str()) {
+            ("GET", "/api/status") => self.status().await,
+            ("GET", "/api/files") => self.files(&request.query).await,
+            ("GET", "/api/history") => self.history(&request.query).await,
+            ("GET", "/api/verify") => self.verify_results().await,
+            ("POST", "/api/verify") => self.trigger_verify(&request.query).await,
+            (_, "/api/status" | "/api/files" | "/api/history" | "/api/verify") => {
+                Ok(Response::error(405, "Method not allowed"))
+            }
+            _ => Ok(Response::error(404, "Not found")),
+        };
+        result.unwrap_or_else(|e| match e.root() {
+            DdriveError::Validation { .. } | DdriveError::GlobPattern(_) => {
+                Response::failed(400, &e)
+            }
+            _ => {
+                warn!(
+                    "API request {} {} failed: {e}",
+                    request.method, request.path
+                );
+                Response::failed(500, &e)
+            }
+        })
+    }
+
+    fn authorized(&self, request: &Request) -> bool {
+        request
+            .authorization
+            .as_deref()
+            .and_then(|value| value.strip_prefix("Bearer "))
+            // blake3::Hash compares in constant time
+            .is_some_and(|token| blake3::hash(token.trim().as_bytes()) == self.token)
+    }
+
+    async fn status(&self) -> Result<Response> {
+        let stats = StatusCommand::new(&self.context).gather_stats().await?;
+        Ok(Response::ok(json!({
+            "tracked_files": stats.tracked_files,
+            "total_tracked_size": stats.total_tracked_size,
+            "untracked_files": stats.untracked_files,
+            "total_untracked_size": stats.total_untracked_size,
+            "duplicate_groups": stats.duplicate_groups,
+            "duplicate_files": stats.duplicate_files,
+            "wasted_space": stats.wasted_space,
+            "files_needing_check": stats.files_needing_check,
+            "newest_tracked": stats.newest_tracked,
+            "new_files": stats.new_files,
+            "deleted_files": stats.deleted_files,
+            "renamed_files": stats.renamed_files,
+            "moved_directories": stats.moved_directories,
+            "updated_files": stats.updated_files,
+            "unresolved_failures": stats.unresolved_failures,
+            "unreadable_files": stats.unreadable_files,
+            "object_store_size": stats.object_store_size,
+            "object_store_extra": stats.object_store_extra,
+            "reflinked": stats.reflinked,
+            "stored_objects": stats.stored_objects,
+            "missing_objects": stats.missing_objects,
+            "orphaned_objects": stats.orphaned_objects,
+            "devices": stats.devices.iter().map(|device| serde_json::json!({
+                "dev": device.dev,
+                "mount_point": device.mount_point,
+                "fs_type": device.fs_type,
+                "uuid": device.uuid,
+                "files": device.files,
+                "size": device.size,
+                "failures": device.failures,
+            })).collect::<Vec<_>>(),
+        })))
+    }
+
+    async fn files(&self, query: &HashMap<String, String>) -> Result<Response> {
+        let prefix = query.get("prefix").map_or("", String::as_str);
+        let offset = match query.get("offset") {
+            Some(offset) => offset.parse().map_err(|_| DdriveError::Validation {
+                message: format!("Invalid offset: {offset}"),
+            })?,
+            None => 0,
+        };
+        let files = self
+            .context
+            .database
+            .get_files_page(prefix, parse_limit(query)?, offset)
+            .await?;
+        let files: Vec<Value> = files.iter().map(file_json).collect();
+        Ok(Response::ok(json!({ "files": files })))
+    }
+
+    async fn history(&self, query: &HashMap<String, String>) -> Result<Response> {
+        let action_type = query
+            .get("type")
+            .map(|value| {
+                value
+                    .parse::<ActionType>()
+                    .map_err(|_| DdriveError::Validation {
+                        message: format!("Unknown action type: {value}"),
+                    })
+            })
+            .transpose()?;
+        let records = self
+            .context
+            .database
+            .get_history_entries(Some(parse_limit(query)?), action_type)
+            .await?;
+        let entries: Vec<Value> = records
+            .iter()
+            .map(|record| {
+                json!({
+                    "action_id": record.action_id_base58(),
+                    "action_type": record.action_type_enum(),
+                    "timestamp": record.action_timestamp(),
+                    "path": record.path,
+                    "b3sum": record.b3sum,
+                    "size": record.size,
+                })
+            })
+            .collect();
+        Ok(Response::ok(json!({ "history": entries })))
+    }
+
+    async fn verify_results(&self) -> Result<Response> {
+        let failures = self
+            .context
+            .database
+            .get_unresolved_verification_failures()
+            .await?;
+        let failures: Vec<Value> = failures
+            .iter()
+            .map(|failure| {
+                json!({
+                    "path": failure.path,
+                    "algorithm": failure.algorithm,
+                    "expected_checksum": failure.expected_checksum,
+                    "actual_checksum": failure.actual_checksum,
+                    "detected_at": failure.detected_at,
+                    "last_detected_at": failure.last_detected_at,
+                })
+            })
+            .collect();
+
+        let job = self.verify.lock().await;
+        Ok(Response::ok(json!({
+            "running": job.running,
+            "last_run": job.last_result,
+            "unresolved_failures": failures,
+        })))
+    }
+
+    /// Start a verify run in the background; `?force=true` checks every file
+    async fn trigger_verify(self: &Arc<Self>, query: &HashMap<String, String>) -> Result<Response> {
+        let force = query.get("force").is_some_and(|v| v == "true" || v == "1");
+        let path = query
+            .get("path")
+            .map(|path| path.parse::<PathFilter>())
+            .transpose()?;
+        let since = query
+            .get("since")
+            .map(|since| utils::parse_date(since))
+            .transpose()?;
+
+        {
+            let mut job = self.verify.lock().await;
+            if job.running {
+                return Ok(Response::error(409, "A verify run is already in progress"));
+            }
+            job.running = true;
+        }
+
+        let state = Arc::clone(self);
+        tokio::spawn(async move {
+            let result = VerifyCommand::new(&state.context)
+                .execute(path.as_ref(), since, force, HashAlgorithm::Blake3, false)
+                .await;
+            let summary = match result {
+                Ok(result) => json!({
+                    "finished_at": chrono::Utc::now(),
+                    "checked_files": result.checked_files,
+                    "passed_files": result.passed_files,
+                    "modified_files": result.modified_files,
+                    "failed_files": result.failed_files,
+                    "skipped_files": result.skipped_files,
+                }),
+                Err(e) => json!({
+                    "finished_at": chrono::Utc::now(),
+                    "error": e.to_string(),
+                    "code": e.code().id,
+                }),
+            };
+            let mut job = state.verify.lock().await;
+            job.running = false;
+            job.last_result = Some(summary);
+        });
+
+        Ok(Response {
+            status: 202,
+            body: json!({ "started": true }),
+        })
+    }
+}
+
+fn parse_limit(query: &HashMap<String, String>) -> Result<usize> {
+    match query.get("limit") {
+        Some(limit) => limit.parse().map_err(|_| DdriveError::Validation {
+            message: format!("Invalid limit: {limit}"),
+        }),
+        None => Ok(100),
+    }
+}
+
+fn file_json(record: &FileRecord) -> Value {
+    json!({
+        "path": record.path,
+        "size": record.size,
+        "b3sum": record.b3sum,
+        "updated_at": record.updated_at,
+        "last_checked": record.last_checked,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{AddOptions, api::testing::configured_repository};
+
+    #[test]
+    fn test_parse_request() {
+        let request = parse_request(
+            "GET /api/files?prefix=my%20photos&limit=5 HTTP/1.1\r\nHost: x\r\nauthorization: Bearer abc\r\n\r\n",
+        )
+        .unwrap();
+        assert_eq!(request.method, "GET");
+        assert_eq!(request.path, "/api/files");
+        assert_eq!(request.query["prefix"], "my photos");
+        assert_eq!(request.query["limit"], "5");
+        assert_eq!(request.authorization.as_deref(), Some("Bearer abc"));
+
+        assert!(parse_request("garbage\r\n\r\n").is_none());
+        assert_eq!(percent_decode("100%"), "100%");
+        assert_eq!(percent_decode("%e2%9c%93"), "✓");
+    }
+
+    #[tokio::test]
+    async fn test_slow_clients_time_out() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap())
+            .await
+            .unwrap();
+        // Part of a request head, then nothing
+        client
+            .write_all(b"GET /api/status HTTP/1.1\r\n")
+            .await
+            .unwrap();
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let response = receive(&mut stream, Duration::from_millis(50))
+            .await
+            .unwrap()
+            .err()
+            .unwrap();
+        assert_eq!(response.status, 408);
+    }
+
+    #[tokio::test]
+    async fn test_files_are_paged() {
+        let (temp, ddrive) = configured_repository(
+            &[
+                ("docs/a.txt", "a"),
+                ("docs/b.txt", "b"),
+                ("docs/c.txt", "c"),
+                ("e.txt", "e"),
+            ],
+            |config| config.serve.token = Some("secret".to_string()),
+        )
+        .await;
+        ddrive
+            .add(&[temp.path()], &AddOptions::default())
+            .await
+            .unwrap();
+        let server = ServeCommand::new(ddrive.context()).unwrap();
+        let files = |query: &[(&str, &str)]| {
+            let query = query
+                .iter()
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect();
+            let server = &server;
+            async move {
+                let response = server.state.files(&query).await.unwrap();
+                response.body["files"]
+                    .as_array()
+                    .unwrap()
+                    .iter()
+                    .map(|file| file["path"].as_str().unwrap().to_string())
+                    .collect::<Vec<_>>()
+            }
+        };
+
+        assert_eq!(files(&[("limit", "2")]).await, ["docs/a.txt", "docs/b.txt"]);
+        assert_eq!(
+            files(&[("limit", "2"), ("offset", "2")]).await,
+            ["docs/c.txt", "e.txt"]
+        );
+        assert_eq!(
+            files(&[("prefix", "docs/"), ("offset", "1")]).await,
+            ["docs/b.txt", "docs/c.txt"]
+        );
+        assert!(
+            server
+                .state
+                .files(&HashMap::from([("offset".to_string(), "-1".to_string())]))
+                .await
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_generate_token() {
+        let token = generate_token().unwrap();
+        assert_eq!(bs58::decode(&token).into_vec().unwrap().len(), 24);
+        assert_ne!(token, generate_token().unwrap());
+    }
+}
@@ -0,0 +1,562 @@
+use crate::{
+    AppContext, Result,
+    cli::ls::ListCommand,
+    database::{ActionType, DeviceRecord, FileProfile},
+    utils::{format_count, shorten_path},
+};
+use chrono::{DateTime, Utc};
+use std::collections::HashMap;
+use std::path::Path;
+use tracing::info;
+
+/// Name shown for files directly in the repository root
+const ROOT_DIRECTORY: &str = ".";
+
+/// Name shown for files without an extension
+const NO_EXTENSION: &str = "(none)";
+
+/// Name shown for files whose content type could not be detected
+const UNKNOWN_TYPE: &str = "(unknown)";
+
+/// Name shown when the age, size or device of a file is not known
+const UNKNOWN: &str = "unknown";
+
+/// File age buckets by their upper bound in days
+const AGE_BUCKETS: &[(&str, i64)] = &[
+    ("under 1 month", 30),
+    ("1-6 months", 182),
+    ("6-12 months", 365),
+    ("1-3 years", 3 * 365),
+    ("over 3 years", i64::MAX),
+];
+
+/// File size buckets by their upper bound in bytes
+const SIZE_BUCKETS: &[(&str, i64)] = &[
+    ("under 1 MB", 1 << 20),
+    ("1-100 MB", 100 << 20),
+    ("100 MB-1 GB", 1 << 30),
+    ("over 1 GB", i64::MAX),
+];
+
+/// Fewest failures in one directory or device to call them a cluster
+const MIN_CLUSTER_FAILURES: usize = 3;
+
+/// How often the files of one directory or file type changed
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Churn {
+    /// Directory relative to the repository root, or file extension
+    pub name: String,
+    pub added: usize,
+    pub updated: usize,
+    pub deleted: usize,
+    pub renamed: usize,
+    /// Size of the new versions recorded for updated files
+    pub rewritten_bytes: u64,
+}
+
+impl Churn {
+    pub fn changes(&self) -> usize {
+        self.added + self.updated + self.deleted + self.renamed
+    }
+}
+
+/// Directories and file types by how often their files changed, most first
+#[derive(Debug, Default)]
+pub struct ChurnReport {
+    pub directories: Vec<Churn>,
+    pub extensions: Vec<Churn>,
+}
+
+/// Tracked files of one content type
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct TypeStats {
+    /// MIME type detected from the content
+    pub content_type: String,
+    pub files: usize,
+    pub bytes: u64,
+}
+
+/// Integrity failures among the files of one age, size, directory, device
+/// or content type
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct FailureGroup {
+    pub name: String,
+    /// Failures detected in files of the group
+    pub failures: usize,
+    /// Tracked files in the group now
+    pub files: usize,
+}
+
+impl FailureGroup {
+    /// Failures per tracked file, if the group has any files
+    pub fn rate(&self) -> Option<f64> {
+        (self.files > 0).then(|| self.failures as f64 / self.files as f64)
+    }
+}
+
+/// Integrity failures from the whole verification history, grouped by the
+/// properties of the files they were detected in
+#[derive(Debug, Default)]
+pub struct CorruptionReport {
+    pub failures: usize,
+    pub files: usize,
+    pub by_age: Vec<FailureGroup>,
+    pub by_size: Vec<FailureGroup>,
+    pub by_directory: Vec<FailureGroup>,
+    pub by_device: Vec<FailureGroup>,
+    pub by_type: Vec<FailureGroup>,
+}
+
+impl CorruptionReport {
+    /// The device or directory holding at least half of the failures while
+    /// holding at most a quarter of the files, which points at a bad disk
+    /// or disk region rather than random bit rot
+    pub fn cluster(&self) -> Option<(&'static str, &FailureGroup)> {
+        let clustered = |group: &&FailureGroup| {
+            let failure_share = group.failures as f64 / self.failures.max(1) as f64;
+            let file_share = group.files as f64 / self.files.max(1) as f64;
+            group.failures >= MIN_CLUSTER_FAILURES
+                && failure_share >= 0.5
+                && failure_share >= 2.0 * file_share
+        };
+        self.by_device
+            .iter()
+            .find(clustered)
+            .map(|group| ("device", group))
+            .or_else(|| {
+                self.by_directory
+                    .iter()
+                    .find(clustered)
+                    .map(|group| ("directory", group))
+            })
+    }
+}
+
+pub struct StatsCommand<'a> {
+    context: &'a AppContext,
+}
+
+impl<'a> StatsCommand<'a> {
+    pub fn new(context: &'a AppContext) -> Self {
+        Self { context }
+    }
+
+    /// Show the `limit` directories and file types whose files changed most
+    /// often since `since` (or ever), to help tune ignore rules and
+    /// verification intervals
+    pub async fn churn(&self, since: Option<DateTime<Utc>>, limit: usize) -> Result<ChurnReport> {
+        let report = self.gather_churn(since).await?;
+        if report.directories.is_empty() {
+            info!("No changes recorded in history");
+            return Ok(report);
+        }
+
+        match since {
+            Some(since) => info!("Changes since {}", since.format("%Y-%m-%d %H:%M")),
+            None => info!("Changes in the whole history"),
+        }
+        info!("Directories:");
+        self.display(&report.directories, limit);
+        info!("File types:");
+        self.display(&report.extensions, limit);
+        Ok(report)
+    }
+
+    /// Aggregate the history recorded since `since` per directory and per
+    /// file extension
+    pub async fn gather_churn(&self, since: Option<DateTime<Utc>>) -> Result<ChurnReport> {
+        let since = since.map(|since| since.timestamp());
+        let changes = self.context.database.get_file_changes_since(since).await?;
+
+        let mut directories: HashMap<String, Churn> = HashMap::new();
+        let mut extensions: HashMap<String, Churn> = HashMap::new();
+        for change in &changes {
+            let action_type = change.action_type_enum();
+            if !matches!(
+                action_type,
+                ActionType::Add | ActionType::Update | ActionType::Delete | ActionType::Rename
+            ) {
+                continue;
+            }
+            let path = Path::new(&change.path);
+            let directory = path
+                .parent()
+                .map(|parent| parent.to_string_lossy().into_owned())
+                .filter(|parent| !parent.is_empty())
+                .unwrap_or_else(|| ROOT_DIRECTORY.to_string());
+            let extension = path
+                .extension()
+                .map(|extension| extension.to_string_lossy().to_lowercase())
+                .unwrap_or_else(|| NO_EXTENSION.to_string());
+
+            for (groups, name) in [(&mut directories, directory), (&mut extensions, extension)] {
+                let churn = groups.entry(name.clone()).or_insert_with(|| Churn {
+                    name,
+                    ..Default::default()
+                });
+                match action_type {
+                    ActionType::Add => churn.added += 1,
+                    ActionType::Update => {
+                        churn.updated += 1;
+                        churn.rewritten_bytes += change.size.unwrap_or_default() as u64;
+                    }
+                    ActionType::Delete => churn.deleted += 1,
+                    _ => churn.renamed += 1,
+                }
+            }
+        }
+
+        Ok(ChurnReport {
+            directories: most_changed(directories),
+            extensions: most_changed(extensions),
+        })
+    }
+
+    /// Show how many tracked files and bytes there are of each content type,
+    /// the `limit` largest types first
+    pub async fn types(&self, limit: usize) -> Result<Vec<TypeStats>> {
+        let files = ListCommand::new(self.context)
+            .typed_files(self.context.repo.root())
+            .await?;
+        let mut types: HashMap<String, TypeStats> = HashMap::new();
+        for file in &files {
+            let content_type = file.content_type.as_deref().unwrap_or(UNKNOWN_TYPE);
+            let stats = types
+                .entry(content_type.to_string())
+                .or_insert_with(|| TypeStats {
+                    content_type: content_type.to_string(),
+                    ..Default::default()
+                });
+            stats.files += 1;
+            stats.bytes += file.size as u64;
+        }
+        let mut types: Vec<TypeStats> = types.into_values().collect();
+        types.sort_by(|a, b| {
+            b.bytes
+                .cmp(&a.bytes)
+                .then(b.files.cmp(&a.files))
+                .then_with(|| a.content_type.cmp(&b.content_type))
+        });
+
+        if types.is_empty() {
+            info!("No tracked files");
+            return Ok(types);
+        }
+        let symbols = self.context.symbols();
+        for stats in types.iter().take(limit) {
+            let content_type = Some(stats.content_type.as_str()).filter(|t| *t != UNKNOWN_TYPE);
+            info!(
+                "  {}{}: {} files ({})",
+                symbols.type_icon(content_type, ""),
+                stats.content_type,
+                format_count(stats.files),
+                self.context.format_size(stats.bytes)
+            );
+        }
+        if types.len() > limit {
+            info!("  and {} more...", types.len() - limit);
+        }
+        Ok(types)
+    }
+
+    /// Show how the integrity failures ever detected by verify spread over
+    /// file age, size, directory, device and content type, compared with the
+    /// tracked files, so that failures clustering on one disk or region stand
+    /// out from random bit rot
+    pub async fn corruption(&self, limit: usize) -> Result<CorruptionReport> {
+        let report = self.gather_corruption().await?;
+        if report.failures == 0 {
+            info!("No integrity failures recorded");
+            return Ok(report);
+        }
+
+        info!(
+            "{} integrity failures detected, {} files tracked",
+            format_count(report.failures),
+            format_count(report.files)
+        );
+        for (title, groups) in [
+            ("By file age:", &report.by_age),
+            ("By file size:", &report.by_size),
+            ("By directory:", &report.by_directory),
+            ("By device:", &report.by_device),
+            ("By content type:", &report.by_type),
+        ] {
+            info!("{}", title);
+            for group in groups.iter().take(limit) {
+                let rate = group
+                    .rate()
+                    .map(|rate| format!(" ({:.2}%)", rate * 100.0))
+                    .unwrap_or_default();
+                info!(
+                    "  {}: {} failures in {} files{}",
+                    shorten_path(&group.name, 50),
+                    format_count(group.failures),
+                    format_count(group.files),
+                    rate
+                );
+            }
+            if groups.len() > limit {
+                info!("  and {} more...", groups.len() - limit);
+            }
+        }
+
+        let symbols = self.context.symbols();
+        match report.cluster() {
+            Some((kind, group)) => info!(
+                "{} Failures cluster on {} {}: check the disk (SMART status, bad sectors) and restore the files from stored copies",
+                symbols.warning(),
+                kind,
+                group.name
+            ),
+            None => info!("Failures are spread out, as expected of random bit rot"),
+        }
+        Ok(report)
+    }
+
+    /// Group the recorded integrity failures and the tracked files by age,
+    /// size, directory, device and content type
+    pub async fn gather_corruption(&self) -> Result<CorruptionReport> {
+        let database = &self.context.database;
+        let failures = database.get_failure_profiles().await?;
+        let files = database.get_file_profiles().await?;
+        let devices = database.get_devices().await?;
+
+        // Failures and tracked files per group, for each of the five groupings
+        let mut counts: [HashMap<String, FailureGroup>; 5] = Default::default();
+        for (profile, failed) in files
+            .iter()
+            .map(|file| (file, false))
+            .chain(failures.iter().map(|failure| (failure, true)))
+        {
+            for (groups, name) in counts.iter_mut().zip(group_names(profile, &devices)) {
+                let group = groups.entry(name.clone()).or_insert_with(|| FailureGroup {
+                    name,
+                    ..Default::default()
+                });
+                if failed {
+                    group.failures += 1;
+                } else {
+                    group.files += 1;
+                }
+            }
+        }
+
+        let [by_age, by_size, by_directory, by_device, by_type] = counts;
+        Ok(CorruptionReport {
+            failures: failures.len(),
+            files: files.len(),
+            by_age: in_bucket_order(by_age, AGE_BUCKETS),
+            by_size: in_bucket_order(by_size, SIZE_BUCKETS),
+            by_directory: most_failed(by_directory),
+            by_device: most_failed(by_device),
+            by_type: most_failed(by_type),
+        })
+    }
+
+    fn display(&self, churn: &[Churn], limit: usize) {
+        for group in churn.iter().take(limit) {
+            let parts: Vec<String> = [
+                (group.added, "added"),
+                (group.updated, "updated"),
+                (group.deleted, "deleted"),
+                (group.renamed, "renamed"),
+            ]
+            .into_iter()
+            .filter(|(count, _)| *count > 0)
+            .map(|(count, label)| format!("{} {}", format_count(count), label))
+            .collect();
+            let rewritten = if group.rewritten_bytes > 0 {
+                format!(
+                    ", {} rewritten",
+                    self.context.format_size(group.rewritten_bytes)
+                )
+            } else {
+                String::new()
+            };
+            info!(
+                "  {}: {} changes ({}){}",
+                shorten_path(&group.name, 50),
+                format_count(group.changes()),
+                parts.join(", "),
+                rewritten
+            );
+        }
+        if churn.len() > limit {
+            info!("  and {} more...", churn.len() - limit);
+        }
+    }
+}
+
+/// The groups ordered by number of changes, then by rewritten bytes
+fn most_changed(groups: HashMap<String, Churn>) -> Vec<Churn> {
+    let mut groups: Vec<Churn> = groups.into_values().collect();
+    groups.sort_by(|a, b| {
+        b.changes()
+            .cmp(&a.changes())
+            .then(b.rewritten_bytes.cmp(&a.rewritten_bytes))
+            .then_with(|| a.name.cmp(&b.name))
+    });
+    groups
+}
+
+/// Names of the age, size, directory, device and content type groups a file
+/// belongs to
+fn group_names(profile: &FileProfile, devices: &HashMap<i64, DeviceRecord>) -> [String; 5] {
+    let age = profile
+        .age()
+        .map(|age| bucket(AGE_BUCKETS, age.num_days()))
+        .unwrap_or(UNKNOWN);
+    let size = profile
+        .size
+        .map(|size| bucket(SIZE_BUCKETS, size))
+        .unwrap_or(UNKNOWN);
+    let path = Path::new(&profile.path);
+    let directory = path
+        .parent()
+        .map(|parent| parent.to_string_lossy().into_owned())
+        .filter(|parent| !parent.is_empty())
+        .unwrap_or_else(|| ROOT_DIRECTORY.to_string());
+    let device = match profile.dev {
+        Some(dev) => devices
+            .get(&dev)
+            .map(|device| device.label())
+            .unwrap_or_else(|| format!("device {}", dev)),
+        None => UNKNOWN.to_string(),
+    };
+    // Files recorded before content types were detected go by extension
+    let content_type = match (&profile.content_type, path.extension()) {
+        (Some(content_type), _) => content_type.clone(),
+        (None, Some(extension)) => format!(".{}", extension.to_string_lossy().to_lowercase()),
+        (None, None) => UNKNOWN_TYPE.to_string(),
+    };
+    [
+        age.to_string(),
+        size.to_string(),
+        directory,
+        device,
+        content_type,
+    ]
+}
+
+/// Label of the first bucket whose upper bound is above `value`
+fn bucket(buckets: &[(&'static str, i64)], value: i64) -> &'static str {
+    buckets
+        .iter()
+        .find(|(_, bound)| value < *bound)
+        .map_or(UNKNOWN, |(label, _)| label)
+}
+
+/// The groups in bucket order, followed by the unknown group
+fn in_bucket_order(
+    groups: HashMap<String, FailureGroup>,
+    buckets: &[(&str, i64)],
+) -> Vec<FailureGroup> {
+    let mut groups: Vec<FailureGroup> = groups.into_values().collect();
+    groups.sort_by_key(|group| {
+        buckets
+            .iter()
+            .position(|(label, _)| *label == group.name)
+            .unwrap_or(buckets.len())
+    });
+    groups
+}
+
+/// The groups with failures, most first, then by highest failure rate
+fn most_failed(groups: HashMap<String, FailureGroup>) -> Vec<FailureGroup> {
+    let mut groups: Vec<FailureGroup> = groups
+        .into_values()
+        .filter(|group| group.failures > 0)
+        .collect();
+    groups.sort_by(|a, b| {
+        b.failures
+            .cmp(&a.failures)
+            .then(a.files.cmp(&b.files))
+            .then_with(|| a.name.cmp(&b.name))
+    });
+    groups
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{AddOptions, api::testing::repository};
+    use assert_fs::prelude::*;
+
+    #[tokio::test]
+    async fn test_churn_per_directory_and_extension() {
+        let (temp, ddrive) =
+            repository(&[("docs/a.txt", "a"), ("docs/b.md", "b"), ("c.txt", "c")]).await;
+        ddrive
+            .add(&[temp.path()], &AddOptions::default())
+            .await
+            .unwrap();
+        temp.child("docs/a.txt").write_str("changed").unwrap();
+        ddrive
+            .add(&[temp.path()], &AddOptions::default())
+            .await
+            .unwrap();
+
+        let report = StatsCommand::new(ddrive.context())
+            .gather_churn(None)
+            .await
+            .unwrap();
+        let docs = &report.directories[0];
+        assert_eq!(
+            (
+                docs.name.as_str(),
+                docs.added,
+                docs.updated,
+                docs.rewritten_bytes
+            ),
+            ("docs", 2, 1, 7)
+        );
+        assert_eq!(report.directories[1].name, ".");
+        let txt = &report.extensions[0];
+        assert_eq!((txt.name.as_str(), txt.changes()), ("txt", 3));
+    }
+
+    #[tokio::test]
+    async fn test_corruption_clusters_by_directory() {
+        use crate::checksum::HashAlgorithm;
+
+        let (temp, ddrive) = repository(&[]).await;
+        for i in 0..3 {
+            temp.child(format!("bad/{i}.jpg")).write_str("bad").unwrap();
+        }
+        for i in 0..9 {
+            temp.child(format!("good/{i}.txt")).write_str("ok").unwrap();
+        }
+        ddrive
+            .add(&[temp.path()], &AddOptions::default())
+            .await
+            .unwrap();
+        let database = &ddrive.context().database;
+        for i in 0..3 {
+            database
+                .record_verification_failure(
+                    &format!("bad/{i}.jpg"),
+                    HashAlgorithm::Blake3,
+                    "expected",
+                    "actual",
+                )
+                .await
+                .unwrap();
+        }
+
+        let report = StatsCommand::new(ddrive.context())
+            .gather_corruption()
+            .await
+            .unwrap();
+        assert_eq!((report.failures, report.files), (3, 12));
+        assert_eq!(report.by_directory.len(), 1);
+        assert_eq!(report.by_age[0].name, "under 1 month");
+        assert_eq!(
+            (report.by_size[0].failures, report.by_size[0].files),
+            (3, 12)
+        );
+        let (kind, group) = report.cluster().unwrap();
+        assert_eq!((kind, group.name.as_str()), ("directory", "bad"));
+        assert_eq!(group.rate(), Some(1.0));
+    }
+}
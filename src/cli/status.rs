@@ -1,12 +1,34 @@
 use crate::{
-    AppContext, Result,
-    utils::{display_directory_listing, format_size, group_files_by_directory},
+    AppContext, DdriveError, Result,
+    cli::add::FailureKind,
+    codes,
+    database::FileRecord,
+    database::{DeviceStats, RepositoryTotals},
+    scanner::{SkipReason, WalkFilter},
+    tr,
+    utils::{
+        DirectoryGrouping, FileChange, RenameConfidence, TrackedDirectories,
+        display_directory_listing, display_directory_tree, find_directory_moves, format_count,
+        group_files, to_storage_path,
+    },
 };
-use std::collections::HashMap;
-use tracing::info;
+use futures_util::stream;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::path::Path;
+use tracing::{info, warn};
 
 pub struct StatusCommand<'a> {
     context: &'a AppContext,
+    listing: StatusListing,
+}
+
+/// How `status` lists the new, updated and deleted files
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StatusListing {
+    pub grouping: DirectoryGrouping,
+    /// Show a tree of directories with the files and bytes below each,
+    /// instead of sample files per directory
+    pub tree: bool,
 }
 
 #[derive(Debug)]
@@ -22,13 +44,47 @@ pub struct RepositoryStats {
     pub newest_tracked: Option<chrono::NaiveDateTime>,
     pub new_files: Vec<String>,
     pub deleted_files: Vec<String>,
-    pub renamed_files: Vec<(String, String)>, // (old_path, new_path)
+    pub renamed_files: Vec<(String, String, RenameConfidence)>, // (old_path, new_path, confidence)
+    /// Directories that moved with all their files, which are not listed in
+    /// `renamed_files`
+    pub moved_directories: Vec<(String, String, usize)>, // (old_path, new_path, files)
     pub updated_files: Vec<String>, // Files with metadata changes (size/modification time)
+    /// Sizes of the new, updated and deleted files, for per-directory totals
+    pub file_sizes: HashMap<String, u64>,
+    /// Files with integrity failures that have not been resolved
+    pub unresolved_failures: Vec<String>,
+    /// Files and folders that could not be read for lack of permission, as
+    /// found by this scan or recorded by earlier adds
+    pub unreadable_files: Vec<String>,
+    /// Bytes held in the object store
+    pub object_store_size: u64,
+    /// Estimated disk space the object store takes beyond the tracked files:
+    /// all of it for full copies, only past versions for reflinked objects
+    pub object_store_extra: u64,
+    /// Whether stored objects share blocks with the tracked files
+    pub reflinked: bool,
+    /// Objects held by the object store, including the cold store
+    pub stored_objects: usize,
+    /// Tracked files recorded as stored whose object is not in the store
+    pub missing_objects: Vec<String>,
+    /// Objects in the store that no file record or history entry references
+    pub orphaned_objects: usize,
+    /// Tracked files and unresolved failures per device
+    pub devices: Vec<DeviceStats>,
 }
 
 impl<'a> StatusCommand<'a> {
     pub fn new(context: &'a AppContext) -> Self {
-        Self { context }
+        Self {
+            context,
+            listing: StatusListing::default(),
+        }
+    }
+
+    /// List changed files as `listing` says
+    pub fn listing(mut self, listing: StatusListing) -> Self {
+        self.listing = listing;
+        self
     }
 
     pub async fn execute(&self) -> Result<RepositoryStats> {
@@ -37,57 +93,236 @@ impl<'a> StatusCommand<'a> {
         Ok(stats)
     }
 
-    async fn gather_stats(&self) -> Result<RepositoryStats> {
+    /// Whether the repository is large enough for `status` to show the
+    /// cached totals by default
+    pub async fn prefers_fast(&self) -> Result<bool> {
+        let totals = self.context.database.get_totals().await?;
+        Ok(totals.tracked_files as u64 >= self.context.config.status.fast_threshold)
+    }
+
+    /// Show the cached totals, without scanning the tree or loading records
+    pub async fn execute_fast(&self) -> Result<RepositoryTotals> {
+        let totals = self.context.database.get_totals().await?;
+        info!("Protected files:");
+        info!(
+            "  {} files ({})",
+            totals.tracked_files,
+            self.context.format_size(totals.tracked_size as u64)
+        );
+        if let Some(last_action) = totals
+            .last_action_id
+            .and_then(|action_id| chrono::DateTime::from_timestamp(action_id, 0))
+        {
+            info!(
+                "  Last change: {}",
+                last_action.format("%B %d, %Y at %H:%M")
+            );
+        }
+        info!("");
+        info!("Run 'ddrive status --full' to look for new, changed and deleted files");
+        Ok(totals)
+    }
+
+    /// List the files that are not tracked, `per_page` at a time, and
+    /// return the listed page with the total number of untracked files
+    pub async fn untracked(&self, page: usize, per_page: usize) -> Result<(Vec<String>, usize)> {
+        let scanner = crate::scanner::FileScanner::with_config(
+            self.context.repo.root().clone(),
+            self.context.config.scan.clone(),
+        );
+        let tracked: HashSet<String> = self
+            .context
+            .database
+            .get_tracked_file_paths()
+            .await?
+            .into_iter()
+            .map(|file| file.path)
+            .collect();
+        let mut untracked: Vec<(String, u64)> = scanner
+            .scan(self.context.repo.root())?
+            .files
+            .into_iter()
+            .map(|file| (to_storage_path(&file.path), file.size))
+            .filter(|(path, _)| !tracked.contains(path))
+            .collect();
+        untracked.sort();
+
+        let total = untracked.len();
+        if total == 0 {
+            info!("All files are protected");
+            return Ok((Vec::new(), 0));
+        }
+        let per_page = per_page.max(1);
+        let pages = total.div_ceil(per_page);
+        let page = page.clamp(1, pages);
+        let start = (page - 1) * per_page;
+        let listed: Vec<_> = untracked.into_iter().skip(start).take(per_page).collect();
+
+        info!(
+            "Files not yet protected ({}-{} of {}, page {} of {}):",
+            start + 1,
+            start + listed.len(),
+            total,
+            page,
+            pages
+        );
+        for (path, size) in &listed {
+            info!("  {} ({})", path, self.context.format_size(*size));
+        }
+        if page < pages {
+            info!(
+                "Run 'ddrive status untracked --page {} --per-page {}' for more",
+                page + 1,
+                per_page
+            );
+        }
+        info!("Run 'ddrive status untracked --why <path>' to see why a file is skipped");
+        Ok((listed.into_iter().map(|(path, _)| path).collect(), total))
+    }
+
+    /// Explain whether `path` is protected and, if it is not, why `add`
+    /// skips it. Returns the reason a scan skips it, if any.
+    pub async fn explain(&self, path: &Path) -> Result<Option<SkipReason>> {
+        let repo_root = self.context.repo.root().canonicalize()?;
+        let path = path.canonicalize()?;
+        let relative = path
+            .strip_prefix(&repo_root)
+            .map_err(|_| DdriveError::Validation {
+                message: format!("{} is not inside the repository", path.display()),
+            })?;
+        let storage_path = to_storage_path(relative);
+
+        if path.is_file()
+            && self
+                .context
+                .database
+                .get_file_by_path(&storage_path)
+                .await?
+                .is_some()
+        {
+            info!("{} is protected", storage_path);
+            return Ok(None);
+        }
+
+        let scanner =
+            crate::scanner::FileScanner::with_config(repo_root, self.context.config.scan.clone());
+        let reason = scanner.skip_reason(&path)?;
+        match &reason {
+            Some(reason) => info!("{} is skipped: {}", storage_path, reason),
+            None => {
+                info!("{} is not ignored", storage_path);
+                if !self.context.config.track.policy()?.allows(&storage_path) {
+                    info!("  'ddrive add --auto' skips it: the [track] globs do not include it");
+                }
+                info!("  Run 'ddrive add {}' to protect it", storage_path);
+            }
+        }
+        Ok(reason)
+    }
+
+    /// Collect the statistics without displaying them
+    pub async fn gather_stats(&self) -> Result<RepositoryStats> {
         // Get lightweight tracked file info for status
         let tracked_files = self.context.database.get_tracked_file_paths().await?;
         let (tracked_count, total_tracked_size, newest_tracked) =
             self.analyze_tracked_file_info(&tracked_files);
 
         let files_needing_check = self.context.database.get_files_for_check().await?.len();
+        let unresolved_failures = self
+            .context
+            .database
+            .get_unresolved_verification_failures()
+            .await?
+            .into_iter()
+            .map(|failure| failure.path)
+            .collect();
+        let devices = self.context.database.get_device_stats().await?;
 
         // Get all file paths from the filesystem (lightweight scan)
-        let scanner = crate::scanner::FileScanner::new(self.context.repo.root().clone());
-        let all_files = scanner.get_all_files(self.context.repo.root())?;
+        let scanner = crate::scanner::FileScanner::with_config(
+            self.context.repo.root().clone(),
+            self.context.config.scan.clone(),
+        );
+        let scan = scanner.scan(self.context.repo.root())?;
+        let permission_denied = FailureKind::PermissionDenied.to_string();
+        let unreadable_files: BTreeSet<String> = self
+            .context
+            .database
+            .get_problems()
+            .await?
+            .into_iter()
+            .filter(|problem| problem.kind == permission_denied)
+            .map(|problem| problem.path)
+            .chain(
+                scan.unreadable
+                    .iter()
+                    .map(|entry| to_storage_path(&entry.path)),
+            )
+            .collect();
+        let all_files = scan.files;
 
         // Get full tracked file records for change detection
         let tracked_file_records = self.context.database.get_all_files().await?;
+        let current: HashSet<&str> = tracked_file_records
+            .iter()
+            .map(|file| file.b3sum.as_str())
+            .collect();
+        let (object_store_size, object_store_extra, reflinked) =
+            self.object_store_usage(&current)?;
+        drop(current);
+        let (stored_objects, missing_objects, orphaned_objects) =
+            self.object_store_health(&tracked_file_records).await?;
 
         // Use lightweight change detection to find new, deleted, and renamed files
         let processor = crate::utils::FileProcessor::new(self.context);
-        let (new_files, changed_files, deleted_files, renames) = processor
-            .detect_changes(&all_files, &tracked_file_records, false)
+        let mut new_files_paths = Vec::new();
+        let mut total_untracked_size = 0u64;
+        let mut deleted_files = Vec::new();
+        let mut renamed_files = Vec::new();
+        let mut updated_files = Vec::new();
+        let mut file_sizes = HashMap::new();
+        processor
+            .detect_changes(
+                &all_files,
+                stream::iter(tracked_file_records.iter().map(Ok)),
+                false,
+                |change| match change {
+                    FileChange::New(file) => {
+                        total_untracked_size += file.size;
+                        let path = file.path.to_string_lossy().into_owned();
+                        file_sizes.insert(path.clone(), file.size);
+                        new_files_paths.push(path);
+                    }
+                    FileChange::Changed { file, .. } => {
+                        let path = file.path.to_string_lossy().into_owned();
+                        file_sizes.insert(path.clone(), file.size);
+                        updated_files.push(path);
+                    }
+                    FileChange::Deleted(file) => {
+                        let path = file.path.to_string_lossy().into_owned();
+                        file_sizes.insert(path.clone(), file.size);
+                        deleted_files.push(path);
+                    }
+                    FileChange::Renamed {
+                        from,
+                        to,
+                        confidence,
+                        ..
+                    } => renamed_files.push((
+                        from.path.to_string_lossy().into_owned(),
+                        to.path.to_string_lossy().into_owned(),
+                        confidence,
+                    )),
+                },
+            )
             .await?;
-
-        // Convert to string paths for display
-        let new_files_paths: Vec<String> = new_files
-            .iter()
-            .map(|f| f.path.to_string_lossy().into_owned())
-            .collect();
-
-        let deleted_files: Vec<String> = deleted_files
-            .iter()
-            .map(|f| f.path.to_string_lossy().into_owned())
-            .collect();
-
-        let renamed_files: Vec<(String, String)> = renames
-            .iter()
-            .map(|(old, new)| {
-                (
-                    old.path.to_string_lossy().into_owned(),
-                    new.path.to_string_lossy().into_owned(),
-                )
-            })
-            .collect();
-
-        // Convert changed files to string paths for display
-        let updated_files: Vec<String> = changed_files
-            .iter()
-            .map(|f| f.path.to_string_lossy().into_owned())
-            .collect();
+        let (moved_directories, renamed_files) =
+            group_directory_moves(renamed_files, &tracked_file_records);
+        drop(tracked_file_records);
+        drop(all_files);
 
         // Calculate untracked file statistics
-        let untracked_count = new_files.len();
-        let total_untracked_size: u64 = new_files.iter().map(|f| f.size).sum();
+        let untracked_count = new_files_paths.len();
 
         // Calculate duplicate statistics
         let (duplicate_groups, duplicate_files, wasted_space) = self.get_duplicate_stats().await?;
@@ -105,164 +340,424 @@ impl<'a> StatusCommand<'a> {
             new_files: new_files_paths,
             deleted_files,
             renamed_files,
+            moved_directories,
             updated_files,
+            file_sizes,
+            unresolved_failures,
+            unreadable_files: unreadable_files.into_iter().collect(),
+            object_store_size,
+            object_store_extra,
+            reflinked,
+            stored_objects,
+            missing_objects,
+            orphaned_objects,
+            devices,
         })
     }
 
+    /// Size of the object store and the disk space it takes beyond the
+    /// tracked files. Objects of current content are free when reflinked;
+    /// objects of past versions no longer share blocks with anything.
+    /// Remote object stores take no local space and are not listed.
+    fn object_store_usage(&self, current: &HashSet<&str>) -> Result<(u64, u64, bool)> {
+        let Some(objects_root) = self.context.objects.local_root() else {
+            return Ok((0, 0, false));
+        };
+        if !objects_root.exists() {
+            return Ok((0, 0, false));
+        }
+        let objects = crate::scanner::get_all_files(&objects_root, &objects_root, WalkFilter::ALL)?;
+        let size: u64 = objects.iter().map(|object| object.size).sum();
+        if size == 0 {
+            return Ok((0, 0, false));
+        }
+
+        let reflinked = self.context.repo.probe_storage().shares_blocks();
+        let extra = if reflinked {
+            objects
+                .iter()
+                .filter(|object| {
+                    object
+                        .path
+                        .file_name()
+                        .and_then(|name| name.to_str())
+                        .is_none_or(|name| !current.contains(name))
+                })
+                .map(|object| object.size)
+                .sum()
+        } else {
+            size
+        };
+        Ok((size, extra, reflinked))
+    }
+
+    /// Number of objects in the store, tracked files whose stored object is
+    /// gone, and number of objects nothing references any more, counting
+    /// those the database does not know about
+    async fn object_store_health(
+        &self,
+        tracked_files: &[FileRecord],
+    ) -> Result<(usize, Vec<String>, usize)> {
+        let objects = self.context.objects.list()?;
+        let held: HashSet<&str> = objects
+            .iter()
+            .map(|object| object.checksum.as_str())
+            .collect();
+        let missing = tracked_files
+            .iter()
+            .filter(|file| file.stored && !held.contains(file.b3sum.as_str()))
+            .map(|file| file.path.clone())
+            .collect();
+
+        let database = &self.context.database;
+        let known = database.get_object_checksums().await?;
+        let unreferenced: HashSet<String> = database
+            .find_orphaned_objects(chrono::Utc::now())
+            .await?
+            .into_iter()
+            .map(|object| object.b3sum)
+            .collect();
+        let orphaned = held
+            .iter()
+            .filter(|checksum| !known.contains(**checksum) || unreferenced.contains(**checksum))
+            .count();
+        Ok((held.len(), missing, orphaned))
+    }
+
     fn analyze_tracked_file_info(
         &self,
         tracked_files: &[crate::database::TrackedFileInfo],
     ) -> (usize, u64, Option<chrono::NaiveDateTime>) {
         let tracked_count = tracked_files.len();
-        let total_tracked_size: u64 = tracked_files.iter().map(|f| f.size as u64).sum();
-        let newest_tracked = tracked_files.iter().map(|f| f.created_at).max();
+
+        // Hardlinks share storage, so each inode only counts once towards size
+        let mut seen_inodes = HashSet::new();
+        let total_tracked_size: u64 = tracked_files
+            .iter()
+            .filter(|f| f.dev.zip(f.ino).is_none_or(|key| seen_inodes.insert(key)))
+            .map(|f| f.size as u64)
+            .sum();
+        let newest_tracked = tracked_files.iter().filter_map(|f| f.created_at).max();
 
         (tracked_count, total_tracked_size, newest_tracked)
     }
 
     async fn get_duplicate_stats(&self) -> Result<(usize, usize, u64)> {
-        let all_files = self.context.database.find_duplicates().await?;
-        let mut checksum_groups: HashMap<String, Vec<_>> = HashMap::new();
-
-        // Group files by checksum
-        for file in all_files {
-            checksum_groups
-                .entry(file.b3sum.clone())
-                .or_default()
-                .push(file);
-        }
-
-        let mut duplicate_groups = 0;
-        let mut duplicate_files = 0;
-        let mut wasted_space = 0u64;
-
-        for (_, files) in checksum_groups {
-            if files.len() > 1 {
-                duplicate_groups += 1;
-                duplicate_files += files.len();
-                wasted_space += (files[0].size as u64) * (files.len() as u64 - 1);
-            }
-        }
-
-        Ok((duplicate_groups, duplicate_files, wasted_space))
+        let stats = self.context.database.get_duplicate_stats().await?;
+        Ok((
+            stats.groups as usize,
+            stats.files as usize,
+            stats.wasted_space as u64,
+        ))
     }
 
-    // This method has been moved to utils.rs as a utility function
-
-    fn display_status(&self, stats: &RepositoryStats) {
+    /// Lines listing `files` by directory, as the listing options say
+    fn list_files(&self, files: &[String], stats: &RepositoryStats) -> Vec<String> {
         // Define constants for path display
         const MAX_PATH_LENGTH: usize = 50; // Maximum length for displayed paths
         const MAX_SAMPLES: usize = 3; // Maximum number of sample files to show per directory
+        let files: Vec<(String, u64)> = files
+            .iter()
+            .map(|path| {
+                (
+                    path.clone(),
+                    stats.file_sizes.get(path).copied().unwrap_or(0),
+                )
+            })
+            .collect();
+        let grouping = &self.listing.grouping;
+        let symbols = self.context.symbols();
+        if self.listing.tree {
+            display_directory_tree(&files, grouping, symbols, |size| {
+                self.context.format_size(size)
+            })
+        } else {
+            let grouped_files = group_files(&files, grouping);
+            display_directory_listing(&grouped_files, MAX_PATH_LENGTH, MAX_SAMPLES, symbols)
+        }
+    }
+
+    fn display_status(&self, stats: &RepositoryStats) {
+        const MAX_SAMPLES: usize = 3; // Maximum number of renamed files to show
+        let symbols = self.context.symbols();
 
         // Updated files section (metadata changes only)
         if !stats.updated_files.is_empty() {
-            info!("Files with metadata changes (size/modification time):");
+            info!("{}", tr!("status-updated-header"));
 
-            // Group files by directory using the utility function
-            let grouped_files = group_files_by_directory(&stats.updated_files);
-
-            // Display directory listing using the utility function
-            for line in display_directory_listing(&grouped_files, MAX_PATH_LENGTH, MAX_SAMPLES) {
+            for line in self.list_files(&stats.updated_files, stats) {
                 info!("{}", line);
             }
-            info!("  Run 'ddrive verify' to check if content has actually changed");
+            info!("{}", tr!("status-updated-hint"));
             info!("");
         }
 
         // New files summary by directory
         if !stats.new_files.is_empty() {
-            info!("New files found:");
-
-            // Group files by directory using the utility function
-            let grouped_files = group_files_by_directory(&stats.new_files);
+            info!("{}", tr!("status-new-header"));
 
-            // Display directory listing using the utility function
-            for line in display_directory_listing(&grouped_files, MAX_PATH_LENGTH, MAX_SAMPLES) {
+            for line in self.list_files(&stats.new_files, stats) {
                 info!("{}", line);
             }
             info!("");
         }
 
+        if !stats.moved_directories.is_empty() {
+            info!("{}", tr!("status-moved-header"));
+            for (old_path, new_path, files) in &stats.moved_directories {
+                info!(
+                    "{}",
+                    tr!(
+                        "status-moved-line",
+                        from = old_path,
+                        arrow = symbols.arrow(),
+                        to = new_path,
+                        files = format_count(*files)
+                    )
+                );
+            }
+            info!("{}", tr!("status-moved-hint"));
+            info!("");
+        }
+
         // Renamed files section
         if !stats.renamed_files.is_empty() {
-            info!("Potentially renamed files:");
+            info!("{}", tr!("status-renamed-header"));
             let display_count = std::cmp::min(stats.renamed_files.len(), MAX_SAMPLES);
-            for (old_path, new_path) in stats.renamed_files.iter().take(display_count) {
-                info!("  {} → {}", old_path, new_path);
+            for (old_path, new_path, confidence) in stats.renamed_files.iter().take(display_count) {
+                info!(
+                    "{}",
+                    tr!(
+                        "status-renamed-line",
+                        from = old_path,
+                        arrow = symbols.arrow(),
+                        to = new_path,
+                        confidence = confidence
+                    )
+                );
             }
             if stats.renamed_files.len() > display_count {
                 info!(
-                    "  ... and {} more",
-                    stats.renamed_files.len() - display_count
+                    "  {}",
+                    tr!(
+                        "status-and-more",
+                        count = stats.renamed_files.len() - display_count
+                    )
+                );
+            }
+            info!("{}", tr!("status-renamed-hint"));
+            let low_confidence = stats
+                .renamed_files
+                .iter()
+                .filter(|(_, _, confidence)| confidence.is_low())
+                .count();
+            if low_confidence > 0 {
+                info!(
+                    "{}",
+                    tr!("status-renamed-low-confidence", count = low_confidence)
                 );
             }
-            info!("  Run 'ddrive add <path>' to confirm these renames");
             info!("");
         }
 
         // Deleted files with more friendly wording
         if !stats.deleted_files.is_empty() {
-            info!("Files no longer present:");
+            info!("{}", tr!("status-deleted-header"));
 
-            // Group files by directory using the utility function
-            let grouped_files = group_files_by_directory(&stats.deleted_files);
-
-            // Display directory listing using the utility function
-            for line in display_directory_listing(&grouped_files, MAX_PATH_LENGTH, MAX_SAMPLES) {
+            for line in self.list_files(&stats.deleted_files, stats) {
                 info!("{}", line);
             }
             info!("");
         }
 
+        if !stats.unresolved_failures.is_empty() {
+            warn!(
+                code = %codes::UNRESOLVED_FAILURES,
+                "{}",
+                tr!(
+                    "status-failures-header",
+                    count = stats.unresolved_failures.len()
+                )
+            );
+            for path in stats.unresolved_failures.iter().take(5) {
+                warn!("  {}", path);
+            }
+            if stats.unresolved_failures.len() > 5 {
+                warn!(
+                    "  {}",
+                    tr!(
+                        "status-and-more",
+                        count = stats.unresolved_failures.len() - 5
+                    )
+                );
+            }
+            warn!("{}", tr!("status-failures-hint"));
+            info!("");
+        }
+
+        if !stats.unreadable_files.is_empty() {
+            warn!(
+                code = %codes::UNREADABLE_FILES,
+                "{}",
+                tr!(
+                    "status-unreadable-header",
+                    count = stats.unreadable_files.len()
+                )
+            );
+            for path in stats.unreadable_files.iter().take(5) {
+                warn!("  {}", path);
+            }
+            if stats.unreadable_files.len() > 5 {
+                warn!(
+                    "  {}",
+                    tr!("status-and-more", count = stats.unreadable_files.len() - 5)
+                );
+            }
+            warn!("{}", tr!("status-unreadable-hint"));
+            info!("");
+        }
+
         // Integrity status section with more friendly wording
         if stats.files_needing_check > 0 {
-            info!(
-                "Files due for verification: {} files",
-                stats.files_needing_check
-            );
-            info!("Run 'ddrive verify' to verify if any tracked files have changed");
+            info!("{}", tr!("status-due", count = stats.files_needing_check));
+            info!("{}", tr!("status-due-hint"));
         } else {
-            info!("All your files have been verified recently");
+            info!("{}", tr!("status-all-verified"));
         }
         info!("");
 
         // Tracked files section with more friendly wording
-        info!("Protected files:");
+        info!("{}", tr!("status-protected-header"));
         info!(
-            "  {} files ({})",
-            stats.tracked_files,
-            format_size(stats.total_tracked_size)
+            "{}",
+            tr!(
+                "status-files-size",
+                count = stats.tracked_files,
+                size = self.context.format_size(stats.total_tracked_size)
+            )
         );
 
         if let Some(newest) = stats.newest_tracked {
-            info!("  Last backup: {}", newest.format("%B %d, %Y at %H:%M"));
+            let date = newest.format(&tr!("status-date-format")).to_string();
+            info!("{}", tr!("status-last-backup", date = date));
         }
         info!("");
 
+        // Per-device breakdown, when files span disks or a disk has failures
+        if stats.devices.len() > 1 || stats.devices.iter().any(|device| device.failures > 0) {
+            info!("{}", tr!("status-devices-header"));
+            for device in &stats.devices {
+                let line = tr!(
+                    "status-device-line",
+                    device = device.label(),
+                    files = device.files,
+                    size = self.context.format_size(device.size as u64)
+                );
+                if device.failures > 0 {
+                    warn!(
+                        "{}",
+                        tr!(
+                            "status-device-failures",
+                            line = line,
+                            failures = device.failures
+                        )
+                    );
+                } else {
+                    info!("{}", line);
+                }
+            }
+            info!("");
+        }
+
+        if stats.stored_objects > 0 || !stats.missing_objects.is_empty() {
+            info!("{}", tr!("status-stored-header"));
+            info!(
+                "{}",
+                tr!(
+                    "status-stored-objects",
+                    count = format_count(stats.stored_objects),
+                    location = self.context.objects.location()
+                )
+            );
+            if stats.object_store_size > 0 {
+                info!(
+                    "{}",
+                    tr!(
+                        "status-stored-size",
+                        size = self.context.format_size(stats.object_store_size),
+                        extra = self.context.format_size(stats.object_store_extra)
+                    )
+                );
+                if stats.reflinked {
+                    info!("{}", tr!("status-stored-reflinked"));
+                } else {
+                    warn!(code = %codes::NO_REFLINK, "{}", tr!("status-stored-full-copies"));
+                    info!("{}", tr!("status-doctor-hint"));
+                }
+            }
+            if !stats.missing_objects.is_empty() {
+                warn!(
+                    code = %codes::MISSING_STORED_COPY,
+                    "{}",
+                    tr!("status-stored-missing", count = stats.missing_objects.len())
+                );
+                for path in stats.missing_objects.iter().take(5) {
+                    warn!("    {}", path);
+                }
+                if stats.missing_objects.len() > 5 {
+                    warn!(
+                        "    {}",
+                        tr!("status-and-more", count = stats.missing_objects.len() - 5)
+                    );
+                }
+                info!("{}", tr!("status-stored-missing-hint"));
+            }
+            if stats.orphaned_objects > 0 {
+                info!(
+                    "{}",
+                    tr!(
+                        "status-stored-orphaned",
+                        count = format_count(stats.orphaned_objects)
+                    )
+                );
+            }
+            info!("");
+        }
+
         // Untracked files section with more friendly wording
         if stats.untracked_files > 0 {
-            info!("Files not yet protected:");
+            info!("{}", tr!("status-untracked-header"));
             info!(
-                "  {} files ({})",
-                stats.untracked_files,
-                format_size(stats.total_untracked_size)
+                "{}",
+                tr!(
+                    "status-files-size",
+                    count = stats.untracked_files,
+                    size = self.context.format_size(stats.total_untracked_size)
+                )
             );
-            info!("  Run 'ddrive add <path>' to protect these files");
+            info!("{}", tr!("status-untracked-hint"));
             info!("");
         }
 
         // Duplicates section with more friendly wording
         if stats.duplicate_groups > 0 {
-            info!("Duplicate files found:");
+            info!("{}", tr!("status-duplicates-header"));
             info!(
-                "  {} sets of duplicates with {} total files",
-                stats.duplicate_groups, stats.duplicate_files
+                "{}",
+                tr!(
+                    "status-duplicates-sets",
+                    sets = stats.duplicate_groups,
+                    files = stats.duplicate_files
+                )
             );
             info!(
-                "  Storage used by duplicates: {}",
-                format_size(stats.wasted_space)
+                "{}",
+                tr!(
+                    "status-duplicates-size",
+                    size = self.context.format_size(stats.wasted_space)
+                )
             );
-            info!("  Run 'ddrive dedup' to see details");
+            info!("{}", tr!("status-duplicates-hint"));
             info!("");
         }
 
@@ -270,16 +765,115 @@ impl<'a> StatusCommand<'a> {
         let total_files = stats.tracked_files + stats.untracked_files;
         let total_size = stats.total_tracked_size + stats.total_untracked_size;
 
-        info!("Summary:");
+        info!("{}", tr!("status-summary-header"));
         info!(
-            "  Total: {} files ({})",
-            total_files,
-            format_size(total_size)
+            "{}",
+            tr!(
+                "status-summary-total",
+                count = total_files,
+                size = self.context.format_size(total_size)
+            )
         );
 
         if stats.tracked_files > 0 && total_files > 0 {
             let tracking_percentage = (stats.tracked_files as f64 / total_files as f64) * 100.0;
-            info!("  Protection coverage: {:.1}%", tracking_percentage);
+            let percent = format!("{tracking_percentage:.1}");
+            info!("{}", tr!("status-summary-coverage", percent = percent));
         }
     }
 }
+
+/// A rename found by `status`: (old_path, new_path, confidence)
+type Rename = (String, String, RenameConfidence);
+
+/// Split renames into the directories that moved as a whole, as (old_path,
+/// new_path, files), and the files renamed on their own
+fn group_directory_moves(
+    renames: Vec<Rename>,
+    tracked_files: &[FileRecord],
+) -> (Vec<(String, String, usize)>, Vec<Rename>) {
+    let pairs: Vec<(String, String)> = renames
+        .iter()
+        .map(|(old_path, new_path, _)| (old_path.clone(), new_path.clone()))
+        .collect();
+    let tracked: TrackedDirectories = tracked_files.iter().map(|f| f.path.as_str()).collect();
+    let directory_moves = find_directory_moves(&pairs, &tracked);
+
+    let moved: HashSet<usize> = directory_moves
+        .iter()
+        .flat_map(|directory_move| directory_move.files.iter().copied())
+        .collect();
+    let renames = renames
+        .into_iter()
+        .enumerate()
+        .filter(|(index, _)| !moved.contains(index))
+        .map(|(_, rename)| rename)
+        .collect();
+    let directory_moves = directory_moves
+        .into_iter()
+        .map(|directory_move| {
+            (
+                directory_move.from,
+                directory_move.to,
+                directory_move.files.len(),
+            )
+        })
+        .collect();
+    (directory_moves, renames)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{AddOptions, api::testing::repository};
+    use assert_fs::prelude::*;
+
+    #[tokio::test]
+    async fn test_status_reports_object_store_usage() {
+        let (temp, ddrive) = repository(&[("docs/a.txt", "first")]).await;
+        ddrive
+            .add(&[temp.path().join("docs")], &AddOptions::default())
+            .await
+            .unwrap();
+        temp.child("docs/a.txt").write_str("second").unwrap();
+        ddrive
+            .add(&[temp.path().join("docs")], &AddOptions::default())
+            .await
+            .unwrap();
+
+        let stats = ddrive.status().await.unwrap();
+        assert_eq!(stats.object_store_size, 11);
+        let capabilities = ddrive.context().repo.probe_storage();
+        assert_eq!(stats.reflinked, capabilities.shares_blocks());
+        assert_eq!(capabilities.warnings().is_empty(), stats.reflinked);
+        // Only the past version takes extra space when objects are reflinked
+        let expected_extra = if stats.reflinked { 5 } else { 11 };
+        assert_eq!(stats.object_store_extra, expected_extra);
+        assert_eq!(stats.stored_objects, 2);
+        assert!(stats.missing_objects.is_empty());
+        // The past version is still referenced by history
+        assert_eq!(stats.orphaned_objects, 0);
+    }
+
+    #[tokio::test]
+    async fn test_status_reports_missing_and_orphaned_objects() {
+        let (temp, ddrive) = repository(&[("docs/a.txt", "first"), ("docs/b.txt", "other")]).await;
+        ddrive
+            .add(&[temp.path().join("docs")], &AddOptions::default())
+            .await
+            .unwrap();
+
+        let objects = &ddrive.context().objects;
+        let a = blake3::hash(b"first").to_hex().to_string();
+        assert!(objects.delete(&a).unwrap());
+        let stray = blake3::hash(b"stray").to_hex().to_string();
+        let source = temp.path().join("stray.bin");
+        std::fs::write(&source, "stray").unwrap();
+        objects.put(&stray, &source).unwrap();
+        std::fs::remove_file(&source).unwrap();
+
+        let stats = ddrive.status().await.unwrap();
+        assert_eq!(stats.stored_objects, 2);
+        assert_eq!(stats.missing_objects, vec!["docs/a.txt".to_string()]);
+        assert_eq!(stats.orphaned_objects, 1);
+    }
+}
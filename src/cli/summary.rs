@@ -0,0 +1,137 @@
+//! Machine-readable summaries written with `--summary-file`.
+//!
+//! Cron jobs and CI wrappers read the JSON file instead of scraping logs. It
+//! is written once the command finishes, also when it fails.
+
+use crate::Result;
+use serde_json::{Value, json};
+use std::fs;
+use std::path::PathBuf;
+use std::time::Instant;
+use tracing::warn;
+
+/// Base58 action ID, as shown by `ddrive log`
+pub fn action_id_json(action_id: Option<i64>) -> Value {
+    action_id
+        .map(|id| bs58::encode(id.to_be_bytes()).into_string())
+        .into()
+}
+
+pub struct SummaryFile {
+    path: Option<PathBuf>,
+    command: &'static str,
+    started: Instant,
+}
+
+impl SummaryFile {
+    /// Start timing `command`; nothing is written unless `path` is given
+    pub fn new(path: Option<PathBuf>, command: &'static str) -> Self {
+        Self {
+            path,
+            command,
+            started: Instant::now(),
+        }
+    }
+
+    /// Write the summary with the command's `details` and return `outcome`
+    /// unchanged. Failing to write the file only fails a successful command.
+    pub fn finish(&self, details: Option<Value>, outcome: Result<()>) -> Result<()> {
+        let Some(path) = &self.path else {
+            return outcome;
+        };
+
+        let mut summary = json!({
+            "command": self.command,
+            "success": outcome.is_ok(),
+            "exit_code": outcome.as_ref().err().map_or(0, |e| e.exit_code()),
+            "error": outcome.as_ref().err().map(|e| e.to_string()),
+            "error_code": outcome.as_ref().err().map(|e| e.code().id),
+            "error_context": outcome.as_ref().err().and_then(|e| e.context()).map(|context| json!({
+                "operation": context.operation.name(),
+                "path": context.path,
+                "action_id": action_id_json(context.action_id),
+            })),
+            "duration_secs": self.started.elapsed().as_secs_f64(),
+            "finished_at": chrono::Utc::now(),
+        });
+        if let (Some(summary), Some(Value::Object(details))) = (summary.as_object_mut(), details) {
+            summary.extend(details);
+        }
+
+        // Write to a temporary file first so readers never see a partial summary
+        let tmp_path = path.with_extension("tmp");
+        let written = fs::write(&tmp_path, format!("{summary:#}\n"))
+            .and_then(|()| fs::rename(&tmp_path, path));
+        match (written, outcome) {
+            (Err(e), Ok(())) => Err(e.into()),
+            (Err(e), outcome) => {
+                warn!("Failed to write summary to {}: {}", path.display(), e);
+                outcome
+            }
+            (Ok(()), outcome) => outcome,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{DdriveError, Operation, ResultExt, cli::add::AddResult};
+
+    fn read(path: &std::path::Path) -> Value {
+        serde_json::from_str(&fs::read_to_string(path).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_summary_file() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        let path = temp.path().join("summary.json");
+
+        let added = AddResult {
+            new_files: 2,
+            changed_files: 1,
+            action_id: Some(1),
+            ..Default::default()
+        };
+        SummaryFile::new(Some(path.clone()), "add")
+            .finish(Some(added.summary()), Ok(()))
+            .unwrap();
+        let summary = read(&path);
+        assert_eq!(summary["command"], "add");
+        assert_eq!(summary["success"], true);
+        assert_eq!(summary["exit_code"], 0);
+        assert!(summary["error"].is_null() && summary["error_context"].is_null());
+        assert!(summary["duration_secs"].is_f64());
+        assert!(summary["finished_at"].is_string());
+        assert_eq!(summary["action_id"], "11111112");
+        assert_eq!(
+            (
+                summary["new_files"].as_u64(),
+                summary["changed_files"].as_u64()
+            ),
+            (Some(2), Some(1))
+        );
+        assert_eq!(summary["failed"], json!([]));
+        assert!(!path.with_extension("tmp").exists());
+
+        // A failed command is summarized too, and keeps its error
+        let failed = Err(DdriveError::PartialFailure { failed: 3 })
+            .context(Operation::StoringCopy, "photos/a.jpg");
+        let outcome = SummaryFile::new(Some(path.clone()), "verify").finish(None, failed);
+        assert_eq!(outcome.unwrap_err().exit_code(), 12);
+        let summary = read(&path);
+        assert_eq!(summary["command"], "verify");
+        assert_eq!(summary["success"], false);
+        assert_eq!(summary["exit_code"], 12);
+        assert_eq!(summary["error_code"], "DD0016");
+        assert_eq!(summary["error_context"]["operation"], "storing-copy");
+        assert_eq!(summary["error_context"]["path"], "photos/a.jpg");
+
+        // Without a path nothing is written
+        fs::remove_file(&path).unwrap();
+        SummaryFile::new(None, "prune")
+            .finish(None, Ok(()))
+            .unwrap();
+        assert!(!path.exists());
+    }
+}
@@ -0,0 +1,181 @@
+//! Moving old objects to cold storage.
+//!
+//! `TierCommand` moves the objects that only history still references, and
+//! has for `[object_store] cold_after_days`, from the object store to the
+//! cold store of `cold_url`, recording the tier of each in the `objects`
+//! table. Restores and verification keep finding them through the tiered
+//! backend of the context.
+
+use crate::{
+    AppContext, DdriveError, Result, backend,
+    checksum::ChecksumCalculator,
+    database::{ObjectTier, TieredObject},
+    utils::format_count,
+};
+use chrono::Utc;
+use tracing::{info, warn};
+
+#[derive(Debug, Default)]
+pub struct TierResult {
+    /// Objects moved to the cold store
+    pub moved: Vec<TieredObject>,
+    pub moved_bytes: u64,
+    /// Objects that could not be moved, with the reason
+    pub failed: Vec<(String, String)>,
+}
+
+pub struct TierCommand<'a> {
+    context: &'a AppContext,
+    calculator: ChecksumCalculator,
+}
+
+impl<'a> TierCommand<'a> {
+    pub fn new(context: &'a AppContext) -> Self {
+        Self {
+            context,
+            calculator: ChecksumCalculator::from_config(&context.config.checksum),
+        }
+    }
+
+    /// Move the objects due for the cold store there
+    pub async fn execute(&self, dry_run: bool) -> Result<TierResult> {
+        let config = &self.context.config.object_store;
+        let (hot, cold) = backend::open_tiers(&self.context.repo, config)?;
+        let Some(cold) = cold else {
+            return Err(DdriveError::Configuration {
+                message: "No cold store configured; set object_store.cold_url".to_string(),
+            });
+        };
+        let _lock = self.context.repo.lock()?;
+
+        let cutoff = Utc::now() - chrono::Duration::days(i64::from(config.cold_after_days));
+        let candidates = self.context.database.find_cold_candidates(cutoff).await?;
+        let tmp_dir = self.context.repo.tmp_dir();
+        let mut result = TierResult::default();
+        for object in candidates {
+            if dry_run {
+                if hot.exists(&object.b3sum)? {
+                    result.moved_bytes += object.size as u64;
+                    result.moved.push(object);
+                }
+                continue;
+            }
+
+            let uploaded = backend::with_object(hot.as_ref(), &object.b3sum, &tmp_dir, |path| {
+                if self.calculator.calculate_checksum(path)? != object.b3sum {
+                    return Err(DdriveError::Validation {
+                        message: "stored copy is damaged; run verify --objects".to_string(),
+                    });
+                }
+                cold.put(&object.b3sum, path)
+            });
+            match uploaded {
+                Ok(Some(_)) => {
+                    hot.delete(&object.b3sum)?;
+                    result.moved_bytes += object.size as u64;
+                }
+                // Content that was never stored, or was already moved
+                Ok(None) if !cold.exists(&object.b3sum)? => continue,
+                Ok(None) => {}
+                Err(e) => {
+                    warn!("Failed to move {} to the cold store: {}", object.b3sum, e);
+                    result.failed.push((object.b3sum, e.to_string()));
+                    continue;
+                }
+            }
+            self.context
+                .database
+                .set_object_tier(&object.b3sum, ObjectTier::Cold)
+                .await?;
+            result.moved.push(object);
+        }
+
+        info!(
+            "{} {} objects ({}) unused for {} days to {}",
+            if dry_run { "Would move" } else { "Moved" },
+            format_count(result.moved.len()),
+            self.context.format_size(result.moved_bytes),
+            config.cold_after_days,
+            cold.location()
+        );
+        let (objects, bytes) = self
+            .context
+            .database
+            .get_tier_totals(ObjectTier::Cold)
+            .await?;
+        info!(
+            "The cold store holds {} objects ({})",
+            format_count(objects as usize),
+            self.context.format_size(bytes)
+        );
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{AddOptions, Ddrive};
+    use assert_fs::{TempDir, prelude::*};
+
+    #[tokio::test]
+    async fn test_old_versions_move_to_cold_store() {
+        let temp = TempDir::new().unwrap();
+        let cold = TempDir::new().unwrap();
+        temp.child("notes.txt").write_str("first draft").unwrap();
+        crate::repository::Repository::init_repository(temp.path().to_path_buf())
+            .await
+            .unwrap();
+        let cold_url = format!("file://{}", cold.path().display());
+        let context = Ddrive::builder(temp.path())
+            .configure(move |config| {
+                config.object_store.cold_url = Some(cold_url);
+                config.object_store.cold_after_days = 0;
+            })
+            .build()
+            .await
+            .unwrap();
+        let ddrive = Ddrive::from_context(context);
+        ddrive
+            .add(&[temp.path()], &AddOptions::default())
+            .await
+            .unwrap();
+        temp.child("notes.txt").write_str("final version").unwrap();
+        ddrive
+            .add(&[temp.path()], &AddOptions::default())
+            .await
+            .unwrap();
+
+        let result = TierCommand::new(ddrive.context())
+            .execute(false)
+            .await
+            .unwrap();
+        let old = blake3::hash(b"first draft").to_hex().to_string();
+        let current = blake3::hash(b"final version").to_hex().to_string();
+        assert_eq!(result.moved.len(), 1);
+        assert_eq!(result.moved[0].b3sum, old);
+        assert!(backend::object_path(cold.path(), &old).exists());
+        let objects_root = ddrive.context().repo.objects_root();
+        assert!(!backend::object_path(&objects_root, &old).exists());
+        assert!(backend::object_path(&objects_root, &current).exists());
+        let database = &ddrive.context().database;
+        assert_eq!(
+            database.get_tier_totals(ObjectTier::Cold).await.unwrap(),
+            (1, 11)
+        );
+
+        // The old version is still restored from the cold store
+        let objects = ddrive.context().objects.as_ref();
+        let restored = backend::with_object(objects, &old, &temp.path().join("tmp"), |path| {
+            Ok(std::fs::read_to_string(path)?)
+        })
+        .unwrap();
+        assert_eq!(restored.as_deref(), Some("first draft"));
+
+        let again = TierCommand::new(ddrive.context())
+            .execute(false)
+            .await
+            .unwrap();
+        assert!(again.moved.is_empty());
+    }
+}
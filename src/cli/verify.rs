@@ -1,29 +1,213 @@
 use crate::{
-    AppContext, DdriveError, Result, config::Config, database::FileRecord, utils::FileProcessor,
+    AppContext, DdriveError, Operation, Result, ResultExt, backend,
+    checksum::HashAlgorithm,
+    cli::summary,
+    codes,
+    config::{Config, VerifySchedule},
+    database::{ActionType, FailureResolution, FileRecord},
+    device, logging,
+    metadata::{FileAttributes, file_identity},
+    notifications,
+    progress::{ProgressEvent, VerifyOutcome},
+    scanner::{self, WalkFilter},
+    tr,
+    utils::{FileProcessor, Symbols, format_count, from_storage_path, to_storage_path},
 };
-use chrono::DateTime;
+use chrono::{DateTime, Utc};
 use glob::Pattern;
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::{Duration, Instant, UNIX_EPOCH};
 use tracing::{debug, info, warn};
 
 pub struct VerifyCommand<'a> {
     context: &'a AppContext,
     processor: FileProcessor<'a>,
+    symbols: Symbols,
+    /// Record check times, failures and the run in the database
+    update: bool,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Default)]
 pub struct VerifyResult {
     pub checked_files: usize,
     pub passed_files: usize,
+    /// Files whose content and modification time both changed: most likely
+    /// edited since they were last added, so not counted as failures
+    pub modified_files: usize,
     pub failed_files: usize,
+    /// Files not checked at all: without a checksum for the requested
+    /// algorithm, or on unavailable media
     pub skipped_files: usize,
+    /// Files without a checksum for the requested algorithm
+    pub missing_checksum: usize,
+    /// Files that passed on unchanged size and modification time without
+    /// being read
+    pub metadata_skipped: usize,
+    /// Tracked files left out by the path filter or `--since`
+    pub filtered_files: usize,
+    /// Files already checked by the interrupted run this one continued
+    pub resumed_files: usize,
+    /// Files read off disk and hashed, and their total size
+    pub hashed_files: usize,
+    pub bytes_hashed: u64,
+    /// Missing files that live on media that is not currently available,
+    /// such as a detached external disk. Counted as skipped, not failed.
+    pub unavailable_files: Vec<String>,
     pub failures: Vec<IntegrityFailure>,
+    /// Corrupted and unreadable files per device (mount point, file system
+    /// and UUID), most failures first
+    pub failures_by_device: Vec<(String, usize)>,
+    /// Files whose permissions, ownership or xattrs differ from the recorded values
+    pub metadata_drift: Vec<MetadataDrift>,
+    /// Stored objects checked with `verify --objects`
+    pub checked_objects: usize,
+    pub object_failures: Vec<ObjectFailure>,
+    /// History action quarantined files were recorded under, if any were
+    pub action_id: Option<i64>,
+}
+
+impl VerifyResult {
+    /// Details for `--summary-file`
+    pub fn summary(&self) -> serde_json::Value {
+        let failures: Vec<_> = self
+            .failures
+            .iter()
+            .map(|failure| {
+                serde_json::json!({
+                    "path": failure.file_path,
+                    "kind": match failure.kind {
+                        MismatchKind::Modified => "modified",
+                        MismatchKind::Corrupted => "corrupted",
+                    },
+                    "expected_checksum": failure.expected_checksum,
+                    "actual_checksum": failure.actual_checksum,
+                    "quarantined_to": failure.quarantined_to,
+                })
+            })
+            .collect();
+        let object_failures: Vec<_> = self
+            .object_failures
+            .iter()
+            .map(|failure| {
+                serde_json::json!({
+                    "b3sum": failure.b3sum,
+                    "paths": failure.paths,
+                    "missing": failure.actual_checksum.is_none(),
+                })
+            })
+            .collect();
+        serde_json::json!({
+            "action_id": summary::action_id_json(self.action_id),
+            "checked_files": self.checked_files,
+            "passed_files": self.passed_files,
+            "modified_files": self.modified_files,
+            "failed_files": self.failed_files,
+            "skipped_files": self.skipped_files,
+            "metadata_skipped": self.metadata_skipped,
+            "resumed_files": self.resumed_files,
+            "hashed_files": self.hashed_files,
+            "bytes_hashed": self.bytes_hashed,
+            "unavailable_files": self.unavailable_files,
+            "failures": failures,
+            "failures_by_device": self
+                .failures_by_device
+                .iter()
+                .map(|(device, failed_files)| serde_json::json!({
+                    "device": device,
+                    "failed_files": failed_files,
+                }))
+                .collect::<Vec<_>>(),
+            "checked_objects": self.checked_objects,
+            "object_failures": object_failures,
+        })
+    }
+}
+
+/// A stored object that is missing or no longer matches its checksum
+#[derive(Debug)]
+pub struct ObjectFailure {
+    pub b3sum: String,
+    /// Tracked files whose content the object holds
+    pub paths: Vec<String>,
+    /// `None` if the object is missing from the store
+    pub actual_checksum: Option<String>,
+}
+
+#[derive(Debug)]
+pub struct MetadataDrift {
+    pub file_path: String,
+    pub changes: Vec<String>,
+}
+
+/// How a checksum mismatch is classified
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MismatchKind {
+    /// Size or modification time changed too: a legitimate edit
+    Modified,
+    /// Content changed while size and modification time did not
+    Corrupted,
 }
 
 #[derive(Debug)]
 pub struct IntegrityFailure {
     pub file_path: String,
+    pub kind: MismatchKind,
     pub expected_checksum: String,
     pub actual_checksum: String,
+    /// The checksum matched but the keyed MAC did not: the file and the
+    /// database were likely both modified
+    pub mac_mismatch: bool,
+    /// Corrupt byte ranges (start, end exclusive), if chunk hashes were recorded
+    pub damaged_ranges: Vec<(u64, u64)>,
+    /// Where the corrupted file was moved when it was quarantined and replaced
+    /// with the stored copy
+    pub quarantined_to: Option<PathBuf>,
+}
+
+/// A copy of the repository elsewhere, such as a restore test on another
+/// disk, compared with the tracked files by relative path
+#[derive(Debug, Default)]
+pub struct ExternalVerification {
+    /// Files whose content matches the recorded checksum, and their size
+    pub matched_files: usize,
+    pub matched_bytes: u64,
+    /// Tracked files the copy lacks
+    pub missing: Vec<String>,
+    /// Files whose size or content differs from the recorded ones
+    pub mismatched: Vec<String>,
+    /// Files in the copy that are not tracked
+    pub extra: Vec<String>,
+    /// Files in the copy that could not be read
+    pub unreadable: Vec<String>,
+    /// Tracked files without a checksum for the requested algorithm
+    pub skipped_files: usize,
+    /// Tracked files left out by the path filter
+    pub filtered_files: usize,
+}
+
+impl ExternalVerification {
+    /// Whether every tracked file is in the copy with the right content
+    pub fn passed(&self) -> bool {
+        self.missing.is_empty() && self.mismatched.is_empty() && self.unreadable.is_empty()
+    }
+
+    /// Details for `--summary-file`
+    pub fn summary(&self) -> serde_json::Value {
+        serde_json::json!({
+            "action_id": null,
+            "matched_files": self.matched_files,
+            "matched_bytes": self.matched_bytes,
+            "missing": self.missing,
+            "mismatched": self.mismatched,
+            "extra": self.extra,
+            "unreadable": self.unreadable,
+            "skipped_files": self.skipped_files,
+            "filtered_files": self.filtered_files,
+        })
+    }
 }
 
 impl<'a> VerifyCommand<'a> {
@@ -31,56 +215,183 @@ impl<'a> VerifyCommand<'a> {
         VerifyCommand {
             context,
             processor: FileProcessor::new(context),
+            symbols: context.symbols(),
+            update: !context.database.is_read_only(),
         }
     }
 
-    /// Execute the verify command with optional filters and force option
+    /// Whether to record check times, failures and the run; without it
+    /// verify only reports, and files stay due for their next check
+    pub fn update(mut self, update: bool) -> Self {
+        self.update = update;
+        self
+    }
+
+    /// Execute the verify command with optional filters and force option.
+    /// Files are checked against the checksum recorded for `algorithm`; files
+    /// without one (secondary algorithms are optional) are skipped. With
+    /// `since`, only files modified or added at or after that time are
+    /// considered. With `failed_only`, only files with unresolved integrity
+    /// failures are checked.
     pub async fn execute(
         &self,
-        path_filter: Option<&Pattern>,
+        path_filter: Option<&PathFilter>,
+        since: Option<DateTime<Utc>>,
         force: bool,
+        algorithm: HashAlgorithm,
+        failed_only: bool,
     ) -> Result<VerifyResult> {
+        let started = Instant::now();
+        // Files that failed before are always hashed, so a pass really resolves them
+        let previously_failed: HashSet<String> = self
+            .context
+            .database
+            .get_unresolved_verification_failures()
+            .await?
+            .into_iter()
+            .map(|failure| failure.path)
+            .collect();
+
         // Get all files that match the filter
-        let files_to_check = self
-            .get_files_for_verification(path_filter, force, &self.context.config)
-            .await?;
+        let (files_to_check, filtered_files) = if failed_only {
+            let paths: Vec<&str> = previously_failed.iter().map(String::as_str).collect();
+            let files = self.context.database.get_files_by_paths(&paths).await?;
+            apply_path_filter(files, path_filter)
+        } else {
+            self.get_files_for_verification(path_filter, since, force, &self.context.config)
+                .await?
+        };
+
+        let mut result = VerifyResult {
+            filtered_files,
+            ..Default::default()
+        };
 
+        // Continue an interrupted run with the same filters under its action ID
+        let parameters = session_parameters(path_filter, since, force, algorithm, failed_only);
+        let session = if self.update {
+            self.context
+                .database
+                .find_verify_session(&parameters)
+                .await?
+        } else {
+            None
+        };
+        let mut files_to_check = files_to_check;
+        if let Some(session) = &session {
+            files_to_check.retain(|file_record| !session.checked.contains(&file_record.path));
+            result.resumed_files = session.checked.len();
+            info!(
+                "Resuming an interrupted verify run: {} of {} files were already checked",
+                format_count(session.checked.len()),
+                format_count(session.total_files as usize)
+            );
+        }
         if files_to_check.is_empty() {
+            if let Some(session) = &session {
+                self.context
+                    .database
+                    .finish_verify_session(session.action_id)
+                    .await?;
+            }
             info!("No files need verification at this time");
-            return Ok(VerifyResult {
-                checked_files: 0,
-                passed_files: 0,
-                failed_files: 0,
-                skipped_files: 0,
-                failures: Vec::new(),
-            });
+            return Ok(result);
         }
 
         info!("Verifying {} files", files_to_check.len());
+        self.context.emit(ProgressEvent::Started {
+            command: "verify",
+            files: files_to_check.len(),
+        });
 
-        let mut result = VerifyResult {
-            checked_files: 0,
-            passed_files: 0,
-            failed_files: 0,
-            skipped_files: 0,
-            failures: Vec::new(),
+        let action_id = match &session {
+            Some(session) => session.action_id,
+            None => chrono::Utc::now().timestamp(),
         };
+        logging::set_action_id(action_id);
+        if self.update && session.is_none() {
+            let stale_before = Utc::now()
+                - chrono::Duration::days(i64::from(self.context.config.verify.interval_days));
+            self.context
+                .database
+                .start_verify_session(action_id, &parameters, files_to_check.len(), stale_before)
+                .await?;
+        }
+        let mut progress = SessionProgress::new(action_id, started);
+
+        let devs: BTreeSet<u64> = files_to_check
+            .iter()
+            .filter_map(|file_record| file_record.dev)
+            .map(|dev| dev as u64)
+            .collect();
+        if self.update {
+            self.context
+                .database
+                .record_devices(&device::describe(devs))
+                .await?;
+        }
+        let mut failed_devs = Vec::new();
 
         for file_record in &files_to_check {
-            match self.verify_file(file_record, force).await {
+            // The previous file is done once the loop comes around again
+            if let Some(previous) = progress.current.replace(file_record.path.clone()) {
+                progress.checked.push(previous);
+                self.checkpoint(&mut progress, &result, false).await?;
+            }
+            let Some(expected_checksum) = file_record.checksum(algorithm) else {
+                debug!(
+                    "No {} checksum recorded for {}",
+                    algorithm, file_record.path
+                );
+                result.skipped_files += 1;
+                result.missing_checksum += 1;
+                self.report(file_record, VerifyOutcome::Skipped);
+                continue;
+            };
+
+            if self.is_unavailable(file_record) {
+                debug!("{} is on unavailable media", file_record.path);
+                result.skipped_files += 1;
+                result.unavailable_files.push(file_record.path.clone());
+                self.report(file_record, VerifyOutcome::Skipped);
+                continue;
+            }
+
+            let force = force || previously_failed.contains(&file_record.path);
+            match self
+                .verify_with_retry(file_record, expected_checksum, algorithm, force)
+                .await
+            {
                 Ok(verification_result) => {
                     result.checked_files += 1;
+                    match verification_result.bytes_hashed {
+                        Some(bytes) => {
+                            result.hashed_files += 1;
+                            result.bytes_hashed += bytes;
+                        }
+                        None => result.metadata_skipped += 1,
+                    }
+
+                    if let Some(drift) = self.check_attribute_drift(file_record) {
+                        result.metadata_drift.push(drift);
+                    }
 
                     if verification_result.passed {
                         result.passed_files += 1;
-                        info!("✓ {}", file_record.path);
+                        info!("{} {}", self.symbols.pass(), file_record.path);
+                        self.report(file_record, VerifyOutcome::Passed);
+                        if previously_failed.contains(&file_record.path) {
+                            self.resolve_failure(file_record, FailureResolution::Verified)
+                                .await;
+                        }
 
                         let absolute_path = self.resolve_absolute_path(&file_record.path)?;
-                        if let Err(e) = self
-                            .context
-                            .database
-                            .update_last_checked(&absolute_path.to_string_lossy())
-                            .await
+                        if self.update
+                            && let Err(e) = self
+                                .context
+                                .database
+                                .update_last_checked(&absolute_path.to_string_lossy())
+                                .await
                         {
                             warn!(
                                 "Failed to update last_checked timestamp for {}: {}",
@@ -88,50 +399,509 @@ impl<'a> VerifyCommand<'a> {
                             );
                         }
                     } else {
-                        result.failed_files += 1;
-                        warn!("✗ {}", file_record.path);
+                        let kind = if verification_result.metadata_changed
+                            && !verification_result.mac_mismatch
+                        {
+                            result.modified_files += 1;
+                            info!("~ {} (modified)", file_record.path);
+                            self.report(file_record, VerifyOutcome::Modified);
+                            if previously_failed.contains(&file_record.path) {
+                                self.resolve_failure(file_record, FailureResolution::Modified)
+                                    .await;
+                            }
+                            MismatchKind::Modified
+                        } else {
+                            result.failed_files += 1;
+                            failed_devs.push(file_record.dev);
+                            let code = if verification_result.mac_mismatch {
+                                codes::MAC_MISMATCH
+                            } else {
+                                codes::CHECKSUM_MISMATCH
+                            };
+                            warn!(code = %code, "{} {}", self.symbols.fail(), file_record.path);
+                            self.report(file_record, VerifyOutcome::Corrupted);
+                            if self.update
+                                && let Err(e) = self
+                                    .context
+                                    .database
+                                    .record_verification_failure(
+                                        &file_record.path,
+                                        algorithm,
+                                        expected_checksum,
+                                        &verification_result.actual_checksum,
+                                    )
+                                    .await
+                            {
+                                warn!("Failed to record failure of {}: {}", file_record.path, e);
+                            }
+                            MismatchKind::Corrupted
+                        };
 
+                        let damaged_ranges = if kind == MismatchKind::Corrupted
+                            && algorithm == HashAlgorithm::Blake3
+                            && !verification_result.mac_mismatch
+                        {
+                            self.locate_damage(file_record).await
+                        } else {
+                            Vec::new()
+                        };
+                        let quarantined_to = if kind == MismatchKind::Corrupted
+                            && !verification_result.mac_mismatch
+                            && self.context.config.verify.quarantine
+                            && self.update
+                        {
+                            match self
+                                .quarantine(
+                                    file_record,
+                                    &verification_result.actual_checksum,
+                                    action_id,
+                                )
+                                .await
+                            {
+                                Ok(Some(quarantined_to)) => {
+                                    result.action_id = Some(action_id);
+                                    Some(quarantined_to)
+                                }
+                                Ok(None) => None,
+                                Err(e) => {
+                                    warn!("Failed to quarantine {}: {}", file_record.path, e);
+                                    None
+                                }
+                            }
+                        } else {
+                            None
+                        };
                         result.failures.push(IntegrityFailure {
                             file_path: file_record.path.clone(),
-                            expected_checksum: file_record.b3sum.clone(),
+                            kind,
+                            expected_checksum: expected_checksum.to_string(),
                             actual_checksum: verification_result.actual_checksum,
+                            mac_mismatch: verification_result.mac_mismatch,
+                            damaged_ranges,
+                            quarantined_to,
                         });
                     }
                 }
                 Err(e) => {
                     warn!("Error verifying {}: {}", file_record.path, e);
                     result.failed_files += 1;
+                    failed_devs.push(file_record.dev);
+                    self.report(file_record, VerifyOutcome::Error);
                 }
             }
         }
 
+        result.failures_by_device = self.group_by_device(&failed_devs).await?;
+        if self.context.config.verify.objects {
+            self.verify_objects(&files_to_check, &mut result);
+        }
+
         self.display_summary(&result);
+        if let Some(notification) = notifications::verify_notification(
+            &self.context.config.notifications,
+            self.context.repo.root(),
+            &result,
+        ) {
+            notifications::send(notification).await;
+        }
+        progress.checked.extend(progress.current.take());
+        self.checkpoint(&mut progress, &result, true).await?;
+        if self.update {
+            self.context
+                .database
+                .finish_verify_session(action_id)
+                .await
+                .during(Operation::FinishingVerify)?;
+        }
         Ok(result)
     }
 
-    /// Get files that need verification based on last_checked timestamps and optional path filter
+    /// Compare `directory`, a copy of the repository such as a restored
+    /// backup, with the tracked files by relative path: each tracked file must
+    /// be there with its recorded `algorithm` checksum. Files the repository
+    /// does not track are listed as extra. Nothing is recorded.
+    pub async fn external(
+        &self,
+        directory: &Path,
+        path_filter: Option<&PathFilter>,
+        algorithm: HashAlgorithm,
+    ) -> Result<ExternalVerification> {
+        let directory = directory
+            .canonicalize()
+            .context(Operation::Resolving, directory)?;
+        if directory.starts_with(self.context.repo.root().canonicalize()?) {
+            return Err(DdriveError::Validation {
+                message: format!(
+                    "{} is inside the repository; run verify without --external",
+                    directory.display()
+                ),
+            });
+        }
+
+        let (files, filtered_files) =
+            apply_path_filter(self.context.database.get_all_files().await?, path_filter);
+        let mut result = ExternalVerification {
+            filtered_files,
+            ..Default::default()
+        };
+        info!(
+            "{}",
+            tr!(
+                "verify-external-start",
+                count = format_count(files.len()),
+                directory = directory.display()
+            )
+        );
+
+        let tracked: HashSet<&str> = files.iter().map(|file| file.path.as_str()).collect();
+        // Hidden and ignored files count as well: a restore should not add any
+        let mut found = scanner::get_all_files(&directory, &directory, WalkFilter::ALL)
+            .context(Operation::Scanning, &directory)?;
+        found.sort_by(|a, b| a.path.cmp(&b.path));
+        result.extra = found
+            .iter()
+            .map(|file| to_storage_path(&file.path))
+            .filter(|path| !tracked.contains(path.as_str()))
+            .filter(|path| path_filter.is_none_or(|filter| filter.matches(path)))
+            .collect();
+
+        for file in &files {
+            let Some(expected) = file.checksum(algorithm) else {
+                result.skipped_files += 1;
+                continue;
+            };
+            let path = directory.join(from_storage_path(&file.path));
+            let size = match fs::metadata(&path) {
+                Ok(metadata) if metadata.is_file() => metadata.len(),
+                Ok(_) => {
+                    result.missing.push(file.path.clone());
+                    continue;
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                    result.missing.push(file.path.clone());
+                    continue;
+                }
+                Err(e) => {
+                    warn!("Failed to read {}: {}", path.display(), e);
+                    result.unreadable.push(file.path.clone());
+                    continue;
+                }
+            };
+            // A different size is a different file; no need to read it
+            if size != file.size as u64 {
+                result.mismatched.push(file.path.clone());
+                continue;
+            }
+            match self
+                .processor
+                .calculate_single_checksum_with(&path, algorithm)
+            {
+                Ok(actual) if actual == expected => {
+                    result.matched_files += 1;
+                    result.matched_bytes += size;
+                }
+                Ok(_) => result.mismatched.push(file.path.clone()),
+                Err(e) => {
+                    warn!("Failed to hash {}: {}", path.display(), e);
+                    result.unreadable.push(file.path.clone());
+                }
+            }
+        }
+
+        self.display_external(&directory, &result);
+        Ok(result)
+    }
+
+    fn display_external(&self, directory: &Path, result: &ExternalVerification) {
+        info!(
+            "{}",
+            tr!(
+                "verify-external-matched",
+                count = format_count(result.matched_files),
+                size = self.context.format_size(result.matched_bytes)
+            )
+        );
+        if result.skipped_files > 0 {
+            info!(
+                "{}",
+                tr!("verify-missing-checksum", count = result.skipped_files)
+            );
+        }
+        if result.filtered_files > 0 {
+            info!("{}", tr!("verify-filtered", count = result.filtered_files));
+        }
+
+        let count = |paths: &[String]| format_count(paths.len());
+        let sections = [
+            (
+                tr!("verify-external-missing", count = count(&result.missing)),
+                &result.missing,
+                true,
+            ),
+            (
+                tr!(
+                    "verify-external-mismatched",
+                    count = count(&result.mismatched)
+                ),
+                &result.mismatched,
+                true,
+            ),
+            (
+                tr!(
+                    "verify-external-unreadable",
+                    count = count(&result.unreadable)
+                ),
+                &result.unreadable,
+                true,
+            ),
+            (
+                tr!("verify-external-extra", count = count(&result.extra)),
+                &result.extra,
+                false,
+            ),
+        ];
+        for (header, paths, failure) in sections {
+            if paths.is_empty() {
+                continue;
+            }
+            if failure {
+                warn!(code = %codes::RESTORE_MISMATCH, "{header}");
+            } else {
+                info!("{header}");
+            }
+            for path in paths.iter().take(EXTERNAL_PREVIEW) {
+                if failure {
+                    warn!("  {path}");
+                } else {
+                    info!("  {path}");
+                }
+            }
+            if paths.len() > EXTERNAL_PREVIEW {
+                info!(
+                    "{}",
+                    tr!("status-and-more", count = paths.len() - EXTERNAL_PREVIEW)
+                );
+            }
+        }
+
+        if result.passed() {
+            info!(
+                "{}",
+                tr!(
+                    "verify-external-passed",
+                    success = self.symbols.success(),
+                    directory = directory.display()
+                )
+            );
+        }
+    }
+
+    /// Record the files checked since the last checkpoint in the run's
+    /// session once there are enough of them, or now with `flush`
+    async fn checkpoint(
+        &self,
+        progress: &mut SessionProgress,
+        result: &VerifyResult,
+        flush: bool,
+    ) -> Result<()> {
+        if !self.update {
+            progress.checked.clear();
+            return Ok(());
+        }
+        if !flush && progress.checked.len() < CHECKPOINT_FILES {
+            return Ok(());
+        }
+        let errors = result.failed_files + result.object_failures.len();
+        self.context
+            .database
+            .record_verify_progress(
+                progress.action_id,
+                &progress.checked,
+                progress.since.elapsed(),
+                result.checked_files - progress.files,
+                result.bytes_hashed - progress.bytes,
+                errors - progress.errors,
+            )
+            .await
+            .during(Operation::SavingVerifyProgress)?;
+        progress.checked.clear();
+        progress.since = Instant::now();
+        progress.files = result.checked_files;
+        progress.bytes = result.bytes_hashed;
+        progress.errors = errors;
+        Ok(())
+    }
+
+    /// Count failed files per device, labelled with the mount point and file
+    /// system they were last seen on
+    async fn group_by_device(&self, failed_devs: &[Option<i64>]) -> Result<Vec<(String, usize)>> {
+        if failed_devs.is_empty() {
+            return Ok(Vec::new());
+        }
+        let devices = self.context.database.get_devices().await?;
+        let mut counts: BTreeMap<String, usize> = BTreeMap::new();
+        for dev in failed_devs {
+            let label = match dev {
+                Some(dev) => devices
+                    .get(dev)
+                    .map(|device| device.label())
+                    .unwrap_or_else(|| format!("device {}", dev)),
+                None => "unknown device".to_string(),
+            };
+            *counts.entry(label).or_default() += 1;
+        }
+        let mut counts: Vec<_> = counts.into_iter().collect();
+        counts.sort_by_key(|(_, failed_files)| std::cmp::Reverse(*failed_files));
+        Ok(counts)
+    }
+
+    fn report(&self, file_record: &FileRecord, outcome: VerifyOutcome) {
+        self.context.emit(ProgressEvent::VerifyResult {
+            path: file_record.path.clone(),
+            outcome,
+        });
+    }
+
+    async fn resolve_failure(&self, file_record: &FileRecord, resolution: FailureResolution) {
+        if !self.update {
+            return;
+        }
+        if let Err(e) = self
+            .context
+            .database
+            .resolve_verification_failures(&file_record.path, resolution)
+            .await
+        {
+            warn!(
+                "Failed to resolve recorded failure of {}: {}",
+                file_record.path, e
+            );
+        }
+    }
+
+    /// Get files that need verification based on last_checked timestamps and optional path filter.
+    /// Also returns how many files the path filter and `since` left out. With
+    /// either, the candidates are selected in SQL and the schedule is applied
+    /// to them here.
     async fn get_files_for_verification(
         &self,
-        path_filter: Option<&Pattern>,
+        path_filter: Option<&PathFilter>,
+        since: Option<DateTime<Utc>>,
         force: bool,
         config: &Config,
-    ) -> Result<Vec<FileRecord>> {
-        let mut files = if force {
-            // When force is true, get all files regardless of last_checked timestamp
-            self.context.database.get_all_files().await?
+    ) -> Result<(Vec<FileRecord>, usize)> {
+        let database = &self.context.database;
+        let (mut files, filtered_files) = if path_filter.is_none() && since.is_none() {
+            let files = if force {
+                // When force is true, get all files regardless of last_checked timestamp
+                database.get_all_files().await?
+            } else {
+                match config.verify.schedule {
+                    // Least recently checked files first, trimmed to this run's share below
+                    VerifySchedule::Spread => database.get_files_by_last_checked().await?,
+                    // Files that haven't been checked within the configured interval
+                    VerifySchedule::Cutoff => {
+                        database
+                            .get_files_not_checked_since(config.verify.cutoff_date())
+                            .await?
+                    }
+                }
+            };
+            (files, 0)
         } else {
-            // Otherwise, get files that haven't been checked within the configured interval
-            self.context
-                .database
-                .get_files_not_checked_since(config.verify.cutoff_date())
-                .await?
+            let prefix = path_filter.map_or("", PathFilter::sql_prefix);
+            let mut files = match since {
+                Some(since) => database.get_files_changed_since(since, prefix).await?,
+                None => database.get_files_by_path_prefix(prefix).await?,
+            };
+            if let Some(filter) = path_filter {
+                files.retain(|file| filter.matches(&file.path));
+            }
+            let tracked_files = database.get_totals().await?.tracked_files as usize;
+            let filtered_files = tracked_files.saturating_sub(files.len());
+            if !force {
+                files = due_files(files, config);
+            }
+            (files, filtered_files)
+        };
+
+        if !force && config.verify.schedule == VerifySchedule::Spread {
+            let files_per_run = config.verify.files_per_run(files.len());
+            debug!(
+                "Verifying {} of {} files this run (interval {} days)",
+                files_per_run.min(files.len()),
+                files.len(),
+                config.verify.interval_days
+            );
+            files.truncate(files_per_run);
+        }
+
+        Ok((files, filtered_files))
+    }
+
+    /// Verify a file, retrying with exponential backoff after read errors that
+    /// may be transient (e.g. a drive spinning up or a flaky connection)
+    async fn verify_with_retry(
+        &self,
+        file_record: &FileRecord,
+        expected_checksum: &str,
+        algorithm: HashAlgorithm,
+        force: bool,
+    ) -> Result<VerificationResult> {
+        let config = &self.context.config.verify;
+        let mut delay = Duration::from_millis(config.retry_backoff_ms);
+        let mut attempt = 0;
+        loop {
+            match self
+                .verify_file(file_record, expected_checksum, algorithm, force)
+                .await
+            {
+                Err(e) if attempt < config.retries && is_transient(&e) => {
+                    attempt += 1;
+                    warn!(
+                        "Error reading {} (retry {} of {} in {:?}): {}",
+                        file_record.path, attempt, config.retries, delay, e
+                    );
+                    tokio::time::sleep(delay).await;
+                    delay *= 2;
+                }
+                outcome => return outcome,
+            }
+        }
+    }
+
+    /// Whether a missing file lives on media that is not currently available
+    /// rather than having been deleted: its nearest existing ancestor directory
+    /// is on another device than the one recorded for the file, or, if no
+    /// device was recorded, its parent directory is gone as well.
+    fn is_unavailable(&self, file_record: &FileRecord) -> bool {
+        let Ok(path) = self.resolve_absolute_path(&file_record.path) else {
+            return false;
         };
+        if path.exists() {
+            return false;
+        }
 
-        if let Some(filter) = path_filter {
-            files.retain(|file| filter.matches(&file.path));
+        let parent = path.parent();
+        let mut ancestor = parent;
+        while let Some(dir) = ancestor {
+            if dir.exists() {
+                break;
+            }
+            ancestor = dir.parent();
         }
+        let Some(existing) = ancestor else {
+            return true;
+        };
 
-        Ok(files)
+        let current_device = fs::metadata(existing)
+            .ok()
+            .and_then(|metadata| file_identity(&metadata))
+            .map(|(dev, _)| dev);
+        match (file_record.dev, current_device) {
+            (Some(recorded), Some(current)) => recorded as u64 != current,
+            _ => Some(existing) != parent,
+        }
     }
 
     /// Verify a single file's integrity
@@ -139,6 +909,8 @@ impl<'a> VerifyCommand<'a> {
     async fn verify_file(
         &self,
         file_record: &FileRecord,
+        expected_checksum: &str,
+        algorithm: HashAlgorithm,
         force: bool,
     ) -> Result<VerificationResult> {
         let absolute_path = self.resolve_absolute_path(&file_record.path)?;
@@ -149,22 +921,29 @@ impl<'a> VerifyCommand<'a> {
             });
         }
 
+        // Size or modification time differing from the record tells an edit apart
+        // from corruption, which changes content but leaves metadata alone
+        let metadata_changed = self
+            .check_metadata_changes(&absolute_path, file_record)
+            .unwrap_or(true);
+
         // If force is true, skip metadata check and go straight to checksum verification
-        if !force {
+        if !force && self.context.config.verify.metadata_shortcut {
             // First check metadata (size, modified time) before expensive checksum calculation
             // This is a significant optimization for large files that haven't changed
-            if let Ok(metadata_changed) = self.check_metadata_changes(&absolute_path, file_record) {
-                if !metadata_changed {
-                    // Metadata hasn't changed, assume file is still valid without calculating checksum
-                    debug!(
-                        "Skipping checksum verification for {} (metadata unchanged)",
-                        file_record.path
-                    );
-                    return Ok(VerificationResult {
-                        passed: true,
-                        actual_checksum: file_record.b3sum.clone(),
-                    });
-                }
+            if !metadata_changed {
+                // Metadata hasn't changed, assume file is still valid without calculating checksum
+                debug!(
+                    "Skipping checksum verification for {} (metadata unchanged)",
+                    file_record.path
+                );
+                return Ok(VerificationResult {
+                    passed: true,
+                    actual_checksum: expected_checksum.to_string(),
+                    mac_mismatch: false,
+                    metadata_changed,
+                    bytes_hashed: None,
+                });
             }
         }
 
@@ -173,12 +952,45 @@ impl<'a> VerifyCommand<'a> {
             "Performing full checksum verification for {}",
             file_record.path
         );
-        let actual_checksum = self.processor.calculate_single_checksum(&absolute_path)?;
-        let passed = actual_checksum == file_record.b3sum;
+        let bytes_hashed = fs::metadata(&absolute_path).map(|m| m.len()).ok();
+        // With a MAC key, BLAKE3 verification also checks the keyed MAC in the same pass
+        if algorithm == HashAlgorithm::Blake3
+            && let Some(recorded_mac) = &file_record.mac
+            && self
+                .processor
+                .has_secondary_checksums(file_record.size as u64)
+        {
+            let (actual_checksum, secondary) = self
+                .processor
+                .calculate_single_checksums(&absolute_path)
+                .context(Operation::Hashing, &absolute_path)?;
+            let checksum_matches = actual_checksum == expected_checksum;
+            let mac_mismatch = checksum_matches
+                && secondary
+                    .mac
+                    .as_ref()
+                    .is_some_and(|mac| mac != recorded_mac);
+            return Ok(VerificationResult {
+                passed: checksum_matches && !mac_mismatch,
+                actual_checksum,
+                mac_mismatch,
+                metadata_changed,
+                bytes_hashed,
+            });
+        }
+
+        let actual_checksum = self
+            .processor
+            .calculate_single_checksum_with(&absolute_path, algorithm)
+            .context(Operation::Hashing, &absolute_path)?;
+        let passed = actual_checksum == expected_checksum;
 
         Ok(VerificationResult {
             passed,
             actual_checksum,
+            mac_mismatch: false,
+            metadata_changed,
+            bytes_hashed,
         })
     }
 
@@ -203,60 +1015,467 @@ impl<'a> VerifyCommand<'a> {
         }
 
         // Only check modified time if size is the same
-        let modified_time_changed = if let Ok(modified) = metadata.modified() {
-            // Convert file's modified time to naive datetime for comparison
-            if let Ok(system_time_since_epoch) = modified.duration_since(std::time::UNIX_EPOCH) {
-                let file_modified = DateTime::from_timestamp(
-                    system_time_since_epoch.as_secs() as i64,
-                    system_time_since_epoch.subsec_nanos(),
-                )
-                .map(|dt| dt.naive_utc());
-
-                if let Some(file_modified) = file_modified {
-                    // Allow for small timestamp differences (1 second) due to filesystem precision
-                    let time_diff = (file_modified - file_record.updated_at).num_seconds().abs();
-                    time_diff > 1
-                } else {
-                    true // Couldn't parse time, assume changed
+        let modified_time_changed = match metadata.modified().map(|m| m.duration_since(UNIX_EPOCH))
+        {
+            Ok(Ok(since_epoch)) => match file_record.mtime_ns {
+                // Nanosecond precision record: require an exact match
+                Some(mtime_ns) => since_epoch.as_nanos() as i64 != mtime_ns,
+                None => {
+                    // Allow for small timestamp differences (1 second) for second-precision records
+                    let recorded = file_record.updated_at.and_utc().timestamp();
+                    (since_epoch.as_secs() as i64 - recorded).abs() > 1
                 }
-            } else {
-                true // Couldn't get duration, assume changed
-            }
-        } else {
-            true // Couldn't get modified time, assume changed
+            },
+            _ => true, // Couldn't get modified time, assume changed
         };
 
         Ok(size_changed || modified_time_changed)
     }
 
+    /// Compare recorded permissions, ownership and xattrs against the file on disk
+    fn check_attribute_drift(&self, file_record: &FileRecord) -> Option<MetadataDrift> {
+        let absolute_path = self.resolve_absolute_path(&file_record.path).ok()?;
+        let current = FileAttributes::read(&absolute_path, &self.context.config.metadata).ok()?;
+        let changes = FileAttributes::from_record(file_record).drift(&current);
+        if changes.is_empty() {
+            return None;
+        }
+        Some(MetadataDrift {
+            file_path: file_record.path.clone(),
+            changes,
+        })
+    }
+
+    /// Re-hash the stored objects holding the content of `files`, so damage to
+    /// the copies that would be restored from is caught too. Files that were
+    /// never stored are skipped.
+    fn verify_objects(&self, files: &[FileRecord], result: &mut VerifyResult) {
+        let mut objects: BTreeMap<&str, Vec<String>> = BTreeMap::new();
+        for file in files.iter().filter(|file| file.stored) {
+            objects
+                .entry(file.b3sum.as_str())
+                .or_default()
+                .push(file.path.clone());
+        }
+        info!("Verifying {} stored objects", objects.len());
+
+        let tmp_dir = self.context.repo.tmp_dir();
+        for (b3sum, paths) in objects {
+            result.checked_objects += 1;
+            let actual =
+                backend::with_object(self.context.objects.as_ref(), b3sum, &tmp_dir, |path| {
+                    self.processor.calculate_single_checksum(path)
+                });
+            match actual {
+                Ok(None) => {
+                    warn!(
+                        code = %codes::OBJECT_DAMAGED,
+                        "{} object {} is missing",
+                        self.symbols.fail(),
+                        b3sum
+                    );
+                    result.object_failures.push(ObjectFailure {
+                        b3sum: b3sum.to_string(),
+                        paths,
+                        actual_checksum: None,
+                    });
+                }
+                Ok(Some(actual)) => {
+                    if actual == b3sum {
+                        debug!("{} object {}", self.symbols.pass(), b3sum);
+                        continue;
+                    }
+                    warn!(code = %codes::OBJECT_DAMAGED, "{} object {}", self.symbols.fail(), b3sum);
+                    result.object_failures.push(ObjectFailure {
+                        b3sum: b3sum.to_string(),
+                        paths,
+                        actual_checksum: Some(actual),
+                    });
+                }
+                Err(e) => {
+                    warn!(code = %codes::OBJECT_DAMAGED, "Error verifying object {}: {}", b3sum, e);
+                    result.object_failures.push(ObjectFailure {
+                        b3sum: b3sum.to_string(),
+                        paths,
+                        actual_checksum: None,
+                    });
+                }
+            }
+        }
+    }
+
+    /// Move a corrupted file into this run's quarantine directory and put the
+    /// stored copy back in its place, provided the stored copy is intact.
+    /// Returns where the corrupted file was moved.
+    async fn quarantine(
+        &self,
+        file_record: &FileRecord,
+        actual_checksum: &str,
+        action_id: i64,
+    ) -> Result<Option<PathBuf>> {
+        if !file_record.stored {
+            warn!(
+                code = %codes::RESTORE_SKIPPED,
+                "{} has no copy in the object store, leaving it in place",
+                file_record.path
+            );
+            return Ok(None);
+        }
+        // The stored copy is fetched first, so the file is only moved once
+        // there is an intact copy to put back
+        let absolute_path = self.resolve_absolute_path(&file_record.path)?;
+        let tmp_dir = self.context.repo.tmp_dir();
+        fs::create_dir_all(&tmp_dir).context(Operation::Creating, &tmp_dir)?;
+        let restore_path = tmp_dir.join(format!(
+            "restore-{}-{}",
+            std::process::id(),
+            &file_record.b3sum
+        ));
+        let fetched = backend::with_object(
+            self.context.objects.as_ref(),
+            &file_record.b3sum,
+            &tmp_dir,
+            |object_path| {
+                let checksum = self
+                    .processor
+                    .calculate_single_checksum(object_path)
+                    .context(Operation::HashingStoredCopy, object_path)?;
+                if checksum != file_record.b3sum {
+                    return Ok(false);
+                }
+                reflink_copy::reflink_or_copy(object_path, &restore_path)
+                    .context(Operation::FetchingStoredCopy, &restore_path)?;
+                Ok(true)
+            },
+        )
+        .inspect_err(|_| {
+            let _ = fs::remove_file(&restore_path);
+        })?;
+        match fetched {
+            None => {
+                warn!(
+                    code = %codes::RESTORE_SKIPPED,
+                    "No stored copy of {} to restore, leaving it in place",
+                    file_record.path
+                );
+                return Ok(None);
+            }
+            Some(false) => {
+                warn!(
+                    code = %codes::RESTORE_SKIPPED,
+                    "Stored copy of {} is damaged too, leaving it in place",
+                    file_record.path
+                );
+                return Ok(None);
+            }
+            Some(true) => {}
+        }
+
+        let quarantine_path = self
+            .context
+            .repo
+            .quarantine_dir(action_id)
+            .join(from_storage_path(&file_record.path));
+        if let Some(parent) = quarantine_path.parent() {
+            fs::create_dir_all(parent).context(Operation::Creating, parent)?;
+        }
+        if fs::rename(&absolute_path, &quarantine_path).is_err() {
+            // Quarantine may be on another file system than the file
+            fs::copy(&absolute_path, &quarantine_path)
+                .context(Operation::CopyingToQuarantine, &absolute_path)?;
+            fs::remove_file(&absolute_path).context(Operation::Removing, &absolute_path)?;
+        }
+        if fs::rename(&restore_path, &absolute_path).is_err() {
+            reflink_copy::reflink_or_copy(&restore_path, &absolute_path)
+                .context(Operation::RestoringStoredCopy, &absolute_path)?;
+            fs::remove_file(&restore_path).context(Operation::Removing, &restore_path)?;
+        }
+        self.restore_metadata(file_record, &absolute_path);
+
+        let quarantined_to = quarantine_path
+            .strip_prefix(self.context.repo.root())
+            .map(to_storage_path)
+            .unwrap_or_default();
+        self.context
+            .database
+            .insert_history_entries(
+                action_id,
+                ActionType::Quarantine,
+                &[(
+                    file_record.path.clone(),
+                    Some(file_record.b3sum.clone()),
+                    Some(file_record.size),
+                )],
+                Some(serde_json::json!({
+                    "quarantined_to": quarantined_to,
+                    "actual_checksum": actual_checksum,
+                })),
+            )
+            .await
+            .context(Operation::RecordingQuarantine, &absolute_path)?;
+        self.context.emit(ProgressEvent::ActionCommitted {
+            action_id,
+            action_type: ActionType::Quarantine,
+            files: 1,
+        });
+        self.resolve_failure(file_record, FailureResolution::Restored)
+            .await;
+
+        info!(
+            "Quarantined {} to {} and restored the stored copy",
+            file_record.path, quarantined_to
+        );
+        Ok(Some(quarantine_path))
+    }
+
+    /// Reapply the recorded modification time and attributes to a restored file
+    fn restore_metadata(&self, file_record: &FileRecord, path: &Path) {
+        if let Some(mtime_ns) = file_record.mtime_ns {
+            let modified = UNIX_EPOCH + Duration::from_nanos(mtime_ns as u64);
+            if let Err(e) = fs::File::options()
+                .write(true)
+                .open(path)
+                .and_then(|file| file.set_modified(modified))
+            {
+                warn!(
+                    "Failed to restore modification time of {}: {}",
+                    file_record.path, e
+                );
+            }
+        }
+        if let Err(e) = FileAttributes::from_record(file_record).apply(path) {
+            warn!(
+                "Failed to restore attributes of {}: {}",
+                file_record.path, e
+            );
+        }
+    }
+
+    /// Compare recorded chunk hashes against the current file to find which
+    /// byte ranges are damaged. Empty if no chunk hashes were recorded.
+    async fn locate_damage(&self, file_record: &FileRecord) -> Vec<(u64, u64)> {
+        let recorded = match self
+            .context
+            .database
+            .get_chunk_hashes(&file_record.b3sum)
+            .await
+        {
+            Ok(Some(recorded)) => recorded,
+            Ok(None) => return Vec::new(),
+            Err(e) => {
+                warn!(
+                    "Could not load chunk hashes for {}: {}",
+                    file_record.path, e
+                );
+                return Vec::new();
+            }
+        };
+        let Ok(absolute_path) = self.resolve_absolute_path(&file_record.path) else {
+            return Vec::new();
+        };
+        match self
+            .processor
+            .calculate_single_chunk_hashes(&absolute_path, recorded.chunk_size)
+        {
+            Ok(current) => recorded.damaged_ranges(&current, file_record.size as u64),
+            Err(e) => {
+                warn!("Could not hash chunks of {}: {}", file_record.path, e);
+                Vec::new()
+            }
+        }
+    }
+
     /// Convert relative path from database to absolute path for file access
     fn resolve_absolute_path(&self, relative_path: &str) -> Result<std::path::PathBuf> {
-        Ok(self.context.repo.root().join(relative_path))
+        Ok(self
+            .context
+            .repo
+            .root()
+            .join(from_storage_path(relative_path)))
     }
 
     /// Display summary of check results
     fn display_summary(&self, result: &VerifyResult) {
         info!(
-            "Verification complete: {}/{} passed, {} failed, {} skipped",
-            result.passed_files, result.checked_files, result.failed_files, result.skipped_files
+            "{}",
+            tr!(
+                "verify-complete",
+                passed = result.passed_files,
+                checked = result.checked_files,
+                modified = result.modified_files,
+                failed = result.failed_files,
+                skipped = result.skipped_files
+            )
+        );
+        info!(
+            "{}",
+            tr!(
+                "verify-read",
+                files = result.hashed_files,
+                size = self.context.format_size(result.bytes_hashed),
+                unchanged = result.metadata_skipped
+            )
         );
+        if result.missing_checksum > 0 {
+            info!(
+                "{}",
+                tr!("verify-missing-checksum", count = result.missing_checksum)
+            );
+        }
+        if result.filtered_files > 0 {
+            info!("{}", tr!("verify-filtered", count = result.filtered_files));
+        }
 
-        if !result.failures.is_empty() {
-            warn!("Integrity failures:");
+        if result.modified_files > 0 {
+            info!("{}", tr!("verify-modified-header"));
             for failure in &result.failures {
-                warn!("  {}: checksum mismatch", failure.file_path);
-                warn!("    Expected: {}", failure.expected_checksum);
-                warn!("    Actual:   {}", failure.actual_checksum);
+                if failure.kind == MismatchKind::Modified {
+                    info!("  {}", failure.file_path);
+                }
+            }
+        }
+
+        if result
+            .failures
+            .iter()
+            .any(|failure| failure.kind == MismatchKind::Corrupted)
+        {
+            warn!("{}", tr!("verify-failures-header"));
+            for failure in &result.failures {
+                if failure.kind != MismatchKind::Corrupted {
+                    continue;
+                }
+                if failure.mac_mismatch {
+                    warn!(
+                        code = %codes::MAC_MISMATCH,
+                        "{}",
+                        tr!("verify-mac-mismatch", path = failure.file_path)
+                    );
+                    continue;
+                }
+                warn!(
+                    code = %codes::CHECKSUM_MISMATCH,
+                    "{}",
+                    tr!("verify-checksum-mismatch", path = failure.file_path)
+                );
+                warn!(
+                    "{}",
+                    tr!("verify-expected", checksum = failure.expected_checksum)
+                );
+                warn!(
+                    "{}",
+                    tr!("verify-actual", checksum = failure.actual_checksum)
+                );
+                if !failure.damaged_ranges.is_empty() {
+                    let ranges: Vec<String> = failure
+                        .damaged_ranges
+                        .iter()
+                        .map(|(start, end)| format!("{}-{}", start, end))
+                        .collect();
+                    warn!(
+                        "{}",
+                        tr!("verify-damaged-bytes", ranges = ranges.join(", "))
+                    );
+                }
+                if let Some(quarantined_to) = &failure.quarantined_to {
+                    warn!(
+                        "{}",
+                        tr!("verify-quarantined", path = quarantined_to.display())
+                    );
+                }
+            }
+        }
+
+        if !result.metadata_drift.is_empty() {
+            warn!(code = %codes::METADATA_DRIFT, "{}", tr!("verify-drift-header"));
+            for drift in &result.metadata_drift {
+                warn!("  {}: {}", drift.file_path, drift.changes.join(", "));
+            }
+        }
+
+        if result.checked_objects > 0 {
+            info!(
+                "{}",
+                tr!(
+                    "verify-objects-intact",
+                    intact = result.checked_objects - result.object_failures.len(),
+                    checked = result.checked_objects
+                )
+            );
+        }
+        if !result.object_failures.is_empty() {
+            warn!("{}", tr!("verify-objects-header"));
+            for failure in &result.object_failures {
+                match &failure.actual_checksum {
+                    Some(_) => warn!("{}", tr!("verify-checksum-mismatch", path = failure.b3sum)),
+                    None => warn!("{}", tr!("verify-object-missing", checksum = failure.b3sum)),
+                }
+                for path in &failure.paths {
+                    warn!("{}", tr!("verify-object-held", path = path));
+                }
+            }
+        }
+
+        if !result.failures_by_device.is_empty() {
+            warn!("{}", tr!("verify-devices-header"));
+            for (device, failed_files) in &result.failures_by_device {
+                warn!(
+                    "{}",
+                    tr!("verify-device-line", device = device, count = failed_files)
+                );
+            }
+        }
+
+        if !result.unavailable_files.is_empty() {
+            warn!(
+                code = %codes::MEDIA_UNAVAILABLE,
+                "{}",
+                tr!("verify-unavailable", count = result.unavailable_files.len())
+            );
+            for path in result.unavailable_files.iter().take(5) {
+                warn!("  {}", path);
+            }
+            if result.unavailable_files.len() > 5 {
+                warn!(
+                    "  {}",
+                    tr!(
+                        "status-and-more",
+                        count = result.unavailable_files.len() - 5
+                    )
+                );
             }
         }
 
         if result.failed_files > 0 {
             warn!(
-                "⚠️  {} file(s) failed integrity verification!",
-                result.failed_files
+                code = %codes::CHECKSUM_MISMATCH,
+                "{}",
+                tr!(
+                    "verify-failed",
+                    warning = self.symbols.warning(),
+                    count = result.failed_files
+                )
+            );
+        } else if !result.object_failures.is_empty() {
+            warn!(
+                code = %codes::OBJECT_DAMAGED,
+                "{}",
+                tr!(
+                    "verify-objects-failed",
+                    warning = self.symbols.warning(),
+                    count = result.object_failures.len()
+                )
+            );
+        } else if result.modified_files > 0 {
+            info!(
+                "{}",
+                tr!("verify-only-modified", count = result.modified_files)
             );
         } else if result.checked_files > 0 {
-            info!("✅ All files passed integrity verification!");
+            info!(
+                "{}",
+                tr!("verify-all-passed", success = self.symbols.success())
+            );
         }
     }
 }
@@ -265,4 +1484,824 @@ impl<'a> VerifyCommand<'a> {
 struct VerificationResult {
     passed: bool,
     actual_checksum: String,
+    mac_mismatch: bool,
+    metadata_changed: bool,
+    /// Size of the file when it was read and hashed; `None` if it passed on
+    /// unchanged metadata alone
+    bytes_hashed: Option<u64>,
+}
+
+/// Whether a verification error may go away when the file is read again.
+/// Missing files and permission problems are not retried.
+fn is_transient(error: &DdriveError) -> bool {
+    match error.root() {
+        DdriveError::Io(e) => !matches!(
+            e.kind(),
+            std::io::ErrorKind::NotFound | std::io::ErrorKind::PermissionDenied
+        ),
+        DdriveError::Checksum { .. } => true,
+        _ => false,
+    }
+}
+
+/// Files checked between writes of a run's progress to its session
+const CHECKPOINT_FILES: usize = 100;
+
+/// Paths listed per section of a `verify --external` report
+const EXTERNAL_PREVIEW: usize = 20;
+
+/// Progress of a verify run not yet written to its session
+struct SessionProgress {
+    action_id: i64,
+    /// The file being checked
+    current: Option<String>,
+    /// Files gone through since the last checkpoint
+    checked: Vec<String>,
+    since: Instant,
+    /// Checked files, bytes hashed and errors of the result at the last checkpoint
+    files: usize,
+    bytes: u64,
+    errors: usize,
+}
+
+impl SessionProgress {
+    fn new(action_id: i64, since: Instant) -> Self {
+        Self {
+            action_id,
+            current: None,
+            checked: Vec::new(),
+            since,
+            files: 0,
+            bytes: 0,
+            errors: 0,
+        }
+    }
+}
+
+/// The filters of a verify run, which an interrupted run must share with the
+/// run that continues it
+fn session_parameters(
+    path_filter: Option<&PathFilter>,
+    since: Option<DateTime<Utc>>,
+    force: bool,
+    algorithm: HashAlgorithm,
+    failed_only: bool,
+) -> String {
+    serde_json::json!({
+        "path": path_filter.map(PathFilter::as_str),
+        "since": since,
+        "force": force,
+        "algorithm": algorithm,
+        "failed": failed_only,
+    })
+    .to_string()
+}
+
+/// Which tracked files `verify --path` selects
+#[derive(Debug, Clone)]
+pub enum PathFilter {
+    /// A directory and everything under it, or a single file (`photos/2024`)
+    Prefix(String),
+    /// A glob pattern over storage paths (`photos/*/raw/*.cr2`)
+    Glob(Pattern),
+}
+
+impl PathFilter {
+    pub fn matches(&self, path: &str) -> bool {
+        match self {
+            PathFilter::Prefix(prefix) => {
+                prefix.is_empty()
+                    || path
+                        .strip_prefix(prefix.as_str())
+                        .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+            }
+            PathFilter::Glob(pattern) => pattern.matches(path),
+        }
+    }
+
+    /// The filter as given, normalized
+    pub fn as_str(&self) -> &str {
+        match self {
+            PathFilter::Prefix(prefix) => prefix,
+            PathFilter::Glob(pattern) => pattern.as_str(),
+        }
+    }
+
+    /// Literal start every matching path shares, for narrowing the query
+    fn sql_prefix(&self) -> &str {
+        match self {
+            PathFilter::Prefix(prefix) => prefix,
+            PathFilter::Glob(pattern) => {
+                let pattern = pattern.as_str();
+                &pattern[..pattern.find(GLOB_CHARS).unwrap_or(pattern.len())]
+            }
+        }
+    }
+}
+
+/// Characters that make a `--path` a glob pattern rather than a prefix
+const GLOB_CHARS: [char; 3] = ['*', '?', '['];
+
+impl FromStr for PathFilter {
+    type Err = DdriveError;
+
+    fn from_str(filter: &str) -> Result<Self> {
+        if filter.contains(GLOB_CHARS) {
+            return Ok(PathFilter::Glob(Pattern::new(filter)?));
+        }
+        let prefix = filter.trim_start_matches("./").trim_end_matches('/');
+        let prefix = if prefix == "." { "" } else { prefix };
+        Ok(PathFilter::Prefix(prefix.to_string()))
+    }
+}
+
+/// The files `config.verify.schedule` considers due, least recently checked
+/// first
+fn due_files(mut files: Vec<FileRecord>, config: &Config) -> Vec<FileRecord> {
+    match config.verify.schedule {
+        VerifySchedule::Spread => files.sort_by(|a, b| {
+            (a.last_checked.is_some(), a.last_checked, &a.path).cmp(&(
+                b.last_checked.is_some(),
+                b.last_checked,
+                &b.path,
+            ))
+        }),
+        VerifySchedule::Cutoff => {
+            let cutoff = config.verify.cutoff_date().naive_utc();
+            files.retain(|file| file.last_checked.is_none_or(|checked| checked < cutoff));
+        }
+    }
+    files
+}
+
+/// Keep the files matching `filter`, returning them with the number left out
+fn apply_path_filter(
+    mut files: Vec<FileRecord>,
+    filter: Option<&PathFilter>,
+) -> (Vec<FileRecord>, usize) {
+    let before = files.len();
+    if let Some(filter) = filter {
+        files.retain(|file| filter.matches(&file.path));
+    }
+    let filtered = before - files.len();
+    (files, filtered)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::testing::repository;
+    use crate::{
+        AddOptions, Ddrive, VerifyOptions, database::ActionStatus, repository::Repository,
+    };
+    use assert_fs::{TempDir, prelude::*};
+
+    /// Replace the content of `path` with `content` of the same size, keeping
+    /// its modification time, as silent corruption would
+    fn corrupt(path: &Path, content: &str) {
+        let modified = fs::metadata(path).unwrap().modified().unwrap();
+        fs::write(path, content).unwrap();
+        fs::File::options()
+            .write(true)
+            .open(path)
+            .unwrap()
+            .set_modified(modified)
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_modified_and_corrupted_files() {
+        let temp = TempDir::new().unwrap();
+        for name in ["edited.txt", "rotten.txt", "intact.txt"] {
+            temp.child(name).write_str("content").unwrap();
+        }
+        let ddrive = Ddrive::init(temp.path()).await.unwrap();
+        ddrive
+            .add(&[temp.path()], &AddOptions::default())
+            .await
+            .unwrap();
+
+        // New content with a new modification time is an edit; the same size
+        // and time with other content is corruption
+        let edited = temp.path().join("edited.txt");
+        let modified = fs::metadata(&edited).unwrap().modified().unwrap();
+        fs::write(&edited, "CONTENT").unwrap();
+        fs::File::options()
+            .write(true)
+            .open(&edited)
+            .unwrap()
+            .set_modified(modified + Duration::from_secs(60))
+            .unwrap();
+        corrupt(&temp.path().join("rotten.txt"), "CONTENT");
+
+        let result = ddrive
+            .verify(&VerifyOptions {
+                force: true,
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        assert_eq!(
+            (
+                result.passed_files,
+                result.modified_files,
+                result.failed_files
+            ),
+            (1, 1, 1)
+        );
+        let kinds: Vec<(&str, MismatchKind)> = result
+            .failures
+            .iter()
+            .map(|failure| (failure.file_path.as_str(), failure.kind))
+            .collect();
+        assert!(kinds.contains(&("edited.txt", MismatchKind::Modified)));
+        assert!(kinds.contains(&("rotten.txt", MismatchKind::Corrupted)));
+    }
+
+    #[tokio::test]
+    async fn test_failed_only_rechecks_recorded_failures() {
+        let temp = TempDir::new().unwrap();
+        for name in ["a.txt", "b.txt", "c.txt"] {
+            temp.child(name).write_str(name).unwrap();
+        }
+        let ddrive = Ddrive::init(temp.path()).await.unwrap();
+        ddrive
+            .add(&[temp.path()], &AddOptions::default())
+            .await
+            .unwrap();
+        let database = &ddrive.context().database;
+        let failed_paths = || async {
+            database
+                .get_unresolved_verification_failures()
+                .await
+                .unwrap()
+                .into_iter()
+                .map(|failure| failure.path)
+                .collect::<Vec<_>>()
+        };
+
+        let a = temp.path().join("a.txt");
+        corrupt(&a, "A.TXT");
+        let all = VerifyOptions {
+            force: true,
+            ..Default::default()
+        };
+        let result = ddrive.verify(&all).await.unwrap();
+        assert_eq!(result.failed_files, 1);
+        assert_eq!(failed_paths().await, ["a.txt"]);
+
+        let failed_only = VerifyOptions {
+            failed_only: true,
+            ..Default::default()
+        };
+        let result = ddrive.verify(&failed_only).await.unwrap();
+        assert_eq!((result.checked_files, result.failed_files), (1, 1));
+        assert_eq!(failed_paths().await, ["a.txt"]);
+
+        // Once the file is intact again the failure is resolved
+        corrupt(&a, "a.txt");
+        let result = ddrive.verify(&failed_only).await.unwrap();
+        assert_eq!((result.checked_files, result.passed_files), (1, 1));
+        assert!(failed_paths().await.is_empty());
+        let result = ddrive.verify(&failed_only).await.unwrap();
+        assert_eq!(result.checked_files, 0);
+    }
+
+    #[tokio::test]
+    async fn test_damaged_and_missing_objects() {
+        let temp = TempDir::new().unwrap();
+        for (name, content) in [("a.txt", "alpha"), ("b.txt", "bravo"), ("c.txt", "charlie")] {
+            temp.child(name).write_str(content).unwrap();
+        }
+        let ddrive = Ddrive::init(temp.path()).await.unwrap();
+        ddrive
+            .add(&[temp.path()], &AddOptions::default())
+            .await
+            .unwrap();
+
+        let objects = &ddrive.context().objects;
+        let [alpha, bravo] = [b"alpha".as_slice(), b"bravo"]
+            .map(|content| blake3::hash(content).to_hex().to_string());
+        fs::write(objects.local_path(&alpha).unwrap(), "ALPHA").unwrap();
+        assert!(objects.delete(&bravo).unwrap());
+
+        let result = ddrive
+            .verify(&VerifyOptions {
+                force: true,
+                objects: true,
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        // The files themselves are intact
+        assert_eq!(result.failed_files, 0);
+        assert_eq!(result.checked_objects, 3);
+        let mut failures: Vec<_> = result
+            .object_failures
+            .iter()
+            .map(|failure| {
+                (
+                    failure.b3sum.as_str(),
+                    failure.paths.clone(),
+                    failure.actual_checksum.clone(),
+                )
+            })
+            .collect();
+        failures.sort();
+        let mut expected = vec![
+            (
+                alpha.as_str(),
+                vec!["a.txt".to_string()],
+                Some(blake3::hash(b"ALPHA").to_hex().to_string()),
+            ),
+            (bravo.as_str(), vec!["b.txt".to_string()], None),
+        ];
+        expected.sort();
+        assert_eq!(failures, expected);
+    }
+
+    #[tokio::test]
+    async fn test_spread_schedule_checks_oldest_first() {
+        let temp = TempDir::new().unwrap();
+        for name in ["f0", "f1", "f2", "f3", "f4", "f5"] {
+            temp.child(format!("{name}.txt")).write_str(name).unwrap();
+        }
+        Repository::init_repository(temp.path().to_path_buf())
+            .await
+            .unwrap();
+        let context = Ddrive::builder(temp.path())
+            .configure(|config| config.verify.interval_days = 3)
+            .build()
+            .await
+            .unwrap();
+        let ddrive = Ddrive::from_context(context);
+        ddrive
+            .add(&[temp.path()], &AddOptions::default())
+            .await
+            .unwrap();
+        let pool = &ddrive.context().database.pool;
+        for (path, checked) in [
+            ("f1.txt", "2021-01-01 00:00:00"),
+            ("f2.txt", "2024-03-01 00:00:00"),
+            ("f3.txt", "2020-01-01 00:00:00"),
+            ("f4.txt", "2024-01-01 00:00:00"),
+            ("f5.txt", "2024-02-01 00:00:00"),
+        ] {
+            sqlx::query("UPDATE files SET last_checked = ?1 WHERE path = ?2")
+                .bind(checked)
+                .bind(path)
+                .execute(pool)
+                .await
+                .unwrap();
+        }
+
+        // A third of the files per run, never-checked and oldest-checked first
+        let mut covered = Vec::new();
+        for expected in [
+            ["f0.txt", "f3.txt"],
+            ["f1.txt", "f4.txt"],
+            ["f2.txt", "f5.txt"],
+        ] {
+            let result = ddrive.verify(&VerifyOptions::default()).await.unwrap();
+            assert_eq!(result.checked_files, 2);
+            let checked: Vec<String> = sqlx::query_scalar(
+                "SELECT path FROM files WHERE last_checked > '2025-01-01' ORDER BY path",
+            )
+            .fetch_all(pool)
+            .await
+            .unwrap();
+            covered.extend(expected);
+            covered.sort();
+            assert_eq!(checked, covered);
+        }
+
+        // A shorter interval checks a bigger share on each run
+        let context = Ddrive::builder(temp.path())
+            .configure(|config| config.verify.interval_days = 2)
+            .build()
+            .await
+            .unwrap();
+        let result = Ddrive::from_context(context)
+            .verify(&VerifyOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(result.checked_files, 3);
+    }
+
+    #[tokio::test]
+    async fn test_metadata_shortcut_accounting() {
+        let temp = TempDir::new().unwrap();
+        for (path, content) in [
+            ("docs/a.txt", "alpha"),
+            ("docs/b.txt", "bravo"),
+            ("docs/c.txt", "charlie"),
+            ("other/d.txt", "delta"),
+        ] {
+            temp.child(path).write_str(content).unwrap();
+        }
+        // Every due file in one run rather than a share of them
+        Repository::init_repository(temp.path().to_path_buf())
+            .await
+            .unwrap();
+        let context = Ddrive::builder(temp.path())
+            .configure(|config| config.verify.schedule = VerifySchedule::Cutoff)
+            .build()
+            .await
+            .unwrap();
+        let ddrive = Ddrive::from_context(context);
+        ddrive
+            .add(&[temp.path()], &AddOptions::default())
+            .await
+            .unwrap();
+
+        // b.txt is touched with the same content, c.txt damaged in place
+        let touched = temp.child("docs/b.txt");
+        let modified = fs::metadata(&touched).unwrap().modified().unwrap();
+        fs::File::options()
+            .write(true)
+            .open(&touched)
+            .unwrap()
+            .set_modified(modified + std::time::Duration::from_secs(60))
+            .unwrap();
+        corrupt(&temp.child("docs/c.txt"), "CHARLIE");
+
+        let options = VerifyOptions {
+            path: Some(PathFilter::Prefix("docs".to_string())),
+            no_update: true,
+            ..Default::default()
+        };
+        let trusted = ddrive.verify(&options).await.unwrap();
+        assert_eq!(trusted.filtered_files, 1);
+        assert_eq!(trusted.checked_files, 3);
+        assert_eq!((trusted.metadata_skipped, trusted.hashed_files), (2, 1));
+        assert_eq!(trusted.bytes_hashed, 5);
+        assert_eq!(
+            trusted.metadata_skipped + trusted.hashed_files,
+            trusted.checked_files
+        );
+        assert_eq!(
+            trusted.checked_files + trusted.skipped_files + trusted.filtered_files,
+            4
+        );
+        // The damage went unnoticed, as the metadata was left alone
+        assert_eq!((trusted.passed_files, trusted.failed_files), (3, 0));
+
+        let hashed = ddrive
+            .verify(&VerifyOptions {
+                metadata_shortcut: false,
+                ..options
+            })
+            .await
+            .unwrap();
+        assert_eq!(hashed.checked_files, 3);
+        assert_eq!((hashed.metadata_skipped, hashed.hashed_files), (0, 3));
+        assert_eq!(hashed.bytes_hashed, 17);
+        assert_eq!((hashed.passed_files, hashed.failed_files), (2, 1));
+        assert_eq!(hashed.failures[0].file_path, "docs/c.txt");
+    }
+
+    #[tokio::test]
+    async fn test_files_on_unavailable_media_are_skipped() {
+        let temp = TempDir::new().unwrap();
+        temp.child("disk/photos/a.jpg").write_str("a").unwrap();
+        temp.child("docs/gone.txt").write_str("gone").unwrap();
+        temp.child("docs/kept.txt").write_str("kept").unwrap();
+        let ddrive = Ddrive::init(temp.path()).await.unwrap();
+        ddrive
+            .add(&[temp.path()], &AddOptions::default())
+            .await
+            .unwrap();
+
+        // The photos were on a disk mounted at disk/, now detached: the mount
+        // point is empty and on the device of the repository. A file deleted
+        // from a directory that is still there is a failure.
+        sqlx::query("UPDATE files SET dev = dev + 1 WHERE path LIKE 'disk/%'")
+            .execute(&ddrive.context().database.pool)
+            .await
+            .unwrap();
+        fs::remove_dir_all(temp.path().join("disk/photos")).unwrap();
+        fs::remove_file(temp.path().join("docs/gone.txt")).unwrap();
+
+        let result = ddrive
+            .verify(&VerifyOptions {
+                force: true,
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        assert_eq!(result.unavailable_files, ["disk/photos/a.jpg"]);
+        assert_eq!(result.skipped_files, 1);
+        assert_eq!((result.passed_files, result.failed_files), (1, 1));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_quarantine_restores_stored_copy() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let temp = TempDir::new().unwrap();
+        for (name, content) in [
+            ("docs/a.txt", "alpha"),
+            ("b.txt", "bravo"),
+            ("c.txt", "charlie"),
+        ] {
+            temp.child(name).write_str(content).unwrap();
+        }
+        let a = temp.path().join("docs/a.txt");
+        fs::set_permissions(&a, fs::Permissions::from_mode(0o640)).unwrap();
+        let modified = fs::metadata(&a).unwrap().modified().unwrap();
+        let ddrive = Ddrive::init(temp.path()).await.unwrap();
+        ddrive
+            .add(&[temp.path()], &AddOptions::default())
+            .await
+            .unwrap();
+
+        corrupt(&a, "ALPHA");
+        fs::set_permissions(&a, fs::Permissions::from_mode(0o600)).unwrap();
+        // b.txt has no stored copy left and the one of c.txt is damaged
+        corrupt(&temp.path().join("b.txt"), "BRAVO");
+        corrupt(&temp.path().join("c.txt"), "CHARLIE");
+        let objects = &ddrive.context().objects;
+        assert!(objects.delete(&blake3::hash(b"bravo").to_hex()).unwrap());
+        let damaged = objects
+            .local_path(&blake3::hash(b"charlie").to_hex())
+            .unwrap();
+        fs::write(damaged, "CHARLIE").unwrap();
+
+        let result = ddrive
+            .verify(&VerifyOptions {
+                force: true,
+                quarantine: true,
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        assert_eq!(result.failed_files, 3);
+        let action_id = result.action_id.unwrap();
+
+        let quarantined = ddrive
+            .context()
+            .repo
+            .quarantine_dir(action_id)
+            .join("docs/a.txt");
+        assert_eq!(fs::read_to_string(&quarantined).unwrap(), "ALPHA");
+        assert_eq!(fs::read_to_string(&a).unwrap(), "alpha");
+        let metadata = fs::metadata(&a).unwrap();
+        assert_eq!(metadata.modified().unwrap(), modified);
+        assert_eq!(metadata.permissions().mode() & 0o777, 0o640);
+
+        let entries: Vec<String> = sqlx::query_scalar(
+            "SELECT path FROM history WHERE action_id = ?1 AND action_type = ?2",
+        )
+        .bind(action_id)
+        .bind(ActionType::Quarantine.to_i32())
+        .fetch_all(&ddrive.context().database.pool)
+        .await
+        .unwrap();
+        assert_eq!(entries, ["docs/a.txt"]);
+
+        // Without an intact stored copy the files stay as they are
+        for (name, content) in [("b.txt", "BRAVO"), ("c.txt", "CHARLIE")] {
+            assert_eq!(fs::read_to_string(temp.path().join(name)).unwrap(), content);
+            assert!(
+                !ddrive
+                    .context()
+                    .repo
+                    .quarantine_dir(action_id)
+                    .join(name)
+                    .exists()
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_external_copy() {
+        let temp = TempDir::new().unwrap();
+        temp.child("docs/a.txt").write_str("alpha").unwrap();
+        temp.child("docs/b.txt").write_str("beta").unwrap();
+        temp.child("c.txt").write_str("gamma").unwrap();
+        let ddrive = Ddrive::init(temp.path()).await.unwrap();
+        ddrive
+            .add(&[temp.path()], &AddOptions::default())
+            .await
+            .unwrap();
+
+        let restored = TempDir::new().unwrap();
+        restored.child("docs/a.txt").write_str("alpha").unwrap();
+        restored.child("docs/b.txt").write_str("BETA").unwrap();
+        restored.child("docs/new.txt").write_str("new").unwrap();
+        let command = VerifyCommand::new(ddrive.context());
+        let result = command
+            .external(restored.path(), None, HashAlgorithm::Blake3)
+            .await
+            .unwrap();
+        assert_eq!(result.matched_files, 1);
+        assert_eq!(result.missing, ["c.txt"]);
+        assert_eq!(result.mismatched, ["docs/b.txt"]);
+        assert_eq!(result.extra, ["docs/new.txt"]);
+        assert!(!result.passed());
+
+        let docs: PathFilter = "docs/a.txt".parse().unwrap();
+        let result = command
+            .external(restored.path(), Some(&docs), HashAlgorithm::Blake3)
+            .await
+            .unwrap();
+        assert!(result.passed() && result.extra.is_empty());
+        assert_eq!(result.filtered_files, 2);
+
+        // The repository itself is not a copy of it
+        assert!(
+            command
+                .external(temp.path(), None, HashAlgorithm::Blake3)
+                .await
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_interrupted_run_resumes() {
+        let temp = TempDir::new().unwrap();
+        for name in ["a.jpg", "b.jpg", "c.jpg"] {
+            temp.child(name).write_str(name).unwrap();
+        }
+        let ddrive = Ddrive::init(temp.path()).await.unwrap();
+        ddrive
+            .add(&[temp.path()], &AddOptions::default())
+            .await
+            .unwrap();
+
+        // A forced run that was killed after checking a.jpg
+        let database = &ddrive.context().database;
+        let parameters = session_parameters(None, None, true, HashAlgorithm::Blake3, false);
+        database
+            .start_verify_session(
+                1_000,
+                &parameters,
+                3,
+                Utc::now() - chrono::Duration::days(1),
+            )
+            .await
+            .unwrap();
+        database
+            .record_verify_progress(1_000, &["a.jpg".to_string()], Duration::ZERO, 1, 1, 0)
+            .await
+            .unwrap();
+        let run = database.get_action_run(1_000).await.unwrap().unwrap();
+        assert_eq!(run.status_enum(), ActionStatus::Interrupted);
+
+        // Another run with other filters starts afresh
+        let other = VerifyCommand::new(ddrive.context())
+            .execute(None, None, false, HashAlgorithm::Blake3, true)
+            .await
+            .unwrap();
+        assert_eq!(other.resumed_files, 0);
+
+        let result = VerifyCommand::new(ddrive.context())
+            .execute(None, None, true, HashAlgorithm::Blake3, false)
+            .await
+            .unwrap();
+        assert_eq!(result.resumed_files, 1);
+        assert_eq!(result.checked_files, 2);
+        let run = database.get_action_run(1_000).await.unwrap().unwrap();
+        assert_eq!(run.status_enum(), ActionStatus::Success);
+        assert_eq!(run.files, 3);
+        assert!(
+            database
+                .find_verify_session(&parameters)
+                .await
+                .unwrap()
+                .is_none()
+        );
+
+        // The next forced run checks everything again
+        let again = VerifyCommand::new(ddrive.context())
+            .execute(None, None, true, HashAlgorithm::Blake3, false)
+            .await
+            .unwrap();
+        assert_eq!(again.checked_files, 3);
+    }
+
+    #[tokio::test]
+    async fn test_read_only_context() {
+        let (temp, ddrive) = repository(&[("photos/a.jpg", "a"), ("photos/b.jpg", "b")]).await;
+        ddrive
+            .add(&[temp.path()], &AddOptions::default())
+            .await
+            .unwrap();
+        let database = &ddrive.context().database;
+        let actions = database.get_recorded_action_ids().await.unwrap();
+
+        let context = Ddrive::builder(temp.path())
+            .read_only(true)
+            .build()
+            .await
+            .unwrap();
+        assert!(context.database.is_read_only());
+        let read_only = Ddrive::from_context(context);
+        assert_eq!(read_only.status().await.unwrap().tracked_files, 2);
+        let verified = read_only
+            .verify(&VerifyOptions {
+                force: true,
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        assert_eq!(verified.passed_files, 2);
+        // The run was not recorded
+        assert_eq!(database.get_recorded_action_ids().await.unwrap(), actions);
+
+        temp.child("photos/c.jpg").write_str("c").unwrap();
+        assert!(
+            read_only
+                .add(&[temp.path()], &AddOptions::default())
+                .await
+                .is_err()
+        );
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn test_failures_grouped_by_device() {
+        let (temp, ddrive) =
+            repository(&[("docs/a.txt", "intact"), ("docs/b.txt", "before")]).await;
+        ddrive
+            .add(&[temp.path().join("docs")], &AddOptions::default())
+            .await
+            .unwrap();
+
+        let database = &ddrive.context().database;
+        let devices = database.get_device_stats().await.unwrap();
+        assert_eq!(devices.len(), 1);
+        assert_eq!(devices[0].files, 2);
+        assert!(devices[0].mount_point.is_some());
+
+        corrupt(&temp.path().join("docs/b.txt"), "after!");
+
+        let verified = ddrive
+            .verify(&VerifyOptions {
+                force: true,
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        assert_eq!(verified.failed_files, 1);
+        assert_eq!(verified.failures_by_device, vec![(devices[0].label(), 1)]);
+        let devices = database.get_device_stats().await.unwrap();
+        assert_eq!(devices[0].failures, 1);
+    }
+
+    #[tokio::test]
+    async fn test_verify_path_prefix_and_since() {
+        let (temp, ddrive) = repository(&[
+            ("docs/a.txt", "a"),
+            ("docs/b.txt", "b"),
+            ("docs2/c.txt", "c"),
+            ("photos/d.jpg", "d"),
+        ])
+        .await;
+        // Modified in the future, so it is newer than any recent date
+        let future = std::time::SystemTime::now() + std::time::Duration::from_secs(4 * 365 * 86400);
+        std::fs::File::options()
+            .write(true)
+            .open(temp.path().join("docs/b.txt"))
+            .unwrap()
+            .set_modified(future)
+            .unwrap();
+        for dir in ["docs", "docs2", "photos"] {
+            ddrive
+                .add(&[temp.path().join(dir)], &AddOptions::default())
+                .await
+                .unwrap();
+        }
+
+        let verify = |path: Option<&str>, since: Option<DateTime<Utc>>| VerifyOptions {
+            path: path.map(|path| path.parse().unwrap()),
+            since,
+            force: true,
+            ..Default::default()
+        };
+        let verified = ddrive.verify(&verify(Some("docs/"), None)).await.unwrap();
+        assert_eq!((verified.checked_files, verified.filtered_files), (2, 2));
+        let verified = ddrive
+            .verify(&verify(Some("docs2/c.txt"), None))
+            .await
+            .unwrap();
+        assert_eq!(verified.checked_files, 1);
+        let verified = ddrive.verify(&verify(Some("*/*.txt"), None)).await.unwrap();
+        assert_eq!(verified.checked_files, 3);
+
+        // Everything was added within the last hour
+        let hour_ago = Utc::now() - chrono::Duration::hours(1);
+        let verified = ddrive.verify(&verify(None, Some(hour_ago))).await.unwrap();
+        assert_eq!(verified.checked_files, 4);
+        // Only docs/b.txt was modified after next year
+        let next_year = Utc::now() + chrono::Duration::days(365);
+        let verified = ddrive.verify(&verify(None, Some(next_year))).await.unwrap();
+        assert_eq!((verified.checked_files, verified.filtered_files), (1, 3));
+        let verified = ddrive
+            .verify(&verify(Some("photos"), Some(next_year)))
+            .await
+            .unwrap();
+        assert_eq!(verified.checked_files, 0);
+    }
 }
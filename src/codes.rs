@@ -0,0 +1,321 @@
+//! Stable codes for errors and warnings.
+//!
+//! Every [`DdriveError`](crate::DdriveError) variant and the warnings worth
+//! alerting on carry a code such as `DD0101`. Codes never change meaning or
+//! get reused, so monitoring rules can match them while the wording of
+//! messages changes or is translated. Errors are printed as
+//! `error[DD0008]: ...`, warnings log the code as their `code` field, and JSON
+//! output has it next to the message.
+//!
+//! `docs/CODES.md` is generated from [`REGISTRY`] with
+//! `ddrive codes --markdown`.
+
+use std::fmt;
+
+/// Whether a code belongs to an error, which ends the command, or a warning
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Error => f.pad("error"),
+            Severity::Warning => f.pad("warning"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Code {
+    /// `DD` and four digits: 00xx for errors, 01xx for warnings
+    pub id: &'static str,
+    pub name: &'static str,
+    pub severity: Severity,
+    pub summary: &'static str,
+}
+
+impl fmt::Display for Code {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(self.id)
+    }
+}
+
+const fn error(id: &'static str, name: &'static str, summary: &'static str) -> Code {
+    Code {
+        id,
+        name,
+        severity: Severity::Error,
+        summary,
+    }
+}
+
+const fn warning(id: &'static str, name: &'static str, summary: &'static str) -> Code {
+    Code {
+        id,
+        name,
+        severity: Severity::Warning,
+        summary,
+    }
+}
+
+pub const INVALID_DIRECTORY: Code = error(
+    "DD0001",
+    "InvalidDirectory",
+    "A path is not a usable directory",
+);
+pub const INVALID_PATH: Code = error(
+    "DD0002",
+    "InvalidPath",
+    "A path is not inside the directory it was expected in",
+);
+pub const DATABASE: Code = error(
+    "DD0003",
+    "Database",
+    "Reading or writing the repository database failed",
+);
+pub const IO: Code = error("DD0004", "Io", "A file system operation failed");
+pub const FILE_SYSTEM: Code = error(
+    "DD0005",
+    "FileSystem",
+    "A file is not what the operation needs, e.g. missing or not a regular file",
+);
+pub const HARD_LINK: Code = error("DD0006", "HardLink", "Creating a hard link failed");
+pub const CHECKSUM: Code = error(
+    "DD0007",
+    "Checksum",
+    "A checksum could not be calculated or parsed",
+);
+pub const REPOSITORY: Code = error(
+    "DD0008",
+    "Repository",
+    "No repository was found, or it is unusable",
+);
+pub const VALIDATION: Code = error("DD0009", "Validation", "An argument or request is invalid");
+pub const MIGRATION: Code = error(
+    "DD0010",
+    "Migration",
+    "Upgrading the database schema failed",
+);
+pub const IGNORE_PATTERN: Code = error("DD0011", "IgnorePattern", "An ignore pattern is invalid");
+pub const GLOB_PATTERN: Code = error("DD0012", "GlobPattern", "A glob pattern is invalid");
+pub const PERMISSION_DENIED: Code = error(
+    "DD0013",
+    "PermissionDenied",
+    "The operation is not permitted",
+);
+pub const CONFIGURATION: Code = error("DD0014", "Configuration", "The configuration is invalid");
+pub const CANCELLED: Code = error("DD0015", "Cancelled", "The user declined to go on");
+pub const PARTIAL_FAILURE: Code = error(
+    "DD0016",
+    "PartialFailure",
+    "Some files could not be protected",
+);
+pub const UNREADABLE: Code = error(
+    "DD0017",
+    "Unreadable",
+    "Some files or folders could not be read",
+);
+pub const OBJECT_STORE: Code = error(
+    "DD0018",
+    "ObjectStore",
+    "The object store failed or is misconfigured",
+);
+pub const MIRROR: Code = error("DD0019", "Mirror", "Syncing a mirror failed");
+pub const ENCRYPTION: Code = error(
+    "DD0020",
+    "Encryption",
+    "Encrypting or decrypting stored objects failed",
+);
+
+pub const CHECKSUM_MISMATCH: Code = warning(
+    "DD0101",
+    "ChecksumMismatch",
+    "A file's content no longer matches its checksum while its metadata is unchanged",
+);
+pub const MAC_MISMATCH: Code = warning(
+    "DD0102",
+    "MacMismatch",
+    "A file's MAC does not match: the file or the database may have been tampered with",
+);
+pub const METADATA_DRIFT: Code = warning(
+    "DD0103",
+    "MetadataDrift",
+    "Permissions, owner or attributes changed while the content is intact",
+);
+pub const OBJECT_DAMAGED: Code = warning(
+    "DD0104",
+    "ObjectDamaged",
+    "A stored object is missing, unreadable or damaged",
+);
+pub const MEDIA_UNAVAILABLE: Code = warning(
+    "DD0105",
+    "MediaUnavailable",
+    "Files were skipped because their disk is not attached",
+);
+pub const UNRESOLVED_FAILURES: Code = warning(
+    "DD0106",
+    "UnresolvedFailures",
+    "Files that failed verification have not been re-checked or resolved",
+);
+pub const UNREADABLE_FILES: Code = warning(
+    "DD0107",
+    "UnreadableFiles",
+    "Files cannot be read and are not protected",
+);
+pub const MISSING_STORED_COPY: Code = warning(
+    "DD0108",
+    "MissingStoredCopy",
+    "Tracked files have lost their stored copy",
+);
+pub const NO_REFLINK: Code = warning(
+    "DD0109",
+    "NoReflink",
+    "Stored copies are full copies because reflinks are not available",
+);
+pub const UNSTABLE_FILES: Code = warning(
+    "DD0110",
+    "UnstableFiles",
+    "Files changed while being added and were skipped",
+);
+pub const STORE_FAILED: Code = warning(
+    "DD0111",
+    "StoreFailed",
+    "A file could not be stored or recorded",
+);
+pub const RESTORE_SKIPPED: Code = warning(
+    "DD0112",
+    "RestoreSkipped",
+    "A corrupted file was left in place because no intact stored copy exists",
+);
+pub const LOW_SPACE: Code = warning(
+    "DD0113",
+    "LowSpace",
+    "Pruning did not free the requested space",
+);
+pub const IGNORE_FILE: Code = warning(
+    "DD0114",
+    "IgnoreFile",
+    "An ignore file has errors; its valid patterns still apply",
+);
+pub const REFCOUNT_DRIFT: Code = warning(
+    "DD0115",
+    "RefcountDrift",
+    "Object reference counts did not match file records and history and were corrected",
+);
+pub const RESTORE_MISMATCH: Code = warning(
+    "DD0116",
+    "RestoreMismatch",
+    "A copy checked with verify --external lacks tracked files or has different content",
+);
+
+/// Every code, in order
+pub const REGISTRY: &[Code] = &[
+    INVALID_DIRECTORY,
+    INVALID_PATH,
+    DATABASE,
+    IO,
+    FILE_SYSTEM,
+    HARD_LINK,
+    CHECKSUM,
+    REPOSITORY,
+    VALIDATION,
+    MIGRATION,
+    IGNORE_PATTERN,
+    GLOB_PATTERN,
+    PERMISSION_DENIED,
+    CONFIGURATION,
+    CANCELLED,
+    PARTIAL_FAILURE,
+    UNREADABLE,
+    OBJECT_STORE,
+    MIRROR,
+    ENCRYPTION,
+    CHECKSUM_MISMATCH,
+    MAC_MISMATCH,
+    METADATA_DRIFT,
+    OBJECT_DAMAGED,
+    MEDIA_UNAVAILABLE,
+    UNRESOLVED_FAILURES,
+    UNREADABLE_FILES,
+    MISSING_STORED_COPY,
+    NO_REFLINK,
+    UNSTABLE_FILES,
+    STORE_FAILED,
+    RESTORE_SKIPPED,
+    LOW_SPACE,
+    IGNORE_FILE,
+    REFCOUNT_DRIFT,
+    RESTORE_MISMATCH,
+];
+
+/// The registry as the Markdown of `docs/CODES.md`
+pub fn registry_markdown() -> String {
+    let mut markdown = String::from(
+        "# Error and warning codes\n\n\
+         Generated by `ddrive codes --markdown`; do not edit by hand. Codes keep\n\
+         their meaning and are never reused, so they are safe to match in\n\
+         monitoring rules while message wording changes.\n\n\
+         | Code | Name | Severity | Meaning |\n\
+         |------|------|----------|---------|\n",
+    );
+    for code in REGISTRY {
+        markdown.push_str(&format!(
+            "| {} | {} | {} | {} |\n",
+            code.id, code.name, code.severity, code.summary
+        ));
+    }
+    markdown
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_codes_are_unique() {
+        let ids: HashSet<&str> = REGISTRY.iter().map(|code| code.id).collect();
+        let names: HashSet<&str> = REGISTRY.iter().map(|code| code.name).collect();
+        assert_eq!(ids.len(), REGISTRY.len());
+        assert_eq!(names.len(), REGISTRY.len());
+        for code in REGISTRY {
+            let prefix = match code.severity {
+                Severity::Error => "DD00",
+                Severity::Warning => "DD01",
+            };
+            assert!(
+                code.id.starts_with(prefix) && code.id.len() == 6,
+                "{}",
+                code.id
+            );
+        }
+    }
+
+    #[test]
+    fn test_error_codes_are_registered() {
+        let errors = [
+            crate::DdriveError::UserCancelled,
+            crate::DdriveError::PartialFailure { failed: 1 },
+            crate::DdriveError::Io(std::io::Error::other("disk")),
+        ];
+        for error in errors {
+            assert!(REGISTRY.contains(&error.code()));
+            assert_eq!(error.code().severity, Severity::Error);
+        }
+        assert_eq!(crate::DdriveError::UserCancelled.code().id, "DD0015");
+    }
+
+    #[test]
+    fn test_generated_registry_is_current() {
+        let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("docs/CODES.md");
+        let written = std::fs::read_to_string(path).unwrap();
+        assert_eq!(
+            written,
+            registry_markdown(),
+            "docs/CODES.md is stale; regenerate it with `ddrive codes --markdown > docs/CODES.md`"
+        );
+    }
+}
@@ -1,5 +1,7 @@
-use crate::{DdriveError, Result};
+use crate::{DdriveError, Result, checksum::HashAlgorithm, logging::LogFormat, utils::SizeUnits};
 use chrono::{DateTime, Duration, Utc};
+use clap::ValueEnum;
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
 use serde::{Deserialize, Serialize};
 use std::fs;
 use std::path::{Path, PathBuf};
@@ -20,9 +22,57 @@ pub struct Config {
     #[serde(default)]
     pub prune: PruneConfig,
 
+    /// Untracking settings
+    #[serde(default)]
+    pub rm: RmConfig,
+
+    /// Status settings
+    #[serde(default)]
+    pub status: StatusConfig,
+
+    /// What `add` and `status` count as a changed file
+    #[serde(default)]
+    pub changes: ChangesConfig,
+
     /// Object store settings
     #[serde(default)]
     pub object_store: ObjectStoreConfig,
+
+    /// File attribute tracking settings
+    #[serde(default)]
+    pub metadata: MetadataConfig,
+
+    /// Scanner filter settings
+    #[serde(default)]
+    pub scan: ScanConfig,
+
+    /// Tracking policy for `add --auto`
+    #[serde(default)]
+    pub track: TrackConfig,
+
+    /// Checksum calculation settings
+    #[serde(default)]
+    pub checksum: ChecksumConfig,
+
+    /// Hashing threads and process priority
+    #[serde(default)]
+    pub performance: PerformanceConfig,
+
+    /// HTTP API server settings
+    #[serde(default)]
+    pub serve: ServeConfig,
+
+    /// Log file settings
+    #[serde(default)]
+    pub logging: LoggingConfig,
+
+    /// Photo and video settings
+    #[serde(default)]
+    pub media: MediaConfig,
+
+    /// Desktop notification settings
+    #[serde(default)]
+    pub notifications: NotificationsConfig,
 }
 
 /// General configuration settings
@@ -31,6 +81,18 @@ pub struct GeneralConfig {
     /// Enable verbose logging
     #[serde(default = "default_verbose")]
     pub verbose: bool,
+
+    /// Use emoji and unicode symbols in output; ASCII markers when false
+    #[serde(default = "default_true")]
+    pub emoji: bool,
+
+    /// Units sizes are displayed in
+    #[serde(default)]
+    pub size_units: SizeUnits,
+
+    /// Language of messages, e.g. `de`; the system locale when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub locale: Option<String>,
 }
 
 /// Verification settings
@@ -39,26 +101,169 @@ pub struct VerifyConfig {
     /// Days between automatic checksum verification
     #[serde(default = "default_verify_interval")]
     pub interval_days: u32,
+    /// How files are picked for verification when not forced
+    #[serde(default)]
+    pub schedule: VerifySchedule,
+    /// Move corrupted files to `.ddrive/quarantine` and restore the stored copy
+    #[serde(default)]
+    pub quarantine: bool,
+    /// Also re-hash the stored objects of the files being verified
+    #[serde(default)]
+    pub objects: bool,
+    /// Trust files whose size and modification time are unchanged instead of
+    /// hashing them, unless verification is forced
+    #[serde(default = "default_true")]
+    pub metadata_shortcut: bool,
+    /// Times a file is re-read after a read error before it counts as failed
+    #[serde(default = "default_verify_retries")]
+    pub retries: u32,
+    /// Delay before the first retry, doubled for each further retry
+    #[serde(default = "default_retry_backoff_ms")]
+    pub retry_backoff_ms: u64,
 }
 
 impl VerifyConfig {
     pub fn cutoff_date(&self) -> DateTime<Utc> {
         Utc::now() - Duration::days(self.interval_days as i64)
     }
+
+    /// Number of files a daily run verifies so that all `total` files are
+    /// verified once every `interval_days`
+    pub fn files_per_run(&self, total: usize) -> usize {
+        total.div_ceil(self.interval_days.max(1) as usize)
+    }
+}
+
+/// How verify picks files when not forced
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum VerifySchedule {
+    /// Verify a share of the files on every run, least recently checked first,
+    /// so the whole repository is covered once per interval without big scrubs
+    #[default]
+    Spread,
+    /// Verify every file not checked within the interval
+    Cutoff,
 }
 
 /// Prune settings
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct PruneConfig {
-    /// Days to keep deleted files before pruning
+    /// Days to keep deleted files before pruning. With a keep policy, history
+    /// actions newer than this are always kept.
     #[serde(default = "default_retention_days")]
     pub retention_days: u32,
+    /// Days an object must have been unreferenced before prune moves it to
+    /// `.ddrive/trash`, and days it then stays there before being deleted
+    #[serde(default = "default_orphan_grace_days")]
+    pub orphan_grace_days: u32,
+    /// Keep the most recent N history actions
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub keep_last: Option<u32>,
+    /// Keep the most recent action of each of the last N days that have one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub keep_daily: Option<u32>,
+    /// Keep the most recent action of each of the last N weeks that have one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub keep_weekly: Option<u32>,
+    /// Keep the most recent action of each of the last N months that have one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub keep_monthly: Option<u32>,
 }
 
 impl PruneConfig {
     pub fn cutoff_date(&self) -> DateTime<Utc> {
         Utc::now() - Duration::days(self.retention_days as i64)
     }
+
+    /// Objects unreferenced (or trashed) before this are old enough to remove
+    pub fn orphan_grace_cutoff(&self) -> DateTime<Utc> {
+        Utc::now() - Duration::days(self.orphan_grace_days as i64)
+    }
+
+    /// Whether any `keep_*` policy is set. Without one only the history of
+    /// deleted files is pruned, by `retention_days`.
+    pub fn has_keep_policy(&self) -> bool {
+        self.keep_last.is_some()
+            || self.keep_daily.is_some()
+            || self.keep_weekly.is_some()
+            || self.keep_monthly.is_some()
+    }
+}
+
+/// Status settings
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct StatusConfig {
+    /// Show the cached totals instead of scanning the tree when at least this
+    /// many files are tracked, unless `status --full` is given
+    #[serde(default = "default_fast_threshold")]
+    pub fast_threshold: u64,
+}
+
+/// Photo and video settings
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct MediaConfig {
+    /// Date photos by the EXIF time they were taken rather than their
+    /// modification time, in `import --rename` and `organize --by-date`
+    #[serde(default)]
+    pub exif_dates: bool,
+}
+
+/// Change detection settings
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct ChangesConfig {
+    /// Which metadata marks a tracked file as possibly changed
+    #[serde(default)]
+    pub detect: ChangeDetection,
+
+    /// Modification times within this many milliseconds of the recorded one
+    /// count as unchanged, for file systems with coarse timestamps (FAT32
+    /// keeps 2 seconds, some NAS mounts round to whole seconds)
+    #[serde(default)]
+    pub mtime_tolerance_ms: u64,
+
+    /// Compare paths ignoring case, so that renaming `Photo.JPG` to
+    /// `photo.jpg` is a rename; detected from the file system when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub case_insensitive: Option<bool>,
+}
+
+/// Which metadata marks a tracked file as possibly changed. Files that look
+/// changed are hashed by `add` and listed by `status`.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum ChangeDetection {
+    /// Only a different modification time
+    Mtime,
+    /// A different size or modification time
+    #[default]
+    MtimeSize,
+    /// A different size, modification time or inode change time; catches
+    /// edits that restored the modification time
+    Ctime,
+    /// `add` hashes every tracked file; `status` compares size and
+    /// modification time as it does not hash
+    AlwaysHash,
+}
+
+/// Untracking settings
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct RmConfig {
+    /// What `rm deleted` does when a file's stored copy is missing, so that
+    /// removing its record would drop the last pointer to its content
+    #[serde(default)]
+    pub missing_objects: MissingObjects,
+}
+
+/// How `rm deleted` treats files whose object is missing from the store
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum MissingObjects {
+    /// Remove nothing unless `--force` is passed
+    #[default]
+    Abort,
+    /// Log the files and remove them anyway
+    Warn,
 }
 
 /// Object store settings
@@ -67,9 +272,407 @@ pub struct ObjectStoreConfig {
     /// Path to object store directory (relative to repository root)
     #[serde(default = "default_object_store_path")]
     pub path: String,
+
+    /// Where objects are kept, by URL: `file:///mnt/backup/objects` or
+    /// `s3://bucket/prefix` (default: `.ddrive/objects`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+
+    /// Endpoint of an S3-compatible service, e.g. `http://localhost:9000` for MinIO (default: AWS)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub endpoint: Option<String>,
+
+    /// Region of the S3 bucket (default: `AWS_REGION`, or us-east-1)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub region: Option<String>,
+
+    /// Cheaper, slower store that `tier` moves old objects to, by URL like `url`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cold_url: Option<String>,
+
+    /// Move objects to the cold store once only history has referenced them
+    /// for this many days
+    #[serde(default = "default_cold_after_days")]
+    pub cold_after_days: u32,
+
+    /// How added files are stored
+    #[serde(default)]
+    pub mode: ObjectStoreMode,
+
+    /// Only store copies of files up to this many bytes; larger files are just hashed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_file_size: Option<u64>,
+
+    /// Globs of repository paths to store copies of, e.g. `photos/**` (empty means all)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub include: Vec<String>,
+
+    /// Globs of repository paths whose copies are never stored, even if included
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub exclude: Vec<String>,
+}
+
+impl ObjectStoreConfig {
+    /// Compile the rules deciding which files get a copy in the object store
+    pub fn policy(&self) -> Result<StorePolicy> {
+        let (include, exclude) = compile_globs(&self.include, &self.exclude, "object_store")?;
+        Ok(StorePolicy {
+            mode: self.mode,
+            max_file_size: self.max_file_size,
+            include,
+            exclude,
+        })
+    }
+}
+
+/// Compiled `[object_store]` rules
+#[derive(Debug, Clone)]
+pub struct StorePolicy {
+    mode: ObjectStoreMode,
+    max_file_size: Option<u64>,
+    include: Option<GlobSet>,
+    exclude: GlobSet,
+}
+
+impl StorePolicy {
+    /// Check whether a copy of a file, given by its repository path in
+    /// storage form and size, is to be kept in the object store
+    pub fn stores(&self, path: &str, size: u64) -> bool {
+        self.mode != ObjectStoreMode::None
+            && self.max_file_size.is_none_or(|max| size <= max)
+            && self.include.as_ref().is_none_or(|set| set.is_match(path))
+            && !self.exclude.is_match(path)
+    }
+}
+
+/// How `add` keeps copies of tracked files
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum ObjectStoreMode {
+    /// Store a copy of every file, reflinked where the filesystem supports it
+    #[default]
+    Copy,
+    /// Store reflinked copies only; files that cannot be reflinked are just hashed
+    Reflink,
+    /// Store no copies, only checksums and metadata
+    None,
+}
+
+/// File attribute tracking settings
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct MetadataConfig {
+    /// Record permission bits
+    #[serde(default = "default_true")]
+    pub permissions: bool,
+
+    /// Record owner uid/gid
+    #[serde(default = "default_true")]
+    pub ownership: bool,
+
+    /// Record extended attributes
+    #[serde(default)]
+    pub xattrs: bool,
+}
+
+/// Scanner filter settings
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ScanConfig {
+    /// Skip files larger than this many bytes
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_file_size: Option<u64>,
+
+    /// Skip files smaller than this many bytes
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_file_size: Option<u64>,
+
+    /// Only track files with these extensions (empty means all)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub include_extensions: Vec<String>,
+
+    /// Never track files with these extensions
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub exclude_extensions: Vec<String>,
+
+    /// Do not cross mount points below the repository root
+    #[serde(default)]
+    pub same_file_system: bool,
+
+    /// Skip files matched by `.gitignore` files and git's excludes, when the
+    /// repository is inside a git work tree
+    #[serde(default = "default_true")]
+    pub respect_gitignore: bool,
+
+    /// Track hidden files and directories, whose names start with a dot.
+    /// `.ddrive` and version control metadata (`.git`, `.hg`, `.svn`) are
+    /// never tracked either way.
+    #[serde(default = "default_true")]
+    pub include_hidden: bool,
+}
+
+/// Tracking policy for `add --auto`
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct TrackConfig {
+    /// Globs of repository paths to protect, e.g. `**/*.{raw,jpg}` (empty means all)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub include: Vec<String>,
+
+    /// Globs of repository paths never to protect, even if included
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub exclude: Vec<String>,
+}
+
+impl TrackConfig {
+    /// Compile the include and exclude globs
+    pub fn policy(&self) -> Result<TrackPolicy> {
+        let (include, exclude) = compile_globs(&self.include, &self.exclude, "track")?;
+        Ok(TrackPolicy { include, exclude })
+    }
+}
+
+/// Compiled `[track]` globs
+#[derive(Debug, Clone)]
+pub struct TrackPolicy {
+    include: Option<GlobSet>,
+    exclude: GlobSet,
+}
+
+impl TrackPolicy {
+    /// Check whether a repository path in storage form is to be protected
+    pub fn allows(&self, path: &str) -> bool {
+        self.include.as_ref().is_none_or(|set| set.is_match(path)) && !self.exclude.is_match(path)
+    }
+}
+
+/// Compile include and exclude globs of a config section; no include globs
+/// means everything is included
+fn compile_globs(
+    include: &[String],
+    exclude: &[String],
+    section: &str,
+) -> Result<(Option<GlobSet>, GlobSet)> {
+    let include = if include.is_empty() {
+        None
+    } else {
+        Some(build_glob_set(include, section)?)
+    };
+    Ok((include, build_glob_set(exclude, section)?))
+}
+
+/// Build a set of globs where `*` stays within one path component
+fn build_glob_set(globs: &[String], section: &str) -> Result<GlobSet> {
+    let mut builder = GlobSetBuilder::new();
+    for glob in globs {
+        let glob = GlobBuilder::new(glob)
+            .literal_separator(true)
+            .build()
+            .map_err(|e| DdriveError::Configuration {
+                message: format!("Invalid [{section}] glob: {e}"),
+            })?;
+        builder.add(glob);
+    }
+    builder.build().map_err(|e| DdriveError::Configuration {
+        message: format!("Invalid [{section}] globs: {e}"),
+    })
+}
+
+/// Log file settings
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct LoggingConfig {
+    /// Also write logs to `.ddrive/logs/ddrive.log`
+    #[serde(default)]
+    pub file: bool,
+
+    /// Line format of the log file
+    #[serde(default)]
+    pub format: LogFormat,
+
+    /// Most verbose level written to the file ("info" or "debug")
+    #[serde(default = "default_log_level")]
+    pub level: String,
+
+    /// Rotate the log file once it would grow beyond this many bytes
+    #[serde(default = "default_log_max_size")]
+    pub max_size: u64,
+
+    /// Number of rotated log files kept
+    #[serde(default = "default_log_max_files")]
+    pub max_files: u32,
+}
+
+/// Desktop notification settings
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct NotificationsConfig {
+    /// Show desktop notifications for verify runs
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// Notify when verify finds damaged or unreadable files or stored objects
+    #[serde(default = "default_true")]
+    pub on_failure: bool,
+
+    /// Also notify when a verify run finishes without failures
+    #[serde(default)]
+    pub on_complete: bool,
+}
+
+/// HTTP API server settings
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ServeConfig {
+    /// Address the API listens on
+    #[serde(default = "default_serve_listen")]
+    pub listen: String,
+
+    /// Bearer token clients must send; `DDRIVE_TOKEN` overrides it. Without
+    /// either, a random token is generated and logged on startup.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token: Option<String>,
+}
+
+/// Hashing threads and process priority
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct PerformanceConfig {
+    /// Threads hashing files in parallel (default: one per core)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hash_threads: Option<usize>,
+
+    /// Best-effort I/O priority of verify runs, from 0 (highest) to 7 (lowest)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub io_nice: Option<u8>,
+
+    /// Nice value of verify runs, from 0 to 19 (lowest priority)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cpu_nice: Option<i32>,
+}
+
+/// Checksum calculation settings
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ChecksumConfig {
+    /// Hash large files through a memory map using all cores
+    #[serde(default = "default_true")]
+    pub mmap: bool,
+
+    /// Files at least this many bytes use the memory-mapped path
+    #[serde(default = "default_mmap_threshold")]
+    pub mmap_threshold: u64,
+
+    /// Fixed read buffer size in bytes; by default it is sized per file
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub buffer_size: Option<usize>,
+
+    /// Secondary checksums ("sha256", "xxh3") recorded alongside BLAKE3
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub secondary: Vec<HashAlgorithm>,
+
+    /// Key file for keyed BLAKE3 MACs (32 raw bytes or 64 hex digits). It must
+    /// live outside the repository so that whoever can rewrite files and the
+    /// database cannot also forge the MACs.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mac_key_file: Option<PathBuf>,
+
+    /// Key read from `mac_key_file` when the configuration is loaded
+    #[serde(skip)]
+    pub mac_key: Option<[u8; 32]>,
+
+    /// Record per-chunk hashes of large files so damage can be located
+    #[serde(default = "default_true")]
+    pub chunk_hashes: bool,
+
+    /// Chunk size in bytes for chunk hashes
+    #[serde(default = "default_chunk_size")]
+    pub chunk_size: u64,
+
+    /// Only files at least this many bytes get chunk hashes
+    #[serde(default = "default_chunk_min_file_size")]
+    pub chunk_min_file_size: u64,
+}
+
+impl ChecksumConfig {
+    /// Read the MAC key, refusing key files stored inside the repository
+    fn load_mac_key(&mut self, repo_root: &Path) -> Result<()> {
+        let Some(key_file) = &self.mac_key_file else {
+            return Ok(());
+        };
+        let key_path = key_file
+            .canonicalize()
+            .map_err(|e| DdriveError::Configuration {
+                message: format!("Cannot read MAC key {}: {e}", key_file.display()),
+            })?;
+        if key_path.starts_with(repo_root.canonicalize()?) {
+            return Err(DdriveError::Configuration {
+                message: format!(
+                    "MAC key {} must be stored outside the repository",
+                    key_file.display()
+                ),
+            });
+        }
+
+        let bytes = fs::read(&key_path)?;
+        let text = String::from_utf8_lossy(&bytes);
+        let key = match bytes.len() {
+            32 => bytes.as_slice().try_into().ok(),
+            _ => blake3::Hash::from_hex(text.trim())
+                .ok()
+                .map(|hash| *hash.as_bytes()),
+        };
+        let Some(key) = key else {
+            return Err(DdriveError::Configuration {
+                message: format!(
+                    "MAC key {} must be 32 bytes or 64 hex digits",
+                    key_file.display()
+                ),
+            });
+        };
+        self.mac_key = Some(key);
+        Ok(())
+    }
+}
+
+impl ScanConfig {
+    /// Check whether a file passes the size and extension filters
+    pub fn allows(&self, path: &Path, size: u64) -> bool {
+        self.filter_reason(path, size).is_none()
+    }
+
+    /// The setting that filters out a file, if any. Extensions are compared
+    /// case-insensitively, with or without a leading dot.
+    pub fn filter_reason(&self, path: &Path, size: u64) -> Option<String> {
+        if let Some(max) = self.max_file_size.filter(|&max| size > max) {
+            return Some(format!("larger than scan.max_file_size = {max}"));
+        }
+        if let Some(min) = self.min_file_size.filter(|&min| size < min) {
+            return Some(format!("smaller than scan.min_file_size = {min}"));
+        }
+        self.extension_filter_reason(path)
+    }
+
+    /// The extension setting that filters out a file, if any
+    pub fn extension_filter_reason(&self, path: &Path) -> Option<String> {
+        let extension = path
+            .extension()
+            .map(|e| e.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        let matches = |list: &[String]| {
+            list.iter()
+                .any(|e| e.trim_start_matches('.').eq_ignore_ascii_case(&extension))
+        };
+
+        if !self.include_extensions.is_empty() && !matches(&self.include_extensions) {
+            return Some("extension not in scan.include_extensions".to_string());
+        }
+        if matches(&self.exclude_extensions) {
+            return Some(format!(
+                "extension \"{extension}\" in scan.exclude_extensions"
+            ));
+        }
+        None
+    }
 }
 
 // Default values
+fn default_true() -> bool {
+    true
+}
+
 fn default_verbose() -> bool {
     false
 }
@@ -78,10 +681,58 @@ fn default_verify_interval() -> u32 {
     30 // 30 days between automatic checksum verification
 }
 
+fn default_verify_retries() -> u32 {
+    2
+}
+
+fn default_retry_backoff_ms() -> u64 {
+    500
+}
+
 fn default_retention_days() -> u32 {
     90 // 90 days retention for deleted files
 }
 
+fn default_orphan_grace_days() -> u32 {
+    7
+}
+
+fn default_mmap_threshold() -> u64 {
+    64 * 1024 * 1024 // 64 MiB; below this threads cost more than they save
+}
+
+fn default_chunk_size() -> u64 {
+    16 * 1024 * 1024 // 16 MiB
+}
+
+fn default_chunk_min_file_size() -> u64 {
+    64 * 1024 * 1024 // 64 MiB
+}
+
+fn default_log_level() -> String {
+    "info".to_string()
+}
+
+fn default_log_max_size() -> u64 {
+    10 * 1024 * 1024 // 10 MiB
+}
+
+fn default_log_max_files() -> u32 {
+    5
+}
+
+fn default_serve_listen() -> String {
+    "127.0.0.1:7878".to_string()
+}
+
+fn default_fast_threshold() -> u64 {
+    100_000
+}
+
+fn default_cold_after_days() -> u32 {
+    180
+}
+
 fn default_object_store_path() -> String {
     ".ddrive/objects".to_string()
 }
@@ -91,6 +742,9 @@ impl Default for GeneralConfig {
     fn default() -> Self {
         Self {
             verbose: default_verbose(),
+            emoji: default_true(),
+            size_units: SizeUnits::default(),
+            locale: None,
         }
     }
 }
@@ -99,6 +753,12 @@ impl Default for VerifyConfig {
     fn default() -> Self {
         Self {
             interval_days: default_verify_interval(),
+            schedule: VerifySchedule::default(),
+            quarantine: false,
+            objects: false,
+            metadata_shortcut: true,
+            retries: default_verify_retries(),
+            retry_backoff_ms: default_retry_backoff_ms(),
         }
     }
 }
@@ -107,6 +767,33 @@ impl Default for PruneConfig {
     fn default() -> Self {
         Self {
             retention_days: default_retention_days(),
+            orphan_grace_days: default_orphan_grace_days(),
+            keep_last: None,
+            keep_daily: None,
+            keep_weekly: None,
+            keep_monthly: None,
+        }
+    }
+}
+
+impl Default for ScanConfig {
+    fn default() -> Self {
+        Self {
+            max_file_size: None,
+            min_file_size: None,
+            include_extensions: Vec::new(),
+            exclude_extensions: Vec::new(),
+            same_file_system: false,
+            respect_gitignore: default_true(),
+            include_hidden: default_true(),
+        }
+    }
+}
+
+impl Default for StatusConfig {
+    fn default() -> Self {
+        Self {
+            fast_threshold: default_fast_threshold(),
         }
     }
 }
@@ -115,6 +802,72 @@ impl Default for ObjectStoreConfig {
     fn default() -> Self {
         Self {
             path: default_object_store_path(),
+            url: None,
+            endpoint: None,
+            region: None,
+            cold_url: None,
+            cold_after_days: default_cold_after_days(),
+            mode: ObjectStoreMode::default(),
+            max_file_size: None,
+            include: Vec::new(),
+            exclude: Vec::new(),
+        }
+    }
+}
+
+impl Default for MetadataConfig {
+    fn default() -> Self {
+        Self {
+            permissions: default_true(),
+            ownership: default_true(),
+            xattrs: false,
+        }
+    }
+}
+
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        Self {
+            file: false,
+            format: LogFormat::default(),
+            level: default_log_level(),
+            max_size: default_log_max_size(),
+            max_files: default_log_max_files(),
+        }
+    }
+}
+
+impl Default for NotificationsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            on_failure: default_true(),
+            on_complete: false,
+        }
+    }
+}
+
+impl Default for ServeConfig {
+    fn default() -> Self {
+        Self {
+            listen: default_serve_listen(),
+            token: None,
+        }
+    }
+}
+
+impl Default for ChecksumConfig {
+    fn default() -> Self {
+        Self {
+            mmap: default_true(),
+            mmap_threshold: default_mmap_threshold(),
+            buffer_size: None,
+            secondary: Vec::new(),
+            mac_key_file: None,
+            mac_key: None,
+            chunk_hashes: default_true(),
+            chunk_size: default_chunk_size(),
+            chunk_min_file_size: default_chunk_min_file_size(),
         }
     }
 }
@@ -138,10 +891,11 @@ impl Config {
             message: format!("Failed to read config file: {e}"),
         })?;
 
-        let config: Config =
+        let mut config: Config =
             toml::from_str(&config_str).map_err(|e| DdriveError::Configuration {
                 message: format!("Failed to parse config file: {e}"),
             })?;
+        config.checksum.load_mac_key(repo_root)?;
 
         debug!("Loaded configuration from {}", config_path.display());
         Ok(config)
@@ -169,6 +923,47 @@ impl Config {
         Ok(())
     }
 
+    /// Set the value at a dotted `key` such as `verify.interval_days`. The
+    /// value is read as TOML (numbers, booleans, arrays), or else as a string.
+    pub fn set(&mut self, key: &str, value: &str) -> Result<()> {
+        let invalid = |message: String| DdriveError::Configuration { message };
+        let value = toml::from_str::<toml::Table>(&format!("value = {value}"))
+            .ok()
+            .and_then(|mut table| table.remove("value"))
+            .unwrap_or_else(|| toml::Value::String(value.to_string()));
+
+        let mut root = toml::Value::try_from(&*self)
+            .map_err(|e| invalid(format!("Failed to serialize config: {e}")))?;
+        let (path, name) = key.rsplit_once('.').unwrap_or(("", key));
+        let mut table = root.as_table_mut().expect("config is a table");
+        for section in path.split('.').filter(|section| !section.is_empty()) {
+            table = table
+                .entry(section)
+                .or_insert_with(|| toml::Value::Table(toml::Table::new()))
+                .as_table_mut()
+                .ok_or_else(|| invalid(format!("Unknown config key '{key}'")))?;
+        }
+        table.insert(name.to_string(), value);
+
+        let mut config: Config = root
+            .try_into()
+            .map_err(|e| invalid(format!("Invalid value for '{key}': {e}")))?;
+        // Unknown keys are dropped on the way back, so check the key survived
+        let applied = toml::Value::try_from(&config)
+            .ok()
+            .and_then(|root| {
+                key.split('.')
+                    .try_fold(root, |value, part| value.get(part).cloned())
+            })
+            .is_some();
+        if !applied {
+            return Err(invalid(format!("Unknown config key '{key}'")));
+        }
+        config.checksum.mac_key = self.checksum.mac_key;
+        *self = config;
+        Ok(())
+    }
+
     /// Get the absolute path to the object store
     pub fn object_store_path(&self, repo_root: &Path) -> PathBuf {
         repo_root.join(".ddrive").join("objects")
@@ -0,0 +1,103 @@
+//! Content types of tracked files.
+//!
+//! Files are typed by their leading magic bytes rather than their name, so a
+//! misnamed photo is still an image. Text, which has no magic bytes, is
+//! recognized by being valid UTF-8.
+
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+/// How much of a file is read to detect its type
+const SNIFF_LEN: u64 = 8192;
+
+/// Type given to files recognized as text only
+pub const TEXT_PLAIN: &str = "text/plain";
+
+/// MIME type of the file at `path` judged by its content, or `None` if it is
+/// empty, unreadable or not recognized
+pub fn detect(path: &Path) -> Option<String> {
+    let mut sample = Vec::new();
+    File::open(path)
+        .ok()?
+        .take(SNIFF_LEN)
+        .read_to_end(&mut sample)
+        .ok()?;
+    detect_bytes(&sample)
+}
+
+/// MIME type of content starting with `sample`
+pub fn detect_bytes(sample: &[u8]) -> Option<String> {
+    if sample.is_empty() {
+        return None;
+    }
+    if let Some(kind) = infer::get(sample) {
+        return Some(kind.mime_type().to_string());
+    }
+    is_text(sample).then(|| TEXT_PLAIN.to_string())
+}
+
+/// Whether `content_type` is `filter`, or of its kind when `filter` has no
+/// subtype: `image` matches `image/png` and `image/jpeg`
+pub fn matches(content_type: &str, filter: &str) -> bool {
+    let filter = filter.trim().to_lowercase();
+    if filter.contains('/') {
+        content_type == filter
+    } else {
+        kind(content_type) == filter
+    }
+}
+
+/// The kind of a MIME type, such as `image` for `image/png`
+pub fn kind(content_type: &str) -> &str {
+    content_type
+        .split_once('/')
+        .map_or(content_type, |(kind, _)| kind)
+}
+
+/// Whether a sample looks like text: valid UTF-8 without NUL bytes, allowing
+/// for a character cut off at the end of the sample
+fn is_text(sample: &[u8]) -> bool {
+    if sample.contains(&0) {
+        return false;
+    }
+    match std::str::from_utf8(sample) {
+        Ok(_) => true,
+        Err(e) => e.error_len().is_none(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_bytes() {
+        let png = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR";
+        assert_eq!(detect_bytes(png).as_deref(), Some("image/png"));
+        assert_eq!(
+            detect_bytes(b"%PDF-1.7\n").as_deref(),
+            Some("application/pdf")
+        );
+        assert_eq!(
+            detect_bytes("héllo\n".as_bytes()).as_deref(),
+            Some(TEXT_PLAIN)
+        );
+        // A multi-byte character cut off by the sample is still text
+        assert_eq!(
+            detect_bytes(&"é".as_bytes()[..1]).as_deref(),
+            Some(TEXT_PLAIN)
+        );
+        assert_eq!(detect_bytes(b"\0\x01\x02"), None);
+        assert_eq!(detect_bytes(b""), None);
+    }
+
+    #[test]
+    fn test_matches() {
+        assert!(matches("image/png", "image"));
+        assert!(matches("image/png", "Image/PNG"));
+        assert!(!matches("image/png", "image/jpeg"));
+        assert!(!matches("video/mp4", "image"));
+        assert_eq!(kind("application/zip"), "application");
+    }
+}
@@ -1,16 +1,28 @@
 use crate::{
     DdriveError, Result,
-    scanner::{FileInfo, get_all_files},
+    backend::ObjectBackend,
+    checksum::{ChunkHashes, HashAlgorithm, SecondaryChecksums},
+    codes,
+    device::Device,
+    scanner::FileInfo,
+    utils::{
+        DirectoryMove, from_storage_path, native_to_forward_slashes, normalize_path,
+        to_storage_path,
+    },
 };
 use chrono::{DateTime, Utc};
+use futures_util::{Stream, TryStreamExt};
 use serde_json::Value as JsonValue;
-use sqlx::{FromRow, QueryBuilder, SqlitePool};
+use sqlx::{FromRow, QueryBuilder, SqlitePool, sqlite::SqliteConnectOptions};
 use std::{
+    borrow::Cow,
+    collections::{HashMap, HashSet},
     path::{Path, PathBuf},
+    str::FromStr,
     time::{Duration, UNIX_EPOCH},
 };
 use strum::{Display, EnumString};
-use tracing::info;
+use tracing::{info, warn};
 
 /// Action types for history tracking
 #[derive(
@@ -25,6 +37,11 @@ pub enum ActionType {
     Delete = 2,
     Update = 3,
     Rename = 4,
+    Import = 5,
+    Quarantine = 6,
+    /// A directory moved with all its files; the files' own rename entries
+    /// name the directory in their metadata
+    DirectoryRename = 7,
 }
 
 impl ActionType {
@@ -40,20 +57,65 @@ impl From<i64> for ActionType {
             2 => Self::Delete,
             3 => Self::Update,
             4 => Self::Rename,
+            5 => Self::Import,
+            6 => Self::Quarantine,
+            7 => Self::DirectoryRename,
             _ => Self::Unknown,
         }
     }
 }
 
+/// Outcome of a command run recorded in the actions table
+#[derive(Debug, Clone, Copy, Display, EnumString, PartialEq, Eq)]
+#[strum(serialize_all = "snake_case")]
+pub enum ActionStatus {
+    Success,
+    /// The run finished, but some files could not be processed
+    PartialFailure,
+    /// The run stopped before processing every file; verify runs are
+    /// continued by the next run with the same filters
+    Interrupted,
+}
+
+/// Which store holds an object
+#[derive(Debug, Clone, Copy, Display, EnumString, PartialEq, Eq)]
+#[strum(serialize_all = "snake_case")]
+pub enum ObjectTier {
+    /// The object store
+    Hot,
+    /// The cold store of `[object_store] cold_url`
+    Cold,
+}
+
+/// How an integrity failure recorded by verify was resolved
+#[derive(Debug, Clone, Copy, Display, EnumString, PartialEq, Eq)]
+#[strum(serialize_all = "snake_case")]
+pub enum FailureResolution {
+    /// The file passed a later verification
+    Verified,
+    /// The file was found modified by the user rather than corrupted
+    Modified,
+    /// A new version of the file was recorded by add
+    Updated,
+    /// The file is no longer tracked
+    Removed,
+    /// The file was quarantined and replaced with the stored copy
+    Restored,
+}
+
+/// Transformation applied to every stored path by a data migration
+type PathRewrite = fn(&str) -> Cow<'_, str>;
+
 /// Database abstraction layer for ddrive file tracking
 ///
 /// Manages SQLite database operations including file record storage,
 /// integrity checking, and duplicate detection. All file paths are
-/// stored as relative paths from the repository root.
+/// stored as `/`-separated, NFC-normalized paths relative to the repository root.
 #[derive(Clone)]
 pub struct Database {
     pub pool: SqlitePool,
     pub repo_root: PathBuf,
+    read_only: bool,
 }
 
 impl Database {
@@ -64,20 +126,259 @@ impl Database {
         // This is safe to run multiple times as sqlx tracks which migrations have been applied
         sqlx::migrate!("./migrations").run(&pool).await?;
 
-        Ok(Database { pool, repo_root })
+        let database = Database {
+            pool,
+            repo_root,
+            read_only: false,
+        };
+        database.run_data_migrations().await?;
+        Ok(database)
+    }
+
+    /// Open the database without write access, for looking at a repository
+    /// another process is changing or one on read-only media. Nothing is
+    /// migrated, so the schema must already be current.
+    pub async fn open_read_only(database_url: &str, repo_root: PathBuf) -> Result<Self> {
+        let options = SqliteConnectOptions::from_str(database_url)?.read_only(true);
+        let pool = SqlitePool::connect_with(options).await?;
+
+        let latest = sqlx::migrate!("./migrations")
+            .iter()
+            .map(|migration| migration.version)
+            .max();
+        let applied: Option<i64> = sqlx::query_scalar("SELECT MAX(version) FROM _sqlx_migrations")
+            .fetch_one(&pool)
+            .await
+            .unwrap_or(None);
+        if applied < latest {
+            return Err(DdriveError::Repository {
+                message: "the database needs upgrading; run a command without --read-only first"
+                    .to_string(),
+            });
+        }
+
+        Ok(Database {
+            pool,
+            repo_root,
+            read_only: true,
+        })
+    }
+
+    /// Whether the database was opened with [`Database::open_read_only`]
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    /// Run one-off data migrations that can't be expressed in SQL, recording
+    /// each by name so it is only applied once
+    async fn run_data_migrations(&self) -> Result<()> {
+        let migrations: [(&str, PathRewrite); 2] = [
+            ("nfc_paths", normalize_path),
+            ("forward_slash_paths", native_to_forward_slashes),
+        ];
+
+        for (name, rewrite) in migrations {
+            let applied = sqlx::query("SELECT name FROM data_migrations WHERE name = ?1")
+                .bind(name)
+                .fetch_optional(&self.pool)
+                .await?;
+            if applied.is_some() {
+                continue;
+            }
+
+            let rewritten = self.rewrite_stored_paths(rewrite).await?;
+            if rewritten > 0 {
+                info!("Migrated {} stored paths ({})", rewritten, name);
+            }
+            sqlx::query("INSERT INTO data_migrations (name) VALUES (?1)")
+                .bind(name)
+                .execute(&self.pool)
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Rewrite every stored path with `rewrite`. When a rewritten path is
+    /// already tracked, the duplicate row is dropped in favour of the existing one.
+    async fn rewrite_stored_paths(&self, rewrite: PathRewrite) -> Result<usize> {
+        let mut rewritten = 0;
+        let mut tx = self.pool.begin().await?;
+
+        let files: Vec<(i64, String)> = sqlx::query_as("SELECT id, path FROM files")
+            .fetch_all(&mut *tx)
+            .await?;
+        for (id, path) in files {
+            let new_path = rewrite(&path);
+            if new_path == path.as_str() {
+                continue;
+            }
+            let existing: Option<(i64,)> = sqlx::query_as("SELECT id FROM files WHERE path = ?1")
+                .bind(new_path.as_ref())
+                .fetch_optional(&mut *tx)
+                .await?;
+            if existing.is_some() {
+                sqlx::query("DELETE FROM files WHERE id = ?1")
+                    .bind(id)
+                    .execute(&mut *tx)
+                    .await?;
+            } else {
+                sqlx::query("UPDATE files SET path = ?1 WHERE id = ?2")
+                    .bind(new_path.as_ref())
+                    .bind(id)
+                    .execute(&mut *tx)
+                    .await?;
+            }
+            rewritten += 1;
+        }
+
+        let history: Vec<(i64, String)> = sqlx::query_as("SELECT id, path FROM history")
+            .fetch_all(&mut *tx)
+            .await?;
+        for (id, path) in history {
+            let new_path = rewrite(&path);
+            if new_path != path.as_str() {
+                sqlx::query("UPDATE history SET path = ?1 WHERE id = ?2")
+                    .bind(new_path.as_ref())
+                    .bind(id)
+                    .execute(&mut *tx)
+                    .await?;
+            }
+        }
+
+        tx.commit().await?;
+        Ok(rewritten)
     }
 
-    /// Insert multiple file records in a single transaction for better performance
-    pub async fn batch_insert_file_records(
+    /// Record the renamed, new and changed files of an add action in a single
+    /// transaction that also closes its journal entry, so an interrupted add
+    /// leaves either all or none of its records behind
+    pub async fn commit_add_action(
         &self,
         action_id: i64,
-        records: &[&crate::scanner::FileInfo],
+        renames: &[(String, String)],
+        directory_moves: &[DirectoryMove],
+        new_files: &[&FileInfo],
+        changed_files: &[&FileInfo],
     ) -> Result<()> {
-        if records.is_empty() {
-            return Ok(());
+        let mut tx = self.pool.begin().await?;
+        let mut in_moved_directory = vec![false; renames.len()];
+        for directory_move in directory_moves {
+            let files: Vec<(String, String)> = directory_move
+                .files
+                .iter()
+                .map(|&index| {
+                    in_moved_directory[index] = true;
+                    renames[index].clone()
+                })
+                .collect();
+            self.record_directory_move(&mut tx, action_id, directory_move, &files)
+                .await?;
+        }
+        let renames: Vec<(String, String)> = renames
+            .iter()
+            .zip(in_moved_directory)
+            .filter(|(_, moved)| !moved)
+            .map(|(rename, _)| rename.clone())
+            .collect();
+        self.rename_files(&mut tx, action_id, &renames, None)
+            .await?;
+        self.insert_file_records(&mut tx, action_id, new_files)
+            .await?;
+        self.update_file_records(&mut tx, action_id, changed_files)
+            .await?;
+
+        sqlx::query!(
+            "DELETE FROM journal_objects WHERE action_id = ?1",
+            action_id
+        )
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query!("DELETE FROM journal WHERE action_id = ?1", action_id)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Open a journal entry for an add action before it stores any object
+    pub async fn begin_journal(&self, action_id: i64) -> Result<()> {
+        sqlx::query!(
+            "INSERT OR REPLACE INTO journal (action_id) VALUES (?1)",
+            action_id
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Note an object created by an add action that has not committed yet
+    pub async fn journal_object(&self, action_id: i64, b3sum: &str) -> Result<()> {
+        sqlx::query!(
+            "INSERT OR IGNORE INTO journal_objects (action_id, b3sum) VALUES (?1, ?2)",
+            action_id,
+            b3sum
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Clean up after add actions that were interrupted before committing by
+    /// deleting the objects they created that no file record or history entry
+    /// references.
+    /// Must only run while holding the repository lock. Returns the number of
+    /// objects deleted.
+    pub async fn recover_journal(&self, objects: &dyn ObjectBackend) -> Result<usize> {
+        let interrupted = sqlx::query_scalar!("SELECT COUNT(*) FROM journal")
+            .fetch_one(&self.pool)
+            .await?;
+        if interrupted == 0 {
+            return Ok(0);
+        }
+
+        self.recount_object_references().await?;
+        // Objects the interrupted action re-created for content that history
+        // or another record still references are kept
+        let orphaned = sqlx::query_scalar!(
+            r#"
+            SELECT DISTINCT b3sum FROM journal_objects
+            WHERE b3sum NOT IN (SELECT b3sum FROM objects WHERE refcount > 0)
+            "#
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut deleted = 0;
+        for b3sum in &orphaned {
+            if objects.delete(b3sum)? {
+                deleted += 1;
+            }
         }
 
         let mut tx = self.pool.begin().await?;
+        sqlx::query!("DELETE FROM journal_objects")
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query!("DELETE FROM journal")
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+
+        info!(
+            "Recovered {} interrupted add action(s), deleted {} uncommitted objects",
+            interrupted, deleted
+        );
+        Ok(deleted)
+    }
+
+    /// Insert file records for newly added files
+    async fn insert_file_records(
+        &self,
+        tx: &mut sqlx::Transaction<'_, sqlx::Sqlite>,
+        action_id: i64,
+        records: &[&crate::scanner::FileInfo],
+    ) -> Result<()> {
         for file_info in records {
             let relative_path = self.convert_to_relative_path(&file_info.path.to_string_lossy())?;
             let b3sum = file_info.b3sum.as_ref().expect("b3sum should be present");
@@ -99,14 +400,34 @@ impl Database {
             .bind(&relative_path)
             .bind(b3sum)
             .bind(file_size)
-            .execute(&mut *tx)
+            .execute(&mut **tx)
             .await?;
 
             // Insert into files table
             sqlx::query(
                 r#"
-                INSERT INTO files (path, b3sum, size, created_at, updated_at)
-                VALUES (?1, ?2, ?3, ?4, ?5)
+                INSERT INTO files (path, b3sum, size, created_at, updated_at, mode, uid, gid, xattrs, dev, ino, mtime_ns, sha256, xxh3, mac, stored, ctime_ns, content_type)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18)
+                ON CONFLICT(path) DO UPDATE SET
+                    b3sum = excluded.b3sum,
+                    size = excluded.size,
+                    created_at = excluded.created_at,
+                    updated_at = excluded.updated_at,
+                    mode = excluded.mode,
+                    uid = excluded.uid,
+                    gid = excluded.gid,
+                    xattrs = excluded.xattrs,
+                    dev = excluded.dev,
+                    ino = excluded.ino,
+                    mtime_ns = excluded.mtime_ns,
+                    sha256 = excluded.sha256,
+                    xxh3 = excluded.xxh3,
+                    mac = excluded.mac,
+                    stored = excluded.stored,
+                    ctime_ns = excluded.ctime_ns,
+                    content_type = excluded.content_type,
+                    last_checked = NULL,
+                    deleted_at = NULL
                 "#,
             )
             .bind(&relative_path)
@@ -114,28 +435,40 @@ impl Database {
             .bind(file_size)
             .bind(created_at)
             .bind(modified_at)
-            .execute(&mut *tx)
+            .bind(file_info.attributes.mode)
+            .bind(file_info.attributes.uid)
+            .bind(file_info.attributes.gid)
+            .bind(&file_info.attributes.xattrs)
+            .bind(file_info.dev.map(|d| d as i64))
+            .bind(file_info.ino.map(|i| i as i64))
+            .bind(file_info.modified_ns())
+            .bind(&file_info.secondary.sha256)
+            .bind(&file_info.secondary.xxh3)
+            .bind(&file_info.secondary.mac)
+            .bind(file_info.stored)
+            .bind(file_info.ctime_ns)
+            .bind(&file_info.content_type)
+            .execute(&mut **tx)
             .await?;
+
+            if let Some(chunks) = &file_info.secondary.chunks {
+                Self::insert_chunk_hashes(tx, b3sum, chunks).await?;
+            }
         }
 
-        tx.commit().await?;
         Ok(())
     }
 
-    /// Update multiple file records in a single transaction for better performance
-    pub async fn batch_update_file_records(
+    /// Update file records of changed files
+    async fn update_file_records(
         &self,
+        tx: &mut sqlx::Transaction<'_, sqlx::Sqlite>,
         action_id: i64,
         records: &[&FileInfo], // (file_path, b3sum, file_size)
     ) -> Result<()> {
-        if records.is_empty() {
-            return Ok(());
-        }
-
-        let mut tx = self.pool.begin().await?;
         for file in records {
             let b3sum = file.b3sum.as_ref().expect("b3sum");
-            let relative_path = file.path.to_str().expect("relative path");
+            let relative_path = to_storage_path(&file.path);
 
             // Insert into history for tracking
             sqlx::query(
@@ -146,10 +479,10 @@ impl Database {
             )
             .bind(action_id)
             .bind(ActionType::Update.to_i32())
-            .bind(relative_path)
+            .bind(&relative_path)
             .bind(b3sum)
             .bind(file.size as i64)
-            .execute(&mut *tx)
+            .execute(&mut **tx)
             .await?;
 
             let updated_at = file.modified_at();
@@ -161,23 +494,58 @@ impl Database {
                 SET b3sum = ?1, 
                     size = ?2, 
                     updated_at = ?3, 
-                    last_checked = NULL
-                WHERE path = ?4
+                    last_checked = NULL,
+                    mode = ?5,
+                    uid = ?6,
+                    gid = ?7,
+                    xattrs = ?8,
+                    dev = ?9,
+                    ino = ?10,
+                    mtime_ns = ?11,
+                    sha256 = ?12,
+                    xxh3 = ?13,
+                    mac = ?14,
+                    stored = ?15,
+                    ctime_ns = ?16,
+                    content_type = ?17
+                WHERE path = ?4 AND deleted_at IS NULL
                 "#,
             )
             .bind(b3sum)
             .bind(file.size as i64)
             .bind(updated_at)
-            .bind(relative_path)
-            .execute(&mut *tx)
+            .bind(&relative_path)
+            .bind(file.attributes.mode)
+            .bind(file.attributes.uid)
+            .bind(file.attributes.gid)
+            .bind(&file.attributes.xattrs)
+            .bind(file.dev.map(|d| d as i64))
+            .bind(file.ino.map(|i| i as i64))
+            .bind(file.modified_ns())
+            .bind(&file.secondary.sha256)
+            .bind(&file.secondary.xxh3)
+            .bind(&file.secondary.mac)
+            .bind(file.stored)
+            .bind(file.ctime_ns)
+            .bind(&file.content_type)
+            .execute(&mut **tx)
             .await?;
+
+            if let Some(chunks) = &file.secondary.chunks {
+                Self::insert_chunk_hashes(tx, b3sum, chunks).await?;
+            }
+
+            Self::resolve_verification_failures_tx(tx, &relative_path, FailureResolution::Updated)
+                .await?;
         }
 
-        tx.commit().await?;
         Ok(())
     }
 
     /// Batch delete file records in a single transaction
+    ///
+    /// Records are tombstoned with a `deleted_at` timestamp rather than removed,
+    /// so the deletion can be reverted until `prune` purges them.
     pub async fn batch_delete_file_records(
         &self,
         action_id: i64,
@@ -204,10 +572,19 @@ impl Database {
             .execute(&mut *tx)
             .await?;
 
-            // Delete from files table
-            sqlx::query("DELETE FROM files WHERE path = ?1")
-                .bind(file_path)
-                .execute(&mut *tx)
+            // Tombstone the files table entry
+            sqlx::query(
+                r#"
+                UPDATE files
+                SET deleted_at = CURRENT_TIMESTAMP
+                WHERE path = ?1 AND deleted_at IS NULL
+                "#,
+            )
+            .bind(file_path)
+            .execute(&mut *tx)
+            .await?;
+
+            Self::resolve_verification_failures_tx(&mut tx, file_path, FailureResolution::Removed)
                 .await?;
         }
 
@@ -215,76 +592,366 @@ impl Database {
         Ok(())
     }
 
-    /// Get all checksums referenced in the database (both files and history tables)
-    pub async fn get_all_referenced_checksums(&self) -> Result<std::collections::HashSet<String>> {
-        let mut checksums = std::collections::HashSet::new();
+    /// Objects no file record or history entry has referenced since `grace_cutoff`
+    pub async fn find_orphaned_objects(
+        &self,
+        grace_cutoff: chrono::DateTime<Utc>,
+    ) -> Result<Vec<OrphanedObject>> {
+        let grace_cutoff = grace_cutoff.naive_utc();
+        let records = sqlx::query!(
+            r#"
+            SELECT b3sum, size
+            FROM objects
+            WHERE refcount <= 0 AND (unreferenced_at IS NULL OR unreferenced_at < ?1)
+            ORDER BY b3sum
+            "#,
+            grace_cutoff
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(records
+            .into_iter()
+            .map(|record| self.orphaned_object(record.b3sum, record.size))
+            .collect())
+    }
 
-        // Get checksums from active files
-        let active_checksums = sqlx::query!(
+    /// Referenced objects that become orphaned once prune has removed the history
+    /// entries of `pruned_actions` (only those of `action_type`, if given) and the
+    /// records deleted before `records_cutoff`
+    pub async fn find_objects_released_by_prune(
+        &self,
+        pruned_actions: &[i64],
+        action_type: Option<ActionType>,
+        records_cutoff: chrono::DateTime<Utc>,
+    ) -> Result<Vec<OrphanedObject>> {
+        let pruned_actions = json_id_list(pruned_actions);
+        let action_type = action_type.map(ActionType::to_i32);
+        let records_cutoff = records_cutoff.naive_utc();
+        let records = sqlx::query!(
             r#"
-            SELECT DISTINCT b3sum
-            FROM files
-            WHERE b3sum IS NOT NULL
-            "#
+            SELECT b3sum, size
+            FROM objects
+            WHERE refcount > 0
+              AND refcount
+                - (SELECT COUNT(*) FROM history
+                   WHERE history.b3sum = objects.b3sum
+                     AND action_id IN (SELECT value FROM json_each(?1))
+                     AND (?2 IS NULL OR action_type = ?2))
+                - (SELECT COUNT(*) FROM files
+                   WHERE files.b3sum = objects.b3sum
+                     AND deleted_at IS NOT NULL AND deleted_at < ?3)
+                <= 0
+            ORDER BY b3sum
+            "#,
+            pruned_actions,
+            action_type,
+            records_cutoff
         )
         .fetch_all(&self.pool)
         .await?;
 
-        for record in active_checksums {
-            checksums.insert(record.b3sum);
-        }
+        Ok(records
+            .into_iter()
+            .map(|record| self.orphaned_object(record.b3sum, record.size))
+            .collect())
+    }
 
-        // Get checksums from history (to preserve deleted files)
-        let history_checksums = sqlx::query!(
+    /// Objects `action_id` started referencing that no earlier history entry
+    /// referenced, and the content it replaced or deleted that no tracked file
+    /// has anymore, each with the paths it belonged to. Also the objects that
+    /// would become orphaned if the action's history expired.
+    pub async fn get_action_objects(&self, action_id: i64) -> Result<ActionObjects> {
+        let introduced = sqlx::query!(
             r#"
-            SELECT DISTINCT b3sum
-            FROM history
-            "#
+            SELECT h.b3sum AS "b3sum!", h.size AS "size!", h.path
+            FROM history h
+            WHERE h.action_id = ?1 AND h.b3sum != ''
+              AND NOT EXISTS (
+                  SELECT 1 FROM history e WHERE e.b3sum = h.b3sum AND e.action_id < ?1
+              )
+            ORDER BY h.b3sum, h.path
+            "#,
+            action_id
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        let update = ActionType::Update.to_i32();
+        let delete = ActionType::Delete.to_i32();
+        let replaced_types = json_id_list(
+            &[
+                ActionType::Add,
+                ActionType::Update,
+                ActionType::Rename,
+                ActionType::Import,
+            ]
+            .map(|action_type| action_type.to_i32() as i64),
+        );
+        let released = sqlx::query!(
+            r#"
+            WITH released (b3sum, size, path) AS (
+                -- The content an updated file had before this action
+                SELECT previous.b3sum, previous.size, h.path
+                FROM history h
+                JOIN history previous ON previous.id = (
+                    SELECT p.id FROM history p
+                    WHERE p.path = h.path AND p.action_id < h.action_id AND p.b3sum != ''
+                      AND p.action_type IN (SELECT value FROM json_each(?4))
+                    ORDER BY p.action_id DESC, p.id DESC
+                    LIMIT 1
+                )
+                WHERE h.action_id = ?1 AND h.action_type = ?2 AND previous.b3sum != h.b3sum
+                UNION ALL
+                SELECT b3sum, size, path FROM history
+                WHERE action_id = ?1 AND action_type = ?3
+            )
+            SELECT b3sum AS "b3sum!: String", size AS "size!: i64", path AS "path!: String"
+            FROM released
+            WHERE NOT EXISTS (
+                SELECT 1 FROM files WHERE files.b3sum = released.b3sum AND deleted_at IS NULL
+            )
+            ORDER BY b3sum, path
+            "#,
+            action_id,
+            update,
+            delete,
+            replaced_types
         )
         .fetch_all(&self.pool)
         .await?;
 
-        for record in history_checksums {
-            checksums.insert(record.b3sum);
+        let reclaimable = self
+            .find_objects_released_by_prune(&[action_id], None, DateTime::<Utc>::MIN_UTC)
+            .await?;
+        Ok(ActionObjects {
+            introduced: group_action_objects(
+                introduced
+                    .into_iter()
+                    .map(|record| (record.b3sum, record.size, record.path)),
+            ),
+            released: group_action_objects(
+                released
+                    .into_iter()
+                    .map(|record| (record.b3sum, record.size, record.path)),
+            ),
+            reclaimable,
+        })
+    }
+
+    fn orphaned_object(&self, b3sum: String, size: i64) -> OrphanedObject {
+        OrphanedObject {
+            size: size as u64,
+            b3sum,
         }
+    }
 
-        Ok(checksums)
+    /// Move objects orphaned before `grace_cutoff` from the object store to
+    /// `trash_dir`, returning the moved objects. An object's row goes only
+    /// once it is in the trash. Objects not kept on this machine, in a remote
+    /// object store or the cold store, have no trash and are left in place.
+    pub async fn cleanup_orphaned_objects(
+        &self,
+        grace_cutoff: chrono::DateTime<Utc>,
+        trash_dir: &Path,
+        objects: &dyn ObjectBackend,
+    ) -> Result<Vec<OrphanedObject>> {
+        let orphaned = self.find_orphaned_objects(grace_cutoff).await?;
+        self.trash_objects(orphaned, trash_dir, objects).await
     }
 
-    /// Clean up orphaned objects from the object store
-    pub async fn cleanup_orphaned_objects(&self) -> Result<usize> {
-        let referenced_checksums = self.get_all_referenced_checksums().await?;
-        let objects_dir = self.repo_root.join(".ddrive").join("objects");
+    /// Move the unreferenced objects among `orphaned` from the object store
+    /// to `trash_dir`, returning the moved objects. An object's row goes only
+    /// once it is in the trash, and objects referenced again meanwhile are
+    /// put back. Objects not kept on this machine are left in place.
+    async fn trash_objects(
+        &self,
+        orphaned: Vec<OrphanedObject>,
+        trash_dir: &Path,
+        objects: &dyn ObjectBackend,
+    ) -> Result<Vec<OrphanedObject>> {
+        let mut trashed = Vec::with_capacity(orphaned.len());
+        let mut kept = 0;
+        for object in orphaned {
+            let Some(object_path) = objects.local_path(&object.b3sum) else {
+                kept += 1;
+                continue;
+            };
+            let trash_path = trash_dir.join(&object.b3sum);
+            std::fs::create_dir_all(trash_dir)?;
+            let moved = match std::fs::rename(&object_path, &trash_path) {
+                Ok(()) => true,
+                // Not in the local store, but possibly in the cold one
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                    if objects.exists(&object.b3sum)? {
+                        kept += 1;
+                        continue;
+                    }
+                    // Gone already, so only the row is left to remove
+                    false
+                }
+                Err(e) => return Err(e.into()),
+            };
+            // Only objects still unreferenced when their row goes are removed
+            let result = sqlx::query!(
+                "DELETE FROM objects WHERE b3sum = ?1 AND refcount <= 0",
+                object.b3sum
+            )
+            .execute(&self.pool)
+            .await?;
+            if !moved {
+                continue;
+            }
+            if result.rows_affected() == 0 {
+                // Referenced again since it was found
+                std::fs::rename(&trash_path, &object_path)?;
+                continue;
+            }
+            trashed.push(object);
+        }
 
-        if !objects_dir.exists() {
-            return Ok(0);
+        if kept > 0 {
+            warn!(
+                "Kept {} orphaned objects that are not stored on this machine ({})",
+                kept,
+                objects.location()
+            );
         }
+        Ok(trashed)
+    }
+
+    /// Remove the history entries of `action_id` and the tombstoned records of
+    /// the deletions it recorded, then permanently delete the objects this leaves
+    /// unreferenced, returning them. Unlike `cleanup_orphaned_objects` this skips
+    /// the trash: it serves `prune --target-free`, where trashed objects would
+    /// still take up the space it has to free, and only runs for actions older
+    /// than the orphan grace period.
+    pub async fn reclaim_action(
+        &self,
+        action_id: i64,
+        objects: &dyn ObjectBackend,
+    ) -> Result<Vec<OrphanedObject>> {
+        let delete = ActionType::Delete.to_i32();
+        let mut tx = self.pool.begin().await?;
+
+        sqlx::query!(
+            r#"
+            DELETE FROM files
+            WHERE deleted_at IS NOT NULL AND EXISTS (
+                SELECT 1 FROM history
+                WHERE history.action_id = ?1 AND history.action_type = ?2
+                  AND history.path = files.path AND history.b3sum = files.b3sum
+            )
+            "#,
+            action_id,
+            delete
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        let checksums = sqlx::query_scalar!(
+            "SELECT DISTINCT b3sum FROM history WHERE action_id = ?1",
+            action_id
+        )
+        .fetch_all(&mut *tx)
+        .await?;
+        sqlx::query!("DELETE FROM history WHERE action_id = ?1", action_id)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query!("DELETE FROM actions WHERE action_id = ?1", action_id)
+            .execute(&mut *tx)
+            .await?;
 
-        let mut deleted_count = 0;
+        let mut released = Vec::new();
+        for b3sum in checksums {
+            let object = sqlx::query!(
+                "DELETE FROM objects WHERE b3sum = ?1 AND refcount <= 0 RETURNING b3sum, size",
+                b3sum
+            )
+            .fetch_optional(&mut *tx)
+            .await?;
+            if let Some(object) = object {
+                released.push(self.orphaned_object(object.b3sum, object.size));
+            }
+        }
+        tx.commit().await?;
 
-        // Walk through the object store directory structure
-        let files = get_all_files(&self.repo_root, &objects_dir, true, false)?;
+        for object in &released {
+            objects.delete(&object.b3sum)?;
+        }
+        Ok(released)
+    }
 
-        info!("Active objects: {}", referenced_checksums.len());
-        info!("Available objects: {}", files.len());
+    /// Forget `paths`: drop their history entries and tombstoned records past
+    /// the retention `cutoff`, then move the objects this leaves unreferenced
+    /// to `trash_dir`, returning them. Objects other files, recent history or
+    /// tombstones still reference are kept. With `dry_run` the changes are
+    /// rolled back and only the objects that would be trashed are returned.
+    pub async fn purge_paths(
+        &self,
+        paths: &[String],
+        cutoff: DateTime<Utc>,
+        trash_dir: &Path,
+        dry_run: bool,
+        objects: &dyn ObjectBackend,
+    ) -> Result<Vec<OrphanedObject>> {
+        let history_cutoff = cutoff.timestamp();
+        let records_cutoff = cutoff.naive_utc();
+        let mut tx = self.pool.begin().await?;
+        let mut checksums = HashSet::new();
+        for path in paths {
+            let history_checksums = sqlx::query_scalar!(
+                r#"SELECT DISTINCT b3sum AS "b3sum!" FROM history WHERE path = ?1 AND action_id < ?2"#,
+                path,
+                history_cutoff
+            )
+            .fetch_all(&mut *tx)
+            .await?;
+            let record_checksums = sqlx::query_scalar!(
+                r#"SELECT b3sum AS "b3sum!" FROM files WHERE path = ?1 AND deleted_at < ?2"#,
+                path,
+                records_cutoff
+            )
+            .fetch_all(&mut *tx)
+            .await?;
+            checksums.extend(history_checksums);
+            checksums.extend(record_checksums);
 
-        for file in files {
-            let checksum = file
-                .path
-                .file_name()
-                .expect("filename")
-                .to_str()
-                .expect("filename");
+            sqlx::query!(
+                "DELETE FROM files WHERE path = ?1 AND deleted_at < ?2",
+                path,
+                records_cutoff
+            )
+            .execute(&mut *tx)
+            .await?;
+            sqlx::query!(
+                "DELETE FROM history WHERE path = ?1 AND action_id < ?2",
+                path,
+                history_cutoff
+            )
+            .execute(&mut *tx)
+            .await?;
+        }
 
-            if referenced_checksums.contains(checksum) {
-                continue;
+        let mut released = Vec::new();
+        for b3sum in checksums {
+            let object = sqlx::query!(
+                "SELECT b3sum, size FROM objects WHERE b3sum = ?1 AND refcount <= 0",
+                b3sum
+            )
+            .fetch_optional(&mut *tx)
+            .await?;
+            if let Some(object) = object {
+                released.push(self.orphaned_object(object.b3sum, object.size));
             }
-            deleted_count += 1;
-            std::fs::remove_file(&file.path)?;
-            info!("Deleted orphaned object: {}", file.path.display());
         }
+        if dry_run {
+            tx.rollback().await?;
+            return Ok(released);
+        }
+        tx.commit().await?;
 
-        Ok(deleted_count)
+        self.trash_objects(released, trash_dir, objects).await
     }
 
     /// Get a file record by path
@@ -294,9 +961,9 @@ impl Database {
         let record = sqlx::query_as!(
             FileRecord,
             r#"
-            SELECT id, path, created_at, updated_at, last_checked, b3sum, size
+            SELECT id, path, created_at, updated_at, last_checked, b3sum, size, mode, uid, gid, xattrs, dev, ino, mtime_ns, sha256, xxh3, mac, stored, ctime_ns
             FROM files 
-            WHERE path = ?1
+            WHERE path = ?1 AND deleted_at IS NULL
             "#,
             relative_path
         )
@@ -308,15 +975,22 @@ impl Database {
 
     /// Get all the records matching given path
     pub async fn get_files_by_paths(&self, file_paths: &Vec<&str>) -> Result<Vec<FileRecord>> {
+        if file_paths.is_empty() {
+            return Ok(Vec::new());
+        }
+        let file_paths = file_paths
+            .iter()
+            .map(|path| self.convert_to_relative_path(path))
+            .collect::<Result<Vec<_>>>()?;
         let mut query_builder = QueryBuilder::new(
-            "SELECT id, path, created_at, updated_at, last_checked, b3sum, size FROM files WHERE path IN (",
+            "SELECT id, path, created_at, updated_at, last_checked, b3sum, size, mode, uid, gid, xattrs, dev, ino, mtime_ns, sha256, xxh3, mac, stored, ctime_ns FROM files WHERE path IN (",
         );
 
-        query_builder.push_values(file_paths, |mut b, path| {
+        query_builder.push_values(&file_paths, |mut b, path| {
             b.push_bind(path);
         });
 
-        query_builder.push(")");
+        query_builder.push(") AND deleted_at IS NULL");
         let query = query_builder.build_query_as::<FileRecord>();
         let records = query.fetch_all(&self.pool).await?;
         Ok(records)
@@ -330,7 +1004,7 @@ impl Database {
             r#"
             UPDATE files 
             SET last_checked = CURRENT_TIMESTAMP
-            WHERE path = ?1
+            WHERE path = ?1 AND deleted_at IS NULL
             "#,
             relative_path
         )
@@ -353,7 +1027,9 @@ impl Database {
             let relative_path = self.convert_to_relative_path(file_path)?;
 
             // Create a new query for each record
-            sqlx::query("UPDATE files SET last_checked = CURRENT_TIMESTAMP WHERE path = ?")
+            sqlx::query(
+                "UPDATE files SET last_checked = CURRENT_TIMESTAMP WHERE path = ? AND deleted_at IS NULL",
+            )
                 .bind(relative_path)
                 .execute(&mut *tx)
                 .await?;
@@ -365,13 +1041,20 @@ impl Database {
         Ok(())
     }
 
-    /// Find all active files for duplicate detection
+    /// Find the active files whose content at least one other active file shares
     pub async fn find_duplicates(&self) -> Result<Vec<FileRecord>> {
         let records = sqlx::query_as!(
             FileRecord,
             r#"
-            SELECT id, path, created_at, updated_at, last_checked, b3sum, size
+            SELECT id, path, created_at, updated_at, last_checked, b3sum, size, mode, uid, gid, xattrs, dev, ino, mtime_ns, sha256, xxh3, mac, stored, ctime_ns
             FROM files 
+            WHERE deleted_at IS NULL
+              AND b3sum IN (
+                SELECT b3sum FROM files
+                WHERE deleted_at IS NULL
+                GROUP BY b3sum
+                HAVING COUNT(*) > 1
+              )
             ORDER BY b3sum, path
             "#
         )
@@ -381,40 +1064,755 @@ impl Database {
         Ok(records)
     }
 
-    /// Delete a file record from the database (hard delete)
-    pub async fn delete_file_record(&self, file_path: &str) -> Result<()> {
-        let relative_path = self.convert_to_relative_path(file_path)?;
-        sqlx::query!("DELETE FROM files WHERE path = ?1", relative_path)
-            .execute(&self.pool)
-            .await?;
-
-        Ok(())
+    /// Count duplicate groups, the files in them and the space the extra
+    /// copies take. Hardlinks of one inode share storage and do not count as
+    /// duplicates of each other.
+    pub async fn get_duplicate_stats(&self) -> Result<DuplicateStats> {
+        let stats = sqlx::query_as!(
+            DuplicateStats,
+            r#"
+            SELECT
+                COUNT(*) AS "groups!: i64",
+                COALESCE(SUM(files), 0) AS "files!: i64",
+                COALESCE(SUM(size * (inodes - 1)), 0) AS "wasted_space!: i64"
+            FROM (
+                SELECT
+                    COUNT(*) AS files,
+                    MAX(size) AS size,
+                    COUNT(DISTINCT CASE
+                        WHEN dev IS NULL OR ino IS NULL THEN 'id:' || id
+                        ELSE dev || ':' || ino
+                    END) AS inodes
+                FROM files
+                WHERE deleted_at IS NULL
+                GROUP BY b3sum
+                HAVING inodes > 1
+            )
+            "#
+        )
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(stats)
     }
 
-    /// Get all tracked files
-    pub async fn get_all_files(&self) -> Result<Vec<FileRecord>> {
+    /// Tracked files and bytes per top-level directory (`""` for files in
+    /// the repository root), with the oldest verification in each
+    pub async fn get_directory_coverage(&self) -> Result<Vec<TrackedDirectory>> {
         let records = sqlx::query_as!(
-            FileRecord,
+            TrackedDirectory,
             r#"
-            SELECT id, path, created_at, updated_at, last_checked, b3sum, size
-            FROM files 
-            ORDER BY path
+            SELECT
+                CASE WHEN instr(path, '/') > 0
+                    THEN substr(path, 1, instr(path, '/') - 1)
+                    ELSE ''
+                END AS "directory!: String",
+                COUNT(*) AS "files!: i64",
+                COALESCE(SUM(size), 0) AS "size!: i64",
+                COUNT(*) - COUNT(last_checked) AS "unverified!: i64",
+                MIN(last_checked) AS "oldest_verified: chrono::NaiveDateTime"
+            FROM files
+            WHERE deleted_at IS NULL
+            GROUP BY 1
+            ORDER BY 1
             "#
         )
         .fetch_all(&self.pool)
         .await?;
-
         Ok(records)
     }
 
-    /// Get files that match a path prefix
-    pub async fn get_files_by_path_prefix(&self, path_prefix: &str) -> Result<Vec<FileRecord>> {
-        let records = sqlx::query_as!(
-            FileRecord,
-            r#"
-            SELECT id, path, created_at, updated_at, last_checked, b3sum, size
-            FROM files 
-            WHERE path LIKE ?1 || '%'
+    /// Remember the mount point and file system of devices files were seen on
+    pub async fn record_devices(&self, devices: &[Device]) -> Result<()> {
+        for device in devices {
+            let dev = device.dev as i64;
+            let mount_point = device.mount_point.to_string_lossy();
+            sqlx::query!(
+                r#"
+                INSERT INTO devices (dev, mount_point, fs_type, source, uuid, last_seen_at)
+                VALUES (?1, ?2, ?3, ?4, ?5, CURRENT_TIMESTAMP)
+                ON CONFLICT(dev) DO UPDATE SET
+                    mount_point = excluded.mount_point,
+                    fs_type = excluded.fs_type,
+                    source = excluded.source,
+                    uuid = excluded.uuid,
+                    last_seen_at = excluded.last_seen_at
+                "#,
+                dev,
+                mount_point,
+                device.fs_type,
+                device.source,
+                device.uuid
+            )
+            .execute(&self.pool)
+            .await?;
+        }
+        Ok(())
+    }
+
+    /// Devices files were last seen on, by device number
+    pub async fn get_devices(&self) -> Result<HashMap<i64, DeviceRecord>> {
+        let records = sqlx::query_as!(
+            DeviceRecord,
+            "SELECT dev, mount_point, fs_type, uuid FROM devices"
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(records
+            .into_iter()
+            .map(|record| (record.dev, record))
+            .collect())
+    }
+
+    /// Tracked files, bytes and unresolved integrity failures per device
+    pub async fn get_device_stats(&self) -> Result<Vec<DeviceStats>> {
+        let records = sqlx::query_as!(
+            DeviceStats,
+            r#"
+            SELECT
+                files.dev AS "dev?: i64",
+                devices.mount_point AS "mount_point?: String",
+                devices.fs_type AS "fs_type?: String",
+                devices.uuid AS "uuid?: String",
+                COUNT(*) AS "files!: i64",
+                COALESCE(SUM(files.size), 0) AS "size!: i64",
+                COUNT(failures.path) AS "failures!: i64"
+            FROM files
+            LEFT JOIN devices ON devices.dev = files.dev
+            LEFT JOIN (
+                SELECT DISTINCT path FROM verification_failures WHERE resolved_at IS NULL
+            ) AS failures ON failures.path = files.path
+            WHERE files.deleted_at IS NULL
+            GROUP BY files.dev
+            ORDER BY devices.mount_point, files.dev
+            "#
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(records)
+    }
+
+    /// Delete a file record from the database (soft delete)
+    pub async fn delete_file_record(&self, file_path: &str) -> Result<()> {
+        let relative_path = self.convert_to_relative_path(file_path)?;
+        sqlx::query!(
+            r#"
+            UPDATE files
+            SET deleted_at = CURRENT_TIMESTAMP
+            WHERE path = ?1 AND deleted_at IS NULL
+            "#,
+            relative_path
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Soft-deleted records that `purge_deleted_file_records` would remove
+    pub async fn get_purgeable_file_records(
+        &self,
+        cutoff_date: chrono::DateTime<Utc>,
+    ) -> Result<Vec<FileRecord>> {
+        let cutoff = cutoff_date.naive_utc();
+        let records = sqlx::query_as!(
+            FileRecord,
+            r#"
+            SELECT id, path, created_at, updated_at, last_checked, b3sum, size, mode, uid, gid, xattrs, dev, ino, mtime_ns, sha256, xxh3, mac, stored, ctime_ns
+            FROM files
+            WHERE deleted_at IS NOT NULL AND deleted_at < ?1
+            ORDER BY path
+            "#,
+            cutoff
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(records)
+    }
+
+    /// Permanently remove file records that were soft deleted before the cutoff
+    pub async fn purge_deleted_file_records(
+        &self,
+        cutoff_date: chrono::DateTime<Utc>,
+    ) -> Result<usize> {
+        let cutoff = cutoff_date.naive_utc();
+        let result = sqlx::query!(
+            r#"
+            DELETE FROM files
+            WHERE deleted_at IS NOT NULL AND deleted_at < ?1
+            "#,
+            cutoff
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() as usize)
+    }
+
+    /// Record chunk hashes for a content checksum, unless already present
+    async fn insert_chunk_hashes(
+        tx: &mut sqlx::Transaction<'_, sqlx::Sqlite>,
+        b3sum: &str,
+        chunks: &ChunkHashes,
+    ) -> Result<()> {
+        let chunk_size = chunks.chunk_size as i64;
+        for (index, hash) in chunks.hashes.iter().enumerate() {
+            let index = index as i64;
+            sqlx::query!(
+                r#"
+                INSERT OR IGNORE INTO chunks (b3sum, chunk_index, chunk_size, hash)
+                VALUES (?1, ?2, ?3, ?4)
+                "#,
+                b3sum,
+                index,
+                chunk_size,
+                hash
+            )
+            .execute(&mut **tx)
+            .await?;
+        }
+        Ok(())
+    }
+
+    /// Chunk hashes recorded for a content checksum, if any
+    pub async fn get_chunk_hashes(&self, b3sum: &str) -> Result<Option<ChunkHashes>> {
+        let rows = sqlx::query!(
+            "SELECT chunk_size, hash FROM chunks WHERE b3sum = ?1 ORDER BY chunk_index",
+            b3sum
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        let Some(first) = rows.first() else {
+            return Ok(None);
+        };
+        Ok(Some(ChunkHashes {
+            chunk_size: first.chunk_size as u64,
+            hashes: rows.into_iter().map(|row| row.hash).collect(),
+        }))
+    }
+
+    /// Remove chunk hashes of contents no longer referenced by any file record
+    pub async fn cleanup_orphaned_chunks(&self) -> Result<usize> {
+        let result =
+            sqlx::query!("DELETE FROM chunks WHERE b3sum NOT IN (SELECT b3sum FROM files)")
+                .execute(&self.pool)
+                .await?;
+        Ok(result.rows_affected() as usize)
+    }
+
+    /// Record files that could not be protected, replacing earlier failures
+    /// for the same paths
+    pub async fn record_problems(&self, action_id: i64, problems: &[Problem]) -> Result<()> {
+        if problems.is_empty() {
+            return Ok(());
+        }
+
+        let mut tx = self.pool.begin().await?;
+        for problem in problems {
+            sqlx::query!(
+                r#"
+                INSERT OR REPLACE INTO problems (path, action_id, kind, message)
+                VALUES (?1, ?2, ?3, ?4)
+                "#,
+                problem.path,
+                action_id,
+                problem.kind,
+                problem.message
+            )
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Clear recorded problems of files that have since been stored
+    pub async fn resolve_problems(&self, paths: &[&str]) -> Result<()> {
+        if paths.is_empty() {
+            return Ok(());
+        }
+
+        let mut tx = self.pool.begin().await?;
+        for path in paths {
+            sqlx::query!("DELETE FROM problems WHERE path = ?1", path)
+                .execute(&mut *tx)
+                .await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Clear problems of one kind recorded for `prefix` or paths below it, or
+    /// for every path when `prefix` is empty, before a rescan records them anew
+    pub async fn clear_problems_under(&self, prefix: &str, kind: &str) -> Result<()> {
+        sqlx::query!(
+            r#"
+            DELETE FROM problems
+            WHERE kind = ?1
+              AND (?2 = '' OR path = ?2 OR substr(path, 1, length(?2) + 1) = ?2 || '/')
+            "#,
+            kind,
+            prefix
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// All outstanding problems, oldest first
+    pub async fn get_problems(&self) -> Result<Vec<Problem>> {
+        let problems = sqlx::query_as!(
+            Problem,
+            "SELECT path, kind, message FROM problems ORDER BY occurred_at, path"
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(problems)
+    }
+
+    /// Record an integrity failure, or refresh the unresolved one already
+    /// recorded for the same path
+    pub async fn record_verification_failure(
+        &self,
+        path: &str,
+        algorithm: HashAlgorithm,
+        expected_checksum: &str,
+        actual_checksum: &str,
+    ) -> Result<()> {
+        let algorithm = algorithm.to_string();
+        let mut tx = self.pool.begin().await?;
+        let updated = sqlx::query!(
+            r#"
+            UPDATE verification_failures
+            SET algorithm = ?2, expected_checksum = ?3, actual_checksum = ?4,
+                last_detected_at = CURRENT_TIMESTAMP
+            WHERE path = ?1 AND resolved_at IS NULL
+            "#,
+            path,
+            algorithm,
+            expected_checksum,
+            actual_checksum
+        )
+        .execute(&mut *tx)
+        .await?;
+        if updated.rows_affected() == 0 {
+            sqlx::query!(
+                r#"
+                INSERT INTO verification_failures (path, algorithm, expected_checksum, actual_checksum)
+                VALUES (?1, ?2, ?3, ?4)
+                "#,
+                path,
+                algorithm,
+                expected_checksum,
+                actual_checksum
+            )
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Mark the unresolved integrity failure of a file as resolved
+    pub async fn resolve_verification_failures(
+        &self,
+        path: &str,
+        resolution: FailureResolution,
+    ) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+        Self::resolve_verification_failures_tx(&mut tx, path, resolution).await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn resolve_verification_failures_tx(
+        tx: &mut sqlx::Transaction<'_, sqlx::Sqlite>,
+        path: &str,
+        resolution: FailureResolution,
+    ) -> Result<()> {
+        let resolution = resolution.to_string();
+        sqlx::query!(
+            r#"
+            UPDATE verification_failures
+            SET resolved_at = CURRENT_TIMESTAMP, resolution = ?2
+            WHERE path = ?1 AND resolved_at IS NULL
+            "#,
+            path,
+            resolution
+        )
+        .execute(&mut **tx)
+        .await?;
+        Ok(())
+    }
+
+    /// Integrity failures that have not been resolved yet, oldest first
+    pub async fn get_unresolved_verification_failures(&self) -> Result<Vec<VerificationFailure>> {
+        let failures = sqlx::query_as!(
+            VerificationFailure,
+            r#"
+            SELECT path, algorithm, expected_checksum, actual_checksum, detected_at, last_detected_at
+            FROM verification_failures
+            WHERE resolved_at IS NULL
+            ORDER BY detected_at, path
+            "#
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(failures)
+    }
+
+    /// Every integrity failure ever detected, with the size, age, device and
+    /// content type of the file it was detected in, oldest first
+    pub async fn get_failure_profiles(&self) -> Result<Vec<FileProfile>> {
+        let failures = sqlx::query_as!(
+            FileProfile,
+            r#"
+            SELECT
+                verification_failures.path,
+                files.size AS "size?: i64",
+                COALESCE(files.created_at, files.updated_at) AS "born_at?: chrono::NaiveDateTime",
+                files.dev AS "dev?: i64",
+                files.content_type AS "content_type?: String",
+                verification_failures.detected_at AS "seen_at!: chrono::NaiveDateTime"
+            FROM verification_failures
+            LEFT JOIN files ON files.path = verification_failures.path
+            ORDER BY verification_failures.detected_at, verification_failures.path
+            "#
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(failures)
+    }
+
+    /// The size, age, device and content type of every tracked file
+    pub async fn get_file_profiles(&self) -> Result<Vec<FileProfile>> {
+        let files = sqlx::query_as!(
+            FileProfile,
+            r#"
+            SELECT
+                path,
+                size AS "size?: i64",
+                COALESCE(created_at, updated_at) AS "born_at?: chrono::NaiveDateTime",
+                dev AS "dev?: i64",
+                content_type AS "content_type?: String",
+                CURRENT_TIMESTAMP AS "seen_at!: chrono::NaiveDateTime"
+            FROM files
+            WHERE deleted_at IS NULL
+            ORDER BY path
+            "#
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(files)
+    }
+
+    /// Files last copied to the mirror at `destination`
+    pub async fn get_mirrored_files(&self, destination: &str) -> Result<Vec<MirroredFile>> {
+        let files = sqlx::query_as!(
+            MirroredFile,
+            "SELECT path, b3sum, size FROM mirrored_files WHERE destination = ?1 ORDER BY path",
+            destination
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(files)
+    }
+
+    /// Up to `limit` files of the mirror at `destination`, picked at random
+    pub async fn get_mirror_sample(
+        &self,
+        destination: &str,
+        limit: usize,
+    ) -> Result<Vec<MirroredFile>> {
+        let limit = limit as i64;
+        let files = sqlx::query_as!(
+            MirroredFile,
+            r#"
+            SELECT path, b3sum, size FROM mirrored_files
+            WHERE destination = ?1
+            ORDER BY RANDOM()
+            LIMIT ?2
+            "#,
+            destination,
+            limit
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(files)
+    }
+
+    /// Record `copied` as mirrored to `destination` and forget `removed`
+    pub async fn update_mirrored_files(
+        &self,
+        destination: &str,
+        copied: &[MirroredFile],
+        removed: &[String],
+    ) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+        for file in copied {
+            sqlx::query!(
+                r#"
+                INSERT INTO mirrored_files (destination, path, b3sum, size)
+                VALUES (?1, ?2, ?3, ?4)
+                ON CONFLICT (destination, path) DO UPDATE SET
+                    b3sum = excluded.b3sum,
+                    size = excluded.size,
+                    mirrored_at = CURRENT_TIMESTAMP
+                "#,
+                destination,
+                file.path,
+                file.b3sum,
+                file.size
+            )
+            .execute(&mut *tx)
+            .await?;
+        }
+        for path in removed {
+            sqlx::query!(
+                "DELETE FROM mirrored_files WHERE destination = ?1 AND path = ?2",
+                destination,
+                path
+            )
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Checksums held by any exported medium
+    pub async fn get_exported_checksums(&self) -> Result<HashSet<String>> {
+        let checksums = sqlx::query_scalar!("SELECT DISTINCT b3sum FROM media_objects")
+            .fetch_all(&self.pool)
+            .await?;
+        Ok(checksums.into_iter().collect())
+    }
+
+    /// Record the medium `label` as holding `objects`
+    pub async fn record_media(
+        &self,
+        label: &str,
+        capacity: u64,
+        objects: &[MediaObject],
+    ) -> Result<()> {
+        let capacity = capacity as i64;
+        let mut tx = self.pool.begin().await?;
+        sqlx::query!(
+            "INSERT INTO media (label, capacity) VALUES (?1, ?2)",
+            label,
+            capacity
+        )
+        .execute(&mut *tx)
+        .await?;
+        for object in objects {
+            sqlx::query!(
+                "INSERT INTO media_objects (label, b3sum, size) VALUES (?1, ?2, ?3)",
+                label,
+                object.b3sum,
+                object.size
+            )
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Every exported medium with its object count and size, oldest first
+    pub async fn get_media(&self) -> Result<Vec<MediaRecord>> {
+        let media = sqlx::query_as!(
+            MediaRecord,
+            r#"
+            SELECT
+                m.label,
+                m.capacity,
+                m.created_at AS "created_at: chrono::NaiveDateTime",
+                m.last_verified_at AS "last_verified_at: chrono::NaiveDateTime",
+                m.last_verify_failures,
+                COUNT(o.b3sum) AS "objects!: i64",
+                COALESCE(SUM(o.size), 0) AS "bytes!: i64"
+            FROM media m
+            LEFT JOIN media_objects o ON o.label = m.label
+            GROUP BY m.label
+            ORDER BY m.created_at, m.label
+            "#
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(media)
+    }
+
+    /// The objects recorded on the medium `label`
+    pub async fn get_media_objects(&self, label: &str) -> Result<Vec<MediaObject>> {
+        let objects = sqlx::query_as!(
+            MediaObject,
+            "SELECT b3sum, size FROM media_objects WHERE label = ?1 ORDER BY b3sum",
+            label
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(objects)
+    }
+
+    /// Record that the medium `label` was verified with `failures` missing
+    /// or damaged objects
+    pub async fn record_media_verification(&self, label: &str, failures: usize) -> Result<()> {
+        let failures = failures as i64;
+        sqlx::query!(
+            r#"
+            UPDATE media
+            SET last_verified_at = CURRENT_TIMESTAMP, last_verify_failures = ?2
+            WHERE label = ?1
+            "#,
+            label,
+            failures
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Objects in the hot store that no tracked file has, and that history
+    /// last referenced at or before `cutoff`
+    pub async fn find_cold_candidates(
+        &self,
+        cutoff: chrono::DateTime<Utc>,
+    ) -> Result<Vec<TieredObject>> {
+        let cutoff = cutoff.timestamp();
+        let hot = ObjectTier::Hot.to_string();
+        let objects = sqlx::query_as!(
+            TieredObject,
+            r#"
+            SELECT b3sum, size
+            FROM objects
+            WHERE tier = ?1 AND refcount > 0
+              AND NOT EXISTS (
+                  SELECT 1 FROM files
+                  WHERE files.b3sum = objects.b3sum AND files.deleted_at IS NULL
+              )
+              AND (SELECT MAX(action_id) FROM history WHERE history.b3sum = objects.b3sum) <= ?2
+            ORDER BY b3sum
+            "#,
+            hot,
+            cutoff
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(objects)
+    }
+
+    pub async fn set_object_tier(&self, b3sum: &str, tier: ObjectTier) -> Result<()> {
+        let tier = tier.to_string();
+        sqlx::query!("UPDATE objects SET tier = ?1 WHERE b3sum = ?2", tier, b3sum)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Number and size of the objects recorded in `tier`
+    pub async fn get_tier_totals(&self, tier: ObjectTier) -> Result<(u64, u64)> {
+        let tier = tier.to_string();
+        let totals = sqlx::query!(
+            r#"
+            SELECT COUNT(*) AS "objects!: i64", COALESCE(SUM(size), 0) AS "bytes!: i64"
+            FROM objects
+            WHERE tier = ?1 AND refcount > 0
+            "#,
+            tier
+        )
+        .fetch_one(&self.pool)
+        .await?;
+        Ok((totals.objects as u64, totals.bytes as u64))
+    }
+
+    /// Load all cached checksums
+    pub async fn load_hash_cache(&self) -> Result<Vec<CachedChecksum>> {
+        let entries = sqlx::query_as!(
+            CachedChecksum,
+            "SELECT dev, ino, size, mtime_ns, b3sum FROM hash_cache"
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(entries)
+    }
+
+    /// Store checksums in the cache, replacing older entries for the same file
+    pub async fn save_hash_cache(&self, entries: &[CachedChecksum]) -> Result<()> {
+        if entries.is_empty() {
+            return Ok(());
+        }
+
+        let mut tx = self.pool.begin().await?;
+        for entry in entries {
+            sqlx::query!(
+                r#"
+                INSERT OR REPLACE INTO hash_cache (dev, ino, size, mtime_ns, b3sum)
+                VALUES (?1, ?2, ?3, ?4, ?5)
+                "#,
+                entry.dev,
+                entry.ino,
+                entry.size,
+                entry.mtime_ns,
+                entry.b3sum
+            )
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Remove cached checksums older than the cutoff
+    pub async fn purge_hash_cache(&self, cutoff_date: chrono::DateTime<Utc>) -> Result<usize> {
+        let cutoff = cutoff_date.naive_utc();
+        let result = sqlx::query!("DELETE FROM hash_cache WHERE cached_at < ?1", cutoff)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected() as usize)
+    }
+
+    /// Get all tracked files
+    pub async fn get_all_files(&self) -> Result<Vec<FileRecord>> {
+        let records = sqlx::query_as!(
+            FileRecord,
+            r#"
+            SELECT id, path, created_at, updated_at, last_checked, b3sum, size, mode, uid, gid, xattrs, dev, ino, mtime_ns, sha256, xxh3, mac, stored, ctime_ns
+            FROM files 
+            WHERE deleted_at IS NULL
+            ORDER BY path
+            "#
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(records)
+    }
+
+    /// Stream all tracked files in path order, for walking them alongside a
+    /// scan without loading every record
+    pub fn stream_files(&self) -> impl Stream<Item = Result<FileRecord>> + '_ {
+        sqlx::query_as!(
+            FileRecord,
+            r#"
+            SELECT id, path, created_at, updated_at, last_checked, b3sum, size, mode, uid, gid, xattrs, dev, ino, mtime_ns, sha256, xxh3, mac, stored, ctime_ns
+            FROM files 
+            WHERE deleted_at IS NULL
+            ORDER BY path
+            "#
+        )
+        .fetch(&self.pool)
+        .map_err(DdriveError::from)
+    }
+
+    /// Get files that match a path prefix
+    pub async fn get_files_by_path_prefix(&self, path_prefix: &str) -> Result<Vec<FileRecord>> {
+        let records = sqlx::query_as!(
+            FileRecord,
+            r#"
+            SELECT id, path, created_at, updated_at, last_checked, b3sum, size, mode, uid, gid, xattrs, dev, ino, mtime_ns, sha256, xxh3, mac, stored, ctime_ns
+            FROM files
+            WHERE substr(path, 1, length(?1)) = ?1 AND deleted_at IS NULL
             ORDER BY path
             "#,
             path_prefix
@@ -425,6 +1823,107 @@ impl Database {
         Ok(records)
     }
 
+    /// One page of the files that match a path prefix, in path order
+    pub async fn get_files_page(
+        &self,
+        path_prefix: &str,
+        limit: usize,
+        offset: usize,
+    ) -> Result<Vec<FileRecord>> {
+        let (limit, offset) = (limit as i64, offset as i64);
+        let records = sqlx::query_as!(
+            FileRecord,
+            r#"
+            SELECT id, path, created_at, updated_at, last_checked, b3sum, size, mode, uid, gid, xattrs, dev, ino, mtime_ns, sha256, xxh3, mac, stored, ctime_ns
+            FROM files
+            WHERE substr(path, 1, length(?1)) = ?1 AND deleted_at IS NULL
+            ORDER BY path
+            LIMIT ?2 OFFSET ?3
+            "#,
+            path_prefix,
+            limit,
+            offset
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(records)
+    }
+
+    /// Get the path, size and content type of the files that match a path prefix
+    pub async fn get_typed_files(&self, path_prefix: &str) -> Result<Vec<TypedFile>> {
+        let files = sqlx::query_as!(
+            TypedFile,
+            r#"
+            SELECT path, size, content_type
+            FROM files
+            WHERE substr(path, 1, length(?1)) = ?1 AND deleted_at IS NULL
+            ORDER BY path
+            "#,
+            path_prefix
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(files)
+    }
+
+    /// Record the content types detected for files recorded without one
+    pub async fn set_content_types(&self, content_types: &[(String, String)]) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+        for (path, content_type) in content_types {
+            sqlx::query!(
+                "UPDATE files SET content_type = ?1 WHERE path = ?2 AND deleted_at IS NULL",
+                content_type,
+                path
+            )
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Get the files under `path_prefix` that were modified or added at or
+    /// after `since`
+    pub async fn get_files_changed_since(
+        &self,
+        since: chrono::DateTime<Utc>,
+        path_prefix: &str,
+    ) -> Result<Vec<FileRecord>> {
+        let since_ns = since.timestamp_nanos_opt().unwrap_or(i64::MIN);
+        let since_naive = since.naive_utc();
+        let since_action = since.timestamp();
+        let add = ActionType::Add.to_i32();
+        let records = sqlx::query_as!(
+            FileRecord,
+            r#"
+            SELECT id, path, created_at, updated_at, last_checked, b3sum, size, mode, uid, gid, xattrs, dev, ino, mtime_ns, sha256, xxh3, mac, stored, ctime_ns
+            FROM files
+            WHERE substr(path, 1, length(?1)) = ?1 AND deleted_at IS NULL
+                AND (
+                    COALESCE(mtime_ns >= ?2, updated_at >= ?3)
+                    OR EXISTS (
+                        SELECT 1 FROM history
+                        WHERE history.path = files.path
+                            AND history.action_type = ?4
+                            AND history.action_id >= ?5
+                    )
+                )
+            ORDER BY path
+            "#,
+            path_prefix,
+            since_ns,
+            since_naive,
+            add,
+            since_action
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(records)
+    }
+
     /// Get files that haven't been checked since a specific date
     pub async fn get_files_not_checked_since(
         &self,
@@ -433,9 +1932,9 @@ impl Database {
         let records = sqlx::query_as!(
             FileRecord,
             r#"
-            SELECT id, path, created_at, updated_at, last_checked, b3sum, size
+            SELECT id, path, created_at, updated_at, last_checked, b3sum, size, mode, uid, gid, xattrs, dev, ino, mtime_ns, sha256, xxh3, mac, stored, ctime_ns
             FROM files
-            WHERE (last_checked IS NULL OR last_checked < ?)
+            WHERE (last_checked IS NULL OR last_checked < ?) AND deleted_at IS NULL
             "#,
             cutoff_date
         )
@@ -445,6 +1944,23 @@ impl Database {
         Ok(records)
     }
 
+    /// Get all tracked files, least recently checked first (never checked files lead)
+    pub async fn get_files_by_last_checked(&self) -> Result<Vec<FileRecord>> {
+        let records = sqlx::query_as!(
+            FileRecord,
+            r#"
+            SELECT id, path, created_at, updated_at, last_checked, b3sum, size, mode, uid, gid, xattrs, dev, ino, mtime_ns, sha256, xxh3, mac, stored, ctime_ns
+            FROM files
+            WHERE deleted_at IS NULL
+            ORDER BY last_checked IS NOT NULL, last_checked, path
+            "#
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(records)
+    }
+
     /// Add a history entry for a batch of files
     pub async fn add_history_entry(
         &self,
@@ -465,18 +1981,66 @@ impl Database {
         file_entries: &[(String, Option<String>, Option<i64>)], // (file_path, file_b3sum, file_size)
         metadata: Option<JsonValue>,
     ) -> Result<()> {
-        if file_entries.is_empty() {
+        if file_entries.is_empty() {
+            return Ok(());
+        }
+
+        let mut tx = self.pool.begin().await?;
+        let metadata_json = metadata
+            .map(|m| serde_json::to_string(&m).unwrap_or_default())
+            .unwrap_or_default();
+
+        for (file_path, b3sum, size) in file_entries {
+            let relative_path = self.convert_to_relative_path(file_path)?;
+
+            sqlx::query(
+                r#"
+                INSERT INTO history (action_id, action_type, path, b3sum, size, metadata)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+                "#,
+            )
+            .bind(action_id)
+            .bind(action_type.to_i32())
+            .bind(&relative_path)
+            .bind(b3sum)
+            .bind(size)
+            .bind(&metadata_json)
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Record checksums imported from an external list. Each file gets a history
+    /// entry with the list as provenance; verified secondary checksums are also
+    /// stored on the file record if none was recorded yet.
+    pub async fn record_imported_checksums(
+        &self,
+        action_id: i64,
+        source: &str,
+        algorithm: HashAlgorithm,
+        entries: &[ImportedChecksum],
+    ) -> Result<()> {
+        if entries.is_empty() {
             return Ok(());
         }
 
-        let mut tx = self.pool.begin().await?;
-        let metadata_json = metadata
-            .map(|m| serde_json::to_string(&m).unwrap_or_default())
-            .unwrap_or_default();
-
-        for (file_path, b3sum, size) in file_entries {
-            let relative_path = self.convert_to_relative_path(file_path)?;
+        let column = match algorithm {
+            HashAlgorithm::Blake3 => None,
+            HashAlgorithm::Sha256 => Some("sha256"),
+            HashAlgorithm::Xxh3 => Some("xxh3"),
+        };
 
+        let mut tx = self.pool.begin().await?;
+        for entry in entries {
+            let metadata = serde_json::json!({
+                "source": source,
+                "algorithm": algorithm.to_string(),
+                "expected": entry.expected,
+                "verified": entry.verified,
+            });
             sqlx::query(
                 r#"
                 INSERT INTO history (action_id, action_type, path, b3sum, size, metadata)
@@ -484,20 +2048,32 @@ impl Database {
                 "#,
             )
             .bind(action_id)
-            .bind(action_type.to_i32())
-            .bind(&relative_path)
-            .bind(b3sum)
-            .bind(size)
-            .bind(&metadata_json)
+            .bind(ActionType::Import.to_i32())
+            .bind(&entry.path)
+            .bind(&entry.b3sum)
+            .bind(entry.size)
+            .bind(metadata.to_string())
             .execute(&mut *tx)
             .await?;
+
+            if let Some(column) = column
+                && entry.verified
+            {
+                sqlx::query(&format!(
+                    "UPDATE files SET {column} = ?1 WHERE path = ?2 AND {column} IS NULL AND deleted_at IS NULL"
+                ))
+                .bind(&entry.expected)
+                .bind(&entry.path)
+                .execute(&mut *tx)
+                .await?;
+            }
         }
 
         tx.commit().await?;
         Ok(())
     }
 
-    /// Get history entries with optional limit and filter
+    /// Get history entries with optional limit and filter, newest first
     pub async fn get_history_entries(
         &self,
         limit: Option<usize>,
@@ -514,6 +2090,7 @@ impl Database {
                     SELECT id, action_id, action_type, path, b3sum, size, metadata
                     FROM history
                     WHERE action_type = ?1
+                    ORDER BY action_id DESC, id
                     LIMIT ?2
                     "#,
                     action_type,
@@ -528,6 +2105,7 @@ impl Database {
                     r#"
                     SELECT id, action_id, action_type, path, b3sum, size, metadata
                     FROM history
+                    ORDER BY action_id DESC, id
                     LIMIT ?1
                     "#,
                     limit
@@ -537,42 +2115,639 @@ impl Database {
             }
         };
 
-        Ok(records)
+        Ok(records)
+    }
+
+    /// History entries of single files (not moved directories) recorded in
+    /// actions at or after `since`, oldest first
+    pub async fn get_file_changes_since(&self, since: Option<i64>) -> Result<Vec<HistoryRecord>> {
+        let directory_rename = ActionType::DirectoryRename.to_i32();
+        let records = sqlx::query_as!(
+            HistoryRecord,
+            r#"
+            SELECT id, action_id, action_type, path, b3sum, size, metadata
+            FROM history
+            WHERE (?1 IS NULL OR action_id >= ?1) AND action_type != ?2
+            ORDER BY action_id, id
+            "#,
+            since,
+            directory_rename
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(records)
+    }
+
+    /// Entry counts and sizes per action and action type of the actions
+    /// selected by `query`, newest first
+    pub async fn get_action_summaries(&self, query: &HistoryQuery) -> Result<Vec<ActionSummary>> {
+        let action_type = query.action_type.map(ActionType::to_i32);
+        let before = query.before.map(|before| before.timestamp());
+        let after = query.after.map(|after| after.timestamp());
+        let limit = query.limit as i64;
+        let offset = query.offset as i64;
+        let summaries = sqlx::query_as!(
+            ActionSummary,
+            r#"
+            WITH page AS (
+                SELECT action_id FROM history
+                WHERE (?1 IS NULL OR action_type = ?1)
+                  AND (?2 IS NULL OR action_id < ?2)
+                  AND (?3 IS NULL OR action_id >= ?3)
+                UNION
+                SELECT action_id FROM actions
+                WHERE ?1 IS NULL
+                  AND (?2 IS NULL OR action_id < ?2)
+                  AND (?3 IS NULL OR action_id >= ?3)
+                ORDER BY action_id DESC
+                LIMIT ?4 OFFSET ?5
+            )
+            SELECT
+                page.action_id AS "action_id!: i64",
+                history.action_type AS "action_type?: i64",
+                COUNT(history.id) AS "entries!: i64",
+                COALESCE(SUM(history.size), 0) AS "size!: i64",
+                COUNT(history.id) - COUNT(
+                    CASE WHEN json_valid(history.metadata)
+                        THEN json_extract(history.metadata, '$.directory') END
+                ) AS "listed!: i64"
+            FROM page
+            LEFT JOIN history ON history.action_id = page.action_id
+                AND (?1 IS NULL OR history.action_type = ?1)
+            GROUP BY page.action_id, history.action_type
+            ORDER BY page.action_id DESC, history.action_type
+            "#,
+            action_type,
+            before,
+            after,
+            limit,
+            offset
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(summaries)
+    }
+
+    /// Record how a command run went under `action_id`, folding it into the
+    /// run already recorded under the same ID
+    pub async fn record_action_run(
+        &self,
+        action_id: i64,
+        command: &str,
+        duration: Duration,
+        files: usize,
+        bytes: u64,
+        errors: usize,
+    ) -> Result<()> {
+        let duration_ms = duration.as_millis() as i64;
+        let files = files as i64;
+        let bytes = bytes as i64;
+        let errors = errors as i64;
+        let status = if errors > 0 {
+            ActionStatus::PartialFailure
+        } else {
+            ActionStatus::Success
+        }
+        .to_string();
+        let partial_failure = ActionStatus::PartialFailure.to_string();
+        sqlx::query!(
+            r#"
+            INSERT INTO actions (action_id, command, duration_ms, files, bytes, errors, status)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+            ON CONFLICT(action_id) DO UPDATE SET
+                duration_ms = duration_ms + excluded.duration_ms,
+                files = files + excluded.files,
+                bytes = bytes + excluded.bytes,
+                errors = errors + excluded.errors,
+                status = CASE WHEN errors + excluded.errors > 0 THEN ?8 ELSE status END
+            "#,
+            action_id,
+            command,
+            duration_ms,
+            files,
+            bytes,
+            errors,
+            status,
+            partial_failure
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// The unfinished verify run started with `parameters`, with the files it
+    /// has checked
+    pub async fn find_verify_session(&self, parameters: &str) -> Result<Option<VerifySession>> {
+        let Some(session) = sqlx::query!(
+            r#"
+            SELECT action_id, total_files FROM verify_sessions
+            WHERE parameters = ?1
+            ORDER BY action_id DESC
+            LIMIT 1
+            "#,
+            parameters
+        )
+        .fetch_optional(&self.pool)
+        .await?
+        else {
+            return Ok(None);
+        };
+        let checked = sqlx::query_scalar!(
+            "SELECT path FROM verify_session_files WHERE action_id = ?1",
+            session.action_id
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(Some(VerifySession {
+            action_id: session.action_id,
+            total_files: session.total_files,
+            checked: checked.into_iter().collect(),
+        }))
+    }
+
+    /// Record the start of a verify run over `total_files` files, dropping
+    /// unfinished runs started before `stale_before`, which are not resumed
+    pub async fn start_verify_session(
+        &self,
+        action_id: i64,
+        parameters: &str,
+        total_files: usize,
+        stale_before: DateTime<Utc>,
+    ) -> Result<()> {
+        let total_files = total_files as i64;
+        let mut tx = self.pool.begin().await?;
+        sqlx::query!(
+            "DELETE FROM verify_sessions WHERE started_at < ?1",
+            stale_before
+        )
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query!(
+            "INSERT INTO verify_sessions (action_id, parameters, total_files) VALUES (?1, ?2, ?3)",
+            action_id,
+            parameters,
+            total_files
+        )
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Record files a verify run has gone through, adding the files checked,
+    /// bytes hashed and errors since the last call to its run, which stays
+    /// interrupted until it finishes
+    pub async fn record_verify_progress(
+        &self,
+        action_id: i64,
+        paths: &[String],
+        duration: Duration,
+        files: usize,
+        bytes: u64,
+        errors: usize,
+    ) -> Result<()> {
+        let duration_ms = duration.as_millis() as i64;
+        let files = files as i64;
+        let bytes = bytes as i64;
+        let errors = errors as i64;
+        let interrupted = ActionStatus::Interrupted.to_string();
+        let mut tx = self.pool.begin().await?;
+        for path in paths {
+            sqlx::query!(
+                "INSERT OR IGNORE INTO verify_session_files (action_id, path) VALUES (?1, ?2)",
+                action_id,
+                path
+            )
+            .execute(&mut *tx)
+            .await?;
+        }
+        sqlx::query!(
+            r#"
+            INSERT INTO actions (action_id, command, duration_ms, files, bytes, errors, status)
+            VALUES (?1, 'verify', ?2, ?3, ?4, ?5, ?6)
+            ON CONFLICT(action_id) DO UPDATE SET
+                duration_ms = duration_ms + excluded.duration_ms,
+                files = files + excluded.files,
+                bytes = bytes + excluded.bytes,
+                errors = errors + excluded.errors,
+                status = excluded.status
+            "#,
+            action_id,
+            duration_ms,
+            files,
+            bytes,
+            errors,
+            interrupted
+        )
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Mark a verify run finished: forget its session and settle its status
+    /// on whether any of its parts found errors
+    pub async fn finish_verify_session(&self, action_id: i64) -> Result<()> {
+        let success = ActionStatus::Success.to_string();
+        let partial_failure = ActionStatus::PartialFailure.to_string();
+        let mut tx = self.pool.begin().await?;
+        sqlx::query!(
+            "DELETE FROM verify_sessions WHERE action_id = ?1",
+            action_id
+        )
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query!(
+            r#"
+            UPDATE actions
+            SET status = CASE WHEN errors > 0 THEN ?2 ELSE ?3 END
+            WHERE action_id = ?1
+            "#,
+            action_id,
+            partial_failure,
+            success
+        )
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// How the command run recorded under `action_id` went, if it was recorded
+    pub async fn get_action_run(&self, action_id: i64) -> Result<Option<ActionRun>> {
+        let run = sqlx::query_as!(
+            ActionRun,
+            r#"
+            SELECT action_id, command, duration_ms, files, bytes, errors, status
+            FROM actions
+            WHERE action_id = ?1
+            "#,
+            action_id
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(run)
+    }
+
+    /// The first `limit` entries of an action by path (only those of
+    /// `action_type`, if given), leaving out renames of files in a moved
+    /// directory
+    pub async fn get_action_sample(
+        &self,
+        action_id: i64,
+        action_type: Option<ActionType>,
+        limit: usize,
+    ) -> Result<Vec<HistoryRecord>> {
+        let action_type = action_type.map(ActionType::to_i32);
+        let directory_rename = ActionType::DirectoryRename.to_i32();
+        let limit = limit as i64;
+        let records = sqlx::query_as!(
+            HistoryRecord,
+            r#"
+            SELECT id, action_id, action_type, path, b3sum, size, metadata
+            FROM history
+            WHERE action_id = ?1
+              AND (?2 IS NULL OR action_type = ?2)
+              AND (NOT json_valid(metadata) OR json_extract(metadata, '$.directory') IS NULL)
+            ORDER BY action_type = ?3 DESC, path
+            LIMIT ?4
+            "#,
+            action_id,
+            action_type,
+            directory_rename,
+            limit
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(records)
+    }
+
+    /// IDs of all actions in history or with a recorded run, newest first
+    pub async fn get_recorded_action_ids(&self) -> Result<Vec<i64>> {
+        let action_ids = sqlx::query_scalar!(
+            r#"
+            SELECT action_id AS "action_id!: i64" FROM history
+            UNION
+            SELECT action_id FROM actions
+            ORDER BY action_id DESC
+            "#
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(action_ids)
+    }
+
+    /// Resolve a base58 action ID, or an unambiguous prefix of one, to the
+    /// action ID it names
+    pub async fn resolve_action_id(&self, action_id_base58: &str) -> Result<i64> {
+        // A full ID names its action even before it is in the history
+        let decoded = bs58::decode(action_id_base58)
+            .into_vec()
+            .unwrap_or_default();
+        if let Ok(bytes) = <[u8; 8]>::try_from(decoded.as_slice()) {
+            return Ok(i64::from_be_bytes(bytes));
+        }
+
+        let matches: Vec<(i64, String)> = self
+            .get_recorded_action_ids()
+            .await?
+            .into_iter()
+            .map(|action_id| {
+                (
+                    action_id,
+                    bs58::encode(action_id.to_be_bytes()).into_string(),
+                )
+            })
+            .filter(|(_, encoded)| encoded.starts_with(action_id_base58))
+            .collect();
+        match matches.as_slice() {
+            [] => Err(DdriveError::Validation {
+                message: format!("No action ID starts with '{action_id_base58}'"),
+            }),
+            [(action_id, _)] => Ok(*action_id),
+            _ => Err(DdriveError::Validation {
+                message: format!(
+                    "Action ID prefix '{}' is ambiguous, it matches {}",
+                    action_id_base58,
+                    matches
+                        .iter()
+                        .map(|(_, encoded)| encoded.as_str())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ),
+            }),
+        }
+    }
+
+    /// Get history entries by action ID (base58 encoded, or an unambiguous
+    /// prefix of one)
+    pub async fn get_history_entries_by_action_id_base58(
+        &self,
+        action_id_base58: &str,
+    ) -> Result<Vec<HistoryRecord>> {
+        let action_id = self.resolve_action_id(action_id_base58).await?;
+        self.get_history_entries_by_action_id(action_id).await
+    }
+
+    /// Get the history entries of one action, by path
+    pub async fn get_history_entries_by_action_id(
+        &self,
+        action_id: i64,
+    ) -> Result<Vec<HistoryRecord>> {
+        let records = sqlx::query_as!(
+            HistoryRecord,
+            r#"
+            SELECT id, action_id, action_type, path, b3sum, size, metadata
+            FROM history
+            WHERE action_id = ?1
+            ORDER BY path
+            "#,
+            action_id
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(records)
+    }
+
+    /// Get files that need verification based on configuration
+    pub async fn get_files_for_check(&self) -> Result<Vec<FileRecord>> {
+        let records = sqlx::query_as!(
+            FileRecord,
+            r#"
+            SELECT id, path, created_at, updated_at, last_checked, b3sum, size, mode, uid, gid, xattrs, dev, ino, mtime_ns, sha256, xxh3, mac, stored, ctime_ns
+            FROM files
+            WHERE last_checked IS NULL AND deleted_at IS NULL
+            ORDER BY path
+            "#
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(records)
+    }
+
+    /// Get lightweight file info for status display
+    pub async fn get_tracked_file_paths(&self) -> Result<Vec<TrackedFileInfo>> {
+        let records = sqlx::query_as!(
+            TrackedFileInfo,
+            r#"
+            SELECT path, size, created_at, dev, ino
+            FROM files
+            WHERE deleted_at IS NULL
+            ORDER BY path
+            "#
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(records)
+    }
+
+    /// Running totals of tracked files, maintained by triggers
+    pub async fn get_totals(&self) -> Result<RepositoryTotals> {
+        let totals = sqlx::query_as!(
+            RepositoryTotals,
+            "SELECT tracked_files, tracked_size, last_action_id FROM stats WHERE id = 1"
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(totals.unwrap_or_default())
+    }
+
+    /// Number of schema migrations the database has applied, and how many of
+    /// those did not complete
+    pub async fn get_migration_state(&self) -> Result<(i64, i64)> {
+        let (applied, failed): (i64, i64) =
+            sqlx::query_as("SELECT COUNT(*), COALESCE(SUM(success = 0), 0) FROM _sqlx_migrations")
+                .fetch_one(&self.pool)
+                .await?;
+        Ok((applied, failed))
+    }
+
+    /// Problems reported by SQLite's `quick_check`; empty when the database is intact
+    pub async fn quick_check(&self) -> Result<Vec<String>> {
+        let rows: Vec<String> = sqlx::query_scalar("PRAGMA quick_check")
+            .fetch_all(&self.pool)
+            .await?;
+        Ok(rows.into_iter().filter(|row| row != "ok").collect())
+    }
+
+    /// Checksums whose `objects` reference count disagrees with the file records
+    /// and history entries that actually reference them
+    pub async fn get_refcount_mismatches(&self) -> Result<Vec<String>> {
+        let mismatches = sqlx::query_scalar!(
+            r#"
+            WITH refs AS (
+                SELECT b3sum, COUNT(*) AS count FROM (
+                    SELECT b3sum FROM files WHERE b3sum IS NOT NULL
+                    UNION ALL
+                    SELECT b3sum FROM history
+                )
+                GROUP BY b3sum
+            )
+            SELECT objects.b3sum AS "b3sum!"
+            FROM objects LEFT JOIN refs ON refs.b3sum = objects.b3sum
+            WHERE objects.refcount != COALESCE(refs.count, 0)
+            UNION
+            SELECT refs.b3sum
+            FROM refs LEFT JOIN objects ON objects.b3sum = refs.b3sum
+            WHERE objects.b3sum IS NULL
+            "#
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(mismatches)
+    }
+
+    /// Set the reference count of each object that drifted from its file
+    /// records and history entries back to their number, adding rows for
+    /// checksums without one. Commands that delete or rewrite content run this
+    /// under the repository lock first, so an object history still needs is
+    /// never taken for an orphan. Returns the corrected checksums.
+    pub async fn recount_object_references(&self) -> Result<Vec<String>> {
+        let mismatches = self.get_refcount_mismatches().await?;
+        if mismatches.is_empty() {
+            return Ok(mismatches);
+        }
+
+        let mut tx = self.pool.begin().await?;
+        for b3sum in &mismatches {
+            sqlx::query!(
+                r#"
+                INSERT OR IGNORE INTO objects (b3sum, size, refcount)
+                VALUES (?1, COALESCE(
+                    (SELECT MAX(size) FROM (
+                        SELECT size FROM files WHERE b3sum = ?1
+                        UNION ALL
+                        SELECT size FROM history WHERE b3sum = ?1
+                    )), 0), 0)
+                "#,
+                b3sum
+            )
+            .execute(&mut *tx)
+            .await?;
+            sqlx::query!(
+                r#"
+                UPDATE objects
+                SET refcount = (SELECT COUNT(*) FROM files WHERE b3sum = ?1)
+                             + (SELECT COUNT(*) FROM history WHERE b3sum = ?1),
+                    unreferenced_at = CASE
+                        WHEN EXISTS (SELECT 1 FROM files WHERE b3sum = ?1)
+                          OR EXISTS (SELECT 1 FROM history WHERE b3sum = ?1) THEN NULL
+                        ELSE COALESCE(unreferenced_at, CURRENT_TIMESTAMP)
+                    END
+                WHERE b3sum = ?1
+                "#,
+                b3sum
+            )
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await?;
+
+        warn!(
+            code = %codes::REFCOUNT_DRIFT,
+            "Corrected the reference counts of {} objects to match file records and history",
+            mismatches.len()
+        );
+        Ok(mismatches)
+    }
+
+    /// Every checksum with an `objects` row
+    pub async fn get_object_checksums(&self) -> Result<std::collections::HashSet<String>> {
+        let checksums = sqlx::query_scalar!("SELECT b3sum FROM objects")
+            .fetch_all(&self.pool)
+            .await?;
+        Ok(checksums.into_iter().collect())
+    }
+
+    /// Distinct checksums and paths of history entries whose content no file
+    /// record references
+    pub async fn get_history_only_checksums(&self) -> Result<Vec<(String, String)>> {
+        let records = sqlx::query!(
+            r#"
+            SELECT b3sum AS "b3sum!", MIN(path) AS "path!: String"
+            FROM history
+            WHERE b3sum NOT IN (SELECT b3sum FROM files WHERE b3sum IS NOT NULL)
+            GROUP BY b3sum
+            "#
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(records
+            .into_iter()
+            .map(|record| (record.b3sum, record.path))
+            .collect())
     }
 
-    /// Get history entries by action ID (base58 encoded)
-    pub async fn get_history_entries_by_action_id_base58(
+    /// Every path stored in file records or history entries
+    pub async fn get_stored_paths(&self) -> Result<Vec<String>> {
+        let paths = sqlx::query_scalar!(
+            r#"
+            SELECT path AS "path!" FROM files
+            UNION
+            SELECT path FROM history
+            "#
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(paths)
+    }
+
+    /// Number of add actions that were interrupted before committing
+    pub async fn count_interrupted_actions(&self) -> Result<i64> {
+        let count = sqlx::query_scalar!("SELECT COUNT(*) FROM journal")
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(count)
+    }
+
+    /// Newest update time of any file record and newest action ID, used to spot
+    /// timestamps written by a clock that was ahead
+    pub async fn get_latest_timestamps(
         &self,
-        action_id_base58: &str,
-    ) -> Result<Vec<HistoryRecord>> {
-        // Decode base58 action ID
-        let decoded =
-            bs58::decode(action_id_base58)
-                .into_vec()
-                .map_err(|_| DdriveError::Validation {
-                    message: "Invalid action ID format".to_string(),
-                })?;
-
-        if decoded.len() != 8 {
-            return Err(DdriveError::Validation {
-                message: "Invalid action ID length".to_string(),
-            });
-        }
+    ) -> Result<(Option<chrono::NaiveDateTime>, Option<i64>)> {
+        let updated_at = sqlx::query_scalar!(
+            r#"SELECT MAX(updated_at) AS "updated_at: chrono::NaiveDateTime" FROM files"#
+        )
+        .fetch_one(&self.pool)
+        .await?;
+        let action_id =
+            sqlx::query_scalar!(r#"SELECT MAX(action_id) AS "action_id: i64" FROM history"#)
+                .fetch_one(&self.pool)
+                .await?;
+        Ok((updated_at, action_id))
+    }
+
+    /// IDs of all actions in history, newest first
+    pub async fn get_history_action_ids(&self) -> Result<Vec<i64>> {
+        let action_ids = sqlx::query_scalar!(
+            r#"
+            SELECT DISTINCT action_id
+            FROM history
+            ORDER BY action_id DESC
+            "#
+        )
+        .fetch_all(&self.pool)
+        .await?;
 
-        // Convert bytes to i64
-        let mut bytes = [0u8; 8];
-        bytes.copy_from_slice(&decoded);
-        let action_id = i64::from_be_bytes(bytes);
+        Ok(action_ids)
+    }
 
+    /// History entries belonging to any of `action_ids`
+    pub async fn get_history_for_actions(&self, action_ids: &[i64]) -> Result<Vec<HistoryRecord>> {
+        let action_ids = json_id_list(action_ids);
         let records = sqlx::query_as!(
             HistoryRecord,
             r#"
             SELECT id, action_id, action_type, path, b3sum, size, metadata
             FROM history
-            WHERE action_id = ?1
-            ORDER BY path
+            WHERE action_id IN (SELECT value FROM json_each(?1))
+            ORDER BY action_id, path
             "#,
-            action_id
+            action_ids
         )
         .fetch_all(&self.pool)
         .await?;
@@ -580,32 +2755,47 @@ impl Database {
         Ok(records)
     }
 
-    /// Get files that need verification based on configuration
-    pub async fn get_files_for_check(&self) -> Result<Vec<FileRecord>> {
-        let records = sqlx::query_as!(
-            FileRecord,
+    /// Remove all history entries of `action_ids` and their recorded runs
+    pub async fn delete_history_actions(&self, action_ids: &[i64]) -> Result<usize> {
+        let action_ids = json_id_list(action_ids);
+        let mut tx = self.pool.begin().await?;
+        let result = sqlx::query!(
             r#"
-            SELECT id, path, created_at, updated_at, last_checked, b3sum, size
-            FROM files
-            WHERE last_checked IS NULL
-            ORDER BY path
-            "#
+            DELETE FROM history
+            WHERE action_id IN (SELECT value FROM json_each(?1))
+            "#,
+            action_ids
         )
-        .fetch_all(&self.pool)
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query!(
+            "DELETE FROM actions WHERE action_id IN (SELECT value FROM json_each(?1))",
+            action_ids
+        )
+        .execute(&mut *tx)
         .await?;
+        tx.commit().await?;
 
-        Ok(records)
+        Ok(result.rows_affected() as usize)
     }
 
-    /// Get lightweight file info for status display
-    pub async fn get_tracked_file_paths(&self) -> Result<Vec<TrackedFileInfo>> {
+    /// History entries that `cleanup_old_history` would remove
+    pub async fn get_old_history(
+        &self,
+        action_type: ActionType,
+        cutoff_timestamp: i64,
+    ) -> Result<Vec<HistoryRecord>> {
+        let action_type = action_type.to_i32();
         let records = sqlx::query_as!(
-            TrackedFileInfo,
+            HistoryRecord,
             r#"
-            SELECT path, size, created_at
-            FROM files
-            ORDER BY path
-            "#
+            SELECT id, action_id, action_type, path, b3sum, size, metadata
+            FROM history
+            WHERE action_type = ?1 AND action_id < ?2
+            ORDER BY action_id, path
+            "#,
+            action_type,
+            cutoff_timestamp
         )
         .fetch_all(&self.pool)
         .await?;
@@ -634,87 +2824,83 @@ impl Database {
         Ok(result.rows_affected() as usize)
     }
 
-    /// Find potential renames by matching deleted files with new files by checksum and size
-    pub async fn find_potential_renames(
+    /// Record a directory move as one entry for the directory, named by the
+    /// rename entries of its `files`
+    async fn record_directory_move(
         &self,
-        deleted_files: &[FileInfo],
-        new_files: &[FileInfo],
-    ) -> Result<Vec<(FileInfo, FileInfo)>> {
-        let mut potential_renames = Vec::new();
-
-        // Create lookup maps for efficient matching
-        let mut deleted_by_checksum: std::collections::HashMap<String, Vec<&FileInfo>> =
-            std::collections::HashMap::new();
-        let mut new_by_checksum: std::collections::HashMap<String, Vec<&FileInfo>> =
-            std::collections::HashMap::new();
-
-        // Group deleted files by checksum (if available)
-        for file in deleted_files {
-            if let Some(ref checksum) = file.b3sum {
-                deleted_by_checksum
-                    .entry(checksum.clone())
-                    .or_default()
-                    .push(file);
-            }
-        }
-
-        // Group new files by checksum (if available)
-        for file in new_files {
-            if let Some(ref checksum) = file.b3sum {
-                new_by_checksum
-                    .entry(checksum.clone())
-                    .or_default()
-                    .push(file);
-            }
-        }
-
-        // Find matches by checksum and size
-        for (checksum, deleted_list) in deleted_by_checksum {
-            if let Some(new_list) = new_by_checksum.get(&checksum) {
-                // Match files with same checksum and size
-                for deleted_file in deleted_list {
-                    for new_file in new_list {
-                        if deleted_file.size == new_file.size {
-                            potential_renames.push(((*deleted_file).clone(), (*new_file).clone()));
-                            break; // Only match each deleted file once
-                        }
-                    }
-                }
-            }
-        }
-
-        Ok(potential_renames)
+        tx: &mut sqlx::Transaction<'_, sqlx::Sqlite>,
+        action_id: i64,
+        directory_move: &DirectoryMove,
+        files: &[(String, String)],
+    ) -> Result<()> {
+        let size = self
+            .rename_files(tx, action_id, files, Some(&directory_move.to))
+            .await?;
+        let metadata = serde_json::json!({
+            "old_path": directory_move.from,
+            "files": files.len(),
+        });
+        sqlx::query(
+            r#"
+            INSERT INTO history (action_id, action_type, path, b3sum, size, metadata)
+            VALUES (?1, ?2, ?3, '', ?4, ?5)
+            "#,
+        )
+        .bind(action_id)
+        .bind(ActionType::DirectoryRename.to_i32())
+        .bind(&directory_move.to)
+        .bind(size)
+        .bind(metadata.to_string())
+        .execute(&mut **tx)
+        .await?;
+        Ok(())
     }
 
-    /// Process file renames in batch
+    /// Move the file records of `renames` (old path, new path) to their new
+    /// paths, recording the renames as one history action
     pub async fn batch_rename_files(
         &self,
         action_id: i64,
-        renames: &[(String, String)], // (old_path, new_path)
+        renames: &[(String, String)],
     ) -> Result<()> {
-        if renames.is_empty() {
-            return Ok(());
-        }
-
         let mut tx = self.pool.begin().await?;
+        self.rename_files(&mut tx, action_id, renames, None).await?;
+        tx.commit().await?;
+        Ok(())
+    }
 
+    /// Move file records of renamed files to their new paths. Their rename
+    /// entries name the moved `directory` they belong to, if any. Returns the
+    /// total size of the renamed files.
+    async fn rename_files(
+        &self,
+        tx: &mut sqlx::Transaction<'_, sqlx::Sqlite>,
+        action_id: i64,
+        renames: &[(String, String)], // (old_path, new_path)
+        directory: Option<&str>,
+    ) -> Result<i64> {
+        let mut total_size = 0;
         for (old_path, new_path) in renames {
             let old_relative_path = self.convert_to_relative_path(old_path)?;
             let new_relative_path = self.convert_to_relative_path(new_path)?;
 
             // Get the file record to preserve checksum and size
             let file_record = sqlx::query!(
-                "SELECT b3sum, size FROM files WHERE path = ?1",
+                "SELECT b3sum, size FROM files WHERE path = ?1 AND deleted_at IS NULL",
                 old_relative_path
             )
-            .fetch_optional(&mut *tx)
+            .fetch_optional(&mut **tx)
             .await?;
 
             if let Some(record) = file_record {
+                total_size += record.size;
                 // Insert rename history entry with metadata containing old path
-                let metadata = serde_json::json!({
+                let mut metadata = serde_json::json!({
                     "old_path": old_relative_path
                 });
+                if let Some(directory) = directory {
+                    metadata["directory"] = directory.into();
+                }
                 let metadata_str = serde_json::to_string(&metadata).unwrap_or_default();
 
                 sqlx::query(
@@ -729,9 +2915,15 @@ impl Database {
                 .bind(&record.b3sum)
                 .bind(record.size)
                 .bind(&metadata_str)
-                .execute(&mut *tx)
+                .execute(&mut **tx)
                 .await?;
 
+                // A tombstone at the destination would violate the unique path constraint
+                sqlx::query("DELETE FROM files WHERE path = ?1 AND deleted_at IS NOT NULL")
+                    .bind(&new_relative_path)
+                    .execute(&mut **tx)
+                    .await?;
+
                 // Update the file record with new path
                 sqlx::query(
                     r#"
@@ -742,26 +2934,36 @@ impl Database {
                 )
                 .bind(&new_relative_path)
                 .bind(&old_relative_path)
-                .execute(&mut *tx)
+                .execute(&mut **tx)
+                .await?;
+
+                sqlx::query!(
+                    "UPDATE verification_failures SET path = ?1 WHERE path = ?2 AND resolved_at IS NULL",
+                    new_relative_path,
+                    old_relative_path
+                )
+                .execute(&mut **tx)
                 .await?;
             }
         }
 
-        tx.commit().await?;
-        Ok(())
+        Ok(total_size)
     }
 
     /// Convert an absolute path to a path relative to the repository root
+    ///
+    /// Paths are returned in their stored form (see [`to_storage_path`]). Relative paths are already
+    /// relative to the repository root and are otherwise unchanged, since deleted or renamed files no longer exist on
+    /// disk and cannot be canonicalized.
     fn convert_to_relative_path(&self, file_path: &str) -> Result<String> {
         let path = Path::new(file_path);
-        let absolute_path = if path.is_absolute() {
-            path.to_path_buf().canonicalize()?
-        } else {
-            self.repo_root.join(path).canonicalize()?
-        };
+        if !path.is_absolute() {
+            return Ok(to_storage_path(path));
+        }
+        let absolute_path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
 
         match absolute_path.strip_prefix(&self.repo_root) {
-            Ok(relative) => Ok(relative.to_string_lossy().into_owned()),
+            Ok(relative) => Ok(to_storage_path(relative)),
             Err(_) => Err(DdriveError::FileSystem {
                 message: format!(
                     "Path {} is not within repository root {}",
@@ -773,38 +2975,410 @@ impl Database {
     }
 }
 
+/// Outcome of checking a tracked file against an imported checksum list
+#[derive(Debug, Clone)]
+pub struct ImportedChecksum {
+    pub path: String,
+    pub b3sum: String,
+    pub size: i64,
+    /// Checksum published in the list (lowercase hex)
+    pub expected: String,
+    pub verified: bool,
+}
+
+/// An integrity failure recorded by verify
+#[derive(Debug, Clone, FromRow)]
+pub struct VerificationFailure {
+    pub path: String,
+    pub algorithm: String,
+    pub expected_checksum: String,
+    pub actual_checksum: String,
+    pub detected_at: chrono::NaiveDateTime,
+    pub last_detected_at: chrono::NaiveDateTime,
+}
+
+/// A file that could not be protected by the last action that touched it
+#[derive(Debug, Clone, FromRow)]
+pub struct Problem {
+    pub path: String,
+    pub kind: String,
+    pub message: String,
+}
+
+/// Cached checksum of a file, valid while its size and mtime are unchanged
+#[derive(Debug, Clone, FromRow)]
+pub struct CachedChecksum {
+    pub dev: i64,
+    pub ino: i64,
+    pub size: i64,
+    pub mtime_ns: i64,
+    pub b3sum: String,
+}
+
+/// A tracked file with the MIME type detected from its content, if known
+#[derive(Debug, Clone, FromRow)]
+pub struct TypedFile {
+    pub path: String,
+    pub size: i64,
+    pub content_type: Option<String>,
+}
+
 /// File record from the database
 #[derive(Debug, FromRow)]
 pub struct FileRecord {
     pub id: i64,
     pub path: String,
-    pub created_at: chrono::NaiveDateTime,
+    pub created_at: Option<chrono::NaiveDateTime>,
     pub updated_at: chrono::NaiveDateTime,
     pub last_checked: Option<chrono::NaiveDateTime>,
     pub b3sum: String,
     pub size: i64,
+    pub mode: Option<i64>,
+    pub uid: Option<i64>,
+    pub gid: Option<i64>,
+    pub xattrs: Option<String>,
+    pub dev: Option<i64>,
+    pub ino: Option<i64>,
+    /// Modification time in nanoseconds since the Unix epoch (UTC)
+    pub mtime_ns: Option<i64>,
+    pub sha256: Option<String>,
+    pub xxh3: Option<String>,
+    /// Keyed BLAKE3 MAC, if a MAC key was configured when the file was recorded
+    pub mac: Option<String>,
+    /// Whether the object store holds a copy of this content
+    pub stored: bool,
+    /// Inode change time in nanoseconds since the Unix epoch (UTC), if known
+    pub ctime_ns: Option<i64>,
+}
+
+impl FileRecord {
+    /// (device, inode) pair identifying the underlying file, if recorded
+    pub fn inode_key(&self) -> Option<(u64, u64)> {
+        self.dev.zip(self.ino).map(|(d, i)| (d as u64, i as u64))
+    }
+
+    /// Recorded checksum for the given algorithm, if any
+    pub fn checksum(&self, algorithm: HashAlgorithm) -> Option<&str> {
+        match algorithm {
+            HashAlgorithm::Blake3 => Some(&self.b3sum),
+            HashAlgorithm::Sha256 => self.sha256.as_deref(),
+            HashAlgorithm::Xxh3 => self.xxh3.as_deref(),
+        }
+    }
 }
 
 impl From<&FileRecord> for crate::scanner::FileInfo {
     fn from(record: &FileRecord) -> Self {
         Self {
-            path: std::path::PathBuf::from(&record.path),
+            path: from_storage_path(&record.path),
             size: record.size as u64,
-            modified: UNIX_EPOCH
-                + Duration::from_secs(record.updated_at.and_utc().timestamp() as u64),
-            created: UNIX_EPOCH
-                + Duration::from_secs(record.created_at.and_utc().timestamp() as u64),
+            modified: match record.mtime_ns {
+                Some(mtime_ns) => UNIX_EPOCH + Duration::from_nanos(mtime_ns as u64),
+                None => {
+                    UNIX_EPOCH + Duration::from_secs(record.updated_at.and_utc().timestamp() as u64)
+                }
+            },
+            created: record.created_at.map(|created_at| {
+                UNIX_EPOCH + Duration::from_secs(created_at.and_utc().timestamp() as u64)
+            }),
             b3sum: Some(record.b3sum.clone()),
+            attributes: crate::metadata::FileAttributes::from_record(record),
+            dev: record.dev.map(|d| d as u64),
+            ino: record.ino.map(|i| i as u64),
+            secondary: SecondaryChecksums {
+                sha256: record.sha256.clone(),
+                xxh3: record.xxh3.clone(),
+                mac: record.mac.clone(),
+                chunks: None,
+            },
+            stored: record.stored,
+            ctime_ns: record.ctime_ns,
+            content_type: None,
+        }
+    }
+}
+
+/// Duplicate content among the active files
+#[derive(Debug, Default, FromRow)]
+pub struct DuplicateStats {
+    pub groups: i64,
+    /// Files in duplicate groups, hardlinks included
+    pub files: i64,
+    /// Bytes taken by all but one copy of each group
+    pub wasted_space: i64,
+}
+
+/// Device files were last seen on
+#[derive(Debug, Clone, FromRow)]
+pub struct DeviceRecord {
+    pub dev: i64,
+    pub mount_point: String,
+    pub fs_type: String,
+    pub uuid: Option<String>,
+}
+
+impl DeviceRecord {
+    /// Mount point with file system and UUID, e.g. `/mnt/disk2 (ext4, UUID 1a2b)`
+    pub fn label(&self) -> String {
+        device_label(&self.mount_point, &self.fs_type, self.uuid.as_deref())
+    }
+}
+
+/// What is known about a file for correlating integrity failures: a failure
+/// is seen when it was detected, a tracked file now. The file fields are
+/// unknown for failures of files that are no longer tracked.
+#[derive(Debug, Clone, FromRow)]
+pub struct FileProfile {
+    pub path: String,
+    pub size: Option<i64>,
+    /// Creation time of the file, or when it was last recorded if unknown
+    pub born_at: Option<chrono::NaiveDateTime>,
+    pub dev: Option<i64>,
+    pub content_type: Option<String>,
+    pub seen_at: chrono::NaiveDateTime,
+}
+
+impl FileProfile {
+    /// How old the file was when it was seen
+    pub fn age(&self) -> Option<chrono::Duration> {
+        self.born_at.map(|born_at| self.seen_at - born_at)
+    }
+}
+
+/// An object that can move between the hot and cold stores
+#[derive(Debug, Clone, PartialEq, Eq, FromRow)]
+pub struct TieredObject {
+    pub b3sum: String,
+    pub size: i64,
+}
+
+/// An object written to an exported medium
+#[derive(Debug, Clone, PartialEq, Eq, FromRow)]
+pub struct MediaObject {
+    pub b3sum: String,
+    pub size: i64,
+}
+
+/// An exported medium and what it holds
+#[derive(Debug, Clone, FromRow)]
+pub struct MediaRecord {
+    pub label: String,
+    pub capacity: i64,
+    pub created_at: chrono::NaiveDateTime,
+    pub last_verified_at: Option<chrono::NaiveDateTime>,
+    /// Missing or damaged objects found by the last verification
+    pub last_verify_failures: Option<i64>,
+    pub objects: i64,
+    pub bytes: i64,
+}
+
+/// A file as it was copied to a mirror
+#[derive(Debug, Clone, PartialEq, Eq, FromRow)]
+pub struct MirroredFile {
+    pub path: String,
+    pub b3sum: String,
+    pub size: i64,
+}
+
+/// Tracked files on one device
+#[derive(Debug, Default, FromRow)]
+pub struct DeviceStats {
+    /// Device number, if one was recorded
+    pub dev: Option<i64>,
+    pub mount_point: Option<String>,
+    pub fs_type: Option<String>,
+    pub uuid: Option<String>,
+    pub files: i64,
+    pub size: i64,
+    /// Files with unresolved integrity failures
+    pub failures: i64,
+}
+
+impl DeviceStats {
+    pub fn label(&self) -> String {
+        match (&self.mount_point, self.dev) {
+            (Some(mount_point), _) => device_label(
+                mount_point,
+                self.fs_type.as_deref().unwrap_or("unknown"),
+                self.uuid.as_deref(),
+            ),
+            (None, Some(dev)) => format!("device {}", dev),
+            (None, None) => "unknown device".to_string(),
+        }
+    }
+}
+
+fn device_label(mount_point: &str, fs_type: &str, uuid: Option<&str>) -> String {
+    match uuid {
+        Some(uuid) => format!("{} ({}, UUID {})", mount_point, fs_type, uuid),
+        None => format!("{} ({})", mount_point, fs_type),
+    }
+}
+
+/// Tracked files under one top-level directory
+#[derive(Debug, Default, FromRow)]
+pub struct TrackedDirectory {
+    pub directory: String,
+    pub files: i64,
+    pub size: i64,
+    /// Files never verified since they were added or changed
+    pub unverified: i64,
+    /// Oldest verification among the verified files
+    pub oldest_verified: Option<chrono::NaiveDateTime>,
+}
+
+impl TrackedDirectory {
+    /// When every file in the directory was last verified, if all were
+    pub fn last_fully_verified(&self) -> Option<chrono::NaiveDateTime> {
+        if self.unverified == 0 {
+            self.oldest_verified
+        } else {
+            None
         }
     }
 }
 
+/// Running totals of tracked files from the `stats` table
+#[derive(Debug, Default, FromRow)]
+pub struct RepositoryTotals {
+    pub tracked_files: i64,
+    /// Sum of recorded sizes; hardlinks count once per path
+    pub tracked_size: i64,
+    /// Newest action recorded in history
+    pub last_action_id: Option<i64>,
+}
+
 /// Lightweight file info for status display
 #[derive(Debug, FromRow)]
 pub struct TrackedFileInfo {
     pub path: String,
     pub size: i64,
-    pub created_at: chrono::NaiveDateTime,
+    pub created_at: Option<chrono::NaiveDateTime>,
+    pub dev: Option<i64>,
+    pub ino: Option<i64>,
+}
+
+/// JSON array of IDs, for matching against with SQLite's `json_each`
+fn json_id_list(ids: &[i64]) -> String {
+    let ids: Vec<String> = ids.iter().map(i64::to_string).collect();
+    format!("[{}]", ids.join(","))
+}
+
+/// Object store file that no record or history entry references
+#[derive(Debug)]
+pub struct OrphanedObject {
+    pub b3sum: String,
+    pub size: u64,
+}
+
+/// An object an action started or stopped referencing
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ActionObject {
+    pub b3sum: String,
+    pub size: u64,
+    /// Paths of the action's files with this content
+    pub paths: Vec<String>,
+}
+
+/// How an action changed what the object store has to keep
+#[derive(Debug, Default)]
+pub struct ActionObjects {
+    /// Content no earlier history entry referenced
+    pub introduced: Vec<ActionObject>,
+    /// Content the action replaced or deleted that no tracked file has
+    /// anymore; older history entries may still keep it
+    pub released: Vec<ActionObject>,
+    /// Objects no longer referenced once the action's history expires
+    pub reclaimable: Vec<OrphanedObject>,
+}
+
+/// Group (b3sum, size, path) rows ordered by checksum into objects
+fn group_action_objects(rows: impl Iterator<Item = (String, i64, String)>) -> Vec<ActionObject> {
+    let mut objects: Vec<ActionObject> = Vec::new();
+    for (b3sum, size, path) in rows {
+        match objects.last_mut() {
+            Some(object) if object.b3sum == b3sum => object.paths.push(path),
+            _ => objects.push(ActionObject {
+                b3sum,
+                size: size as u64,
+                paths: vec![path],
+            }),
+        }
+    }
+    objects
+}
+
+/// Which history actions to list, newest first
+#[derive(Debug, Clone)]
+pub struct HistoryQuery {
+    /// Only actions with entries of this type, and only those entries
+    pub action_type: Option<ActionType>,
+    /// Only actions before this time
+    pub before: Option<DateTime<Utc>>,
+    /// Only actions at or after this time
+    pub after: Option<DateTime<Utc>>,
+    /// Number of actions to list
+    pub limit: usize,
+    /// Number of newer actions to skip
+    pub offset: usize,
+}
+
+impl Default for HistoryQuery {
+    fn default() -> Self {
+        Self {
+            action_type: None,
+            before: None,
+            after: None,
+            limit: 20,
+            offset: 0,
+        }
+    }
+}
+
+/// A command run recorded in the actions table
+#[derive(Debug, FromRow)]
+pub struct ActionRun {
+    pub action_id: i64,
+    pub command: String,
+    pub duration_ms: i64,
+    /// Files processed and their total size
+    pub files: i64,
+    pub bytes: i64,
+    /// Files that could not be processed
+    pub errors: i64,
+    pub status: String,
+}
+
+impl ActionRun {
+    pub fn status_enum(&self) -> ActionStatus {
+        self.status.parse().unwrap_or(ActionStatus::Success)
+    }
+
+    pub fn duration(&self) -> Duration {
+        Duration::from_millis(self.duration_ms as u64)
+    }
+}
+
+/// An unfinished verify run
+#[derive(Debug, Clone)]
+pub struct VerifySession {
+    pub action_id: i64,
+    /// Files the run set out to check
+    pub total_files: i64,
+    /// Files it has checked so far
+    pub checked: HashSet<String>,
+}
+
+/// The entries of one type in one history action. Actions that only have
+/// a recorded run have a single summary without an action type or entries.
+#[derive(Debug, FromRow)]
+pub struct ActionSummary {
+    pub action_id: i64,
+    pub action_type: Option<i64>,
+    pub entries: i64,
+    pub size: i64,
+    /// Entries listed on their own, not as part of a moved directory
+    pub listed: i64,
 }
 
 /// History record from the database
@@ -832,3 +3406,175 @@ impl HistoryRecord {
         bs58::encode(self.action_id.to_be_bytes()).into_string()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{AddOptions, Ddrive, api::testing::repository};
+    use assert_fs::prelude::*;
+
+    #[tokio::test]
+    async fn test_mtime_ns_migration_seeds_existing_rows() {
+        let (temp, _) = repository(&[("docs/a.txt", "same")]).await;
+        let file = temp.child("docs/a.txt");
+        let set_mtime = |nanos: u64| {
+            std::fs::File::options()
+                .write(true)
+                .open(&file)
+                .unwrap()
+                .set_modified(std::time::UNIX_EPOCH + std::time::Duration::from_nanos(nanos))
+                .unwrap();
+        };
+        let recorded = 1_700_000_000 * 1_000_000_000;
+        set_mtime(recorded);
+
+        // A database from before mtime_ns, which kept whole seconds in updated_at
+        let db_path = temp.path().join(".ddrive/metadata.sqlite3");
+        std::fs::remove_file(&db_path).unwrap();
+        std::fs::File::create(&db_path).unwrap();
+        let pool = sqlx::SqlitePool::connect(&format!("sqlite://{}", db_path.display()))
+            .await
+            .unwrap();
+        let mut migrator = sqlx::migrate!("./migrations");
+        migrator.migrations = migrator.migrations[..5].to_vec().into();
+        migrator.run(&pool).await.unwrap();
+        sqlx::query(
+            "INSERT INTO files (path, b3sum, size, updated_at) VALUES ('docs/a.txt', ?1, 4, '2023-11-14 22:13:20')",
+        )
+        .bind(blake3::hash(b"same").to_hex().to_string())
+        .execute(&pool)
+        .await
+        .unwrap();
+        pool.close().await;
+
+        let ddrive = Ddrive::open(temp.path()).await.unwrap();
+        let pool = &ddrive.context().database.pool;
+        let mtime_ns = || async {
+            sqlx::query_scalar::<_, i64>("SELECT mtime_ns FROM files")
+                .fetch_one(pool)
+                .await
+                .unwrap()
+        };
+        assert_eq!(mtime_ns().await, recorded as i64);
+        let docs = [temp.path().join("docs")];
+        let added = ddrive.add(&docs, &AddOptions::default()).await.unwrap();
+        assert_eq!((added.new_files, added.changed_files), (0, 0));
+
+        // An edit within the same second is no longer missed
+        file.write_str("SAME").unwrap();
+        set_mtime(recorded + 300_000_000);
+        let added = ddrive.add(&docs, &AddOptions::default()).await.unwrap();
+        assert_eq!(added.changed_files, 1);
+        assert_eq!(mtime_ns().await, recorded as i64 + 300_000_000);
+    }
+
+    #[tokio::test]
+    async fn test_refcounts_follow_records_and_history() {
+        let (temp, ddrive) = repository(&[("docs/a.txt", "first")]).await;
+        let docs = [temp.path().join("docs")];
+        let database = &ddrive.context().database;
+        let [first, second] = [b"first".as_slice(), b"second version"]
+            .map(|content| blake3::hash(content).to_hex().to_string());
+        let refcounts = || async {
+            let mut counts = Vec::new();
+            for b3sum in [&first, &second] {
+                let count: Option<i64> =
+                    sqlx::query_scalar("SELECT refcount FROM objects WHERE b3sum = ?1")
+                        .bind(b3sum)
+                        .fetch_optional(&database.pool)
+                        .await
+                        .unwrap();
+                counts.push(count.unwrap_or(0));
+            }
+            assert!(database.get_refcount_mismatches().await.unwrap().is_empty());
+            counts
+        };
+
+        // The record and the add's history entry
+        ddrive.add(&docs, &AddOptions::default()).await.unwrap();
+        assert_eq!(refcounts().await, [2, 0]);
+
+        // The record moves to the new content; history keeps the old
+        temp.child("docs/a.txt")
+            .write_str("second version")
+            .unwrap();
+        ddrive.add(&docs, &AddOptions::default()).await.unwrap();
+        assert_eq!(refcounts().await, [1, 2]);
+
+        // The tombstoned record still counts, and so does the deletion
+        crate::cli::rm::RmCommand::new(ddrive.context())
+            .tracked(
+                glob::Pattern::new("docs/a.txt").unwrap(),
+                crate::cli::rm::RmOptions {
+                    yes: true,
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(refcounts().await, [1, 3]);
+
+        // Pruning history and the tombstone releases every reference
+        for action_type in [ActionType::Add, ActionType::Update, ActionType::Delete] {
+            database
+                .cleanup_old_history(action_type, i64::MAX)
+                .await
+                .unwrap();
+        }
+        database
+            .purge_deleted_file_records(Utc::now() + chrono::Duration::days(1))
+            .await
+            .unwrap();
+        assert_eq!(refcounts().await, [0, 0]);
+    }
+
+    #[tokio::test]
+    async fn test_totals_follow_changes() {
+        let (temp, ddrive) = repository(&[("docs/a.txt", "a"), ("docs/b.txt", "bb")]).await;
+        let docs = temp.path().join("docs");
+        ddrive.add(&[&docs], &AddOptions::default()).await.unwrap();
+
+        let database = &ddrive.context().database;
+        let totals = database.get_totals().await.unwrap();
+        assert_eq!((totals.tracked_files, totals.tracked_size), (2, 3));
+        assert!(totals.last_action_id.is_some());
+
+        temp.child("docs/a.txt").write_str("aaaa").unwrap();
+        std::fs::remove_file(docs.join("b.txt")).unwrap();
+        ddrive.add(&[&docs], &AddOptions::default()).await.unwrap();
+        let removed = crate::cli::rm::RmCommand::new(ddrive.context())
+            .deleted(None, false)
+            .await
+            .unwrap();
+        assert_eq!(removed, 1);
+        let totals = database.get_totals().await.unwrap();
+        assert_eq!((totals.tracked_files, totals.tracked_size), (1, 4));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_duplicate_stats() {
+        let (temp, ddrive) = repository(&[
+            ("docs/a.txt", "same"),
+            ("docs/b.txt", "same"),
+            ("docs/c.txt", "unique"),
+        ])
+        .await;
+        std::fs::hard_link(
+            temp.path().join("docs/a.txt"),
+            temp.path().join("docs/d.txt"),
+        )
+        .unwrap();
+        ddrive
+            .add(&[temp.path().join("docs")], &AddOptions::default())
+            .await
+            .unwrap();
+
+        let database = &ddrive.context().database;
+        let stats = database.get_duplicate_stats().await.unwrap();
+        assert_eq!((stats.groups, stats.files, stats.wasted_space), (1, 3, 4));
+        let duplicates = database.find_duplicates().await.unwrap();
+        let paths: Vec<_> = duplicates.iter().map(|file| file.path.as_str()).collect();
+        assert_eq!(paths, ["docs/a.txt", "docs/b.txt", "docs/d.txt"]);
+    }
+}
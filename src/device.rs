@@ -0,0 +1,174 @@
+//! Devices that tracked files reside on.
+//!
+//! Files record their device number (`st_dev`); this module maps device
+//! numbers to the mount point, file system and, where the system exposes it,
+//! the file system UUID, so failures and statistics can be reported per disk.
+
+use std::collections::HashMap;
+#[cfg(target_os = "linux")]
+use std::path::Path;
+use std::path::PathBuf;
+
+/// A mounted file system
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Device {
+    /// Device number, as recorded in `files.dev`
+    pub dev: u64,
+    pub mount_point: PathBuf,
+    pub fs_type: String,
+    /// What is mounted, e.g. `/dev/sdb1`
+    pub source: String,
+    pub uuid: Option<String>,
+}
+
+/// Describe the mounted devices with the given device numbers; numbers
+/// without a known mount are left out
+pub fn describe(devs: impl IntoIterator<Item = u64>) -> Vec<Device> {
+    let mounts = mounted_devices();
+    let mut devices: Vec<Device> = devs
+        .into_iter()
+        .filter_map(|dev| mounts.get(&dev).cloned())
+        .collect();
+    devices.sort_by_key(|device| device.dev);
+    devices.dedup_by_key(|device| device.dev);
+    devices
+}
+
+/// Mounted devices by device number
+#[cfg(target_os = "linux")]
+pub fn mounted_devices() -> HashMap<u64, Device> {
+    let Ok(mountinfo) = std::fs::read_to_string("/proc/self/mountinfo") else {
+        return HashMap::new();
+    };
+    let uuids = read_uuids(Path::new("/dev/disk/by-uuid"));
+    parse_mountinfo(&mountinfo)
+        .into_iter()
+        .map(|mut device| {
+            device.uuid = std::fs::canonicalize(&device.source)
+                .ok()
+                .and_then(|source| uuids.get(&source).cloned());
+            (device.dev, device)
+        })
+        .collect()
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn mounted_devices() -> HashMap<u64, Device> {
+    HashMap::new()
+}
+
+/// Parse `/proc/self/mountinfo`. Where a device is mounted more than once,
+/// the mount of its root wins over bind mounts of subdirectories, and the
+/// first mount over later ones.
+#[cfg(target_os = "linux")]
+fn parse_mountinfo(mountinfo: &str) -> Vec<Device> {
+    let mut devices: Vec<(bool, Device)> = Vec::new();
+    for line in mountinfo.lines() {
+        // ID parent major:minor root mount-point options [optional...] - type source super-options
+        let Some((mount, file_system)) = line.split_once(" - ") else {
+            continue;
+        };
+        let fields: Vec<&str> = mount.split(' ').collect();
+        let mut file_system = file_system.split(' ');
+        let (Some(numbers), Some(root), Some(mount_point), Some(fs_type), Some(source)) = (
+            fields.get(2),
+            fields.get(3),
+            fields.get(4),
+            file_system.next(),
+            file_system.next(),
+        ) else {
+            continue;
+        };
+        let Some((major, minor)) = numbers.split_once(':') else {
+            continue;
+        };
+        let (Ok(major), Ok(minor)) = (major.parse(), minor.parse()) else {
+            continue;
+        };
+
+        let device = Device {
+            dev: rustix::fs::makedev(major, minor),
+            mount_point: PathBuf::from(unescape(mount_point)),
+            fs_type: fs_type.to_string(),
+            source: unescape(source),
+            uuid: None,
+        };
+        let whole = *root == "/";
+        match devices.iter_mut().find(|(_, seen)| seen.dev == device.dev) {
+            Some(seen) if whole && !seen.0 => *seen = (whole, device),
+            Some(_) => {}
+            None => devices.push((whole, device)),
+        }
+    }
+    devices.into_iter().map(|(_, device)| device).collect()
+}
+
+/// Decode the octal escapes (`\040` for a space) mountinfo uses in paths
+#[cfg(target_os = "linux")]
+fn unescape(field: &str) -> String {
+    let bytes = field.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'\\'
+            && let Some(octal) = field.get(i + 1..i + 4)
+            && let Ok(byte) = u8::from_str_radix(octal, 8)
+        {
+            decoded.push(byte);
+            i += 4;
+            continue;
+        }
+        decoded.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// Block devices by file system UUID, from udev's `/dev/disk/by-uuid` links
+#[cfg(target_os = "linux")]
+fn read_uuids(dir: &Path) -> HashMap<PathBuf, String> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return HashMap::new();
+    };
+    entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let device = std::fs::canonicalize(entry.path()).ok()?;
+            Some((device, entry.file_name().to_string_lossy().into_owned()))
+        })
+        .collect()
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_mountinfo() {
+        let mountinfo = "\
+22 1 8:1 / / rw,relatime shared:1 - ext4 /dev/sda1 rw
+23 22 8:17 /photos /srv/photos rw - ext4 /dev/sdb1 rw
+24 22 8:17 / /mnt/backup\\040disk rw shared:2 - btrfs /dev/sdb1 rw,ssd
+25 22 0:22 / /proc rw - proc proc rw
+malformed line
+";
+        let devices = parse_mountinfo(mountinfo);
+        assert_eq!(devices.len(), 3);
+        assert_eq!(devices[0].mount_point, Path::new("/"));
+        assert_eq!(devices[0].dev, rustix::fs::makedev(8, 1));
+        // The root mount of 8:17 wins over the bind mount of a subdirectory
+        assert_eq!(devices[1].mount_point, Path::new("/mnt/backup disk"));
+        assert_eq!(devices[1].fs_type, "btrfs");
+        assert_eq!(devices[1].source, "/dev/sdb1");
+        assert_eq!(devices[2].fs_type, "proc");
+    }
+
+    #[test]
+    fn test_describe_current_device() {
+        use std::os::unix::fs::MetadataExt;
+        let dev = std::fs::metadata("/proc/self/mountinfo").unwrap().dev();
+        let devices = describe([dev, dev]);
+        assert_eq!(devices.len(), 1);
+        assert_eq!(devices[0].fs_type, "proc");
+    }
+}
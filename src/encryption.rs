@@ -0,0 +1,187 @@
+//! Passphrase encryption of metadata database backups.
+//!
+//! A copy of the metadata database lists every tracked path and the whole
+//! history. Encrypted copies are sealed with ChaCha20-Poly1305 under a key
+//! derived from the passphrase with PBKDF2-HMAC-SHA256; the iteration count,
+//! salt and nonce are kept in a header in front of the ciphertext, which the
+//! seal also covers.
+
+use crate::{DdriveError, Result, utils};
+use ring::{
+    aead::{self, Aad, CHACHA20_POLY1305, LessSafeKey, Nonce, UnboundKey},
+    pbkdf2,
+    rand::{SecureRandom, SystemRandom},
+};
+use std::{num::NonZeroU32, path::Path};
+
+/// Environment variable holding the passphrase
+pub const PASSPHRASE_ENV: &str = "DDRIVE_PASSPHRASE";
+
+const MAGIC: &[u8; 8] = b"DDRVENC1";
+const ITERATIONS: u32 = 600_000;
+/// Most iterations a header may ask for; the count is read before the seal
+/// is checked, so a crafted file could otherwise make decryption run for hours
+const MAX_ITERATIONS: u32 = 10 * ITERATIONS;
+const SALT_LEN: usize = 16;
+const HEADER_LEN: usize = MAGIC.len() + 4 + SALT_LEN + aead::NONCE_LEN;
+
+/// Whether `data` was written by `encrypt`
+pub fn is_encrypted(data: &[u8]) -> bool {
+    data.starts_with(MAGIC)
+}
+
+/// Encrypt `plaintext` under `passphrase`
+pub fn encrypt(plaintext: &[u8], passphrase: &[u8]) -> Result<Vec<u8>> {
+    seal(plaintext, passphrase, ITERATIONS)
+}
+
+fn seal(plaintext: &[u8], passphrase: &[u8], iterations: u32) -> Result<Vec<u8>> {
+    let random = SystemRandom::new();
+    let mut salt = [0u8; SALT_LEN];
+    let mut nonce = [0u8; aead::NONCE_LEN];
+    random
+        .fill(&mut salt)
+        .and_then(|()| random.fill(&mut nonce))
+        .map_err(|_| encryption_error("no random numbers available"))?;
+
+    let mut sealed = Vec::with_capacity(HEADER_LEN + plaintext.len() + aead::MAX_TAG_LEN);
+    sealed.extend_from_slice(MAGIC);
+    sealed.extend_from_slice(&iterations.to_be_bytes());
+    sealed.extend_from_slice(&salt);
+    sealed.extend_from_slice(&nonce);
+    let mut ciphertext = plaintext.to_vec();
+    derive_key(passphrase, &salt, iterations)?
+        .seal_in_place_append_tag(
+            Nonce::assume_unique_for_key(nonce),
+            Aad::from(&sealed[..HEADER_LEN]),
+            &mut ciphertext,
+        )
+        .map_err(|_| encryption_error("the data is too large to encrypt"))?;
+    sealed.extend_from_slice(&ciphertext);
+    Ok(sealed)
+}
+
+/// Decrypt data written by `encrypt`, failing on a wrong passphrase or any
+/// change to the data
+pub fn decrypt(data: &[u8], passphrase: &[u8]) -> Result<Vec<u8>> {
+    if !is_encrypted(data) || data.len() < HEADER_LEN + aead::MAX_TAG_LEN {
+        return Err(encryption_error("not an encrypted ddrive file"));
+    }
+    let (header, ciphertext) = data.split_at(HEADER_LEN);
+    let (iterations, rest) = header[MAGIC.len()..].split_at(4);
+    let (salt, nonce) = rest.split_at(SALT_LEN);
+    let iterations = u32::from_be_bytes(iterations.try_into().unwrap_or_default());
+    if iterations > MAX_ITERATIONS {
+        return Err(encryption_error(
+            "damaged header: implausible key derivation iteration count",
+        ));
+    }
+    let nonce =
+        Nonce::try_assume_unique_for_key(nonce).map_err(|_| encryption_error("damaged header"))?;
+
+    let mut plaintext = ciphertext.to_vec();
+    let length = derive_key(passphrase, salt, iterations)?
+        .open_in_place(nonce, Aad::from(header), &mut plaintext)
+        .map_err(|_| encryption_error("wrong passphrase, or the file is damaged"))?
+        .len();
+    plaintext.truncate(length);
+    Ok(plaintext)
+}
+
+fn derive_key(passphrase: &[u8], salt: &[u8], iterations: u32) -> Result<LessSafeKey> {
+    let iterations =
+        NonZeroU32::new(iterations).ok_or_else(|| encryption_error("damaged header"))?;
+    let mut key = [0u8; 32];
+    pbkdf2::derive(
+        pbkdf2::PBKDF2_HMAC_SHA256,
+        iterations,
+        salt,
+        passphrase,
+        &mut key,
+    );
+    let key = UnboundKey::new(&CHACHA20_POLY1305, &key)
+        .map_err(|_| encryption_error("invalid key length"))?;
+    Ok(LessSafeKey::new(key))
+}
+
+/// The passphrase from `keyfile` (its contents, without a final newline),
+/// else from `DDRIVE_PASSPHRASE`, else asked on the terminal, twice when
+/// `confirm` is set
+pub fn read_passphrase(keyfile: Option<&Path>, confirm: bool) -> Result<Vec<u8>> {
+    let passphrase = match keyfile {
+        Some(keyfile) => {
+            let mut contents = std::fs::read(keyfile)?;
+            while contents.last().is_some_and(|b| *b == b'\n' || *b == b'\r') {
+                contents.pop();
+            }
+            contents
+        }
+        None => match std::env::var(PASSPHRASE_ENV) {
+            Ok(passphrase) => passphrase.into_bytes(),
+            Err(_) => {
+                let passphrase = utils::ask_secret("Passphrase")?.ok_or_else(|| {
+                    DdriveError::Configuration {
+                        message: format!(
+                            "No passphrase: set {PASSPHRASE_ENV}, pass --keyfile or run on a terminal"
+                        ),
+                    }
+                })?;
+                if confirm && utils::ask_secret("Repeat passphrase")?.as_ref() != Some(&passphrase)
+                {
+                    return Err(DdriveError::Validation {
+                        message: "The passphrases do not match".to_string(),
+                    });
+                }
+                passphrase.into_bytes()
+            }
+        },
+    };
+    if passphrase.is_empty() {
+        return Err(DdriveError::Validation {
+            message: "The passphrase is empty".to_string(),
+        });
+    }
+    Ok(passphrase)
+}
+
+fn encryption_error(message: &str) -> DdriveError {
+    DdriveError::Encryption {
+        message: message.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let sealed = seal(b"photos/2024/beach.jpg", b"correct horse", 1000).unwrap();
+        assert!(is_encrypted(&sealed));
+        assert!(!sealed.windows(5).any(|window| window == b"beach"));
+        assert_eq!(
+            decrypt(&sealed, b"correct horse").unwrap(),
+            b"photos/2024/beach.jpg"
+        );
+    }
+
+    #[test]
+    fn test_wrong_passphrase_and_tampering_fail() {
+        let mut sealed = seal(b"history", b"correct horse", 1000).unwrap();
+        assert!(decrypt(&sealed, b"battery staple").is_err());
+
+        // The header is authenticated too
+        sealed[MAGIC.len() + 3] ^= 1;
+        assert!(decrypt(&sealed, b"correct horse").is_err());
+        assert!(decrypt(b"SQLite format 3\0", b"correct horse").is_err());
+    }
+
+    #[test]
+    fn test_excessive_iterations_are_rejected() {
+        let mut sealed = seal(b"history", b"correct horse", 1000).unwrap();
+        sealed[MAGIC.len()..MAGIC.len() + 4].copy_from_slice(&u32::MAX.to_be_bytes());
+        let started = std::time::Instant::now();
+        assert!(decrypt(&sealed, b"correct horse").is_err());
+        assert!(started.elapsed() < std::time::Duration::from_secs(5));
+    }
+}
@@ -1,58 +1,175 @@
-use std::path::StripPrefixError;
+use std::fmt;
+use std::path::{Path, PathBuf, StripPrefixError};
 
 use thiserror::Error;
 
+use crate::codes::{self, Code};
+
 #[derive(Error, Debug)]
 pub enum DdriveError {
-    #[error("Invalid directory")]
+    #[error("{}", crate::tr!("error-invalid-directory"))]
     InvalidDirectory,
 
-    #[error("error getting relative path: {0}")]
+    #[error("{}", crate::tr!("error-invalid-path", error = .0))]
     InvalidPath(#[from] StripPrefixError),
 
-    #[error("Database error: {0}")]
+    #[error("{}", crate::tr!("error-database", error = .0))]
     Database(#[from] sqlx::Error),
 
-    #[error("IO error: {0}")]
+    #[error("{}", crate::tr!("error-io", error = .0))]
     Io(#[from] std::io::Error),
 
-    #[error("File system error: {message}")]
+    #[error("{}", crate::tr!("error-file-system", message = .message))]
     FileSystem { message: String },
 
-    #[error("Hard link error: {message}")]
+    #[error("{}", crate::tr!("error-hard-link", message = .message))]
     HardLink { message: String },
 
-    #[error("Checksum calculation error: {message}")]
+    #[error("{}", crate::tr!("error-checksum", message = .message))]
     Checksum { message: String },
 
-    #[error("Repository error: {message}")]
+    #[error("{}", crate::tr!("error-repository", message = .message))]
     Repository { message: String },
 
-    #[error("Validation error: {message}")]
+    #[error("{}", crate::tr!("error-validation", message = .message))]
     Validation { message: String },
 
-    #[error("Database migration error: {0}")]
+    #[error("{}", crate::tr!("error-migration", error = .0))]
     SqlxMigration(#[from] sqlx::migrate::MigrateError),
 
-    #[error("Ignore pattern error: {message}")]
+    #[error("{}", crate::tr!("error-ignore-pattern", message = .message))]
     IgnorePattern { message: String },
 
-    #[error("Glob pattern error: {0}")]
+    #[error("{}", crate::tr!("error-glob-pattern", error = .0))]
     GlobPattern(#[from] glob::PatternError),
 
-    #[error("Permission denied: {message}")]
+    #[error("{}", crate::tr!("error-permission-denied", message = .message))]
     PermissionDenied { message: String },
 
-    #[error("Configuration error: {message}")]
+    #[error("{}", crate::tr!("error-configuration", message = .message))]
     Configuration { message: String },
 
-    #[error("User cancelled operation")]
+    #[error("{}", crate::tr!("error-cancelled"))]
     UserCancelled,
+
+    #[error("{}", crate::tr!("error-partial-failure", count = .failed))]
+    PartialFailure { failed: usize },
+
+    #[error("{}", crate::tr!("error-unreadable", count = .unreadable))]
+    Unreadable { unreadable: usize },
+
+    #[error("{}", crate::tr!("error-object-store", message = .message))]
+    ObjectStore { message: String },
+
+    #[error("{}", crate::tr!("error-mirror", message = .message))]
+    Mirror { message: String },
+
+    #[error("{}", crate::tr!("error-encryption", message = .message))]
+    Encryption { message: String },
+
+    /// An error with what was being done when it happened
+    #[error("{}", crate::tr!("error-context", context = .context, error = .source))]
+    Context {
+        context: ErrorContext,
+        source: Box<DdriveError>,
+    },
+}
+
+/// Operations named in an [`ErrorContext`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, strum::IntoStaticStr)]
+#[strum(serialize_all = "kebab-case")]
+pub enum Operation {
+    Resolving,
+    Scanning,
+    Hashing,
+    ReadingMetadata,
+    ReadingMtime,
+    Creating,
+    Copying,
+    Removing,
+    StoringCopy,
+    StagingCopy,
+    HashingStagedCopy,
+    StoringStagedCopy,
+    StartingJournal,
+    JournalingCopy,
+    RecordingAdd,
+    HashingStoredCopy,
+    FetchingStoredCopy,
+    RestoringStoredCopy,
+    CopyingToQuarantine,
+    RecordingQuarantine,
+    SavingVerifyProgress,
+    FinishingVerify,
+}
+
+impl Operation {
+    /// Stable name of the operation, for machine-readable output
+    pub fn name(self) -> &'static str {
+        self.into()
+    }
+}
+
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let text = match self {
+            Operation::Resolving => crate::tr!("operation-resolving"),
+            Operation::Scanning => crate::tr!("operation-scanning"),
+            Operation::Hashing => crate::tr!("operation-hashing"),
+            Operation::ReadingMetadata => crate::tr!("operation-reading-metadata"),
+            Operation::ReadingMtime => crate::tr!("operation-reading-mtime"),
+            Operation::Creating => crate::tr!("operation-creating"),
+            Operation::Copying => crate::tr!("operation-copying"),
+            Operation::Removing => crate::tr!("operation-removing"),
+            Operation::StoringCopy => crate::tr!("operation-storing-copy"),
+            Operation::StagingCopy => crate::tr!("operation-staging-copy"),
+            Operation::HashingStagedCopy => crate::tr!("operation-hashing-staged-copy"),
+            Operation::StoringStagedCopy => crate::tr!("operation-storing-staged-copy"),
+            Operation::StartingJournal => crate::tr!("operation-starting-journal"),
+            Operation::JournalingCopy => crate::tr!("operation-journaling-copy"),
+            Operation::RecordingAdd => crate::tr!("operation-recording-add"),
+            Operation::HashingStoredCopy => crate::tr!("operation-hashing-stored-copy"),
+            Operation::FetchingStoredCopy => crate::tr!("operation-fetching-stored-copy"),
+            Operation::RestoringStoredCopy => crate::tr!("operation-restoring-stored-copy"),
+            Operation::CopyingToQuarantine => crate::tr!("operation-copying-to-quarantine"),
+            Operation::RecordingQuarantine => crate::tr!("operation-recording-quarantine"),
+            Operation::SavingVerifyProgress => crate::tr!("operation-saving-verify-progress"),
+            Operation::FinishingVerify => crate::tr!("operation-finishing-verify"),
+        };
+        f.write_str(&text)
+    }
+}
+
+/// What a command was doing when an error happened: the operation, the path
+/// it worked on and the action it was recording
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ErrorContext {
+    pub operation: Operation,
+    pub path: Option<PathBuf>,
+    pub action_id: Option<i64>,
+}
+
+impl fmt::Display for ErrorContext {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.operation)?;
+        if let Some(path) = &self.path {
+            write!(f, " {}", path.display())?;
+        }
+        if let Some(action_id) = self.action_id {
+            let action = bs58::encode(action_id.to_be_bytes()).into_string();
+            write!(
+                f,
+                " {}",
+                crate::tr!("error-context-action", action = action)
+            )?;
+        }
+        Ok(())
+    }
 }
 
 impl DdriveError {
     pub fn exit_code(&self) -> i32 {
-        match self {
+        match self.root() {
             DdriveError::Repository { .. } => 2,
             DdriveError::Database(_) | DdriveError::SqlxMigration(_) => 3,
             DdriveError::FileSystem { .. }
@@ -66,8 +183,123 @@ impl DdriveError {
             DdriveError::PermissionDenied { .. } => 9,
             DdriveError::Configuration { .. } => 10,
             DdriveError::UserCancelled => 11,
+            DdriveError::PartialFailure { .. } => 12,
+            DdriveError::Unreadable { .. } => 13,
+            DdriveError::ObjectStore { .. } => 14,
+            DdriveError::Mirror { .. } => 15,
+            DdriveError::Encryption { .. } => 16,
+            DdriveError::Context { .. } => unreachable!("root has no context"),
+        }
+    }
+
+    /// Stable code of the error, which does not change with its wording
+    pub fn code(&self) -> Code {
+        match self.root() {
+            DdriveError::InvalidDirectory => codes::INVALID_DIRECTORY,
+            DdriveError::InvalidPath(_) => codes::INVALID_PATH,
+            DdriveError::Database(_) => codes::DATABASE,
+            DdriveError::Io(_) => codes::IO,
+            DdriveError::FileSystem { .. } => codes::FILE_SYSTEM,
+            DdriveError::HardLink { .. } => codes::HARD_LINK,
+            DdriveError::Checksum { .. } => codes::CHECKSUM,
+            DdriveError::Repository { .. } => codes::REPOSITORY,
+            DdriveError::Validation { .. } => codes::VALIDATION,
+            DdriveError::SqlxMigration(_) => codes::MIGRATION,
+            DdriveError::IgnorePattern { .. } => codes::IGNORE_PATTERN,
+            DdriveError::GlobPattern(_) => codes::GLOB_PATTERN,
+            DdriveError::PermissionDenied { .. } => codes::PERMISSION_DENIED,
+            DdriveError::Configuration { .. } => codes::CONFIGURATION,
+            DdriveError::UserCancelled => codes::CANCELLED,
+            DdriveError::PartialFailure { .. } => codes::PARTIAL_FAILURE,
+            DdriveError::Unreadable { .. } => codes::UNREADABLE,
+            DdriveError::ObjectStore { .. } => codes::OBJECT_STORE,
+            DdriveError::Mirror { .. } => codes::MIRROR,
+            DdriveError::Encryption { .. } => codes::ENCRYPTION,
+            DdriveError::Context { .. } => unreachable!("root has no context"),
+        }
+    }
+
+    /// The error without the context around it, to match on its kind
+    pub fn root(&self) -> &DdriveError {
+        match self {
+            DdriveError::Context { source, .. } => source.root(),
+            error => error,
+        }
+    }
+
+    /// What was being done when the error happened, if known
+    pub fn context(&self) -> Option<&ErrorContext> {
+        match self {
+            DdriveError::Context { context, .. } => Some(context),
+            _ => None,
+        }
+    }
+
+    /// Note that the error happened during `operation` on `path`, within the
+    /// action being recorded. The innermost context is kept, as it is the
+    /// most specific.
+    pub fn with_context(self, operation: Operation, path: Option<&Path>) -> Self {
+        if matches!(self, DdriveError::Context { .. }) {
+            return self;
+        }
+        DdriveError::Context {
+            context: ErrorContext {
+                operation,
+                path: path.map(Path::to_path_buf),
+                action_id: crate::logging::action_id(),
+            },
+            source: Box::new(self),
         }
     }
 }
 
 pub type Result<T> = std::result::Result<T, DdriveError>;
+
+/// Attach an [`ErrorContext`] to the error of a result
+pub trait ResultExt<T> {
+    /// Note the operation and the path it worked on
+    fn context(self, operation: Operation, path: impl AsRef<Path>) -> Result<T>;
+
+    /// Note the operation, for errors not tied to one path
+    fn during(self, operation: Operation) -> Result<T>;
+}
+
+impl<T, E: Into<DdriveError>> ResultExt<T> for std::result::Result<T, E> {
+    fn context(self, operation: Operation, path: impl AsRef<Path>) -> Result<T> {
+        self.map_err(|e| e.into().with_context(operation, Some(path.as_ref())))
+    }
+
+    fn during(self, operation: Operation) -> Result<T> {
+        self.map_err(|e| e.into().with_context(operation, None))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_error_context() {
+        let denied = std::io::Error::from(std::io::ErrorKind::PermissionDenied);
+        let error = Err::<(), _>(denied)
+            .context(Operation::Hashing, "photos/beach.jpg")
+            .context(Operation::StoringCopy, "photos")
+            .unwrap_err();
+
+        // The innermost context is kept and the kind of error shows through
+        let context = error.context().unwrap();
+        assert_eq!(context.operation, Operation::Hashing);
+        assert_eq!(context.operation.name(), "hashing");
+        assert_eq!(context.path.as_deref(), Some(Path::new("photos/beach.jpg")));
+        assert!(matches!(error.root(), DdriveError::Io(_)));
+        assert_eq!(error.code(), codes::IO);
+        assert_eq!(error.exit_code(), 8);
+        assert!(error.to_string().starts_with("hashing photos/beach.jpg"));
+
+        let error = Err::<(), _>(DdriveError::UserCancelled)
+            .during(Operation::RecordingAdd)
+            .unwrap_err();
+        assert!(error.context().unwrap().path.is_none());
+        assert_eq!(error.code(), codes::CANCELLED);
+    }
+}
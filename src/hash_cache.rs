@@ -0,0 +1,132 @@
+//! Persistent checksum cache for files that are not (yet) tracked.
+//!
+//! Rename detection and `add` hash untracked files that merely lack a
+//! checksum in their scanned [`FileInfo`]. Entries are keyed by device and
+//! inode and are only reused while the file's size and modification time
+//! still match, so a modified file is always re-hashed.
+
+use crate::{
+    Result,
+    database::{CachedChecksum, Database},
+    scanner::FileInfo,
+};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, RwLock};
+
+#[derive(Default)]
+pub struct HashCache {
+    entries: RwLock<HashMap<(i64, i64), CachedChecksum>>,
+    /// Checksums calculated since the last flush
+    pending: Mutex<Vec<CachedChecksum>>,
+    loaded: AtomicBool,
+}
+
+impl HashCache {
+    /// Load cached checksums from the database, once
+    pub async fn load(&self, database: &Database) -> Result<()> {
+        if self.loaded.swap(true, Ordering::SeqCst) {
+            return Ok(());
+        }
+        let cached = database.load_hash_cache().await?;
+        let mut entries = self.entries.write().expect("hash cache lock");
+        for entry in cached {
+            entries.insert((entry.dev, entry.ino), entry);
+        }
+        Ok(())
+    }
+
+    /// Cached checksum of a file, if its size and modification time are unchanged
+    pub fn get(&self, file: &FileInfo) -> Option<String> {
+        let (key, size, mtime_ns) = cache_key(file)?;
+        let entries = self.entries.read().expect("hash cache lock");
+        entries
+            .get(&key)
+            .filter(|entry| entry.size == size && entry.mtime_ns == mtime_ns)
+            .map(|entry| entry.b3sum.clone())
+    }
+
+    /// Remember a freshly calculated checksum
+    pub fn insert(&self, file: &FileInfo, b3sum: &str) {
+        let Some(((dev, ino), size, mtime_ns)) = cache_key(file) else {
+            return;
+        };
+        let entry = CachedChecksum {
+            dev,
+            ino,
+            size,
+            mtime_ns,
+            b3sum: b3sum.to_string(),
+        };
+        self.entries
+            .write()
+            .expect("hash cache lock")
+            .insert((dev, ino), entry.clone());
+        self.pending.lock().expect("hash cache lock").push(entry);
+    }
+
+    /// Persist checksums calculated since the last flush
+    pub async fn flush(&self, database: &Database) -> Result<()> {
+        let pending = std::mem::take(&mut *self.pending.lock().expect("hash cache lock"));
+        // The cache only saves work, so a read-only database just goes without
+        if database.is_read_only() {
+            return Ok(());
+        }
+        database.save_hash_cache(&pending).await
+    }
+}
+
+fn cache_key(file: &FileInfo) -> Option<((i64, i64), i64, i64)> {
+    let (dev, ino) = file.inode_key()?;
+    Some((
+        (dev as i64, ino as i64),
+        file.size as i64,
+        file.modified_ns()?,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metadata::FileAttributes;
+    use std::path::PathBuf;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    fn file(size: u64, mtime_secs: u64) -> FileInfo {
+        FileInfo {
+            path: PathBuf::from("video.mp4"),
+            size,
+            modified: UNIX_EPOCH + Duration::from_secs(mtime_secs),
+            created: None,
+            b3sum: None,
+            attributes: FileAttributes::default(),
+            dev: Some(1),
+            ino: Some(42),
+            secondary: Default::default(),
+            stored: false,
+            ctime_ns: None,
+            content_type: None,
+        }
+    }
+
+    #[test]
+    fn test_cache_hit_requires_unchanged_size_and_mtime() {
+        let cache = HashCache::default();
+        cache.insert(&file(100, 1000), "abc");
+
+        assert_eq!(cache.get(&file(100, 1000)).as_deref(), Some("abc"));
+        assert_eq!(cache.get(&file(100, 1001)), None);
+        assert_eq!(cache.get(&file(101, 1000)), None);
+    }
+
+    #[test]
+    fn test_files_without_inode_are_not_cached() {
+        let cache = HashCache::default();
+        let mut unknown = file(100, 1000);
+        unknown.ino = None;
+        cache.insert(&unknown, "abc");
+
+        assert_eq!(cache.get(&unknown), None);
+        assert!(cache.pending.lock().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,211 @@
+//! Translated user-facing messages.
+//!
+//! Status sections, verify summaries and errors are looked up by message ID
+//! in the catalog of the chosen locale. Catalogs are embedded from
+//! `locales/<language>.ftl` and use a subset of Fluent syntax: one
+//! `id = text` per line, where the text is the rest of the line after `= `
+//! (leading indentation included), `{ $name }` placeables and `#` comments.
+//! Messages a catalog leaves out are shown in English.
+//!
+//! Only the text people read changes: log targets, structured log fields,
+//! JSON output and exit codes are the same in every locale, so scripts do
+//! not depend on it.
+//!
+//! The locale is `DDRIVE_LOCALE`, else `general.locale`, else the language
+//! of `LC_ALL`, `LC_MESSAGES` or `LANG`, else English.
+
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::sync::OnceLock;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Environment variable choosing the locale over the configuration
+pub const LOCALE_ENV: &str = "DDRIVE_LOCALE";
+
+/// Embedded catalogs by language; English comes first and is the fallback
+const CATALOGS: &[(&str, &str)] = &[
+    ("en", include_str!("../locales/en.ftl")),
+    ("de", include_str!("../locales/de.ftl")),
+];
+
+/// Index into `CATALOGS` of the active locale
+static ACTIVE: AtomicUsize = AtomicUsize::new(0);
+
+static PARSED: OnceLock<Vec<HashMap<&'static str, &'static str>>> = OnceLock::new();
+
+fn catalogs() -> &'static [HashMap<&'static str, &'static str>] {
+    PARSED.get_or_init(|| CATALOGS.iter().map(|(_, source)| parse(source)).collect())
+}
+
+/// Messages of a catalog by ID
+fn parse(source: &'static str) -> HashMap<&'static str, &'static str> {
+    source
+        .lines()
+        .filter(|line| !line.trim_start().starts_with('#'))
+        .filter_map(|line| {
+            let (id, text) = line.split_once(" =")?;
+            Some((id.trim(), text.strip_prefix(' ').unwrap_or(text)))
+        })
+        .collect()
+}
+
+/// Language of a locale name such as `de_DE.UTF-8`, if there is a catalog
+/// for it
+fn catalog_index(locale: &str) -> Option<usize> {
+    let language = locale
+        .split(['_', '-', '.', '@'])
+        .next()
+        .unwrap_or_default()
+        .to_lowercase();
+    CATALOGS.iter().position(|(name, _)| *name == language)
+}
+
+/// Choose the locale from `DDRIVE_LOCALE`, then `configured`
+/// (`general.locale`), then the system locale. Unknown languages fall back
+/// to English.
+pub fn init(configured: Option<&str>) {
+    let system = ["LC_ALL", "LC_MESSAGES", "LANG"]
+        .into_iter()
+        .filter_map(|name| std::env::var(name).ok())
+        .find(|value| !value.is_empty());
+    let index = std::env::var(LOCALE_ENV)
+        .ok()
+        .filter(|value| !value.is_empty())
+        .or_else(|| configured.map(str::to_string))
+        .or(system)
+        .and_then(|locale| catalog_index(&locale))
+        .unwrap_or(0);
+    ACTIVE.store(index, Ordering::Relaxed);
+}
+
+/// The message `id` in the active locale with its placeables filled in from
+/// `args`. Unknown IDs are returned as they are.
+pub fn message(id: &str, args: &[(&str, &dyn Display)]) -> String {
+    let catalogs = catalogs();
+    let text = catalogs[ACTIVE.load(Ordering::Relaxed)]
+        .get(id)
+        .or_else(|| catalogs[0].get(id))
+        .copied()
+        .unwrap_or(id);
+    format_message(text, args)
+}
+
+/// Replace each `{ $name }` in `text` with its argument
+fn format_message(text: &str, args: &[(&str, &dyn Display)]) -> String {
+    let mut output = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('{') {
+        output.push_str(&rest[..start]);
+        let Some(end) = rest[start..].find('}') else {
+            rest = &rest[start..];
+            break;
+        };
+        let placeable = rest[start + 1..start + end].trim();
+        match placeable
+            .strip_prefix('$')
+            .and_then(|name| args.iter().find(|(arg, _)| *arg == name))
+        {
+            Some((_, value)) => output.push_str(&value.to_string()),
+            None => output.push_str(&rest[start..=start + end]),
+        }
+        rest = &rest[start + end + 1..];
+    }
+    output.push_str(rest);
+    output
+}
+
+/// Look up a message in the active locale:
+/// `tr!("status-summary-total", count = files, size = size)`
+#[macro_export]
+macro_rules! tr {
+    ($id:literal) => {
+        $crate::i18n::message($id, &[])
+    };
+    ($id:literal, $($name:ident = $value:expr),+ $(,)?) => {
+        $crate::i18n::message(
+            $id,
+            &[$((stringify!($name), &$value as &dyn ::std::fmt::Display)),+],
+        )
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    /// Placeable names in `text`
+    fn placeables(text: &str) -> HashSet<&str> {
+        text.split('{')
+            .skip(1)
+            .filter_map(|part| part.split_once('}'))
+            .map(|(placeable, _)| placeable.trim().trim_start_matches('$'))
+            .collect()
+    }
+
+    #[test]
+    fn test_format_message() {
+        let count = 3;
+        assert_eq!(
+            format_message(
+                "  { $count } files ({ $size })",
+                &[("count", &count), ("size", &"1 KB")]
+            ),
+            "  3 files (1 KB)"
+        );
+        // Unknown placeables are left as they are
+        assert_eq!(format_message("{ $other } {", &[]), "{ $other } {");
+    }
+
+    #[test]
+    fn test_catalogs_match_english() {
+        let catalogs = catalogs();
+        let english = &catalogs[0];
+        for (index, catalog) in catalogs.iter().enumerate().skip(1) {
+            let language = CATALOGS[index].0;
+            for (id, text) in catalog {
+                let reference = english
+                    .get(id)
+                    .unwrap_or_else(|| panic!("{language}: {id} is not an English message"));
+                assert_eq!(
+                    placeables(text),
+                    placeables(reference),
+                    "{language}: placeables of {id}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_used_messages_exist() {
+        // Every message the sources look up with tr! is an English message
+        let english = &catalogs()[0];
+        let mut pending = vec![std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("src")];
+        while let Some(path) = pending.pop() {
+            if path.is_dir() {
+                pending.extend(std::fs::read_dir(&path).unwrap().map(|e| e.unwrap().path()));
+                continue;
+            }
+            let source = std::fs::read_to_string(&path).unwrap();
+            for (start, _) in source.match_indices("tr!(\"") {
+                // Not the end of a longer name such as include_str!
+                if source[..start].ends_with(|c: char| c == '_' || c.is_alphanumeric()) {
+                    continue;
+                }
+                let id = source[start + 5..].split('"').next().unwrap();
+                assert!(
+                    english.contains_key(id),
+                    "{} uses unknown message {id}",
+                    path.display()
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_locale_selection() {
+        assert_eq!(catalog_index("de_DE.UTF-8"), Some(1));
+        assert_eq!(catalog_index("en"), Some(0));
+        assert_eq!(catalog_index("C"), None);
+    }
+}
@@ -1,14 +1,29 @@
+pub mod api;
+pub mod backend;
 pub mod checksum;
 pub mod cli;
+pub mod codes;
 pub mod config;
+pub mod content_type;
 pub mod database;
+pub mod device;
+pub mod encryption;
 pub mod error;
+pub mod hash_cache;
+pub mod i18n;
+pub mod logging;
+pub mod media;
+pub mod metadata;
+pub mod notifications;
+pub mod progress;
+pub mod registry;
 pub mod repository;
 pub mod scanner;
 pub mod utils;
 
 use crate::repository::Repository;
-pub use error::{DdriveError, Result};
+pub use api::{AddOptions, Ddrive, PruneOptions, VerifyOptions};
+pub use error::{DdriveError, ErrorContext, Operation, Result, ResultExt};
 
 /// Application context that holds shared state
 #[derive(Clone)]
@@ -16,21 +31,33 @@ pub struct AppContext {
     pub database: database::Database,
     pub repo: Repository,
     pub config: config::Config,
+    /// Where stored objects are kept, from `[object_store] url`
+    pub objects: backend::SharedBackend,
+    /// Where commands send progress events, if anywhere
+    pub progress: Option<progress::ProgressSender>,
 }
 
 impl AppContext {
+    /// Open the repository's database and configuration from `.ddrive`
     pub async fn new(repo: Repository) -> Result<Self> {
-        let db_path = repo.root().join(".ddrive").join("metadata.sqlite3");
-        let database_url = format!("sqlite://{}", db_path.display());
-        let database = database::Database::new(&database_url, repo.root().clone()).await?;
+        AppContextBuilder::new(repo).build().await
+    }
 
-        let config = config::Config::load(repo.root())?;
+    /// Send a progress event to the front-end, if one is listening
+    pub fn emit(&self, event: progress::ProgressEvent) {
+        if let Some(progress) = &self.progress {
+            let _ = progress.send(event);
+        }
+    }
 
-        Ok(Self {
-            database,
-            repo,
-            config,
-        })
+    /// Markers for terminal output, honoring `general.emoji`
+    pub fn symbols(&self) -> utils::Symbols {
+        utils::Symbols::new(self.config.general.emoji)
+    }
+
+    /// Format a size in the units chosen by `general.size_units`
+    pub fn format_size(&self, size: u64) -> String {
+        utils::format_size_as(size, self.config.general.size_units)
     }
 
     /// Get a reference to the database
@@ -38,3 +65,90 @@ impl AppContext {
         &self.database
     }
 }
+
+/// Configuration change applied by [`AppContextBuilder::configure`]
+type ConfigOverride = Box<dyn FnOnce(&mut config::Config) + Send>;
+
+/// Builds an [`AppContext`] with a different database or configuration than
+/// the repository's `.ddrive` files, for embedding ddrive in other tools.
+pub struct AppContextBuilder {
+    repo: Repository,
+    database_url: Option<String>,
+    config: Option<config::Config>,
+    overrides: Vec<ConfigOverride>,
+    progress: Option<progress::ProgressSender>,
+    read_only: bool,
+}
+
+impl AppContextBuilder {
+    pub fn new(repo: Repository) -> Self {
+        Self {
+            repo,
+            database_url: None,
+            config: None,
+            overrides: Vec::new(),
+            progress: None,
+            read_only: false,
+        }
+    }
+
+    /// Open the metadata database at `url` (e.g. `sqlite:///tmp/ddrive.db?mode=rwc`)
+    /// instead of `.ddrive/metadata.sqlite3`
+    pub fn database_url(mut self, url: impl Into<String>) -> Self {
+        self.database_url = Some(url.into());
+        self
+    }
+
+    /// Use `config` as is, without reading or creating `.ddrive/config.toml`
+    pub fn config(mut self, config: config::Config) -> Self {
+        self.config = Some(config);
+        self
+    }
+
+    /// Adjust the configuration once it is loaded; overrides apply in order
+    pub fn configure(mut self, apply: impl FnOnce(&mut config::Config) + Send + 'static) -> Self {
+        self.overrides.push(Box::new(apply));
+        self
+    }
+
+    /// Send progress events of commands run with this context to `sender`
+    pub fn progress(mut self, sender: progress::ProgressSender) -> Self {
+        self.progress = Some(sender);
+        self
+    }
+
+    /// Open the database read-only; commands that record anything fail
+    pub fn read_only(mut self, read_only: bool) -> Self {
+        self.read_only = read_only;
+        self
+    }
+
+    pub async fn build(self) -> Result<AppContext> {
+        let database_url = self
+            .database_url
+            .unwrap_or_else(|| format!("sqlite://{}", self.repo.database_path().display()));
+        let database = if self.read_only {
+            database::Database::open_read_only(&database_url, self.repo.root().clone()).await?
+        } else {
+            database::Database::new(&database_url, self.repo.root().clone()).await?
+        };
+
+        let mut config = match self.config {
+            Some(config) => config,
+            None => config::Config::load(self.repo.root())?,
+        };
+        for apply in self.overrides {
+            apply(&mut config);
+        }
+        logging::open_log_file(&self.repo, &config.logging)?;
+        let objects = backend::open(&self.repo, &config.object_store)?;
+
+        Ok(AppContext {
+            database,
+            repo: self.repo,
+            config,
+            objects,
+            progress: self.progress,
+        })
+    }
+}
@@ -0,0 +1,316 @@
+//! Logging setup: terminal output plus an optional rotating log file.
+//!
+//! The CLI calls [`init`] before parsing arguments. Once a repository is
+//! opened, [`open_log_file`] starts writing its `[logging]` file, if enabled.
+//! Every file line carries the run ID of the process and, once the command
+//! has started one, the action ID, so the log of a run can be found from
+//! `ddrive log` output with `grep`.
+
+use crate::{DdriveError, Result, config::LoggingConfig, repository::Repository};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::fmt::Debug;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::{Mutex, OnceLock};
+use tracing::field::{Field, Visit};
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::layer::{Context, SubscriberExt};
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{EnvFilter, Layer, filter};
+
+/// Format of log file lines
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    #[default]
+    Text,
+    /// One JSON object per line
+    Json,
+}
+
+/// The open log file; `None` until a repository enables one
+static LOG_FILE: OnceLock<Mutex<Option<LogFile>>> = OnceLock::new();
+
+/// Action the current command records its changes under, 0 before it has one
+static ACTION_ID: AtomicI64 = AtomicI64::new(0);
+
+/// Set up terminal output (`RUST_LOG` filters it, ddrive messages at info by
+/// default) and the log file layer
+pub fn init() {
+    let terminal = tracing_subscriber::fmt::layer()
+        .without_time()
+        .with_level(false)
+        .with_ansi(true)
+        .with_target(false)
+        .with_filter(EnvFilter::from_default_env().add_directive("ddrive=info".parse().unwrap()));
+    let file = FileLayer.with_filter(filter::filter_fn(|metadata| {
+        metadata.target().starts_with("ddrive") && *metadata.level() <= Level::DEBUG
+    }));
+
+    let _ = LOG_FILE.set(Mutex::new(None));
+    tracing_subscriber::registry()
+        .with(terminal)
+        .with(file)
+        .init();
+}
+
+/// Start writing the repository's log file if `[logging] file` is enabled.
+/// Does nothing unless [`init`] set up logging.
+pub fn open_log_file(repo: &Repository, config: &LoggingConfig) -> Result<()> {
+    let Some(slot) = LOG_FILE.get() else {
+        return Ok(());
+    };
+    if !config.file {
+        return Ok(());
+    }
+
+    let level = Level::from_str(&config.level).map_err(|_| DdriveError::Configuration {
+        message: format!("Unknown log level: {}", config.level),
+    })?;
+    let mut log_file = LogFile::open(log_path(repo), config, level)?;
+    let args: Vec<String> = std::env::args().collect();
+    log_file.write(
+        Level::INFO,
+        "ddrive",
+        &format!("Run started: {}", args.join(" ")),
+        Map::new(),
+    );
+    *slot.lock().expect("log file lock") = Some(log_file);
+    Ok(())
+}
+
+/// Tag the following log lines with the action the command records under
+pub fn set_action_id(action_id: i64) {
+    ACTION_ID.store(action_id, Ordering::Relaxed);
+}
+
+/// Action the current command records its changes under, if it has one
+pub fn action_id() -> Option<i64> {
+    match ACTION_ID.load(Ordering::Relaxed) {
+        0 => None,
+        id => Some(id),
+    }
+}
+
+/// Path of the current log file; rotated files get `.1`, `.2`, ... appended
+pub fn log_path(repo: &Repository) -> PathBuf {
+    repo.root().join(".ddrive").join("logs").join("ddrive.log")
+}
+
+/// ID shared by all lines written by this process
+fn run_id() -> &'static str {
+    static RUN_ID: OnceLock<String> = OnceLock::new();
+    RUN_ID.get_or_init(|| format!("{}-{}", chrono::Utc::now().timestamp(), std::process::id()))
+}
+
+struct LogFile {
+    path: PathBuf,
+    file: File,
+    size: u64,
+    max_size: u64,
+    max_files: u32,
+    format: LogFormat,
+    level: Level,
+}
+
+impl LogFile {
+    fn open(path: PathBuf, config: &LoggingConfig, level: Level) -> Result<Self> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let size = file.metadata()?.len();
+        Ok(Self {
+            path,
+            file,
+            size,
+            max_size: config.max_size,
+            max_files: config.max_files,
+            format: config.format,
+            level,
+        })
+    }
+
+    fn write(&mut self, level: Level, target: &str, message: &str, fields: Map<String, Value>) {
+        if level > self.level {
+            return;
+        }
+
+        let timestamp = chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, true);
+        let action_id = action_id().map(|id| bs58::encode(id.to_be_bytes()).into_string());
+        let line = match self.format {
+            LogFormat::Json => {
+                let mut object = Map::new();
+                object.insert("timestamp".into(), timestamp.into());
+                object.insert("level".into(), level.as_str().into());
+                object.insert("target".into(), target.into());
+                object.insert("run".into(), run_id().into());
+                object.insert("action".into(), action_id.into());
+                object.insert("message".into(), message.into());
+                object.extend(fields);
+                Value::Object(object).to_string()
+            }
+            LogFormat::Text => {
+                let mut line = format!("{timestamp} {:5} run={}", level.as_str(), run_id());
+                if let Some(action_id) = action_id {
+                    line.push_str(&format!(" action={action_id}"));
+                }
+                line.push(' ');
+                line.push_str(message);
+                for (name, value) in fields {
+                    // Strings without the JSON quotes
+                    match value {
+                        Value::String(value) => line.push_str(&format!(" {name}={value}")),
+                        value => line.push_str(&format!(" {name}={value}")),
+                    }
+                }
+                line
+            }
+        };
+
+        if let Err(e) = self.append(&line) {
+            // Logging through tracing here would re-enter the layer
+            eprintln!("Failed to write {}: {}", self.path.display(), e);
+        }
+    }
+
+    fn append(&mut self, line: &str) -> std::io::Result<()> {
+        let len = line.len() as u64 + 1;
+        if self.size > 0 && self.size + len > self.max_size {
+            self.rotate()?;
+        }
+        writeln!(self.file, "{line}")?;
+        self.size += len;
+        Ok(())
+    }
+
+    /// Shift `ddrive.log.N` to `.N+1`, dropping the oldest, and start a new file
+    fn rotate(&mut self) -> std::io::Result<()> {
+        let rotated = |n: u32| -> PathBuf {
+            let mut name = self.path.clone().into_os_string();
+            name.push(format!(".{n}"));
+            name.into()
+        };
+        if self.max_files > 0 {
+            let _ = fs::remove_file(rotated(self.max_files));
+            for n in (1..self.max_files).rev() {
+                let from = rotated(n);
+                if from.exists() {
+                    fs::rename(&from, rotated(n + 1))?;
+                }
+            }
+            fs::rename(&self.path, rotated(1))?;
+        }
+        self.file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(true)
+            .open(&self.path)?;
+        self.size = 0;
+        Ok(())
+    }
+}
+
+struct FileLayer;
+
+impl<S: Subscriber> Layer<S> for FileLayer {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let Some(slot) = LOG_FILE.get() else {
+            return;
+        };
+        let Ok(mut log_file) = slot.lock() else {
+            return;
+        };
+        let Some(log_file) = log_file.as_mut() else {
+            return;
+        };
+
+        let mut visitor = FieldVisitor::default();
+        event.record(&mut visitor);
+        let metadata = event.metadata();
+        log_file.write(
+            *metadata.level(),
+            metadata.target(),
+            &visitor.message,
+            visitor.fields,
+        );
+    }
+}
+
+/// Collects an event's message and fields
+#[derive(Default)]
+struct FieldVisitor {
+    message: String,
+    fields: Map<String, Value>,
+}
+
+impl Visit for FieldVisitor {
+    fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+        if field.name() == "message" {
+            self.message = format!("{value:?}");
+        } else {
+            self.fields
+                .insert(field.name().into(), format!("{value:?}").into());
+        }
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            self.message = value.to_string();
+        } else {
+            self.fields.insert(field.name().into(), value.into());
+        }
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.fields.insert(field.name().into(), value.into());
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.fields.insert(field.name().into(), value.into());
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.fields.insert(field.name().into(), value.into());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_log_rotation() {
+        let temp = TempDir::new().unwrap();
+        let repo = Repository::new(temp.path().to_path_buf());
+        let config = LoggingConfig {
+            file: true,
+            format: LogFormat::Json,
+            max_size: 200,
+            max_files: 2,
+            ..Default::default()
+        };
+        let mut log_file = LogFile::open(log_path(&repo), &config, Level::INFO).unwrap();
+        for i in 0..10 {
+            log_file.write(Level::INFO, "ddrive", &format!("line {i}"), Map::new());
+        }
+        log_file.write(Level::DEBUG, "ddrive", "too verbose", Map::new());
+
+        let path = log_path(&repo);
+        let rotated = |n: u32| PathBuf::from(format!("{}.{n}", path.display()));
+        for file in [path.clone(), rotated(1), rotated(2)] {
+            assert!(fs::metadata(file).unwrap().len() <= 200);
+        }
+        assert!(!rotated(3).exists());
+        let current = fs::read_to_string(&path).unwrap();
+        let last: Value = serde_json::from_str(current.lines().last().unwrap()).unwrap();
+        assert_eq!(last["message"], "line 9");
+        assert_eq!(last["level"], "INFO");
+        assert!(!current.contains("too verbose"));
+    }
+}
@@ -0,0 +1,51 @@
+//! Photos and videos.
+//!
+//! Recognizes media files by extension and dates them by the time they were
+//! taken: the EXIF `DateTimeOriginal` of photos when `[media] exif_dates` is
+//! enabled, or else the modification time, which cameras set when shooting.
+
+use crate::config::MediaConfig;
+use chrono::{DateTime, Local, NaiveDate, TimeZone};
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+use std::time::SystemTime;
+
+/// Extensions of photo and video files, lowercase
+const MEDIA_EXTENSIONS: &[&str] = &[
+    "jpg", "jpeg", "png", "gif", "webp", "heic", "heif", "avif", "tif", "tiff", "dng", "cr2",
+    "cr3", "nef", "arw", "raf", "orf", "rw2", "mp4", "mov", "m4v", "avi", "mkv", "3gp", "mts",
+];
+
+/// Whether `path` names a photo or video
+pub fn is_media(path: &Path) -> bool {
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| MEDIA_EXTENSIONS.contains(&extension.to_lowercase().as_str()))
+}
+
+/// When the photo or video at `path`, last modified at `modified`, was taken
+pub fn capture_time(path: &Path, modified: SystemTime, config: &MediaConfig) -> DateTime<Local> {
+    config
+        .exif_dates
+        .then(|| exif_date(path))
+        .flatten()
+        .unwrap_or_else(|| modified.into())
+}
+
+/// The EXIF time a photo was taken (or, failing that, last edited), in
+/// local time as cameras record it
+pub fn exif_date(path: &Path) -> Option<DateTime<Local>> {
+    let mut reader = BufReader::new(File::open(path).ok()?);
+    let exif = exif::Reader::new().read_from_container(&mut reader).ok()?;
+    let field = [exif::Tag::DateTimeOriginal, exif::Tag::DateTime]
+        .into_iter()
+        .find_map(|tag| exif.get_field(tag, exif::In::PRIMARY))?;
+    let exif::Value::Ascii(values) = &field.value else {
+        return None;
+    };
+    let date = exif::DateTime::from_ascii(values.first()?).ok()?;
+    let taken = NaiveDate::from_ymd_opt(date.year.into(), date.month.into(), date.day.into())?
+        .and_hms_opt(date.hour.into(), date.minute.into(), date.second.into())?;
+    Local.from_local_datetime(&taken).earliest()
+}
@@ -0,0 +1,252 @@
+//! File attribute tracking: permission bits, ownership and extended attributes.
+//!
+//! Attributes are captured alongside checksums so that `verify` can report
+//! metadata drift separately from content corruption, and so restored files
+//! can have their original attributes reapplied.
+
+use crate::{DdriveError, Result, config::MetadataConfig, database::FileRecord};
+use std::collections::BTreeMap;
+use std::fs::Metadata;
+use std::path::Path;
+
+/// Snapshot of the tracked attributes of a file
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FileAttributes {
+    pub mode: Option<u32>,
+    pub uid: Option<u32>,
+    pub gid: Option<u32>,
+    /// JSON object of attribute name to hex-encoded value
+    pub xattrs: Option<String>,
+}
+
+impl FileAttributes {
+    /// Read attributes from filesystem metadata, honoring the configured policy
+    pub fn from_metadata(metadata: &Metadata, config: &MetadataConfig) -> Self {
+        let (mode, uid, gid) = unix_attributes(metadata);
+        Self {
+            mode: mode.filter(|_| config.permissions),
+            uid: uid.filter(|_| config.ownership),
+            gid: gid.filter(|_| config.ownership),
+            xattrs: None,
+        }
+    }
+
+    /// Read attributes of the file at `path`, including xattrs when enabled
+    pub fn read(path: &Path, config: &MetadataConfig) -> Result<Self> {
+        let metadata = std::fs::metadata(path)?;
+        let mut attributes = Self::from_metadata(&metadata, config);
+        if config.xattrs {
+            attributes.xattrs = read_xattrs(path)?;
+        }
+        Ok(attributes)
+    }
+
+    /// Attributes as recorded in the database
+    pub fn from_record(record: &FileRecord) -> Self {
+        Self {
+            mode: record.mode.map(|m| m as u32),
+            uid: record.uid.map(|u| u as u32),
+            gid: record.gid.map(|g| g as u32),
+            xattrs: record.xattrs.clone(),
+        }
+    }
+
+    /// Describe how `current` differs from these (recorded) attributes.
+    /// Attributes that were not recorded are not compared.
+    pub fn drift(&self, current: &FileAttributes) -> Vec<String> {
+        let mut changes = Vec::new();
+        if let Some(mode) = self.mode
+            && current.mode != Some(mode)
+        {
+            changes.push(format!(
+                "mode {:o} -> {}",
+                mode & 0o7777,
+                current
+                    .mode
+                    .map(|m| format!("{:o}", m & 0o7777))
+                    .unwrap_or_else(|| "unknown".to_string())
+            ));
+        }
+        if let Some(uid) = self.uid
+            && current.uid != Some(uid)
+        {
+            changes.push(format!("uid {uid} -> {}", display_id(current.uid)));
+        }
+        if let Some(gid) = self.gid
+            && current.gid != Some(gid)
+        {
+            changes.push(format!("gid {gid} -> {}", display_id(current.gid)));
+        }
+        if self.xattrs.is_some() && current.xattrs != self.xattrs {
+            changes.push("extended attributes changed".to_string());
+        }
+        changes
+    }
+
+    /// Reapply recorded attributes to a file, e.g. after restoring it
+    pub fn apply(&self, path: &Path) -> Result<()> {
+        apply_attributes(path, self)
+    }
+}
+
+fn display_id(id: Option<u32>) -> String {
+    id.map(|i| i.to_string())
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+/// Device and inode numbers identifying the underlying file (shared by hardlinks)
+#[cfg(unix)]
+pub fn file_identity(metadata: &Metadata) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+    Some((metadata.dev(), metadata.ino()))
+}
+
+#[cfg(not(unix))]
+pub fn file_identity(_metadata: &Metadata) -> Option<(u64, u64)> {
+    None
+}
+
+/// Inode change time in nanoseconds since the Unix epoch. Unlike the
+/// modification time it cannot be set back, so it reveals edits that
+/// restored the mtime.
+#[cfg(unix)]
+pub fn change_time_ns(metadata: &Metadata) -> Option<i64> {
+    use std::os::unix::fs::MetadataExt;
+    metadata
+        .ctime()
+        .checked_mul(1_000_000_000)?
+        .checked_add(metadata.ctime_nsec())
+}
+
+#[cfg(not(unix))]
+pub fn change_time_ns(_metadata: &Metadata) -> Option<i64> {
+    None
+}
+
+#[cfg(unix)]
+fn unix_attributes(metadata: &Metadata) -> (Option<u32>, Option<u32>, Option<u32>) {
+    use std::os::unix::fs::MetadataExt;
+    (
+        Some(metadata.mode()),
+        Some(metadata.uid()),
+        Some(metadata.gid()),
+    )
+}
+
+#[cfg(not(unix))]
+fn unix_attributes(_metadata: &Metadata) -> (Option<u32>, Option<u32>, Option<u32>) {
+    (None, None, None)
+}
+
+/// Read all extended attributes of a file as a JSON object of hex-encoded values
+#[cfg(unix)]
+pub fn read_xattrs(path: &Path) -> Result<Option<String>> {
+    let mut attributes = BTreeMap::new();
+    let names = xattr::list(path).map_err(|e| DdriveError::FileSystem {
+        message: format!("Could not list xattrs of {}: {}", path.display(), e),
+    })?;
+    for name in names {
+        let value = xattr::get(path, &name).map_err(|e| DdriveError::FileSystem {
+            message: format!("Could not read xattrs of {}: {}", path.display(), e),
+        })?;
+        attributes.insert(
+            name.to_string_lossy().into_owned(),
+            value.map(|v| encode_hex(&v)).unwrap_or_default(),
+        );
+    }
+    if attributes.is_empty() {
+        return Ok(None);
+    }
+    Ok(serde_json::to_string(&attributes).ok())
+}
+
+#[cfg(not(unix))]
+pub fn read_xattrs(_path: &Path) -> Result<Option<String>> {
+    Ok(None)
+}
+
+#[cfg(unix)]
+fn apply_attributes(path: &Path, attributes: &FileAttributes) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    if let Some(mode) = attributes.mode {
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode & 0o7777))?;
+    }
+    if attributes.uid.is_some() || attributes.gid.is_some() {
+        std::os::unix::fs::chown(path, attributes.uid, attributes.gid).map_err(|e| {
+            DdriveError::PermissionDenied {
+                message: format!("Could not change owner of {}: {}", path.display(), e),
+            }
+        })?;
+    }
+    if let Some(xattrs) = &attributes.xattrs {
+        let values: BTreeMap<String, String> =
+            serde_json::from_str(xattrs).map_err(|e| DdriveError::Validation {
+                message: format!("Invalid xattr record for {}: {}", path.display(), e),
+            })?;
+        for (name, value) in values {
+            xattr::set(path, &name, &decode_hex(&value)).map_err(|e| DdriveError::FileSystem {
+                message: format!("Could not set xattr {} on {}: {}", name, path.display(), e),
+            })?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn apply_attributes(_path: &Path, _attributes: &FileAttributes) -> Result<()> {
+    Ok(())
+}
+
+fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+fn decode_hex(hex: &str) -> Vec<u8> {
+    (0..hex.len())
+        .step_by(2)
+        .filter_map(|i| hex.get(i..i + 2))
+        .filter_map(|b| u8::from_str_radix(b, 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hex_roundtrip() {
+        let bytes = b"user.comment\x00\xff";
+        assert_eq!(decode_hex(&encode_hex(bytes)), bytes.to_vec());
+    }
+
+    #[test]
+    fn test_drift_ignores_unrecorded_attributes() {
+        let recorded = FileAttributes {
+            mode: Some(0o100644),
+            uid: None,
+            gid: None,
+            xattrs: None,
+        };
+        let current = FileAttributes {
+            mode: Some(0o100644),
+            uid: Some(1000),
+            gid: Some(1000),
+            xattrs: Some("{}".to_string()),
+        };
+        assert!(recorded.drift(&current).is_empty());
+    }
+
+    #[test]
+    fn test_drift_reports_mode_change() {
+        let recorded = FileAttributes {
+            mode: Some(0o100644),
+            ..Default::default()
+        };
+        let current = FileAttributes {
+            mode: Some(0o100600),
+            ..Default::default()
+        };
+        assert_eq!(recorded.drift(&current), vec!["mode 644 -> 600"]);
+    }
+}
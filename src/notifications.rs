@@ -0,0 +1,123 @@
+//! Desktop notifications.
+//!
+//! Workstation users rarely read logs, so with `notifications.enabled` a
+//! verify run shows a desktop notification when it finds damaged files or
+//! stored objects, and with `notifications.on_complete` whenever it finishes.
+//! Runs started by a timer, by `ddrive repos verify` or through
+//! `ddrive serve` all notify.
+//!
+//! Sending is best effort: without a notification service, as on a server or
+//! in a headless session, the run goes on and only a debug message is logged.
+
+use crate::{cli::verify::VerifyResult, config::NotificationsConfig, tr};
+use std::path::Path;
+use tracing::debug;
+
+/// A notification to show
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Notification {
+    pub title: String,
+    pub body: String,
+    /// Failures stay on screen until dismissed where the desktop supports it
+    pub urgent: bool,
+}
+
+/// The notification `config` asks for after a verify run in `repo_root`, if any
+pub fn verify_notification(
+    config: &NotificationsConfig,
+    repo_root: &Path,
+    result: &VerifyResult,
+) -> Option<Notification> {
+    if !config.enabled {
+        return None;
+    }
+    let repo = repo_root
+        .file_name()
+        .unwrap_or(repo_root.as_os_str())
+        .to_string_lossy();
+    let failed = result.failed_files > 0 || !result.object_failures.is_empty();
+    if failed && config.on_failure {
+        return Some(Notification {
+            title: tr!("notify-failed-title", repo = repo),
+            body: tr!(
+                "notify-failed-body",
+                failed = result.failed_files,
+                objects = result.object_failures.len()
+            ),
+            urgent: true,
+        });
+    }
+    if !failed && config.on_complete {
+        return Some(Notification {
+            title: tr!("notify-complete-title", repo = repo),
+            body: tr!(
+                "notify-complete-body",
+                passed = result.passed_files,
+                checked = result.checked_files,
+                modified = result.modified_files,
+                skipped = result.skipped_files
+            ),
+            urgent: false,
+        });
+    }
+    None
+}
+
+/// Show `notification` on the desktop, logging rather than failing when
+/// there is no notification service
+pub async fn send(notification: Notification) {
+    let shown = tokio::task::spawn_blocking(move || {
+        let mut desktop = notify_rust::Notification::new();
+        desktop
+            .appname("ddrive")
+            .summary(&notification.title)
+            .body(&notification.body);
+        #[cfg(all(unix, not(target_os = "macos")))]
+        if notification.urgent {
+            desktop.urgency(notify_rust::Urgency::Critical);
+        }
+        desktop.show().map(|_| ())
+    })
+    .await;
+    match shown {
+        Ok(Ok(())) => {}
+        Ok(Err(e)) => debug!("Could not show a desktop notification: {}", e),
+        Err(e) => debug!("Could not show a desktop notification: {}", e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_verify_notification() {
+        let root = Path::new("/home/me/photos");
+        let mut config = NotificationsConfig::default();
+        let passed = VerifyResult {
+            checked_files: 3,
+            passed_files: 3,
+            ..VerifyResult::default()
+        };
+        let failed = VerifyResult {
+            checked_files: 3,
+            passed_files: 2,
+            failed_files: 1,
+            ..VerifyResult::default()
+        };
+        // Off unless enabled
+        assert_eq!(verify_notification(&config, root, &failed), None);
+
+        config.enabled = true;
+        let notification = verify_notification(&config, root, &failed).unwrap();
+        assert!(notification.urgent);
+        assert!(notification.title.contains("photos"));
+        assert_eq!(verify_notification(&config, root, &passed), None);
+
+        config.on_complete = true;
+        let notification = verify_notification(&config, root, &passed).unwrap();
+        assert!(!notification.urgent);
+        config.on_failure = false;
+        assert_eq!(verify_notification(&config, root, &failed), None);
+    }
+}
@@ -0,0 +1,109 @@
+//! Live progress events for front-ends embedding ddrive.
+//!
+//! When an [`AppContext`](crate::AppContext) has a progress sender, commands
+//! send a [`ProgressEvent`] as they work, so a GUI can show progress without
+//! parsing log lines. Events are dropped silently once the receiver is gone.
+
+use crate::database::ActionType;
+use std::path::PathBuf;
+use tokio::sync::mpsc::UnboundedSender;
+
+/// Channel commands send progress events to
+pub type ProgressSender = UnboundedSender<ProgressEvent>;
+
+/// How verifying one file turned out
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VerifyOutcome {
+    Passed,
+    /// Content changed along with its modification time
+    Modified,
+    /// Content changed but the metadata did not
+    Corrupted,
+    /// No checksum for the algorithm, or the file's media is not attached
+    Skipped,
+    /// The file could not be read
+    Error,
+}
+
+#[derive(Debug, Clone)]
+pub enum ProgressEvent {
+    /// A command is about to process `files` files
+    Started { command: &'static str, files: usize },
+    /// A file was read and hashed
+    FileHashed {
+        path: PathBuf,
+        size: u64,
+        b3sum: String,
+    },
+    /// A new object was written to the object store
+    ObjectCopied { b3sum: String, size: u64 },
+    /// A file was verified
+    VerifyResult {
+        path: String,
+        outcome: VerifyOutcome,
+    },
+    /// An action was recorded in history
+    ActionCommitted {
+        action_id: i64,
+        action_type: ActionType,
+        files: usize,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{AddOptions, Ddrive, VerifyOptions, api::testing::repository};
+
+    #[tokio::test]
+    async fn test_progress_events() {
+        let (temp, _) = repository(&[("photos/a.jpg", "a")]).await;
+        let (sender, mut receiver) = tokio::sync::mpsc::unbounded_channel();
+        let context = Ddrive::builder(temp.path())
+            .progress(sender)
+            .build()
+            .await
+            .unwrap();
+        let ddrive = Ddrive::from_context(context);
+        ddrive
+            .add(&[temp.path().join("photos")], &AddOptions::default())
+            .await
+            .unwrap();
+        ddrive
+            .verify(&VerifyOptions {
+                force: true,
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+
+        let mut events = Vec::new();
+        while let Ok(event) = receiver.try_recv() {
+            events.push(event);
+        }
+        assert!(matches!(
+            events[0],
+            ProgressEvent::Started {
+                command: "add",
+                files: 1
+            }
+        ));
+        assert!(
+            events
+                .iter()
+                .any(|e| matches!(e, ProgressEvent::ObjectCopied { size: 1, .. }))
+        );
+        assert!(
+            events
+                .iter()
+                .any(|e| matches!(e, ProgressEvent::ActionCommitted { files: 1, .. }))
+        );
+        assert!(events.iter().any(|e| matches!(
+            e,
+            ProgressEvent::VerifyResult {
+                outcome: VerifyOutcome::Passed,
+                ..
+            }
+        )));
+    }
+}
@@ -0,0 +1,194 @@
+//! Registry of all repositories on this machine.
+//!
+//! Repositories are recorded in `repos.toml` in the user's configuration
+//! directory (`$XDG_CONFIG_HOME/ddrive`, or `~/.config/ddrive`) when they are
+//! initialized or added with `ddrive repos add`, so maintenance can run across
+//! repositories on several drives from one command. Changes are made under a
+//! lock next to the file and written atomically, so ddrive processes running
+//! at once neither lose each other's entries nor leave a partial file.
+
+use crate::{DdriveError, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::debug;
+
+/// The repositories known on this machine
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Registry {
+    /// File the registry is stored in
+    #[serde(skip)]
+    file: PathBuf,
+
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub repos: Vec<RegisteredRepo>,
+}
+
+/// A repository recorded in the registry
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RegisteredRepo {
+    /// Canonical path of the repository root
+    pub path: PathBuf,
+}
+
+impl RegisteredRepo {
+    /// Whether the repository can be opened now; repositories on detached
+    /// drives are not
+    pub fn is_available(&self) -> bool {
+        self.path.join(".ddrive").join("metadata.sqlite3").is_file()
+    }
+}
+
+impl Registry {
+    /// Registry file in the user's configuration directory, if there is one
+    pub fn default_file() -> Option<PathBuf> {
+        let config_dir = std::env::var_os("XDG_CONFIG_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| std::env::home_dir().map(|home| home.join(".config")))?;
+        Some(config_dir.join("ddrive").join("repos.toml"))
+    }
+
+    /// Load the registry in the user's configuration directory
+    pub fn load_default() -> Result<Self> {
+        let file = Self::default_file().ok_or_else(|| DdriveError::Configuration {
+            message: "Cannot locate the user configuration directory".to_string(),
+        })?;
+        Self::load(&file)
+    }
+
+    /// Load the registry stored in `file`; a missing file is an empty registry
+    pub fn load(file: &Path) -> Result<Self> {
+        let mut registry: Registry = match fs::read_to_string(file) {
+            Ok(registry_str) => {
+                toml::from_str(&registry_str).map_err(|e| DdriveError::Configuration {
+                    message: format!("Failed to parse {}: {e}", file.display()),
+                })?
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Registry::default(),
+            Err(e) => return Err(e.into()),
+        };
+        registry.file = file.to_path_buf();
+        Ok(registry)
+    }
+
+    /// Load the registry in `file` under its lock and let `change` modify it,
+    /// saving it if `change` returns true. Returns what `change` returned.
+    pub fn update(file: &Path, change: impl FnOnce(&mut Registry) -> Result<bool>) -> Result<bool> {
+        if let Some(dir) = file.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        // Released when the lock file is closed at the end of the update
+        let lock = fs::OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(file.with_extension("toml.lock"))?;
+        lock.lock()?;
+
+        let mut registry = Self::load(file)?;
+        let changed = change(&mut registry)?;
+        if changed {
+            registry.save()?;
+        }
+        Ok(changed)
+    }
+
+    /// The file the registry is stored in
+    pub fn file(&self) -> &Path {
+        &self.file
+    }
+
+    /// Write the registry back to its file through a temporary file, so
+    /// readers never see a partial one. Callers hold the registry lock.
+    fn save(&self) -> Result<()> {
+        let registry_str =
+            toml::to_string_pretty(self).map_err(|e| DdriveError::Configuration {
+                message: format!("Failed to serialize registry: {e}"),
+            })?;
+        let tmp_file = self.file.with_extension("toml.tmp");
+        fs::write(&tmp_file, registry_str)?;
+        fs::rename(&tmp_file, &self.file)?;
+        debug!("Registry saved to {}", self.file.display());
+        Ok(())
+    }
+
+    /// Record a repository root. Returns whether it was not registered yet.
+    pub fn register(&mut self, repo_root: &Path) -> Result<bool> {
+        let path = repo_root.canonicalize()?;
+        if self.repos.iter().any(|repo| repo.path == path) {
+            return Ok(false);
+        }
+        self.repos.push(RegisteredRepo { path });
+        self.repos.sort_by(|a, b| a.path.cmp(&b.path));
+        Ok(true)
+    }
+
+    /// Forget a repository. Returns whether it was registered.
+    pub fn unregister(&mut self, repo_root: &Path) -> bool {
+        let path = repo_root
+            .canonicalize()
+            .unwrap_or_else(|_| repo_root.to_path_buf());
+        let count = self.repos.len();
+        self.repos.retain(|repo| repo.path != path);
+        self.repos.len() != count
+    }
+}
+
+/// Record `repo_root` in the user's registry. Returns whether it was not
+/// registered yet.
+pub fn register_repository(repo_root: &Path) -> Result<bool> {
+    let file = Registry::default_file().ok_or_else(|| DdriveError::Configuration {
+        message: "Cannot locate the user configuration directory".to_string(),
+    })?;
+    Registry::update(&file, |registry| registry.register(repo_root))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_fs::TempDir;
+
+    #[test]
+    fn test_register_and_unregister() {
+        let temp = TempDir::new().unwrap();
+        let file = temp.path().join("config/ddrive/repos.toml");
+        let repo = temp.path().join("repo");
+        fs::create_dir_all(&repo).unwrap();
+
+        assert!(Registry::load(&file).unwrap().repos.is_empty());
+        assert!(Registry::update(&file, |registry| registry.register(&repo)).unwrap());
+        assert!(!Registry::update(&file, |registry| registry.register(&repo.join("."))).unwrap());
+        assert!(!file.with_extension("toml.tmp").exists());
+
+        let registry = Registry::load(&file).unwrap();
+        assert_eq!(registry.repos.len(), 1);
+        assert!(!registry.repos[0].is_available());
+        assert!(Registry::update(&file, |registry| Ok(registry.unregister(&repo))).unwrap());
+        assert!(!Registry::update(&file, |registry| Ok(registry.unregister(&repo))).unwrap());
+        assert!(Registry::load(&file).unwrap().repos.is_empty());
+    }
+
+    #[test]
+    fn test_concurrent_registrations_are_kept() {
+        let temp = TempDir::new().unwrap();
+        let file = temp.path().join("config/ddrive/repos.toml");
+        let repos: Vec<PathBuf> = (0..8)
+            .map(|i| {
+                let repo = temp.path().join(format!("repo{i}"));
+                fs::create_dir_all(&repo).unwrap();
+                repo
+            })
+            .collect();
+
+        std::thread::scope(|scope| {
+            for repo in &repos {
+                let file = &file;
+                scope.spawn(move || {
+                    Registry::update(file, |registry| registry.register(repo)).unwrap();
+                });
+            }
+        });
+        assert_eq!(Registry::load(&file).unwrap().repos.len(), repos.len());
+    }
+}
@@ -1,8 +1,42 @@
-use crate::{DdriveError, Result};
+use crate::{DdriveError, Result, metadata::file_identity};
 use std::fs;
 use std::path::{Path, PathBuf};
 use tracing::{debug, info};
 
+/// What the file system under a repository offers the object store
+#[derive(Debug, Clone)]
+pub struct StorageCapabilities {
+    /// Whether the object store is on the same device as the repository
+    /// root, if that could be determined
+    pub same_device: Option<bool>,
+    /// Why files cannot be reflinked, if they cannot
+    pub reflink_error: Option<String>,
+}
+
+impl StorageCapabilities {
+    /// Whether stored objects can share blocks with the tracked files
+    pub fn shares_blocks(&self) -> bool {
+        self.reflink_error.is_none() && self.same_device != Some(false)
+    }
+
+    /// Ways copy-on-write copies silently degrade into full copies here
+    pub fn warnings(&self) -> Vec<String> {
+        let mut warnings = Vec::new();
+        if let Some(e) = &self.reflink_error {
+            warnings.push(format!(
+                "Reflinks are not supported on this file system ({e}); stored objects are full copies"
+            ));
+        }
+        if self.same_device == Some(false) {
+            warnings.push(
+                "The object store is on another device than the repository; stored objects cannot share blocks with tracked files"
+                    .to_string(),
+            );
+        }
+        warnings
+    }
+}
+
 #[derive(Default, Clone)]
 pub struct Repository {
     repo_root: PathBuf,
@@ -105,14 +139,201 @@ impl Repository {
         Ok(())
     }
 
+    /// Take an exclusive lock on the repository for the lifetime of the
+    /// returned file. The OS releases it if the process dies.
+    pub fn lock(&self) -> Result<fs::File> {
+        let lock_path = self.repo_root.join(".ddrive").join("lock");
+        let file = fs::OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&lock_path)?;
+        match file.try_lock() {
+            Ok(()) => Ok(file),
+            Err(fs::TryLockError::WouldBlock) => Err(DdriveError::Repository {
+                message: "another ddrive process is modifying this repository".to_string(),
+            }),
+            Err(fs::TryLockError::Error(e)) => Err(e.into()),
+        }
+    }
+
+    /// Remove files left in the staging directory by an interrupted add.
+    /// Must only run while holding the repository lock.
+    pub fn clear_tmp_dir(&self) -> Result<usize> {
+        let tmp_dir = self.tmp_dir();
+        if !tmp_dir.exists() {
+            return Ok(0);
+        }
+        let mut removed = 0;
+        for entry in fs::read_dir(&tmp_dir)? {
+            let entry = entry?;
+            if entry.file_type()?.is_file() {
+                fs::remove_file(entry.path())?;
+                removed += 1;
+            }
+        }
+        if removed > 0 {
+            debug!(
+                "Removed {} staged objects from {}",
+                removed,
+                tmp_dir.display()
+            );
+        }
+        Ok(removed)
+    }
+
+    /// Directory corrupted files found by one verify run are moved to
+    pub fn quarantine_dir(&self, action_id: i64) -> PathBuf {
+        self.repo_root
+            .join(".ddrive")
+            .join("quarantine")
+            .join(action_id.to_string())
+    }
+
+    /// Directory orphaned objects removed by one prune run are moved to. They
+    /// are deleted for good once they have been there for the grace period.
+    pub fn trash_dir(&self, action_id: i64) -> PathBuf {
+        self.repo_root
+            .join(".ddrive")
+            .join("trash")
+            .join(action_id.to_string())
+    }
+
+    /// Objects trashed by prune runs before `cutoff_timestamp`, with their sizes
+    pub fn expired_trash(&self, cutoff_timestamp: i64) -> Result<Vec<(PathBuf, u64)>> {
+        let mut objects = Vec::new();
+        for (action_id, run_dir) in self.trash_runs()? {
+            if action_id < cutoff_timestamp {
+                objects.extend(self.trash_run_objects(&run_dir)?);
+            }
+        }
+        Ok(objects)
+    }
+
+    /// Permanently delete the objects trashed by prune runs before
+    /// `cutoff_timestamp`, returning them with their sizes
+    pub fn empty_trash(&self, cutoff_timestamp: i64) -> Result<Vec<(PathBuf, u64)>> {
+        let mut objects = Vec::new();
+        for (action_id, run_dir) in self.trash_runs()? {
+            if action_id < cutoff_timestamp {
+                objects.extend(self.remove_trash_run(&run_dir)?);
+            }
+        }
+        Ok(objects)
+    }
+
+    /// Trash directories of past prune runs with their action IDs, oldest first
+    pub fn trash_runs(&self) -> Result<Vec<(i64, PathBuf)>> {
+        let trash = self.repo_root.join(".ddrive").join("trash");
+        if !trash.exists() {
+            return Ok(Vec::new());
+        }
+        let mut runs = Vec::new();
+        for entry in fs::read_dir(&trash)? {
+            let entry = entry?;
+            let action_id = entry
+                .file_name()
+                .to_str()
+                .and_then(|n| n.parse::<i64>().ok());
+            if let Some(action_id) = action_id
+                && entry.file_type()?.is_dir()
+            {
+                runs.push((action_id, entry.path()));
+            }
+        }
+        runs.sort();
+        Ok(runs)
+    }
+
+    /// Permanently delete one prune run's trash directory, returning the
+    /// objects it held with their sizes
+    pub fn remove_trash_run(&self, run_dir: &Path) -> Result<Vec<(PathBuf, u64)>> {
+        let objects = self.trash_run_objects(run_dir)?;
+        fs::remove_dir_all(run_dir)?;
+        Ok(objects)
+    }
+
+    /// Objects in one prune run's trash directory, with their sizes
+    pub fn trash_run_objects(&self, run_dir: &Path) -> Result<Vec<(PathBuf, u64)>> {
+        let mut objects = Vec::new();
+        for entry in fs::read_dir(run_dir)? {
+            let entry = entry?;
+            let metadata = entry.metadata()?;
+            if metadata.is_file() {
+                objects.push((entry.path(), metadata.len()));
+            }
+        }
+        objects.sort();
+        Ok(objects)
+    }
+
+    /// Staging directory for objects being written, on the same filesystem as
+    /// the object store so finished objects can be renamed into place
+    pub fn tmp_dir(&self) -> PathBuf {
+        self.repo_root.join(".ddrive").join("tmp")
+    }
+
+    /// The metadata database
+    pub fn database_path(&self) -> PathBuf {
+        self.repo_root.join(".ddrive").join("metadata.sqlite3")
+    }
+
+    /// Root of the object store
+    pub fn objects_root(&self) -> PathBuf {
+        self.repo_root.join(".ddrive").join("objects")
+    }
+
+    /// Test whether files can be reflinked in the repository and whether the
+    /// object store shares a device with the tracked files. Writes and removes
+    /// a small probe file in the staging directory.
+    pub fn probe_storage(&self) -> StorageCapabilities {
+        let device = |path: &Path| {
+            fs::metadata(path)
+                .ok()
+                .and_then(|metadata| file_identity(&metadata))
+                .map(|(dev, _)| dev)
+        };
+        let same_device = device(&self.repo_root)
+            .zip(device(&self.objects_root()))
+            .map(|(root, objects)| root == objects);
+
+        let tmp_dir = self.tmp_dir();
+        let source = tmp_dir.join(format!("probe-{}", std::process::id()));
+        let target = source.with_extension("reflink");
+        let probe = fs::create_dir_all(&tmp_dir)
+            .and_then(|()| fs::write(&source, b"ddrive reflink probe"))
+            .and_then(|()| reflink_copy::reflink(&source, &target));
+        let _ = fs::remove_file(&source);
+        let _ = fs::remove_file(&target);
+
+        StorageCapabilities {
+            same_device,
+            reflink_error: probe.err().map(|e| e.to_string()),
+        }
+    }
+
+    /// Whether the file system under the repository ignores the case of
+    /// names (the default on macOS and Windows), tested by looking up
+    /// `.ddrive` under another case
+    pub fn is_case_insensitive(&self) -> bool {
+        let identity = |path: &Path| {
+            fs::metadata(path)
+                .ok()
+                .map(|metadata| file_identity(&metadata))
+        };
+        match (
+            identity(&self.repo_root.join(".ddrive")),
+            identity(&self.repo_root.join(".DDRIVE")),
+        ) {
+            (Some(lower), Some(upper)) => lower == upper,
+            _ => false,
+        }
+    }
+
     pub fn object_dir(&self, checksum: &str) -> PathBuf {
         // Create object store directory structure (first 2 chars / next 2 chars)
         let prefix1 = &checksum[0..2];
         let prefix2 = &checksum[2..4];
-        self.repo_root
-            .join(".ddrive")
-            .join("objects")
-            .join(prefix1)
-            .join(prefix2)
+        self.objects_root().join(prefix1).join(prefix2)
     }
 }
@@ -1,32 +1,318 @@
-use crate::Result;
+use crate::{
+    Result,
+    checksum::SecondaryChecksums,
+    codes,
+    config::ScanConfig,
+    metadata::{FileAttributes, change_time_ns, file_identity},
+};
 use chrono::NaiveDateTime;
-use ignore::WalkBuilder;
+use ignore::{
+    Match, WalkBuilder,
+    gitignore::{Gitignore, GitignoreBuilder},
+};
+use std::ffi::OsStr;
+use std::fmt;
 use std::path::{Path, PathBuf};
 use std::time::{Instant, SystemTime};
 use tracing::{debug, warn};
 
+/// Per-directory ignore file, in gitignore syntax, read alongside `.ignore`
+pub const IGNORE_FILE: &str = ".ddriveignore";
+
+/// Directory of repository data, excluded from every scan
+const REPOSITORY_DIR: &str = ".ddrive";
+
+/// Version control metadata directories, excluded from every scan like
+/// `.ddrive` whatever `scan.include_hidden` says
+const VCS_DIRS: [&str; 3] = [".git", ".hg", ".svn"];
+
 pub struct FileScanner {
     repo_root: PathBuf,
+    config: ScanConfig,
 }
 
 impl FileScanner {
     pub fn new(repo_root: PathBuf) -> Self {
-        FileScanner { repo_root }
+        Self::with_config(repo_root, ScanConfig::default())
+    }
+
+    /// Create a scanner that applies the configured size and extension filters
+    pub fn with_config(repo_root: PathBuf, config: ScanConfig) -> Self {
+        FileScanner { repo_root, config }
     }
 
     /// Recursively scan directory structure and return paths
     pub fn get_all_files(&self, path: &PathBuf) -> Result<Vec<FileInfo>> {
+        Ok(self.scan(path)?.files)
+    }
+
+    /// Recursively scan directory structure, also reporting the files and
+    /// directories that could not be read
+    pub fn scan(&self, path: &PathBuf) -> Result<Scan> {
         let instant = Instant::now();
-        let file_paths: Vec<_> = get_all_files(&self.repo_root, path, false, true)?;
+        if self.config.same_file_system && !same_device(&self.repo_root, path) {
+            warn!(
+                "Skipping {}: it is on a different file system than the repository",
+                path.display()
+            );
+            return Ok(Scan::default());
+        }
+
+        let mut scan = walk(&self.repo_root, path, WalkFilter::for_scan(&self.config));
+        scan.files
+            .retain(|file| self.config.allows(&file.path, file.size));
 
         debug!(
             "Found {} files in {}ms",
-            file_paths.len(),
+            scan.files.len(),
+            instant.elapsed().as_millis()
+        );
+
+        Ok(scan)
+    }
+
+    /// Explain why a scan skips `path` (absolute, inside the repository), or
+    /// return `None` if it would be found. A path that does not exist is
+    /// judged as a file by its name alone, without the size limits.
+    pub fn skip_reason(&self, path: &Path) -> Result<Option<SkipReason>> {
+        let relative = path.strip_prefix(&self.repo_root).unwrap_or(path);
+        let metadata = match std::fs::metadata(path) {
+            Ok(metadata) => Some(metadata),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+            Err(e) => return Err(e.into()),
+        };
+        let is_dir = metadata.as_ref().is_some_and(|metadata| metadata.is_dir());
+        if in_repository_dir(relative, is_dir) {
+            return Ok(Some(SkipReason::RepositoryData));
+        }
+        if in_vcs_dir(relative, is_dir) {
+            return Ok(Some(SkipReason::VcsMetadata));
+        }
+        match self.ignore_rule(path, is_dir) {
+            Some(rule) if !rule.whitelist => {
+                return Ok(Some(SkipReason::IgnoreFile {
+                    file: rule.file,
+                    pattern: rule.pattern,
+                }));
+            }
+            // A negated pattern keeps hidden files too, as in the walker
+            Some(_) => {}
+            None if !self.config.include_hidden && is_hidden(relative) => {
+                return Ok(Some(SkipReason::ScanConfig(
+                    "hidden, and scan.include_hidden is off".to_string(),
+                )));
+            }
+            None => {}
+        }
+
+        let reason = match &metadata {
+            Some(metadata) if metadata.is_file() => {
+                self.config.filter_reason(relative, metadata.len())
+            }
+            Some(_) => None,
+            None => self.config.extension_filter_reason(relative),
+        };
+        if let Some(reason) = reason {
+            return Ok(Some(SkipReason::ScanConfig(reason)));
+        }
+        if metadata.is_some() && self.config.same_file_system && !same_device(&self.repo_root, path)
+        {
+            return Ok(Some(SkipReason::OtherFileSystem));
+        }
+        Ok(None)
+    }
+
+    /// The ignore file pattern deciding whether `path` is ignored, if any
+    /// matches it or one of its parents. Ignore files are checked from the
+    /// path's directory up to the repository root, with the walker's
+    /// precedence: `.ddriveignore` over `.ignore` over `.gitignore`, and
+    /// deeper files over shallower ones. `.gitignore` files count only with
+    /// `scan.respect_gitignore`.
+    pub fn ignore_rule(&self, path: &Path, is_dir: bool) -> Option<IgnoreRule> {
+        let in_git_repo = self.config.respect_gitignore
+            && self
+                .repo_root
+                .ancestors()
+                .any(|dir| dir.join(".git").exists());
+        let ignore_files = [IGNORE_FILE, ".ignore", ".gitignore"]
+            .into_iter()
+            .filter(|name| in_git_repo || *name != ".gitignore");
+        let dirs: Vec<&Path> = path
+            .ancestors()
+            .skip(1)
+            .take_while(|dir| dir.starts_with(&self.repo_root))
+            .collect();
+        for name in ignore_files {
+            for dir in &dirs {
+                let ignore_path = dir.join(name);
+                if !ignore_path.is_file() {
+                    continue;
+                }
+                let ignore = read_ignore_file(dir, &ignore_path);
+                let (glob, whitelist) = match ignore.matched_path_or_any_parents(path, is_dir) {
+                    Match::None => continue,
+                    Match::Whitelist(glob) => (glob, true),
+                    Match::Ignore(glob) => (glob, false),
+                };
+                let file = ignore_path
+                    .strip_prefix(&self.repo_root)
+                    .unwrap_or(&ignore_path);
+                return Some(IgnoreRule {
+                    file: file.to_path_buf(),
+                    pattern: glob.original().to_string(),
+                    whitelist,
+                });
+            }
+        }
+        None
+    }
+
+    /// Stat exactly the listed files (absolute paths inside the repository)
+    /// without walking directories. Missing, non-regular and filtered files
+    /// are skipped with a warning.
+    pub fn get_files(&self, paths: &[PathBuf]) -> Scan {
+        let instant = Instant::now();
+        let mut scan = Scan::default();
+        for path in paths {
+            let relative = path.strip_prefix(&self.repo_root).unwrap_or(path);
+            if in_repository_dir(relative, false) {
+                warn!(
+                    "Skipping {}: repository data is never tracked",
+                    path.display()
+                );
+                continue;
+            }
+            if in_vcs_dir(relative, false) {
+                warn!(
+                    "Skipping {}: version control metadata is never tracked",
+                    path.display()
+                );
+                continue;
+            }
+            let metadata = match std::fs::metadata(path) {
+                Ok(metadata) => metadata,
+                Err(e) if e.kind() == std::io::ErrorKind::PermissionDenied => {
+                    warn!(code = %codes::UNREADABLE_FILES, "Cannot read {}: permission denied", path.display());
+                    scan.unreadable.push(UnreadablePath {
+                        path: relative.to_path_buf(),
+                        message: e.to_string(),
+                    });
+                    continue;
+                }
+                Err(e) => {
+                    warn!("Skipping {}: {}", path.display(), e);
+                    continue;
+                }
+            };
+            if !metadata.is_file() {
+                warn!("Skipping {}: not a regular file", path.display());
+                continue;
+            }
+            if self.config.same_file_system && !same_device(&self.repo_root, path) {
+                warn!(
+                    "Skipping {}: it is on a different file system",
+                    path.display()
+                );
+                continue;
+            }
+            let Ok(modified) = metadata.modified() else {
+                continue;
+            };
+            let file = FileInfo::new(relative, &metadata, modified, metadata.created().ok());
+            if self.config.allows(&file.path, file.size) {
+                scan.files.push(file);
+            }
+        }
+
+        debug!(
+            "Found {} of {} listed files in {}ms",
+            scan.files.len(),
+            paths.len(),
             instant.elapsed().as_millis()
         );
+        scan
+    }
+}
+
+/// A pattern in an ignore file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IgnoreRule {
+    /// Ignore file, relative to the repository root
+    pub file: PathBuf,
+    pub pattern: String,
+    /// Whether the pattern is negated with `!`, keeping the path
+    pub whitelist: bool,
+}
+
+impl fmt::Display for IgnoreRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "'{}' in {}", self.pattern, self.file.display())
+    }
+}
+
+/// Why a scan skips a path
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SkipReason {
+    /// A `.ddrive` directory, of the repository or one nested in it, which
+    /// is never tracked
+    RepositoryData,
+    /// A `.git`, `.hg` or `.svn` directory, which is never tracked
+    VcsMetadata,
+    /// A pattern in an ignore file
+    IgnoreFile {
+        /// Ignore file, relative to the repository root
+        file: PathBuf,
+        pattern: String,
+    },
+    /// A `[scan]` setting
+    ScanConfig(String),
+    /// On another file system than the repository, with `scan.same_file_system`
+    OtherFileSystem,
+}
+
+impl fmt::Display for SkipReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::RepositoryData => write!(f, "it is repository data in .ddrive (always excluded)"),
+            Self::VcsMetadata => write!(f, "it is version control metadata (always excluded)"),
+            Self::IgnoreFile { file, pattern } => {
+                write!(f, "it matches '{}' in {}", pattern, file.display())
+            }
+            Self::ScanConfig(reason) => write!(f, "the config excludes it: {reason}"),
+            Self::OtherFileSystem => write!(
+                f,
+                "it is on another file system and scan.same_file_system is set"
+            ),
+        }
+    }
+}
 
-        Ok(file_paths)
+/// Parse one ignore file, logging rather than failing on bad patterns as
+/// the walker does
+fn read_ignore_file(dir: &Path, ignore_path: &Path) -> Gitignore {
+    let mut builder = GitignoreBuilder::new(dir);
+    if let Some(e) = builder.add(ignore_path) {
+        warn!(code = %codes::IGNORE_FILE, "Error in {}: {}", ignore_path.display(), e);
     }
+    builder.build().unwrap_or_else(|e| {
+        warn!(code = %codes::IGNORE_FILE, "Error in {}: {}", ignore_path.display(), e);
+        Gitignore::empty()
+    })
+}
+
+#[derive(Debug, Default)]
+pub struct Scan {
+    pub files: Vec<FileInfo>,
+    /// Files and directories skipped because permission to read them was denied
+    pub unreadable: Vec<UnreadablePath>,
+}
+
+/// A file or directory the scan was not allowed to read
+#[derive(Debug, Clone)]
+pub struct UnreadablePath {
+    /// Path relative to the repository root
+    pub path: PathBuf,
+    pub message: String,
 }
 
 #[derive(Debug, Clone)]
@@ -34,13 +320,56 @@ pub struct FileInfo {
     pub path: PathBuf,
     pub size: u64,
     pub modified: SystemTime,
-    pub created: SystemTime,
+    /// Birth time, if the filesystem records it
+    pub created: Option<SystemTime>,
     pub b3sum: Option<String>,
+    /// Permission, ownership and xattr snapshot, filled in when a file is recorded
+    pub attributes: FileAttributes,
+    /// Device number (`st_dev`)
+    pub dev: Option<u64>,
+    /// Inode number (`st_ino`); equal (dev, ino) pairs are hardlinks of one file
+    pub ino: Option<u64>,
+    /// Optional SHA-256/xxh3 checksums, filled in when a file is recorded
+    pub secondary: SecondaryChecksums,
+    /// Whether the object store holds a copy, filled in when a file is recorded
+    pub stored: bool,
+    /// Inode change time in nanoseconds since the Unix epoch (UTC), if known
+    pub ctime_ns: Option<i64>,
+    /// MIME type detected from the content, filled in when a file is recorded
+    pub content_type: Option<String>,
 }
 
 impl FileInfo {
+    fn new(
+        path: &Path,
+        metadata: &std::fs::Metadata,
+        modified: SystemTime,
+        created: Option<SystemTime>,
+    ) -> Self {
+        let identity = file_identity(metadata);
+        FileInfo {
+            path: path.to_path_buf(),
+            size: metadata.len(),
+            modified,
+            created,
+            b3sum: None,
+            attributes: FileAttributes::default(),
+            dev: identity.map(|(dev, _)| dev),
+            ino: identity.map(|(_, ino)| ino),
+            secondary: SecondaryChecksums::default(),
+            stored: false,
+            ctime_ns: change_time_ns(metadata),
+            content_type: None,
+        }
+    }
+
+    /// (device, inode) pair identifying the underlying file, if known
+    pub fn inode_key(&self) -> Option<(u64, u64)> {
+        self.dev.zip(self.ino)
+    }
+
     pub fn created_at(&self) -> Option<NaiveDateTime> {
-        self.created
+        self.created?
             .duration_since(std::time::UNIX_EPOCH)
             .ok()
             .and_then(|duration| {
@@ -49,6 +378,14 @@ impl FileInfo {
             })
     }
 
+    /// Modification time in nanoseconds since the Unix epoch (UTC)
+    pub fn modified_ns(&self) -> Option<i64> {
+        self.modified
+            .duration_since(std::time::UNIX_EPOCH)
+            .ok()
+            .map(|duration| duration.as_nanos() as i64)
+    }
+
     pub fn modified_at(&self) -> Option<NaiveDateTime> {
         self.modified
             .duration_since(std::time::UNIX_EPOCH)
@@ -60,60 +397,563 @@ impl FileInfo {
     }
 }
 
+/// Whether `path` is in a `.ddrive` directory, or is one if `is_dir`
+fn in_repository_dir(path: &Path, is_dir: bool) -> bool {
+    in_dir_named(path, is_dir, |name| name == REPOSITORY_DIR)
+}
+
+/// Whether `path` is in a version control metadata directory, or is one if
+/// `is_dir`
+fn in_vcs_dir(path: &Path, is_dir: bool) -> bool {
+    in_dir_named(path, is_dir, is_vcs_dir)
+}
+
+fn is_vcs_dir(name: &OsStr) -> bool {
+    VCS_DIRS.iter().any(|dir| name == *dir)
+}
+
+/// Whether a directory of `path` has a name `matches` accepts, the last
+/// component counting only if `is_dir`
+fn in_dir_named(path: &Path, is_dir: bool, matches: impl Fn(&OsStr) -> bool) -> bool {
+    let mut components: Vec<_> = path.components().collect();
+    if !is_dir {
+        components.pop();
+    }
+    components
+        .iter()
+        .any(|component| matches(component.as_os_str()))
+}
+
+/// Whether any component of `path` is hidden, as the walker judges it
+fn is_hidden(path: &Path) -> bool {
+    path.components()
+        .any(|component| component.as_os_str().to_string_lossy().starts_with('.'))
+}
+
+/// Check whether two paths reside on the same device
+#[cfg(unix)]
+fn same_device(a: &Path, b: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    match (std::fs::metadata(a), std::fs::metadata(b)) {
+        (Ok(a), Ok(b)) => a.dev() == b.dev(),
+        _ => true,
+    }
+}
+
+#[cfg(not(unix))]
+fn same_device(_a: &Path, _b: &Path) -> bool {
+    true
+}
+
+/// Which files a walk skips
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct WalkFilter {
+    /// Skip hidden files and directories
+    pub hidden: bool,
+    /// Skip files matched by `.ddriveignore` and `.ignore` files
+    pub ignore_files: bool,
+    /// Skip files matched by `.gitignore` files and git's excludes, inside a
+    /// git work tree
+    pub gitignore: bool,
+    /// Do not cross mount points
+    pub same_file_system: bool,
+}
+
+impl WalkFilter {
+    /// Every file, as the object store is walked: objects are found whatever
+    /// the ignore files or the `[scan]` settings say
+    pub const ALL: Self = Self {
+        hidden: false,
+        ignore_files: false,
+        gitignore: false,
+        same_file_system: false,
+    };
+
+    /// The files a scan of the repository finds under `config`
+    pub fn for_scan(config: &ScanConfig) -> Self {
+        Self {
+            hidden: !config.include_hidden,
+            ignore_files: true,
+            gitignore: config.respect_gitignore,
+            same_file_system: config.same_file_system,
+        }
+    }
+}
+
+/// Walk `path` collecting the regular files `filter` keeps
 pub fn get_all_files<P: AsRef<Path>>(
     repo_root: P,
     path: P,
-    hidden: bool,
-    ignore: bool,
+    filter: WalkFilter,
 ) -> Result<Vec<FileInfo>> {
+    Ok(walk(repo_root, path, filter).files)
+}
+
+/// Walk `path` collecting regular files and the entries that could not be read
+fn walk<P: AsRef<Path>>(repo_root: P, path: P, filter: WalkFilter) -> Scan {
     let instant = Instant::now();
+    let repo_root = repo_root.as_ref();
     let path = path.as_ref();
+    let relative = |path: &Path| path.strip_prefix(repo_root).unwrap_or(path).to_path_buf();
 
-    let file_paths: Vec<_> = WalkBuilder::new(path)
+    let mut scan = Scan::default();
+    let mut builder = WalkBuilder::new(path);
+    builder
         .follow_links(false)
-        .hidden(hidden)
-        .ignore(ignore)
-        .build()
-        .filter_map(|entry| match entry {
-            Ok(entry) => {
-                let path = entry
-                    .path()
-                    .strip_prefix(&repo_root)
-                    .unwrap_or(entry.path());
-                let metadata = std::fs::metadata(path).ok()?;
-                let modified = metadata.modified().ok()?;
-                let created = metadata.created().ok()?; // Birth time/creation time
-                if metadata.is_file() {
-                    Some(FileInfo {
-                        path: path.to_path_buf(),
-                        size: metadata.len(),
-                        modified,
-                        created,
-                        b3sum: None,
-                    })
-                } else {
-                    None
+        .hidden(filter.hidden)
+        .ignore(filter.ignore_files)
+        .git_ignore(filter.gitignore)
+        .git_global(filter.gitignore)
+        .git_exclude(filter.gitignore)
+        .same_file_system(filter.same_file_system)
+        // In path order, so change detection can walk the files alongside
+        // the records
+        .sort_by_file_name(|a, b| a.cmp(b));
+    if filter.ignore_files {
+        builder.add_custom_ignore_filename(IGNORE_FILE);
+    }
+    // Repository metadata and object stores are never tracked: the
+    // repository's own, whatever path the walk starts from, and those of
+    // repositories nested in it. Neither is version control metadata.
+    builder.filter_entry(|entry| {
+        entry.depth() == 0
+            || (entry.file_name() != REPOSITORY_DIR && !is_vcs_dir(entry.file_name()))
+            || !entry
+                .file_type()
+                .is_some_and(|file_type| file_type.is_dir())
+    });
+    let walker = builder.build();
+    for entry in walker {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(e) => {
+                let denied = e
+                    .io_error()
+                    .is_some_and(|e| e.kind() == std::io::ErrorKind::PermissionDenied);
+                match error_path(&e) {
+                    Some(path) if denied => {
+                        warn!(code = %codes::UNREADABLE_FILES, "Cannot read {}: permission denied", path.display());
+                        scan.unreadable.push(UnreadablePath {
+                            path: relative(path),
+                            message: e.to_string(),
+                        });
+                    }
+                    _ => warn!("Error accessing path: {}", e),
                 }
+                continue;
             }
-            Err(e) => {
-                warn!("Error accessing path: {}", e);
-                None
+        };
+
+        let metadata = match std::fs::metadata(entry.path()) {
+            Ok(metadata) => metadata,
+            Err(e) if e.kind() == std::io::ErrorKind::PermissionDenied => {
+                warn!(code = %codes::UNREADABLE_FILES, "Cannot read {}: permission denied", entry.path().display());
+                scan.unreadable.push(UnreadablePath {
+                    path: relative(entry.path()),
+                    message: e.to_string(),
+                });
+                continue;
             }
-        })
-        .collect();
+            Err(_) => continue,
+        };
+        let Ok(modified) = metadata.modified() else {
+            continue;
+        };
+        // Birth time is unavailable on many filesystems (e.g. NFS); track the file anyway
+        let created = metadata.created().ok();
+        if metadata.is_file() {
+            let file = FileInfo::new(&relative(entry.path()), &metadata, modified, created);
+            scan.files.push(file);
+        }
+    }
 
     debug!(
         "Found {} files in {}ms",
-        file_paths.len(),
+        scan.files.len(),
         instant.elapsed().as_millis()
     );
 
-    Ok(file_paths)
+    scan
+}
+
+/// Path a walk error is about, if it names one
+fn error_path(error: &ignore::Error) -> Option<&Path> {
+    match error {
+        ignore::Error::WithPath { path, .. } => Some(path),
+        ignore::Error::WithDepth { err, .. } | ignore::Error::WithLineNumber { err, .. } => {
+            error_path(err)
+        }
+        _ => None,
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::config::TrackConfig;
+
+    #[test]
+    fn test_scan_config_size_filters() {
+        let config = ScanConfig {
+            min_file_size: Some(10),
+            max_file_size: Some(100),
+            ..Default::default()
+        };
+        assert!(!config.allows(Path::new("a.bin"), 5));
+        assert!(config.allows(Path::new("a.bin"), 50));
+        assert!(!config.allows(Path::new("a.bin"), 500));
+    }
+
+    #[test]
+    fn test_scan_config_extension_filters() {
+        let config = ScanConfig {
+            include_extensions: vec!["jpg".to_string(), ".MP4".to_string()],
+            exclude_extensions: vec!["tmp".to_string()],
+            ..Default::default()
+        };
+        assert!(config.allows(Path::new("photos/a.JPG"), 1));
+        assert!(config.allows(Path::new("videos/b.mp4"), 1));
+        assert!(!config.allows(Path::new("notes.txt"), 1));
+        assert!(!config.allows(Path::new("noextension"), 1));
+
+        let config = ScanConfig {
+            exclude_extensions: vec!["vmdk".to_string()],
+            ..Default::default()
+        };
+        assert!(config.allows(Path::new("notes.txt"), 1));
+        assert!(!config.allows(Path::new("vm/disk.vmdk"), 1));
+    }
+
+    #[test]
+    fn test_track_policy() {
+        let config = TrackConfig {
+            include: vec!["**/*.{raw,jpg,mp4}".to_string()],
+            exclude: vec!["**/.cache/**".to_string()],
+        };
+        let policy = config.policy().unwrap();
+        assert!(policy.allows("a.jpg"));
+        assert!(policy.allows("photos/2024/b.raw"));
+        assert!(!policy.allows("photos/notes.txt"));
+        assert!(!policy.allows(".cache/a.jpg"));
+        assert!(!policy.allows("app/.cache/thumbs/b.jpg"));
+
+        let everything = TrackConfig::default().policy().unwrap();
+        assert!(everything.allows("any/file.txt"));
+        assert!(
+            TrackConfig {
+                include: vec!["[".to_string()],
+                ..Default::default()
+            }
+            .policy()
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn test_ignored_files() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        std::fs::write(temp.path().join(IGNORE_FILE), "*.tmp\ncache/\n").unwrap();
+        std::fs::create_dir(temp.path().join("cache")).unwrap();
+        std::fs::write(temp.path().join("cache/a.jpg"), "a").unwrap();
+        std::fs::write(temp.path().join("b.tmp"), "b").unwrap();
+        std::fs::write(temp.path().join("c.jpg"), "c").unwrap();
+        std::fs::create_dir_all(temp.path().join(".ddrive/objects")).unwrap();
+        std::fs::write(temp.path().join(".ddrive/objects/d"), "d").unwrap();
+
+        let scanner = FileScanner::new(temp.path().to_path_buf());
+        let files = scanner.get_all_files(&temp.path().to_path_buf()).unwrap();
+        let mut paths: Vec<_> = files.iter().map(|file| file.path.clone()).collect();
+        paths.sort();
+        assert_eq!(
+            paths,
+            vec![PathBuf::from(IGNORE_FILE), PathBuf::from("c.jpg")]
+        );
+    }
+
+    #[test]
+    fn test_skip_reason() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        let root = temp.path().canonicalize().unwrap();
+        std::fs::write(root.join(IGNORE_FILE), "*.tmp\ncache/\n").unwrap();
+        std::fs::create_dir_all(root.join("cache")).unwrap();
+        std::fs::create_dir_all(root.join("docs")).unwrap();
+        std::fs::write(root.join("docs").join(IGNORE_FILE), "!keep.tmp\n").unwrap();
+        std::fs::create_dir_all(root.join(".ddrive/objects")).unwrap();
+        for file in [
+            "cache/a.jpg",
+            "b.tmp",
+            "docs/keep.tmp",
+            "c.jpg",
+            "d.iso",
+            ".ddrive/objects/e",
+        ] {
+            std::fs::write(root.join(file), "x").unwrap();
+        }
+
+        let config = ScanConfig {
+            exclude_extensions: vec!["iso".to_string()],
+            ..Default::default()
+        };
+        let scanner = FileScanner::with_config(root.clone(), config);
+        let reason = |file: &str| scanner.skip_reason(&root.join(file)).unwrap();
+        assert!(matches!(
+            reason("cache/a.jpg"),
+            Some(SkipReason::IgnoreFile { file, pattern }) if file == Path::new(IGNORE_FILE) && pattern == "cache/"
+        ));
+        assert!(matches!(
+            reason("b.tmp"),
+            Some(SkipReason::IgnoreFile { pattern, .. }) if pattern == "*.tmp"
+        ));
+        assert!(reason("docs/keep.tmp").is_none());
+        assert!(reason("c.jpg").is_none());
+        assert!(matches!(reason("d.iso"), Some(SkipReason::ScanConfig(_))));
+        assert!(matches!(
+            reason(".ddrive/objects/e"),
+            Some(SkipReason::RepositoryData)
+        ));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_same_file_system() {
+        // A directory on another file system stands in for a drive mounted
+        // in the repository; without one there is nothing to test
+        let temp = assert_fs::TempDir::new().unwrap();
+        let root = temp.path().canonicalize().unwrap();
+        let Ok(mount) = tempfile::TempDir::new_in("/dev/shm") else {
+            return;
+        };
+        if same_device(&root, mount.path()) {
+            return;
+        }
+        let file = mount.path().join("a.jpg");
+        std::fs::write(&file, "x").unwrap();
+
+        let crossing = FileScanner::new(root.clone());
+        assert_eq!(
+            crossing
+                .get_all_files(&mount.path().to_path_buf())
+                .unwrap()
+                .len(),
+            1
+        );
+        assert_eq!(
+            crossing.get_files(std::slice::from_ref(&file)).files.len(),
+            1
+        );
+        assert!(crossing.skip_reason(&file).unwrap().is_none());
+
+        let config = ScanConfig {
+            same_file_system: true,
+            ..Default::default()
+        };
+        let scanner = FileScanner::with_config(root.clone(), config);
+        assert!(
+            scanner
+                .get_all_files(&mount.path().to_path_buf())
+                .unwrap()
+                .is_empty()
+        );
+        assert!(
+            scanner
+                .get_files(std::slice::from_ref(&file))
+                .files
+                .is_empty()
+        );
+        assert_eq!(
+            scanner.skip_reason(&file).unwrap(),
+            Some(SkipReason::OtherFileSystem)
+        );
+        // Files on the repository's own file system are still found
+        std::fs::write(root.join("b.jpg"), "x").unwrap();
+        assert_eq!(scanner.get_all_files(&root).unwrap().len(), 1);
+    }
+
+    /// A git work tree with an ignored file, a hidden file and a hidden
+    /// directory, and the files a scan finds in it
+    fn scan_git_tree(respect_gitignore: bool, include_hidden: bool) -> Vec<String> {
+        let temp = assert_fs::TempDir::new().unwrap();
+        let root = temp.path().canonicalize().unwrap();
+        std::fs::create_dir_all(root.join(".git")).unwrap();
+        std::fs::create_dir_all(root.join(".config")).unwrap();
+        std::fs::write(root.join(".gitignore"), "build.log\n").unwrap();
+        std::fs::write(root.join(".git/HEAD"), "ref: refs/heads/main\n").unwrap();
+        for file in ["build.log", ".bashrc", ".config/app.toml", "notes.txt"] {
+            std::fs::write(root.join(file), "x").unwrap();
+        }
+
+        let config = ScanConfig {
+            respect_gitignore,
+            include_hidden,
+            ..Default::default()
+        };
+        let scanner = FileScanner::with_config(root.clone(), config);
+        let mut found: Vec<String> = scanner
+            .get_all_files(&root)
+            .unwrap()
+            .iter()
+            .map(|file| file.path.to_string_lossy().into_owned())
+            .filter(|path| path != ".gitignore")
+            .collect();
+        found.sort();
+
+        // skip_reason agrees with the walk
+        for file in ["build.log", ".bashrc", ".config/app.toml", "notes.txt"] {
+            let skipped = scanner.skip_reason(&root.join(file)).unwrap().is_some();
+            assert_eq!(skipped, !found.contains(&file.to_string()), "{file}");
+        }
+        found
+    }
+
+    #[test]
+    fn test_gitignore_and_hidden_settings() {
+        assert_eq!(
+            scan_git_tree(true, true),
+            vec![".bashrc", ".config/app.toml", "notes.txt"]
+        );
+        assert_eq!(
+            scan_git_tree(false, true),
+            vec![".bashrc", ".config/app.toml", "build.log", "notes.txt"]
+        );
+        assert_eq!(scan_git_tree(true, false), vec!["notes.txt"]);
+        assert_eq!(scan_git_tree(false, false), vec!["build.log", "notes.txt"]);
+    }
+
+    #[test]
+    fn test_nested_hidden_directories() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        let root = temp.path().canonicalize().unwrap();
+        let files = [
+            ".config/nvim/init.lua",
+            "photos/.thumbnails/a.jpg",
+            "a/.b/.c/d.txt",
+            "a/.b/e.txt",
+            "photos/beach.jpg",
+            ".ddrive/metadata.sqlite3",
+            "archive/.ddrive/objects/ab/cd/abcd",
+        ];
+        for file in files {
+            std::fs::create_dir_all(root.join(file).parent().unwrap()).unwrap();
+            std::fs::write(root.join(file), "x").unwrap();
+        }
+
+        let scan = |include_hidden: bool| {
+            let config = ScanConfig {
+                include_hidden,
+                ..Default::default()
+            };
+            let scanner = FileScanner::with_config(root.clone(), config);
+            let mut found: Vec<String> = scanner
+                .get_all_files(&root)
+                .unwrap()
+                .iter()
+                .map(|file| file.path.to_string_lossy().into_owned())
+                .collect();
+            found.sort();
+            for file in files {
+                let skipped = scanner.skip_reason(&root.join(file)).unwrap().is_some();
+                assert_eq!(skipped, !found.contains(&file.to_string()), "{file}");
+            }
+            // Naming repository data explicitly does not track it either
+            assert!(
+                scanner
+                    .get_files(&[root.join(".ddrive/metadata.sqlite3")])
+                    .files
+                    .is_empty()
+            );
+            found
+        };
+        assert_eq!(
+            scan(true),
+            vec![
+                ".config/nvim/init.lua",
+                "a/.b/.c/d.txt",
+                "a/.b/e.txt",
+                "photos/.thumbnails/a.jpg",
+                "photos/beach.jpg",
+            ]
+        );
+        assert_eq!(scan(false), vec!["photos/beach.jpg"]);
+
+        // The repository's data is excluded when scanning from a subdirectory
+        let scanner = FileScanner::new(root.clone());
+        let found = scanner.get_all_files(&root.join("archive")).unwrap();
+        assert!(found.is_empty());
+    }
+
+    #[test]
+    fn test_vcs_metadata_is_never_tracked() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        let root = temp.path().canonicalize().unwrap();
+        let files = [
+            ".git/HEAD",
+            ".git/objects/ab/cdef",
+            "code/.hg/store/data",
+            "code/.svn/entries",
+            "code/main.rs",
+        ];
+        for file in files {
+            std::fs::create_dir_all(root.join(file).parent().unwrap()).unwrap();
+            std::fs::write(root.join(file), "x").unwrap();
+        }
+        // Even an ignore file keeping them does not track them
+        std::fs::write(root.join(IGNORE_FILE), "!.git\n").unwrap();
+
+        let scanner = FileScanner::new(root.clone());
+        let found: Vec<PathBuf> = scanner
+            .get_all_files(&root)
+            .unwrap()
+            .into_iter()
+            .map(|file| file.path)
+            .filter(|path| path != Path::new(IGNORE_FILE))
+            .collect();
+        assert_eq!(found, vec![PathBuf::from("code/main.rs")]);
+        for file in &files[..4] {
+            assert_eq!(
+                scanner.skip_reason(&root.join(file)).unwrap(),
+                Some(SkipReason::VcsMetadata),
+                "{file}"
+            );
+        }
+        assert_eq!(
+            scanner.skip_reason(&root.join(".git")).unwrap(),
+            Some(SkipReason::VcsMetadata)
+        );
+        assert!(
+            scanner
+                .get_files(&[root.join(".git/HEAD")])
+                .files
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_object_store_walk_ignores_settings() {
+        // An object store inside a git work tree that ignores everything
+        let temp = assert_fs::TempDir::new().unwrap();
+        let root = temp.path();
+        std::fs::create_dir_all(root.join(".git")).unwrap();
+        std::fs::write(root.join(".gitignore"), "*\n").unwrap();
+        std::fs::write(root.join(IGNORE_FILE), "*\n").unwrap();
+        std::fs::create_dir_all(root.join("ab/cd")).unwrap();
+        std::fs::write(root.join("ab/cd/abcd1234"), "object").unwrap();
+        std::fs::write(root.join("ab/cd/.hidden"), "stray").unwrap();
+
+        let mut found: Vec<PathBuf> = get_all_files(root, root, WalkFilter::ALL)
+            .unwrap()
+            .into_iter()
+            .map(|file| file.path)
+            .filter(|path| path.starts_with("ab"))
+            .collect();
+        found.sort();
+        assert_eq!(
+            found,
+            vec![
+                PathBuf::from("ab/cd/.hidden"),
+                PathBuf::from("ab/cd/abcd1234")
+            ]
+        );
+    }
 
     #[test]
     fn test_scan_directory_nonexistent() {
@@ -1,26 +1,52 @@
-use std::collections::HashMap;
-use std::path::PathBuf;
-use std::time::Instant;
+use futures_util::{Stream, TryStreamExt};
+use std::borrow::{Borrow, Cow};
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::pin::{Pin, pin};
+use std::time::{Duration, Instant};
 use std::{collections::HashSet, time::UNIX_EPOCH};
 use tracing::{debug, warn};
+use unicode_normalization::{IsNormalized, UnicodeNormalization, is_nfc_quick};
 use unicode_segmentation::UnicodeSegmentation;
 
 use crate::{
-    AppContext, Result, checksum::ChecksumCalculator, database::FileRecord, scanner::FileInfo,
+    AppContext, DdriveError, Result,
+    checksum::{ChecksumCalculator, HashAlgorithm, SecondaryChecksums},
+    config::{ChangeDetection, PerformanceConfig},
+    database::FileRecord,
+    hash_cache::HashCache,
+    scanner::FileInfo,
 };
+use clap::ValueEnum;
 use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use strum::Display;
 
 /// Shared utilities for file processing operations
 pub struct FileProcessor<'a> {
     context: &'a AppContext,
     checksum_calculator: ChecksumCalculator,
+    hash_cache: HashCache,
+    /// Threads hashing files, instead of rayon's global pool
+    pool: Option<rayon::ThreadPool>,
 }
 
 impl<'a> FileProcessor<'a> {
     pub fn new(context: &'a AppContext) -> Self {
         Self {
             context,
-            checksum_calculator: ChecksumCalculator::new(),
+            checksum_calculator: ChecksumCalculator::from_config(&context.config.checksum),
+            hash_cache: HashCache::default(),
+            pool: hash_pool(&context.config.performance),
+        }
+    }
+
+    /// Run `op` on the hashing threads, so that parallel iterators and the
+    /// multi-threaded hashing of large files inside it use them
+    fn install<R: Send>(&self, op: impl FnOnce() -> R + Send) -> R {
+        match &self.pool {
+            Some(pool) => pool.install(op),
+            None => op(),
         }
     }
 
@@ -43,21 +69,23 @@ impl<'a> FileProcessor<'a> {
             .collect();
 
         // Calculate checksums for remaining files in parallel
-        let calculated_results: Vec<_> = files_needing_calculation
-            .par_iter()
-            .filter_map(
-                |file| match self.checksum_calculator.calculate_checksum(&file.path) {
-                    Ok(checksum) => {
-                        let file_path_str = file.path.to_string_lossy().into_owned();
-                        Some((file_path_str, checksum, file.size as i64))
-                    }
-                    Err(e) => {
-                        warn!("Checksum error for {}: {}", file.path.display(), e);
-                        None
-                    }
-                },
-            )
-            .collect();
+        let calculated_results: Vec<_> = self.install(|| {
+            files_needing_calculation
+                .par_iter()
+                .filter_map(
+                    |file| match self.checksum_calculator.calculate_checksum(&file.path) {
+                        Ok(checksum) => {
+                            let file_path_str = file.path.to_string_lossy().into_owned();
+                            Some((file_path_str, checksum, file.size as i64))
+                        }
+                        Err(e) => {
+                            warn!("Checksum error for {}: {}", file.path.display(), e);
+                            None
+                        }
+                    },
+                )
+                .collect()
+        });
 
         results.extend(calculated_results);
 
@@ -72,222 +100,1192 @@ impl<'a> FileProcessor<'a> {
         results
     }
 
-    /// Internal method that handles both lightweight and full change detection
-    pub async fn detect_changes(
+    /// Compare scanned files against tracked records, reporting each difference
+    /// through `on_change`.
+    ///
+    /// Both sides are walked together in path order, as the scanner and the
+    /// database return them, so neither is loaded whole: a file and its
+    /// record are compared as soon as both have been seen. Entries that arrive
+    /// out of order wait until their counterpart does; whatever is unmatched
+    /// at the end is a new or deleted file, or half of a rename.
+    pub async fn detect_changes<'s, R, F>(
         &self,
-        scanned_files: &[FileInfo],
-        tracked_files: &[FileRecord],
+        scanned_files: impl IntoIterator<Item = &'s FileInfo>,
+        tracked_files: impl Stream<Item = Result<R>>,
         use_checksums: bool,
-    ) -> Result<(
-        Vec<FileInfo>,
-        Vec<FileInfo>,
-        Vec<FileInfo>,
-        Vec<(FileInfo, FileInfo)>,
-    )> {
-        let mut new_files = Vec::new();
-        let mut changed_files = Vec::new();
-        let mut deleted_files = Vec::new();
-
-        // Build a hash map of scanned paths for quick lookups (avoid cloning paths)
-        let scanned_paths: HashMap<&PathBuf, &FileInfo> = scanned_files
-            .iter()
-            .map(|file| (&file.path, file))
-            .collect();
+        mut on_change: F,
+    ) -> Result<()>
+    where
+        R: Borrow<FileRecord>,
+        F: FnMut(FileChange<'s>),
+    {
+        self.hash_cache.load(&self.context.database).await?;
+
+        // Paths are compared in their stored form so decomposed names and
+        // native separators match their records
+        let mut scanned = scanned_files.into_iter().map(with_storage_path).peekable();
+        let mut tracked = pin!(tracked_files);
+        let mut record = next_record(&mut tracked).await?;
+        let mut unmatched_scanned: BTreeMap<String, &'s FileInfo> = BTreeMap::new();
+        let mut unmatched_tracked: BTreeMap<String, R> = BTreeMap::new();
+        loop {
+            let scanned_first = match (scanned.peek(), &record) {
+                (None, None) => break,
+                (Some((path, _)), Some((record_path, _))) => path <= record_path,
+                (next_scanned, _) => next_scanned.is_some(),
+            };
+            if scanned_first {
+                let Some((path, file)) = scanned.next() else {
+                    break;
+                };
+                let matched = match record.take_if(|(record_path, _)| *record_path == path) {
+                    Some((_, matched)) => {
+                        record = next_record(&mut tracked).await?;
+                        Some(matched)
+                    }
+                    None => unmatched_tracked.remove(&path),
+                };
+                match matched {
+                    Some(matched) => {
+                        self.report_change(file, matched.borrow(), use_checksums, &mut on_change)?
+                    }
+                    None => {
+                        unmatched_scanned.insert(path, file);
+                    }
+                }
+            } else if let Some((path, matched)) = record.take() {
+                record = next_record(&mut tracked).await?;
+                match unmatched_scanned.remove(&path) {
+                    Some(file) => {
+                        self.report_change(file, matched.borrow(), use_checksums, &mut on_change)?
+                    }
+                    None => {
+                        unmatched_tracked.insert(path, matched);
+                    }
+                }
+            }
+        }
 
-        // Find deleted files (avoid creating PathBuf for each lookup)
-        for tracked_file in tracked_files {
-            let tracked_path = PathBuf::from(&tracked_file.path);
-            if !scanned_paths.contains_key(&tracked_path) {
-                deleted_files.push(tracked_file.into());
+        // On case-insensitive file systems a record under another case than
+        // an untracked scanned file is that file, renamed to a new case
+        let case_insensitive = self
+            .context
+            .config
+            .changes
+            .case_insensitive
+            .unwrap_or_else(|| self.context.repo.is_case_insensitive());
+        if case_insensitive && !unmatched_tracked.is_empty() {
+            let folded: HashMap<String, &String> = unmatched_tracked
+                .keys()
+                .map(|path| (path.to_lowercase(), path))
+                .collect();
+            let case_renames: Vec<(String, String)> = unmatched_scanned
+                .keys()
+                .filter_map(|path| {
+                    let record_path = folded.get(&path.to_lowercase())?;
+                    Some((path.clone(), (*record_path).clone()))
+                })
+                .collect();
+            drop(folded);
+            for (path, record_path) in case_renames {
+                let (Some(file), Some(matched)) = (
+                    unmatched_scanned.remove(&path),
+                    unmatched_tracked.remove(&record_path),
+                ) else {
+                    continue;
+                };
+                let matched = matched.borrow();
+                on_change(FileChange::Renamed {
+                    from: FileInfo::from(matched),
+                    to: file,
+                    b3sum: Some(matched.b3sum.clone()),
+                    // The file system resolves both names to this file
+                    confidence: RenameConfidence::Inode,
+                });
+                self.report_change(file, matched, use_checksums, &mut on_change)?;
             }
         }
 
-        // Create a lookup map from tracked files for O(1) access (avoid database calls)
-        let tracked_lookup: HashMap<&str, &FileRecord> = tracked_files
-            .iter()
-            .map(|record| (record.path.as_str(), record))
+        let deleted_files: Vec<FileInfo> = unmatched_tracked
+            .into_values()
+            .map(|record| FileInfo::from(record.borrow()))
             .collect();
+        let new_files: Vec<&'s FileInfo> = unmatched_scanned.into_values().collect();
 
-        // Process scanned files using the lookup map
-        for file in scanned_files {
-            let file_path_str = file.path.to_string_lossy();
-            match tracked_lookup.get(file_path_str.as_ref()) {
-                Some(record) => {
-                    let modified_time = file
-                        .modified
-                        .duration_since(std::time::UNIX_EPOCH)
-                        .map_err(|e| crate::DdriveError::FileSystem {
-                            message: format!("Invalid modification time: {e:?}"),
-                        })?
-                        .as_secs();
-
-                    // Skip if size and time haven't changed
-                    if file.size == record.size as u64
-                        && modified_time <= record.updated_at.and_utc().timestamp() as u64
-                    {
-                        continue;
-                    }
-
-                    if use_checksums {
-                        // Reuse existing checksum if available, otherwise calculate
-                        let current_checksum = if let Some(ref existing_checksum) = file.b3sum {
-                            existing_checksum.clone()
-                        } else {
-                            self.checksum_calculator.calculate_checksum(&file.path)?
-                        };
-
-                        if current_checksum != record.b3sum {
-                            let mut changed_file = file.clone();
-                            changed_file.b3sum = Some(current_checksum);
-                            changed_files.push(changed_file);
-                        }
-                    } else {
-                        // For lightweight mode, assume file changed if size/time differs
-                        let mut changed_file = file.clone();
-                        changed_file.b3sum = None;
-                        changed_files.push(changed_file);
-                    }
-                }
-                None => {
-                    new_files.push(file.clone());
-                }
+        // Inode matches are the strongest rename signal, so pair those first and
+        // only fall back to checksum or metadata heuristics for the remainder
+        let mut renames = match_renames_by_inode(&deleted_files, &new_files);
+        if !renames.is_empty() {
+            debug!("Matched {} renames by inode", renames.len());
+        }
+        let matched_deleted: HashSet<usize> = renames.iter().map(|r| r.deleted).collect();
+        let matched_new: HashSet<usize> = renames.iter().map(|r| r.new).collect();
+        let remaining_deleted: Vec<usize> = (0..deleted_files.len())
+            .filter(|i| !matched_deleted.contains(i))
+            .collect();
+        let remaining_new: Vec<usize> = (0..new_files.len())
+            .filter(|i| !matched_new.contains(i))
+            .collect();
+        renames.extend(self.find_heuristic_renames(
+            &deleted_files,
+            &remaining_deleted,
+            &new_files,
+            &remaining_new,
+            use_checksums,
+        )?);
+
+        // Report renames, then the unmatched new and deleted files
+        let mut deleted_files: Vec<Option<FileInfo>> =
+            deleted_files.into_iter().map(Some).collect();
+        let mut renamed_new = HashSet::with_capacity(renames.len());
+        for rename in renames {
+            renamed_new.insert(rename.new);
+            if let Some(from) = deleted_files[rename.deleted].take() {
+                on_change(FileChange::Renamed {
+                    from,
+                    to: new_files[rename.new],
+                    b3sum: rename.b3sum,
+                    confidence: rename.confidence,
+                });
+            }
+        }
+        for (index, file) in new_files.into_iter().enumerate() {
+            if !renamed_new.contains(&index) {
+                on_change(FileChange::New(file));
             }
         }
+        for file in deleted_files.into_iter().flatten() {
+            on_change(FileChange::Deleted(file));
+        }
+
+        self.flush_hash_cache().await
+    }
+
+    /// Report `file` as changed if it differs from its record
+    fn report_change<'s>(
+        &self,
+        file: &'s FileInfo,
+        record: &FileRecord,
+        use_checksums: bool,
+        on_change: &mut impl FnMut(FileChange<'s>),
+    ) -> Result<()> {
+        // Skip files whose metadata shows no change
+        if !self.metadata_changed(file, record, use_checksums)? {
+            return Ok(());
+        }
+
+        if use_checksums {
+            // Reuse existing checksum if available, otherwise calculate
+            let current_checksum = self.checksum_for(file)?;
 
-        // Detect potential renames based on metadata
-        let potential_renames = if use_checksums {
-            // Full rename detection with checksums
-            let new_files_with_checksums = self.ensure_checksums_for_files(&new_files).await?;
-            self.context
-                .database
-                .find_potential_renames(&deleted_files, &new_files_with_checksums)
-                .await?
+            if current_checksum != record.b3sum {
+                on_change(FileChange::Changed {
+                    file,
+                    b3sum: Some(current_checksum),
+                });
+            }
         } else {
-            // Lightweight rename detection based on size and modification time
-            self.find_potential_renames_by_metadata(&deleted_files, &new_files)
-        };
+            // For lightweight mode, assume file changed if size/time differs
+            on_change(FileChange::Changed { file, b3sum: None });
+        }
+        Ok(())
+    }
 
-        // Remove renamed files from new_files and deleted_files lists
-        let rename_new_paths: HashSet<_> = potential_renames
+    /// Whether a scanned file may differ from its record under the configured
+    /// `[changes]` heuristic
+    fn metadata_changed(
+        &self,
+        file: &FileInfo,
+        record: &FileRecord,
+        use_checksums: bool,
+    ) -> Result<bool> {
+        let changes = &self.context.config.changes;
+        let tolerance = Duration::from_millis(changes.mtime_tolerance_ms);
+        let size_changed = file.size != record.size as u64;
+        let mtime_changed = modified_since_recorded(file, record, tolerance)?;
+        Ok(match changes.detect {
+            ChangeDetection::Mtime => mtime_changed,
+            ChangeDetection::MtimeSize => size_changed || mtime_changed,
+            ChangeDetection::Ctime => {
+                // Records from before ctimes were kept fall back to mtime and size
+                let ctime_changed = file
+                    .ctime_ns
+                    .zip(record.ctime_ns)
+                    .is_some_and(|(current, recorded)| current != recorded);
+                size_changed || mtime_changed || ctime_changed
+            }
+            ChangeDetection::AlwaysHash => use_checksums || size_changed || mtime_changed,
+        })
+    }
+
+    /// Detect renames among the given candidate indexes by checksum (full mode)
+    /// or size and creation time (lightweight mode)
+    fn find_heuristic_renames(
+        &self,
+        deleted_files: &[FileInfo],
+        deleted_candidates: &[usize],
+        new_files: &[&FileInfo],
+        new_candidates: &[usize],
+        use_checksums: bool,
+    ) -> Result<Vec<RenameMatch>> {
+        if deleted_candidates.is_empty() || new_candidates.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        if !use_checksums {
+            // Lightweight rename detection based on size and creation time
+            return Ok(find_potential_renames_by_metadata(
+                deleted_files,
+                deleted_candidates,
+                new_files,
+                new_candidates,
+            ));
+        }
+
+        // Only new files whose size matches a deleted file can be a rename,
+        // so avoid hashing everything else
+        let deleted_sizes: HashSet<u64> = deleted_candidates
             .iter()
-            .map(|(_, new_file)| &new_file.path)
+            .map(|&i| deleted_files[i].size)
             .collect();
-        let rename_old_paths: HashSet<_> = potential_renames
+        let hash_candidates: Vec<usize> = new_candidates
             .iter()
-            .map(|(old_file, _)| &old_file.path)
+            .copied()
+            .filter(|&i| deleted_sizes.contains(&new_files[i].size))
             .collect();
+        let new_checksums = self.checksums_for_files(new_files, &hash_candidates)?;
 
-        // Filter out files involved in renames
-        new_files.retain(|f| !rename_new_paths.contains(&f.path));
-        deleted_files.retain(|f| !rename_old_paths.contains(&f.path));
+        let mut renames =
+            match_renames_by_checksum(deleted_files, deleted_candidates, new_files, &new_checksums);
 
-        Ok((new_files, changed_files, deleted_files, potential_renames))
+        // Files that kept their size and creation time but not their content
+        // may have been renamed and edited; those pairs are only a guess
+        let matched_deleted: HashSet<usize> = renames.iter().map(|r| r.deleted).collect();
+        let matched_new: HashSet<usize> = renames.iter().map(|r| r.new).collect();
+        let remaining_deleted: Vec<usize> = deleted_candidates
+            .iter()
+            .copied()
+            .filter(|i| !matched_deleted.contains(i))
+            .collect();
+        let remaining_new: Vec<usize> = hash_candidates
+            .iter()
+            .copied()
+            .filter(|i| !matched_new.contains(i))
+            .collect();
+        let new_checksums: HashMap<usize, String> = new_checksums.into_iter().collect();
+        renames.extend(
+            find_potential_renames_by_metadata(
+                deleted_files,
+                &remaining_deleted,
+                new_files,
+                &remaining_new,
+            )
+            .into_iter()
+            .map(|rename| RenameMatch {
+                b3sum: new_checksums.get(&rename.new).cloned(),
+                ..rename
+            }),
+        );
+        Ok(renames)
     }
 
-    /// Find potential renames based on file metadata (size and creation time) without checksums
-    fn find_potential_renames_by_metadata(
+    /// Calculate checksums for the files at the given indexes, reusing existing ones
+    fn checksums_for_files(
         &self,
-        deleted_files: &[FileInfo],
-        new_files: &[FileInfo],
-    ) -> Vec<(FileInfo, FileInfo)> {
-        fn creation_time_secs(file: &FileInfo) -> Option<u64> {
-            file.created
-                .duration_since(UNIX_EPOCH)
-                .ok()
-                .map(|d| d.as_secs())
-        }
-
-        fn group_by_key(files: &[FileInfo]) -> HashMap<(u64, Option<u64>), Vec<&FileInfo>> {
-            let mut map: HashMap<(u64, Option<u64>), Vec<&FileInfo>> = HashMap::new();
-            for file in files {
-                let key = (file.size, creation_time_secs(file));
-                map.entry(key).or_default().push(file);
-            }
-            map
+        files: &[&FileInfo],
+        indexes: &[usize],
+    ) -> Result<Vec<(usize, String)>> {
+        let checksum = |index: usize| -> Result<(usize, String)> {
+            Ok((index, self.checksum_for(files[index])?))
+        };
+
+        // Use parallel processing if we have many files to process
+        if indexes.len() > 10 {
+            self.install(|| indexes.par_iter().map(|&i| checksum(i)).collect())
+        } else {
+            indexes.iter().map(|&i| checksum(i)).collect()
+        }
+    }
+
+    /// Checksum of a scanned file: its existing checksum, a cached one if the
+    /// file is unchanged since it was cached, or a freshly calculated one
+    pub fn checksum_for(&self, file: &FileInfo) -> Result<String> {
+        if let Some(existing) = &file.b3sum {
+            return Ok(existing.clone());
+        }
+        if let Some(cached) = self.hash_cache.get(file) {
+            return Ok(cached);
         }
+        let checksum = self.calculate_single_checksum(&file.path)?;
+        self.hash_cache.insert(file, &checksum);
+        Ok(checksum)
+    }
 
-        let deleted_by_key = group_by_key(deleted_files);
-        let new_by_key = group_by_key(new_files);
+    /// Checksum of a scanned file if it is already known or cached, without
+    /// reading the file
+    pub fn known_checksum(&self, file: &FileInfo) -> Option<String> {
+        file.b3sum.clone().or_else(|| self.hash_cache.get(file))
+    }
 
-        let mut renames = Vec::new();
+    /// Persist checksums calculated since the last flush to the hash cache
+    pub async fn flush_hash_cache(&self) -> Result<()> {
+        self.hash_cache.flush(&self.context.database).await
+    }
 
-        for (key, deleted_group) in deleted_by_key {
-            if let Some(new_group) = new_by_key.get(&key) {
-                // Match first deleted with first new file of same metadata
-                if let (Some(&deleted), Some(&new)) = (deleted_group.first(), new_group.first()) {
-                    let mut new_file = new.clone();
-                    new_file.b3sum = None; // Clear checksum for lightweight mode
-                    renames.push((deleted.clone(), new_file));
-                }
+    /// Calculate checksum for a single file, resolving relative paths against the repository root
+    pub fn calculate_single_checksum<P: AsRef<std::path::Path>>(&self, path: P) -> Result<String> {
+        let path = self.context.repo.root().join(path);
+        self.install(|| self.checksum_calculator.calculate_checksum(path))
+    }
+
+    /// Whether recording a file of `file_size` bytes needs more than its BLAKE3 checksum
+    pub fn has_secondary_checksums(&self, file_size: u64) -> bool {
+        self.checksum_calculator.has_secondary(file_size)
+    }
+
+    /// Calculate chunk hashes of a single file with the given chunk size
+    pub fn calculate_single_chunk_hashes<P: AsRef<std::path::Path>>(
+        &self,
+        path: P,
+        chunk_size: u64,
+    ) -> Result<Vec<String>> {
+        let path = self.context.repo.root().join(path);
+        self.install(|| {
+            self.checksum_calculator
+                .calculate_chunk_hashes(path, chunk_size)
+        })
+    }
+
+    /// Calculate the BLAKE3 and configured secondary checksums of a single file
+    pub fn calculate_single_checksums<P: AsRef<std::path::Path>>(
+        &self,
+        path: P,
+    ) -> Result<(String, SecondaryChecksums)> {
+        let path = self.context.repo.root().join(path);
+        self.install(|| self.checksum_calculator.calculate_checksums(path))
+    }
+
+    /// Copy a single file to `writer`, calculating its BLAKE3 and configured
+    /// secondary checksums in the same pass
+    pub fn copy_with_checksums<P: AsRef<std::path::Path>, W: std::io::Write>(
+        &self,
+        path: P,
+        writer: &mut W,
+    ) -> Result<(String, SecondaryChecksums)> {
+        let path = self.context.repo.root().join(path);
+        self.checksum_calculator.copy_with_checksums(path, writer)
+    }
+
+    /// Calculate the checksum of a single file with a specific algorithm
+    pub fn calculate_single_checksum_with<P: AsRef<std::path::Path>>(
+        &self,
+        path: P,
+        algorithm: HashAlgorithm,
+    ) -> Result<String> {
+        let path = self.context.repo.root().join(path);
+        self.install(|| self.checksum_calculator.calculate_with(path, algorithm))
+    }
+}
+
+/// A pool of `[performance] hash_threads` threads, or none if it cannot be
+/// started, leaving hashing to rayon's global pool
+fn hash_pool(config: &PerformanceConfig) -> Option<rayon::ThreadPool> {
+    rayon::ThreadPoolBuilder::new()
+        .num_threads(config.hash_threads.unwrap_or(0))
+        .thread_name(|i| format!("ddrive-hash-{i}"))
+        .build()
+        .inspect_err(|e| warn!("Could not start hashing threads: {}", e))
+        .ok()
+}
+
+/// Compare a file's modification time against the recorded one. Records with
+/// nanosecond precision must match to within `tolerance`; older
+/// second-precision records only count newer modification times as changes.
+fn modified_since_recorded(
+    file: &FileInfo,
+    record: &FileRecord,
+    tolerance: Duration,
+) -> Result<bool> {
+    let modified =
+        file.modified
+            .duration_since(UNIX_EPOCH)
+            .map_err(|e| crate::DdriveError::FileSystem {
+                message: format!("Invalid modification time: {e:?}"),
+            })?;
+
+    Ok(match record.mtime_ns {
+        Some(mtime_ns) => {
+            (modified.as_nanos() as i64).abs_diff(mtime_ns) > tolerance.as_nanos() as u64
+        }
+        None => {
+            modified.as_secs()
+                > record.updated_at.and_utc().timestamp() as u64 + tolerance.as_secs()
+        }
+    })
+}
+
+/// A single difference between the filesystem and the tracked records
+#[derive(Debug)]
+pub enum FileChange<'s> {
+    /// File on disk that is not tracked yet
+    New(&'s FileInfo),
+    /// Tracked file whose size or modification time changed. In full mode the
+    /// new checksum is included and only content changes are reported.
+    Changed {
+        file: &'s FileInfo,
+        b3sum: Option<String>,
+    },
+    /// Tracked file that is no longer on disk
+    Deleted(FileInfo),
+    /// Tracked file that moved to a new path. In full mode a low-confidence
+    /// rename carries the checksum of the new content, which may differ.
+    Renamed {
+        from: FileInfo,
+        to: &'s FileInfo,
+        b3sum: Option<String>,
+        confidence: RenameConfidence,
+    },
+}
+
+/// How a rename was detected, from the weakest to the strongest signal
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Display, Serialize, Deserialize,
+)]
+#[strum(serialize_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum RenameConfidence {
+    /// Same size and creation time; unrelated files can match by chance
+    Metadata,
+    /// Same size and content
+    Checksum,
+    /// Same device and inode, so the very same file
+    Inode,
+}
+
+impl RenameConfidence {
+    /// Whether `add` needs confirmation before recording the rename
+    pub fn is_low(self) -> bool {
+        self < RenameConfidence::Checksum
+    }
+}
+
+/// Pairing of a deleted file with a new file by their candidate indexes
+#[derive(Debug, PartialEq, Eq)]
+pub struct RenameMatch {
+    pub deleted: usize,
+    pub new: usize,
+    /// Checksum of the content, when known
+    pub b3sum: Option<String>,
+    pub confidence: RenameConfidence,
+}
+
+/// Pair deleted and new files that share the same (device, inode).
+///
+/// A matching inode with the same size and no newer modification time than
+/// the recorded one is a pure rename; anything else is left to other heuristics
+/// since inode numbers can be reused after a delete.
+pub fn match_renames_by_inode(
+    deleted_files: &[FileInfo],
+    new_files: &[&FileInfo],
+) -> Vec<RenameMatch> {
+    let new_by_inode: HashMap<(u64, u64), usize> = new_files
+        .iter()
+        .enumerate()
+        .filter_map(|(index, file)| file.inode_key().map(|key| (key, index)))
+        .collect();
+
+    let modified_secs = |f: &FileInfo| {
+        f.modified
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default()
+    };
+
+    deleted_files
+        .iter()
+        .enumerate()
+        .filter_map(|(index, deleted)| {
+            let new_index = *new_by_inode.get(&deleted.inode_key()?)?;
+            let new = new_files[new_index];
+            if new.size != deleted.size || modified_secs(new) > modified_secs(deleted) + 1 {
+                return None;
             }
+            Some(RenameMatch {
+                deleted: index,
+                new: new_index,
+                b3sum: deleted.b3sum.clone(),
+                confidence: RenameConfidence::Inode,
+            })
+        })
+        .collect()
+}
+
+/// Pair deleted and new candidates with identical checksum and size
+pub fn match_renames_by_checksum(
+    deleted_files: &[FileInfo],
+    deleted_candidates: &[usize],
+    new_files: &[&FileInfo],
+    new_checksums: &[(usize, String)],
+) -> Vec<RenameMatch> {
+    let mut new_by_checksum: HashMap<(&str, u64), Vec<usize>> = HashMap::new();
+    for (index, checksum) in new_checksums {
+        new_by_checksum
+            .entry((checksum.as_str(), new_files[*index].size))
+            .or_default()
+            .push(*index);
+    }
+
+    let mut renames = Vec::new();
+    for &index in deleted_candidates {
+        let deleted = &deleted_files[index];
+        let Some(checksum) = deleted.b3sum.as_deref() else {
+            continue;
+        };
+        // Only match each new file once
+        if let Some(new_list) = new_by_checksum.get_mut(&(checksum, deleted.size))
+            && let Some(new_index) = new_list.pop()
+        {
+            renames.push(RenameMatch {
+                deleted: index,
+                new: new_index,
+                b3sum: Some(checksum.to_string()),
+                confidence: RenameConfidence::Checksum,
+            });
         }
+    }
+    renames
+}
 
-        renames
+/// Find potential renames based on file metadata (size and creation time) without checksums.
+///
+/// Files without a known creation time are never paired, since size alone is
+/// far too weak a signal.
+pub fn find_potential_renames_by_metadata(
+    deleted_files: &[FileInfo],
+    deleted_candidates: &[usize],
+    new_files: &[&FileInfo],
+    new_candidates: &[usize],
+) -> Vec<RenameMatch> {
+    fn creation_time_secs(file: &FileInfo) -> Option<u64> {
+        file.created?
+            .duration_since(UNIX_EPOCH)
+            .ok()
+            .map(|d| d.as_secs())
     }
 
-    /// Ensure checksums are present for a list of files, reusing existing ones
-    async fn ensure_checksums_for_files(&self, files: &[FileInfo]) -> Result<Vec<FileInfo>> {
-        // Separate files that already have checksums from those that need calculation
-        let (files_with_checksums, files_needing_checksums): (Vec<_>, Vec<_>) =
-            files.iter().partition(|file| file.b3sum.is_some());
+    let mut new_by_key: HashMap<(u64, u64), usize> = HashMap::new();
+    for &index in new_candidates {
+        let file = new_files[index];
+        let Some(created) = creation_time_secs(file) else {
+            continue;
+        };
+        // Match the first new file of the same metadata
+        new_by_key.entry((file.size, created)).or_insert(index);
+    }
 
-        let mut result = Vec::with_capacity(files.len());
+    let mut renames = Vec::new();
+    for &index in deleted_candidates {
+        let file = &deleted_files[index];
+        let Some(created) = creation_time_secs(file) else {
+            continue;
+        };
+        if let Some(new_index) = new_by_key.remove(&(file.size, created)) {
+            renames.push(RenameMatch {
+                deleted: index,
+                new: new_index,
+                b3sum: None,
+                confidence: RenameConfidence::Metadata,
+            });
+        }
+    }
+    renames
+}
 
-        // Add files that already have checksums (no cloning needed for checksum calculation)
-        result.extend(files_with_checksums.into_iter().cloned());
+/// Tracked files that moved along with a directory, such as all files under
+/// `photos/2021` now being under `archive/2021`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DirectoryMove {
+    pub from: String,
+    pub to: String,
+    /// Indexes of the renames of the files in the directory
+    pub files: Vec<usize>,
+}
 
-        // Calculate checksums for remaining files
-        // Use parallel processing if we have many files to process
-        if files_needing_checksums.len() > 10 {
-            let calculated_files: Result<Vec<_>> = files_needing_checksums
-                .par_iter()
-                .map(|file| {
-                    let checksum = self.checksum_calculator.calculate_checksum(&file.path)?;
-                    let mut file_with_checksum = (*file).clone();
-                    file_with_checksum.b3sum = Some(checksum);
-                    Ok(file_with_checksum)
-                })
-                .collect();
-            result.extend(calculated_files?);
-        } else {
-            // Sequential processing for small numbers of files
-            for file in files_needing_checksums {
-                let checksum = self.checksum_calculator.calculate_checksum(&file.path)?;
-                let mut file_with_checksum = file.clone();
-                file_with_checksum.b3sum = Some(checksum);
-                result.push(file_with_checksum);
+/// Number of tracked files under each directory, counted as records go by
+/// so that directory moves can be told apart without keeping every path
+#[derive(Debug, Default)]
+pub struct TrackedDirectories(HashMap<String, usize>);
+
+impl TrackedDirectories {
+    /// Count the tracked file at storage path `path`
+    pub fn insert(&mut self, path: &str) {
+        for (index, _) in path.match_indices('/') {
+            match self.0.get_mut(&path[..index]) {
+                Some(count) => *count += 1,
+                None => {
+                    self.0.insert(path[..index].to_string(), 1);
+                }
+            }
+        }
+    }
+}
+
+impl<'a> FromIterator<&'a str> for TrackedDirectories {
+    fn from_iter<I: IntoIterator<Item = &'a str>>(paths: I) -> Self {
+        let mut directories = Self::default();
+        for path in paths {
+            directories.insert(path);
+        }
+        directories
+    }
+}
+
+/// Find the directories that moved as a whole among `renames` (old and new
+/// storage paths). A directory moved if every tracked file under it was
+/// renamed into the same new directory, which held no tracked files before;
+/// the outermost such directory is reported. `tracked` counts the tracked
+/// records before the renames. Renames of single files are left out.
+pub fn find_directory_moves(
+    renames: &[(String, String)],
+    tracked: &TrackedDirectories,
+) -> Vec<DirectoryMove> {
+    // Each rename keeping its last `depth` components moves the directories
+    // above them, from the innermost to the outermost
+    fn candidates<'p>(old: &'p str, new: &'p str) -> Vec<(&'p str, &'p str)> {
+        let old_parts: Vec<&str> = old.split('/').collect();
+        let new_parts: Vec<&str> = new.split('/').collect();
+        let common = old_parts
+            .iter()
+            .rev()
+            .zip(new_parts.iter().rev())
+            .take_while(|(a, b)| a == b)
+            .count();
+        let mut candidates = Vec::new();
+        for depth in 1..=common {
+            if depth >= old_parts.len() || depth >= new_parts.len() {
+                break;
             }
+            let old_len: usize = old_parts[old_parts.len() - depth..]
+                .iter()
+                .map(|part| part.len() + 1)
+                .sum();
+            let new_len: usize = new_parts[new_parts.len() - depth..]
+                .iter()
+                .map(|part| part.len() + 1)
+                .sum();
+            candidates.push((&old[..old.len() - old_len], &new[..new.len() - new_len]));
         }
+        candidates
+    }
 
-        Ok(result)
+    let tracked_under = &tracked.0;
+    let mut moved_under: HashMap<(&str, &str), usize> = HashMap::new();
+    for (old, new) in renames {
+        for candidate in candidates(old, new) {
+            *moved_under.entry(candidate).or_default() += 1;
+        }
     }
 
-    /// Calculate checksum for a single file
-    pub fn calculate_single_checksum<P: AsRef<std::path::Path>>(&self, path: P) -> Result<String> {
-        self.checksum_calculator.calculate_checksum(path)
+    let mut moves: Vec<DirectoryMove> = Vec::new();
+    let mut move_index: HashMap<(&str, &str), usize> = HashMap::new();
+    for (index, (old, new)) in renames.iter().enumerate() {
+        let outermost = candidates(old, new).into_iter().rev().find(|&(from, to)| {
+            moved_under.get(&(from, to)) == tracked_under.get(from)
+                && !tracked_under.contains_key(to)
+        });
+        let Some((from, to)) = outermost else {
+            continue;
+        };
+        let slot = *move_index.entry((from, to)).or_insert_with(|| {
+            moves.push(DirectoryMove {
+                from: from.to_string(),
+                to: to.to_string(),
+                files: Vec::new(),
+            });
+            moves.len() - 1
+        });
+        moves[slot].files.push(index);
+    }
+    moves.retain(|directory_move| directory_move.files.len() > 1);
+    moves
+}
+
+/// A scanned file with its path in stored form
+fn with_storage_path(file: &FileInfo) -> (String, &FileInfo) {
+    (to_storage_path(&file.path), file)
+}
+
+/// The next record of `tracked` with its path in stored form
+async fn next_record<R: Borrow<FileRecord>>(
+    tracked: &mut Pin<&mut impl Stream<Item = Result<R>>>,
+) -> Result<Option<(String, R)>> {
+    Ok(tracked
+        .try_next()
+        .await?
+        .map(|record| (normalize_path(&record.borrow().path).into_owned(), record)))
+}
+
+/// Normalize a path string to Unicode NFC, the form stored in the database.
+/// macOS reports decomposed (NFD) names, so without this the same file could
+/// show up as both deleted and new.
+pub fn normalize_path(path: &str) -> Cow<'_, str> {
+    match is_nfc_quick(path.chars()) {
+        IsNormalized::Yes => Cow::Borrowed(path),
+        _ => Cow::Owned(path.nfc().collect()),
+    }
+}
+
+/// Convert a repository-relative path to its stored form: `/`-separated and
+/// NFC-normalized, so a repository written on one platform can be verified on
+/// another without every file appearing renamed.
+pub fn to_storage_path(path: &Path) -> String {
+    let joined = path
+        .components()
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/");
+    normalize_path(&joined).into_owned()
+}
+
+/// Replace native `\` separators with `/`. Only Windows uses backslashes as
+/// separators; elsewhere they are valid file name characters and are kept.
+pub fn native_to_forward_slashes(path: &str) -> Cow<'_, str> {
+    if cfg!(windows) && path.contains('\\') {
+        Cow::Owned(path.replace('\\', "/"))
+    } else {
+        Cow::Borrowed(path)
+    }
+}
+
+/// Convert a stored path back to a native relative path
+pub fn from_storage_path(path: &str) -> PathBuf {
+    path.split('/').filter(|part| !part.is_empty()).collect()
+}
+
+/// Count distinct underlying files, treating entries with the same
+/// (device, inode) as one (hardlinks). Entries without an inode are distinct.
+pub fn count_distinct_inodes<I>(inode_keys: I) -> usize
+where
+    I: IntoIterator<Item = Option<(u64, u64)>>,
+{
+    let mut seen = HashSet::new();
+    inode_keys
+        .into_iter()
+        .filter(|key| key.is_none_or(|k| seen.insert(k)))
+        .count()
+}
+
+/// How sizes are displayed
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, ValueEnum, Display,
+)]
+#[serde(rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase")]
+pub enum SizeUnits {
+    /// Powers of 1024 labelled KB, MB, GB
+    #[default]
+    Jedec,
+    /// Powers of 1024 labelled KiB, MiB, GiB
+    Binary,
+    /// Powers of 1000 labelled kB, MB, GB
+    Decimal,
+    /// Exact byte counts
+    Bytes,
+}
+
+/// `path`, or the first of `path-1`, `path-2`, ... that neither exists nor
+/// is in `taken`
+pub fn unique_path(path: PathBuf, taken: &HashSet<PathBuf>) -> PathBuf {
+    let free = |path: &Path| !path.exists() && !taken.contains(path);
+    if free(&path) {
+        return path;
+    }
+    let stem = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let extension = path
+        .extension()
+        .map(|extension| extension.to_string_lossy());
+    (1..)
+        .map(|n| {
+            let name = match &extension {
+                Some(extension) => format!("{stem}-{n}.{extension}"),
+                None => format!("{stem}-{n}"),
+            };
+            path.with_file_name(name)
+        })
+        .find(|candidate| free(candidate))
+        .expect("some numbered name is free")
+}
+
+/// Format a count with thousands separators, such as `1,234`
+pub fn format_count(count: usize) -> String {
+    let digits = count.to_string();
+    let mut formatted = String::with_capacity(digits.len() + digits.len() / 3);
+    for (index, digit) in digits.chars().enumerate() {
+        if index > 0 && (digits.len() - index).is_multiple_of(3) {
+            formatted.push(',');
+        }
+        formatted.push(digit);
+    }
+    formatted
+}
+
+/// The shortest prefix of `id`, at least `min_len` characters long, that no
+/// other entry of the sorted `ids` starts with, like git's abbreviated hashes
+pub fn unique_prefix<'a>(id: &'a str, ids: &[String], min_len: usize) -> &'a str {
+    let common = |other: &String| {
+        id.bytes()
+            .zip(other.bytes())
+            .take_while(|(a, b)| a == b)
+            .count()
+    };
+    // Only the sorted neighbours can share a longer prefix with `id`
+    let index = ids.partition_point(|other| other.as_str() < id);
+    let before = index.checked_sub(1).and_then(|index| ids.get(index));
+    let after = ids[index..].iter().find(|other| other.as_str() != id);
+    let shared = before.into_iter().chain(after).map(common).max();
+    let len = shared.map_or(0, |shared| shared + 1).max(min_len);
+    &id[..len.min(id.len())]
+}
+
+/// Format a duration for people, such as `850ms`, `12.3s`, `4m 05s` or `2h 07m`
+pub fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    match secs {
+        0 => format!("{}ms", duration.as_millis()),
+        1..60 => format!("{:.1}s", duration.as_secs_f64()),
+        60..3600 => format!("{}m {:02}s", secs / 60, secs % 60),
+        _ => format!("{}h {:02}m", secs / 3600, secs % 3600 / 60),
     }
 }
 
 /// Format file size in human-readable format
 pub fn format_size(size: u64) -> String {
-    const KB: u64 = 1024;
-    const MB: u64 = KB * 1024;
-    const GB: u64 = MB * 1024;
-    const TB: u64 = GB * 1024;
+    format_size_as(size, SizeUnits::Jedec)
+}
 
-    if size < KB {
+/// Format file size in the given units
+pub fn format_size_as(size: u64, units: SizeUnits) -> String {
+    let (base, labels) = match units {
+        SizeUnits::Jedec => (1024, ["KB", "MB", "GB", "TB"]),
+        SizeUnits::Binary => (1024, ["KiB", "MiB", "GiB", "TiB"]),
+        SizeUnits::Decimal => (1000, ["kB", "MB", "GB", "TB"]),
+        SizeUnits::Bytes => return format!("{size} B"),
+    };
+    let kb: u64 = base;
+    let mb = kb * base;
+    let gb = mb * base;
+    let tb = gb * base;
+
+    if size < kb {
         format!("{size} B",)
-    } else if size < MB {
-        format!("{:.1} KB", size as f64 / KB as f64)
-    } else if size < GB {
-        format!("{:.1} MB", size as f64 / MB as f64)
-    } else if size < TB {
-        format!("{:.2} GB", size as f64 / GB as f64)
+    } else if size < mb {
+        format!("{:.1} {}", size as f64 / kb as f64, labels[0])
+    } else if size < gb {
+        format!("{:.1} {}", size as f64 / mb as f64, labels[1])
+    } else if size < tb {
+        format!("{:.2} {}", size as f64 / gb as f64, labels[2])
     } else {
-        format!("{:.2} TB", size as f64 / TB as f64)
+        format!("{:.2} {}", size as f64 / tb as f64, labels[3])
     }
 }
 
+/// Parse a size such as `100GB`, `1.5T`, `512MiB` or `4096`. Units are powers
+/// of 1024, matching `format_size`.
+pub fn parse_size(size: &str) -> Result<u64> {
+    let size = size.trim();
+    let split = size
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(size.len());
+    let (number, unit) = size.split_at(split);
+    let invalid = || DdriveError::Validation {
+        message: format!("invalid size '{size}', expected e.g. 500MB or 100GB"),
+    };
+
+    let number: f64 = number.parse().map_err(|_| invalid())?;
+    let exponent = match unit.trim().to_ascii_uppercase().as_str() {
+        "" | "B" => 0,
+        "K" | "KB" | "KIB" => 1,
+        "M" | "MB" | "MIB" => 2,
+        "G" | "GB" | "GIB" => 3,
+        "T" | "TB" | "TIB" => 4,
+        _ => return Err(invalid()),
+    };
+    Ok((number * 1024f64.powi(exponent)) as u64)
+}
+
+/// Parse a date such as `2024-05-01`, `2024-05-01T18:30:00` (both local
+/// time), an RFC 3339 timestamp, or an age such as `12h`, `90d` or `2w` ago
+pub fn parse_date(date: &str) -> Result<chrono::DateTime<chrono::Utc>> {
+    use chrono::{DateTime, Duration, Local, NaiveDate, NaiveDateTime, TimeZone, Utc};
+
+    let date = date.trim();
+    if let Ok(timestamp) = DateTime::parse_from_rfc3339(date) {
+        return Ok(timestamp.with_timezone(&Utc));
+    }
+    if let Some((count, unit)) = date
+        .char_indices()
+        .last()
+        .and_then(|(index, unit)| Some((date[..index].parse::<u32>().ok()?.into(), unit)))
+    {
+        let age = match unit {
+            'h' => Duration::try_hours(count),
+            'd' => Duration::try_days(count),
+            'w' => Duration::try_weeks(count),
+            _ => None,
+        };
+        if let Some(age) = age {
+            return Ok(Utc::now() - age);
+        }
+    }
+    let local = NaiveDateTime::parse_from_str(date, "%Y-%m-%dT%H:%M:%S")
+        .or_else(|_| NaiveDateTime::parse_from_str(date, "%Y-%m-%d %H:%M:%S"))
+        .or_else(|_| NaiveDate::parse_from_str(date, "%Y-%m-%d").map(|day| day.into()))
+        .map_err(|_| DdriveError::Validation {
+            message: format!("invalid date '{date}', expected e.g. 2024-05-01 or 90d"),
+        })?;
+    let local = Local
+        .from_local_datetime(&local)
+        .earliest()
+        .ok_or_else(|| DdriveError::Validation {
+            message: format!("'{date}' does not exist in the local time zone"),
+        })?;
+    Ok(local.with_timezone(&Utc))
+}
+
+/// Free space available to unprivileged users on the file system holding `path`
+#[cfg(unix)]
+pub fn available_space(path: &Path) -> Result<u64> {
+    let stat = rustix::fs::statvfs(path).map_err(std::io::Error::from)?;
+    Ok(stat.f_bavail * stat.f_frsize)
+}
+
+#[cfg(not(unix))]
+pub fn available_space(_path: &Path) -> Result<u64> {
+    Err(DdriveError::Validation {
+        message: "free space is only available on unix".to_string(),
+    })
+}
+
+/// Read a list of paths separated by newlines, or by NUL bytes when `nul`
+/// is set (as written by `find -print0`). Empty entries are skipped.
+pub fn read_path_list(mut reader: impl std::io::Read, nul: bool) -> Result<Vec<PathBuf>> {
+    let mut bytes = Vec::new();
+    reader.read_to_end(&mut bytes)?;
+    let separator = if nul { b'\0' } else { b'\n' };
+    Ok(bytes
+        .split(|&b| b == separator)
+        .map(|entry| {
+            if nul {
+                entry
+            } else {
+                entry.strip_suffix(b"\r").unwrap_or(entry)
+            }
+        })
+        .filter(|entry| !entry.is_empty())
+        .map(path_from_bytes)
+        .collect())
+}
+
+#[cfg(unix)]
+fn path_from_bytes(bytes: &[u8]) -> PathBuf {
+    use std::os::unix::ffi::OsStrExt;
+    PathBuf::from(std::ffi::OsStr::from_bytes(bytes))
+}
+
+#[cfg(not(unix))]
+fn path_from_bytes(bytes: &[u8]) -> PathBuf {
+    PathBuf::from(String::from_utf8_lossy(bytes).into_owned())
+}
+
+/// Lower the CPU and I/O priority of every thread of this process as
+/// `[performance] cpu_nice` and `io_nice` ask, so a long verify leaves the
+/// machine responsive. Threads started afterwards inherit it.
+#[cfg(target_os = "linux")]
+pub fn lower_priority(config: &PerformanceConfig) -> Result<()> {
+    // ioprio_set(2) arguments for the best-effort class
+    const IOPRIO_WHO_PROCESS: libc::c_int = 1;
+    const IOPRIO_CLASS_BE: libc::c_int = 2;
+
+    check_priority(config)?;
+    if config.cpu_nice.is_none() && config.io_nice.is_none() {
+        return Ok(());
+    }
+    // Linux keeps both priorities per thread
+    for entry in std::fs::read_dir("/proc/self/task")? {
+        let Some(tid) = entry?.file_name().to_str().and_then(|tid| tid.parse().ok()) else {
+            continue;
+        };
+        if let Some(nice) = config.cpu_nice {
+            rustix::process::setpriority_process(rustix::process::Pid::from_raw(tid), nice)
+                .map_err(std::io::Error::from)?;
+        }
+        if let Some(level) = config.io_nice {
+            let priority = (IOPRIO_CLASS_BE << 13) | libc::c_int::from(level);
+            // SAFETY: ioprio_set only reads its integer arguments
+            let result =
+                unsafe { libc::syscall(libc::SYS_ioprio_set, IOPRIO_WHO_PROCESS, tid, priority) };
+            if result != 0 {
+                return Err(std::io::Error::last_os_error().into());
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(all(unix, not(target_os = "linux")))]
+pub fn lower_priority(config: &PerformanceConfig) -> Result<()> {
+    check_priority(config)?;
+    if let Some(nice) = config.cpu_nice {
+        rustix::process::setpriority_process(None, nice).map_err(std::io::Error::from)?;
+    }
+    if config.io_nice.is_some() {
+        debug!("performance.io_nice is only supported on Linux");
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn lower_priority(config: &PerformanceConfig) -> Result<()> {
+    check_priority(config)?;
+    if config.cpu_nice.is_some() || config.io_nice.is_some() {
+        debug!("performance.cpu_nice and io_nice are not supported on this platform");
+    }
+    Ok(())
+}
+
+fn check_priority(config: &PerformanceConfig) -> Result<()> {
+    if config
+        .cpu_nice
+        .is_some_and(|nice| !(0..=19).contains(&nice))
+    {
+        return Err(DdriveError::Configuration {
+            message: "performance.cpu_nice must be between 0 and 19".to_string(),
+        });
+    }
+    if config.io_nice.is_some_and(|level| level > 7) {
+        return Err(DdriveError::Configuration {
+            message: "performance.io_nice must be between 0 and 7".to_string(),
+        });
+    }
+    Ok(())
+}
+
+/// Ask a yes/no question on the terminal. Without a terminal to answer on,
+/// the answer is no.
+pub fn confirm(prompt: &str) -> Result<bool> {
+    use std::io::{IsTerminal, Write};
+
+    if !std::io::stdin().is_terminal() {
+        return Ok(false);
+    }
+    print!("{prompt} [y/N] ");
+    std::io::stdout().flush()?;
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer)?;
+    Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"))
+}
+
+/// Ask for a value on the terminal. An empty answer, or no terminal to
+/// answer on, gives `default`.
+pub fn ask(prompt: &str, default: &str) -> Result<String> {
+    use std::io::{IsTerminal, Write};
+
+    if !std::io::stdin().is_terminal() {
+        return Ok(default.to_string());
+    }
+    print!("{prompt} [{default}]: ");
+    std::io::stdout().flush()?;
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer)?;
+    let answer = answer.trim();
+    Ok(if answer.is_empty() { default } else { answer }.to_string())
+}
+
+/// Ask for a secret on the terminal without echoing it. Returns `None`
+/// without a terminal to answer on.
+pub fn ask_secret(prompt: &str) -> Result<Option<String>> {
+    use std::io::{IsTerminal, Write};
+
+    let stdin = std::io::stdin();
+    if !stdin.is_terminal() {
+        return Ok(None);
+    }
+    print!("{prompt}: ");
+    std::io::stdout().flush()?;
+    #[cfg(unix)]
+    let echoing = {
+        use rustix::termios::{LocalModes, OptionalActions, tcgetattr, tcsetattr};
+        let echoing = tcgetattr(&stdin).map_err(std::io::Error::from)?;
+        let mut silent = echoing.clone();
+        silent.local_modes.remove(LocalModes::ECHO);
+        tcsetattr(&stdin, OptionalActions::Now, &silent).map_err(std::io::Error::from)?;
+        echoing
+    };
+    let mut answer = String::new();
+    let read = stdin.read_line(&mut answer);
+    #[cfg(unix)]
+    rustix::termios::tcsetattr(&stdin, rustix::termios::OptionalActions::Now, &echoing)
+        .map_err(std::io::Error::from)?;
+    println!();
+    read?;
+    Ok(Some(answer.trim_end_matches(['\n', '\r']).to_string()))
+}
+
+/// Let the user pick some of `items` on the terminal by number. Returns the
+/// indices of the chosen items, none if the answer is empty or there is no
+/// terminal to answer on.
+pub fn select(prompt: &str, items: &[String]) -> Result<Vec<usize>> {
+    use std::io::{IsTerminal, Write};
+
+    if !std::io::stdin().is_terminal() {
+        return Ok(Vec::new());
+    }
+    let width = items.len().to_string().len();
+    for (i, item) in items.iter().enumerate() {
+        println!("  {:>width$}) {item}", i + 1);
+    }
+    loop {
+        print!("{prompt} (e.g. 1 3 5-8, a for all, empty for none): ");
+        std::io::stdout().flush()?;
+        let mut answer = String::new();
+        std::io::stdin().read_line(&mut answer)?;
+        match parse_selection(&answer, items.len()) {
+            Ok(selected) => return Ok(selected),
+            Err(e) => println!("{e}"),
+        }
+    }
+}
+
+/// Parse a selection such as `1 3 5-8` or `a` of items numbered from 1 to
+/// `count` into sorted, distinct zero-based indices
+pub fn parse_selection(answer: &str, count: usize) -> Result<Vec<usize>> {
+    let answer = answer.trim();
+    if answer.eq_ignore_ascii_case("a") || answer.eq_ignore_ascii_case("all") {
+        return Ok((0..count).collect());
+    }
+
+    let invalid = |part: &str| DdriveError::Validation {
+        message: format!("Invalid selection '{part}': use numbers from 1 to {count}"),
+    };
+    let mut selected = std::collections::BTreeSet::new();
+    for part in answer.split(|c: char| c == ',' || c.is_whitespace()) {
+        if part.is_empty() {
+            continue;
+        }
+        let (start, end) = part.split_once('-').unwrap_or((part, part));
+        let start: usize = start.trim().parse().map_err(|_| invalid(part))?;
+        let end: usize = end.trim().parse().map_err(|_| invalid(part))?;
+        if start == 0 || end > count || start > end {
+            return Err(invalid(part));
+        }
+        selected.extend(start - 1..end);
+    }
+    Ok(selected.into_iter().collect())
+}
+
 /// Shorten a path with ellipsis if it's too long, with proper Unicode support
 pub fn shorten_path(path: &str, max_length: usize) -> String {
     // Count grapheme clusters (visible characters) instead of bytes or code points
@@ -337,10 +1335,101 @@ pub fn shorten_path(path: &str, max_length: usize) -> String {
     format!("{first_part}...{last_part}",)
 }
 
+/// How files are grouped by directory in change summaries
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DirectoryGrouping {
+    /// Directories listed before the rest are lumped together; 0 lists all
+    pub max_dirs: usize,
+    /// Group by the first this many directory levels, instead of by
+    /// top-level directory with nested ones split out
+    pub depth: Option<usize>,
+}
+
+impl Default for DirectoryGrouping {
+    fn default() -> Self {
+        Self {
+            max_dirs: 10,
+            depth: None,
+        }
+    }
+}
+
 /// Group files by directory for better summary display, focusing on top-level directories
 /// with proper Unicode support
 pub fn group_files_by_directory(
     files: &[String],
+) -> std::collections::BTreeMap<String, Vec<(String, u64)>> {
+    let files: Vec<(String, u64)> = files.iter().map(|file| (file.clone(), 0)).collect();
+    group_files(&files, &DirectoryGrouping::default())
+}
+
+/// Group files with their sizes by directory as `grouping` says. When
+/// there are more directories than `max_dirs`, those with the most files
+/// are kept and the rest are listed under "Other directories".
+pub fn group_files(
+    files: &[(String, u64)],
+    grouping: &DirectoryGrouping,
+) -> std::collections::BTreeMap<String, Vec<(String, u64)>> {
+    let mut dir_groups = match grouping.depth {
+        Some(depth) => group_files_by_depth(files, depth.max(1)),
+        None => group_files_by_top_directory(files),
+    };
+
+    if grouping.max_dirs > 0 && dir_groups.len() > grouping.max_dirs {
+        let mut by_count: Vec<(String, usize)> = dir_groups
+            .iter()
+            .map(|(dir, files)| (dir.clone(), files.len()))
+            .collect();
+        by_count.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+
+        let mut other_files = Vec::new();
+        for (dir, _) in by_count.into_iter().skip(grouping.max_dirs - 1) {
+            let files = dir_groups.remove(&dir).unwrap_or_default();
+            // Collect remaining files under "Other directories"
+            other_files.extend(files.into_iter().map(|(name, size)| {
+                if dir == "./" {
+                    (name, size)
+                } else {
+                    (format!("{dir}/{name}"), size)
+                }
+            }));
+        }
+        if !other_files.is_empty() {
+            dir_groups.insert("Other directories".to_string(), other_files);
+        }
+    }
+
+    dir_groups
+}
+
+/// Group by the first `depth` directories of each path
+fn group_files_by_depth(
+    files: &[(String, u64)],
+    depth: usize,
+) -> std::collections::BTreeMap<String, Vec<(String, u64)>> {
+    let mut dir_groups: std::collections::BTreeMap<String, Vec<(String, u64)>> =
+        std::collections::BTreeMap::new();
+    for (file_path, size) in files {
+        let normalized_path = file_path.replace('\\', "/");
+        let components: Vec<&str> = normalized_path.split('/').collect();
+        let dirs = (components.len() - 1).min(depth);
+        let dir_key = if dirs == 0 {
+            String::from("./")
+        } else {
+            components[..dirs].join("/")
+        };
+        dir_groups
+            .entry(dir_key)
+            .or_default()
+            .push((components[dirs..].join("/"), *size));
+    }
+    dir_groups
+}
+
+/// Group under top-level directories, listing the files of nested
+/// directories by their path below it
+fn group_files_by_top_directory(
+    files: &[(String, u64)],
 ) -> std::collections::BTreeMap<String, Vec<(String, u64)>> {
     let mut dir_groups: std::collections::BTreeMap<String, Vec<(String, u64)>> =
         std::collections::BTreeMap::new();
@@ -349,7 +1438,7 @@ pub fn group_files_by_directory(
         std::collections::HashMap::new();
 
     // First pass: identify top-level directories and collect files
-    for file_path in files {
+    for (file_path, size) in files {
         // Handle paths with both forward and backward slashes for cross-platform compatibility
         let normalized_path = file_path.replace('\\', "/");
         let path = std::path::Path::new(&normalized_path);
@@ -388,7 +1477,10 @@ pub fn group_files_by_directory(
             .unwrap_or_else(|| normalized_path.clone());
 
         // Store the file with its full path directory
-        deep_paths.entry(dir_key).or_default().push((file_name, 0));
+        deep_paths
+            .entry(dir_key)
+            .or_default()
+            .push((file_name, *size));
     }
 
     // Second pass: organize files by top-level directory or full path as needed
@@ -432,102 +1524,158 @@ pub fn group_files_by_directory(
         }
     }
 
-    // Limit the number of top directories if there are too many
-    const MAX_TOP_DIRS: usize = 10;
-    if dir_groups.len() > MAX_TOP_DIRS {
-        // Create a new map with limited entries
-        let mut limited_groups = std::collections::BTreeMap::new();
-        let mut other_files = Vec::new();
-        let mut count = 0;
+    dir_groups
+}
 
-        for (dir, files) in dir_groups {
-            if count < MAX_TOP_DIRS - 1 {
-                limited_groups.insert(dir, files);
-                count += 1;
-            } else {
-                // Collect remaining files under "Other directories"
-                other_files.extend(
-                    files
-                        .into_iter()
-                        .map(|(name, size)| (format!("{dir}/{name}",), size)),
-                );
-            }
-        }
+/// Markers used in terminal output: emoji and unicode symbols, or ASCII for
+/// terminals and log collectors that mangle them
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Symbols {
+    emoji: bool,
+}
 
-        // Add the "Other directories" entry if we have files there
-        if !other_files.is_empty() {
-            limited_groups.insert("Other directories".to_string(), other_files);
-        }
+impl Symbols {
+    pub fn new(emoji: bool) -> Self {
+        Self { emoji }
+    }
 
-        return limited_groups;
+    fn pick(&self, fancy: &'static str, plain: &'static str) -> &'static str {
+        if self.emoji { fancy } else { plain }
     }
 
-    dir_groups
-}
+    pub fn pass(&self) -> &'static str {
+        self.pick("✓", "OK")
+    }
 
-/// Display a directory listing with files in a user-friendly format
-/// with proper Unicode support for paths and emojis
-pub fn display_directory_listing(
-    dir_groups: &std::collections::BTreeMap<String, Vec<(String, u64)>>,
-    max_path_length: usize,
-    max_samples: usize,
-) -> Vec<String> {
-    let mut output = Vec::new();
+    pub fn fail(&self) -> &'static str {
+        self.pick("✗", "FAIL")
+    }
+
+    /// Prefix of warning summaries
+    pub fn warning(&self) -> &'static str {
+        self.pick("⚠️ ", "WARNING:")
+    }
+
+    /// Prefix of success summaries
+    pub fn success(&self) -> &'static str {
+        self.pick("✅", "OK:")
+    }
+
+    pub fn arrow(&self) -> &'static str {
+        self.pick("→", "->")
+    }
+
+    pub fn bullet(&self) -> &'static str {
+        self.pick("•", "-")
+    }
+
+    /// Tree branch to an entry with more entries after it
+    pub fn tree_branch(&self) -> &'static str {
+        self.pick("├── ", "|-- ")
+    }
+
+    /// Tree branch to the last entry
+    pub fn tree_last(&self) -> &'static str {
+        self.pick("└── ", "`-- ")
+    }
+
+    /// Indentation below an entry with more entries after it
+    pub fn tree_pipe(&self) -> &'static str {
+        self.pick("│   ", "|   ")
+    }
+
+    /// Directory marker, including its trailing space
+    pub fn folder(&self) -> &'static str {
+        self.pick("📁 ", "")
+    }
+
+    /// File type marker for a file with the MIME type detected from its
+    /// content, falling back to guessing by `name` when it is unknown
+    pub fn type_icon(&self, content_type: Option<&str>, name: &str) -> &'static str {
+        if !self.emoji {
+            return "";
+        }
+
+        let Some(content_type) = content_type else {
+            return self.file_icon(name);
+        };
+        match crate::content_type::kind(content_type) {
+            "image" => "🖼️ ",
+            "video" => "🎬 ",
+            "audio" => "🎵 ",
+            "text" => "📝 ",
+            _ => match content_type {
+                "application/pdf" => "📄 ",
+                "application/msword"
+                | "application/rtf"
+                | "application/vnd.oasis.opendocument.text"
+                | "application/vnd.openxmlformats-officedocument.wordprocessingml.document" => {
+                    "📝 "
+                }
+                "application/vnd.ms-excel"
+                | "application/vnd.oasis.opendocument.spreadsheet"
+                | "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet" => "📊 ",
+                "application/zip"
+                | "application/gzip"
+                | "application/x-tar"
+                | "application/x-bzip2"
+                | "application/x-xz"
+                | "application/zstd"
+                | "application/x-7z-compressed"
+                | "application/vnd.rar" => "🗜️ ",
+                "application/x-executable"
+                | "application/x-mach-binary"
+                | "application/vnd.microsoft.portable-executable" => "⚙️ ",
+                _ => self.file_icon(name),
+            },
+        }
+    }
+
+    /// File type marker for `name`, including its trailing space
+    pub fn file_icon(&self, name: &str) -> &'static str {
+        if !self.emoji {
+            return "";
+        }
 
-    // Define file type emojis based on extensions
-    let get_file_emoji = |name: &str| -> &str {
         let lowercase = name.to_lowercase();
-        if lowercase.ends_with(".jpg")
-            || lowercase.ends_with(".jpeg")
-            || lowercase.ends_with(".png")
-            || lowercase.ends_with(".gif")
-            || lowercase.ends_with(".webp")
-            || lowercase.ends_with(".svg")
-        {
+        let has_extension = |extensions: &[&str]| {
+            extensions
+                .iter()
+                .any(|extension| lowercase.ends_with(extension))
+        };
+        if has_extension(&[".jpg", ".jpeg", ".png", ".gif", ".webp", ".svg"]) {
             "🖼️ " // Image files
-        } else if lowercase.ends_with(".mp4")
-            || lowercase.ends_with(".mov")
-            || lowercase.ends_with(".avi")
-            || lowercase.ends_with(".mkv")
-        {
+        } else if has_extension(&[".mp4", ".mov", ".avi", ".mkv"]) {
             "🎬 " // Video files
-        } else if lowercase.ends_with(".mp3")
-            || lowercase.ends_with(".wav")
-            || lowercase.ends_with(".ogg")
-            || lowercase.ends_with(".flac")
-        {
+        } else if has_extension(&[".mp3", ".wav", ".ogg", ".flac"]) {
             "🎵 " // Audio files
-        } else if lowercase.ends_with(".pdf") {
+        } else if has_extension(&[".pdf"]) {
             "📄 " // PDF documents
-        } else if lowercase.ends_with(".doc")
-            || lowercase.ends_with(".docx")
-            || lowercase.ends_with(".txt")
-            || lowercase.ends_with(".md")
-        {
+        } else if has_extension(&[".doc", ".docx", ".txt", ".md"]) {
             "📝 " // Text documents
-        } else if lowercase.ends_with(".xls")
-            || lowercase.ends_with(".xlsx")
-            || lowercase.ends_with(".csv")
-        {
+        } else if has_extension(&[".xls", ".xlsx", ".csv"]) {
             "📊 " // Spreadsheets
-        } else if lowercase.ends_with(".zip")
-            || lowercase.ends_with(".tar")
-            || lowercase.ends_with(".gz")
-            || lowercase.ends_with(".7z")
-        {
+        } else if has_extension(&[".zip", ".tar", ".gz", ".7z"]) {
             "🗜️ " // Archives
-        } else if lowercase.ends_with(".exe")
-            || lowercase.ends_with(".app")
-            || lowercase.ends_with(".sh")
-            || lowercase.ends_with(".bat")
-        {
+        } else if has_extension(&[".exe", ".app", ".sh", ".bat"]) {
             "⚙️ " // Executables
         } else if name.contains("/") {
             "📂 " // Subdirectory
         } else {
             "📄 " // Default file
         }
-    };
+    }
+}
+
+/// Display a directory listing with files in a user-friendly format
+/// with proper Unicode support for paths and emojis
+pub fn display_directory_listing(
+    dir_groups: &std::collections::BTreeMap<String, Vec<(String, u64)>>,
+    max_path_length: usize,
+    max_samples: usize,
+    symbols: Symbols,
+) -> Vec<String> {
+    let mut output = Vec::new();
 
     for (dir, files) in dir_groups {
         let file_count = files.len();
@@ -539,21 +1687,22 @@ pub fn display_directory_listing(
             shorten_path(dir, max_path_length)
         };
 
-        // Use folder emoji with proper Unicode handling
-        output.push(format!("  📁 {dir_display} - {file_count} files"));
+        let folder = symbols.folder();
+        output.push(format!("  {folder}{dir_display} - {file_count} files"));
 
-        // Show sample files from each directory with appropriate emoji
+        // Show sample files from each directory with their file type marker
+        let bullet = symbols.bullet();
         let sample_count = std::cmp::min(max_samples, file_count);
         for file in files.iter().take(sample_count) {
             let file_name = shorten_path(&file.0, max_path_length);
-            let emoji = get_file_emoji(&file.0);
-            output.push(format!("    • {emoji}{file_name}",));
+            let icon = symbols.file_icon(&file.0);
+            output.push(format!("    {bullet} {icon}{file_name}"));
         }
 
         // Show count of remaining files if there are more than the samples
         if file_count > sample_count {
             output.push(format!(
-                "    • ... and {} more files",
+                "    {bullet} ... and {} more files",
                 file_count - sample_count
             ));
         }
@@ -562,5 +1711,126 @@ pub fn display_directory_listing(
     output
 }
 
+/// Files and bytes below a directory, and its subdirectories
+#[derive(Debug, Default)]
+struct DirectoryNode {
+    files: usize,
+    bytes: u64,
+    children: std::collections::BTreeMap<String, DirectoryNode>,
+}
+
+/// Display files as a tree of their directories, `grouping.depth` levels
+/// deep, with the number of files and bytes below each. Each level lists
+/// the `max_dirs` directories holding the most bytes and sums up the rest.
+pub fn display_directory_tree(
+    files: &[(String, u64)],
+    grouping: &DirectoryGrouping,
+    symbols: Symbols,
+    format_size: impl Fn(u64) -> String,
+) -> Vec<String> {
+    let depth = grouping.depth.unwrap_or(2).max(1);
+    let mut root = DirectoryNode::default();
+    for (file_path, size) in files {
+        let normalized_path = file_path.replace('\\', "/");
+        let components: Vec<&str> = normalized_path.split('/').collect();
+        root.files += 1;
+        root.bytes += size;
+        let mut node = &mut root;
+        for dir in components.iter().take(components.len() - 1).take(depth) {
+            node = node.children.entry((*dir).to_string()).or_default();
+            node.files += 1;
+            node.bytes += size;
+        }
+    }
+
+    let mut output = Vec::new();
+    let direct_files = root.files
+        - root
+            .children
+            .values()
+            .map(|child| child.files)
+            .sum::<usize>();
+    if direct_files > 0 {
+        let direct_bytes =
+            root.bytes - root.children.values().map(|child| child.bytes).sum::<u64>();
+        output.push(format!(
+            "  {}Root directory - {} files ({})",
+            symbols.folder(),
+            format_count(direct_files),
+            format_size(direct_bytes)
+        ));
+    }
+    let format_node = |label: &str, files: usize, bytes: u64| {
+        format!(
+            "{label} - {} files ({})",
+            format_count(files),
+            format_size(bytes)
+        )
+    };
+    render_tree(
+        &root,
+        "  ",
+        grouping.max_dirs,
+        symbols,
+        &format_node,
+        &mut output,
+    );
+    output
+}
+
+fn render_tree(
+    node: &DirectoryNode,
+    prefix: &str,
+    max_dirs: usize,
+    symbols: Symbols,
+    format_node: &dyn Fn(&str, usize, u64) -> String,
+    output: &mut Vec<String>,
+) {
+    let mut children: Vec<(&String, &DirectoryNode)> = node.children.iter().collect();
+    children.sort_by(|a, b| b.1.bytes.cmp(&a.1.bytes).then_with(|| a.0.cmp(b.0)));
+    let shown = if max_dirs > 0 && children.len() > max_dirs {
+        max_dirs - 1
+    } else {
+        children.len()
+    };
+    let hidden = &children[shown..];
+
+    for (index, (name, child)) in children[..shown].iter().enumerate() {
+        let last = index + 1 == shown && hidden.is_empty();
+        let branch = if last {
+            symbols.tree_last()
+        } else {
+            symbols.tree_branch()
+        };
+        output.push(format!(
+            "{prefix}{branch}{}",
+            format_node(&format!("{name}/"), child.files, child.bytes)
+        ));
+        let indent = if last { "    " } else { symbols.tree_pipe() };
+        render_tree(
+            child,
+            &format!("{prefix}{indent}"),
+            max_dirs,
+            symbols,
+            format_node,
+            output,
+        );
+    }
+    if !hidden.is_empty() {
+        let files: usize = hidden.iter().map(|(_, child)| child.files).sum();
+        let bytes: u64 = hidden.iter().map(|(_, child)| child.bytes).sum();
+        output.push(format!(
+            "{prefix}{}{}",
+            symbols.tree_last(),
+            format_node(
+                &format!("... and {} more directories", hidden.len()),
+                files,
+                bytes
+            )
+        ));
+    }
+}
+
 #[cfg(test)]
-mod tests;
+#[path = "tests.rs"]
+mod unit_tests;
@@ -1,14 +1,25 @@
 #[cfg(test)]
 mod tests {
     use crate::utils::{
-        display_directory_listing, format_size, group_files_by_directory, shorten_path,
+        DirectoryGrouping, display_directory_tree, group_files, hash_pool, lower_priority,
+    };
+    use crate::utils::{
+        DirectoryMove, RenameConfidence, RenameMatch, Symbols, count_distinct_inodes,
+        display_directory_listing, find_directory_moves, find_potential_renames_by_metadata,
+        format_count, format_duration, format_size, format_size_as, from_storage_path,
+        group_files_by_directory, match_renames_by_checksum, match_renames_by_inode,
+        normalize_path, parse_date, parse_selection, parse_size, read_path_list, shorten_path,
+        to_storage_path, unique_prefix,
+    };
+    use crate::{
+        AddOptions, Ddrive, api::testing::repository, checksum::ChecksumCalculator,
+        config::PerformanceConfig, database::FileRecord, scanner::FileInfo,
     };
-    use crate::{checksum::ChecksumCalculator, database::FileRecord, scanner::FileInfo};
     use assert_fs::TempDir;
     use assert_fs::prelude::*;
     use chrono::DateTime;
     use std::collections::BTreeMap;
-    use std::path::PathBuf;
+    use std::path::{Path, PathBuf};
     use std::time::{Duration, UNIX_EPOCH};
 
     // Helper to create a minimal FileProcessor for testing checksum functionality
@@ -28,8 +39,15 @@ mod tests {
             path: PathBuf::from(path),
             size,
             modified: UNIX_EPOCH + Duration::from_secs(modified_secs),
-            created: UNIX_EPOCH + Duration::from_secs(created_secs),
+            created: Some(UNIX_EPOCH + Duration::from_secs(created_secs)),
             b3sum: checksum,
+            attributes: Default::default(),
+            dev: None,
+            ino: None,
+            secondary: Default::default(),
+            stored: false,
+            ctime_ns: None,
+            content_type: None,
         }
     }
 
@@ -44,15 +62,29 @@ mod tests {
         FileRecord {
             id: 1,
             path: path.to_string(),
-            created_at: DateTime::from_timestamp(updated_at_secs, 0)
-                .unwrap()
-                .naive_utc(),
+            created_at: Some(
+                DateTime::from_timestamp(updated_at_secs, 0)
+                    .unwrap()
+                    .naive_utc(),
+            ),
             updated_at: DateTime::from_timestamp(updated_at_secs, 0)
                 .unwrap()
                 .naive_utc(),
             last_checked: None,
             b3sum: checksum.to_string(),
             size,
+            mode: None,
+            uid: None,
+            gid: None,
+            xattrs: None,
+            dev: None,
+            ino: None,
+            mtime_ns: None,
+            sha256: None,
+            xxh3: None,
+            mac: None,
+            stored: true,
+            ctime_ns: None,
         }
     }
 
@@ -68,6 +100,148 @@ mod tests {
         assert_eq!(format_size(2199023255552), "2.00 TB");
     }
 
+    #[test]
+    fn test_format_count() {
+        assert_eq!(format_count(0), "0");
+        assert_eq!(format_count(999), "999");
+        assert_eq!(format_count(1234), "1,234");
+        assert_eq!(format_count(1234567), "1,234,567");
+    }
+
+    #[test]
+    fn test_unique_prefix() {
+        let ids: Vec<String> = ["1111aaa", "1111abc", "1111abd", "1111b"]
+            .map(String::from)
+            .to_vec();
+        assert_eq!(unique_prefix("1111aaa", &ids, 2), "1111aa");
+        assert_eq!(unique_prefix("1111abc", &ids, 2), "1111abc");
+        assert_eq!(unique_prefix("1111b", &ids, 2), "1111b");
+        assert_eq!(unique_prefix("1111b", &ids, 3), "1111b");
+        assert_eq!(unique_prefix("1111aaa", &ids, 7), "1111aaa");
+        assert_eq!(unique_prefix("2", &ids, 1), "2");
+    }
+
+    #[test]
+    fn test_format_duration() {
+        assert_eq!(format_duration(Duration::from_millis(850)), "850ms");
+        assert_eq!(format_duration(Duration::from_millis(12_340)), "12.3s");
+        assert_eq!(format_duration(Duration::from_secs(245)), "4m 05s");
+        assert_eq!(format_duration(Duration::from_secs(7_620)), "2h 07m");
+    }
+
+    #[test]
+    fn test_format_size_as() {
+        use crate::utils::SizeUnits;
+
+        assert_eq!(format_size_as(1536, SizeUnits::Jedec), "1.5 KB");
+        assert_eq!(format_size_as(1536, SizeUnits::Binary), "1.5 KiB");
+        assert_eq!(format_size_as(1536, SizeUnits::Decimal), "1.5 kB");
+        assert_eq!(format_size_as(1536, SizeUnits::Bytes), "1536 B");
+        assert_eq!(format_size_as(999, SizeUnits::Decimal), "999 B");
+        assert_eq!(format_size_as(1_000_000, SizeUnits::Decimal), "1.0 MB");
+        assert_eq!(format_size_as(1073741824, SizeUnits::Binary), "1.00 GiB");
+        assert_eq!(
+            format_size_as(5_000_000_000_000, SizeUnits::Bytes),
+            "5000000000000 B"
+        );
+    }
+
+    #[test]
+    fn test_parse_selection() {
+        assert_eq!(
+            parse_selection("1 3 5-7\n", 8).unwrap(),
+            vec![0, 2, 4, 5, 6]
+        );
+        assert_eq!(parse_selection("2,1, 2", 3).unwrap(), vec![0, 1]);
+        assert_eq!(parse_selection("a", 3).unwrap(), vec![0, 1, 2]);
+        assert!(parse_selection("", 3).unwrap().is_empty());
+        assert!(parse_selection("0", 3).is_err());
+        assert!(parse_selection("4", 3).is_err());
+        assert!(parse_selection("3-2", 3).is_err());
+        assert!(parse_selection("x", 3).is_err());
+    }
+
+    #[test]
+    fn test_read_path_list() {
+        let paths = read_path_list("a.jpg\r\n\nsub/b c.mp4\n".as_bytes(), false).unwrap();
+        assert_eq!(
+            paths,
+            vec![PathBuf::from("a.jpg"), PathBuf::from("sub/b c.mp4")]
+        );
+
+        let paths = read_path_list("a\nb\0c\0".as_bytes(), true).unwrap();
+        assert_eq!(paths, vec![PathBuf::from("a\nb"), PathBuf::from("c")]);
+    }
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size("4096").unwrap(), 4096);
+        assert_eq!(parse_size("512B").unwrap(), 512);
+        assert_eq!(parse_size("1KB").unwrap(), 1024);
+        assert_eq!(parse_size("100GB").unwrap(), 100 * 1024 * 1024 * 1024);
+        assert_eq!(parse_size("1.5T").unwrap(), 3 * 512 * 1024 * 1024 * 1024);
+        assert_eq!(parse_size("256 MiB").unwrap(), 256 * 1024 * 1024);
+        assert_eq!(parse_size("2gb").unwrap(), 2 * 1024 * 1024 * 1024);
+        assert!(parse_size("").is_err());
+        assert!(parse_size("GB").is_err());
+        assert!(parse_size("10 parsecs").is_err());
+    }
+
+    #[test]
+    fn test_parse_date() {
+        use chrono::{Local, TimeZone};
+        let midnight = Local.with_ymd_and_hms(2024, 5, 1, 0, 0, 0).unwrap();
+        assert_eq!(parse_date("2024-05-01").unwrap(), midnight);
+        assert_eq!(
+            parse_date("2024-05-01T18:30:00").unwrap(),
+            Local.with_ymd_and_hms(2024, 5, 1, 18, 30, 0).unwrap()
+        );
+        assert_eq!(
+            parse_date("2024-05-01T12:00:00Z").unwrap().timestamp(),
+            1714564800
+        );
+        let ninety_days_ago = chrono::Utc::now() - chrono::Duration::days(90);
+        assert!(
+            (parse_date("90d").unwrap() - ninety_days_ago)
+                .num_seconds()
+                .abs()
+                < 5
+        );
+        assert!(parse_date("yesterday").is_err());
+        assert!(parse_date("2024-13-01").is_err());
+        assert!(parse_date("90y").is_err());
+    }
+
+    #[test]
+    fn test_hash_pool_size() {
+        let config = PerformanceConfig {
+            hash_threads: Some(2),
+            ..Default::default()
+        };
+        assert_eq!(hash_pool(&config).unwrap().current_num_threads(), 2);
+    }
+
+    #[test]
+    fn test_lower_priority_checks_ranges() {
+        let nice = PerformanceConfig {
+            cpu_nice: Some(20),
+            ..Default::default()
+        };
+        assert!(lower_priority(&nice).is_err());
+        let io = PerformanceConfig {
+            io_nice: Some(8),
+            ..Default::default()
+        };
+        assert!(lower_priority(&io).is_err());
+        assert!(lower_priority(&PerformanceConfig::default()).is_ok());
+    }
+
+    #[test]
+    fn test_count_distinct_inodes() {
+        let keys = vec![Some((1, 10)), Some((1, 10)), Some((1, 11)), None, None];
+        assert_eq!(count_distinct_inodes(keys), 4);
+    }
+
     #[test]
     fn test_shorten_path_no_truncation_needed() {
         let path = "short/path.txt";
@@ -221,6 +395,74 @@ mod tests {
         assert!(result.contains_key("Other directories"));
     }
 
+    #[test]
+    fn test_group_files_by_depth_keeps_largest_directories() {
+        let mut files = vec![("notes.txt".to_string(), 1)];
+        for i in 0..5 {
+            files.push((format!("photos/2024/{i}.jpg"), 10));
+        }
+        for i in 0..3 {
+            files.push((format!("photos/2023/raw/{i}.raw"), 10));
+        }
+        for dir in ["a", "b", "c"] {
+            files.push((format!("{dir}/file.txt"), 1));
+        }
+
+        let grouping = DirectoryGrouping {
+            max_dirs: 3,
+            depth: Some(2),
+        };
+        let result = group_files(&files, &grouping);
+        let dirs: Vec<&String> = result.keys().collect();
+        assert_eq!(
+            dirs,
+            vec!["Other directories", "photos/2023", "photos/2024"]
+        );
+        assert!(result["photos/2023"].contains(&("raw/0.raw".to_string(), 10)));
+        let other = &result["Other directories"];
+        assert_eq!(other.len(), 4);
+        assert!(other.contains(&("notes.txt".to_string(), 1)));
+        assert!(other.contains(&("a/file.txt".to_string(), 1)));
+
+        // 0 lists every directory
+        let grouping = DirectoryGrouping {
+            max_dirs: 0,
+            depth: Some(1),
+        };
+        assert_eq!(group_files(&files, &grouping).len(), 5);
+    }
+
+    #[test]
+    fn test_display_directory_tree() {
+        let mut files = vec![("notes.txt".to_string(), 5)];
+        for i in 0..3 {
+            files.push((format!("photos/2024/{i}.jpg"), 100));
+        }
+        files.push(("photos/2023/raw/a.raw".to_string(), 50));
+        files.push(("docs/a.pdf".to_string(), 20));
+        files.push(("music/a.mp3".to_string(), 10));
+        files.push(("video/a.mp4".to_string(), 1));
+
+        let grouping = DirectoryGrouping {
+            max_dirs: 3,
+            depth: None,
+        };
+        let result = display_directory_tree(&files, &grouping, Symbols::new(false), |size| {
+            format!("{size} B")
+        });
+        assert_eq!(
+            result,
+            vec![
+                "  Root directory - 1 files (5 B)",
+                "  |-- photos/ - 4 files (350 B)",
+                "  |   |-- 2024/ - 3 files (300 B)",
+                "  |   `-- 2023/ - 1 files (50 B)",
+                "  |-- docs/ - 1 files (20 B)",
+                "  `-- ... and 2 more directories - 2 files (11 B)",
+            ]
+        );
+    }
+
     #[test]
     fn test_display_directory_listing_basic() {
         let mut dir_groups = BTreeMap::new();
@@ -229,7 +471,7 @@ mod tests {
             vec![("main.rs".to_string(), 1024), ("lib.rs".to_string(), 2048)],
         );
 
-        let result = display_directory_listing(&dir_groups, 50, 10);
+        let result = display_directory_listing(&dir_groups, 50, 10, Symbols::new(true));
 
         assert!(!result.is_empty());
         assert!(result[0].contains("📁 src - 2 files"));
@@ -237,6 +479,30 @@ mod tests {
         assert!(result.iter().any(|line| line.contains("lib.rs")));
     }
 
+    #[test]
+    fn test_display_directory_listing_plain() {
+        let mut dir_groups = BTreeMap::new();
+        dir_groups.insert(
+            "assets".to_string(),
+            vec![
+                ("image.jpg".to_string(), 1024),
+                ("notes.txt".to_string(), 10),
+            ],
+        );
+
+        let result = display_directory_listing(&dir_groups, 50, 1, Symbols::new(false));
+
+        assert_eq!(
+            result,
+            vec![
+                "  assets - 2 files",
+                "    - image.jpg",
+                "    - ... and 1 more files"
+            ]
+        );
+        assert!(result.iter().all(|line| line.is_ascii()));
+    }
+
     #[test]
     fn test_display_directory_listing_with_emojis() {
         let mut dir_groups = BTreeMap::new();
@@ -250,7 +516,7 @@ mod tests {
             ],
         );
 
-        let result = display_directory_listing(&dir_groups, 50, 10);
+        let result = display_directory_listing(&dir_groups, 50, 10, Symbols::new(true));
 
         // Check for appropriate emojis
         assert!(
@@ -287,7 +553,7 @@ mod tests {
 
         dir_groups.insert("test".to_string(), files);
 
-        let result = display_directory_listing(&dir_groups, 50, 5);
+        let result = display_directory_listing(&dir_groups, 50, 5, Symbols::new(true));
 
         // Should show only 5 samples plus "... and X more files" message
         // .txt files use 📝 emoji, not 📄
@@ -306,7 +572,7 @@ mod tests {
             vec![("verylongfilenamethatexceedslimit.txt".to_string(), 1024)],
         );
 
-        let result = display_directory_listing(&dir_groups, 20, 10);
+        let result = display_directory_listing(&dir_groups, 20, 10, Symbols::new(true));
 
         // Paths should be shortened
         assert!(result.iter().any(|line| line.contains("...")));
@@ -426,7 +692,6 @@ mod tests {
 
     #[test]
     fn test_find_potential_renames_by_metadata() {
-        // Test the rename detection logic directly
         let deleted1 = create_test_file_info("old/path1.txt", 1024, None, 1000, 500);
         let deleted2 = create_test_file_info("old/path2.txt", 2048, None, 1001, 501);
         let deleted_files = vec![deleted1, deleted2];
@@ -435,59 +700,91 @@ mod tests {
         let new1 = create_test_file_info("new/path1.txt", 1024, None, 1002, 500); // Same size and creation time
         let new2 = create_test_file_info("new/path2.txt", 2048, None, 1003, 501); // Same size and creation time
         let new3 = create_test_file_info("new/path3.txt", 512, None, 1004, 502); // Different metadata
-        let new_files = vec![new1, new2, new3];
-
-        // Simulate the rename detection logic
-        let mut potential_renames = Vec::new();
-
-        // Group by (size, creation_time)
-        let mut deleted_by_key = std::collections::HashMap::new();
-        let mut new_by_key = std::collections::HashMap::new();
-
-        for file in &deleted_files {
-            let creation_time = file.created.duration_since(UNIX_EPOCH).unwrap().as_secs();
-            let key = (file.size, creation_time);
-            deleted_by_key
-                .entry(key)
-                .or_insert_with(Vec::new)
-                .push(file);
-        }
+        let new_files = vec![&new1, &new2, &new3];
 
-        for file in &new_files {
-            let creation_time = file.created.duration_since(UNIX_EPOCH).unwrap().as_secs();
-            let key = (file.size, creation_time);
-            new_by_key.entry(key).or_insert_with(Vec::new).push(file);
-        }
-
-        // Find matches
-        for (key, deleted_list) in deleted_by_key {
-            if let Some(new_list) = new_by_key.get(&key) {
-                if let (Some(&deleted), Some(&new)) = (deleted_list.first(), new_list.first()) {
-                    potential_renames.push((deleted.clone(), new.clone()));
-                }
-            }
-        }
+        let potential_renames =
+            find_potential_renames_by_metadata(&deleted_files, &[0, 1], &new_files, &[0, 1, 2]);
 
         // Should find 2 potential renames
         assert_eq!(potential_renames.len(), 2);
 
         // Check that renames match by size and creation time
-        for (old_file, new_file) in &potential_renames {
+        for rename in &potential_renames {
+            let old_file = &deleted_files[rename.deleted];
+            let new_file = new_files[rename.new];
             assert_eq!(old_file.size, new_file.size);
-            let old_created = old_file
-                .created
-                .duration_since(UNIX_EPOCH)
-                .unwrap()
-                .as_secs();
-            let new_created = new_file
-                .created
-                .duration_since(UNIX_EPOCH)
-                .unwrap()
-                .as_secs();
-            assert_eq!(old_created, new_created);
+            assert_eq!(old_file.created, new_file.created);
+            assert!(rename.b3sum.is_none());
+            assert!(rename.confidence.is_low());
         }
     }
 
+    #[test]
+    fn test_match_renames_by_inode() {
+        let mut deleted = create_test_file_info("old/a.txt", 1024, Some("abc".into()), 1000, 500);
+        deleted.dev = Some(1);
+        deleted.ino = Some(42);
+        let mut renamed = create_test_file_info("new/a.txt", 1024, None, 1000, 900);
+        renamed.dev = Some(1);
+        renamed.ino = Some(42);
+        let mut other = create_test_file_info("new/b.txt", 1024, None, 1000, 500);
+        other.dev = Some(1);
+        other.ino = Some(43);
+
+        let renames = match_renames_by_inode(&[deleted], &[&other, &renamed]);
+        assert_eq!(
+            renames,
+            vec![RenameMatch {
+                deleted: 0,
+                new: 1,
+                b3sum: Some("abc".to_string()),
+                confidence: RenameConfidence::Inode,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_match_renames_by_inode_rejects_reused_inode() {
+        let mut deleted = create_test_file_info("old/a.txt", 1024, None, 1000, 500);
+        deleted.dev = Some(1);
+        deleted.ino = Some(42);
+        // Same inode but different size and newer content: inode was reused
+        let mut reused = create_test_file_info("new/a.txt", 10, None, 5000, 5000);
+        reused.dev = Some(1);
+        reused.ino = Some(42);
+
+        assert!(match_renames_by_inode(&[deleted], &[&reused]).is_empty());
+    }
+
+    #[test]
+    fn test_match_renames_by_checksum() {
+        let deleted = vec![
+            create_test_file_info("old/a.txt", 10, Some("aaa".into()), 1000, 500),
+            create_test_file_info("old/b.txt", 10, Some("bbb".into()), 1000, 500),
+        ];
+        let new_a = create_test_file_info("new/a.txt", 10, None, 1000, 600);
+        let new_c = create_test_file_info("new/c.txt", 10, None, 1000, 600);
+        let new_files = vec![&new_a, &new_c];
+        let checksums = vec![(0, "aaa".to_string()), (1, "ccc".to_string())];
+
+        let renames = match_renames_by_checksum(&deleted, &[0, 1], &new_files, &checksums);
+        assert_eq!(renames.len(), 1);
+        assert_eq!((renames[0].deleted, renames[0].new), (0, 0));
+        assert_eq!(renames[0].confidence, RenameConfidence::Checksum);
+    }
+
+    #[test]
+    fn test_find_potential_renames_without_creation_time() {
+        // Same size but unknown birth time must not be paired
+        let mut deleted = create_test_file_info("old/path1.txt", 1024, None, 1000, 500);
+        deleted.created = None;
+        let mut new = create_test_file_info("new/path1.txt", 1024, None, 1002, 500);
+        new.created = None;
+
+        let potential_renames = find_potential_renames_by_metadata(&[deleted], &[0], &[&new], &[0]);
+        assert!(potential_renames.is_empty());
+    }
+
     #[test]
     fn test_find_potential_renames_no_matches() {
         // Test case where no renames should be detected
@@ -496,40 +793,12 @@ mod tests {
 
         // Create new files with different metadata
         let new1 = create_test_file_info("new/path1.txt", 2048, None, 1002, 600); // Different size and creation time
-        let new_files = vec![new1];
-
-        // Simulate the rename detection logic
-        let mut potential_renames = Vec::new();
-
-        // Group by (size, creation_time)
-        let mut deleted_by_key = std::collections::HashMap::new();
-        let mut new_by_key = std::collections::HashMap::new();
-
-        for file in &deleted_files {
-            let creation_time = file.created.duration_since(UNIX_EPOCH).unwrap().as_secs();
-            let key = (file.size, creation_time);
-            deleted_by_key
-                .entry(key)
-                .or_insert_with(Vec::new)
-                .push(file);
-        }
-
-        for file in &new_files {
-            let creation_time = file.created.duration_since(UNIX_EPOCH).unwrap().as_secs();
-            let key = (file.size, creation_time);
-            new_by_key.entry(key).or_insert_with(Vec::new).push(file);
-        }
+        let new_files = vec![&new1];
 
-        // Find matches
-        for (key, deleted_list) in deleted_by_key {
-            if let Some(new_list) = new_by_key.get(&key) {
-                if let (Some(&deleted), Some(&new)) = (deleted_list.first(), new_list.first()) {
-                    potential_renames.push((deleted.clone(), new.clone()));
-                }
-            }
-        }
+        let potential_renames =
+            find_potential_renames_by_metadata(&deleted_files, &[0], &new_files, &[0]);
 
-        // Should find no renames
+        // Should find no potential renames
         assert_eq!(potential_renames.len(), 0);
     }
 
@@ -575,4 +844,134 @@ mod tests {
         empty_file.assert(predicates::path::exists());
         binary_file.assert(predicates::path::exists());
     }
+
+    #[test]
+    fn test_normalize_path_composes_nfd() {
+        let nfd = "photos/Cafe\u{301}.jpg";
+        let nfc = "photos/Caf\u{e9}.jpg";
+        assert_eq!(normalize_path(nfd), nfc);
+        assert!(matches!(normalize_path(nfc), std::borrow::Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn test_storage_path_roundtrip() {
+        let native: PathBuf = ["photos", "2024", "img.jpg"].iter().collect();
+        let stored = to_storage_path(&native);
+        assert_eq!(stored, "photos/2024/img.jpg");
+        assert_eq!(from_storage_path(&stored), native);
+    }
+
+    #[test]
+    fn test_find_directory_moves() {
+        let rename = |old: &str, new: &str| (old.to_string(), new.to_string());
+        let renames = vec![
+            rename("photos/2021/a.jpg", "archive/2021/a.jpg"),
+            rename("photos/2021/raw/b.cr2", "archive/2021/raw/b.cr2"),
+            rename("docs/old.txt", "docs/new.txt"),
+        ];
+        let tracked = [
+            "photos/2021/a.jpg",
+            "photos/2021/raw/b.cr2",
+            "photos/2022/c.jpg",
+            "docs/old.txt",
+        ];
+
+        // photos/2022 stayed, so only photos/2021 moved
+        assert_eq!(
+            find_directory_moves(&renames, &tracked.into_iter().collect()),
+            vec![DirectoryMove {
+                from: "photos/2021".to_string(),
+                to: "archive/2021".to_string(),
+                files: vec![0, 1],
+            }]
+        );
+
+        // With photos/2022 gone too the whole directory was renamed
+        let moves = find_directory_moves(&renames, &tracked[..2].iter().copied().collect());
+        assert_eq!(
+            (moves[0].from.as_str(), moves[0].to.as_str()),
+            ("photos", "archive")
+        );
+
+        // Moving into a directory that already held files is not a rename of it
+        let tracked = [
+            "photos/2021/a.jpg",
+            "photos/2021/raw/b.cr2",
+            "archive/x.jpg",
+        ];
+        let moves = find_directory_moves(&renames, &tracked.into_iter().collect());
+        assert_eq!(moves[0].to, "archive/2021");
+    }
+
+    #[tokio::test]
+    async fn test_change_detection_settings() {
+        use crate::config::ChangeDetection;
+
+        async fn changed_files(
+            root: &Path,
+            detect: ChangeDetection,
+            mtime_tolerance_ms: u64,
+        ) -> Vec<String> {
+            let context = Ddrive::builder(root)
+                .configure(move |config| {
+                    config.changes.detect = detect;
+                    config.changes.mtime_tolerance_ms = mtime_tolerance_ms;
+                })
+                .build()
+                .await
+                .unwrap();
+            let mut changed = Ddrive::from_context(context)
+                .status()
+                .await
+                .unwrap()
+                .updated_files;
+            changed.sort();
+            changed
+        }
+
+        let (temp, ddrive) =
+            repository(&[("docs/a.txt", "before"), ("docs/b.txt", "second")]).await;
+        ddrive
+            .add(&[temp.path().join("docs")], &AddOptions::default())
+            .await
+            .unwrap();
+        // a.txt: timestamp off by one second, as FAT32 media round it
+        let set_modified = |path: &str, modified: std::time::SystemTime| {
+            std::fs::File::options()
+                .write(true)
+                .open(temp.path().join(path))
+                .unwrap()
+                .set_modified(modified)
+                .unwrap();
+        };
+        let a_modified = std::fs::metadata(temp.path().join("docs/a.txt"))
+            .unwrap()
+            .modified()
+            .unwrap();
+        set_modified("docs/a.txt", a_modified + std::time::Duration::from_secs(1));
+        // b.txt: rewritten with its modification time restored
+        let b_modified = std::fs::metadata(temp.path().join("docs/b.txt"))
+            .unwrap()
+            .modified()
+            .unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        std::fs::write(temp.path().join("docs/b.txt"), "edited").unwrap();
+        set_modified("docs/b.txt", b_modified);
+
+        let root = temp.path();
+        assert_eq!(
+            changed_files(root, ChangeDetection::MtimeSize, 0).await,
+            vec!["docs/a.txt"]
+        );
+        assert!(
+            changed_files(root, ChangeDetection::MtimeSize, 2000)
+                .await
+                .is_empty()
+        );
+        // Setting a.txt's modification time changed its inode change time too
+        assert_eq!(
+            changed_files(root, ChangeDetection::Ctime, 2000).await,
+            vec!["docs/a.txt", "docs/b.txt"]
+        );
+    }
 }
@@ -0,0 +1,143 @@
+//! Several ddrive processes working on one repository at once.
+//!
+//! Commands that change the tracked files or the object store (add, rm,
+//! prune, import, organize, mirror, export, tier) take the repository lock,
+//! so a second one fails right away instead of interleaving with the first.
+//! verify, status and log do not take it and can run alongside any of them:
+//! SQLite serializes their few writes with the locked command's.
+
+use assert_cmd::Command;
+use assert_fs::{TempDir, prelude::*};
+use std::fs;
+use std::process::{self, Stdio};
+
+/// A repository directory, and a user configuration directory of its own so
+/// the binary never registers test repositories in the developer's
+struct Sandbox {
+    repo: TempDir,
+    config: TempDir,
+}
+
+impl Sandbox {
+    fn new() -> Self {
+        Self {
+            repo: TempDir::new().unwrap(),
+            config: TempDir::new().unwrap(),
+        }
+    }
+
+    fn ddrive(&self) -> Command {
+        let mut command = Command::cargo_bin("ddrive").unwrap();
+        command
+            .current_dir(self.repo.path())
+            .env("XDG_CONFIG_HOME", self.config.path());
+        command
+    }
+
+    fn spawn(&self, args: &[&str]) -> process::Child {
+        process::Command::new(assert_cmd::cargo::cargo_bin("ddrive"))
+            .args(args)
+            .current_dir(self.repo.path())
+            .env("XDG_CONFIG_HOME", self.config.path())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .unwrap()
+    }
+
+    fn write_files(&self, dir: &str, count: usize) {
+        for i in 0..count {
+            self.repo
+                .child(format!("{dir}/{i}.txt"))
+                .write_str(&format!("{dir} file {i}\n").repeat(64))
+                .unwrap();
+        }
+    }
+
+    /// Number of files `export-checksums` lists, which is every tracked file
+    fn tracked_files(&self) -> usize {
+        let output = self.ddrive().arg("export-checksums").output().unwrap();
+        assert!(output.status.success());
+        String::from_utf8(output.stdout)
+            .unwrap()
+            .lines()
+            .filter(|line| line.ends_with(".txt"))
+            .count()
+    }
+}
+
+#[test]
+fn verify_and_status_run_alongside_add() {
+    let sandbox = Sandbox::new();
+    sandbox.ddrive().arg("init").assert().success();
+    sandbox.write_files("photos", 200);
+    sandbox.ddrive().args(["add", "."]).assert().success();
+    sandbox.write_files("inbox", 200);
+
+    let add = sandbox.spawn(&["add", "."]);
+    let verify = sandbox.spawn(&["verify", "--force"]);
+    let status = sandbox.spawn(&["--read-only", "status"]);
+    for mut child in [add, verify, status] {
+        assert!(child.wait().unwrap().success());
+    }
+
+    assert_eq!(sandbox.tracked_files(), 400);
+    sandbox
+        .ddrive()
+        .args(["verify", "--force"])
+        .assert()
+        .success();
+}
+
+#[test]
+fn second_writer_is_refused() {
+    let sandbox = Sandbox::new();
+    sandbox.ddrive().arg("init").assert().success();
+    sandbox.write_files("photos", 3);
+
+    // Another process holding the repository lock
+    let lock = fs::OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(sandbox.repo.path().join(".ddrive/lock"))
+        .unwrap();
+    lock.try_lock().unwrap();
+    sandbox
+        .ddrive()
+        .args(["add", "."])
+        .assert()
+        .failure()
+        .code(2);
+    sandbox.ddrive().args(["prune"]).assert().failure().code(2);
+    sandbox.ddrive().args(["verify"]).assert().success();
+    sandbox.ddrive().args(["log"]).assert().success();
+
+    drop(lock);
+    sandbox.ddrive().args(["add", "."]).assert().success();
+    assert_eq!(sandbox.tracked_files(), 3);
+}
+
+#[test]
+fn read_only_refuses_changes() {
+    let sandbox = Sandbox::new();
+    sandbox.ddrive().arg("init").assert().success();
+    sandbox.write_files("photos", 3);
+    sandbox.ddrive().args(["add", "."]).assert().success();
+
+    for args in [
+        &["--read-only", "status"][..],
+        &["--read-only", "log"],
+        &["--read-only", "verify", "--force"],
+        &["verify", "--force", "--no-update"],
+    ] {
+        sandbox.ddrive().args(args).assert().success();
+    }
+    for args in [
+        &["--read-only", "add", "."][..],
+        &["--read-only", "prune"],
+        &["--read-only", "verify", "--quarantine"],
+    ] {
+        sandbox.ddrive().args(args).assert().failure().code(6);
+    }
+}
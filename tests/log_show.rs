@@ -0,0 +1,136 @@
+//! `ddrive log show` filtering an action's entries by type, summing them
+//! with `--stat` and listing all of them with `--all`.
+
+use assert_cmd::Command;
+use assert_fs::{TempDir, prelude::*};
+
+/// A repository directory, and a user configuration directory of its own so
+/// the binary never registers test repositories in the developer's
+struct Sandbox {
+    repo: TempDir,
+    config: TempDir,
+}
+
+impl Sandbox {
+    fn new() -> Self {
+        Self {
+            repo: TempDir::new().unwrap(),
+            config: TempDir::new().unwrap(),
+        }
+    }
+
+    fn ddrive(&self) -> Command {
+        let mut command = Command::cargo_bin("ddrive").unwrap();
+        command
+            .current_dir(self.repo.path())
+            .env("XDG_CONFIG_HOME", self.config.path());
+        command
+    }
+
+    fn output(&self, args: &[&str]) -> Vec<String> {
+        let output = self.ddrive().args(args).output().unwrap();
+        assert!(output.status.success());
+        String::from_utf8(output.stdout)
+            .unwrap()
+            .lines()
+            .map(str::to_string)
+            .collect()
+    }
+
+    /// The entries `log show` lists, without the action and run lines
+    fn entries(&self, args: &[&str]) -> Vec<String> {
+        self.output(&[&["log", "show"], args].concat())
+            .into_iter()
+            .skip(2)
+            .collect()
+    }
+}
+
+#[test]
+fn log_show_filters_and_sums_entries() {
+    let sandbox = Sandbox::new();
+    for i in 0..7 {
+        sandbox
+            .repo
+            .child(format!("docs/{i}.txt"))
+            .write_str(&format!("file {i}"))
+            .unwrap();
+    }
+    sandbox
+        .repo
+        .child("docs/changed.txt")
+        .write_str("old")
+        .unwrap();
+    sandbox
+        .repo
+        .child("docs/moved.txt")
+        .write_str("moved")
+        .unwrap();
+    sandbox.ddrive().arg("init").assert().success();
+    sandbox.ddrive().args(["add", "."]).assert().success();
+
+    // Action IDs are timestamps, so the second add gets its own a second later
+    std::thread::sleep(std::time::Duration::from_millis(1100));
+    sandbox
+        .repo
+        .child("docs/changed.txt")
+        .write_str("new content")
+        .unwrap();
+    std::fs::rename(
+        sandbox.repo.path().join("docs/moved.txt"),
+        sandbox.repo.path().join("docs/renamed.txt"),
+    )
+    .unwrap();
+    sandbox.repo.child("docs/new.txt").write_str("n").unwrap();
+    sandbox.ddrive().args(["add", "."]).assert().success();
+
+    // Newest first, each action starting with its date and ID
+    let ids: Vec<String> = sandbox
+        .output(&["log"])
+        .iter()
+        .filter(|line| !line.starts_with(' '))
+        .map(|line| {
+            line.split_whitespace()
+                .nth(3)
+                .unwrap()
+                .trim_end_matches(':')
+                .to_string()
+        })
+        .collect();
+    let [second, first] = ids.as_slice() else {
+        panic!("expected two actions, got {ids:?}");
+    };
+
+    // A preview of the first few entries unless --all is given
+    let preview = sandbox.entries(&[first]);
+    assert_eq!(preview.len(), 6);
+    assert_eq!(preview[5], "  and 4 more... (use --all to list them)");
+    let all = sandbox.entries(&[first, "--all"]);
+    assert_eq!(all.len(), 9);
+    assert!(all.iter().all(|line| line.starts_with("  add docs/")));
+    assert_eq!(
+        sandbox.entries(&[first, "--stat"]),
+        ["  add: 9 files (50 B)"]
+    );
+
+    // Entries of one type, alone or summed
+    assert_eq!(
+        sandbox.entries(&[second, "--type", "update"]),
+        ["  update docs/changed.txt"]
+    );
+    let renames = sandbox.entries(&[second, "--type", "rename"]);
+    assert_eq!(renames.len(), 1);
+    assert!(renames[0].contains("docs/moved.txt") && renames[0].contains("docs/renamed.txt"));
+    assert_eq!(
+        sandbox.entries(&[second, "--stat"]),
+        [
+            "  add: 1 files (1 B)",
+            "  update: 1 files (11 B)",
+            "  rename: 1 files (5 B)",
+        ]
+    );
+    assert_eq!(
+        sandbox.entries(&[second, "--stat", "--type", "update"]),
+        ["  update: 1 files (11 B)"]
+    );
+}
@@ -0,0 +1,64 @@
+//! The global `-C/--repo` option, which runs a command on the repository at
+//! a path instead of the one containing the working directory.
+
+use assert_cmd::Command;
+use assert_fs::{TempDir, prelude::*};
+use std::path::Path;
+
+/// `ddrive` run in `current_dir`, with the user configuration in `config` so
+/// the test repositories are not registered in the developer's
+fn ddrive(current_dir: &Path, config: &TempDir) -> Command {
+    let mut command = Command::cargo_bin("ddrive").unwrap();
+    command
+        .current_dir(current_dir)
+        .env("XDG_CONFIG_HOME", config.path());
+    command
+}
+
+#[test]
+fn repo_option_selects_the_repository() {
+    let temp = TempDir::new().unwrap();
+    let elsewhere = TempDir::new().unwrap();
+    let config = TempDir::new().unwrap();
+    let repo = temp.child("repo");
+    repo.child("photos/a.jpg").write_str("a").unwrap();
+
+    ddrive(elsewhere.path(), &config)
+        .arg("-C")
+        .arg(repo.path())
+        .arg("init")
+        .assert()
+        .success();
+    assert!(repo.child(".ddrive").exists());
+    assert!(!elsewhere.child(".ddrive").exists());
+    // The new repository, not the working directory, is registered
+    let registry = std::fs::read_to_string(config.path().join("ddrive/repos.toml")).unwrap();
+    let root = repo.path().canonicalize().unwrap();
+    assert!(registry.contains(root.to_str().unwrap()));
+
+    // Paths on the command line are resolved against the repository path
+    ddrive(elsewhere.path(), &config)
+        .arg("--repo")
+        .arg(repo.path())
+        .args(["add", "photos"])
+        .assert()
+        .success();
+
+    // A relative path is taken from the working directory, and a directory
+    // inside the repository is where the command runs, as with a working
+    // directory there
+    for (path, listed) in [("repo", "  photos/a.jpg\n"), ("repo/photos", "  a.jpg\n")] {
+        let output = ddrive(temp.path(), &config)
+            .args(["-C", path, "export-checksums"])
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        assert!(String::from_utf8(output.stdout).unwrap().ends_with(listed));
+    }
+
+    // Without the option the working directory decides
+    ddrive(elsewhere.path(), &config)
+        .arg("export-checksums")
+        .assert()
+        .failure();
+}